@@ -0,0 +1,445 @@
+use std::sync::Arc;
+
+use ciborium::value::Value;
+use coset::iana::{self, EnumI64};
+use passkey_types::ctap2::{Aaguid, AuthenticatorData, Ctap2Error};
+
+use passkey_types::cose::ec2_coordinates;
+
+mod batch;
+pub(crate) mod chain;
+
+pub use self::{
+    batch::BatchAttestation,
+    chain::{AttestationChain, AttestationChainError},
+};
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Signs data with the private key of the credential being attested.
+pub(crate) type CredentialSigner<'a> =
+    dyn Fn(&[u8]) -> Result<Vec<u8>, Ctap2Error> + Send + Sync + 'a;
+
+/// Produces the attestation statement the [`Authenticator`] returns for newly created credentials.
+///
+/// See [Attestation Types] for more information. The built-in providers are [`Attestation`],
+/// [`PackedAttestation`], [`BatchAttestation`] and [`FidoU2fAttestation`].
+///
+/// Creating a statement is asynchronous so that implementations may have the
+/// [`AttestationInput::signature_target`] signed outside of the process, for example by a remote
+/// signing service or a secure element, without the attestation key ever being loaded.
+///
+/// [Attestation Types]: https://w3c.github.io/webauthn/#sctn-attestation-types
+#[async_trait::async_trait]
+pub trait AttestationProvider {
+    /// Create the attestation statement for a new credential.
+    async fn attest(
+        &self,
+        input: &AttestationInput<'_>,
+    ) -> Result<AttestationStatement, Ctap2Error>;
+}
+
+/// Everything an [`AttestationProvider`] may need to attest a new credential.
+pub struct AttestationInput<'a> {
+    /// The serialized authenticator data of the new credential.
+    pub auth_data: &'a [u8],
+    /// The hash of the client data given to `make_credential`.
+    pub client_data_hash: &'a [u8],
+    /// The algorithm of the new credential.
+    pub algorithm: iana::Algorithm,
+    /// The AAGUID of the authenticator creating the credential.
+    pub aaguid: Aaguid,
+    pub(crate) credential_signer: &'a CredentialSigner<'a>,
+}
+
+impl AttestationInput<'_> {
+    /// The data over which attestation signatures are made: `authData || clientDataHash`.
+    pub fn signature_target(&self) -> Vec<u8> {
+        let mut target = self.auth_data.to_vec();
+        target.extend_from_slice(self.client_data_hash);
+        target
+    }
+
+    /// Sign `data` with the new credential's private key, as used for self attestation.
+    ///
+    /// ECDSA signatures are DER encoded.
+    pub fn sign_with_credential(&self, data: &[u8]) -> Result<Vec<u8>, Ctap2Error> {
+        (self.credential_signer)(data)
+    }
+}
+
+/// An attestation statement along with its [format identifier][fmt].
+///
+/// [fmt]: https://w3c.github.io/webauthn/#sctn-attstn-fmt-ids
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationStatement {
+    /// The attestation statement format identifier, e.g. `none` or `packed`.
+    pub fmt: String,
+    /// The attestation statement, whose structure is defined by [`Self::fmt`].
+    pub att_stmt: Value,
+}
+
+/// The kind of attestation statement the [`Authenticator`] returns for newly created credentials,
+/// for attestation which needs no key or certificate besides the credential's own.
+///
+/// Attestation by an attestation key is provided by [`PackedAttestation::Basic`] and the other
+/// [`AttestationProvider`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Attestation {
+    /// Return an empty statement with the `none` attestation format.
+    #[default]
+    None,
+    /// Return a `packed` statement signed by the credential's own private key, proving possession
+    /// of the key for the algorithm it claims. See [Self Attestation].
+    ///
+    /// [Self Attestation]: https://w3c.github.io/webauthn/#self-attestation
+    SelfAttestation,
+}
+
+impl Attestation {
+    /// The [attestation statement format identifier][fmt] of this attestation.
+    ///
+    /// [fmt]: https://w3c.github.io/webauthn/#sctn-attstn-fmt-ids
+    pub fn format(&self) -> &'static str {
+        match self {
+            Attestation::None => "none",
+            Attestation::SelfAttestation => "packed",
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AttestationProvider for Attestation {
+    async fn attest(
+        &self,
+        input: &AttestationInput<'_>,
+    ) -> Result<AttestationStatement, Ctap2Error> {
+        match self {
+            Attestation::None => Ok(AttestationStatement {
+                fmt: self.format().into(),
+                att_stmt: Value::Map(Vec::new()),
+            }),
+            Attestation::SelfAttestation => PackedAttestation::SelfAttestation.attest(input).await,
+        }
+    }
+}
+
+/// Produces statements in the [packed attestation format][packed].
+///
+/// [packed]: https://w3c.github.io/webauthn/#sctn-packed-attestation
+pub enum PackedAttestation {
+    /// Sign the statement with the credential's own private key, proving possession of the key
+    /// for the algorithm it claims. See [Self Attestation].
+    ///
+    /// [Self Attestation]: https://w3c.github.io/webauthn/#self-attestation
+    SelfAttestation,
+    /// Sign the statement with an attestation key and include its certificate chain. See
+    /// [Basic Attestation].
+    ///
+    /// [Basic Attestation]: https://w3c.github.io/webauthn/#basic-attestation
+    ///
+    /// The chain's AAGUID extension, if present, must match the authenticator's AAGUID.
+    Basic(AttestationChain),
+}
+
+#[async_trait::async_trait]
+impl AttestationProvider for PackedAttestation {
+    async fn attest(
+        &self,
+        input: &AttestationInput<'_>,
+    ) -> Result<AttestationStatement, Ctap2Error> {
+        match self {
+            PackedAttestation::SelfAttestation => Ok(AttestationStatement {
+                fmt: "packed".into(),
+                att_stmt: packed_statement(
+                    input.algorithm.to_i64(),
+                    input.sign_with_credential(&input.signature_target())?,
+                    None,
+                ),
+            }),
+            PackedAttestation::Basic(chain) => basic_packed_statement(chain, input),
+        }
+    }
+}
+
+/// Sign a packed statement with the attestation key of `chain`.
+fn basic_packed_statement(
+    chain: &AttestationChain,
+    input: &AttestationInput<'_>,
+) -> Result<AttestationStatement, Ctap2Error> {
+    chain.validate_aaguid(&input.aaguid)?;
+    Ok(AttestationStatement {
+        fmt: "packed".into(),
+        att_stmt: packed_statement(
+            chain.algorithm().to_i64(),
+            chain.sign(&input.signature_target())?,
+            Some(chain.certificates()),
+        ),
+    })
+}
+
+/// Produces statements in the [FIDO U2F attestation format][fido-u2f], for relying parties which
+/// only understand CTAP1/U2F registrations.
+///
+/// The statement is signed with a P-256 attestation key over the same data as a U2F registration
+/// response, which limits it to [`iana::Algorithm::ES256`] credentials. Set it with
+/// [`Authenticator::with_u2f_attestation`] to also attest registrations made through
+/// [`U2fApi`](crate::U2fApi).
+///
+/// [fido-u2f]: https://w3c.github.io/webauthn/#sctn-fido-u2f-attestation
+#[derive(Debug, Clone)]
+pub struct FidoU2fAttestation {
+    chain: Arc<AttestationChain>,
+}
+
+impl FidoU2fAttestation {
+    /// Use the attestation key and leaf certificate of `chain`, which must be a P-256 key.
+    pub fn new(chain: AttestationChain) -> Result<Self, AttestationChainError> {
+        if chain.algorithm() != iana::Algorithm::ES256 {
+            return Err(AttestationChainError::UnsupportedAlgorithm);
+        }
+        Ok(Self {
+            chain: Arc::new(chain),
+        })
+    }
+
+    /// The DER encoded attestation certificate. The format only carries the leaf certificate.
+    pub fn certificate(&self) -> &[u8] {
+        // An AttestationChain always contains at least one certificate.
+        &self.chain.certificates()[0]
+    }
+
+    /// Sign a U2F registration, see [`passkey_types::u2f::RegisterResponse::signature`].
+    ///
+    /// `public_key` is the uncompressed `0x04 || x || y` encoding of the credential public key.
+    pub(crate) fn sign_registration(
+        &self,
+        application: &[u8],
+        challenge: &[u8],
+        key_handle: &[u8],
+        public_key: &[u8],
+    ) -> Result<Vec<u8>, Ctap2Error> {
+        self.chain
+            .sign(&[&[0x00], application, challenge, key_handle, public_key].concat())
+    }
+}
+
+#[async_trait::async_trait]
+impl AttestationProvider for FidoU2fAttestation {
+    async fn attest(
+        &self,
+        input: &AttestationInput<'_>,
+    ) -> Result<AttestationStatement, Ctap2Error> {
+        if input.algorithm != iana::Algorithm::ES256 {
+            return Err(Ctap2Error::UnsupportedAlgorithm);
+        }
+        self.chain.validate_aaguid(&input.aaguid)?;
+
+        let auth_data =
+            AuthenticatorData::from_slice(input.auth_data).map_err(|_| Ctap2Error::InvalidCbor)?;
+        let credential = auth_data
+            .attested_credential_data
+            .as_ref()
+            .ok_or(Ctap2Error::InvalidCredential)?;
+        let (x, y) = ec2_coordinates(&credential.key)?;
+        let sig = self.sign_registration(
+            auth_data.rp_id_hash(),
+            input.client_data_hash,
+            credential.credential_id(),
+            &[&[0x04], x, y].concat(),
+        )?;
+
+        Ok(AttestationStatement {
+            fmt: "fido-u2f".into(),
+            att_stmt: Value::Map(vec![
+                (Value::Text("sig".into()), Value::Bytes(sig)),
+                (
+                    Value::Text("x5c".into()),
+                    Value::Array(vec![Value::Bytes(self.certificate().to_vec())]),
+                ),
+            ]),
+        })
+    }
+}
+
+fn packed_statement(alg: i64, sig: Vec<u8>, x5c: Option<&[Vec<u8>]>) -> Value {
+    let mut statement = vec![
+        (Value::Text("alg".into()), Value::Integer(alg.into())),
+        (Value::Text("sig".into()), Value::Bytes(sig)),
+    ];
+    if let Some(x5c) = x5c {
+        statement.push((
+            Value::Text("x5c".into()),
+            Value::Array(x5c.iter().cloned().map(Value::Bytes).collect()),
+        ));
+    }
+    Value::Map(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use p256::ecdsa::{signature::Verifier, Signature};
+    use passkey_types::rand::random_vec;
+
+    use passkey_types::ctap2::AttestedCredentialData;
+
+    use super::*;
+    use crate::CoseKeyPair;
+
+    fn field<'a>(statement: &'a Value, name: &str) -> Option<&'a Value> {
+        statement
+            .as_map()?
+            .iter()
+            .find(|(k, _)| k.as_text() == Some(name))
+            .map(|(_, v)| v)
+    }
+
+    #[tokio::test]
+    async fn basic_packed_attestation() {
+        let secret_key = p256::SecretKey::random(&mut rand::thread_rng());
+        let verifying_key = p256::ecdsa::VerifyingKey::from(secret_key.public_key());
+        let aaguid = Aaguid::from([9; 16]);
+        let certificate =
+            chain::tests::certificate(&chain::tests::spki(&secret_key), Some(aaguid.0));
+        let provider = PackedAttestation::Basic(
+            AttestationChain::new(
+                CoseKeyPair::from_secret_key(&secret_key, iana::Algorithm::ES256)
+                    .into_parts()
+                    .1,
+                vec![certificate.clone()],
+            )
+            .expect("failed to load chain"),
+        );
+
+        let auth_data = random_vec(37);
+        let client_data_hash = random_vec(32);
+        let input = AttestationInput {
+            auth_data: &auth_data,
+            client_data_hash: &client_data_hash,
+            algorithm: iana::Algorithm::EdDSA,
+            aaguid,
+            credential_signer: &|_| panic!("basic attestation must not use the credential key"),
+        };
+        let statement = provider.attest(&input).await.expect("failed to attest");
+        assert_eq!(statement.fmt, "packed");
+
+        let att_stmt = statement.att_stmt;
+        assert_eq!(
+            field(&att_stmt, "alg").and_then(Value::as_integer),
+            Some(iana::Algorithm::ES256.to_i64().into())
+        );
+        assert_eq!(
+            field(&att_stmt, "x5c"),
+            Some(&Value::Array(vec![Value::Bytes(certificate)]))
+        );
+        let sig = field(&att_stmt, "sig")
+            .and_then(Value::as_bytes)
+            .expect("missing signature");
+        verifying_key
+            .verify(
+                &input.signature_target(),
+                &Signature::from_der(sig).expect("not a DER signature"),
+            )
+            .expect("failed to verify attestation signature");
+
+        let other_authenticator = AttestationInput {
+            aaguid: Aaguid::new_empty(),
+            ..input
+        };
+        assert_eq!(
+            provider.attest(&other_authenticator).await.unwrap_err(),
+            Ctap2Error::InvalidCredential
+        );
+    }
+
+    #[tokio::test]
+    async fn none_attestation_is_empty() {
+        let input = AttestationInput {
+            auth_data: &[],
+            client_data_hash: &[],
+            algorithm: iana::Algorithm::ES256,
+            aaguid: Aaguid::new_empty(),
+            credential_signer: &|_| panic!("none attestation must not sign"),
+        };
+        let statement = Attestation::None.attest(&input).await.unwrap();
+        assert_eq!(statement.fmt, "none");
+        assert_eq!(statement.att_stmt, Value::Map(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn fido_u2f_attestation() {
+        let secret_key = p256::SecretKey::random(&mut rand::thread_rng());
+        let verifying_key = p256::ecdsa::VerifyingKey::from(secret_key.public_key());
+        let certificate = chain::tests::certificate(&chain::tests::spki(&secret_key), None);
+        let provider = FidoU2fAttestation::new(
+            AttestationChain::new(
+                CoseKeyPair::from_secret_key(&secret_key, iana::Algorithm::ES256)
+                    .into_parts()
+                    .1,
+                vec![certificate.clone()],
+            )
+            .expect("failed to load chain"),
+        )
+        .expect("P-256 chains are supported");
+
+        let credential_key = p256::SecretKey::random(&mut rand::thread_rng());
+        let credential_public_key =
+            CoseKeyPair::from_secret_key(&credential_key, iana::Algorithm::ES256)
+                .into_parts()
+                .0;
+        let credential_id = random_vec(16);
+        let auth_data = AuthenticatorData::new("future.1password.com", None)
+            .set_attested_credential_data(
+                AttestedCredentialData::new(
+                    Aaguid::new_empty(),
+                    credential_id.clone(),
+                    credential_public_key.clone(),
+                )
+                .unwrap(),
+            );
+        let auth_data_bytes = auth_data.to_vec();
+        let client_data_hash = random_vec(32);
+        let mut input = AttestationInput {
+            auth_data: &auth_data_bytes,
+            client_data_hash: &client_data_hash,
+            algorithm: iana::Algorithm::ES256,
+            aaguid: Aaguid::new_empty(),
+            credential_signer: &|_| panic!("fido-u2f attestation must not use the credential key"),
+        };
+        let statement = provider.attest(&input).await.expect("failed to attest");
+        assert_eq!(statement.fmt, "fido-u2f");
+        assert_eq!(
+            field(&statement.att_stmt, "x5c"),
+            Some(&Value::Array(vec![Value::Bytes(certificate)]))
+        );
+
+        let (x, y) = ec2_coordinates(&credential_public_key).unwrap();
+        let verification_data = [
+            &[0x00],
+            auth_data.rp_id_hash(),
+            &client_data_hash,
+            &credential_id,
+            &[0x04],
+            x,
+            y,
+        ]
+        .concat();
+        let sig = field(&statement.att_stmt, "sig")
+            .and_then(Value::as_bytes)
+            .expect("missing signature");
+        verifying_key
+            .verify(
+                &verification_data,
+                &Signature::from_der(sig).expect("not a DER signature"),
+            )
+            .expect("failed to verify attestation signature");
+
+        input.algorithm = iana::Algorithm::EdDSA;
+        assert_eq!(
+            provider.attest(&input).await,
+            Err(Ctap2Error::UnsupportedAlgorithm)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::SystemTime,
+};
+
+use passkey_types::ctap2::Ctap2Error;
+
+use super::{basic_packed_statement, AttestationChain, AttestationChainError};
+use crate::{AttestationInput, AttestationProvider, AttestationStatement};
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Produces [Basic Attestation] statements in the packed format from a set of attestation
+/// batches, each valid for a window of time.
+///
+/// Every statement is signed by the active batch: the most recent batch whose window contains
+/// the current time. This allows rotating the attestation key of a long running
+/// [`Authenticator`] by adding the next batch ahead of time through a clone of the provider,
+/// which shares its batches with the original.
+///
+/// [Basic Attestation]: https://w3c.github.io/webauthn/#basic-attestation
+#[derive(Debug, Clone, Default)]
+pub struct BatchAttestation {
+    batches: Arc<RwLock<Vec<AttestationBatch>>>,
+}
+
+#[derive(Debug)]
+struct AttestationBatch {
+    chain: Arc<AttestationChain>,
+    valid_from: SystemTime,
+    valid_until: SystemTime,
+}
+
+impl AttestationBatch {
+    fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.valid_from <= time && time < self.valid_until
+    }
+}
+
+impl BatchAttestation {
+    /// Create a provider without any batches.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a batch whose `chain` is used to attest credentials from `valid_from` until, but
+    /// excluding, `valid_until`.
+    pub fn add_batch(
+        &self,
+        chain: AttestationChain,
+        valid_from: SystemTime,
+        valid_until: SystemTime,
+    ) -> Result<(), AttestationChainError> {
+        if valid_from >= valid_until {
+            return Err(AttestationChainError::InvalidValidity);
+        }
+        self.batches
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(AttestationBatch {
+                chain: Arc::new(chain),
+                valid_from,
+                valid_until,
+            });
+        Ok(())
+    }
+
+    /// Remove the batches which are no longer valid at `time`, returning how many were removed.
+    pub fn remove_expired(&self, time: SystemTime) -> usize {
+        let mut batches = self.batches.write().unwrap_or_else(PoisonError::into_inner);
+        let count = batches.len();
+        batches.retain(|batch| time < batch.valid_until);
+        count - batches.len()
+    }
+
+    /// The chain of the batch active at `time`, the most recent one valid at that time.
+    pub fn active_chain(&self, time: SystemTime) -> Option<Arc<AttestationChain>> {
+        self.batches
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|batch| batch.is_valid_at(time))
+            .max_by_key(|batch| batch.valid_from)
+            .map(|batch| batch.chain.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl AttestationProvider for BatchAttestation {
+    async fn attest(
+        &self,
+        input: &AttestationInput<'_>,
+    ) -> Result<AttestationStatement, Ctap2Error> {
+        let chain = self
+            .active_chain(SystemTime::now())
+            .ok_or(AttestationChainError::NoActiveBatch)?;
+        basic_packed_statement(&chain, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use coset::iana;
+    use passkey_types::{ctap2::Aaguid, rand::random_vec};
+
+    use super::*;
+    use crate::{attestation::chain, CoseKeyPair};
+
+    fn chain() -> AttestationChain {
+        let secret_key = p256::SecretKey::random(&mut rand::thread_rng());
+        AttestationChain::new(
+            CoseKeyPair::from_secret_key(&secret_key, iana::Algorithm::ES256)
+                .into_parts()
+                .1,
+            vec![chain::tests::certificate(
+                &chain::tests::spki(&secret_key),
+                None,
+            )],
+        )
+        .expect("failed to load chain")
+    }
+
+    #[test]
+    fn rotates_to_most_recent_valid_batch() {
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let provider = BatchAttestation::new();
+        let rotated = provider.clone();
+
+        let current = chain();
+        let current_certificate = current.certificates()[0].clone();
+        provider
+            .add_batch(current, now - day, now + 2 * day)
+            .unwrap();
+        let next = chain();
+        let next_certificate = next.certificates()[0].clone();
+        rotated.add_batch(next, now + day, now + 3 * day).unwrap();
+
+        let active = |time| {
+            provider
+                .active_chain(time)
+                .map(|chain| chain.certificates()[0].clone())
+        };
+        assert_eq!(active(now), Some(current_certificate));
+        assert_eq!(active(now + day), Some(next_certificate.clone()));
+        assert_eq!(active(now + 2 * day), Some(next_certificate));
+        assert_eq!(active(now + 3 * day), None);
+        assert_eq!(active(now - 2 * day), None);
+
+        assert_eq!(provider.remove_expired(now + 2 * day), 1);
+        assert_eq!(rotated.remove_expired(now + 2 * day), 0);
+
+        assert_eq!(
+            provider.add_batch(chain(), now, now),
+            Err(AttestationChainError::InvalidValidity)
+        );
+    }
+
+    #[tokio::test]
+    async fn attest_with_active_batch() {
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(60 * 60);
+        let provider = BatchAttestation::new();
+
+        let auth_data = random_vec(37);
+        let client_data_hash = random_vec(32);
+        let input = AttestationInput {
+            auth_data: &auth_data,
+            client_data_hash: &client_data_hash,
+            algorithm: iana::Algorithm::ES256,
+            aaguid: Aaguid::new_empty(),
+            credential_signer: &|_| panic!("basic attestation must not use the credential key"),
+        };
+        assert_eq!(
+            provider.attest(&input).await.unwrap_err(),
+            Ctap2Error::InvalidCredential
+        );
+
+        provider.add_batch(chain(), now - hour, now + hour).unwrap();
+        let statement = provider.attest(&input).await.expect("failed to attest");
+        assert_eq!(statement.fmt, "packed");
+    }
+}
@@ -0,0 +1,351 @@
+use coset::{iana, CoseKey, RegisteredLabelWithPrivate};
+use der::pem::{self, PemLabel};
+use p256::pkcs8::{
+    spki::SubjectPublicKeyInfoRef, DecodePrivateKey, EncodePrivateKey, PrivateKeyInfo,
+};
+use passkey_types::{
+    cose,
+    crypto::zeroize_cose_key,
+    ctap2::{Aaguid, Ctap2Error},
+    x509::Certificate,
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    ed25519_key_from_cose_key, private_key_from_cose_key, public_key_der_from_cose_key,
+    sign_with_cose_key, CoseKeyPair,
+};
+
+/// Reasons an [`AttestationChain`] can fail to load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationChainError {
+    /// The private key is not a valid PKCS#8 document.
+    InvalidPrivateKey,
+    /// The private key's algorithm is not supported.
+    UnsupportedAlgorithm,
+    /// No certificates were given.
+    EmptyChain,
+    /// A certificate is not a valid DER or PEM encoded X.509 certificate.
+    InvalidCertificate,
+    /// The leaf certificate does not certify the private key's public key.
+    KeyMismatch,
+    /// The leaf certificate's AAGUID extension does not match the authenticator's AAGUID.
+    AaguidMismatch,
+    /// A [`BatchAttestation`](crate::BatchAttestation) batch does not end after it starts.
+    InvalidValidity,
+    /// No [`BatchAttestation`](crate::BatchAttestation) batch is valid at the current time.
+    NoActiveBatch,
+}
+
+impl From<AttestationChainError> for Ctap2Error {
+    fn from(src: AttestationChainError) -> Self {
+        match src {
+            AttestationChainError::UnsupportedAlgorithm => Ctap2Error::UnsupportedAlgorithm,
+            _ => Ctap2Error::InvalidCredential,
+        }
+    }
+}
+
+/// An attestation private key along with the X.509 certificate chain that certifies it, as used
+/// for [Basic Attestation](crate::PackedAttestation::Basic).
+///
+/// Loading a chain checks that the leaf certificate is issued for the private key. The AAGUID
+/// extension of the leaf, if present, must match the [`Authenticator`](crate::Authenticator)'s
+/// AAGUID which is checked every time a statement is produced, see
+/// [`AttestationChain::validate_aaguid`].
+///
+/// The private key is zeroized on drop.
+pub struct AttestationChain {
+    signing_key: CoseKey,
+    algorithm: iana::Algorithm,
+    certificates: Vec<Vec<u8>>,
+    aaguid: Option<Aaguid>,
+}
+
+impl AttestationChain {
+    /// Create a chain from a private [`CoseKey`] and DER encoded certificates, leaf first.
+    pub fn new(
+        signing_key: CoseKey,
+        certificates: Vec<Vec<u8>>,
+    ) -> Result<Self, AttestationChainError> {
+        let chain = Self {
+            algorithm: match signing_key.alg {
+                Some(RegisteredLabelWithPrivate::Assigned(alg)) => alg,
+                _ => return Err(AttestationChainError::UnsupportedAlgorithm),
+            },
+            signing_key,
+            certificates,
+            aaguid: None,
+        };
+        chain.validated()
+    }
+
+    /// Create a chain from a PKCS#8 DER encoded private key and DER encoded certificates, leaf
+    /// first.
+    pub fn from_der(
+        private_key: &[u8],
+        certificates: Vec<Vec<u8>>,
+    ) -> Result<Self, AttestationChainError> {
+        Self::new(cose_key_from_pkcs8(private_key)?, certificates)
+    }
+
+    /// Create a chain from a PEM encoded PKCS#8 private key (`PRIVATE KEY`) and a bundle of PEM
+    /// encoded certificates (`CERTIFICATE`), leaf first.
+    pub fn from_pem(private_key: &str, certificates: &str) -> Result<Self, AttestationChainError> {
+        let (label, private_key) = pem::decode_vec(private_key.as_bytes())
+            .map_err(|_| AttestationChainError::InvalidPrivateKey)?;
+        let private_key = Zeroizing::new(private_key);
+        if label != PrivateKeyInfo::PEM_LABEL {
+            return Err(AttestationChainError::InvalidPrivateKey);
+        }
+        Self::from_der(&private_key, pem_certificates(certificates)?)
+    }
+
+    /// The algorithm of the attestation key.
+    pub fn algorithm(&self) -> iana::Algorithm {
+        self.algorithm
+    }
+
+    /// The DER encoded certificates, leaf first.
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        &self.certificates
+    }
+
+    /// The AAGUID from the leaf certificate's `id-fido-gen-ce-aaguid` extension, if present.
+    pub fn aaguid(&self) -> Option<Aaguid> {
+        self.aaguid
+    }
+
+    /// Check that the leaf certificate may be used to attest credentials of an authenticator
+    /// with the given `aaguid`.
+    ///
+    /// Certificates without an AAGUID extension are accepted for any AAGUID.
+    pub fn validate_aaguid(&self, aaguid: &Aaguid) -> Result<(), AttestationChainError> {
+        match self.aaguid {
+            Some(ref certified) if certified != aaguid => {
+                Err(AttestationChainError::AaguidMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sign `data` with the attestation key.
+    pub(crate) fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Ctap2Error> {
+        sign_with_cose_key(&self.signing_key, data, None)
+    }
+
+    /// Parse the certificates and check the leaf against the private key.
+    fn validated(mut self) -> Result<Self, AttestationChainError> {
+        let (leaf, intermediates) = self
+            .certificates
+            .split_first()
+            .ok_or(AttestationChainError::EmptyChain)?;
+        for certificate in intermediates {
+            Certificate::from_der(certificate)
+                .map_err(|_| AttestationChainError::InvalidCertificate)?;
+        }
+        let leaf =
+            Certificate::from_der(leaf).map_err(|_| AttestationChainError::InvalidCertificate)?;
+
+        let public_key = cose::public_key_from_private(&self.signing_key)
+            .map_err(|_| AttestationChainError::InvalidPrivateKey)?;
+        let public_key = public_key_der_from_cose_key(&public_key)
+            .map_err(|_| AttestationChainError::UnsupportedAlgorithm)?;
+        let public_key = SubjectPublicKeyInfoRef::try_from(public_key.as_slice())
+            .map_err(|_| AttestationChainError::InvalidPrivateKey)?;
+        let leaf_public_key = SubjectPublicKeyInfoRef::try_from(leaf.subject_public_key_info)
+            .map_err(|_| AttestationChainError::InvalidCertificate)?;
+        if public_key.algorithm.oid != leaf_public_key.algorithm.oid
+            || public_key.subject_public_key != leaf_public_key.subject_public_key
+        {
+            return Err(AttestationChainError::KeyMismatch);
+        }
+
+        self.aaguid = leaf.aaguid;
+        Ok(self)
+    }
+}
+
+impl Drop for AttestationChain {
+    fn drop(&mut self) {
+        zeroize_cose_key(&mut self.signing_key);
+    }
+}
+
+impl std::fmt::Debug for AttestationChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttestationChain")
+            .field("algorithm", &self.algorithm)
+            .field("certificates", &self.certificates.len())
+            .field("aaguid", &self.aaguid)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Decode a PKCS#8 private key of any supported algorithm.
+pub(crate) fn cose_key_from_pkcs8(der: &[u8]) -> Result<CoseKey, AttestationChainError> {
+    PrivateKeyInfo::try_from(der).map_err(|_| AttestationChainError::InvalidPrivateKey)?;
+
+    let pair = if let Ok(key) = p256::SecretKey::from_pkcs8_der(der) {
+        CoseKeyPair::from_secret_key(&key, iana::Algorithm::ES256)
+    } else if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_der(der) {
+        CoseKeyPair::from_ed25519(&key)
+    } else {
+        #[cfg(feature = "es256k")]
+        if let Ok(key) = k256::SecretKey::from_pkcs8_der(der) {
+            return Ok(CoseKeyPair::from_k256_secret_key(&key).into_parts().1);
+        }
+        return Err(AttestationChainError::UnsupportedAlgorithm);
+    };
+    Ok(pair.into_parts().1)
+}
+
+/// The PKCS#8 document of the private `key` of a credential, or `None` if its algorithm has no
+/// such encoding here.
+pub(crate) fn pkcs8_from_cose_key(key: &CoseKey) -> Option<Vec<u8>> {
+    let document = match key.alg {
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256)) => {
+            private_key_from_cose_key(key).ok()?.to_pkcs8_der().ok()?
+        }
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::EdDSA)) => {
+            ed25519_key_from_cose_key(key).ok()?.to_pkcs8_der().ok()?
+        }
+        _ => return None,
+    };
+    Some(document.as_bytes().to_vec())
+}
+
+/// Split a bundle of PEM encoded certificates into their DER encodings.
+fn pem_certificates(bundle: &str) -> Result<Vec<Vec<u8>>, AttestationChainError> {
+    bundle
+        .split_inclusive("-----END CERTIFICATE-----")
+        .map(str::trim)
+        .filter(|block| !block.is_empty())
+        .map(|block| match pem::decode_vec(block.as_bytes()) {
+            Ok(("CERTIFICATE", der)) => Ok(der),
+            _ => Err(AttestationChainError::InvalidCertificate),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use der::pem::LineEnding;
+    use p256::pkcs8::{EncodePrivateKey, EncodePublicKey};
+    use passkey_types::x509::AAGUID_EXTENSION;
+
+    use super::*;
+
+    /// Encode a DER TLV with the given tag byte.
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len @ 0x80..=0xff => out.extend([0x81, len as u8]),
+            len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// Build a minimal unsigned certificate for `public_key`, with an optional AAGUID extension.
+    pub(crate) fn certificate(public_key: &[u8], aaguid: Option<[u8; 16]>) -> Vec<u8> {
+        // ecdsa-with-SHA256
+        let algorithm = tlv(
+            0x30,
+            &tlv(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02]),
+        );
+        let validity = [tlv(0x17, b"250101000000Z"), tlv(0x17, b"350101000000Z")].concat();
+
+        let mut tbs = [
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            algorithm.clone(),
+            tlv(0x30, &[]),
+            tlv(0x30, &validity),
+            tlv(0x30, &[]),
+            public_key.to_vec(),
+        ]
+        .concat();
+        if let Some(aaguid) = aaguid {
+            let extension = [
+                tlv(0x06, AAGUID_EXTENSION.as_bytes()),
+                tlv(0x04, &tlv(0x04, &aaguid)),
+            ]
+            .concat();
+            tbs.extend(tlv(0xa3, &tlv(0x30, &tlv(0x30, &extension))));
+        }
+
+        tlv(
+            0x30,
+            &[tlv(0x30, &tbs), algorithm, tlv(0x03, &[0, 0x30, 0x00])].concat(),
+        )
+    }
+
+    fn key() -> p256::SecretKey {
+        p256::SecretKey::random(&mut rand::thread_rng())
+    }
+
+    pub(crate) fn spki(key: &p256::SecretKey) -> Vec<u8> {
+        key.public_key().to_public_key_der().unwrap().into_vec()
+    }
+
+    #[test]
+    fn load_chain_from_pem() {
+        let key = key();
+        let aaguid = Aaguid::from([9; 16]);
+        let leaf = certificate(&spki(&key), Some(aaguid.0));
+        let root = certificate(&spki(&self::key()), None);
+        let bundle = [&leaf, &root]
+            .iter()
+            .map(|der| pem::encode_string("CERTIFICATE", LineEnding::LF, der).unwrap())
+            .collect::<String>();
+
+        let chain = AttestationChain::from_pem(&key.to_pkcs8_pem(LineEnding::LF).unwrap(), &bundle)
+            .expect("failed to load chain");
+        assert_eq!(chain.algorithm(), iana::Algorithm::ES256);
+        assert_eq!(chain.certificates(), &[leaf, root]);
+        assert_eq!(chain.aaguid(), Some(aaguid));
+        assert_eq!(chain.validate_aaguid(&aaguid), Ok(()));
+        assert_eq!(
+            chain.validate_aaguid(&Aaguid::new_empty()),
+            Err(AttestationChainError::AaguidMismatch)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_chains() {
+        let key = key();
+        let private_key = key.to_pkcs8_der().unwrap();
+
+        assert_eq!(
+            AttestationChain::from_der(private_key.as_bytes(), Vec::new()).unwrap_err(),
+            AttestationChainError::EmptyChain
+        );
+        assert_eq!(
+            AttestationChain::from_der(private_key.as_bytes(), vec![vec![0x30, 0x00]]).unwrap_err(),
+            AttestationChainError::InvalidCertificate
+        );
+        assert_eq!(
+            AttestationChain::from_der(
+                private_key.as_bytes(),
+                vec![certificate(&spki(&self::key()), None)]
+            )
+            .unwrap_err(),
+            AttestationChainError::KeyMismatch
+        );
+        assert_eq!(
+            AttestationChain::from_der(&[1, 2, 3], vec![certificate(&spki(&key), None)])
+                .unwrap_err(),
+            AttestationChainError::InvalidPrivateKey
+        );
+
+        let chain = AttestationChain::from_der(
+            private_key.as_bytes(),
+            vec![certificate(&spki(&key), None)],
+        )
+        .expect("failed to load chain");
+        assert_eq!(chain.aaguid(), None);
+        assert_eq!(chain.validate_aaguid(&Aaguid::from([1; 16])), Ok(()));
+    }
+}
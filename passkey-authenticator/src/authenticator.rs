@@ -1,14 +1,44 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    num::NonZeroU128,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
 use coset::iana;
 use passkey_types::{
-    ctap2::{Aaguid, Ctap2Error, Flags},
+    ctap2::{Aaguid, Ctap2Error, Flags, StatusCode, U2FError},
     webauthn,
 };
+use rand_core::CryptoRngCore;
 
-use crate::{CredentialStore, UserValidationMethod};
+use crate::{
+    ecdsa_der_to_raw, extensions::ExtensionRegistry, user_validation::UvLockout, Attestation,
+    AttestationProvider, AuthenticatorConfigStore, CancellationToken, Clock, CredentialStore,
+    DeviceKeyStore, EcdsaNonce, Extension, ExtensionHandler, FidoU2fAttestation, HmacSecretConfig,
+    KeepaliveStatus, KeyProvider, LargeBlobStore, MasterSeed, PinState, PinStore, SignatureFormat,
+    SoftwareKeyProvider, SystemClock, UserValidationMethod, UvRateLimit, WrappingKey,
+};
 
+mod authenticator_config;
+mod bio_enrollment;
+mod client_pin;
+mod credential_management;
+mod device_pub_key;
 mod get_assertion;
 mod get_info;
+mod large_blobs;
 mod make_credential;
+mod reset;
+mod selection;
+
+/// An [`AttestationProvider`] used for enterprise attestation, and the RP IDs which may receive
+/// it when vendor facilitated.
+struct EnterpriseAttestation {
+    provider: Box<dyn AttestationProvider + Send + Sync>,
+    rp_ids: Vec<String>,
+}
 
 /// A virtual authenticator with all the necessary state and information.
 pub struct Authenticator<S, U> {
@@ -16,14 +46,90 @@ pub struct Authenticator<S, U> {
     aaguid: Aaguid,
     /// Provides credential storage capabilities
     store: S,
-    /// Current supported algorithms by the authenticator
-    algs: Vec<iana::Algorithm>,
+    /// Generates the credential key pairs and signs with them. This also determines the
+    /// algorithms supported by the authenticator.
+    key_provider: Box<dyn KeyProvider + Send + Sync>,
+    /// Master secret used to wrap non-discoverable credentials into their credential ID instead of
+    /// storing them. When `None`, every credential is saved in the store.
+    wrapping_key: Option<WrappingKey>,
+    /// Master seed from which credential keys are derived instead of generated. Takes precedence
+    /// over the wrapping key when both are set.
+    master_seed: Option<MasterSeed>,
+    /// Source of randomness for credential IDs, key generation and key wrapping.
+    ///
+    /// Defaults to the operating system's RNG.
+    rng: Mutex<Box<dyn CryptoRngCore + Send>>,
+    /// How ECDSA signature nonces are generated, see [`EcdsaNonce`].
+    ecdsa_nonce: EcdsaNonce,
+    /// How ECDSA signatures are encoded in assertions, see [`SignatureFormat`].
+    signature_format: SignatureFormat,
+    /// Produces the attestation statement returned from `make_credential`.
+    attestation_provider: Box<dyn AttestationProvider + Send + Sync>,
+    /// Produces enterprise attestation statements, see [`Authenticator::with_enterprise_attestation`].
+    enterprise_attestation: Option<EnterpriseAttestation>,
+    /// Attests U2F registrations when operating in U2F compatibility mode. When `None`, U2F
+    /// registrations are signed with the new credential's own key.
+    u2f_attestation: Option<FidoU2fAttestation>,
     /// Current supported transports that this authenticator can use to communicate.
     ///
     /// Default values are [`AuthenticatorTransport::Internal`] and [`AuthenticatorTransport::Hybrid`].
     transports: Vec<webauthn::AuthenticatorTransport>,
+    /// How the authenticator is attached to the client, derived from its transports when `None`,
+    /// see [`Authenticator::with_attachment`].
+    attachment: Option<webauthn::AuthenticatorAttachment>,
+    /// The largest message the authenticator's transport can receive, reported in `get_info`.
+    max_msg_size: Option<NonZeroU128>,
+    /// The most credentials the platform may send in an allow or exclude list, reported in
+    /// `get_info`.
+    max_credential_count_in_list: Option<u32>,
+    /// The longest credential ID the platform may send in an allow or exclude list, reported in
+    /// `get_info`.
+    max_credential_id_length: Option<u32>,
+    /// The vendor's firmware version, reported in `get_info`.
+    firmware_version: Option<u64>,
+    /// The certifications of the authenticator and their levels, reported in `get_info`.
+    certifications: Option<BTreeMap<Cow<'static, str>, u64>>,
     /// Provider of user verification factor.
     user_validation: U,
+    /// Throttling of failed user verifications, disabled when `None`.
+    uv_rate_limit: Option<UvRateLimit>,
+    /// The failed user verifications counted against `uv_rate_limit`.
+    uv_lockout: Mutex<UvLockout>,
+    /// Aborts the operation in progress while it waits on the user.
+    cancellation: CancellationToken,
+    /// Reports the status of the operation in progress, see [`Authenticator::with_keepalive`].
+    keepalive: Option<Box<dyn Fn(KeepaliveStatus) + Send + Sync>>,
+    /// Source of the current time for PIN/UV auth token expiry and user verification lockouts.
+    ///
+    /// Defaults to the operating system's clock.
+    clock: Box<dyn Clock + Send + Sync>,
+    /// The PIN, PIN token and key agreement state of `authenticatorClientPIN`.
+    client_pin: client_pin::ClientPin,
+    /// The remainder of the RP or credential enumeration in progress through
+    /// `authenticatorCredentialManagement`.
+    credential_enumeration: Option<credential_management::Enumeration>,
+    /// The large-blob array, the `largeBlobKey`s of credentials and the write in progress of
+    /// `authenticatorLargeBlobs`.
+    large_blobs: large_blobs::LargeBlobs,
+    /// The device keys of the `devicePubKey` extension. They may be generated during
+    /// `get_assertion`, hence the lock.
+    device_keys: Mutex<device_pub_key::DeviceKeys>,
+    /// The handlers of custom extensions, see [`Authenticator::with_extension`].
+    extensions: ExtensionRegistry,
+    /// How the CredRandoms of the `hmac-secret` extension are used.
+    hmac_secret_config: HmacSecretConfig,
+    /// Whether new credentials which are stored are backup eligible, and backed up.
+    backup_eligible: bool,
+    backup_state: bool,
+    /// Whether a new discoverable credential overwrites those of the same RP and user.
+    overwrite_credentials: bool,
+    /// The ID of the enrollment in progress through `authenticatorBioEnrollment`.
+    bio_enrollment: Option<Vec<u8>>,
+    /// The configuration changed through `authenticatorConfig`.
+    config: authenticator_config::Config,
+    /// The capabilities reported by `authenticatorGetInfo` which come from the store and the
+    /// configuration, computed on first use and dropped whenever they may have changed.
+    info: Mutex<Option<passkey_types::ctap2::get_info::Response>>,
 
     /// The display name given when a [`webauthn::CredentialPropertiesOutput`] is requested
     display_name: Option<String>,
@@ -39,13 +145,43 @@ where
         Self {
             aaguid,
             store,
-            // TODO: Change this to a method on the cryptographic backend
-            algs: vec![iana::Algorithm::ES256],
+            key_provider: Box::new(SoftwareKeyProvider),
+            wrapping_key: None,
+            master_seed: None,
+            rng: Mutex::new(Box::new(rand::rngs::OsRng)),
+            ecdsa_nonce: EcdsaNonce::default(),
+            signature_format: SignatureFormat::default(),
+            attestation_provider: Box::new(Attestation::default()),
+            enterprise_attestation: None,
+            u2f_attestation: None,
             transports: vec![
                 webauthn::AuthenticatorTransport::Internal,
                 webauthn::AuthenticatorTransport::Hybrid,
             ],
+            attachment: None,
+            max_msg_size: None,
+            max_credential_count_in_list: None,
+            max_credential_id_length: None,
+            firmware_version: None,
+            certifications: None,
             user_validation: user,
+            uv_rate_limit: None,
+            uv_lockout: Mutex::default(),
+            cancellation: CancellationToken::new(),
+            keepalive: None,
+            clock: Box::new(SystemClock),
+            client_pin: Default::default(),
+            credential_enumeration: None,
+            large_blobs: Default::default(),
+            device_keys: Mutex::default(),
+            extensions: ExtensionRegistry::default(),
+            hmac_secret_config: HmacSecretConfig::default(),
+            backup_eligible: true,
+            backup_state: true,
+            overwrite_credentials: true,
+            bio_enrollment: None,
+            config: Default::default(),
+            info: Mutex::default(),
             display_name: None,
         }
     }
@@ -67,18 +203,525 @@ where
 
     /// Exclusively access the [`CredentialStore`] to look into what is stored and modify it if needed.
     pub fn store_mut(&mut self) -> &mut S {
+        // Modifying the store may change its remaining capacity.
+        self.invalidate_info();
         &mut self.store
     }
 
+    /// Access the [`KeyProvider`] used to generate and sign with credential keys.
+    pub fn key_provider(&self) -> &(dyn KeyProvider + Send + Sync) {
+        self.key_provider.as_ref()
+    }
+
+    /// Builder method for replacing the default [`SoftwareKeyProvider`], for example with one
+    /// backed by a platform keystore.
+    pub fn with_key_provider(self, key_provider: impl KeyProvider + Send + Sync + 'static) -> Self {
+        Self {
+            key_provider: Box::new(key_provider),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for enabling wrapped non-discoverable credentials.
+    ///
+    /// Credentials created with `rk=false` will have their private key encrypted with the given
+    /// [`WrappingKey`] and embedded in the credential ID rather than being saved in the
+    /// [`CredentialStore`]. They are unwrapped again from the allowList during assertions.
+    pub fn with_wrapping_key(self, wrapping_key: impl Into<WrappingKey>) -> Self {
+        Self {
+            wrapping_key: Some(wrapping_key.into()),
+            ..self
+        }
+    }
+
+    /// Builder method for enabling per-RP key derivation from a device master seed.
+    ///
+    /// Credential keys are derived with HKDF from the [`MasterSeed`] and the credential ID instead
+    /// of being generated and stored, see [`MasterSeed`] for details. This takes precedence over
+    /// [`Authenticator::with_wrapping_key`].
+    pub fn with_master_seed(self, master_seed: impl Into<MasterSeed>) -> Self {
+        Self {
+            master_seed: Some(master_seed.into()),
+            ..self
+        }
+    }
+
+    /// Builder method for persisting the PIN state in the given [`PinStore`], loading the state
+    /// saved in it.
+    ///
+    /// Defaults to keeping the PIN state in memory, in which case the PIN is lost along with the
+    /// authenticator.
+    pub fn with_pin_store(self, pin_store: impl PinStore + Send + Sync + 'static) -> Self {
+        Self {
+            client_pin: client_pin::ClientPin::new(Box::new(pin_store)),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for persisting the large-blob array and the `largeBlobKey`s of credentials
+    /// in the given [`LargeBlobStore`], loading the state saved in it.
+    ///
+    /// Defaults to keeping them in memory, in which case they are lost along with the
+    /// authenticator.
+    pub fn with_large_blob_store(
+        self,
+        large_blob_store: impl LargeBlobStore + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            large_blobs: large_blobs::LargeBlobs::new(Box::new(large_blob_store)),
+            ..self
+        }
+    }
+
+    /// Builder method for persisting the device keys of the `devicePubKey` extension in the given
+    /// [`DeviceKeyStore`], loading the keys saved in it.
+    ///
+    /// Defaults to keeping them in memory, in which case they are lost along with the
+    /// authenticator and a new device key is generated for every credential on next use.
+    pub fn with_device_key_store(
+        self,
+        device_key_store: impl DeviceKeyStore + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            device_keys: Mutex::new(device_pub_key::DeviceKeys::new(Box::new(device_key_store))),
+            ..self
+        }
+    }
+
+    /// Builder method for handling the custom extension with the given `identifier`, such as a
+    /// vendor extension, with `handler`. Registering an identifier again replaces its handler.
+    ///
+    /// Handlers may contribute outputs to the signed extensions of the authenticator data as well
+    /// as to the unsigned extension outputs of `make_credential` and `get_assertion`. Extensions
+    /// which the authenticator supports itself cannot be handled this way.
+    pub fn with_extension(
+        mut self,
+        identifier: impl Into<String>,
+        handler: impl ExtensionHandler + Send + Sync + 'static,
+    ) -> Self {
+        self.extensions
+            .register(identifier.into(), Box::new(handler));
+        Self {
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for enabling only the given built-in extensions, which are reported in
+    /// `get_info` along with the custom ones. The inputs of the other built-in extensions are
+    /// ignored.
+    ///
+    /// Defaults to [`Extension::ALL`].
+    pub fn with_builtin_extensions(
+        mut self,
+        extensions: impl IntoIterator<Item = Extension>,
+    ) -> Self {
+        self.extensions.set_builtins(extensions);
+        Self {
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for choosing how the CredRandoms of the `hmac-secret` extension are used,
+    /// see [`HmacSecretConfig`].
+    pub fn with_hmac_secret_config(self, hmac_secret_config: HmacSecretConfig) -> Self {
+        Self {
+            hmac_secret_config,
+            ..self
+        }
+    }
+
+    /// Builder method for choosing the Backup Eligibility and Backup State flags of new
+    /// credentials, for example to create device-bound credentials with neither, or synced
+    /// credentials which are only backed up once they are synced, see
+    /// [`Authenticator::set_backup_state`]. A credential cannot be backed up without being backup
+    /// eligible, so `backup_state` is ignored without `backup_eligible`.
+    ///
+    /// Credentials which are wrapped into or derived from their credential ID rather than stored
+    /// are never backed up, so they never have either flag.
+    ///
+    /// Defaults to both flags being set.
+    pub fn with_backup_flags(self, backup_eligible: bool, backup_state: bool) -> Self {
+        Self {
+            backup_eligible,
+            backup_state: backup_eligible && backup_state,
+            ..self
+        }
+    }
+
+    /// Set whether a new discoverable credential overwrites the credentials saved for the same RP
+    /// and user ID, as found by [`CredentialStore::find_by_rp_and_user_handle`], which are deleted
+    /// when the new one is saved. Disable it for stores which intentionally keep several
+    /// credentials per user.
+    ///
+    /// Overwriting requires the store to implement either [`CredentialStore::enumerate`] or
+    /// [`CredentialStore::find_by_rp_and_user_handle`], otherwise no credential is found to
+    /// overwrite and every credential is kept.
+    ///
+    /// Defaults to overwriting, as required by step 10 of `authenticatorMakeCredential`.
+    pub fn with_credential_overwrite(self, overwrite_credentials: bool) -> Self {
+        Self {
+            overwrite_credentials,
+            ..self
+        }
+    }
+
+    /// The Backup Eligibility and Backup State flags of new credentials which are stored, see
+    /// [`Authenticator::with_backup_flags`].
+    pub(crate) fn backup_flags(&self) -> (bool, bool) {
+        (self.backup_eligible, self.backup_state)
+    }
+
+    /// Builder method for persisting the configuration changed through `authenticatorConfig` in
+    /// the given [`AuthenticatorConfigStore`], loading the configuration saved in it.
+    ///
+    /// Defaults to keeping the configuration in memory, in which case it is lost along with the
+    /// authenticator.
+    pub fn with_config_store(
+        self,
+        config_store: impl AuthenticatorConfigStore + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            config: authenticator_config::Config::new(Box::new(config_store)),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// The current PIN and user verification status, for hosts to display without going through
+    /// `authenticatorClientPIN`.
+    pub fn pin_state(&self) -> PinState {
+        let stored = self.client_pin.stored();
+        let locked_out =
+            self.uv_rate_limit.is_some() && self.uv_lockout().is_locked_out(self.now());
+        PinState {
+            is_set: self.client_pin.is_set(),
+            retries: stored.retries,
+            uv_blocked: stored.uv_retries == 0 || locked_out,
+        }
+    }
+
+    /// Simulate the authenticator being power cycled.
+    ///
+    /// This unblocks PIN operations after too many consecutive wrong PINs, and invalidates the
+    /// key agreement key, PIN/UV auth token and any credential enumeration or bio enrollment in
+    /// progress. The persisted PIN state, including the remaining retries, is unaffected.
+    pub fn power_cycle(&mut self) {
+        self.client_pin.power_cycle();
+        self.credential_enumeration = None;
+        self.bio_enrollment = None;
+    }
+
+    /// Builder method for replacing the operating system's RNG with a caller supplied one, such as
+    /// a hardware RNG or a seeded RNG for reproducible tests.
+    pub fn with_rng(self, rng: impl CryptoRngCore + Send + 'static) -> Self {
+        Self {
+            rng: Mutex::new(Box::new(rng)),
+            ..self
+        }
+    }
+
+    /// Builder method for replacing the operating system's clock, for example with one which a
+    /// test controls to step through PIN/UV auth token expiry or a user verification lockout.
+    pub fn with_clock(self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// The current time according to the authenticator's [`Clock`].
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Builder method for throttling user verification.
+    ///
+    /// After [`UvRateLimit::max_consecutive_failures`] failed user verifications for the "uv"
+    /// option of `make_credential` and `get_assertion`, user verification is refused with
+    /// `CTAP2_ERR_UV_BLOCKED` for the [`UvRateLimit::lockout`] duration. This delay comes on top
+    /// of the UV retries, whose exhaustion blocks user verification until the authenticator is
+    /// reset, whether or not the rate limit is enabled. Disabled by default.
+    pub fn with_uv_rate_limit(self, uv_rate_limit: UvRateLimit) -> Self {
+        Self {
+            uv_rate_limit: Some(uv_rate_limit),
+            ..self
+        }
+    }
+
+    /// The token with which a host can abort the operation in progress while it waits on the user,
+    /// from another task.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Builder method for reporting the status of operations to the transport, so it can send
+    /// keepalive messages to the host while they wait on the user.
+    ///
+    /// Operations which may wait on the user report [`KeepaliveStatus::Processing`] when they
+    /// start, [`KeepaliveStatus::UpNeeded`] while they wait and [`KeepaliveStatus::Processing`]
+    /// again once the user responded.
+    pub fn with_keepalive(
+        self,
+        keepalive: impl Fn(KeepaliveStatus) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            keepalive: Some(Box::new(keepalive)),
+            ..self
+        }
+    }
+
+    /// Start an operation which may wait on the user, forgetting earlier cancellations.
+    pub(crate) fn begin_operation(&self) {
+        self.cancellation.reset();
+        self.report(KeepaliveStatus::Processing);
+    }
+
+    /// Wait on the user through `future`, unless the operation is cancelled first.
+    pub(crate) async fn wait_on_user<F: std::future::Future>(
+        &self,
+        future: F,
+    ) -> Result<F::Output, Ctap2Error> {
+        self.report(KeepaliveStatus::UpNeeded);
+        let result = self.cancellation.run(future).await;
+        self.report(KeepaliveStatus::Processing);
+        result
+    }
+
+    fn report(&self, status: KeepaliveStatus) {
+        if let Some(keepalive) = &self.keepalive {
+            keepalive(status);
+        }
+    }
+
+    /// Exclusively access the failed user verifications counted against the [`UvRateLimit`].
+    fn uv_lockout(&self) -> MutexGuard<'_, UvLockout> {
+        // The counters hold no invariants that a panic could break, so recover from poisoning.
+        self.uv_lockout
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drop the snapshot of the capabilities reported by [`Authenticator::get_info`], so that they
+    /// are computed again when next needed.
+    ///
+    /// The authenticator does this itself whenever its configuration, PIN or credentials change.
+    /// Call this when the [`CredentialStore`] changes outside of it, like when a store shared with
+    /// other authenticators frees up space.
+    pub fn invalidate_info(&self) {
+        *self.info_snapshot() = None;
+    }
+
+    /// Exclusively access the snapshot of the `authenticatorGetInfo` response.
+    fn info_snapshot(&self) -> MutexGuard<'_, Option<passkey_types::ctap2::get_info::Response>> {
+        // The snapshot is only ever replaced as a whole, so recover from poisoning.
+        self.info.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Exclusively access the device keys of the `devicePubKey` extension.
+    pub(crate) fn device_keys(&self) -> MutexGuard<'_, device_pub_key::DeviceKeys> {
+        // The device keys are only replaced once saved, so recover from poisoning.
+        self.device_keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The handlers of custom extensions.
+    pub(crate) fn extensions(&self) -> &ExtensionRegistry {
+        &self.extensions
+    }
+
+    /// Exclusively access the authenticator's RNG.
+    pub(crate) fn rng(&self) -> MutexGuard<'_, Box<dyn CryptoRngCore + Send>> {
+        // The RNG holds no invariants that a panic could break, so recover from poisoning.
+        self.rng.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Builder method for choosing how ECDSA signature nonces are generated.
+    ///
+    /// Defaults to [`EcdsaNonce::Deterministic`].
+    pub fn with_ecdsa_nonce(self, ecdsa_nonce: EcdsaNonce) -> Self {
+        Self {
+            ecdsa_nonce,
+            ..self
+        }
+    }
+
+    /// Builder method for choosing how ECDSA signatures are encoded in assertions.
+    ///
+    /// Defaults to [`SignatureFormat::Der`]. Attestation statements and U2F responses are always
+    /// DER encoded as required by their formats.
+    pub fn with_signature_format(self, signature_format: SignatureFormat) -> Self {
+        Self {
+            signature_format,
+            ..self
+        }
+    }
+
+    /// Sign an assertion's `data` with the credential's private `key`, encoding the signature
+    /// following the configured [`SignatureFormat`].
+    pub(crate) fn sign_assertion(
+        &self,
+        key: &coset::CoseKey,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Ctap2Error> {
+        let signature = self.sign(key, data)?;
+        match self.signature_format {
+            SignatureFormat::Der => Ok(signature),
+            SignatureFormat::Raw => ecdsa_der_to_raw(key, signature),
+        }
+    }
+
+    /// Builder method for choosing the kind of attestation statement returned when creating
+    /// credentials.
+    ///
+    /// Defaults to [`Attestation::None`].
+    pub fn with_attestation(self, attestation: Attestation) -> Self {
+        self.with_attestation_provider(attestation)
+    }
+
+    /// Builder method for replacing the default [`Attestation`] with any [`AttestationProvider`],
+    /// for example a [`PackedAttestation`](crate::PackedAttestation) with an attestation key.
+    pub fn with_attestation_provider(
+        self,
+        attestation_provider: impl AttestationProvider + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            attestation_provider: Box::new(attestation_provider),
+            ..self
+        }
+    }
+
+    /// Builder method for making the authenticator capable of [enterprise attestation].
+    ///
+    /// Enterprise attestation must then be enabled through `authenticatorConfig`, after which
+    /// it stays enabled until the authenticator is reset. Requests for enterprise attestation
+    /// are attested by `attestation_provider` instead of the default provider. Vendor
+    /// facilitated requests are only honored for the given `rp_ids`, while platform managed
+    /// requests are trusted to have been checked by the client.
+    ///
+    /// [enterprise attestation]: https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-feature-descriptions-enterp-attstn
+    pub fn with_enterprise_attestation(
+        self,
+        attestation_provider: impl AttestationProvider + Send + Sync + 'static,
+        rp_ids: Vec<String>,
+    ) -> Self {
+        Self {
+            enterprise_attestation: Some(EnterpriseAttestation {
+                provider: Box::new(attestation_provider),
+                rp_ids,
+            }),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Whether enterprise attestation is enabled on this authenticator.
+    pub fn enterprise_attestation_enabled(&self) -> bool {
+        self.enterprise_attestation.is_some() && self.config.stored().enterprise_attestation
+    }
+
+    /// Whether user verification is required for every credential operation, as toggled through
+    /// `authenticatorConfig`.
+    pub fn always_uv(&self) -> bool {
+        self.config.stored().always_uv
+    }
+
+    /// The attestation provider for a `make_credential` request for `rp_id` with the given
+    /// `enterpriseAttestation` parameter, and whether it produces an enterprise attestation.
+    pub(crate) fn attestation_provider_for(
+        &self,
+        rp_id: &str,
+        enterprise_attestation: Option<u8>,
+    ) -> Result<(&(dyn AttestationProvider + Send + Sync), bool), StatusCode> {
+        let Some(requested) = enterprise_attestation else {
+            return Ok((self.attestation_provider(), false));
+        };
+        let enterprise = self
+            .enterprise_attestation
+            .as_ref()
+            .filter(|_| self.enterprise_attestation_enabled())
+            .ok_or(U2FError::InvalidParameter)?;
+        let allowed = match requested {
+            // Vendor facilitated
+            1 => enterprise.rp_ids.iter().any(|id| id == rp_id),
+            // Platform managed
+            2 => true,
+            _ => return Err(Ctap2Error::InvalidOption.into()),
+        };
+        if allowed {
+            Ok((enterprise.provider.as_ref(), true))
+        } else {
+            Ok((self.attestation_provider(), false))
+        }
+    }
+
+    /// Builder method for enabling U2F compatibility mode.
+    ///
+    /// Registrations made through [`U2fApi`](crate::U2fApi) are signed by the attestation key and
+    /// carry its certificate, and `make_credential` emits `fido-u2f` attestation statements so
+    /// that CTAP1 relying parties behind a bridge can verify them. This replaces the configured
+    /// attestation provider.
+    pub fn with_u2f_attestation(self, u2f_attestation: FidoU2fAttestation) -> Self {
+        Self {
+            attestation_provider: Box::new(u2f_attestation.clone()),
+            u2f_attestation: Some(u2f_attestation),
+            ..self
+        }
+    }
+
+    /// The attestation used for U2F registrations, if in U2F compatibility mode.
+    pub(crate) fn u2f_attestation(&self) -> Option<&FidoU2fAttestation> {
+        self.u2f_attestation.as_ref()
+    }
+
+    /// Access the [`AttestationProvider`] used when creating credentials.
+    pub fn attestation_provider(&self) -> &(dyn AttestationProvider + Send + Sync) {
+        self.attestation_provider.as_ref()
+    }
+
+    /// Sign `data` with the credential's private `key` through the [`KeyProvider`], following the
+    /// configured [`EcdsaNonce`].
+    pub(crate) fn sign(&self, key: &coset::CoseKey, data: &[u8]) -> Result<Vec<u8>, Ctap2Error> {
+        match self.ecdsa_nonce {
+            EcdsaNonce::Deterministic => self.key_provider.sign(key, data, None),
+            EcdsaNonce::Hedged => self.key_provider.sign(key, data, Some(self.rng().as_mut())),
+        }
+    }
+
     /// Access the authenticator's [`Aaguid`]
     pub fn aaguid(&self) -> &Aaguid {
         &self.aaguid
     }
 
     /// Return the current attachment type for this authenticator.
+    ///
+    /// Unless set with [`Authenticator::with_attachment`], authenticators reached through the
+    /// [`webauthn::AuthenticatorTransport::Internal`] transport are platform authenticators, and
+    /// others are roaming authenticators.
     pub fn attachment_type(&self) -> webauthn::AuthenticatorAttachment {
-        // TODO: Make this variable depending on the transport.
-        webauthn::AuthenticatorAttachment::Platform
+        self.attachment.unwrap_or_else(|| {
+            if self
+                .transports
+                .contains(&webauthn::AuthenticatorTransport::Internal)
+            {
+                webauthn::AuthenticatorAttachment::Platform
+            } else {
+                webauthn::AuthenticatorAttachment::CrossPlatform
+            }
+        })
+    }
+
+    /// Builder method for overwriting the attachment type of the authenticator, for example for an
+    /// authenticator built into the device but reached through a transport other than `internal`.
+    pub fn with_attachment(self, attachment: webauthn::AuthenticatorAttachment) -> Self {
+        Self {
+            attachment: Some(attachment),
+            ..self
+        }
     }
 
     /// Validate `params` with the following steps
@@ -94,16 +737,94 @@ where
         &self,
         params: &[webauthn::PublicKeyCredentialParameters],
     ) -> Result<iana::Algorithm, Ctap2Error> {
+        let algs = self.key_provider.supported_algorithms();
         params
             .iter()
-            .find(|param| self.algs.contains(&param.alg))
+            .find(|param| algs.contains(&param.alg))
             .map(|param| param.alg)
             .ok_or(Ctap2Error::UnsupportedAlgorithm)
     }
 
     /// Builder method for overwriting the authenticator's supported transports.
     pub fn transports(self, transports: Vec<webauthn::AuthenticatorTransport>) -> Self {
-        Self { transports, ..self }
+        Self {
+            transports,
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for reporting the largest message in bytes that the authenticator's
+    /// transport can receive. Left out of `get_info` by default, meaning 1024 bytes.
+    pub fn with_max_msg_size(self, max_msg_size: NonZeroU128) -> Self {
+        Self {
+            max_msg_size: Some(max_msg_size),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for reporting the most credentials that the platform may send in the allow
+    /// or exclude list of a request, which it then splits into several requests.
+    pub fn with_max_credential_count_in_list(self, max_credential_count_in_list: u32) -> Self {
+        Self {
+            max_credential_count_in_list: Some(max_credential_count_in_list),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for reporting the longest credential ID in bytes that the platform may send
+    /// in the allow or exclude list of a request. Longer IDs are left out by the platform.
+    pub fn with_max_credential_id_length(self, max_credential_id_length: u32) -> Self {
+        Self {
+            max_credential_id_length: Some(max_credential_id_length),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for reporting the version of the authenticator's firmware.
+    pub fn with_firmware_version(self, firmware_version: u64) -> Self {
+        Self {
+            firmware_version: Some(firmware_version),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// Builder method for reporting the certifications of the authenticator, mapping the name of
+    /// each certification such as "FIDO" to the level which was certified.
+    pub fn with_certifications(
+        self,
+        certifications: impl IntoIterator<Item = (Cow<'static, str>, u64)>,
+    ) -> Self {
+        Self {
+            certifications: Some(certifications.into_iter().collect()),
+            info: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// With alwaysUv enabled, a request which is not authenticated with a PIN/UV auth token must
+    /// verify the user some other way: through built-in user verification as if the "uv" option
+    /// was set if it is configured, otherwise the PIN must be used, or set first.
+    pub(crate) fn require_always_uv(
+        &self,
+        options: &mut passkey_types::ctap2::make_credential::Options,
+        has_pin_auth: bool,
+    ) -> Result<(), Ctap2Error> {
+        if !self.always_uv() || has_pin_auth || options.uv {
+            return Ok(());
+        }
+        if self.user_validation.is_verification_enabled() == Some(true) {
+            options.uv = true;
+            Ok(())
+        } else if self.client_pin.is_set() {
+            Err(Ctap2Error::PuatRequired)
+        } else {
+            Err(Ctap2Error::PinNotSet)
+        }
     }
 
     /// Collect user consent if required. This step MUST happen before the following steps due
@@ -114,30 +835,99 @@ where
     ///            CTAP2_ERR_UNSUPPORTED_OPTION error.
     ///         2. Collect a user-identifiable gesture. If gesture validation fails, return the
     ///            CTAP2_ERR_OPERATION_DENIED error.
+    ///         3. Like for a PIN/UV auth token, every gesture uses up one of the UV retries and a
+    ///            successful one restores them. Return the CTAP2_ERR_UV_BLOCKED error instead
+    ///            once they are used up.
+    ///         4. With a [`UvRateLimit`], also return the CTAP2_ERR_UV_BLOCKED error while user
+    ///            verification is locked out, or when the failure locks it out.
     ///     2. If the "up" option was specified and set to true, collect the user’s consent.
     ///         1. If no consent is obtained and a timeout occurs, return the
     ///            CTAP2_ERR_OPERATION_DENIED error.
+    ///     3. If the host cancels while waiting on the user, return the
+    ///        CTAP2_ERR_KEEPALIVE_CANCEL error.
     async fn check_user(
-        &self,
+        &mut self,
         options: &passkey_types::ctap2::make_credential::Options,
-    ) -> Result<Flags, Ctap2Error> {
+    ) -> Result<Flags, StatusCode> {
         if options.uv {
             let Some(true) = self.user_validation.is_verification_enabled() else {
-                return Err(Ctap2Error::UnsupportedOption);
+                return Err(Ctap2Error::UnsupportedOption.into());
             };
-            if self.user_validation.check_user_verification().await {
-                Ok(Flags::UP | Flags::UV)
+            if self.client_pin.is_uv_blocked()
+                || (self.uv_rate_limit.is_some() && self.uv_lockout().is_locked_out(self.now()))
+            {
+                return Err(Ctap2Error::UserVerficationBlocked.into());
+            }
+            self.client_pin.use_uv_retry()?;
+            let verified = self
+                .wait_on_user(self.user_validation.check_user_verification())
+                .await?;
+            if verified {
+                self.client_pin.reset_uv_retries()?;
+                self.uv_lockout().succeed();
+                return Ok(Flags::UP | Flags::UV);
+            }
+            let locked_out = self
+                .uv_rate_limit
+                .as_ref()
+                .is_some_and(|limit| self.uv_lockout().fail(limit, self.now()));
+            if self.client_pin.is_uv_blocked() || locked_out {
+                Err(Ctap2Error::UserVerficationBlocked.into())
             } else {
-                Err(Ctap2Error::OperationDenied)
+                Err(Ctap2Error::OperationDenied.into())
             }
         } else if options.up {
-            if self.user_validation.check_user_presence().await {
+            if self
+                .wait_on_user(self.user_validation.check_user_presence())
+                .await?
+            {
                 Ok(Flags::UP)
             } else {
-                Err(Ctap2Error::OperationDenied)
+                Err(Ctap2Error::OperationDenied.into())
             }
         } else {
             Ok(Flags::empty())
         }
     }
 }
+
+impl<S, U> Authenticator<S, U>
+where
+    S: CredentialStore + Send,
+    U: UserValidationMethod,
+{
+    /// Update the Backup State flag of the stored credential with the given ID, for example once
+    /// it is synced to other devices. The credential must be backup eligible to be backed up.
+    ///
+    /// This fails with `CTAP2_ERR_NO_CREDENTIALS` if the credential is not stored, and with
+    /// `CTAP2_ERR_NOT_ALLOWED` if it is not backup eligible, see
+    /// [`CredentialStore::set_backup_state`].
+    pub async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.store
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    /// End the transaction of the store started for an operation with the `result` of its
+    /// changes: they are committed if it succeeded, or rolled back if it failed, in which case its
+    /// error is returned even if the rollback fails too.
+    pub(crate) async fn end_transaction<T>(
+        &mut self,
+        result: Result<T, StatusCode>,
+    ) -> Result<T, StatusCode> {
+        match result {
+            Ok(value) => {
+                self.store.commit_transaction().await?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self.store.rollback_transaction().await;
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use passkey_types::ctap2::{
+    authenticator_config::{Request, SetMinPinLengthParams, Subcommand},
+    Ctap2Error, Permissions, StatusCode, U2FError,
+};
+
+use crate::{
+    pin_protocol::PinProtocol, Authenticator, AuthenticatorConfigStore, CredentialStore,
+    StoredConfig, UserValidationMethod,
+};
+
+/// The configuration of `authenticatorConfig` along with the store which persists it.
+pub(crate) struct Config {
+    store: Box<dyn AuthenticatorConfigStore + Send + Sync>,
+    /// The configuration as last saved to the store.
+    stored: StoredConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(Box::new(None::<StoredConfig>))
+    }
+}
+
+impl Config {
+    /// Load the configuration from `store`.
+    pub(crate) fn new(store: Box<dyn AuthenticatorConfigStore + Send + Sync>) -> Self {
+        Self {
+            stored: store.load().unwrap_or_default(),
+            store,
+        }
+    }
+
+    /// The current configuration.
+    pub(crate) fn stored(&self) -> &StoredConfig {
+        &self.stored
+    }
+
+    /// Restore the default configuration.
+    pub(crate) fn reset(&mut self) -> Result<(), StatusCode> {
+        self.update(|stored| *stored = StoredConfig::default())
+    }
+
+    /// Apply `change` to the configuration and save it, keeping the previous configuration if
+    /// saving fails.
+    fn update(&mut self, change: impl FnOnce(&mut StoredConfig)) -> Result<(), StatusCode> {
+        let mut stored = self.stored.clone();
+        change(&mut stored);
+        self.store.save(&stored)?;
+        self.stored = stored;
+        Ok(())
+    }
+}
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// This method is used by the platform to configure the authenticator: to enable enterprise
+    /// attestation, to require user verification for every credential operation, or to raise
+    /// the minimum PIN length and force the user to change their PIN. The configuration is
+    /// persisted through the [`AuthenticatorConfigStore`].
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorConfig>
+    pub fn authenticator_config(&mut self, input: Request) -> Result<(), StatusCode> {
+        // 1. If the subcommand is not supported, return CTAP2_ERR_INVALID_SUBCOMMAND.
+        if input.sub_command == Subcommand::VendorPrototype {
+            return Err(Ctap2Error::InvalidSubcommand.into());
+        }
+
+        // 2. If the authenticator is protected by a PIN or by built-in user verification, or if
+        //    user verification is always required, the request must be authenticated with a
+        //    PIN/UV auth token which has the acfg permission. Otherwise it may be configured
+        //    freely.
+        match input.pin_uv_auth_param.as_deref() {
+            Some(pin_uv_auth_param) => {
+                let protocol = input
+                    .pin_uv_auth_protocol
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                if PinProtocol::from_version(protocol).is_none() {
+                    return Err(U2FError::InvalidParameter.into());
+                }
+                self.client_pin.verify_pin_auth(
+                    Some(protocol),
+                    Permissions::ACFG,
+                    None,
+                    &input.pin_uv_auth_message(),
+                    pin_uv_auth_param,
+                    self.now(),
+                )?;
+            }
+            None if self.client_pin.is_set()
+                || self.user_validation.is_verification_enabled() == Some(true)
+                || self.config.stored().always_uv =>
+            {
+                return Err(Ctap2Error::PuatRequired.into());
+            }
+            None => {}
+        }
+
+        // 3. Perform the subcommand, which changes the configuration reported by `get_info`.
+        self.invalidate_info();
+        match input.sub_command {
+            Subcommand::EnableEnterpriseAttestation => {
+                if self.enterprise_attestation.is_none() {
+                    return Err(Ctap2Error::UnsupportedOption.into());
+                }
+                self.config
+                    .update(|stored| stored.enterprise_attestation = true)
+            }
+            Subcommand::ToggleAlwaysUv => self
+                .config
+                .update(|stored| stored.always_uv = !stored.always_uv),
+            Subcommand::SetMinPinLength => {
+                self.set_min_pin_length(input.sub_command_params.unwrap_or_default())
+            }
+            // Rejected above.
+            Subcommand::VendorPrototype => Err(Ctap2Error::InvalidSubcommand.into()),
+        }
+    }
+
+    fn set_min_pin_length(&mut self, params: SetMinPinLengthParams) -> Result<(), StatusCode> {
+        // The minPinLength extension is not supported, so no RP may be allowed to read the
+        // minimum PIN length.
+        if params
+            .min_pin_length_rp_ids
+            .is_some_and(|rp_ids| !rp_ids.is_empty())
+        {
+            return Err(U2FError::InvalidParameter.into());
+        }
+        let new_min_pin_length = params
+            .new_min_pin_length
+            .unwrap_or(self.client_pin.stored().min_pin_length);
+        self.client_pin.set_min_pin_length(
+            new_min_pin_length,
+            params.force_change_pin.unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use passkey_types::ctap2::Aaguid;
+
+    use super::*;
+    use crate::{
+        authenticator::client_pin::tests::{
+            change_pin, get_pin_token, get_pin_uv_auth_token, set_pin, TestAuthenticator,
+        },
+        user_validation::MockUserValidationMethod,
+        Attestation, MemoryStore,
+    };
+
+    /// An authenticator without built-in user verification.
+    fn authenticator() -> TestAuthenticator {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+    }
+
+    fn request(sub_command: Subcommand) -> Request {
+        Request {
+            sub_command,
+            sub_command_params: None,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+    }
+
+    fn set_min_pin_length_request(
+        new_min_pin_length: Option<u8>,
+        force_change_pin: Option<bool>,
+    ) -> Request {
+        Request {
+            sub_command: Subcommand::SetMinPinLength,
+            sub_command_params: Some(SetMinPinLengthParams {
+                new_min_pin_length,
+                min_pin_length_rp_ids: None,
+                force_change_pin,
+            }),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+    }
+
+    /// Authenticate `request` with a new PIN/UV auth token obtained with `pin`.
+    async fn authenticated(
+        authenticator: &mut TestAuthenticator,
+        pin: &[u8],
+        permissions: Permissions,
+        request: Request,
+    ) -> Request {
+        let protocol = PinProtocol::Two;
+        let token = get_pin_uv_auth_token(authenticator, protocol, pin, permissions, None)
+            .await
+            .expect("failed to get pin uv auth token");
+        let pin_uv_auth_param = protocol.authenticate(&token, &request.pin_uv_auth_message());
+        Request {
+            pin_uv_auth_protocol: Some(protocol.version()),
+            pin_uv_auth_param: Some(pin_uv_auth_param.into()),
+            ..request
+        }
+    }
+
+    #[tokio::test]
+    async fn set_min_pin_length_forces_pin_change() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+
+        let request = authenticated(
+            &mut authenticator,
+            b"1234",
+            Permissions::ACFG,
+            set_min_pin_length_request(Some(6), None),
+        )
+        .await;
+        authenticator
+            .authenticator_config(request)
+            .expect("failed to set min pin length");
+
+        let info = authenticator.get_info().await;
+        assert_eq!(info.min_pin_length, Some(6));
+        assert_eq!(info.force_pin_change, Some(true));
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"1234").await,
+            Err(Ctap2Error::PinPolicyViolation.into())
+        );
+        assert_eq!(
+            change_pin(&mut authenticator, protocol, b"1234", b"12345")
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+        change_pin(&mut authenticator, protocol, b"1234", b"123456")
+            .await
+            .expect("failed to change pin");
+        assert_eq!(authenticator.get_info().await.force_pin_change, Some(false));
+        get_pin_token(&mut authenticator, protocol, b"123456")
+            .await
+            .expect("failed to get pin token with new pin");
+    }
+
+    #[tokio::test]
+    async fn force_pin_change_keeps_min_pin_length() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PinProtocol::Two, b"123456")
+            .await
+            .unwrap();
+
+        let request = authenticated(
+            &mut authenticator,
+            b"123456",
+            Permissions::ACFG,
+            set_min_pin_length_request(None, Some(true)),
+        )
+        .await;
+        authenticator
+            .authenticator_config(request)
+            .expect("failed to force pin change");
+        let stored = authenticator.client_pin.stored();
+        assert!(stored.force_change);
+        assert_eq!(stored.min_pin_length, 4);
+    }
+
+    #[tokio::test]
+    async fn configuration_requires_acfg_permission() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            authenticator.authenticator_config(set_min_pin_length_request(Some(6), None)),
+            Err(Ctap2Error::PuatRequired.into())
+        );
+        let request = authenticated(
+            &mut authenticator,
+            b"1234",
+            Permissions::MC,
+            set_min_pin_length_request(Some(6), None),
+        )
+        .await;
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Ctap2Error::PinAuthInvalid.into())
+        );
+
+        // The token authenticates the parameters, which cannot be swapped.
+        let request = authenticated(
+            &mut authenticator,
+            b"1234",
+            Permissions::ACFG,
+            set_min_pin_length_request(Some(6), None),
+        )
+        .await;
+        let request = Request {
+            sub_command_params: Some(SetMinPinLengthParams {
+                new_min_pin_length: Some(8),
+                ..Default::default()
+            }),
+            ..request
+        };
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Ctap2Error::PinAuthInvalid.into())
+        );
+        assert_eq!(authenticator.client_pin.stored().min_pin_length, 4);
+    }
+
+    #[tokio::test]
+    async fn min_pin_length_only_increases() {
+        let mut authenticator = authenticator();
+        authenticator
+            .authenticator_config(set_min_pin_length_request(Some(8), None))
+            .expect("failed to set min pin length without a pin");
+        assert!(!authenticator.client_pin.stored().force_change);
+
+        assert_eq!(
+            authenticator.authenticator_config(set_min_pin_length_request(Some(6), None)),
+            Err(Ctap2Error::PinPolicyViolation.into())
+        );
+        assert_eq!(
+            authenticator.authenticator_config(set_min_pin_length_request(Some(64), None)),
+            Err(Ctap2Error::PinPolicyViolation.into())
+        );
+        assert_eq!(
+            authenticator.authenticator_config(set_min_pin_length_request(None, Some(true))),
+            Err(Ctap2Error::PinNotSet.into())
+        );
+        assert_eq!(
+            authenticator.authenticator_config(Request {
+                sub_command: Subcommand::VendorPrototype,
+                ..set_min_pin_length_request(None, None)
+            }),
+            Err(Ctap2Error::InvalidSubcommand.into())
+        );
+
+        // New PINs must follow the new minimum.
+        assert_eq!(
+            set_pin(&mut authenticator, PinProtocol::One, b"1234567")
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+        set_pin(&mut authenticator, PinProtocol::One, b"12345678")
+            .await
+            .expect("failed to set pin of minimum length");
+    }
+
+    #[tokio::test]
+    async fn enable_enterprise_attestation() {
+        let mut authenticator = authenticator();
+        assert_eq!(authenticator.get_info().await.options.unwrap().ep, None);
+        assert_eq!(
+            authenticator.authenticator_config(request(Subcommand::EnableEnterpriseAttestation)),
+            Err(Ctap2Error::UnsupportedOption.into())
+        );
+
+        let config_store = Arc::new(Mutex::new(None));
+        let capable = |authenticator: TestAuthenticator| {
+            authenticator
+                .with_enterprise_attestation(Attestation::SelfAttestation, Vec::new())
+                .with_config_store(config_store.clone())
+        };
+        let mut authenticator = capable(authenticator);
+        assert_eq!(
+            authenticator.get_info().await.options.unwrap().ep,
+            Some(false)
+        );
+        authenticator
+            .authenticator_config(request(Subcommand::EnableEnterpriseAttestation))
+            .expect("failed to enable enterprise attestation");
+        assert_eq!(
+            authenticator.get_info().await.options.unwrap().ep,
+            Some(true)
+        );
+
+        // The configuration outlives the authenticator.
+        let authenticator = capable(self::authenticator());
+        assert!(authenticator.enterprise_attestation_enabled());
+    }
+
+    #[tokio::test]
+    async fn toggle_always_uv() {
+        let mut authenticator = authenticator();
+        authenticator
+            .authenticator_config(request(Subcommand::ToggleAlwaysUv))
+            .expect("failed to enable always uv");
+        let info = authenticator.get_info().await;
+        assert_eq!(info.options.unwrap().always_uv, Some(true));
+        assert!(!info.versions.contains(&"U2F_V2".into()));
+
+        // Further configuration requires a PIN/UV auth token, so a PIN must be set first.
+        assert_eq!(
+            authenticator.authenticator_config(request(Subcommand::ToggleAlwaysUv)),
+            Err(Ctap2Error::PuatRequired.into())
+        );
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let request = authenticated(
+            &mut authenticator,
+            b"1234",
+            Permissions::ACFG,
+            request(Subcommand::ToggleAlwaysUv),
+        )
+        .await;
+        authenticator
+            .authenticator_config(request)
+            .expect("failed to disable always uv");
+        let info = authenticator.get_info().await;
+        assert_eq!(info.options.unwrap().always_uv, Some(false));
+        assert!(info.versions.contains(&"U2F_V2".into()));
+    }
+}
@@ -0,0 +1,444 @@
+use std::time::Duration;
+
+use passkey_types::ctap2::{
+    bio_enrollment::{Request, Response, Subcommand, SubcommandParams, MODALITY_FINGERPRINT},
+    Ctap2Error, Permissions, StatusCode, U2FError,
+};
+
+use crate::{
+    pin_protocol::PinProtocol, Authenticator, CredentialStore, EnrollmentSample, FingerprintSensor,
+    UserValidationMethod,
+};
+
+impl<S, U> Authenticator<S, U>
+where
+    S: CredentialStore,
+    U: UserValidationMethod + Sync,
+{
+    /// This method is used by the platform to enroll the user's fingerprints and to list, name
+    /// and remove the enrollments. The sensor itself is driven by the [`UserValidationMethod`],
+    /// which must provide a [`UserValidationMethod::fingerprint_sensor`], otherwise
+    /// `CTAP1_ERR_INVALID_COMMAND` is returned.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorBioEnrollment>
+    pub async fn bio_enrollment(&mut self, input: Request) -> Result<Response, StatusCode> {
+        // 1. Bio enrollment needs a fingerprint sensor.
+        let Some(sensor) = self.user_validation.fingerprint_sensor() else {
+            return Err(U2FError::InvalidCommand.into());
+        };
+
+        // 2. The platform may ask for the supported modality without a subcommand.
+        if input.get_modality == Some(true) {
+            return Ok(Response {
+                modality: Some(MODALITY_FINGERPRINT),
+                ..Default::default()
+            });
+        }
+        let sub_command = input.sub_command.ok_or(Ctap2Error::MissingParameter)?;
+        match input.modality {
+            Some(MODALITY_FINGERPRINT) => {}
+            Some(_) => return Err(Ctap2Error::UnsupportedOption.into()),
+            None => return Err(Ctap2Error::MissingParameter.into()),
+        }
+
+        // 3. Subcommands other than getting the sensor information and cancelling an enrollment
+        //    must be authenticated with a PIN/UV auth token which has the be permission.
+        if sub_command.requires_pin_uv_auth() {
+            let pin_uv_auth_param = input
+                .pin_uv_auth_param
+                .as_deref()
+                .ok_or(Ctap2Error::PuatRequired)?;
+            let protocol = input
+                .pin_uv_auth_protocol
+                .ok_or(Ctap2Error::MissingParameter)?;
+            if PinProtocol::from_version(protocol).is_none() {
+                return Err(U2FError::InvalidParameter.into());
+            }
+            self.client_pin.verify_pin_auth(
+                Some(protocol),
+                Permissions::BE,
+                None,
+                &input.pin_uv_auth_message(),
+                pin_uv_auth_param,
+                self.now(),
+            )?;
+        }
+
+        // 4. Perform the subcommand.
+        let params = input.sub_command_params.unwrap_or_default();
+        match sub_command {
+            Subcommand::GetFingerprintSensorInfo => Ok(Response {
+                modality: Some(MODALITY_FINGERPRINT),
+                fingerprint_kind: Some(sensor.kind.into()),
+                max_capture_samples_required_for_enroll: Some(
+                    sensor.max_capture_samples_required_for_enroll,
+                ),
+                max_template_friendly_name: sensor.max_template_friendly_name,
+                ..Default::default()
+            }),
+            Subcommand::EnrollBegin => self.enroll_begin(params).await,
+            Subcommand::EnrollCaptureNextSample => self.enroll_capture_next_sample(params).await,
+            Subcommand::CancelCurrentEnrollment => {
+                if self.bio_enrollment.take().is_some() {
+                    self.user_validation.cancel_enrollment().await;
+                }
+                Ok(Response::default())
+            }
+            Subcommand::EnumerateEnrollments => {
+                let template_infos = self.user_validation.enumerate_enrollments().await?;
+                if template_infos.is_empty() {
+                    return Err(Ctap2Error::InvalidOption.into());
+                }
+                Ok(Response {
+                    template_infos: Some(template_infos),
+                    ..Default::default()
+                })
+            }
+            Subcommand::SetFriendlyName => self.set_friendly_name(sensor, params).await,
+            Subcommand::RemoveEnrollment => {
+                let template_id = params.template_id.ok_or(Ctap2Error::MissingParameter)?;
+                self.user_validation.remove_enrollment(&template_id).await?;
+                self.bio_enrollment
+                    .take_if(|current| *current == *template_id);
+                Ok(Response::default())
+            }
+        }
+    }
+
+    async fn enroll_begin(&mut self, params: SubcommandParams) -> Result<Response, StatusCode> {
+        // Starting over abandons the enrollment in progress.
+        if self.bio_enrollment.take().is_some() {
+            self.user_validation.cancel_enrollment().await;
+        }
+        let (template_id, sample) = self.user_validation.enroll_begin(timeout(&params)).await?;
+        if sample.remaining_samples > 0 {
+            self.bio_enrollment = Some(template_id.clone());
+        }
+        Ok(Response {
+            template_id: Some(template_id.into()),
+            ..sample_response(sample)
+        })
+    }
+
+    async fn enroll_capture_next_sample(
+        &mut self,
+        params: SubcommandParams,
+    ) -> Result<Response, StatusCode> {
+        let template_id = params
+            .template_id
+            .as_deref()
+            .ok_or(Ctap2Error::MissingParameter)?;
+        match &self.bio_enrollment {
+            Some(current) if current == template_id => {}
+            Some(_) => return Err(U2FError::InvalidParameter.into()),
+            None => return Err(Ctap2Error::NotAllowed.into()),
+        }
+        let sample = self
+            .user_validation
+            .enroll_capture_next_sample(template_id, timeout(&params))
+            .await?;
+        if sample.remaining_samples == 0 {
+            self.bio_enrollment = None;
+        }
+        Ok(sample_response(sample))
+    }
+
+    async fn set_friendly_name(
+        &self,
+        sensor: FingerprintSensor,
+        params: SubcommandParams,
+    ) -> Result<Response, StatusCode> {
+        let (Some(template_id), Some(name)) = (params.template_id, params.template_friendly_name)
+        else {
+            return Err(Ctap2Error::MissingParameter.into());
+        };
+        let too_long = sensor
+            .max_template_friendly_name
+            .and_then(|max| usize::try_from(max).ok())
+            .is_some_and(|max| name.len() > max);
+        if too_long {
+            return Err(U2FError::InvalidLength.into());
+        }
+        self.user_validation
+            .set_friendly_name(&template_id, &name)
+            .await?;
+        Ok(Response::default())
+    }
+}
+
+/// The time to wait for the user to present a sample, if the platform gave one.
+fn timeout(params: &SubcommandParams) -> Option<Duration> {
+    params
+        .timeout_milliseconds
+        .map(|millis| Duration::from_millis(millis.into()))
+}
+
+fn sample_response(sample: EnrollmentSample) -> Response {
+    Response {
+        last_enroll_sample_status: Some(sample.status.into()),
+        remaining_samples: Some(sample.remaining_samples),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use passkey_types::ctap2::{
+        bio_enrollment::{FingerprintKind, LastEnrollSampleStatus, TemplateInfo},
+        Aaguid,
+    };
+
+    use super::*;
+    use crate::{
+        authenticator::client_pin::tests::{get_pin_uv_auth_token, set_pin, TestAuthenticator},
+        user_validation::MockUserValidationMethod,
+        MemoryStore,
+    };
+
+    const SENSOR: FingerprintSensor = FingerprintSensor {
+        kind: FingerprintKind::Touch,
+        max_capture_samples_required_for_enroll: 2,
+        max_template_friendly_name: Some(16),
+    };
+
+    fn request(sub_command: Subcommand, sub_command_params: Option<SubcommandParams>) -> Request {
+        Request {
+            modality: Some(MODALITY_FINGERPRINT),
+            sub_command: Some(sub_command),
+            sub_command_params,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+            get_modality: None,
+        }
+    }
+
+    /// Authenticate `request` with `token`.
+    fn authenticated(token: &[u8], request: Request) -> Request {
+        let protocol = PinProtocol::Two;
+        let pin_uv_auth_param = protocol.authenticate(token, &request.pin_uv_auth_message());
+        Request {
+            pin_uv_auth_protocol: Some(protocol.version()),
+            pin_uv_auth_param: Some(pin_uv_auth_param.into()),
+            ..request
+        }
+    }
+
+    fn template(template_id: &[u8]) -> Option<SubcommandParams> {
+        Some(SubcommandParams {
+            template_id: Some(template_id.to_vec().into()),
+            ..Default::default()
+        })
+    }
+
+    /// An authenticator with a PIN and the fingerprint sensor mocked by `user_mock`, along with a
+    /// PIN/UV auth token with the given permissions.
+    async fn enrolling_authenticator(
+        mut user_mock: MockUserValidationMethod,
+        permissions: Permissions,
+    ) -> (TestAuthenticator, Vec<u8>) {
+        user_mock
+            .expect_fingerprint_sensor()
+            .returning(|| Some(SENSOR));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            PinProtocol::Two,
+            b"1234",
+            permissions,
+            None,
+        )
+        .await
+        .expect("failed to get pin uv auth token");
+        (authenticator, token)
+    }
+
+    #[tokio::test]
+    async fn enroll_fingerprint_in_samples() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock.expect_enroll_begin().times(1).returning(|_| {
+            Box::pin(async {
+                let sample = EnrollmentSample {
+                    status: LastEnrollSampleStatus::Good,
+                    remaining_samples: 1,
+                };
+                Ok((vec![7; 4], sample))
+            })
+        });
+        user_mock
+            .expect_enroll_capture_next_sample()
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(async {
+                    Ok(EnrollmentSample {
+                        status: LastEnrollSampleStatus::Good,
+                        remaining_samples: 0,
+                    })
+                })
+            });
+        let (mut authenticator, token) = enrolling_authenticator(user_mock, Permissions::BE).await;
+
+        // The sensor information does not need a token.
+        let info = authenticator
+            .bio_enrollment(request(Subcommand::GetFingerprintSensorInfo, None))
+            .await
+            .expect("failed to get sensor info");
+        assert_eq!(info.fingerprint_kind, Some(FingerprintKind::Touch.into()));
+        assert_eq!(info.max_capture_samples_required_for_enroll, Some(2));
+
+        let begin = authenticator
+            .bio_enrollment(authenticated(
+                &token,
+                request(Subcommand::EnrollBegin, None),
+            ))
+            .await
+            .expect("failed to begin enrollment");
+        assert_eq!(begin.template_id, Some(vec![7; 4].into()));
+        assert_eq!(begin.remaining_samples, Some(1));
+
+        assert_eq!(
+            authenticator
+                .bio_enrollment(authenticated(
+                    &token,
+                    request(Subcommand::EnrollCaptureNextSample, template(&[8; 4])),
+                ))
+                .await
+                .unwrap_err(),
+            U2FError::InvalidParameter.into()
+        );
+        let next = authenticator
+            .bio_enrollment(authenticated(
+                &token,
+                request(Subcommand::EnrollCaptureNextSample, template(&[7; 4])),
+            ))
+            .await
+            .expect("failed to capture next sample");
+        assert_eq!(
+            next.last_enroll_sample_status,
+            Some(LastEnrollSampleStatus::Good.into())
+        );
+        assert_eq!(next.remaining_samples, Some(0));
+
+        // The enrollment is complete.
+        assert_eq!(
+            authenticator
+                .bio_enrollment(authenticated(
+                    &token,
+                    request(Subcommand::EnrollCaptureNextSample, template(&[7; 4])),
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::NotAllowed.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn manage_enrollments() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock.expect_enumerate_enrollments().returning(|| {
+            Box::pin(async {
+                Ok(vec![TemplateInfo {
+                    template_id: vec![7; 4].into(),
+                    template_friendly_name: None,
+                }])
+            })
+        });
+        user_mock
+            .expect_set_friendly_name()
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        user_mock
+            .expect_remove_enrollment()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let (mut authenticator, token) = enrolling_authenticator(user_mock, Permissions::BE).await;
+
+        let enrollments = authenticator
+            .bio_enrollment(authenticated(
+                &token,
+                request(Subcommand::EnumerateEnrollments, None),
+            ))
+            .await
+            .expect("failed to enumerate enrollments");
+        assert_eq!(enrollments.template_infos.unwrap().len(), 1);
+
+        let name = |name: &str| {
+            Some(SubcommandParams {
+                template_friendly_name: Some(name.into()),
+                ..template(&[7; 4]).unwrap()
+            })
+        };
+        assert_eq!(
+            authenticator
+                .bio_enrollment(authenticated(
+                    &token,
+                    request(Subcommand::SetFriendlyName, name("A very long thumb name")),
+                ))
+                .await
+                .unwrap_err(),
+            U2FError::InvalidLength.into()
+        );
+        authenticator
+            .bio_enrollment(authenticated(
+                &token,
+                request(Subcommand::SetFriendlyName, name("Right thumb")),
+            ))
+            .await
+            .expect("failed to set friendly name");
+        authenticator
+            .bio_enrollment(authenticated(
+                &token,
+                request(Subcommand::RemoveEnrollment, template(&[7; 4])),
+            ))
+            .await
+            .expect("failed to remove enrollment");
+    }
+
+    #[tokio::test]
+    async fn bio_enrollment_requires_a_sensor_and_the_be_permission() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        assert_eq!(
+            authenticator
+                .bio_enrollment(request(Subcommand::GetFingerprintSensorInfo, None))
+                .await
+                .unwrap_err(),
+            U2FError::InvalidCommand.into()
+        );
+
+        let (mut authenticator, token) =
+            enrolling_authenticator(MockUserValidationMethod::new(), Permissions::ACFG).await;
+        assert_eq!(
+            authenticator
+                .bio_enrollment(request(Subcommand::EnumerateEnrollments, None))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PuatRequired.into()
+        );
+        assert_eq!(
+            authenticator
+                .bio_enrollment(authenticated(
+                    &token,
+                    request(Subcommand::EnumerateEnrollments, None),
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinAuthInvalid.into()
+        );
+        let modality = authenticator
+            .bio_enrollment(Request {
+                modality: None,
+                sub_command: None,
+                get_modality: Some(true),
+                ..request(Subcommand::GetFingerprintSensorInfo, None)
+            })
+            .await
+            .expect("failed to get modality");
+        assert_eq!(modality.modality, Some(MODALITY_FINGERPRINT));
+    }
+}
@@ -0,0 +1,1689 @@
+use std::{
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use coset::CoseKey;
+use p256::SecretKey;
+use passkey_types::{
+    crypto::sha256,
+    ctap2::{
+        client_pin::{Request, Response, Subcommand},
+        Ctap2Error, Permissions, PinUvAuthToken, StatusCode, U2FError,
+    },
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::{
+    pin_protocol::{key_agreement_public_key, PinProtocol},
+    pin_store::{MAX_PIN_RETRIES, MAX_UV_RETRIES},
+    Authenticator, CredentialStore, PinStore, StoredPin, UserValidationMethod,
+};
+
+/// The number of consecutive wrong PINs after which PIN operations are blocked until the
+/// authenticator is power cycled.
+const MAX_CONSECUTIVE_MISMATCHES: u8 = 3;
+
+/// The number of times built-in user verification is attempted within a single request for a
+/// PIN/UV auth token before giving up.
+const MAX_UV_ATTEMPTS_FOR_INTERNAL_RETRIES: u8 = 3;
+
+/// The maximum PIN length in bytes.
+const MAX_PIN_LENGTH: usize = 63;
+
+/// The minimum length of `newPinEnc`, the PIN is padded with zeros to at least 64 bytes.
+const MIN_PADDED_PIN_LENGTH: usize = 64;
+
+/// The state of the `authenticatorClientPIN` command.
+pub(crate) struct ClientPin {
+    /// Persists `stored` across restarts.
+    store: Box<dyn PinStore + Send + Sync>,
+    /// The PIN state as last saved to the store.
+    stored: StoredPin,
+    /// The key agreement key pair, generated on first use and regenerated after a wrong PIN.
+    key_agreement: Option<SecretKey>,
+    /// The PIN/UV auth token given out by the last successful token request.
+    ///
+    /// Behind a lock since `get_assertion` binds the token to its RP through a shared reference.
+    pin_token: Mutex<Option<PinUvAuthToken>>,
+    /// The number of wrong PINs since the last correct one or the last power cycle.
+    consecutive_mismatches: u8,
+}
+
+impl Default for ClientPin {
+    fn default() -> Self {
+        Self::new(Box::new(None::<StoredPin>))
+    }
+}
+
+impl ClientPin {
+    /// Load the PIN state from `store`.
+    pub(crate) fn new(store: Box<dyn PinStore + Send + Sync>) -> Self {
+        Self {
+            stored: store.load().unwrap_or_default(),
+            store,
+            key_agreement: None,
+            pin_token: Mutex::new(None),
+            consecutive_mismatches: 0,
+        }
+    }
+
+    /// Whether a PIN has been set on the authenticator.
+    pub(crate) fn is_set(&self) -> bool {
+        self.stored.pin_hash.is_some()
+    }
+
+    /// The PIN state as last saved to the store.
+    pub(crate) fn stored(&self) -> &StoredPin {
+        &self.stored
+    }
+
+    /// Whether built-in user verification is blocked, its retries being used up.
+    pub(crate) fn is_uv_blocked(&self) -> bool {
+        self.stored.uv_retries == 0
+    }
+
+    /// Use up one of the remaining UV retries, which is saved before the user is asked to verify
+    /// so that turning the authenticator off does not give the attempt back.
+    pub(crate) fn use_uv_retry(&mut self) -> Result<(), StatusCode> {
+        self.update(|stored| stored.uv_retries = stored.uv_retries.saturating_sub(1))
+    }
+
+    /// Restore the UV retries after a successful user verification.
+    pub(crate) fn reset_uv_retries(&mut self) -> Result<(), StatusCode> {
+        if self.stored.uv_retries == MAX_UV_RETRIES {
+            return Ok(());
+        }
+        self.update(|stored| stored.uv_retries = MAX_UV_RETRIES)
+    }
+
+    /// The RP the current PIN/UV auth token is restricted to, if any.
+    pub(crate) fn pin_token_rp_id(&self) -> Option<String> {
+        self.pin_token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|token| token.rp_id().map(Into::into))
+    }
+
+    /// Derive the secret shared with the platform's `platform_key` from the current key agreement
+    /// key, which the `hmac-secret` extension reuses to encrypt its salts and outputs.
+    pub(crate) fn decapsulate(
+        &self,
+        protocol: PinProtocol,
+        platform_key: &CoseKey,
+    ) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+        // Without a key agreement key, the platform cannot share a secret with the authenticator.
+        let key_agreement = self
+            .key_agreement
+            .as_ref()
+            .ok_or(Ctap2Error::PinAuthInvalid)?;
+        protocol.decapsulate(key_agreement, platform_key)
+    }
+
+    /// Raise the minimum PIN length to `new_min_pin_length`, forcing a PIN change if the current
+    /// PIN is now too short or if `force_change` is set.
+    pub(crate) fn set_min_pin_length(
+        &mut self,
+        new_min_pin_length: u8,
+        force_change: bool,
+    ) -> Result<(), StatusCode> {
+        // The minimum may not decrease, nor exceed the length of the longest PIN.
+        if new_min_pin_length < self.stored.min_pin_length
+            || usize::from(new_min_pin_length) > MAX_PIN_LENGTH
+        {
+            return Err(Ctap2Error::PinPolicyViolation.into());
+        }
+        if force_change && !self.is_set() {
+            return Err(Ctap2Error::PinNotSet.into());
+        }
+        let too_short = self.is_set() && self.stored.pin_length < new_min_pin_length;
+        self.update(|stored| {
+            stored.min_pin_length = new_min_pin_length;
+            stored.force_change |= force_change || too_short;
+        })
+    }
+
+    /// Forget the state which does not survive a power cycle: the key agreement key, the PIN/UV
+    /// auth token and the count of consecutive wrong PINs which blocks PIN operations.
+    pub(crate) fn power_cycle(&mut self) {
+        self.key_agreement = None;
+        *self
+            .pin_token
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        self.consecutive_mismatches = 0;
+    }
+
+    /// Forget the PIN and restore the retries and minimum PIN length to their defaults, along
+    /// with the state which does not survive a power cycle.
+    pub(crate) fn reset(&mut self) -> Result<(), StatusCode> {
+        self.update(|stored| *stored = StoredPin::default())?;
+        self.power_cycle();
+        Ok(())
+    }
+
+    /// Apply `change` to the PIN state and save it, keeping the previous state if saving fails.
+    fn update(&mut self, change: impl FnOnce(&mut StoredPin)) -> Result<(), StatusCode> {
+        let mut stored = self.stored.clone();
+        change(&mut stored);
+        self.store.save(&stored)?;
+        self.stored = stored;
+        Ok(())
+    }
+
+    /// Verify that `pin_auth` authenticates `message` with the current PIN/UV auth token using
+    /// the `pin_protocol` chosen by the platform, and that the token has not expired and grants
+    /// `permission` for `rp_id` at `now`, see [`PinUvAuthToken::authorize`].
+    pub(crate) fn verify_pin_auth(
+        &self,
+        pin_protocol: Option<u8>,
+        permission: Permissions,
+        rp_id: Option<&str>,
+        message: &[u8],
+        pin_auth: &[u8],
+        now: SystemTime,
+    ) -> Result<(), Ctap2Error> {
+        let protocol = pin_protocol
+            .and_then(PinProtocol::from_version)
+            .ok_or(Ctap2Error::PinAuthInvalid)?;
+        let mut pin_token = self
+            .pin_token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let pin_token = pin_token.as_mut().ok_or(Ctap2Error::PinAuthInvalid)?;
+        if !protocol.verify(pin_token.token(), message, pin_auth) {
+            return Err(Ctap2Error::PinAuthInvalid);
+        }
+        pin_token.authorize(permission, rp_id, now)
+    }
+
+    /// Clear the permissions of the current PIN/UV auth token but lbw, see
+    /// [`PinUvAuthToken::clear_permissions_except_lbw`].
+    pub(crate) fn clear_pin_token_permissions_except_lbw(&self) {
+        if let Some(pin_token) = self
+            .pin_token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            pin_token.clear_permissions_except_lbw();
+        }
+    }
+}
+
+impl<S, U> Authenticator<S, U>
+where
+    S: CredentialStore + Sync,
+    U: UserValidationMethod + Sync,
+{
+    /// This method is used by the platform to set or change the PIN of the authenticator and to
+    /// get a PIN/UV auth token with which it authenticates `make_credential` and `get_assertion`
+    /// requests. The token is given out in exchange for the PIN or, when the
+    /// [`UserValidationMethod`] supports it, after built-in user verification.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorClientPIN>
+    pub async fn client_pin(&mut self, input: Request) -> Result<Response, StatusCode> {
+        let protocol =
+            PinProtocol::from_version(input.pin_protocol).ok_or(U2FError::InvalidParameter)?;
+
+        match input.sub_command {
+            Subcommand::GetRetries => Ok(Response {
+                retries: Some(self.client_pin.stored.retries),
+                power_cycle_state: Some(
+                    self.client_pin.consecutive_mismatches >= MAX_CONSECUTIVE_MISMATCHES,
+                ),
+                ..Default::default()
+            }),
+            Subcommand::GetKeyAgreement => Ok(Response {
+                key_agreement: Some(key_agreement_public_key(self.key_agreement())),
+                ..Default::default()
+            }),
+            // Whether a PIN is set and must be changed is reported by `get_info`.
+            Subcommand::SetPin => {
+                self.invalidate_info();
+                self.set_pin(protocol, input)
+            }
+            Subcommand::ChangePin => {
+                self.invalidate_info();
+                self.change_pin(protocol, input)
+            }
+            // Tokens from the legacy subcommand may be used for any RP, to create credentials
+            // and get assertions.
+            Subcommand::GetPinToken => {
+                self.get_pin_token(protocol, input, Permissions::MC | Permissions::GA, None)
+            }
+            Subcommand::GetPinUvAuthTokenUsingPinWithPermissions => {
+                let permissions = requested_permissions(&input)?;
+                self.check_permissions_supported(permissions)?;
+                let rp_id = input.rp_id.clone();
+                self.get_pin_token(protocol, input, permissions, rp_id)
+            }
+            Subcommand::GetPinUvAuthTokenUsingUvWithPermissions => {
+                let permissions = requested_permissions(&input)?;
+                self.check_permissions_supported(permissions)?;
+                let rp_id = input.rp_id.clone();
+                self.get_uv_token(protocol, input, permissions, rp_id).await
+            }
+            Subcommand::GetUvRetries => {
+                self.check_built_in_uv_supported()?;
+                Ok(Response {
+                    uv_retries: Some(self.client_pin.stored.uv_retries),
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    /// The key agreement key, generating it if this is the first time it is used.
+    fn key_agreement(&mut self) -> &SecretKey {
+        if self.client_pin.key_agreement.is_none() {
+            let key = SecretKey::random(&mut *self.rng());
+            self.client_pin.key_agreement = Some(key);
+        }
+        // SAFETY: set just above if it was missing
+        self.client_pin.key_agreement.as_ref().unwrap()
+    }
+
+    /// Derive the secret shared with the platform from its key agreement key in `input`.
+    fn shared_secret(
+        &mut self,
+        protocol: PinProtocol,
+        input: &Request,
+    ) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+        let platform_key = input
+            .key_agreement
+            .as_ref()
+            .ok_or(Ctap2Error::MissingParameter)?;
+        protocol.decapsulate(self.key_agreement(), platform_key)
+    }
+
+    fn set_pin(&mut self, protocol: PinProtocol, input: Request) -> Result<Response, StatusCode> {
+        // 1. If a PIN has already been set, return CTAP2_ERR_PIN_AUTH_INVALID.
+        if self.client_pin.is_set() {
+            return Err(Ctap2Error::PinAuthInvalid.into());
+        }
+        let (Some(pin_auth), Some(new_pin_enc)) = (&input.pin_auth, &input.new_pin_enc) else {
+            return Err(Ctap2Error::MissingParameter.into());
+        };
+
+        // 2. Generate the shared secret from the platform's key agreement key.
+        let shared_secret = self.shared_secret(protocol, &input)?;
+
+        // 3. Verify pinAuth over newPinEnc with the shared secret.
+        if !protocol.verify(&shared_secret, new_pin_enc, pin_auth) {
+            return Err(Ctap2Error::PinAuthInvalid.into());
+        }
+
+        // 4-6. Decrypt the new PIN, check it against the PIN policy and store its hash.
+        let (pin_hash, pin_length) = decrypt_new_pin(
+            protocol,
+            &shared_secret,
+            new_pin_enc,
+            self.client_pin.stored.min_pin_length,
+        )?;
+        self.client_pin.update(|stored| {
+            stored.pin_hash = Some(*pin_hash);
+            stored.pin_length = pin_length;
+            stored.retries = MAX_PIN_RETRIES;
+        })?;
+        Ok(Response::default())
+    }
+
+    fn change_pin(
+        &mut self,
+        protocol: PinProtocol,
+        input: Request,
+    ) -> Result<Response, StatusCode> {
+        let (Some(pin_auth), Some(new_pin_enc), Some(pin_hash_enc)) =
+            (&input.pin_auth, &input.new_pin_enc, &input.pin_hash_enc)
+        else {
+            return Err(Ctap2Error::MissingParameter.into());
+        };
+        self.check_pin_available()?;
+
+        // 2. Generate the shared secret from the platform's key agreement key.
+        let shared_secret = self.shared_secret(protocol, &input)?;
+
+        // 3. Verify pinAuth over newPinEnc || pinHashEnc with the shared secret.
+        let message = [new_pin_enc.as_slice(), pin_hash_enc.as_slice()].concat();
+        if !protocol.verify(&shared_secret, &message, pin_auth) {
+            return Err(Ctap2Error::PinAuthInvalid.into());
+        }
+
+        // 4-5. Check the current PIN.
+        self.check_pin_hash(protocol, &shared_secret, pin_hash_enc)?;
+
+        // 6-8. Decrypt the new PIN, check it against the PIN policy and store its hash. When a
+        //     PIN change is forced, the new PIN must differ from the current one.
+        let (pin_hash, pin_length) = decrypt_new_pin(
+            protocol,
+            &shared_secret,
+            new_pin_enc,
+            self.client_pin.stored.min_pin_length,
+        )?;
+        let same_pin = self
+            .client_pin
+            .stored
+            .pin_hash
+            .as_ref()
+            .is_some_and(|current| bool::from(current.ct_eq(pin_hash.as_slice())));
+        if self.client_pin.stored.force_change && same_pin {
+            return Err(Ctap2Error::PinPolicyViolation.into());
+        }
+        self.client_pin.update(|stored| {
+            stored.pin_hash = Some(*pin_hash);
+            stored.pin_length = pin_length;
+            stored.force_change = false;
+        })?;
+
+        // 9. Reset the PIN/UV auth token, which was obtained with the previous PIN.
+        *self
+            .client_pin
+            .pin_token
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        Ok(Response::default())
+    }
+
+    fn get_pin_token(
+        &mut self,
+        protocol: PinProtocol,
+        input: Request,
+        permissions: Permissions,
+        rp_id: Option<String>,
+    ) -> Result<Response, StatusCode> {
+        let Some(pin_hash_enc) = &input.pin_hash_enc else {
+            return Err(Ctap2Error::MissingParameter.into());
+        };
+        self.check_pin_available()?;
+
+        // 2. Generate the shared secret from the platform's key agreement key.
+        let shared_secret = self.shared_secret(protocol, &input)?;
+
+        // 3-4. Check the PIN. A PIN which must be changed cannot be used to get a token.
+        self.check_pin_hash(protocol, &shared_secret, pin_hash_enc)?;
+        if self.client_pin.stored.force_change {
+            return Err(Ctap2Error::PinPolicyViolation.into());
+        }
+
+        // 5. Return a new PIN/UV auth token with the requested permissions.
+        self.issue_pin_token(protocol, &shared_secret, permissions, rp_id)
+    }
+
+    async fn get_uv_token(
+        &mut self,
+        protocol: PinProtocol,
+        input: Request,
+        permissions: Permissions,
+        rp_id: Option<String>,
+    ) -> Result<Response, StatusCode> {
+        // 1. Built-in user verification must be enabled and not blocked.
+        self.check_built_in_uv_supported()?;
+        if self.client_pin.is_uv_blocked() {
+            return Err(Ctap2Error::UserVerficationBlocked.into());
+        }
+
+        // 2. Generate the shared secret from the platform's key agreement key.
+        let shared_secret = self.shared_secret(protocol, &input)?;
+
+        // 3. Verify the user.
+        self.check_built_in_uv().await?;
+
+        // 4. Return a new PIN/UV auth token with the requested permissions.
+        self.issue_pin_token(protocol, &shared_secret, permissions, rp_id)
+    }
+
+    /// Give out a new PIN/UV auth token encrypted with the shared secret, invalidating the
+    /// previous one.
+    fn issue_pin_token(
+        &mut self,
+        protocol: PinProtocol,
+        shared_secret: &[u8],
+        permissions: Permissions,
+        rp_id: Option<String>,
+    ) -> Result<Response, StatusCode> {
+        let mut token = Zeroizing::new([0; 32]);
+        self.rng().fill_bytes(token.as_mut());
+        let pin_token_enc =
+            protocol.encrypt(shared_secret, token.as_slice(), self.rng().as_mut())?;
+        *self
+            .client_pin
+            .pin_token
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Some(PinUvAuthToken::new(token, permissions, rp_id, self.now()));
+        Ok(Response {
+            pin_token: Some(pin_token_enc.into()),
+            ..Default::default()
+        })
+    }
+
+    /// Check that the authenticator supports the commands allowed by `permissions`. Bio
+    /// enrollment is only supported with a fingerprint sensor.
+    fn check_permissions_supported(&self, permissions: Permissions) -> Result<(), StatusCode> {
+        if permissions.contains(Permissions::BE)
+            && self.user_validation.fingerprint_sensor().is_none()
+        {
+            return Err(Ctap2Error::UnauthorizedPermission.into());
+        }
+        Ok(())
+    }
+
+    /// Check that the [`UserValidationMethod`] can verify the user by itself and has been
+    /// configured to do so.
+    fn check_built_in_uv_supported(&self) -> Result<(), StatusCode> {
+        match self.user_validation.is_verification_enabled() {
+            Some(true) => Ok(()),
+            Some(false) => Err(Ctap2Error::NotAllowed.into()),
+            None => Err(Ctap2Error::InvalidSubcommand.into()),
+        }
+    }
+
+    /// Verify the user with the [`UserValidationMethod`], trying again a few times before giving
+    /// up. Each attempt uses up one of the remaining UV retries, saved before the user is asked,
+    /// and a successful one resets them.
+    async fn check_built_in_uv(&mut self) -> Result<(), StatusCode> {
+        for _ in 0..MAX_UV_ATTEMPTS_FOR_INTERNAL_RETRIES {
+            if self.client_pin.is_uv_blocked() {
+                break;
+            }
+            self.client_pin.use_uv_retry()?;
+            if self.user_validation.check_user_verification().await {
+                self.client_pin.reset_uv_retries()?;
+                return Ok(());
+            }
+        }
+
+        let error = if self.client_pin.is_uv_blocked() {
+            Ctap2Error::UserVerficationBlocked
+        } else {
+            Ctap2Error::UserVerificationInvalid
+        };
+        Err(error.into())
+    }
+
+    /// Check that a PIN is set and that it can still be tried.
+    fn check_pin_available(&self) -> Result<(), Ctap2Error> {
+        if !self.client_pin.is_set() {
+            Err(Ctap2Error::PinNotSet)
+        } else if self.client_pin.stored.retries == 0 {
+            Err(Ctap2Error::PinBlocked)
+        } else if self.client_pin.consecutive_mismatches >= MAX_CONSECUTIVE_MISMATCHES {
+            Err(Ctap2Error::PinAuthBlocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Compare the platform's encrypted PIN hash against the stored one, counting down the
+    /// remaining retries. The decremented count is saved before comparing. A wrong PIN also
+    /// invalidates the key agreement key.
+    fn check_pin_hash(
+        &mut self,
+        protocol: PinProtocol,
+        shared_secret: &[u8],
+        pin_hash_enc: &[u8],
+    ) -> Result<(), StatusCode> {
+        self.client_pin
+            .update(|stored| stored.retries = stored.retries.saturating_sub(1))?;
+        let matches = protocol
+            .decrypt(shared_secret, pin_hash_enc)
+            .ok()
+            .zip(self.client_pin.stored.pin_hash.as_ref())
+            .is_some_and(|(pin_hash, expected)| bool::from(pin_hash.ct_eq(expected.as_slice())));
+
+        if matches {
+            self.client_pin
+                .update(|stored| stored.retries = MAX_PIN_RETRIES)?;
+            self.client_pin.consecutive_mismatches = 0;
+            return Ok(());
+        }
+
+        let key = SecretKey::random(&mut *self.rng());
+        self.client_pin.key_agreement = Some(key);
+        self.client_pin.consecutive_mismatches += 1;
+        let error = if self.client_pin.stored.retries == 0 {
+            Ctap2Error::PinBlocked
+        } else if self.client_pin.consecutive_mismatches >= MAX_CONSECUTIVE_MISMATCHES {
+            Ctap2Error::PinAuthBlocked
+        } else {
+            Ctap2Error::PinInvalid
+        };
+        Err(error.into())
+    }
+}
+
+/// Decrypt `newPinEnc` and check the PIN against the PIN policy, returning the hash to store
+/// along with the PIN's length in Unicode code points.
+fn decrypt_new_pin(
+    protocol: PinProtocol,
+    shared_secret: &[u8],
+    new_pin_enc: &[u8],
+    min_pin_length: u8,
+) -> Result<(Zeroizing<[u8; 16]>, u8), StatusCode> {
+    if new_pin_enc.len() < MIN_PADDED_PIN_LENGTH {
+        return Err(Ctap2Error::PinPolicyViolation.into());
+    }
+    let padded_pin = protocol.decrypt(shared_secret, new_pin_enc)?;
+    let pin_length = padded_pin
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    if pin_length > MAX_PIN_LENGTH {
+        return Err(Ctap2Error::PinPolicyViolation.into());
+    }
+    // The minimum length is in code points of the UTF-8 encoded PIN rather than in bytes.
+    let pin = &padded_pin[..pin_length];
+    let code_points = std::str::from_utf8(pin)
+        .map_err(|_| Ctap2Error::PinPolicyViolation)?
+        .chars()
+        .count();
+    let code_points = u8::try_from(code_points).map_err(|_| Ctap2Error::PinPolicyViolation)?;
+    if code_points < min_pin_length {
+        return Err(Ctap2Error::PinPolicyViolation.into());
+    }
+
+    let mut pin_hash = Zeroizing::new([0; 16]);
+    pin_hash.copy_from_slice(&sha256(pin)[..16]);
+    Ok((pin_hash, code_points))
+}
+
+/// Parse the permissions requested for a PIN/UV auth token, rejecting those not supported.
+fn requested_permissions(input: &Request) -> Result<Permissions, StatusCode> {
+    let permissions = input.permissions.ok_or(Ctap2Error::MissingParameter)?;
+    let permissions = Permissions::from_bits_truncate(permissions);
+    if permissions.is_empty() {
+        return Err(U2FError::InvalidParameter.into());
+    }
+    Ok(permissions)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use passkey_types::{
+        ctap2::{credential_management, get_assertion, large_blobs, make_credential, Aaguid},
+        webauthn, Bytes, Passkey,
+    };
+
+    use super::*;
+    use crate::{
+        authenticator::credential_management::tests::save_passkey,
+        user_validation::MockUserValidationMethod, MemoryStore, PinState,
+    };
+
+    pub(crate) type TestAuthenticator = Authenticator<MemoryStore, MockUserValidationMethod>;
+
+    pub(crate) fn request(protocol: PinProtocol, sub_command: Subcommand) -> Request {
+        Request {
+            pin_protocol: protocol.version(),
+            sub_command,
+            key_agreement: None,
+            pin_auth: None,
+            new_pin_enc: None,
+            pin_hash_enc: None,
+            permissions: None,
+            rp_id: None,
+        }
+    }
+
+    /// Run a key agreement with the authenticator, returning the platform's public key and the
+    /// shared secret.
+    pub(crate) async fn key_agreement(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+    ) -> (coset::CoseKey, Zeroizing<Vec<u8>>) {
+        let response = authenticator
+            .client_pin(request(protocol, Subcommand::GetKeyAgreement))
+            .await
+            .expect("failed to get key agreement");
+        protocol
+            .encapsulate(&response.key_agreement.unwrap(), &mut rand::thread_rng())
+            .expect("failed to encapsulate shared secret")
+    }
+
+    pub(crate) fn encrypt(
+        protocol: PinProtocol,
+        shared_secret: &[u8],
+        plaintext: &[u8],
+    ) -> Vec<u8> {
+        protocol
+            .encrypt(shared_secret, plaintext, &mut rand::thread_rng())
+            .unwrap()
+    }
+
+    fn pad_pin(pin: &[u8]) -> Vec<u8> {
+        let mut padded = pin.to_vec();
+        padded.resize(MIN_PADDED_PIN_LENGTH, 0);
+        padded
+    }
+
+    pub(crate) async fn set_pin(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+        pin: &[u8],
+    ) -> Result<Response, StatusCode> {
+        let (platform_key, shared_secret) = key_agreement(authenticator, protocol).await;
+        let new_pin_enc = encrypt(protocol, &shared_secret, &pad_pin(pin));
+        authenticator
+            .client_pin(Request {
+                key_agreement: Some(platform_key),
+                pin_auth: Some(protocol.authenticate(&shared_secret, &new_pin_enc).into()),
+                new_pin_enc: Some(new_pin_enc.into()),
+                ..request(protocol, Subcommand::SetPin)
+            })
+            .await
+    }
+
+    pub(crate) async fn change_pin(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+        current_pin: &[u8],
+        new_pin: &[u8],
+    ) -> Result<Response, StatusCode> {
+        let (platform_key, shared_secret) = key_agreement(authenticator, protocol).await;
+        let new_pin_enc = encrypt(protocol, &shared_secret, &pad_pin(new_pin));
+        let pin_hash_enc = encrypt(protocol, &shared_secret, &sha256(current_pin)[..16]);
+        let pin_auth = protocol.authenticate(
+            &shared_secret,
+            &[new_pin_enc.as_slice(), &pin_hash_enc].concat(),
+        );
+        authenticator
+            .client_pin(Request {
+                key_agreement: Some(platform_key),
+                pin_auth: Some(pin_auth.into()),
+                new_pin_enc: Some(new_pin_enc.into()),
+                pin_hash_enc: Some(pin_hash_enc.into()),
+                ..request(protocol, Subcommand::ChangePin)
+            })
+            .await
+    }
+
+    /// Send a token request proving knowledge of `pin`, returning the token decrypted.
+    async fn request_token(
+        authenticator: &mut TestAuthenticator,
+        pin: &[u8],
+        token_request: Request,
+    ) -> Result<Vec<u8>, StatusCode> {
+        let protocol = PinProtocol::from_version(token_request.pin_protocol).unwrap();
+        let (platform_key, shared_secret) = key_agreement(authenticator, protocol).await;
+        let pin_hash_enc = encrypt(protocol, &shared_secret, &sha256(pin)[..16]);
+        let response = authenticator
+            .client_pin(Request {
+                key_agreement: Some(platform_key),
+                pin_hash_enc: Some(pin_hash_enc.into()),
+                ..token_request
+            })
+            .await?;
+        let pin_token = protocol
+            .decrypt(&shared_secret, &response.pin_token.unwrap())
+            .unwrap();
+        Ok(pin_token.to_vec())
+    }
+
+    /// Get a PIN token with `pin` through the legacy subcommand, returning it decrypted.
+    pub(crate) async fn get_pin_token(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+        pin: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        request_token(
+            authenticator,
+            pin,
+            request(protocol, Subcommand::GetPinToken),
+        )
+        .await
+    }
+
+    /// Get a PIN/UV auth token with `pin` and the given `permissions`, returning it decrypted.
+    pub(crate) async fn get_pin_uv_auth_token(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+        pin: &[u8],
+        permissions: Permissions,
+        rp_id: Option<&str>,
+    ) -> Result<Vec<u8>, StatusCode> {
+        request_token(
+            authenticator,
+            pin,
+            Request {
+                permissions: Some(permissions.bits()),
+                rp_id: rp_id.map(Into::into),
+                ..request(
+                    protocol,
+                    Subcommand::GetPinUvAuthTokenUsingPinWithPermissions,
+                )
+            },
+        )
+        .await
+    }
+
+    /// Get a PIN/UV auth token through built-in user verification, returning it decrypted.
+    async fn get_uv_token(
+        authenticator: &mut TestAuthenticator,
+        protocol: PinProtocol,
+        permissions: Permissions,
+        rp_id: Option<&str>,
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (platform_key, shared_secret) = key_agreement(authenticator, protocol).await;
+        let response = authenticator
+            .client_pin(Request {
+                key_agreement: Some(platform_key),
+                permissions: Some(permissions.bits()),
+                rp_id: rp_id.map(Into::into),
+                ..request(
+                    protocol,
+                    Subcommand::GetPinUvAuthTokenUsingUvWithPermissions,
+                )
+            })
+            .await?;
+        let pin_token = protocol
+            .decrypt(&shared_secret, &response.pin_token.unwrap())
+            .unwrap();
+        Ok(pin_token.to_vec())
+    }
+
+    async fn uv_retries(authenticator: &mut TestAuthenticator) -> Result<u8, StatusCode> {
+        let response = authenticator
+            .client_pin(request(PinProtocol::Two, Subcommand::GetUvRetries))
+            .await?;
+        Ok(response.uv_retries.unwrap())
+    }
+
+    /// An authenticator with built-in user verification in the given state, which succeeds
+    /// `verifications` times then fails.
+    fn uv_authenticator(enabled: Option<bool>, verifications: usize) -> TestAuthenticator {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(move || enabled);
+        user_mock
+            .expect_check_user_verification()
+            .times(verifications)
+            .returning(|| Box::pin(async { true }));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { false }));
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+    }
+
+    async fn retries(authenticator: &mut TestAuthenticator) -> u8 {
+        authenticator
+            .client_pin(request(PinProtocol::One, Subcommand::GetRetries))
+            .await
+            .unwrap()
+            .retries
+            .unwrap()
+    }
+
+    pub(crate) fn authenticator() -> TestAuthenticator {
+        Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(0),
+        )
+    }
+
+    #[tokio::test]
+    async fn set_pin_and_get_pin_token() {
+        for protocol in PinProtocol::SUPPORTED {
+            let mut authenticator = authenticator();
+            assert_eq!(
+                get_pin_token(&mut authenticator, protocol, b"1234").await,
+                Err(Ctap2Error::PinNotSet.into())
+            );
+
+            set_pin(&mut authenticator, protocol, b"1234")
+                .await
+                .expect("failed to set pin");
+            assert!(authenticator.client_pin.is_set());
+            assert_eq!(
+                set_pin(&mut authenticator, protocol, b"5678")
+                    .await
+                    .unwrap_err(),
+                Ctap2Error::PinAuthInvalid.into()
+            );
+
+            let pin_token = get_pin_token(&mut authenticator, protocol, b"1234")
+                .await
+                .expect("failed to get pin token");
+            assert_eq!(pin_token.len(), 32);
+            let client_pin = &authenticator.client_pin;
+            let issued = client_pin.pin_token.lock().unwrap();
+            let issued = issued.as_ref().unwrap();
+            assert_eq!(issued.token().as_slice(), pin_token.as_slice());
+            assert_eq!(issued.permissions(), Permissions::MC | Permissions::GA);
+            assert_eq!(issued.rp_id(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn key_agreement_encrypts_hmac_secret_salts() {
+        for protocol in PinProtocol::SUPPORTED {
+            let mut authenticator = authenticator();
+            let (platform_key, shared_secret) = key_agreement(&mut authenticator, protocol).await;
+
+            // The platform encrypts and authenticates the salts as the `hmac-secret` input.
+            let salts = [[1; 32], [2; 32]].concat();
+            let salt_enc = encrypt(protocol, &shared_secret, &salts);
+            let salt_auth = protocol.authenticate(&shared_secret, &salt_enc);
+
+            let authenticator_secret = authenticator
+                .shared_secret(
+                    protocol,
+                    &Request {
+                        key_agreement: Some(platform_key),
+                        ..request(protocol, Subcommand::GetKeyAgreement)
+                    },
+                )
+                .expect("failed to derive shared secret");
+            assert!(protocol.verify(&authenticator_secret, &salt_enc, &salt_auth));
+            let decrypted = protocol.decrypt(&authenticator_secret, &salt_enc).unwrap();
+            assert_eq!(*decrypted, salts);
+        }
+    }
+
+    #[tokio::test]
+    async fn pin_policy_is_enforced() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            set_pin(&mut authenticator, PinProtocol::One, b"123")
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+        assert_eq!(
+            set_pin(&mut authenticator, PinProtocol::Two, &[b'1'; 64])
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+        // The minimum length counts code points, not bytes.
+        assert_eq!(
+            set_pin(&mut authenticator, PinProtocol::Two, "éé".as_bytes())
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+        assert!(!authenticator.client_pin.is_set());
+        set_pin(&mut authenticator, PinProtocol::One, &[b'1'; 63])
+            .await
+            .expect("failed to set pin of maximum length");
+    }
+
+    #[tokio::test]
+    async fn set_pin_requires_valid_pin_auth() {
+        let mut authenticator = authenticator();
+        let (platform_key, shared_secret) =
+            key_agreement(&mut authenticator, PinProtocol::Two).await;
+        let new_pin_enc = encrypt(PinProtocol::Two, &shared_secret, &pad_pin(b"1234"));
+        let result = authenticator
+            .client_pin(Request {
+                key_agreement: Some(platform_key),
+                // Authenticated with the wrong protocol.
+                pin_auth: Some(
+                    PinProtocol::One
+                        .authenticate(&shared_secret, &new_pin_enc)
+                        .into(),
+                ),
+                new_pin_enc: Some(new_pin_enc.into()),
+                ..request(PinProtocol::Two, Subcommand::SetPin)
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PinAuthInvalid.into());
+
+        let result = authenticator
+            .client_pin(Request {
+                pin_protocol: 3,
+                ..request(PinProtocol::One, Subcommand::GetRetries)
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), U2FError::InvalidParameter.into());
+    }
+
+    #[tokio::test]
+    async fn wrong_pin_uses_up_retries() {
+        let protocol = PinProtocol::One;
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES);
+
+        for _ in 0..2 {
+            assert_eq!(
+                get_pin_token(&mut authenticator, protocol, b"0000").await,
+                Err(Ctap2Error::PinInvalid.into())
+            );
+        }
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES - 2);
+
+        // A correct PIN resets the retries.
+        get_pin_token(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES);
+
+        for _ in 0..2 {
+            get_pin_token(&mut authenticator, protocol, b"0000")
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"0000").await,
+            Err(Ctap2Error::PinAuthBlocked.into())
+        );
+        // Blocked until power cycle, even with the correct PIN.
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"1234").await,
+            Err(Ctap2Error::PinAuthBlocked.into())
+        );
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES - 3);
+
+        let power_cycle_state = authenticator
+            .client_pin(request(protocol, Subcommand::GetRetries))
+            .await
+            .unwrap()
+            .power_cycle_state;
+        assert_eq!(power_cycle_state, Some(true));
+
+        // A power cycle lifts the soft block but keeps the remaining retries.
+        authenticator.power_cycle();
+        get_pin_token(&mut authenticator, protocol, b"1234")
+            .await
+            .expect("failed to get pin token after power cycle");
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES);
+
+        authenticator.client_pin.stored.retries = 1;
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"0000").await,
+            Err(Ctap2Error::PinBlocked.into())
+        );
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"1234").await,
+            Err(Ctap2Error::PinBlocked.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn change_pin_replaces_pin() {
+        for protocol in PinProtocol::SUPPORTED {
+            let mut authenticator = authenticator();
+            set_pin(&mut authenticator, protocol, b"1234")
+                .await
+                .unwrap();
+            change_pin(&mut authenticator, protocol, b"1234", b"abcdef")
+                .await
+                .expect("failed to change pin");
+
+            assert_eq!(
+                get_pin_token(&mut authenticator, protocol, b"1234").await,
+                Err(Ctap2Error::PinInvalid.into())
+            );
+            get_pin_token(&mut authenticator, protocol, b"abcdef")
+                .await
+                .expect("failed to get pin token with new pin");
+        }
+    }
+
+    #[tokio::test]
+    async fn change_pin_resets_pin_token() {
+        for protocol in PinProtocol::SUPPORTED {
+            let mut authenticator = authenticator();
+            set_pin(&mut authenticator, protocol, b"1234")
+                .await
+                .unwrap();
+            let token = get_pin_token(&mut authenticator, protocol, b"1234")
+                .await
+                .expect("failed to get pin token");
+            let pin_auth = protocol.authenticate(&token, b"client data hash");
+            let verify = |authenticator: &TestAuthenticator| {
+                authenticator.client_pin.verify_pin_auth(
+                    Some(protocol.version()),
+                    Permissions::MC,
+                    Some("example.com"),
+                    b"client data hash",
+                    &pin_auth,
+                    SystemTime::now(),
+                )
+            };
+            assert_eq!(verify(&authenticator), Ok(()));
+
+            change_pin(&mut authenticator, protocol, b"1234", b"abcdef")
+                .await
+                .expect("failed to change pin");
+            assert_eq!(verify(&authenticator), Err(Ctap2Error::PinAuthInvalid));
+        }
+    }
+
+    #[tokio::test]
+    async fn pin_uv_auth_token_permissions() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = authenticator();
+        authenticator
+            .user_validation
+            .expect_fingerprint_sensor()
+            .returning(|| None);
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+
+        let missing = authenticator
+            .client_pin(request(
+                protocol,
+                Subcommand::GetPinUvAuthTokenUsingPinWithPermissions,
+            ))
+            .await;
+        assert_eq!(missing.unwrap_err(), Ctap2Error::MissingParameter.into());
+        assert_eq!(
+            get_pin_uv_auth_token(
+                &mut authenticator,
+                protocol,
+                b"1234",
+                Permissions::empty(),
+                None
+            )
+            .await,
+            Err(U2FError::InvalidParameter.into())
+        );
+        assert_eq!(
+            get_pin_uv_auth_token(
+                &mut authenticator,
+                protocol,
+                b"1234",
+                Permissions::MC | Permissions::BE,
+                None
+            )
+            .await,
+            Err(Ctap2Error::UnauthorizedPermission.into())
+        );
+
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            protocol,
+            b"1234",
+            Permissions::MC,
+            Some("example.com"),
+        )
+        .await
+        .expect("failed to get pin uv auth token");
+        let pin_auth = protocol.authenticate(&token, b"client data hash");
+        let verify = |permission, rp_id| {
+            authenticator.client_pin.verify_pin_auth(
+                Some(2),
+                permission,
+                Some(rp_id),
+                b"client data hash",
+                &pin_auth,
+                SystemTime::now(),
+            )
+        };
+        assert_eq!(verify(Permissions::MC, "example.com"), Ok(()));
+        assert_eq!(
+            verify(Permissions::GA, "example.com"),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        assert_eq!(
+            verify(Permissions::MC, "other.example.com"),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_uv_auth_token_is_bound_to_first_rp() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        let token =
+            get_pin_uv_auth_token(&mut authenticator, protocol, b"1234", Permissions::GA, None)
+                .await
+                .unwrap();
+
+        let pin_auth = protocol.authenticate(&token, b"client data hash");
+        let verify = |authenticator: &TestAuthenticator, rp_id| {
+            authenticator.client_pin.verify_pin_auth(
+                Some(2),
+                Permissions::GA,
+                Some(rp_id),
+                b"client data hash",
+                &pin_auth,
+                SystemTime::now(),
+            )
+        };
+        assert_eq!(verify(&authenticator, "example.com"), Ok(()));
+        assert_eq!(verify(&authenticator, "example.com"), Ok(()));
+        assert_eq!(
+            verify(&authenticator, "other.example.com"),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+
+        // A new token replaces the previous one.
+        get_pin_token(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        assert_eq!(
+            verify(&authenticator, "example.com"),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+    }
+
+    #[tokio::test]
+    async fn unused_pin_uv_auth_token_expires() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        let token = get_pin_token(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+
+        let pin_auth = protocol.authenticate(&token, b"client data hash");
+        let later = SystemTime::now()
+            + PinUvAuthToken::INITIAL_USAGE_TIME_LIMIT
+            + std::time::Duration::from_secs(1);
+        assert_eq!(
+            authenticator.client_pin.verify_pin_auth(
+                Some(2),
+                Permissions::GA,
+                Some("example.com"),
+                b"client data hash",
+                &pin_auth,
+                later,
+            ),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_state_reports_status() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            authenticator.pin_state(),
+            PinState {
+                is_set: false,
+                retries: MAX_PIN_RETRIES,
+                uv_blocked: false,
+            }
+        );
+
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        get_pin_token(&mut authenticator, PinProtocol::Two, b"0000")
+            .await
+            .unwrap_err();
+        authenticator.client_pin.stored.uv_retries = 0;
+        assert_eq!(
+            authenticator.pin_state(),
+            PinState {
+                is_set: true,
+                retries: MAX_PIN_RETRIES - 1,
+                uv_blocked: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_state_is_persisted() {
+        let store = Arc::new(std::sync::Mutex::new(None::<StoredPin>));
+        let mut authenticator = authenticator().with_pin_store(store.clone());
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        get_pin_token(&mut authenticator, PinProtocol::Two, b"0000")
+            .await
+            .unwrap_err();
+        let stored = store
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("pin state was not saved");
+        assert_eq!(
+            stored.pin_hash.as_ref().map(|hash| hash.as_slice()),
+            Some(&sha256(b"1234")[..16])
+        );
+        assert_eq!(stored.retries, MAX_PIN_RETRIES - 1);
+
+        // A new authenticator picks up the PIN and the retries left.
+        let mut authenticator = self::authenticator().with_pin_store(store.clone());
+        assert!(authenticator.client_pin.is_set());
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES - 1);
+        assert_eq!(
+            set_pin(&mut authenticator, PinProtocol::Two, b"5678")
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinAuthInvalid.into()
+        );
+
+        // Exhausted retries block the PIN across restarts.
+        store.lock().unwrap().as_mut().unwrap().retries = 0;
+        let mut authenticator = self::authenticator().with_pin_store(store);
+        assert_eq!(
+            get_pin_token(&mut authenticator, PinProtocol::Two, b"1234").await,
+            Err(Ctap2Error::PinBlocked.into())
+        );
+    }
+
+    /// A store which can no longer save anything.
+    struct ReadOnlyPinStore(StoredPin);
+
+    impl PinStore for ReadOnlyPinStore {
+        fn load(&self) -> Option<StoredPin> {
+            Some(self.0.clone())
+        }
+
+        fn save(&mut self, _pin: &StoredPin) -> Result<(), StatusCode> {
+            Err(Ctap2Error::KeyStoreFull.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn pin_is_not_checked_when_retries_cannot_be_saved() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PinProtocol::One, b"1234")
+            .await
+            .unwrap();
+        let stored = authenticator.client_pin.stored.clone();
+        let mut authenticator = authenticator.with_pin_store(ReadOnlyPinStore(stored));
+
+        assert_eq!(
+            get_pin_token(&mut authenticator, PinProtocol::One, b"1234").await,
+            Err(Ctap2Error::KeyStoreFull.into())
+        );
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES);
+    }
+
+    #[tokio::test]
+    async fn forced_pin_change() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, protocol, b"1234")
+            .await
+            .unwrap();
+        let stored = StoredPin {
+            force_change: true,
+            ..authenticator.client_pin.stored.clone()
+        };
+        let mut authenticator = authenticator.with_pin_store(Some(stored));
+
+        assert_eq!(
+            get_pin_token(&mut authenticator, protocol, b"1234").await,
+            Err(Ctap2Error::PinPolicyViolation.into())
+        );
+        // The PIN was correct, so the retries are not used up.
+        assert_eq!(retries(&mut authenticator).await, MAX_PIN_RETRIES);
+        assert_eq!(
+            change_pin(&mut authenticator, protocol, b"1234", b"1234")
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinPolicyViolation.into()
+        );
+
+        change_pin(&mut authenticator, protocol, b"1234", b"5678")
+            .await
+            .expect("failed to change pin");
+        assert!(!authenticator.client_pin.stored.force_change);
+        get_pin_token(&mut authenticator, protocol, b"5678")
+            .await
+            .expect("failed to get pin token after changing pin");
+    }
+
+    #[tokio::test]
+    async fn uv_token_satisfies_pin_auth() {
+        for protocol in PinProtocol::SUPPORTED {
+            // No PIN is needed to get a token through built-in user verification.
+            let mut authenticator = uv_authenticator(Some(true), 1);
+            let token = get_uv_token(
+                &mut authenticator,
+                protocol,
+                Permissions::MC,
+                Some("example.com"),
+            )
+            .await
+            .expect("failed to get uv token");
+            assert_eq!(token.len(), 32);
+
+            let pin_auth = protocol.authenticate(&token, b"client data hash");
+            let verify = |permission| {
+                authenticator.client_pin.verify_pin_auth(
+                    Some(protocol.version()),
+                    permission,
+                    Some("example.com"),
+                    b"client data hash",
+                    &pin_auth,
+                    SystemTime::now(),
+                )
+            };
+            assert_eq!(verify(Permissions::MC), Ok(()));
+            assert_eq!(verify(Permissions::GA), Err(Ctap2Error::PinAuthInvalid));
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_uv_uses_up_uv_retries() {
+        let protocol = PinProtocol::Two;
+        let mut authenticator = uv_authenticator(Some(true), 0);
+        assert_eq!(uv_retries(&mut authenticator).await, Ok(MAX_UV_RETRIES));
+
+        // Every request tries to verify the user a few times before giving up.
+        assert_eq!(
+            get_uv_token(&mut authenticator, protocol, Permissions::GA, None).await,
+            Err(Ctap2Error::UserVerificationInvalid.into())
+        );
+        assert_eq!(
+            uv_retries(&mut authenticator).await,
+            Ok(MAX_UV_RETRIES - MAX_UV_ATTEMPTS_FOR_INTERNAL_RETRIES)
+        );
+
+        authenticator.client_pin.stored.uv_retries = 2;
+        assert_eq!(
+            get_uv_token(&mut authenticator, protocol, Permissions::GA, None).await,
+            Err(Ctap2Error::UserVerficationBlocked.into())
+        );
+        assert_eq!(uv_retries(&mut authenticator).await, Ok(0));
+        assert_eq!(
+            get_uv_token(&mut authenticator, protocol, Permissions::GA, None).await,
+            Err(Ctap2Error::UserVerficationBlocked.into())
+        );
+        assert!(authenticator.client_pin.pin_token.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn successful_uv_resets_uv_retries() {
+        let mut authenticator = uv_authenticator(Some(true), 1);
+        authenticator.client_pin.stored.uv_retries = 1;
+        get_uv_token(&mut authenticator, PinProtocol::One, Permissions::MC, None)
+            .await
+            .expect("failed to get uv token");
+        assert_eq!(uv_retries(&mut authenticator).await, Ok(MAX_UV_RETRIES));
+    }
+
+    #[tokio::test]
+    async fn uv_token_requires_built_in_uv() {
+        let mut authenticator = uv_authenticator(None, 0);
+        assert_eq!(
+            get_uv_token(&mut authenticator, PinProtocol::Two, Permissions::MC, None).await,
+            Err(Ctap2Error::InvalidSubcommand.into())
+        );
+        assert_eq!(
+            uv_retries(&mut authenticator).await,
+            Err(Ctap2Error::InvalidSubcommand.into())
+        );
+
+        let mut authenticator = uv_authenticator(Some(false), 0);
+        assert_eq!(
+            get_uv_token(&mut authenticator, PinProtocol::Two, Permissions::MC, None).await,
+            Err(Ctap2Error::NotAllowed.into())
+        );
+        assert_eq!(
+            get_uv_token(&mut authenticator, PinProtocol::Two, Permissions::BE, None).await,
+            Err(Ctap2Error::UnauthorizedPermission.into())
+        );
+    }
+
+    /// The commands authenticated with a PIN/UV auth token.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum TokenCommand {
+        MakeCredential,
+        GetAssertion,
+        CredentialManagement,
+        LargeBlobs,
+    }
+
+    impl TokenCommand {
+        const ALL: [Self; 4] = [
+            Self::MakeCredential,
+            Self::GetAssertion,
+            Self::CredentialManagement,
+            Self::LargeBlobs,
+        ];
+
+        /// The permission a token needs to authenticate this command.
+        fn permission(self) -> Permissions {
+            match self {
+                Self::MakeCredential => Permissions::MC,
+                Self::GetAssertion => Permissions::GA,
+                Self::CredentialManagement => Permissions::CM,
+                Self::LargeBlobs => Permissions::LBW,
+            }
+        }
+    }
+
+    /// Send `command` for the RP of `passkey` with the user present, authenticated with `token`.
+    async fn send(
+        authenticator: &mut TestAuthenticator,
+        command: TokenCommand,
+        passkey: &Passkey,
+        token: &[u8],
+    ) -> Result<(), StatusCode> {
+        let protocol = PinProtocol::Two;
+        let client_data_hash: Bytes = passkey_types::rand::random_vec(32).into();
+        let pin_auth = Some(protocol.authenticate(token, &client_data_hash).into());
+        let options = make_credential::Options {
+            rk: false,
+            up: true,
+            uv: false,
+        };
+        match command {
+            TokenCommand::MakeCredential => authenticator
+                .make_credential(make_credential::Request {
+                    client_data_hash,
+                    rp: make_credential::PublicKeyCredentialRpEntity {
+                        id: passkey.rp_id.clone(),
+                        name: None,
+                    },
+                    user: webauthn::PublicKeyCredentialUserEntity {
+                        id: passkey_types::rand::random_vec(16).into(),
+                        display_name: "wendy".into(),
+                        name: "Appleseed".into(),
+                    },
+                    pub_key_cred_params: vec![webauthn::PublicKeyCredentialParameters {
+                        ty: webauthn::PublicKeyCredentialType::PublicKey,
+                        alg: coset::iana::Algorithm::ES256,
+                    }],
+                    exclude_list: None,
+                    extensions: None,
+                    options,
+                    pin_auth,
+                    pin_protocol: Some(protocol.version()),
+                    enterprise_attestation: None,
+                })
+                .await
+                .map(drop),
+            TokenCommand::GetAssertion => authenticator
+                .get_assertion(get_assertion::Request {
+                    rp_id: passkey.rp_id.clone(),
+                    client_data_hash,
+                    allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                        ty: webauthn::PublicKeyCredentialType::PublicKey,
+                        id: passkey.credential_id.clone(),
+                        transports: None,
+                    }]),
+                    extensions: None,
+                    options,
+                    pin_auth,
+                    pin_protocol: Some(protocol.version()),
+                })
+                .await
+                .map(drop),
+            TokenCommand::CredentialManagement => {
+                let request = credential_management::Request {
+                    sub_command: credential_management::Subcommand::EnumerateCredentialsBegin,
+                    sub_command_params: Some(credential_management::SubcommandParams {
+                        rp_id_hash: Some(sha256(passkey.rp_id.as_bytes()).to_vec().into()),
+                        ..Default::default()
+                    }),
+                    pin_uv_auth_protocol: Some(protocol.version()),
+                    pin_uv_auth_param: None,
+                };
+                let pin_uv_auth_param =
+                    protocol.authenticate(token, &request.pin_uv_auth_message());
+                authenticator
+                    .credential_management(credential_management::Request {
+                        pin_uv_auth_param: Some(pin_uv_auth_param.into()),
+                        ..request
+                    })
+                    .await
+                    .map(drop)
+            }
+            TokenCommand::LargeBlobs => {
+                let request = large_blobs::Request {
+                    get: None,
+                    set: Some(large_blobs::EMPTY_LARGE_BLOB_ARRAY.to_vec().into()),
+                    offset: 0,
+                    length: Some(u32::try_from(large_blobs::EMPTY_LARGE_BLOB_ARRAY.len()).unwrap()),
+                    pin_uv_auth_param: None,
+                    pin_uv_auth_protocol: Some(protocol.version()),
+                };
+                let pin_uv_auth_param =
+                    protocol.authenticate(token, &request.pin_uv_auth_message());
+                authenticator
+                    .large_blobs(large_blobs::Request {
+                        pin_uv_auth_param: Some(pin_uv_auth_param.into()),
+                        ..request
+                    })
+                    .map(drop)
+            }
+        }
+    }
+
+    /// An authenticator with a PIN and a discoverable credential for each of two RPs.
+    async fn authenticator_with_credentials() -> (TestAuthenticator, Passkey, Passkey) {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let a = save_passkey(&mut authenticator, "a.example.com", Some(b"alice"));
+        let b = save_passkey(&mut authenticator, "b.example.com", Some(b"bob"));
+        (authenticator, a, b)
+    }
+
+    #[tokio::test]
+    async fn commands_require_their_permission() {
+        let (mut authenticator, a, _) = authenticator_with_credentials().await;
+        for permission in [
+            Permissions::MC,
+            Permissions::GA,
+            Permissions::CM,
+            Permissions::LBW,
+            Permissions::ACFG,
+        ] {
+            for command in TokenCommand::ALL {
+                let token = get_pin_uv_auth_token(
+                    &mut authenticator,
+                    PinProtocol::Two,
+                    b"1234",
+                    permission,
+                    None,
+                )
+                .await
+                .unwrap();
+                let expected = if command.permission() == permission {
+                    Ok(())
+                } else {
+                    Err(Ctap2Error::PinAuthInvalid.into())
+                };
+                assert_eq!(
+                    send(&mut authenticator, command, &a, &token).await,
+                    expected,
+                    "{command:?} with {permission:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn tokens_are_not_replayed_against_other_rps() {
+        let (mut authenticator, a, b) = authenticator_with_credentials().await;
+        for command in TokenCommand::ALL {
+            let token = get_pin_uv_auth_token(
+                &mut authenticator,
+                PinProtocol::Two,
+                b"1234",
+                command.permission(),
+                Some(&a.rp_id),
+            )
+            .await
+            .unwrap();
+            // The large blob array is shared by every RP.
+            let expected = if command == TokenCommand::LargeBlobs {
+                Ok(())
+            } else {
+                Err(Ctap2Error::PinAuthInvalid.into())
+            };
+            assert_eq!(
+                send(&mut authenticator, command, &b, &token).await,
+                expected,
+                "{command:?}"
+            );
+            assert_eq!(
+                send(&mut authenticator, command, &a, &token).await,
+                Ok(()),
+                "{command:?}"
+            );
+        }
+
+        // A token without an RP ID is bound to the RP of its first request.
+        for command in [TokenCommand::MakeCredential, TokenCommand::GetAssertion] {
+            let token = get_pin_uv_auth_token(
+                &mut authenticator,
+                PinProtocol::Two,
+                b"1234",
+                Permissions::MC | Permissions::GA,
+                None,
+            )
+            .await
+            .unwrap();
+            send(&mut authenticator, command, &a, &token)
+                .await
+                .unwrap_or_else(|err| panic!("{command:?} failed: {err:?}"));
+            assert_eq!(
+                authenticator.client_pin.pin_token_rp_id().as_deref(),
+                Some(a.rp_id.as_str())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tokens_are_used_up_by_requests_with_user_presence() {
+        let (mut authenticator, a, _) = authenticator_with_credentials().await;
+        for command in [TokenCommand::MakeCredential, TokenCommand::GetAssertion] {
+            let token = get_pin_uv_auth_token(
+                &mut authenticator,
+                PinProtocol::Two,
+                b"1234",
+                Permissions::MC | Permissions::GA | Permissions::LBW,
+                None,
+            )
+            .await
+            .unwrap();
+            assert_eq!(send(&mut authenticator, command, &a, &token).await, Ok(()));
+
+            for replayed in [TokenCommand::MakeCredential, TokenCommand::GetAssertion] {
+                assert_eq!(
+                    send(&mut authenticator, replayed, &a, &token).await,
+                    Err(Ctap2Error::PinAuthInvalid.into()),
+                    "{replayed:?} after {command:?}"
+                );
+            }
+            // Large blobs can still be written, like the platform does after getting an
+            // assertion with WebAuthn's largeBlob extension.
+            assert_eq!(
+                send(&mut authenticator, TokenCommand::LargeBlobs, &a, &token).await,
+                Ok(())
+            );
+        }
+    }
+}
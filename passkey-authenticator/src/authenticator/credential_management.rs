@@ -0,0 +1,595 @@
+use std::collections::VecDeque;
+
+use passkey_types::{
+    cose::validate_public_key,
+    crypto::sha256,
+    ctap2::{
+        credential_management::{Request, Response, Subcommand, SubcommandParams},
+        Ctap2Error, Permissions, StatusCode, U2FError,
+    },
+    webauthn::{PublicKeyCredentialDescriptor, PublicKeyCredentialType},
+    Passkey,
+};
+
+use crate::{
+    pin_protocol::PinProtocol, Authenticator, CredentialStore, DiscoverableCredential,
+    UserValidationMethod,
+};
+
+/// The remainder of an RP or credential enumeration, given out one at a time by the `GetNext`
+/// subcommands.
+pub(crate) struct Enumeration {
+    /// The subcommand which continues this enumeration.
+    next: Subcommand,
+    remaining: VecDeque<Response>,
+}
+
+impl<S, U> Authenticator<S, U>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod,
+{
+    /// This method is used by the platform to list, delete and update the discoverable
+    /// credentials of the authenticator. The store must support it through the optional
+    /// [`CredentialStore`] methods, otherwise `CTAP1_ERR_INVALID_COMMAND` is returned.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorCredentialManagement>
+    pub async fn credential_management(&mut self, input: Request) -> Result<Response, StatusCode> {
+        // Continuing an enumeration is not authenticated, while any other subcommand ends it.
+        if input.sub_command.is_enumeration_step() {
+            return self.next_enumerated(input.sub_command);
+        }
+        self.credential_enumeration = None;
+
+        // 1. The request must be authenticated with a PIN/UV auth token which has the cm
+        //    permission.
+        let pin_uv_auth_param = input
+            .pin_uv_auth_param
+            .as_deref()
+            .ok_or(Ctap2Error::PuatRequired)?;
+        let protocol = input
+            .pin_uv_auth_protocol
+            .ok_or(Ctap2Error::MissingParameter)?;
+        if PinProtocol::from_version(protocol).is_none() {
+            return Err(U2FError::InvalidParameter.into());
+        }
+        self.client_pin.verify_pin_auth(
+            Some(protocol),
+            Permissions::CM,
+            None,
+            &input.pin_uv_auth_message(),
+            pin_uv_auth_param,
+            self.now(),
+        )?;
+
+        // 2. Perform the subcommand.
+        let params = input.sub_command_params.unwrap_or_default();
+        match input.sub_command {
+            Subcommand::GetCredsMetadata => self.get_creds_metadata().await,
+            Subcommand::EnumerateRpsBegin => self.enumerate_rps().await,
+            Subcommand::EnumerateCredentialsBegin => self.enumerate_credentials(params).await,
+            Subcommand::DeleteCredential => self.delete_credential(params).await,
+            Subcommand::UpdateUserInformation => self.update_user_information(params).await,
+            // Enumeration steps were handled above.
+            Subcommand::EnumerateRpsGetNextRp
+            | Subcommand::EnumerateCredentialsGetNextCredential => {
+                Err(Ctap2Error::InvalidSubcommand.into())
+            }
+        }
+    }
+
+    /// Check that the PIN/UV auth token may manage the credentials of `rp_id`, or those of every
+    /// RP when `None`. A token restricted to an RP can only manage that RP's credentials.
+    fn check_token_rp_id(&self, rp_id: Option<&str>) -> Result<(), Ctap2Error> {
+        match (self.client_pin.pin_token_rp_id(), rp_id) {
+            (None, _) => Ok(()),
+            (Some(token_rp_id), Some(rp_id)) if token_rp_id == rp_id => Ok(()),
+            _ => Err(Ctap2Error::PinAuthInvalid),
+        }
+    }
+
+    async fn get_creds_metadata(&self) -> Result<Response, StatusCode> {
+        self.check_token_rp_id(None)?;
+        let existing = self.store.discoverable_credentials().await?.len();
+        Ok(Response {
+            existing_resident_credentials_count: Some(saturating_u32(existing)),
+            max_possible_remaining_resident_credentials_count: Some(
+                self.store.remaining_discoverable_credentials().await?,
+            ),
+            ..Default::default()
+        })
+    }
+
+    async fn enumerate_rps(&mut self) -> Result<Response, StatusCode> {
+        self.check_token_rp_id(None)?;
+        let mut rps: Vec<_> = self
+            .store
+            .discoverable_credentials()
+            .await?
+            .into_iter()
+            .map(|cred| cred.rp)
+            .collect();
+        // Keep the order in which RPs are first listed while removing duplicates.
+        let mut seen = std::collections::HashSet::new();
+        rps.retain(|rp| seen.insert(rp.id.clone()));
+
+        let total_rps = saturating_u32(rps.len());
+        let remaining = rps
+            .into_iter()
+            .map(|rp| Response {
+                rp_id_hash: Some(sha256(rp.id.as_bytes()).to_vec().into()),
+                rp: Some(rp),
+                ..Default::default()
+            })
+            .collect();
+        self.begin_enumeration(Subcommand::EnumerateRpsGetNextRp, remaining, |first| {
+            first.total_rps = Some(total_rps);
+        })
+    }
+
+    async fn enumerate_credentials(
+        &mut self,
+        params: SubcommandParams,
+    ) -> Result<Response, StatusCode> {
+        let rp_id_hash = params.rp_id_hash.ok_or(Ctap2Error::MissingParameter)?;
+        let creds: Vec<_> = self
+            .store
+            .discoverable_credentials()
+            .await?
+            .into_iter()
+            .filter(|cred| *rp_id_hash == sha256(cred.rp.id.as_bytes()))
+            .collect();
+        let Some(first) = creds.first() else {
+            return Err(Ctap2Error::NoCredentials.into());
+        };
+        self.check_token_rp_id(Some(&first.rp.id))?;
+
+        let total_credentials = saturating_u32(creds.len());
+        let remaining = creds
+            .into_iter()
+            .map(|cred| {
+                Ok(Response {
+                    public_key: Some(credential_public_key(&cred.passkey)?),
+                    large_blob_key: self
+                        .large_blobs
+                        .key(&cred.passkey.credential_id)
+                        .map(|key| key.to_vec().into()),
+                    credential_id: Some(PublicKeyCredentialDescriptor {
+                        ty: PublicKeyCredentialType::PublicKey,
+                        id: cred.passkey.credential_id.clone(),
+                        transports: None,
+                    }),
+                    user: Some(cred.user),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<_, StatusCode>>()?;
+        self.begin_enumeration(
+            Subcommand::EnumerateCredentialsGetNextCredential,
+            remaining,
+            |first| first.total_credentials = Some(total_credentials),
+        )
+    }
+
+    /// Give out the first of `remaining`, completed by `first`, and keep the rest for the `next`
+    /// subcommand. Returns `CTAP2_ERR_NO_CREDENTIALS` if there is nothing to enumerate.
+    fn begin_enumeration(
+        &mut self,
+        next: Subcommand,
+        mut remaining: VecDeque<Response>,
+        first: impl FnOnce(&mut Response),
+    ) -> Result<Response, StatusCode> {
+        let mut response = remaining.pop_front().ok_or(Ctap2Error::NoCredentials)?;
+        first(&mut response);
+        if !remaining.is_empty() {
+            self.credential_enumeration = Some(Enumeration { next, remaining });
+        }
+        Ok(response)
+    }
+
+    /// Continue the enumeration in progress with its `next` subcommand, ending it once every
+    /// entry was given out.
+    fn next_enumerated(&mut self, next: Subcommand) -> Result<Response, StatusCode> {
+        let enumeration = self
+            .credential_enumeration
+            .as_mut()
+            .filter(|enumeration| enumeration.next == next)
+            .ok_or(Ctap2Error::NotAllowed)?;
+        let response = enumeration
+            .remaining
+            .pop_front()
+            .ok_or(Ctap2Error::NotAllowed)?;
+        if enumeration.remaining.is_empty() {
+            self.credential_enumeration = None;
+        }
+        Ok(response)
+    }
+
+    /// Find the discoverable credential described by `params` which the PIN/UV auth token may
+    /// manage.
+    async fn find_managed_credential(
+        &self,
+        params: &SubcommandParams,
+    ) -> Result<DiscoverableCredential, StatusCode> {
+        let credential_id = params
+            .credential_id
+            .as_ref()
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let cred = self
+            .store
+            .discoverable_credentials()
+            .await?
+            .into_iter()
+            .find(|cred| cred.passkey.credential_id == credential_id.id)
+            .ok_or(Ctap2Error::NoCredentials)?;
+        self.check_token_rp_id(Some(&cred.rp.id))?;
+        Ok(cred)
+    }
+
+    /// Refuse to change the credentials of a read-only store.
+    async fn check_store_writable(&self) -> Result<(), StatusCode> {
+        if self.store.is_read_only().await {
+            return Err(Ctap2Error::OperationDenied.into());
+        }
+        Ok(())
+    }
+
+    async fn delete_credential(
+        &mut self,
+        params: SubcommandParams,
+    ) -> Result<Response, StatusCode> {
+        let cred = self.find_managed_credential(&params).await?;
+        self.check_store_writable().await?;
+        let credential_id = &cred.passkey.credential_id;
+        // The credential is deleted along with its keys in a transaction of the store, so it is
+        // kept when deleting any of them fails.
+        self.store_mut().begin_transaction().await?;
+        let deleted = async {
+            self.store.delete_credential(credential_id).await?;
+            self.large_blobs.remove_key(credential_id)?;
+            self.device_keys().remove_key(credential_id)
+        }
+        .await;
+        self.end_transaction(deleted).await?;
+        Ok(Response::default())
+    }
+
+    async fn update_user_information(
+        &mut self,
+        params: SubcommandParams,
+    ) -> Result<Response, StatusCode> {
+        let cred = self.find_managed_credential(&params).await?;
+        self.check_store_writable().await?;
+        let user = params.user.ok_or(Ctap2Error::MissingParameter)?;
+        // Only the names may change, not which user the credential belongs to.
+        if user.id != cred.user.id {
+            return Err(U2FError::InvalidParameter.into());
+        }
+        self.store
+            .update_user(&cred.passkey.credential_id, user)
+            .await?;
+        Ok(Response::default())
+    }
+}
+
+/// The public key of a stored credential, whose key is only public when it is derived from the
+/// master seed.
+fn credential_public_key(passkey: &Passkey) -> Result<coset::CoseKey, StatusCode> {
+    passkey
+        .public_key()
+        .or_else(|_| validate_public_key(&passkey.key).map(|()| passkey.key.clone()))
+        .map_err(|_| U2FError::Other.into())
+}
+
+fn saturating_u32(count: usize) -> u32 {
+    u32::try_from(count).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use coset::iana;
+    use passkey_types::ctap2::make_credential::PublicKeyCredentialUserEntity;
+
+    use super::*;
+    use crate::{
+        authenticator::client_pin::tests::{
+            authenticator, get_pin_uv_auth_token, set_pin, TestAuthenticator,
+        },
+        KeyProvider, SoftwareKeyProvider,
+    };
+
+    /// Save a credential for `rp_id`, discoverable when given a `user_handle`.
+    pub(crate) fn save_passkey(
+        authenticator: &mut TestAuthenticator,
+        rp_id: &str,
+        user_handle: Option<&[u8]>,
+    ) -> Passkey {
+        let key_pair = SoftwareKeyProvider
+            .generate_key(iana::Algorithm::ES256, &mut rand::rngs::OsRng)
+            .unwrap();
+        let passkey = Passkey {
+            key: key_pair.private.clone(),
+            credential_id: passkey_types::rand::random_vec(16).into(),
+            rp_id: rp_id.into(),
+            user_handle: user_handle.map(|handle| handle.to_vec().into()),
+            counter: None,
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
+        };
+        authenticator
+            .store_mut()
+            .insert(passkey.credential_id.to_vec(), passkey.clone().into());
+        passkey
+    }
+
+    fn request(sub_command: Subcommand, sub_command_params: Option<SubcommandParams>) -> Request {
+        Request {
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+    }
+
+    /// Authenticate `request` with `token`.
+    fn authenticated(token: &[u8], request: Request) -> Request {
+        let protocol = PinProtocol::Two;
+        let pin_uv_auth_param = protocol.authenticate(token, &request.pin_uv_auth_message());
+        Request {
+            pin_uv_auth_protocol: Some(protocol.version()),
+            pin_uv_auth_param: Some(pin_uv_auth_param.into()),
+            ..request
+        }
+    }
+
+    /// An authenticator with a PIN, along with a PIN/UV auth token with the `cm` permission
+    /// optionally restricted to `rp_id`.
+    async fn managed_authenticator(rp_id: Option<&str>) -> (TestAuthenticator, Vec<u8>) {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            PinProtocol::Two,
+            b"1234",
+            Permissions::CM,
+            rp_id,
+        )
+        .await
+        .expect("failed to get pin uv auth token");
+        (authenticator, token)
+    }
+
+    fn descriptor(passkey: &Passkey) -> PublicKeyCredentialDescriptor {
+        PublicKeyCredentialDescriptor {
+            ty: PublicKeyCredentialType::PublicKey,
+            id: passkey.credential_id.clone(),
+            transports: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn enumerate_rps_and_credentials() {
+        let (mut authenticator, token) = managed_authenticator(None).await;
+        let first = save_passkey(&mut authenticator, "a.example.com", Some(b"alice"));
+        save_passkey(&mut authenticator, "b.example.com", Some(b"bob"));
+        save_passkey(&mut authenticator, "a.example.com", Some(b"carol"));
+        // Non-discoverable credentials are not managed.
+        save_passkey(&mut authenticator, "c.example.com", None);
+
+        let metadata = authenticator
+            .credential_management(authenticated(
+                &token,
+                request(Subcommand::GetCredsMetadata, None),
+            ))
+            .await
+            .expect("failed to get metadata");
+        assert_eq!(metadata.existing_resident_credentials_count, Some(3));
+        assert_eq!(
+            metadata.max_possible_remaining_resident_credentials_count,
+            Some(u32::MAX)
+        );
+
+        let rp = authenticator
+            .credential_management(authenticated(
+                &token,
+                request(Subcommand::EnumerateRpsBegin, None),
+            ))
+            .await
+            .expect("failed to begin enumerating rps");
+        assert_eq!(rp.total_rps, Some(2));
+        let next = authenticator
+            .credential_management(request(Subcommand::EnumerateRpsGetNextRp, None))
+            .await
+            .expect("failed to get next rp");
+        let next_rp_id = next.rp.unwrap().id;
+        assert_eq!(
+            next.rp_id_hash.as_deref(),
+            Some(&sha256(next_rp_id.as_bytes()).to_vec())
+        );
+        let mut rp_ids = [rp.rp.unwrap().id, next_rp_id];
+        rp_ids.sort();
+        assert_eq!(rp_ids, ["a.example.com", "b.example.com"]);
+        assert_eq!(
+            authenticator
+                .credential_management(request(Subcommand::EnumerateRpsGetNextRp, None))
+                .await
+                .unwrap_err(),
+            Ctap2Error::NotAllowed.into()
+        );
+
+        let params = SubcommandParams {
+            rp_id_hash: Some(sha256(b"a.example.com").to_vec().into()),
+            ..Default::default()
+        };
+        let cred = authenticator
+            .credential_management(authenticated(
+                &token,
+                request(Subcommand::EnumerateCredentialsBegin, Some(params)),
+            ))
+            .await
+            .expect("failed to begin enumerating credentials");
+        assert_eq!(cred.total_credentials, Some(2));
+        let next = authenticator
+            .credential_management(request(
+                Subcommand::EnumerateCredentialsGetNextCredential,
+                None,
+            ))
+            .await
+            .expect("failed to get next credential");
+        let (alice, carol) = if cred.user.as_ref().unwrap().id == b"alice".to_vec().into() {
+            (cred, next)
+        } else {
+            (next, cred)
+        };
+        assert_eq!(carol.user.unwrap().id, b"carol".to_vec().into());
+        assert_eq!(alice.credential_id.unwrap().id, first.credential_id);
+        assert_eq!(alice.public_key, Some(first.public_key().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn delete_and_update_credentials() {
+        let (mut authenticator, token) = managed_authenticator(None).await;
+        let passkey = save_passkey(&mut authenticator, "a.example.com", Some(b"alice"));
+
+        let params = |user: Option<&[u8]>| SubcommandParams {
+            credential_id: Some(descriptor(&passkey)),
+            user: user.map(|id| PublicKeyCredentialUserEntity {
+                id: id.to_vec().into(),
+                name: Some("alice@example.com".into()),
+                display_name: Some("Alice".into()),
+                icon_url: None,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            authenticator
+                .credential_management(authenticated(
+                    &token,
+                    request(
+                        Subcommand::UpdateUserInformation,
+                        Some(params(Some(b"bob")))
+                    ),
+                ))
+                .await
+                .unwrap_err(),
+            U2FError::InvalidParameter.into()
+        );
+        authenticator
+            .credential_management(authenticated(
+                &token,
+                request(
+                    Subcommand::UpdateUserInformation,
+                    Some(params(Some(b"alice"))),
+                ),
+            ))
+            .await
+            .expect("failed to update user information");
+
+        authenticator
+            .credential_management(authenticated(
+                &token,
+                request(Subcommand::DeleteCredential, Some(params(None))),
+            ))
+            .await
+            .expect("failed to delete credential");
+        assert!(authenticator.store().is_empty());
+        assert_eq!(
+            authenticator
+                .credential_management(authenticated(
+                    &token,
+                    request(Subcommand::DeleteCredential, Some(params(None))),
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::NoCredentials.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn credential_management_requires_cm_permission() {
+        let (mut authenticator, token) = managed_authenticator(Some("b.example.com")).await;
+        let a = save_passkey(&mut authenticator, "a.example.com", Some(b"alice"));
+        let b = save_passkey(&mut authenticator, "b.example.com", Some(b"bob"));
+
+        assert_eq!(
+            authenticator
+                .credential_management(request(Subcommand::GetCredsMetadata, None))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PuatRequired.into()
+        );
+        assert_eq!(
+            authenticator
+                .credential_management(request(
+                    Subcommand::EnumerateCredentialsGetNextCredential,
+                    None
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::NotAllowed.into()
+        );
+
+        // A token restricted to an RP can only manage the credentials of that RP.
+        assert_eq!(
+            authenticator
+                .credential_management(authenticated(
+                    &token,
+                    request(Subcommand::GetCredsMetadata, None),
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinAuthInvalid.into()
+        );
+        let delete = |passkey: &Passkey| {
+            authenticated(
+                &token,
+                request(
+                    Subcommand::DeleteCredential,
+                    Some(SubcommandParams {
+                        credential_id: Some(descriptor(passkey)),
+                        ..Default::default()
+                    }),
+                ),
+            )
+        };
+        assert_eq!(
+            authenticator
+                .credential_management(delete(&a))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinAuthInvalid.into()
+        );
+        authenticator
+            .credential_management(delete(&b))
+            .await
+            .expect("failed to delete credential of the token's rp");
+        assert_eq!(authenticator.store().len(), 1);
+
+        // Tokens without the cm permission are refused.
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            PinProtocol::Two,
+            b"1234",
+            Permissions::MC,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            authenticator
+                .credential_management(authenticated(
+                    &token,
+                    request(Subcommand::EnumerateRpsBegin, None),
+                ))
+                .await
+                .unwrap_err(),
+            Ctap2Error::PinAuthInvalid.into()
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use ciborium::value::Value;
+use coset::{iana, CoseKey};
+use p256::SecretKey;
+use passkey_types::{
+    cose::public_key_from_private,
+    ctap2::{
+        Ctap2Error, DevicePublicKeyAttestation, DevicePublicKeyOutput, DevicePublicKeyScope,
+        StatusCode,
+    },
+};
+
+use crate::{
+    sign_with_cose_key, Authenticator, CoseKeyPair, CredentialStore, DeviceKeyStore,
+    StoredDeviceKeys, UserValidationMethod,
+};
+
+/// The device keys of the `devicePubKey` extension, as last saved to their store.
+pub(crate) struct DeviceKeys {
+    store: Box<dyn DeviceKeyStore + Send + Sync>,
+    stored: StoredDeviceKeys,
+}
+
+impl Default for DeviceKeys {
+    fn default() -> Self {
+        Self::new(Box::new(None::<StoredDeviceKeys>))
+    }
+}
+
+impl DeviceKeys {
+    /// Load the device keys from `store`.
+    pub(crate) fn new(store: Box<dyn DeviceKeyStore + Send + Sync>) -> Self {
+        Self {
+            stored: store.load().unwrap_or_default(),
+            store,
+        }
+    }
+
+    /// The private device key of the credential with the given ID, if it has one.
+    pub(crate) fn key(&self, credential_id: &[u8]) -> Option<&CoseKey> {
+        self.stored.keys.get(credential_id)
+    }
+
+    /// Save the private device key of a credential.
+    pub(crate) fn insert_key(
+        &mut self,
+        credential_id: &[u8],
+        key: CoseKey,
+    ) -> Result<(), StatusCode> {
+        self.update(|stored| {
+            stored.keys.insert(credential_id.to_vec(), key);
+        })
+    }
+
+    /// Forget the device key of a deleted credential.
+    pub(crate) fn remove_key(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        if !self.stored.keys.contains_key(credential_id) {
+            return Ok(());
+        }
+        self.update(|stored| {
+            stored.keys.remove(credential_id);
+        })
+    }
+
+    /// Forget every device key.
+    pub(crate) fn reset(&mut self) -> Result<(), StatusCode> {
+        self.update(|stored| stored.keys.clear())
+    }
+
+    /// Apply `change` to the device keys and save them, keeping the previous keys if saving fails.
+    fn update(&mut self, change: impl FnOnce(&mut StoredDeviceKeys)) -> Result<(), StatusCode> {
+        let mut stored = self.stored.clone();
+        change(&mut stored);
+        self.store.save(&stored)?;
+        self.stored = stored;
+        Ok(())
+    }
+}
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// Generate a new private device key.
+    ///
+    /// Device keys are P-256 keys generated in software rather than through the [`KeyProvider`],
+    /// since they are only used by the `devicePubKey` extension.
+    ///
+    /// [`KeyProvider`]: crate::KeyProvider
+    pub(crate) fn generate_device_key(&self) -> CoseKey {
+        let secret = SecretKey::random(&mut *self.rng());
+        let (_, private) =
+            CoseKeyPair::from_secret_key(&secret, iana::Algorithm::ES256).into_parts();
+        private
+    }
+
+    /// The `devicePubKey` output for the private device `key`, with a signature over
+    /// `signature_target`: the authenticator data followed by the client data hash.
+    ///
+    /// Only `none` attestation is supported, whatever the platform asks for.
+    ///
+    /// <https://w3c.github.io/webauthn/#sctn-device-publickey-extension>
+    pub(crate) fn device_pub_key_output(
+        &self,
+        key: &CoseKey,
+        signature_target: &[u8],
+    ) -> Result<DevicePublicKeyOutput, StatusCode> {
+        let dpk = public_key_from_private(key).map_err(Ctap2Error::from)?;
+        let sig = sign_with_cose_key(key, signature_target, None)?;
+        Ok(DevicePublicKeyOutput {
+            sig: sig.into(),
+            attestation: DevicePublicKeyAttestation {
+                aaguid: *self.aaguid(),
+                dpk,
+                scope: DevicePublicKeyScope::Device,
+                nonce: Vec::new().into(),
+                fmt: "none".into(),
+                att_stmt: Value::Map(Vec::new()),
+            },
+        })
+    }
+}
@@ -1,25 +1,39 @@
-use p256::ecdsa::{signature::SignerMut, SigningKey};
+use ciborium::value::Value;
 use passkey_types::{
     ctap2::{
         get_assertion::{Request, Response},
-        AuthenticatorData, Ctap2Error, StatusCode,
+        AuthenticatorData, Ctap2Error, DevicePublicKeyOutput, Flags, HmacSecretInput,
+        HmacSecretSalts, Permissions, StatusCode, U2FError,
     },
     webauthn::PublicKeyCredentialUserEntity,
     Passkey,
 };
 
-use crate::{private_key_from_cose_key, Authenticator, CredentialStore, UserValidationMethod};
+use crate::{
+    hmac_secret::{cred_random, output_secrets},
+    pin_protocol::PinProtocol,
+    Authenticator, CredentialStore, ExtensionContext, UserValidationMethod,
+};
 
-impl<S: CredentialStore + Sync, U> Authenticator<S, U>
+impl<S: CredentialStore + Sync + Send, U> Authenticator<S, U>
 where
-    S: CredentialStore + Sync,
+    S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync,
     Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
 {
     /// This method is used by a host to request cryptographic proof of user authentication as well
     /// as user consent to a given transaction, using a previously generated credential that is
     /// bound to the authenticator and relying party identifier.
-    pub async fn get_assertion(&self, input: Request) -> Result<Response, StatusCode> {
+    pub async fn get_assertion(&mut self, mut input: Request) -> Result<Response, StatusCode> {
+        self.begin_operation();
+
+        // CTAP 2.1: with alwaysUv, the user must be verified even if the request does not ask for
+        // it.
+        self.require_always_uv(&mut input.options, input.pin_auth.is_some())?;
+        if let Some(extensions) = input.extensions.as_mut() {
+            self.extensions().remove_disabled(extensions);
+        }
+
         // 1. Locate all credentials that are eligible for retrieval under the specified criteria:
         //     1. If an allowList is present and is non-empty, locate all denoted credentials
         //        present on this authenticator and bound to the specified rpId.
@@ -29,6 +43,18 @@ where
         //        --> Seeing as we handle 1 credential per account for an RP, returning the number
         //            of credentials leaks the number of accounts that is stored. This is not ideal,
         //            therefore we will never populate this field.
+        // Credentials wrapped or derived by this authenticator are not stored, they are recovered
+        // from their ID directly. All others are looked up in the store.
+        let unstored_credential = input.allow_list.as_deref().and_then(|list| {
+            self.wrapping_key
+                .as_ref()
+                .and_then(|wrapping_key| wrapping_key.find_credential(list, &input.rp_id))
+                .or_else(|| {
+                    self.master_seed
+                        .as_ref()
+                        .and_then(|master_seed| master_seed.find_credential(list, &input.rp_id))
+                })
+        });
         let maybe_credential = self
             .store()
             .find_credentials(
@@ -49,9 +75,21 @@ where
         //    return CTAP2_ERR_PIN_AUTH_INVALID.
         // 4. If pinAuth parameter is not present and clientPin has been set on the authenticator,
         //    set the "uv" bit to 0 in the response.
-        if input.pin_auth.is_some() {
-            return Err(Ctap2Error::PinAuthInvalid.into());
-        }
+        // CTAP 2.1: the PIN/UV auth token must also have the ga permission and either be bound to
+        // this RP ID or become bound to it.
+        let pin_verified = if let Some(pin_auth) = input.pin_auth.as_deref() {
+            self.client_pin.verify_pin_auth(
+                input.pin_protocol,
+                Permissions::GA,
+                Some(&input.rp_id),
+                &input.client_data_hash,
+                pin_auth,
+                self.now(),
+            )?;
+            true
+        } else {
+            false
+        };
 
         // 5. If the options parameter is present, process all the options.
         //     1. If the option is known but not supported, terminate this procedure and
@@ -62,30 +100,75 @@ where
         // Note that because this specification defines normative behaviors for them, all
         // authenticators MUST understand the "rk", "up", and "uv" options.
 
-        // 6. TODO, if the extensions parameter is present, process any extensions that this
+        // 6. If the extensions parameter is present, process any extensions that this
         //    authenticator supports. Authenticator extension outputs generated by the authenticator
         //    extension processing are returned in the authenticator data.
+        // NB: the hmac-secret extension is processed once the credential is selected and the user
+        // checked, see `Self::hmac_secret`.
 
         // 7. Collect user consent if required. This step MUST happen before the following steps due
         //    to privacy reasons (i.e., authenticator cannot disclose existence of a credential
         //    until the user interacted with the device):
-        let flags = self.check_user(&input.options).await?;
+        let mut flags = self.check_user(&input.options).await?;
+        if pin_verified {
+            flags |= Flags::UV;
+            // CTAP 2.1: a token used while the user was present may not be used for another
+            // request without them.
+            if flags.contains(Flags::UP) {
+                self.client_pin.clear_pin_token_permissions_except_lbw();
+            }
+        }
 
         // 8. If no credentials were located in step 1, return CTAP2_ERR_NO_CREDENTIALS.
-        let credential = maybe_credential?
-            .into_iter()
-            .next()
-            .ok_or(Ctap2Error::NoCredentials)?
-            .try_into()
-            .ok()
+        // Payments can only be confirmed with credentials registered for them.
+        let is_payment = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.payment.as_ref())
+            .and_then(|payment| payment.is_payment)
+            .unwrap_or_default();
+        let has_allow_list = input
+            .allow_list
+            .as_deref()
+            .is_some_and(|list| !list.is_empty());
+        let stored_credential = match maybe_credential {
+            Ok(credentials) => {
+                let mut credentials: Vec<Passkey> = credentials
+                    .into_iter()
+                    .filter_map(|credential| Passkey::try_from(credential).ok())
+                    .collect();
+                // See step 9, credentials of unknown creation time come last.
+                if !has_allow_list {
+                    credentials.sort_by_key(|credential| std::cmp::Reverse(credential.created_at));
+                }
+                credentials
+                    .into_iter()
+                    .find(|credential| !is_payment || credential.third_party_payment)
+            }
+            Err(_) if unstored_credential.is_some() => None,
+            Err(err) => return Err(err),
+        };
+        let is_stored = stored_credential.is_some();
+        let mut credential: Passkey = stored_credential
+            .or(unstored_credential.filter(|_| !is_payment))
             .ok_or(Ctap2Error::NoCredentials)?;
 
+        // Discoverable credentials derived from the master seed are stored with only their public
+        // key, derive the private key again.
+        if let Some(key_pair) = self
+            .master_seed
+            .as_ref()
+            .and_then(|master_seed| master_seed.derive(&input.rp_id, &credential.credential_id))
+        {
+            credential.key = key_pair.into_parts().1;
+        }
+
         // 9. If more than one credential was located in step 1 and allowList is present and not
         //    empty, select any applicable credential and proceed to step 12. Otherwise, order the
         //    credentials by the time when they were created in reverse order. The first credential
         //    is the most recent credential that was created.
-        // NB: This should be done within the `CredentialStore::find_any` implementation. Essentially
-        // if multiple credentials are found, use the most recently created one.
+        // NB: the stored credentials are ordered by their `Passkey::created_at` before one is
+        // selected in step 8, rather than relying on the `CredentialStore` to return them in order.
 
         // 10. If authenticator does not have a display:
         //     1. Remember the authenticatorGetAssertion parameters.
@@ -113,31 +196,172 @@ where
         //      concatenation is safe to use here because the authenticator data describes its own
         //      length. The hash of the serialized client data (which potentially has a variable
         //      length) is always the last element.
-        let auth_data = AuthenticatorData::new(&input.rp_id, credential.counter).set_flags(flags);
+        let mut auth_data = AuthenticatorData::new(&input.rp_id, credential.counter)
+            .set_flags(flags)
+            .set_backup_flags(credential.backup_eligible, credential.backup_state);
+        let mut extension_outputs = Vec::new();
+        if let Some(Some(HmacSecretInput::Get(salts))) = input
+            .extensions
+            .as_ref()
+            .map(|extensions| extensions.hmac_secret.as_ref())
+        {
+            if let Some(output) = self.hmac_secret(salts, &credential, flags)? {
+                extension_outputs.push((Value::Text("hmac-secret".into()), Value::Bytes(output)));
+            }
+        }
+        // The thirdPartyPayment extension reports whether the credential is enabled for
+        // third-party payments, so the platform can reject the ones which are not.
+        if input
+            .extensions
+            .as_ref()
+            .is_some_and(|extensions| extensions.third_party_payment == Some(true))
+        {
+            extension_outputs.push((
+                Value::Text("thirdPartyPayment".into()),
+                Value::Bool(credential.third_party_payment),
+            ));
+        }
+        // Custom extensions registered with `Self::with_extension` add their outputs last.
+        let mut custom_outputs = self
+            .custom_extension_outputs(
+                input.extensions.as_ref(),
+                &ExtensionContext {
+                    rp_id: &input.rp_id,
+                    credential_id: &credential.credential_id,
+                    flags,
+                },
+                true,
+            )
+            .await?;
+        extension_outputs.append(&mut custom_outputs.signed);
+        if !extension_outputs.is_empty() {
+            auth_data = auth_data.set_extensions(Value::Map(extension_outputs));
+        }
         let mut signature_target = auth_data.to_vec();
         signature_target.extend(input.client_data_hash);
 
-        let secret_key = private_key_from_cose_key(&credential.key)?;
+        let signature_bytes = self
+            .sign_assertion(&credential.key, &signature_target)?
+            .into();
 
-        let mut private_key = SigningKey::from(secret_key);
+        // Record when stored credentials were used, unless the store is read-only. The assertion
+        // is made even if the store fails to, as the time is only informational.
+        if is_stored && !self.store.is_read_only().await {
+            let _ = self
+                .store
+                .update_last_used(&credential.credential_id, self.now())
+                .await;
+        }
 
-        let signature: p256::ecdsa::Signature = private_key.sign(&signature_target);
-        let signature_bytes = signature.to_der().to_bytes().to_vec().into();
+        // The devicePubKey extension signs over the same data with the device key of the
+        // credential, generating one when the credential is used on this device for the first
+        // time.
+        let device_pub_key = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.device_pub_key.as_ref())
+            .map(|_| self.device_pub_key(&credential.credential_id, &signature_target))
+            .transpose()?;
+        let unsigned_extension_outputs = custom_outputs.unsigned_extension_outputs(device_pub_key);
 
+        // The names of the user may only be returned once they are verified.
+        let saved_user = if is_stored && flags.contains(Flags::UV) {
+            self.store.saved_user(&credential.credential_id).await
+        } else {
+            None
+        };
         let user_handle = credential.user_handle.clone();
 
+        // CTAP 2.1: return the credential's largeBlobKey if it has one and the platform asks for
+        // it through the largeBlobKey extension.
+        let large_blob_key = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.large_blob_key)
+            .filter(|&requested| requested)
+            .and_then(|_| self.large_blobs.key(&credential.credential_id));
+
         Ok(Response {
             credential: Some(credential.into()),
             auth_data,
             signature: signature_bytes,
-            user: user_handle.map(|id| PublicKeyCredentialUserEntity {
-                id,
-                // TODO: make a Authenticator version of this struct similar to make_credential::PublicKeyCredentialRpEntity
-                // since these fields are optional at the authenticator boundry, but required at the client boundry.
-                display_name: "".into(),
-                name: "".into(),
+            // The user handle identifies the user, along with the names they were saved with when
+            // the store keeps them.
+            user: user_handle.map(|id| {
+                let (name, display_name) = saved_user
+                    .map(|user| (user.name, user.display_name))
+                    .unwrap_or_default();
+                PublicKeyCredentialUserEntity {
+                    id,
+                    // TODO: make a Authenticator version of this struct similar to make_credential::PublicKeyCredentialRpEntity
+                    // since these fields are optional at the authenticator boundry, but required at the client boundry.
+                    display_name: display_name.unwrap_or_default(),
+                    name: name.unwrap_or_default(),
+                }
             }),
             number_of_credentials: None,
+            large_blob_key: large_blob_key.map(|key| key.to_vec().into()),
+            unsigned_extension_outputs,
         })
     }
+
+    /// The `devicePubKey` output for the credential with `credential_id`, signing
+    /// `signature_target` with its device key.
+    fn device_pub_key(
+        &self,
+        credential_id: &[u8],
+        signature_target: &[u8],
+    ) -> Result<DevicePublicKeyOutput, StatusCode> {
+        let mut device_keys = self.device_keys();
+        if let Some(key) = device_keys.key(credential_id) {
+            return self.device_pub_key_output(key, signature_target);
+        }
+        let key = self.generate_device_key();
+        let output = self.device_pub_key_output(&key, signature_target)?;
+        device_keys.insert_key(credential_id, key)?;
+        Ok(output)
+    }
+}
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// Compute the output secrets of the `hmac-secret` extension for `credential`, encrypted with
+    /// the secret shared with the platform. Returns `None` if the credential has no CredRandom.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-hmac-secret-extension>
+    pub(super) fn hmac_secret(
+        &self,
+        salts: &HmacSecretSalts,
+        credential: &Passkey,
+        flags: Flags,
+    ) -> Result<Option<Vec<u8>>, StatusCode> {
+        // 1. The PIN/UV auth protocol defaults to 1 when absent.
+        let protocol = PinProtocol::from_version(salts.pin_uv_auth_protocol.unwrap_or(1))
+            .ok_or(U2FError::InvalidParameter)?;
+        let platform_key = salts
+            .key_agreement
+            .as_ref()
+            .ok_or(Ctap2Error::MissingParameter)?;
+
+        // 2. Derive the shared secret from the platform's key agreement key and verify that the
+        //    salts were encrypted with it.
+        let shared_secret = self.client_pin.decapsulate(protocol, platform_key)?;
+        if !protocol.verify(&shared_secret, &salts.salt_enc, &salts.salt_auth) {
+            return Err(Ctap2Error::PinAuthInvalid.into());
+        }
+        let salts = protocol.decrypt(&shared_secret, &salts.salt_enc)?;
+
+        // 3. Use the CredRandom matching whether the user was verified, compute the HMAC of each
+        //    salt and return them encrypted with the shared secret.
+        let Some(cred_random) = cred_random(
+            credential,
+            flags.contains(Flags::UV),
+            self.hmac_secret_config.uv_policy,
+        ) else {
+            return Ok(None);
+        };
+        let outputs = output_secrets(cred_random.as_slice(), &salts)?;
+        protocol
+            .encrypt(&shared_secret, &outputs, self.rng().as_mut())
+            .map(Some)
+    }
 }
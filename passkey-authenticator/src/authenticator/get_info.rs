@@ -1,24 +1,323 @@
-use passkey_types::ctap2::get_info::{Options, Response};
+use passkey_types::{
+    ctap2::get_info::{Options, Response},
+    webauthn::{PublicKeyCredentialParameters, PublicKeyCredentialType},
+};
 
-use crate::{Authenticator, CredentialStore, UserValidationMethod};
+use super::large_blobs::MAX_SERIALIZED_LARGE_BLOB_ARRAY;
+use crate::{
+    pin_protocol::PinProtocol, Authenticator, CredentialStore, Extension, UserValidationMethod,
+};
 
-impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+/// The `USER_VERIFY_FINGERPRINT_INTERNAL` user verification method of the FIDO registry.
+const USER_VERIFY_FINGERPRINT_INTERNAL: u32 = 0x02;
+
+impl<S: CredentialStore + Sync, U: UserValidationMethod> Authenticator<S, U> {
     /// Using this method, the host can request that the authenticator report a list of all
     /// supported protocol versions, supported extensions, AAGUID of the device, and its capabilities.
-    pub fn get_info(&self) -> Response {
+    ///
+    /// The capabilities which depend on the integration are set through the builder methods, such
+    /// as [`Authenticator::with_max_msg_size`].
+    ///
+    /// The capabilities which come from the store and the configuration are computed once and
+    /// reused until they may have changed, see [`Authenticator::invalidate_info`]. Those of the
+    /// [`UserValidationMethod`] are asked for on every call, since they change with enrollments.
+    pub async fn get_info(&self) -> Response {
+        let mut info = self.capabilities().await;
+        let uv = self.user_validation.is_verification_enabled();
+        let fingerprint_sensor = self.user_validation.fingerprint_sensor();
+        if let Some(options) = info.options.as_mut() {
+            options.uv = uv;
+            options.up = self.user_validation.is_presence_enabled();
+            options.bio_enroll = fingerprint_sensor.as_ref().map(|_| uv == Some(true));
+        }
+        info.uv_modality = fingerprint_sensor.map(|_| USER_VERIFY_FINGERPRINT_INTERNAL);
+        info
+    }
+
+    /// The snapshot of the capabilities which come from the store and the configuration, leaving
+    /// out those of the [`UserValidationMethod`].
+    pub(crate) async fn capabilities(&self) -> Response {
+        let snapshot = self.info_snapshot().clone();
+        if let Some(capabilities) = snapshot {
+            return capabilities;
+        }
+        let capabilities = self.compute_capabilities().await;
+        *self.info_snapshot() = Some(capabilities.clone());
+        capabilities
+    }
+
+    async fn compute_capabilities(&self) -> Response {
         Response {
-            versions: vec!["FIDO_2_0".into(), "U2F_V2".into()],
-            extensions: None,
+            versions: if self.always_uv() {
+                vec!["FIDO_2_0".into(), "FIDO_2_1".into()]
+            } else {
+                vec!["FIDO_2_0".into(), "FIDO_2_1".into(), "U2F_V2".into()]
+            },
+            // The largeBlobKey of discoverable credentials is returned by `make_credential` and
+            // `get_assertion`, to be used with `large_blobs`. The devicePubKey output is returned in
+            // their unsigned extension outputs.
+            extensions: Some(self.extensions().identifiers()),
             aaguid: *self.aaguid(),
             options: Some(Options {
                 rk: true,
-                uv: self.user_validation.is_verification_enabled(),
-                up: self.user_validation.is_presence_enabled(),
+                ep: self
+                    .enterprise_attestation
+                    .is_some()
+                    .then(|| self.enterprise_attestation_enabled()),
+                client_pin: Some(self.client_pin.is_set()),
+                pin_uv_auth_token: Some(true),
+                cred_mgmt: Some(true),
+                large_blobs: Some(self.extensions().is_enabled(Extension::LargeBlobKey)),
+                always_uv: Some(self.always_uv()),
+                authnr_cfg: Some(true),
+                set_min_pin_length: Some(true),
                 ..Default::default()
             }),
-            max_msg_size: None,
-            pin_protocols: None,
+            max_msg_size: self.max_msg_size,
+            pin_protocols: Some(PinProtocol::SUPPORTED.map(PinProtocol::version).to_vec()),
+            max_credential_count_in_list: self.max_credential_count_in_list,
+            max_credential_id_length: self.max_credential_id_length,
             transports: Some(self.transports.clone()),
+            algorithms: Some(
+                self.key_provider
+                    .supported_algorithms()
+                    .into_iter()
+                    .map(|alg| PublicKeyCredentialParameters {
+                        ty: PublicKeyCredentialType::PublicKey,
+                        alg,
+                    })
+                    .collect(),
+            ),
+            max_serialized_large_blob_array: u32::try_from(MAX_SERIALIZED_LARGE_BLOB_ARRAY).ok(),
+            force_pin_change: Some(self.client_pin.stored().force_change),
+            min_pin_length: Some(self.client_pin.stored().min_pin_length),
+            firmware_version: self.firmware_version,
+            max_cred_blob_length: None,
+            max_rpids_for_set_min_pin_length: None,
+            preferred_platform_uv_attempts: None,
+            uv_modality: None,
+            certifications: self.certifications.clone(),
+            remaining_discoverable_credentials: self.store.remaining_capacity().await,
+            vendor_prototype_config_commands: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroU128, sync::Arc};
+
+    use coset::iana;
+    use passkey_types::ctap2::{authenticator_config, Aaguid};
+
+    use super::*;
+    use crate::{
+        credential_store::tests::passkey, user_validation::MockUserValidationMethod, MemoryStore,
+    };
+
+    #[tokio::test]
+    async fn configured_capabilities_are_reported() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let store = MemoryStore::new().with_max_credentials(25);
+        let authenticator = Authenticator::new(Aaguid::new_empty(), store, user_mock)
+            .with_max_msg_size(NonZeroU128::new(1200).unwrap())
+            .with_max_credential_count_in_list(8)
+            .with_max_credential_id_length(128)
+            .with_firmware_version(7)
+            .with_certifications([("FIDO".into(), 2)]);
+
+        let info = authenticator.get_info().await;
+
+        assert_eq!(info.versions, ["FIDO_2_0", "FIDO_2_1", "U2F_V2"]);
+        assert_eq!(
+            info.extensions,
+            Some(vec![
+                "hmac-secret".into(),
+                "hmac-secret-mc".into(),
+                "largeBlobKey".into(),
+                "devicePubKey".into(),
+                "thirdPartyPayment".into()
+            ])
+        );
+        assert_eq!(info.max_msg_size, NonZeroU128::new(1200));
+        assert_eq!(info.max_credential_count_in_list, Some(8));
+        assert_eq!(info.max_credential_id_length, Some(128));
+        assert_eq!(info.firmware_version, Some(7));
+        assert_eq!(info.certifications, Some([("FIDO".into(), 2)].into()));
+        assert_eq!(info.remaining_discoverable_credentials, Some(25));
+        assert_eq!(info.uv_modality, None);
+        let algorithms = info.algorithms.expect("missing algorithms");
+        assert_eq!(algorithms[0].alg, iana::Algorithm::ES256);
+        assert_eq!(algorithms[1].alg, iana::Algorithm::EdDSA);
+    }
+
+    struct VendorExtension;
+
+    impl crate::ExtensionHandler for VendorExtension {}
+
+    #[tokio::test]
+    async fn configured_extensions_are_reported() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+                .with_builtin_extensions([Extension::DevicePubKey, Extension::HmacSecret])
+                .with_extension("example.vendor", VendorExtension);
+
+        let info = authenticator.get_info().await;
+        assert_eq!(
+            info.extensions,
+            Some(vec![
+                "hmac-secret".into(),
+                "devicePubKey".into(),
+                "example.vendor".into()
+            ])
+        );
+        assert_eq!(
+            info.options.and_then(|options| options.large_blobs),
+            Some(false)
+        );
+
+        // Disabled extensions are ignored like unsupported ones.
+        let response = authenticator
+            .make_credential(passkey_types::ctap2::make_credential::Request {
+                client_data_hash: vec![0; 32].into(),
+                rp: passkey_types::ctap2::make_credential::PublicKeyCredentialRpEntity {
+                    id: "future.1password.com".into(),
+                    name: None,
+                },
+                user: passkey_types::webauthn::PublicKeyCredentialUserEntity {
+                    id: vec![1; 16].into(),
+                    display_name: "wendy".into(),
+                    name: "wendy".into(),
+                },
+                pub_key_cred_params: vec![PublicKeyCredentialParameters {
+                    ty: PublicKeyCredentialType::PublicKey,
+                    alg: iana::Algorithm::ES256,
+                }],
+                exclude_list: None,
+                extensions: Some(
+                    passkey_types::webauthn::AuthenticationExtensionsClientInputs {
+                        third_party_payment: Some(true),
+                        ..Default::default()
+                    },
+                ),
+                options: passkey_types::ctap2::make_credential::Options {
+                    rk: true,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+                enterprise_attestation: None,
+            })
+            .await
+            .expect("failed to create a credential");
+        assert_eq!(response.auth_data.extensions, None);
+    }
+
+    #[tokio::test]
+    async fn extensions_configured_after_get_info_are_reported() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let authenticator = Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        assert_eq!(
+            authenticator
+                .get_info()
+                .await
+                .extensions
+                .map(|ext| ext.len()),
+            Some(5)
+        );
+
+        let authenticator = authenticator.with_builtin_extensions([Extension::HmacSecret]);
+        assert_eq!(
+            authenticator.get_info().await.extensions,
+            Some(vec!["hmac-secret".into()])
+        );
+
+        let authenticator = authenticator.with_extension("example.vendor", VendorExtension);
+        assert_eq!(
+            authenticator.get_info().await.extensions,
+            Some(vec!["hmac-secret".into(), "example.vendor".into()])
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_capabilities_are_left_out() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let authenticator = Authenticator::new(Aaguid::new_empty(), None, user_mock);
+
+        let info = authenticator.get_info().await;
+
+        assert_eq!(info.max_msg_size, None);
+        assert_eq!(info.firmware_version, None);
+        assert_eq!(info.certifications, None);
+        assert_eq!(info.remaining_discoverable_credentials, None);
+    }
+
+    #[tokio::test]
+    async fn capabilities_are_computed_again_after_changes() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let store = Arc::new(tokio::sync::Mutex::new(
+            MemoryStore::new().with_max_credentials(25),
+        ));
+        let mut authenticator = Authenticator::new(Aaguid::new_empty(), store.clone(), user_mock);
+        let remaining = |info: Response| info.remaining_discoverable_credentials;
+        assert_eq!(remaining(authenticator.get_info().await), Some(25));
+
+        // Changes made to a shared store are only seen once the snapshot is invalidated.
+        store.lock().await.insert(vec![1], passkey(&[1]).into());
+        assert_eq!(remaining(authenticator.get_info().await), Some(25));
+        authenticator.invalidate_info();
+        assert_eq!(remaining(authenticator.get_info().await), Some(24));
+
+        // Changes made through the authenticator are seen right away.
+        authenticator
+            .store_mut()
+            .lock()
+            .await
+            .insert(vec![2], passkey(&[2]).into());
+        assert_eq!(remaining(authenticator.get_info().await), Some(23));
+        authenticator
+            .authenticator_config(authenticator_config::Request {
+                sub_command: authenticator_config::Subcommand::ToggleAlwaysUv,
+                sub_command_params: None,
+                pin_uv_auth_protocol: None,
+                pin_uv_auth_param: None,
+            })
+            .expect("failed to enable always uv");
+        let info = authenticator.get_info().await;
+        assert_eq!(info.options.unwrap().always_uv, Some(true));
+        assert_eq!(info.versions, ["FIDO_2_0", "FIDO_2_1"]);
+    }
+}
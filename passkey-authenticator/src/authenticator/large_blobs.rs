@@ -0,0 +1,388 @@
+use passkey_types::ctap2::{
+    large_blobs::{is_valid_large_blob_array, Request, Response, LARGE_BLOB_ARRAY_HASH_LENGTH},
+    Ctap2Error, Permissions, StatusCode, U2FError,
+};
+
+use crate::{
+    pin_protocol::PinProtocol, Authenticator, CredentialStore, LargeBlobStore, StoredLargeBlobs,
+    UserValidationMethod,
+};
+
+/// The maximum size of the serialized large-blob array, advertised in `getInfo`.
+pub(crate) const MAX_SERIALIZED_LARGE_BLOB_ARRAY: usize = 4096;
+
+/// The maximum message size of the transport when the authenticator does not report one.
+const DEFAULT_MAX_MSG_SIZE: usize = 1024;
+
+/// The large-blob array and keys of `authenticatorLargeBlobs` along with the write in progress.
+pub(crate) struct LargeBlobs {
+    store: Box<dyn LargeBlobStore + Send + Sync>,
+    /// The large-blob state as last saved to the store.
+    stored: StoredLargeBlobs,
+    /// The fragments of the array being written, and the total length announced for it.
+    pending: Option<(Vec<u8>, usize)>,
+}
+
+impl Default for LargeBlobs {
+    fn default() -> Self {
+        Self::new(Box::new(None::<StoredLargeBlobs>))
+    }
+}
+
+impl LargeBlobs {
+    /// Load the large-blob state from `store`.
+    pub(crate) fn new(store: Box<dyn LargeBlobStore + Send + Sync>) -> Self {
+        Self {
+            stored: store.load().unwrap_or_default(),
+            store,
+            pending: None,
+        }
+    }
+
+    /// The `largeBlobKey` of the credential with the given ID, if it was created with one.
+    pub(crate) fn key(&self, credential_id: &[u8]) -> Option<[u8; 32]> {
+        self.stored.keys.get(credential_id).copied()
+    }
+
+    /// Save the `largeBlobKey` of a new credential.
+    pub(crate) fn insert_key(
+        &mut self,
+        credential_id: &[u8],
+        key: [u8; 32],
+    ) -> Result<(), StatusCode> {
+        self.update(|stored| {
+            stored.keys.insert(credential_id.to_vec(), key);
+        })
+    }
+
+    /// Forget the `largeBlobKey` of a deleted credential.
+    pub(crate) fn remove_key(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        if !self.stored.keys.contains_key(credential_id) {
+            return Ok(());
+        }
+        self.update(|stored| {
+            stored.keys.remove(credential_id);
+        })
+    }
+
+    /// Restore the empty large-blob array and forget every key.
+    pub(crate) fn reset(&mut self) -> Result<(), StatusCode> {
+        self.pending = None;
+        self.update(|stored| *stored = StoredLargeBlobs::default())
+    }
+
+    /// Apply `change` to the large-blob state and save it, keeping the previous state if saving
+    /// fails.
+    fn update(&mut self, change: impl FnOnce(&mut StoredLargeBlobs)) -> Result<(), StatusCode> {
+        let mut stored = self.stored.clone();
+        change(&mut stored);
+        self.store.save(&stored)?;
+        self.stored = stored;
+        Ok(())
+    }
+}
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// This method is used by the platform to read and write the serialized large-blob array in
+    /// fragments. The array is opaque to the authenticator, which only checks its trailing hash
+    /// once a write is complete.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorLargeBlobs>
+    pub fn large_blobs(&mut self, input: Request) -> Result<Response, StatusCode> {
+        let offset = usize::try_from(input.offset).map_err(|_| U2FError::InvalidParameter)?;
+        match (input.get, input.set.as_deref()) {
+            (Some(get), None) => {
+                if input.length.is_some() {
+                    return Err(U2FError::InvalidParameter.into());
+                }
+                self.read_large_blobs(offset, get)
+            }
+            (None, Some(set)) => {
+                self.write_large_blobs(&input, offset, set)?;
+                Ok(Response::default())
+            }
+            _ => Err(U2FError::InvalidParameter.into()),
+        }
+    }
+
+    /// The maximum size of a fragment read or written at once: the maximum message size minus 64
+    /// bytes for the rest of the message.
+    fn max_fragment_length(&self) -> usize {
+        self.max_msg_size
+            .and_then(|max_msg_size| usize::try_from(max_msg_size.get()).ok())
+            .unwrap_or(DEFAULT_MAX_MSG_SIZE)
+            .saturating_sub(64)
+    }
+
+    fn read_large_blobs(&self, offset: usize, get: u32) -> Result<Response, StatusCode> {
+        // 1. The fragment may not be longer than the maximum fragment length, nor start past the
+        //    end of the array.
+        let get = usize::try_from(get)
+            .ok()
+            .filter(|get| *get <= self.max_fragment_length())
+            .ok_or(U2FError::InvalidLength)?;
+        let array = &self.large_blobs.stored.array;
+        if offset > array.len() {
+            return Err(U2FError::InvalidParameter.into());
+        }
+
+        // 2. Return at most `get` bytes from `offset`.
+        let end = array.len().min(offset + get);
+        Ok(Response {
+            config: Some(array[offset..end].to_vec().into()),
+        })
+    }
+
+    fn write_large_blobs(
+        &mut self,
+        input: &Request,
+        offset: usize,
+        set: &[u8],
+    ) -> Result<(), StatusCode> {
+        // 1. The fragment may not be longer than the maximum fragment length.
+        if set.len() > self.max_fragment_length() {
+            return Err(U2FError::InvalidLength.into());
+        }
+
+        // 2. The first fragment announces the length of the whole array, which must fit in the
+        //    authenticator and at least hold its hash. Any previous write is abandoned.
+        if offset == 0 {
+            let length = input
+                .length
+                .and_then(|length| usize::try_from(length).ok())
+                .ok_or(U2FError::InvalidParameter)?;
+            if length > MAX_SERIALIZED_LARGE_BLOB_ARRAY {
+                return Err(Ctap2Error::LargeBlobStorageFull.into());
+            }
+            if length <= LARGE_BLOB_ARRAY_HASH_LENGTH {
+                return Err(U2FError::InvalidParameter.into());
+            }
+            self.large_blobs.pending = Some((Vec::with_capacity(length), length));
+        } else if input.length.is_some() {
+            return Err(U2FError::InvalidParameter.into());
+        }
+
+        // 3. Fragments must follow each other.
+        let expected_offset = self
+            .large_blobs
+            .pending
+            .as_ref()
+            .map(|(buffer, _)| buffer.len());
+        if expected_offset != Some(offset) {
+            return Err(U2FError::InvalidSequence.into());
+        }
+
+        // 4. If the authenticator is protected by a PIN or by built-in user verification, the
+        //    fragment must be authenticated with a PIN/UV auth token which has the lbw permission.
+        if self.client_pin.is_set() || self.user_validation.is_verification_enabled() == Some(true)
+        {
+            let pin_uv_auth_param = input
+                .pin_uv_auth_param
+                .as_deref()
+                .ok_or(Ctap2Error::PuatRequired)?;
+            let protocol = input
+                .pin_uv_auth_protocol
+                .ok_or(Ctap2Error::MissingParameter)?;
+            if PinProtocol::from_version(protocol).is_none() {
+                return Err(U2FError::InvalidParameter.into());
+            }
+            self.client_pin.verify_pin_auth(
+                Some(protocol),
+                Permissions::LBW,
+                None,
+                &input.pin_uv_auth_message(),
+                pin_uv_auth_param,
+                self.now(),
+            )?;
+        }
+
+        // 5. Append the fragment, which may not overflow the announced length.
+        let Some((buffer, length)) = self.large_blobs.pending.as_mut() else {
+            return Err(U2FError::InvalidSequence.into());
+        };
+        if offset + set.len() > *length {
+            return Err(U2FError::InvalidParameter.into());
+        }
+        buffer.extend_from_slice(set);
+        if buffer.len() < *length {
+            return Ok(());
+        }
+
+        // 6. Once the array is complete, check its hash before replacing the stored one.
+        let Some((array, _)) = self.large_blobs.pending.take() else {
+            return Err(U2FError::InvalidSequence.into());
+        };
+        if !is_valid_large_blob_array(&array) {
+            return Err(Ctap2Error::IntegrityFailure.into());
+        }
+        self.large_blobs.update(|stored| stored.array = array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use passkey_types::{
+        crypto::sha256,
+        ctap2::{large_blobs::EMPTY_LARGE_BLOB_ARRAY, Aaguid},
+    };
+
+    use super::*;
+    use crate::{
+        authenticator::client_pin::tests::{get_pin_uv_auth_token, set_pin, TestAuthenticator},
+        user_validation::MockUserValidationMethod,
+        MemoryStore,
+    };
+
+    /// An authenticator without built-in user verification, so that writes need no PIN/UV auth
+    /// token until a PIN is set.
+    fn authenticator() -> TestAuthenticator {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+    }
+
+    /// Serialize a large-blob array holding the opaque `entries`, followed by its hash.
+    fn serialized_array(entries: &[u8]) -> Vec<u8> {
+        let mut array = vec![0x81, 0x59];
+        array.extend(u16::try_from(entries.len()).unwrap().to_be_bytes());
+        array.extend_from_slice(entries);
+        let hash = sha256(&array);
+        array.extend_from_slice(&hash[..LARGE_BLOB_ARRAY_HASH_LENGTH]);
+        array
+    }
+
+    fn read(authenticator: &mut TestAuthenticator, offset: u32, get: u32) -> Vec<u8> {
+        authenticator
+            .large_blobs(Request {
+                get: Some(get),
+                set: None,
+                offset,
+                length: None,
+                pin_uv_auth_param: None,
+                pin_uv_auth_protocol: None,
+            })
+            .expect("failed to read large blobs")
+            .config
+            .unwrap()
+            .to_vec()
+    }
+
+    /// Write `array` in fragments of `fragment_length` bytes, authenticated with `token` if given.
+    fn write(
+        authenticator: &mut TestAuthenticator,
+        array: &[u8],
+        fragment_length: usize,
+        token: Option<&[u8]>,
+    ) -> Result<(), StatusCode> {
+        for (i, fragment) in array.chunks(fragment_length).enumerate() {
+            let offset = u32::try_from(i * fragment_length).unwrap();
+            let mut request = Request {
+                get: None,
+                set: Some(fragment.to_vec().into()),
+                offset,
+                length: (offset == 0).then(|| u32::try_from(array.len()).unwrap()),
+                pin_uv_auth_param: None,
+                pin_uv_auth_protocol: None,
+            };
+            if let Some(token) = token {
+                let protocol = PinProtocol::Two;
+                request.pin_uv_auth_param = Some(
+                    protocol
+                        .authenticate(token, &request.pin_uv_auth_message())
+                        .into(),
+                );
+                request.pin_uv_auth_protocol = Some(protocol.version());
+            }
+            authenticator.large_blobs(request)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_and_read_large_blob_array_in_fragments() {
+        let mut authenticator = authenticator();
+        assert_eq!(read(&mut authenticator, 0, 960), EMPTY_LARGE_BLOB_ARRAY);
+
+        let array = serialized_array(&[7; 1500]);
+        write(&mut authenticator, &array, 960, None).expect("failed to write large blobs");
+        let mut read_back = read(&mut authenticator, 0, 960);
+        read_back.extend(read(&mut authenticator, 960, 960));
+        assert_eq!(read_back, array);
+        assert!(read(&mut authenticator, u32::try_from(array.len()).unwrap(), 960).is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_writes_are_rejected() {
+        let mut authenticator = authenticator();
+
+        let mut corrupted = serialized_array(&[7; 32]);
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            write(&mut authenticator, &corrupted, 960, None),
+            Err(Ctap2Error::IntegrityFailure.into())
+        );
+        assert_eq!(read(&mut authenticator, 0, 960), EMPTY_LARGE_BLOB_ARRAY);
+
+        assert_eq!(
+            write(&mut authenticator, &[0; 4097], 960, None),
+            Err(Ctap2Error::LargeBlobStorageFull.into())
+        );
+
+        // A fragment which does not follow the previous one is out of sequence.
+        let array = serialized_array(&[7; 32]);
+        assert_eq!(
+            authenticator
+                .large_blobs(Request {
+                    get: None,
+                    set: Some(array[10..].to_vec().into()),
+                    offset: 10,
+                    length: None,
+                    pin_uv_auth_param: None,
+                    pin_uv_auth_protocol: None,
+                })
+                .unwrap_err(),
+            U2FError::InvalidSequence.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_require_lbw_permission_with_a_pin() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let array = serialized_array(&[7; 32]);
+        assert_eq!(
+            write(&mut authenticator, &array, 960, None),
+            Err(Ctap2Error::PuatRequired.into())
+        );
+
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            PinProtocol::Two,
+            b"1234",
+            Permissions::GA,
+            Some("example.com"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            write(&mut authenticator, &array, 960, Some(&token)),
+            Err(Ctap2Error::PinAuthInvalid.into())
+        );
+
+        let token = get_pin_uv_auth_token(
+            &mut authenticator,
+            PinProtocol::Two,
+            b"1234",
+            Permissions::LBW,
+            None,
+        )
+        .await
+        .unwrap();
+        write(&mut authenticator, &array, 16, Some(&token)).expect("failed to write large blobs");
+        assert_eq!(read(&mut authenticator, 0, 960), array);
+    }
+}
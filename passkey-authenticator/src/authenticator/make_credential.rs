@@ -1,22 +1,35 @@
-use p256::SecretKey;
+use ciborium::value::Value;
 use passkey_types::{
+    crypto::zeroize_cose_key,
     ctap2::{
         make_credential::{Request, Response},
-        AttestedCredentialData, AuthenticatorData, Ctap2Error, StatusCode,
+        AttestedCredentialData, AuthenticatorData, Ctap2Error, Flags, HmacSecretInput, Permissions,
+        StatusCode,
     },
     Passkey,
 };
 
-use crate::{Authenticator, CoseKeyPair, CredentialStore, UserValidationMethod};
+use crate::{
+    hmac_secret::cred_random, AttestationInput, Authenticator, CredentialStore, ExtensionContext,
+    UserValidationMethod,
+};
 
 impl<S, U> Authenticator<S, U>
 where
-    S: CredentialStore + Sync,
+    S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync,
 {
     /// This method is invoked by the host to request generation of a new credential in the authenticator.
-    pub async fn make_credential(&mut self, input: Request) -> Result<Response, StatusCode> {
-        let flags = if input.options.up {
+    pub async fn make_credential(&mut self, mut input: Request) -> Result<Response, StatusCode> {
+        self.begin_operation();
+
+        // CTAP 2.1: with alwaysUv, the user must be verified even if the request does not ask for
+        // it.
+        self.require_always_uv(&mut input.options, input.pin_auth.is_some())?;
+        if let Some(extensions) = input.extensions.as_mut() {
+            self.extensions().remove_disabled(extensions);
+        }
+        let mut flags = if input.options.up {
             self.check_user(&input.options).await?
         } else {
             return Err(Ctap2Error::InvalidOption.into());
@@ -28,12 +41,15 @@ where
         //    presence check is required for CTAP2 authenticators before the RP gets told that the
         //    token is already registered to behave similarly to CTAP1/U2F authenticators.
 
-        if input
-            .exclude_list
-            .as_ref()
-            .filter(|list| !list.is_empty())
-            .is_some()
-        {
+        if let Some(exclude_list) = input.exclude_list.as_ref().filter(|list| !list.is_empty()) {
+            if self
+                .wrapping_key
+                .as_ref()
+                .and_then(|wrapping_key| wrapping_key.find_credential(exclude_list, &input.rp.id))
+                .is_some()
+            {
+                return Err(Ctap2Error::CredentialExcluded.into());
+            }
             if let Ok(false) = self
                 .store()
                 .find_credentials(input.exclude_list.as_deref(), &input.rp.id)
@@ -57,13 +73,69 @@ where
         //    authenticators MUST understand the "rk", "up", and "uv" options.
         // NB: this is handled at the very begining of the method
 
-        // 4. TODO, if the extensions parameter is present, process any extensions that this
+        // 4. If the extensions parameter is present, process any extensions that this
         //    authenticator supports. Authenticator extension outputs generated by the authenticator
         //    extension processing are returned in the authenticator data.
+        // The inputs of the built-in largeBlobKey, hmac-secret, hmac-secret-mc, devicePubKey,
+        // payment and thirdPartyPayment extensions are read here, their outputs are computed once
+        // the credential exists, before step 11. Custom extensions registered with
+        // `Self::with_extension` are processed last, other extensions are ignored.
+        // CTAP 2.1: the largeBlobKey extension is only valid for discoverable credentials. The key
+        // is returned outside of the authenticator data.
+        let large_blob_key = match input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.large_blob_key)
+            .filter(|&requested| requested)
+        {
+            Some(_) if !input.options.rk => return Err(Ctap2Error::InvalidOption.into()),
+            Some(_) => {
+                let mut key = [0; 32];
+                self.rng().fill_bytes(&mut key);
+                Some(key)
+            }
+            None => None,
+        };
+        // The hmac-secret extension only reports whether the credential has CredRandoms, which are
+        // derived from its private key when it is used, see `hmac_secret::cred_random`. With the
+        // hmac-secret-mc extension, the outputs are also computed for the new credential.
+        let hmac_secret = matches!(
+            input
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.hmac_secret.as_ref()),
+            Some(HmacSecretInput::Create(true))
+        );
+        let hmac_secret_mc = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.hmac_secret_mc.as_ref())
+            .filter(|_| hmac_secret);
+        // The devicePubKey extension generates a device key for the new credential, which stays on
+        // this authenticator even if the credential is synced.
+        let device_key = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.device_pub_key.as_ref())
+            .map(|_| self.generate_device_key());
+        // The payment extension, or the thirdPartyPayment extension through which a platform
+        // conveys it, marks the credential as usable for Secure Payment Confirmation. Only the
+        // latter is reported in the authenticator data.
+        let third_party_payment_input = input
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.third_party_payment);
+        let third_party_payment = third_party_payment_input == Some(true)
+            || input
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.payment.as_ref())
+                .and_then(|payment| payment.is_payment)
+                .unwrap_or_default();
 
-        // NB: We do not currently support any Pin Protocols (1 or 2) as this does not make sense
-        // in the context of 1Password. This is to be revisisted to see if we can hook this into
-        // using some key that we already have, such as the Biometry unlock key for example.
+        // CTAP 2.0: If the platform sends a zero length pinAuth, return CTAP2_ERR_PIN_NOT_SET if
+        // no PIN is set or CTAP2_ERR_PIN_INVALID if one is. This lets platforms check whether
+        // the PIN is set after the user has selected the authenticator.
         // 5. If pinAuth parameter is present and pinProtocol is 1, verify it by matching it against
         //    first 16 bytes of HMAC-SHA-256 of clientDataHash parameter using
         //    pinToken: HMAC- SHA-256(pinToken, clientDataHash).
@@ -73,11 +145,38 @@ where
         //    return CTAP2_ERR_PIN_REQUIRED error.
         // 7. If pinAuth parameter is present and the pinProtocol is not supported,
         //    return CTAP2_ERR_PIN_AUTH_INVALID.
-        if input.pin_auth.is_some() {
-            // we currently don't support pin authentication
-            return Err(Ctap2Error::UnsupportedOption.into());
+        // CTAP 2.1: the PIN/UV auth token must also have the mc permission and either be bound to
+        // this RP ID or become bound to it, see `ClientPin::verify_pin_auth`.
+        // NB: a user verified with the built-in method in the "uv" option does not need a PIN.
+        match input.pin_auth.as_deref().map(Vec::as_slice) {
+            Some([]) if self.client_pin.is_set() => return Err(Ctap2Error::PinInvalid.into()),
+            Some([]) => return Err(Ctap2Error::PinNotSet.into()),
+            Some(pin_auth) => {
+                self.client_pin.verify_pin_auth(
+                    input.pin_protocol,
+                    Permissions::MC,
+                    Some(&input.rp.id),
+                    &input.client_data_hash,
+                    pin_auth,
+                    self.now(),
+                )?;
+                // CTAP 2.1: the user was present for this request, so the token may not be used
+                // for another one without them.
+                self.client_pin.clear_pin_token_permissions_except_lbw();
+                flags |= Flags::UV;
+            }
+            None if self.client_pin.is_set() && !flags.contains(Flags::UV) => {
+                return Err(Ctap2Error::PuatRequired.into());
+            }
+            None => {}
         }
 
+        // CTAP 2.1: If the enterpriseAttestation parameter is present and the authenticator is
+        // not enterprise attestation capable, return CTAP1_ERR_INVALID_PARAMETER. Otherwise decide
+        // whether this request receives an enterprise attestation.
+        let (attestation_provider, ep_att) =
+            self.attestation_provider_for(&input.rp.id, input.enterprise_attestation)?;
+
         // 8. If the authenticator has a display, show the items contained within the user and rp
         //    parameter structures to the user. Alternatively, request user interaction in an
         //    authenticator-specific way (e.g., flash the LED light). Request permission to create
@@ -85,29 +184,54 @@ where
         //    error.
 
         // 9. Generate a new credential key pair for the algorithm specified.
-        let credential_id: Vec<u8> = {
-            use rand::RngCore;
-            let mut data = vec![0u8; 16];
-            rand::thread_rng().fill_bytes(&mut data);
-            data
-        };
-
-        let private_key = {
-            let mut rng = rand::thread_rng();
-            SecretKey::random(&mut rng)
-        };
-
         // Encoding of the keypair into their CoseKey representation before moving the private CoseKey
         // into the passkey. Keeping the public key ready for step 11 below and returning the attested
         // credential.
-        let CoseKeyPair { public, private } = CoseKeyPair::from_secret_key(&private_key, algorithm);
+        // When a master seed is configured the key is derived from it and the credential ID, so
+        // the private key never needs to be stored. Otherwise non-discoverable credentials are
+        // wrapped into their credential ID when a wrapping key is configured, in which case
+        // nothing needs to be stored on the authenticator.
+        let wrapping_key = self.wrapping_key.as_ref().filter(|_| !input.options.rk);
+        let (public, private, credential_id) = {
+            let mut rng = self.rng();
+            if let Some(master_seed) = self.master_seed.as_ref() {
+                let (credential_id, key_pair) =
+                    master_seed.new_credential(&input.rp.id, algorithm, rng.as_mut())?;
+                let (public, private) = key_pair.into_parts();
+                (public, private, credential_id)
+            } else {
+                let (public, private) = self
+                    .key_provider
+                    .generate_key(algorithm, rng.as_mut())?
+                    .into_parts();
 
-        let passkey = Passkey {
+                let credential_id: Vec<u8> = if let Some(wrapping_key) = wrapping_key {
+                    wrapping_key.wrap(&input.rp.id, &private, rng.as_mut())?
+                } else {
+                    let mut data = vec![0u8; 16];
+                    rng.fill_bytes(&mut data);
+                    data
+                };
+                (public, private, credential_id)
+            }
+        };
+        let store_credential =
+            input.options.rk || (self.master_seed.is_none() && wrapping_key.is_none());
+        let derived_public_key = self.master_seed.as_ref().map(|_| public.clone());
+
+        let mut passkey = Passkey {
             key: private,
             rp_id: input.rp.id.clone(),
             credential_id: credential_id.into(),
             user_handle: input.options.rk.then_some(input.user.id.clone()),
             counter: None,
+            third_party_payment,
+            // Credentials which are not stored cannot be backed up.
+            backup_eligible: store_credential && self.backup_eligible,
+            backup_state: store_credential && self.backup_state,
+            cred_randoms: None,
+            created_at: Some(self.clock.now()),
+            last_used_at: None,
         };
 
         // 10. If "rk" in options parameter is set to true:
@@ -116,7 +240,29 @@ where
         //     2. Store the user parameter along the newly-created key pair.
         //     3. If authenticator does not have enough internal storage to persist the new
         //        credential, return CTAP2_ERR_KEY_STORE_FULL.
-        // --> This seems like in the wrong place since we still need the passkey, see after step 11.
+        // The credential is only saved once it is attested, see after step 11, but it must fit in
+        // the store before then. This also applies to non-discoverable credentials which are
+        // stored. The remaining capacity is taken from the snapshot of the capabilities, which is
+        // refreshed whenever the store is modified through the authenticator. A credential which
+        // overwrites another always fits, the overwritten ones being deleted when it is saved.
+        let overwritten = match passkey.user_handle.as_deref() {
+            Some(user_handle) if self.overwrite_credentials => {
+                self.store
+                    .find_by_rp_and_user_handle(&input.rp.id, user_handle)
+                    .await?
+            }
+            _ => Vec::new(),
+        };
+        // Read-only stores cannot save the credential at all.
+        if store_credential && self.store.is_read_only().await {
+            return Err(Ctap2Error::OperationDenied.into());
+        }
+        if store_credential
+            && overwritten.is_empty()
+            && self.capabilities().await.remaining_discoverable_credentials == Some(0)
+        {
+            return Err(Ctap2Error::KeyStoreFull.into());
+        }
 
         // 11. Generate an attestation statement for the newly-created key using clientDataHash.
 
@@ -129,20 +275,109 @@ where
         )
         .unwrap();
 
-        let auth_data = AuthenticatorData::new(&input.rp.id, passkey.counter)
+        let mut auth_data = AuthenticatorData::new(&input.rp.id, passkey.counter)
             .set_flags(flags)
+            .set_backup_flags(passkey.backup_eligible, passkey.backup_state)
             .set_attested_credential_data(acd);
+        let mut extension_outputs = Vec::new();
+        if hmac_secret {
+            let created = cred_random(&passkey, true, self.hmac_secret_config.uv_policy).is_some();
+            extension_outputs.push((Value::Text("hmac-secret".into()), Value::Bool(created)));
+        }
+        if let Some(salts) = hmac_secret_mc {
+            if let Some(output) = self.hmac_secret(salts, &passkey, flags)? {
+                extension_outputs
+                    .push((Value::Text("hmac-secret-mc".into()), Value::Bytes(output)));
+            }
+        }
+        if third_party_payment_input.is_some() {
+            extension_outputs.push((
+                Value::Text("thirdPartyPayment".into()),
+                Value::Bool(third_party_payment),
+            ));
+        }
+        // Custom extensions registered with `Self::with_extension` add their outputs last.
+        let mut custom_outputs = self
+            .custom_extension_outputs(
+                input.extensions.as_ref(),
+                &ExtensionContext {
+                    rp_id: &input.rp.id,
+                    credential_id: &passkey.credential_id,
+                    flags,
+                },
+                false,
+            )
+            .await?;
+        extension_outputs.append(&mut custom_outputs.signed);
+        if !extension_outputs.is_empty() {
+            auth_data = auth_data.set_extensions(Value::Map(extension_outputs));
+        }
+
+        let auth_data_bytes = auth_data.to_vec();
+        let statement = attestation_provider
+            .attest(&AttestationInput {
+                auth_data: &auth_data_bytes,
+                client_data_hash: &input.client_data_hash,
+                algorithm,
+                aaguid: *self.aaguid(),
+                credential_signer: &|data| self.sign(&passkey.key, data),
+            })
+            .await?;
+
+        // The device key signs over the same data as the attestation statement.
+        let device_pub_key = device_key
+            .as_ref()
+            .map(|key| {
+                let signature_target =
+                    [auth_data_bytes.as_slice(), &input.client_data_hash].concat();
+                self.device_pub_key_output(key, &signature_target)
+            })
+            .transpose()?;
+        let unsigned_extension_outputs = custom_outputs.unsigned_extension_outputs(device_pub_key);
 
         let response = Response {
             auth_data,
-            fmt: "None".into(),
-            att_stmt: vec![0xa0].into(), // CBOR exquivalent to empty map
+            fmt: statement.fmt,
+            att_stmt: statement.att_stmt,
+            ep_att: input.enterprise_attestation.map(|_| ep_att),
+            large_blob_key: large_blob_key.map(|key| key.to_vec().into()),
+            unsigned_extension_outputs,
         };
 
         // 10
-        self.store_mut()
-            .save_credential(passkey, input.user.into(), input.rp)
-            .await?;
+        // The credential is saved along with its keys in a transaction of the store, so it is not
+        // kept when saving any of them fails. The same goes for the credentials of the same user
+        // which it overwrites, along with their keys.
+        let credential_id = passkey.credential_id.clone();
+        if store_credential {
+            if let Some(public) = derived_public_key {
+                zeroize_cose_key(&mut passkey.key);
+                passkey.key = public;
+            }
+            self.store_mut().begin_transaction().await?;
+            let saved = async {
+                for old in overwritten {
+                    self.store.delete_credential(&old.credential_id).await?;
+                    self.large_blobs.remove_key(&old.credential_id)?;
+                    self.device_keys().remove_key(&old.credential_id)?;
+                }
+                self.store
+                    .save_credential(passkey, input.user.into(), input.rp)
+                    .await?;
+                if let Some(key) = large_blob_key {
+                    self.large_blobs.insert_key(&credential_id, key)?;
+                }
+                if let Some(key) = device_key {
+                    self.device_keys().insert_key(&credential_id, key)?;
+                }
+                Ok(())
+            }
+            .await;
+            self.end_transaction(saved).await?;
+        } else if let Some(key) = device_key {
+            // Device keys are kept for every credential, including those which are not stored.
+            self.device_keys().insert_key(&credential_id, key)?;
+        }
 
         Ok(response)
     }
@@ -150,12 +385,12 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
-    use coset::iana;
+    use coset::iana::{self, EnumI64};
     use passkey_types::{
         ctap2::make_credential::{Options, PublicKeyCredentialRpEntity},
-        ctap2::Aaguid,
+        ctap2::{Aaguid, HmacSecretOutput, HmacSecretSalts, U2FError},
         rand::random_vec,
         webauthn, Bytes,
     };
@@ -163,7 +398,16 @@ mod tests {
     use tokio::sync::Mutex;
 
     use super::*;
-    use crate::{user_validation::MockUserValidationMethod, MemoryStore};
+    use crate::{
+        authenticator::client_pin,
+        clock::tests::ManualClock,
+        credential_store::tests::{ListStore, TransactionalStore},
+        pin_protocol::PinProtocol,
+        pin_store::MAX_UV_RETRIES,
+        user_validation::MockUserValidationMethod,
+        Attestation, AttestationProvider, AttestationStatement, DeviceKeyStore, MemoryStore,
+        PackedAttestation, StoredConfig, StoredDeviceKeys, UvRateLimit,
+    };
 
     fn good_request() -> Request {
         Request {
@@ -190,6 +434,7 @@ mod tests {
             },
             pin_auth: None,
             pin_protocol: None,
+            enterprise_attestation: None,
         }
     }
 
@@ -227,15 +472,24 @@ mod tests {
         let passkey = Passkey {
             // contents of key doesn't matter, only the id
             key: Default::default(),
-            rp_id: "".into(),
+            rp_id: "future.1password.com".into(),
             credential_id: cred_id.clone(),
             user_handle: Some(response.user.id.clone()),
             counter: None,
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
         };
         let shared_store = Arc::new(Mutex::new(MemoryStore::new()));
         let user_mock = MockUserValidationMethod::verified_user(1);
 
-        shared_store.lock().await.insert(cred_id.into(), passkey);
+        shared_store
+            .lock()
+            .await
+            .insert(cred_id.into(), passkey.into());
 
         let mut authenticator =
             Authenticator::new(Aaguid::new_empty(), shared_store.clone(), user_mock);
@@ -270,4 +524,1676 @@ mod tests {
 
         assert_eq!(err, Ctap2Error::UnsupportedAlgorithm.into());
     }
+
+    #[tokio::test]
+    async fn make_credential_with_eddsa() {
+        let user_mock = MockUserValidationMethod::verified_user(1);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+
+        let request = Request {
+            pub_key_cred_params: vec![webauthn::PublicKeyCredentialParameters {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                alg: iana::Algorithm::EdDSA,
+            }],
+            ..good_request()
+        };
+
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create an EdDSA credential");
+
+        let key = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .key;
+        assert_eq!(
+            key.alg,
+            Some(coset::RegisteredLabelWithPrivate::Assigned(
+                iana::Algorithm::EdDSA
+            ))
+        );
+        assert_eq!(
+            key.kty,
+            coset::RegisteredLabel::Assigned(iana::KeyType::OKP)
+        );
+    }
+
+    #[tokio::test]
+    async fn non_discoverable_credentials_are_wrapped() {
+        let shared_store = Arc::new(Mutex::new(MemoryStore::new()));
+        let user_mock = MockUserValidationMethod::verified_user(2);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), shared_store.clone(), user_mock)
+                .with_wrapping_key([42; 32]);
+
+        let request = Request {
+            options: Options {
+                rk: false,
+                up: true,
+                uv: true,
+            },
+            ..good_request()
+        };
+
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a wrapped credential");
+        assert!(shared_store.lock().await.is_empty());
+
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let response = authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.clone().into(),
+                    transports: None,
+                }]),
+                extensions: None,
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to assert with a wrapped credential");
+
+        assert_eq!(
+            response.credential.map(|cred| cred.id),
+            Some(credential_id.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn seeded_rng_makes_reproducible_credentials() {
+        use rand::SeedableRng;
+
+        let mut attested = Vec::new();
+        for _ in 0..2 {
+            let mut authenticator = Authenticator::new(
+                Aaguid::new_empty(),
+                MemoryStore::new(),
+                MockUserValidationMethod::verified_user(1),
+            )
+            .with_rng(rand::rngs::StdRng::seed_from_u64(1234));
+
+            let response = authenticator
+                .make_credential(good_request())
+                .await
+                .expect("failed to create credential");
+            attested.push(
+                response
+                    .auth_data
+                    .attested_credential_data
+                    .expect("missing attested credential data"),
+            );
+        }
+
+        assert_eq!(attested[0].credential_id(), attested[1].credential_id());
+        assert_eq!(attested[0].key, attested[1].key);
+    }
+
+    #[tokio::test]
+    async fn self_attestation_is_signed_by_credential_key() {
+        use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(1),
+        )
+        .with_attestation(Attestation::SelfAttestation);
+
+        let request = good_request();
+        let client_data_hash = request.client_data_hash.clone();
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create credential");
+        assert_eq!(response.fmt, "packed");
+
+        let ciborium::value::Value::Map(att_stmt) = response.att_stmt else {
+            panic!("attestation statement is not a map");
+        };
+        let field = |name: &str| {
+            att_stmt
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(name))
+                .map(|(_, v)| v.clone())
+                .expect("missing attestation statement field")
+        };
+        assert_eq!(
+            field("alg").as_integer(),
+            Some((iana::Algorithm::ES256 as i64).into())
+        );
+        let sig = Signature::from_der(field("sig").as_bytes().expect("sig is not bytes"))
+            .expect("sig is not a DER signature");
+
+        let public_key = crate::public_key_der_from_cose_key(
+            &response
+                .auth_data
+                .attested_credential_data
+                .as_ref()
+                .unwrap()
+                .key,
+        )
+        .unwrap();
+        let verifying_key = {
+            use p256::pkcs8::DecodePublicKey;
+            VerifyingKey::from_public_key_der(&public_key).expect("invalid public key")
+        };
+        let mut signature_target = response.auth_data.to_vec();
+        signature_target.extend(client_data_hash.iter());
+        verifying_key
+            .verify(&signature_target, &sig)
+            .expect("failed to verify self attestation");
+    }
+
+    #[tokio::test]
+    async fn enterprise_attestation() {
+        let request = |enterprise_attestation| Request {
+            enterprise_attestation,
+            ..good_request()
+        };
+
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(4),
+        );
+        assert_eq!(
+            authenticator
+                .make_credential(request(Some(1)))
+                .await
+                .unwrap_err(),
+            U2FError::InvalidParameter.into()
+        );
+
+        // A capable authenticator still needs enterprise attestation to be enabled.
+        let authenticator = authenticator.with_enterprise_attestation(
+            PackedAttestation::SelfAttestation,
+            vec!["future.1password.com".into()],
+        );
+        assert!(!authenticator.enterprise_attestation_enabled());
+        let mut authenticator = authenticator.with_config_store(Some(StoredConfig {
+            enterprise_attestation: true,
+            ..Default::default()
+        }));
+        assert!(authenticator.enterprise_attestation_enabled());
+
+        let response = authenticator
+            .make_credential(request(Some(1)))
+            .await
+            .expect("failed to create credential");
+        assert_eq!(response.fmt, "packed");
+        assert_eq!(response.ep_att, Some(true));
+
+        let response = authenticator
+            .make_credential(Request {
+                rp: PublicKeyCredentialRpEntity {
+                    id: "example.com".into(),
+                    name: None,
+                },
+                ..request(Some(1))
+            })
+            .await
+            .expect("failed to create credential");
+        assert_eq!(response.fmt, "none");
+        assert_eq!(response.ep_att, Some(false));
+
+        assert_eq!(
+            authenticator
+                .make_credential(request(Some(3)))
+                .await
+                .unwrap_err(),
+            Ctap2Error::InvalidOption.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn derived_credentials_store_no_private_key() {
+        let shared_store = Arc::new(Mutex::new(MemoryStore::new()));
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            shared_store.clone(),
+            MockUserValidationMethod::verified_user(4),
+        )
+        .with_master_seed([9; 32]);
+
+        let mut credential_ids = Vec::new();
+        for rk in [true, false] {
+            let request = Request {
+                options: Options {
+                    rk,
+                    up: true,
+                    uv: true,
+                },
+                ..good_request()
+            };
+            let response = authenticator
+                .make_credential(request)
+                .await
+                .expect("failed to create a derived credential");
+            credential_ids.push(
+                response
+                    .auth_data
+                    .attested_credential_data
+                    .expect("missing attested credential data")
+                    .credential_id()
+                    .to_vec(),
+            );
+        }
+
+        {
+            let store = shared_store.lock().await;
+            assert_eq!(store.len(), 1, "only the discoverable credential is stored");
+            let stored = &store
+                .get(&credential_ids[0])
+                .expect("missing credential")
+                .passkey;
+            assert_eq!(
+                passkey_types::cose::validate_public_key(&stored.key),
+                Ok(())
+            );
+            assert_eq!(
+                passkey_types::cose::validate_private_key(&stored.key),
+                Err(passkey_types::cose::CoseKeyError::MissingParameter)
+            );
+        }
+
+        for credential_id in credential_ids {
+            authenticator
+                .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                    rp_id: "future.1password.com".into(),
+                    client_data_hash: random_vec(32).into(),
+                    allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                        ty: webauthn::PublicKeyCredentialType::PublicKey,
+                        id: credential_id.into(),
+                        transports: None,
+                    }]),
+                    extensions: None,
+                    options: Options {
+                        rk: false,
+                        up: true,
+                        uv: true,
+                    },
+                    pin_auth: None,
+                    pin_protocol: None,
+                })
+                .await
+                .expect("failed to assert with a derived credential");
+        }
+    }
+
+    type SigningRequest = (Vec<u8>, tokio::sync::oneshot::Sender<Vec<u8>>);
+
+    /// Signs attestation statements through a channel, standing in for a remote signing service.
+    struct RemoteAttestation {
+        requests: tokio::sync::mpsc::UnboundedSender<SigningRequest>,
+    }
+
+    #[async_trait::async_trait]
+    impl AttestationProvider for RemoteAttestation {
+        async fn attest(
+            &self,
+            input: &AttestationInput<'_>,
+        ) -> Result<AttestationStatement, Ctap2Error> {
+            let (response, signature) = tokio::sync::oneshot::channel();
+            self.requests
+                .send((input.signature_target(), response))
+                .map_err(|_| Ctap2Error::OperationDenied)?;
+            let sig = signature.await.map_err(|_| Ctap2Error::OperationDenied)?;
+            Ok(AttestationStatement {
+                fmt: "packed".into(),
+                att_stmt: ciborium::value::Value::Map(vec![
+                    ("alg".into(), iana::Algorithm::ES256.to_i64().into()),
+                    ("sig".into(), ciborium::value::Value::Bytes(sig)),
+                ]),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn async_attestation_provider() {
+        use p256::ecdsa::{
+            signature::{Signer, Verifier},
+            Signature, SigningKey,
+        };
+
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        let verifying_key = *signing_key.verifying_key();
+        let (requests, mut pending) = tokio::sync::mpsc::unbounded_channel::<SigningRequest>();
+        let signer = tokio::spawn(async move {
+            while let Some((data, response)) = pending.recv().await {
+                let signature: Signature = signing_key.sign(&data);
+                let _ = response.send(signature.to_der().as_bytes().to_vec());
+            }
+        });
+
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(1),
+        )
+        .with_attestation_provider(RemoteAttestation { requests });
+        let request = good_request();
+        let client_data_hash = request.client_data_hash.clone();
+
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create credential");
+        assert_eq!(response.fmt, "packed");
+
+        let sig = response
+            .att_stmt
+            .as_map()
+            .and_then(|map| map.iter().find(|(k, _)| k.as_text() == Some("sig")))
+            .and_then(|(_, v)| v.as_bytes())
+            .expect("missing signature");
+        let signed_data = [response.auth_data.to_vec(), client_data_hash.to_vec()].concat();
+        verifying_key
+            .verify(&signed_data, &Signature::from_der(sig).unwrap())
+            .expect("failed to verify remote attestation signature");
+
+        drop(authenticator);
+        signer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn uv_rate_limit_locks_out_user_verification() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .times(2)
+            .returning(|| Box::pin(async { false }));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        let clock = ManualClock::new();
+        let limit = UvRateLimit {
+            max_consecutive_failures: 2,
+            lockout: Duration::from_secs(30),
+        };
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+                .with_clock(clock.clone())
+                .with_uv_rate_limit(limit);
+
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::OperationDenied.into());
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Ctap2Error::UserVerficationBlocked.into()
+        );
+
+        assert!(authenticator.pin_state().uv_blocked);
+
+        // The user is not asked to verify while locked out, or they would succeed.
+        clock.advance(Duration::from_secs(29));
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Ctap2Error::UserVerficationBlocked.into()
+        );
+
+        clock.advance(Duration::from_secs(1));
+        let response = authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create credential after the lockout");
+        assert!(!authenticator.pin_state().uv_blocked);
+        assert!(response.auth_data.flags.contains(Flags::UV));
+    }
+
+    #[tokio::test]
+    async fn uv_option_uses_up_uv_retries() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .times(1)
+            .returning(|| Box::pin(async { false }));
+        user_mock
+            .expect_check_user_verification()
+            .times(1)
+            .returning(|| Box::pin(async { true }));
+        user_mock
+            .expect_check_user_verification()
+            .times(usize::from(MAX_UV_RETRIES))
+            .returning(|| Box::pin(async { false }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::OperationDenied.into());
+        assert_eq!(
+            authenticator.client_pin.stored().uv_retries,
+            MAX_UV_RETRIES - 1
+        );
+        authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create credential");
+        assert_eq!(authenticator.client_pin.stored().uv_retries, MAX_UV_RETRIES);
+
+        for _ in 1..MAX_UV_RETRIES {
+            let result = authenticator.make_credential(good_request()).await;
+            assert_eq!(result.unwrap_err(), Ctap2Error::OperationDenied.into());
+        }
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Ctap2Error::UserVerficationBlocked.into()
+        );
+        assert!(authenticator.pin_state().uv_blocked);
+
+        // The user is no longer asked to verify.
+        let result = authenticator.make_credential(good_request()).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Ctap2Error::UserVerficationBlocked.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_auth_verifies_user() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        client_pin::tests::set_pin(&mut authenticator, PinProtocol::One, b"1234")
+            .await
+            .expect("failed to set pin");
+        let pin_token =
+            client_pin::tests::get_pin_token(&mut authenticator, PinProtocol::One, b"1234")
+                .await
+                .expect("failed to get pin token");
+
+        let client_data_hash: Bytes = random_vec(32).into();
+        let request = |pin_auth: Option<Vec<u8>>, pin_protocol| Request {
+            client_data_hash: client_data_hash.clone(),
+            options: Options {
+                rk: true,
+                up: true,
+                uv: false,
+            },
+            pin_auth: pin_auth.map(Into::into),
+            pin_protocol,
+            ..good_request()
+        };
+        let pin_auth = PinProtocol::One.authenticate(&pin_token, &client_data_hash);
+
+        let result = authenticator.make_credential(request(None, None)).await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PuatRequired.into());
+        let result = authenticator
+            .make_credential(request(Some(vec![]), Some(1)))
+            .await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PinInvalid.into());
+        let result = authenticator
+            .make_credential(request(Some(pin_auth.clone()), Some(2)))
+            .await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PinAuthInvalid.into());
+        let result = authenticator
+            .make_credential(request(Some(vec![0; 16]), Some(1)))
+            .await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PinAuthInvalid.into());
+
+        let response = authenticator
+            .make_credential(request(Some(pin_auth), Some(1)))
+            .await
+            .expect("failed to create credential with pin auth");
+        assert!(response.auth_data.flags.contains(Flags::UV));
+        let credential_id: Bytes = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec()
+            .into();
+
+        let assertion_request = |pin_auth: Option<Vec<u8>>, pin_protocol| {
+            passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: client_data_hash.clone(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.clone(),
+                    transports: None,
+                }]),
+                extensions: None,
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: false,
+                },
+                pin_auth: pin_auth.map(Into::into),
+                pin_protocol,
+            }
+        };
+        let response = authenticator
+            .get_assertion(assertion_request(None, None))
+            .await
+            .expect("failed to get assertion without pin auth");
+        assert!(!response.auth_data.flags.contains(Flags::UV));
+        // The token was used up by the registration.
+        let pin_auth = PinProtocol::One.authenticate(&pin_token, &client_data_hash);
+        let result = authenticator
+            .get_assertion(assertion_request(Some(pin_auth), Some(1)))
+            .await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::PinAuthInvalid.into());
+        let pin_token =
+            client_pin::tests::get_pin_token(&mut authenticator, PinProtocol::One, b"1234")
+                .await
+                .expect("failed to get pin token");
+        let pin_auth = PinProtocol::One.authenticate(&pin_token, &client_data_hash);
+        let response = authenticator
+            .get_assertion(assertion_request(Some(pin_auth), Some(1)))
+            .await
+            .expect("failed to get assertion with pin auth");
+        assert!(response.auth_data.flags.contains(Flags::UV));
+    }
+
+    #[tokio::test]
+    async fn large_blob_key_is_returned_on_assertion() {
+        let shared_store = Arc::new(Mutex::new(MemoryStore::new()));
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            shared_store.clone(),
+            MockUserValidationMethod::verified_user(4),
+        );
+        let large_blob_key_input = || webauthn::AuthenticationExtensionsClientInputs {
+            large_blob_key: Some(true),
+            ..Default::default()
+        };
+
+        // Only discoverable credentials can have a large blob.
+        let mut request = good_request();
+        request.options.rk = false;
+        request.extensions = Some(large_blob_key_input());
+        assert_eq!(
+            authenticator.make_credential(request).await.unwrap_err(),
+            Ctap2Error::InvalidOption.into()
+        );
+
+        let mut request = good_request();
+        request.extensions = Some(large_blob_key_input());
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with a large blob key");
+        let large_blob_key = response.large_blob_key.expect("missing large blob key");
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let assertion_request = |extensions| passkey_types::ctap2::get_assertion::Request {
+            rp_id: "future.1password.com".into(),
+            client_data_hash: random_vec(32).into(),
+            allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                id: credential_id.clone().into(),
+                transports: None,
+            }]),
+            extensions,
+            options: Options {
+                rk: false,
+                up: true,
+                uv: true,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+        };
+        let response = authenticator
+            .get_assertion(assertion_request(Some(large_blob_key_input())))
+            .await
+            .expect("failed to get assertion");
+        assert_eq!(response.large_blob_key, Some(large_blob_key));
+
+        // The key is not given out unless it is asked for.
+        let response = authenticator
+            .get_assertion(assertion_request(None))
+            .await
+            .expect("failed to get assertion");
+        assert_eq!(response.large_blob_key, None);
+    }
+
+    #[tokio::test]
+    async fn hmac_secret_outputs_are_returned_on_assertion() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let hmac_secret = |input| webauthn::AuthenticationExtensionsClientInputs {
+            hmac_secret: Some(input),
+            ..Default::default()
+        };
+
+        let mut request = good_request();
+        request.extensions = Some(hmac_secret(HmacSecretInput::Create(true)));
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with hmac-secret");
+        assert!(response.auth_data.flags.contains(Flags::ED));
+        assert_eq!(
+            response.auth_data.parse_extensions().unwrap().hmac_secret,
+            Some(HmacSecretOutput::Created(true))
+        );
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        for protocol in PinProtocol::SUPPORTED {
+            let (platform_key, shared_secret) =
+                client_pin::tests::key_agreement(&mut authenticator, protocol).await;
+            let salts = |salts: &[u8], uv| {
+                let salt_enc = client_pin::tests::encrypt(protocol, &shared_secret, salts);
+                let salt_auth = protocol.authenticate(&shared_secret, &salt_enc);
+                passkey_types::ctap2::get_assertion::Request {
+                    rp_id: "future.1password.com".into(),
+                    client_data_hash: random_vec(32).into(),
+                    allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                        ty: webauthn::PublicKeyCredentialType::PublicKey,
+                        id: credential_id.clone().into(),
+                        transports: None,
+                    }]),
+                    extensions: Some(hmac_secret(HmacSecretInput::Get(HmacSecretSalts {
+                        key_agreement: Some(platform_key.clone()),
+                        salt_enc: salt_enc.into(),
+                        salt_auth: salt_auth.into(),
+                        pin_uv_auth_protocol: Some(protocol.version()),
+                    }))),
+                    options: Options {
+                        rk: false,
+                        up: true,
+                        uv,
+                    },
+                    pin_auth: None,
+                    pin_protocol: None,
+                }
+            };
+            let output = |response: passkey_types::ctap2::get_assertion::Response| {
+                let Some(HmacSecretOutput::Secret(output)) =
+                    response.auth_data.parse_extensions().unwrap().hmac_secret
+                else {
+                    panic!("missing hmac-secret output");
+                };
+                protocol.decrypt(&shared_secret, &output).unwrap()
+            };
+
+            let one = output(
+                authenticator
+                    .get_assertion(salts(&[1; 32], true))
+                    .await
+                    .expect("failed to get assertion with one salt"),
+            );
+            let two = output(
+                authenticator
+                    .get_assertion(salts(&[[1; 32], [2; 32]].concat(), true))
+                    .await
+                    .expect("failed to get assertion with two salts"),
+            );
+            let without_uv = output(
+                authenticator
+                    .get_assertion(salts(&[1; 32], false))
+                    .await
+                    .expect("failed to get assertion without uv"),
+            );
+            assert_eq!(one.len(), 32);
+            assert_eq!(one[..], two[..32]);
+            assert_ne!(two[..32], two[32..]);
+            // A different CredRandom is used without user verification.
+            assert_ne!(one, without_uv);
+
+            let mut request = salts(&[1; 32], true);
+            if let Some(HmacSecretInput::Get(salts)) = request
+                .extensions
+                .as_mut()
+                .and_then(|extensions| extensions.hmac_secret.as_mut())
+            {
+                salts.salt_auth = vec![0; salts.salt_auth.len()].into();
+            }
+            assert_eq!(
+                authenticator.get_assertion(request).await.unwrap_err(),
+                Ctap2Error::PinAuthInvalid.into()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn hmac_secret_outputs_are_computed_on_creation() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let protocol = PinProtocol::Two;
+        let (platform_key, shared_secret) =
+            client_pin::tests::key_agreement(&mut authenticator, protocol).await;
+        let salt_enc = client_pin::tests::encrypt(protocol, &shared_secret, &[1; 32]);
+        let salts = HmacSecretSalts {
+            key_agreement: Some(platform_key),
+            salt_auth: protocol.authenticate(&shared_secret, &salt_enc).into(),
+            salt_enc: salt_enc.into(),
+            pin_uv_auth_protocol: Some(protocol.version()),
+        };
+
+        // Without hmac-secret-mc, the outputs are left to be computed on assertion.
+        let mut request = good_request();
+        request.extensions = Some(webauthn::AuthenticationExtensionsClientInputs {
+            hmac_secret: Some(HmacSecretInput::Create(true)),
+            ..Default::default()
+        });
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with hmac-secret");
+        let outputs = response.auth_data.parse_extensions().unwrap();
+        assert_eq!(outputs.hmac_secret, Some(HmacSecretOutput::Created(true)));
+        assert_eq!(outputs.hmac_secret_mc, None);
+
+        let mut request = good_request();
+        request.extensions = Some(webauthn::AuthenticationExtensionsClientInputs {
+            hmac_secret: Some(HmacSecretInput::Create(true)),
+            hmac_secret_mc: Some(salts.clone()),
+            ..Default::default()
+        });
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with hmac-secret-mc");
+        let outputs = response.auth_data.parse_extensions().unwrap();
+        assert_eq!(outputs.hmac_secret, Some(HmacSecretOutput::Created(true)));
+        let created = protocol
+            .decrypt(
+                &shared_secret,
+                &outputs
+                    .hmac_secret_mc
+                    .expect("missing hmac-secret-mc output"),
+            )
+            .unwrap();
+
+        // The outputs computed on creation are those of a later assertion with the same salts.
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+        let response = authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.into(),
+                    transports: None,
+                }]),
+                extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                    hmac_secret: Some(HmacSecretInput::Get(salts)),
+                    ..Default::default()
+                }),
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to get assertion");
+        let Some(HmacSecretOutput::Secret(asserted)) =
+            response.auth_data.parse_extensions().unwrap().hmac_secret
+        else {
+            panic!("missing hmac-secret output");
+        };
+        assert_eq!(
+            created,
+            protocol.decrypt(&shared_secret, &asserted).unwrap()
+        );
+
+        // hmac-secret-mc is only processed along with hmac-secret.
+        let mut request = good_request();
+        request.extensions = Some(webauthn::AuthenticationExtensionsClientInputs {
+            hmac_secret_mc: Some(HmacSecretSalts {
+                key_agreement: None,
+                salt_enc: vec![0; 48].into(),
+                salt_auth: vec![0; 32].into(),
+                pin_uv_auth_protocol: None,
+            }),
+            ..Default::default()
+        });
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential");
+        assert!(!response.auth_data.flags.contains(Flags::ED));
+    }
+
+    #[tokio::test]
+    async fn backup_flags_are_set_per_credential() {
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(2),
+        )
+        .with_backup_flags(true, false);
+        let response = authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create a credential");
+        assert!(response.auth_data.flags.contains(Flags::BE));
+        assert!(!response.auth_data.flags.contains(Flags::BS));
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        // The credential is backed up once it is synced.
+        authenticator
+            .set_backup_state(&credential_id, true)
+            .await
+            .expect("failed to back up the credential");
+        let response = authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.into(),
+                    transports: None,
+                }]),
+                extensions: None,
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to get assertion");
+        assert!(response.auth_data.flags.contains(Flags::BE | Flags::BS));
+
+        // Device-bound credentials cannot be backed up.
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(1),
+        )
+        .with_backup_flags(false, true);
+        let response = authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create a credential");
+        assert!(!response.auth_data.flags.intersects(Flags::BE | Flags::BS));
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+        assert_eq!(
+            authenticator.set_backup_state(&credential_id, true).await,
+            Err(Ctap2Error::NotAllowed.into())
+        );
+        assert_eq!(
+            authenticator.set_backup_state(&[0; 16], false).await,
+            Err(Ctap2Error::NoCredentials.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn always_uv_verifies_the_user() {
+        let always_uv = Some(StoredConfig {
+            always_uv: true,
+            ..Default::default()
+        });
+        let request = || Request {
+            options: Options {
+                uv: false,
+                ..good_request().options
+            },
+            ..good_request()
+        };
+
+        // Built-in user verification is performed even though the request does not ask for it.
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .times(1)
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+                .with_config_store(always_uv.clone());
+        let response = authenticator
+            .make_credential(request())
+            .await
+            .expect("failed to create credential");
+        assert!(response.auth_data.flags.contains(Flags::UV));
+
+        // Without it, the PIN must be set and used.
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+                .with_config_store(always_uv);
+        assert_eq!(
+            authenticator.make_credential(request()).await.unwrap_err(),
+            Ctap2Error::PinNotSet.into()
+        );
+        client_pin::tests::set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        assert_eq!(
+            authenticator.make_credential(request()).await.unwrap_err(),
+            Ctap2Error::PuatRequired.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn full_store_is_reported() {
+        let store = MemoryStore::new().with_max_credentials(1);
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            store,
+            MockUserValidationMethod::verified_user(3),
+        )
+        .with_wrapping_key([42; 32]);
+        authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create credential");
+
+        assert_eq!(
+            authenticator
+                .make_credential(good_request())
+                .await
+                .unwrap_err(),
+            Ctap2Error::KeyStoreFull.into()
+        );
+
+        // Wrapped credentials are not stored, so they can still be created.
+        let mut request = good_request();
+        request.options.rk = false;
+        authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create non-discoverable credential");
+        assert_eq!(authenticator.store().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn credentials_of_the_same_user_are_overwritten() {
+        let store = MemoryStore::new().with_max_credentials(1);
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            store,
+            MockUserValidationMethod::verified_user(2),
+        );
+        let user_id: Bytes = random_vec(16).into();
+        let request = || {
+            let mut request = good_request();
+            request.user.id = user_id.clone();
+            request
+        };
+        let mut credential_ids = Vec::new();
+        for _ in 0..2 {
+            let response = authenticator
+                .make_credential(request())
+                .await
+                .expect("failed to create credential");
+            credential_ids.push(
+                response
+                    .auth_data
+                    .attested_credential_data
+                    .expect("missing attested credential data")
+                    .credential_id()
+                    .to_vec(),
+            );
+        }
+
+        // The full store still fits the new credential, which replaces the first one.
+        assert_eq!(authenticator.store().len(), 1);
+        assert!(authenticator.store().contains_key(&credential_ids[1]));
+
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(2),
+        )
+        .with_credential_overwrite(false);
+        for _ in 0..2 {
+            authenticator
+                .make_credential(request())
+                .await
+                .expect("failed to create credential");
+        }
+        assert_eq!(authenticator.store().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn user_verification_is_cancelled_by_the_host() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(std::future::pending()));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let token = authenticator.cancellation_token();
+
+        let (result, ()) = tokio::join!(authenticator.make_credential(good_request()), async {
+            tokio::task::yield_now().await;
+            token.cancel();
+        });
+
+        assert_eq!(result.unwrap_err(), Ctap2Error::KeepAliveCancel.into());
+        assert!(authenticator.store().is_empty());
+    }
+
+    #[tokio::test]
+    async fn device_keys_are_kept_on_the_device() {
+        let user_mock = || {
+            let mut user_mock = MockUserValidationMethod::new();
+            user_mock
+                .expect_is_verification_enabled()
+                .returning(|| Some(true));
+            user_mock
+                .expect_check_user_verification()
+                .returning(|| Box::pin(async { true }));
+            user_mock.expect_is_presence_enabled().returning(|| true);
+            user_mock
+                .expect_check_user_presence()
+                .returning(|| Box::pin(async { true }));
+            user_mock
+        };
+        let device_pub_key = || {
+            Some(webauthn::AuthenticationExtensionsClientInputs {
+                device_pub_key: Some(Default::default()),
+                ..Default::default()
+            })
+        };
+        let credential_store = Arc::new(Mutex::new(MemoryStore::new()));
+        let device_key_store = Arc::new(std::sync::Mutex::new(None::<StoredDeviceKeys>));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), credential_store.clone(), user_mock())
+                .with_device_key_store(device_key_store.clone());
+
+        let mut request = good_request();
+        request.extensions = device_pub_key();
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with devicePubKey");
+        let created = response
+            .unsigned_extension_outputs
+            .and_then(|outputs| outputs.device_pub_key)
+            .expect("missing devicePubKey output");
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+        let stored_keys = device_key_store.lock().unwrap().clone().unwrap_or_default();
+        assert_eq!(stored_keys.keys.len(), 1);
+        assert!(stored_keys.keys.contains_key(&credential_id));
+
+        let get_assertion = |mut authenticator: Authenticator<_, _>| {
+            let request = passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.clone().into(),
+                    transports: None,
+                }]),
+                extensions: device_pub_key(),
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            };
+            async move {
+                authenticator
+                    .get_assertion(request)
+                    .await
+                    .expect("failed to get assertion with devicePubKey")
+                    .unsigned_extension_outputs
+                    .and_then(|outputs| outputs.device_pub_key)
+                    .expect("missing devicePubKey output")
+            }
+        };
+
+        // The same device key is used after a restart of the authenticator.
+        drop(authenticator);
+        let restarted =
+            Authenticator::new(Aaguid::new_empty(), credential_store.clone(), user_mock())
+                .with_device_key_store(device_key_store.clone());
+        let asserted = get_assertion(restarted).await;
+        assert!(asserted.attestation.is_same_device(&created.attestation));
+
+        // A synced credential gets a new device key on another device.
+        let other_device = Authenticator::new(Aaguid::new_empty(), credential_store, user_mock());
+        let asserted = get_assertion(other_device).await;
+        assert!(!asserted.attestation.is_same_device(&created.attestation));
+    }
+
+    /// A device key store which cannot save any key.
+    struct FullDeviceKeyStore;
+
+    impl DeviceKeyStore for FullDeviceKeyStore {
+        fn load(&self) -> Option<StoredDeviceKeys> {
+            None
+        }
+
+        fn save(&mut self, _device_keys: &StoredDeviceKeys) -> Result<(), StatusCode> {
+            Err(Ctap2Error::KeyStoreFull.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_saves_are_rolled_back() {
+        let request = || Request {
+            extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                device_pub_key: Some(Default::default()),
+                ..Default::default()
+            }),
+            ..good_request()
+        };
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            TransactionalStore::default(),
+            MockUserValidationMethod::verified_user(2),
+        )
+        .with_device_key_store(FullDeviceKeyStore);
+
+        let result = authenticator.make_credential(request()).await;
+        assert_eq!(result.unwrap_err(), Ctap2Error::KeyStoreFull.into());
+        assert!(authenticator.store().credentials.is_empty());
+        assert_eq!(authenticator.store().rollbacks, 1);
+
+        // Once the device key is saved, the credential is committed.
+        let mut authenticator = authenticator.with_device_key_store(None::<StoredDeviceKeys>);
+        authenticator
+            .make_credential(request())
+            .await
+            .expect("failed to create credential");
+        assert_eq!(authenticator.store().credentials.len(), 1);
+        assert!(authenticator.store().snapshot.is_none());
+        assert_eq!(authenticator.store().rollbacks, 1);
+    }
+
+    #[tokio::test]
+    async fn newest_credential_is_asserted_without_allow_list() {
+        let clock = ManualClock::new();
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            ListStore::default(),
+            MockUserValidationMethod::verified_user(3),
+        )
+        .with_clock(clock.clone());
+
+        let mut credential_ids = Vec::new();
+        for _ in 0..2 {
+            let response = authenticator
+                .make_credential(good_request())
+                .await
+                .expect("failed to create credential");
+            credential_ids.push(
+                response
+                    .auth_data
+                    .attested_credential_data
+                    .expect("missing attested credential data")
+                    .credential_id()
+                    .to_vec(),
+            );
+            clock.advance(Duration::from_secs(60));
+        }
+        let created_at: Vec<_> = authenticator
+            .store()
+            .0
+            .iter()
+            .map(|passkey| passkey.created_at)
+            .collect();
+        assert_eq!(
+            created_at,
+            [
+                Some(authenticator.now() - Duration::from_secs(120)),
+                Some(authenticator.now() - Duration::from_secs(60))
+            ]
+        );
+
+        // The store lists the oldest credential first, the newest one is asserted regardless.
+        let response = authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: None,
+                extensions: None,
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to get assertion");
+        let asserted = response.credential.expect("missing credential");
+        assert_eq!(*asserted.id, credential_ids[1]);
+
+        let last_used_at: Vec<_> = authenticator
+            .store()
+            .0
+            .iter()
+            .map(|passkey| passkey.last_used_at)
+            .collect();
+        assert_eq!(last_used_at, [None, Some(authenticator.now())]);
+    }
+
+    #[tokio::test]
+    async fn user_names_are_returned_once_verified() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let credential_id = authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create credential")
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let request = |uv| passkey_types::ctap2::get_assertion::Request {
+            rp_id: "future.1password.com".into(),
+            client_data_hash: random_vec(32).into(),
+            allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                id: credential_id.clone().into(),
+                transports: None,
+            }]),
+            extensions: None,
+            options: Options {
+                rk: false,
+                up: true,
+                uv,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+        };
+        let user = authenticator
+            .get_assertion(request(true))
+            .await
+            .expect("failed to get assertion")
+            .user
+            .expect("missing user");
+        assert_eq!(user.name, "Appleseed");
+        assert_eq!(user.display_name, "wendy");
+
+        let user = authenticator
+            .get_assertion(request(false))
+            .await
+            .expect("failed to get assertion")
+            .user
+            .expect("missing user");
+        assert!(user.name.is_empty());
+        assert!(user.display_name.is_empty());
+    }
+
+    /// A store mirroring credentials which it cannot change.
+    struct ReadOnlyStore(MemoryStore);
+
+    #[async_trait::async_trait]
+    impl CredentialStore for ReadOnlyStore {
+        type PasskeyItem = Passkey;
+
+        async fn find_credentials(
+            &self,
+            ids: Option<&[webauthn::PublicKeyCredentialDescriptor]>,
+            rp_id: &str,
+        ) -> Result<Vec<Passkey>, StatusCode> {
+            self.0.find_credentials(ids, rp_id).await
+        }
+
+        async fn save_credential(
+            &mut self,
+            cred: Passkey,
+            user: passkey_types::ctap2::make_credential::PublicKeyCredentialUserEntity,
+            rp: PublicKeyCredentialRpEntity,
+        ) -> Result<(), StatusCode> {
+            self.0.save_credential(cred, user, rp).await
+        }
+
+        async fn is_read_only(&self) -> bool {
+            true
+        }
+
+        async fn update_last_used(
+            &mut self,
+            credential_id: &[u8],
+            used_at: std::time::SystemTime,
+        ) -> Result<(), StatusCode> {
+            self.0.update_last_used(credential_id, used_at).await
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_stores_are_not_written_to() {
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(1),
+        );
+        let credential_id = authenticator
+            .make_credential(good_request())
+            .await
+            .expect("failed to create credential")
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            ReadOnlyStore(authenticator.store().clone()),
+            MockUserValidationMethod::verified_user(2),
+        );
+        assert_eq!(
+            authenticator
+                .make_credential(good_request())
+                .await
+                .unwrap_err(),
+            Ctap2Error::OperationDenied.into()
+        );
+
+        // Credentials are still asserted, without recording their use.
+        authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.clone().into(),
+                    transports: None,
+                }]),
+                extensions: None,
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to get assertion");
+        let store = &authenticator.store().0;
+        assert_eq!(store.len(), 1);
+        assert_eq!(store[credential_id.as_slice()].passkey.last_used_at, None);
+    }
+
+    #[tokio::test]
+    async fn third_party_payment_is_reported_on_assertion() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let third_party_payment = |enabled| {
+            Some(webauthn::AuthenticationExtensionsClientInputs {
+                third_party_payment: Some(enabled),
+                ..Default::default()
+            })
+        };
+
+        let mut credential_ids = Vec::new();
+        for enabled in [true, false] {
+            let mut request = good_request();
+            request.extensions = third_party_payment(enabled);
+            let response = authenticator
+                .make_credential(request)
+                .await
+                .expect("failed to create a credential with thirdPartyPayment");
+            assert_eq!(
+                response
+                    .auth_data
+                    .parse_extensions()
+                    .unwrap()
+                    .third_party_payment,
+                Some(enabled)
+            );
+            let credential_id = response
+                .auth_data
+                .attested_credential_data
+                .expect("missing attested credential data")
+                .credential_id()
+                .to_vec();
+            credential_ids.push((credential_id, enabled));
+        }
+
+        for (credential_id, enabled) in credential_ids {
+            let response = authenticator
+                .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                    rp_id: "future.1password.com".into(),
+                    client_data_hash: random_vec(32).into(),
+                    allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                        ty: webauthn::PublicKeyCredentialType::PublicKey,
+                        id: credential_id.into(),
+                        transports: None,
+                    }]),
+                    extensions: third_party_payment(true),
+                    options: Options {
+                        rk: false,
+                        up: true,
+                        uv: true,
+                    },
+                    pin_auth: None,
+                    pin_protocol: None,
+                })
+                .await
+                .expect("failed to get assertion");
+            assert_eq!(
+                response
+                    .auth_data
+                    .parse_extensions()
+                    .unwrap()
+                    .third_party_payment,
+                Some(enabled)
+            );
+        }
+    }
+
+    struct EchoExtension;
+
+    #[async_trait::async_trait]
+    impl crate::ExtensionHandler for EchoExtension {
+        async fn make_credential(
+            &self,
+            input: &Value,
+            _context: &ExtensionContext<'_>,
+        ) -> Result<crate::ExtensionOutput, StatusCode> {
+            Ok(crate::ExtensionOutput {
+                signed: Some(input.clone()),
+                unsigned: None,
+            })
+        }
+
+        async fn get_assertion(
+            &self,
+            input: &Value,
+            context: &ExtensionContext<'_>,
+        ) -> Result<crate::ExtensionOutput, StatusCode> {
+            Ok(crate::ExtensionOutput {
+                signed: None,
+                unsigned: Some(Value::Array(vec![
+                    input.clone(),
+                    Value::Bool(context.flags.contains(Flags::UV)),
+                ])),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_extensions_are_handled() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+                .with_extension("example.echo", EchoExtension);
+        let extensions = || {
+            Some(webauthn::AuthenticationExtensionsClientInputs {
+                unknown_keys: [
+                    ("example.echo".to_owned(), Value::Text("hello".into())),
+                    ("example.unknown".to_owned(), Value::Bool(true)),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            })
+        };
+
+        let mut request = good_request();
+        request.extensions = extensions();
+        let response = authenticator
+            .make_credential(request)
+            .await
+            .expect("failed to create a credential with a custom extension");
+        assert_eq!(
+            response.auth_data.parse_extensions().unwrap().unknown,
+            vec![("example.echo".into(), Value::Text("hello".into()))]
+        );
+        assert_eq!(response.unsigned_extension_outputs, None);
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let response = authenticator
+            .get_assertion(passkey_types::ctap2::get_assertion::Request {
+                rp_id: "future.1password.com".into(),
+                client_data_hash: random_vec(32).into(),
+                allow_list: Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                    ty: webauthn::PublicKeyCredentialType::PublicKey,
+                    id: credential_id.into(),
+                    transports: None,
+                }]),
+                extensions: extensions(),
+                options: Options {
+                    rk: false,
+                    up: true,
+                    uv: true,
+                },
+                pin_auth: None,
+                pin_protocol: None,
+            })
+            .await
+            .expect("failed to get assertion");
+        assert_eq!(response.auth_data.extensions, None);
+        let unsigned = response
+            .unsigned_extension_outputs
+            .expect("missing unsigned extension outputs");
+        assert_eq!(
+            unsigned.unknown_keys.get("example.echo"),
+            Some(&Value::Array(vec![
+                Value::Text("hello".into()),
+                Value::Bool(true)
+            ]))
+        );
+        assert_eq!(unsigned.unknown_keys.len(), 1);
+    }
 }
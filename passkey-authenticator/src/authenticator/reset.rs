@@ -0,0 +1,122 @@
+use passkey_types::ctap2::{Ctap2Error, StatusCode};
+
+use crate::{user_validation::UvLockout, Authenticator, CredentialStore, UserValidationMethod};
+
+impl<S, U> Authenticator<S, U>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod,
+{
+    /// This method is used by the platform to reset the authenticator back to a factory default
+    /// state. Every credential is deleted from the store through [`CredentialStore::clear_all`],
+    /// the PIN is removed along with its retries and minimum length, the large-blob array is
+    /// emptied, and enterprise attestation and alwaysUv are disabled.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorReset>
+    pub async fn reset(&mut self) -> Result<(), StatusCode> {
+        self.begin_operation();
+
+        // 1. Collect the user's consent. If it is not given, return
+        //    CTAP2_ERR_OPERATION_DENIED.
+        if !self
+            .wait_on_user(self.user_validation.check_user_presence())
+            .await?
+        {
+            return Err(Ctap2Error::OperationDenied.into());
+        }
+
+        // 2. Delete every credential, then the PIN and user verification state.
+        self.store.clear_all().await?;
+        self.client_pin.reset()?;
+        self.large_blobs.reset()?;
+        self.device_keys().reset()?;
+        self.config.reset()?;
+        *self.uv_lockout() = UvLockout::default();
+        self.credential_enumeration = None;
+        self.bio_enrollment = None;
+        self.invalidate_info();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use passkey_types::{ctap2::Aaguid, rand::random_vec, Passkey};
+
+    use super::*;
+    use crate::{
+        authenticator::client_pin::tests::{set_pin, TestAuthenticator},
+        pin_protocol::PinProtocol,
+        user_validation::MockUserValidationMethod,
+        MemoryStore,
+    };
+
+    /// An authenticator whose user consents to a reset only if `consents`, with a PIN and a
+    /// credential.
+    async fn authenticator(consents: bool) -> TestAuthenticator {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(move || Box::pin(async move { consents }));
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        let mut authenticator =
+            Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        set_pin(&mut authenticator, PinProtocol::Two, b"1234")
+            .await
+            .unwrap();
+        let passkey = Passkey {
+            key: Default::default(),
+            credential_id: random_vec(16).into(),
+            rp_id: "example.com".into(),
+            user_handle: Some(random_vec(16).into()),
+            counter: Some(3),
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
+        };
+        authenticator
+            .store_mut()
+            .insert(passkey.credential_id.to_vec(), passkey.into());
+        authenticator
+    }
+
+    #[tokio::test]
+    async fn reset_wipes_credentials_and_pin() {
+        let mut authenticator = authenticator(true).await;
+        authenticator
+            .client_pin
+            .set_min_pin_length(6, true)
+            .unwrap();
+
+        authenticator.reset().await.expect("failed to reset");
+        assert!(authenticator.store().is_empty());
+        let info = authenticator.get_info().await;
+        let options = info.options.unwrap();
+        assert_eq!(options.client_pin, Some(false));
+        assert_eq!(info.min_pin_length, Some(4));
+        assert_eq!(info.force_pin_change, Some(false));
+
+        // A new PIN can be set right away.
+        set_pin(&mut authenticator, PinProtocol::Two, b"5678")
+            .await
+            .expect("failed to set pin after reset");
+    }
+
+    #[tokio::test]
+    async fn declined_reset_changes_nothing() {
+        let mut authenticator = authenticator(false).await;
+        assert_eq!(
+            authenticator.reset().await,
+            Err(Ctap2Error::OperationDenied.into())
+        );
+        assert_eq!(authenticator.store().len(), 1);
+        assert!(authenticator.client_pin.is_set());
+    }
+}
@@ -0,0 +1,112 @@
+use passkey_types::ctap2::{Ctap2Error, StatusCode};
+
+use crate::{Authenticator, CredentialStore, UserValidationMethod};
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// This method is used by the platform to let the user pick this authenticator out of many,
+    /// by waiting for their presence through [`UserValidationMethod::check_user_presence`].
+    ///
+    /// The returned future is cancelled by dropping it, which is how a platform stops waiting on
+    /// the authenticators which were not selected. Through the
+    /// [`Authenticator::cancellation_token`], it returns `CTAP2_ERR_KEEPALIVE_CANCEL` instead.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorSelection>
+    pub async fn selection(&self) -> Result<(), StatusCode> {
+        self.begin_operation();
+        if self
+            .wait_on_user(self.user_validation.check_user_presence())
+            .await?
+        {
+            Ok(())
+        } else {
+            Err(Ctap2Error::OperationDenied.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use passkey_types::ctap2::Aaguid;
+
+    use super::*;
+    use crate::{user_validation::MockUserValidationMethod, KeepaliveStatus, MemoryStore};
+
+    #[tokio::test]
+    async fn selection_waits_for_user_presence() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .times(1)
+            .returning(|| Box::pin(async { true }));
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { false }));
+        let authenticator = Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+
+        assert_eq!(authenticator.selection().await, Ok(()));
+        assert_eq!(
+            authenticator.selection().await,
+            Err(Ctap2Error::OperationDenied.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn selection_is_cancelled_by_dropping_it() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(std::future::pending()));
+        let authenticator = Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+
+        let selected = tokio::select! {
+            biased;
+            () = std::future::ready(()) => false,
+            _ = authenticator.selection() => true,
+        };
+        assert!(!selected);
+    }
+
+    #[tokio::test]
+    async fn selection_is_cancelled_by_the_host() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(std::future::pending()));
+        let authenticator = Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock);
+        let token = authenticator.cancellation_token();
+        // A cancellation before the operation started is ignored.
+        token.cancel();
+
+        let (result, ()) = tokio::join!(authenticator.selection(), async {
+            tokio::task::yield_now().await;
+            token.cancel();
+        });
+
+        assert_eq!(result, Err(Ctap2Error::KeepAliveCancel.into()));
+    }
+
+    #[tokio::test]
+    async fn keepalive_reports_waiting_on_the_user() {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let reported = statuses.clone();
+        let authenticator = Authenticator::new(Aaguid::new_empty(), MemoryStore::new(), user_mock)
+            .with_keepalive(move |status| reported.lock().unwrap().push(status));
+
+        authenticator.selection().await.expect("selection failed");
+
+        assert_eq!(
+            *statuses.lock().unwrap(),
+            [
+                KeepaliveStatus::Processing,
+                KeepaliveStatus::UpNeeded,
+                KeepaliveStatus::Processing
+            ]
+        );
+    }
+}
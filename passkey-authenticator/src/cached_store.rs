@@ -0,0 +1,420 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use passkey_types::{
+    ctap2::{
+        make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+        StatusCode,
+    },
+    webauthn::PublicKeyCredentialDescriptor,
+    Passkey,
+};
+
+use crate::{Clock, CredentialStore, DiscoverabilitySupport, DiscoverableCredential, SystemClock};
+
+/// The arguments of a call to [`CredentialStore::find_credentials`].
+#[derive(PartialEq, Eq, Hash)]
+struct Lookup {
+    rp_id: String,
+    ids: Option<Vec<Vec<u8>>>,
+}
+
+/// The credentials found by a lookup, until they expire.
+struct CachedCredentials {
+    credentials: Vec<Passkey>,
+    expires_at: SystemTime,
+}
+
+/// A write-through cache of the credentials found in another store, for stores backed by remote
+/// services such as a synced vault, so that repeated assertions avoid a round trip to the service.
+///
+/// The credentials found by [`CredentialStore::find_credentials`] are kept for the time to live
+/// given to [`CachedCredentialStore::new`]. Every other call goes to the wrapped store, and those
+/// changing credentials drop the lookups they may affect: saving a credential drops the lookups of
+/// its RP, and deleting a credential or changing its backup state drops the lookups which found
+/// it. Recording when a credential is used updates the lookups which found it instead, as it
+/// happens on every assertion.
+///
+/// Changes made to the wrapped store by others, like those synced from other devices, are only
+/// seen once the lookups expire or are dropped with [`CachedCredentialStore::invalidate`].
+pub struct CachedCredentialStore<S> {
+    store: S,
+    ttl: Duration,
+    clock: Box<dyn Clock + Send + Sync>,
+    lookups: Mutex<HashMap<Lookup, CachedCredentials>>,
+}
+
+impl<S> CachedCredentialStore<S> {
+    /// Cache the credentials found in `store` for `ttl`.
+    pub fn new(store: S, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            clock: Box::new(SystemClock),
+            lookups: Mutex::default(),
+        }
+    }
+
+    /// Set the clock which tells when lookups expire, the [`SystemClock`] by default.
+    pub fn with_clock(self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// Access the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Write access to the wrapped store, which drops every cached lookup as the credentials may
+    /// be changed through it.
+    pub fn store_mut(&mut self) -> &mut S {
+        self.invalidate();
+        &mut self.store
+    }
+
+    /// Unwrap the store, dropping the cache.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Drop every cached lookup, so that credentials are found in the wrapped store again. Call
+    /// this when the wrapped store changes outside of the cache, like when it is synced.
+    pub fn invalidate(&self) {
+        self.lookups().clear();
+    }
+
+    /// Exclusively access the cached lookups.
+    fn lookups(&self) -> MutexGuard<'_, HashMap<Lookup, CachedCredentials>> {
+        // Lookups are only ever inserted or removed as a whole, so recover from poisoning.
+        self.lookups.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drop the cached lookups which found the credential with the given ID.
+    fn invalidate_credential(&self, credential_id: &[u8]) {
+        self.lookups().retain(|_, cached| {
+            !cached
+                .credentials
+                .iter()
+                .any(|passkey| passkey.credential_id.as_slice() == credential_id)
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: CredentialStore<PasskeyItem = Passkey> + Send + Sync> CredentialStore
+    for CachedCredentialStore<S>
+{
+    type PasskeyItem = Passkey;
+
+    async fn find_credentials(
+        &self,
+        ids: Option<&[PublicKeyCredentialDescriptor]>,
+        rp_id: &str,
+    ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
+        let lookup = Lookup {
+            rp_id: rp_id.to_owned(),
+            ids: ids.map(|ids| ids.iter().map(|id| id.id.to_vec()).collect()),
+        };
+        let now = self.clock.now();
+        if let Some(cached) = self.lookups().get(&lookup) {
+            if cached.expires_at > now {
+                return Ok(cached.credentials.clone());
+            }
+        }
+
+        let credentials = self.store.find_credentials(ids, rp_id).await?;
+        let mut lookups = self.lookups();
+        lookups.retain(|_, cached| cached.expires_at > now);
+        lookups.insert(
+            lookup,
+            CachedCredentials {
+                credentials: credentials.clone(),
+                expires_at: now + self.ttl,
+            },
+        );
+        Ok(credentials)
+    }
+
+    async fn save_credential(
+        &mut self,
+        cred: Passkey,
+        user: PublicKeyCredentialUserEntity,
+        rp: PublicKeyCredentialRpEntity,
+    ) -> Result<(), StatusCode> {
+        let rp_id = cred.rp_id.clone();
+        let result = self.store.save_credential(cred, user, rp).await;
+        self.lookups().retain(|lookup, _| lookup.rp_id != rp_id);
+        result
+    }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.store.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.store.enumerate(rp_id).await
+    }
+
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.store
+            .find_by_rp_and_user_handle(rp_id, user_handle)
+            .await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.store.saved_user(credential_id).await
+    }
+
+    async fn remaining_capacity(&self) -> Option<u32> {
+        self.store.remaining_capacity().await
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        self.store.remaining_discoverable_credentials().await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        self.store.discoverability().await
+    }
+
+    async fn is_read_only(&self) -> bool {
+        self.store.is_read_only().await
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        let result = self.store.delete_credential(credential_id).await;
+        self.invalidate_credential(credential_id);
+        result
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        // The users of credentials are not cached.
+        self.store.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        let result = self
+            .store
+            .set_backup_state(credential_id, backup_state)
+            .await;
+        self.invalidate_credential(credential_id);
+        result
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.store.update_last_used(credential_id, used_at).await?;
+        self.lookups()
+            .values_mut()
+            .flat_map(|cached| cached.credentials.iter_mut())
+            .filter(|passkey| passkey.credential_id.as_slice() == credential_id)
+            .for_each(|passkey| passkey.last_used_at = Some(used_at));
+        Ok(())
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        self.store.begin_transaction().await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        self.store.commit_transaction().await
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        // The changes rolled back may have dropped any lookup, so every lookup is dropped.
+        let result = self.store.rollback_transaction().await;
+        self.invalidate();
+        result
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        let result = self.store.clear_all().await;
+        self.invalidate();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use passkey_types::webauthn::PublicKeyCredentialType;
+
+    use super::*;
+    use crate::{clock::tests::ManualClock, credential_store::tests::passkey, MemoryStore};
+
+    /// A store counting how often credentials are looked up in it.
+    #[derive(Default)]
+    struct CountingStore {
+        credentials: MemoryStore,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl CredentialStore for CountingStore {
+        type PasskeyItem = Passkey;
+
+        async fn find_credentials(
+            &self,
+            ids: Option<&[PublicKeyCredentialDescriptor]>,
+            rp_id: &str,
+        ) -> Result<Vec<Passkey>, StatusCode> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.credentials.find_credentials(ids, rp_id).await
+        }
+
+        async fn save_credential(
+            &mut self,
+            cred: Passkey,
+            user: PublicKeyCredentialUserEntity,
+            rp: PublicKeyCredentialRpEntity,
+        ) -> Result<(), StatusCode> {
+            self.credentials.save_credential(cred, user, rp).await
+        }
+
+        async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+            self.credentials.delete_credential(credential_id).await
+        }
+
+        async fn update_last_used(
+            &mut self,
+            credential_id: &[u8],
+            used_at: SystemTime,
+        ) -> Result<(), StatusCode> {
+            self.credentials
+                .update_last_used(credential_id, used_at)
+                .await
+        }
+    }
+
+    async fn save(store: &mut CachedCredentialStore<CountingStore>, passkey: Passkey) {
+        let user = PublicKeyCredentialUserEntity {
+            id: passkey.user_handle.clone().unwrap(),
+            name: None,
+            display_name: None,
+            icon_url: None,
+        };
+        let rp = PublicKeyCredentialRpEntity {
+            id: passkey.rp_id.clone(),
+            name: None,
+        };
+        store.save_credential(passkey, user, rp).await.unwrap();
+    }
+
+    fn allow_list(credential_id: u8) -> Vec<PublicKeyCredentialDescriptor> {
+        vec![PublicKeyCredentialDescriptor {
+            ty: PublicKeyCredentialType::PublicKey,
+            id: vec![credential_id].into(),
+            transports: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn lookups_are_cached_until_they_expire() {
+        let clock = ManualClock::new();
+        let inner = CountingStore::default();
+        let lookups = inner.lookups.clone();
+        let mut store =
+            CachedCredentialStore::new(inner, Duration::from_secs(60)).with_clock(clock.clone());
+        save(&mut store, passkey(&[1])).await;
+
+        for _ in 0..3 {
+            let found = store
+                .find_credentials(Some(&allow_list(1)), "example.com")
+                .await
+                .unwrap();
+            assert_eq!(found.len(), 1);
+        }
+        assert_eq!(lookups.load(Ordering::Relaxed), 1);
+
+        // Other lookups are cached separately.
+        store
+            .find_credentials(Some(&allow_list(2)), "example.com")
+            .await
+            .unwrap_err();
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+
+        clock.advance(Duration::from_secs(60));
+        store
+            .find_credentials(Some(&allow_list(1)), "example.com")
+            .await
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+
+        store.invalidate();
+        store
+            .find_credentials(Some(&allow_list(1)), "example.com")
+            .await
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 4);
+    }
+
+    /// Find both credentials of the tests in the `store`.
+    async fn find(store: &CachedCredentialStore<CountingStore>) -> Vec<Passkey> {
+        let allow_list = [allow_list(1), allow_list(2)].concat();
+        store
+            .find_credentials(Some(&allow_list), "example.com")
+            .await
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn changes_are_written_through() {
+        let inner = CountingStore::default();
+        let lookups = inner.lookups.clone();
+        let mut store = CachedCredentialStore::new(inner, Duration::from_secs(60));
+        save(&mut store, passkey(&[1])).await;
+        assert_eq!(find(&store).await.len(), 1);
+
+        // Saving a credential of the RP drops its lookups.
+        save(&mut store, passkey(&[2])).await;
+        assert_eq!(find(&store).await.len(), 2);
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+
+        // Recording the use of a credential updates the cached lookups.
+        let used_at = SystemTime::now();
+        store.update_last_used(&[1], used_at).await.unwrap();
+        let found = find(&store).await;
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+        let used = found
+            .iter()
+            .find(|passkey| passkey.credential_id.as_slice() == [1])
+            .unwrap();
+        assert_eq!(used.last_used_at, Some(used_at));
+        assert_eq!(
+            store.store().credentials[[1].as_slice()]
+                .passkey
+                .last_used_at,
+            Some(used_at)
+        );
+
+        // Deleting a credential drops the lookups which found it.
+        store.delete_credential(&[1]).await.unwrap();
+        assert_eq!(find(&store).await.len(), 1);
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Poll, Waker},
+};
+
+use passkey_types::ctap2::Ctap2Error;
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Lets a host abort the operation in progress on an [`Authenticator`] while it waits on the
+/// user, like `CTAPHID_CANCEL` does on USB.
+///
+/// The waiting operation returns `CTAP2_ERR_KEEPALIVE_CANCEL`. A cancellation while no operation
+/// is in progress is ignored, each operation starts out uncancelled.
+///
+/// Get the token of an authenticator with [`Authenticator::cancellation_token`]. Clones of a token
+/// cancel the same operations.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<Mutex<State>>);
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: bool,
+    /// The tasks waiting on the user, woken up on cancellation.
+    wakers: Vec<Waker>,
+}
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the operation in progress.
+    pub fn cancel(&self) {
+        let mut state = self.state();
+        state.cancelled = true;
+        state.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    /// Whether the operation in progress was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state().cancelled
+    }
+
+    /// Forget earlier cancellations as a new operation starts.
+    pub(crate) fn reset(&self) {
+        let mut state = self.state();
+        state.cancelled = false;
+        state.wakers.clear();
+    }
+
+    /// Wait on the user through `future` unless the operation is cancelled first.
+    pub(crate) async fn run<F: Future>(&self, future: F) -> Result<F::Output, Ctap2Error> {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            {
+                let mut state = self.state();
+                if state.cancelled {
+                    return Poll::Ready(Err(Ctap2Error::KeepAliveCancel));
+                }
+                if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+            }
+            future.as_mut().poll(cx).map(Ok)
+        })
+        .await
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // The state holds no invariants that a panic could break, so recover from poisoning.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_wakes_up_waiting_operation() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+
+        let (result, ()) = tokio::join!(token.run(pending::<()>()), async move {
+            tokio::task::yield_now().await;
+            canceller.cancel();
+        });
+
+        assert_eq!(result, Err(Ctap2Error::KeepAliveCancel));
+    }
+
+    #[tokio::test]
+    async fn reset_forgets_cancellation() {
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(token.is_cancelled());
+
+        token.reset();
+
+        assert!(!token.is_cancelled());
+        assert_eq!(token.run(async { 7 }).await, Ok(7));
+    }
+}
@@ -0,0 +1,56 @@
+use std::{sync::Arc, time::SystemTime};
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Use this on a type which tells the [`Authenticator`] the current time.
+///
+/// The time decides when a PIN/UV auth token expires and when a user verification lockout ends.
+/// Replacing the default [`SystemClock`] lets hosts step through those time windows
+/// deterministically, for example in tests.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The operating system's clock, the default of an [`Authenticator`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+impl<C: Clock> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        self.as_ref().now()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use super::*;
+
+    /// A clock which only moves when told to.
+    pub(crate) struct ManualClock(Mutex<SystemTime>);
+
+    impl ManualClock {
+        pub(crate) fn new() -> Arc<Self> {
+            Arc::new(Self(Mutex::new(SystemTime::now())))
+        }
+
+        pub(crate) fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use passkey_types::ctap2::StatusCode;
+
+#[cfg(doc)]
+use crate::{Authenticator, StoredPin};
+
+/// The configuration of an authenticator changed through `authenticatorConfig` which must
+/// survive restarts.
+///
+/// The minimum PIN length set through `authenticatorConfig` is part of the [`StoredPin`] instead.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StoredConfig {
+    /// Whether enterprise attestation was enabled. It only takes effect on an authenticator
+    /// capable of it, see [`Authenticator::with_enterprise_attestation`].
+    pub enterprise_attestation: bool,
+    /// Whether user verification is required for every credential operation, in which case U2F
+    /// is disabled.
+    pub always_uv: bool,
+}
+
+/// Use this on a type that persists the [`StoredConfig`] of an [`Authenticator`].
+///
+/// The configuration is loaded once when the store is given to the authenticator and saved every
+/// time it changes.
+pub trait AuthenticatorConfigStore {
+    /// Load the persisted configuration, `None` if it was never saved.
+    fn load(&self) -> Option<StoredConfig>;
+
+    /// Persist the new configuration. An error aborts the operation which caused the change.
+    fn save(&mut self, config: &StoredConfig) -> Result<(), StatusCode>;
+}
+
+/// In-memory configuration store, the default of an [`Authenticator`]. The configuration is
+/// forgotten along with it.
+impl AuthenticatorConfigStore for Option<StoredConfig> {
+    fn load(&self) -> Option<StoredConfig> {
+        self.clone()
+    }
+
+    fn save(&mut self, config: &StoredConfig) -> Result<(), StatusCode> {
+        self.replace(config.clone());
+        Ok(())
+    }
+}
+
+impl<S: AuthenticatorConfigStore> AuthenticatorConfigStore for Arc<Mutex<S>> {
+    fn load(&self) -> Option<StoredConfig> {
+        self.lock().unwrap_or_else(PoisonError::into_inner).load()
+    }
+
+    fn save(&mut self, config: &StoredConfig) -> Result<(), StatusCode> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .save(config)
+    }
+}
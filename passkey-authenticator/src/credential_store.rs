@@ -1,15 +1,71 @@
-#[cfg(any(feature = "tokio", test))]
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Once},
+    time::SystemTime,
+};
 
 use passkey_types::{
     ctap2::{
         make_credential::PublicKeyCredentialRpEntity,
-        make_credential::PublicKeyCredentialUserEntity, Ctap2Error, StatusCode,
+        make_credential::PublicKeyCredentialUserEntity, Ctap2Error, StatusCode, U2FError,
     },
     webauthn::PublicKeyCredentialDescriptor,
     Passkey,
 };
 
+use crate::DescriptorIndex;
+
+/// A credential along with the user and RP it was saved with.
+#[derive(Debug, Clone)]
+pub struct StoredCredential {
+    /// The credential itself.
+    pub passkey: Passkey,
+    /// The user the credential was created for.
+    pub user: PublicKeyCredentialUserEntity,
+    /// The RP the credential was created for.
+    pub rp: PublicKeyCredentialRpEntity,
+}
+
+/// A discoverable credential along with the user and RP it was saved with, as listed for
+/// `authenticatorCredentialManagement`.
+pub type DiscoverableCredential = StoredCredential;
+
+impl From<Passkey> for StoredCredential {
+    /// Keep `passkey` with only the user handle and RP ID it knows of.
+    fn from(passkey: Passkey) -> Self {
+        Self {
+            user: PublicKeyCredentialUserEntity {
+                id: passkey.user_handle.clone().unwrap_or_default(),
+                name: None,
+                display_name: None,
+                icon_url: None,
+            },
+            rp: PublicKeyCredentialRpEntity {
+                id: passkey.rp_id.clone(),
+                name: None,
+            },
+            passkey,
+        }
+    }
+}
+
+/// How a [`CredentialStore`] supports discoverable credentials, which a client uses to choose
+/// whether a new credential is discoverable.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverabilitySupport {
+    /// Both discoverable and non-discoverable credentials are supported, the Relying Party's
+    /// requirement decides.
+    #[default]
+    Full,
+    /// Only non-discoverable credentials are supported, so Relying Parties requiring a
+    /// discoverable credential are refused.
+    OnlyNonDiscoverable,
+    /// Every credential is made discoverable, for example because every credential is synced to
+    /// a password manager.
+    ForcedDiscoverable,
+}
+
 /// Use this on a type that enables storage and fetching of credentials
 #[async_trait::async_trait]
 pub trait CredentialStore {
@@ -18,8 +74,16 @@ pub trait CredentialStore {
 
     /// Find all credentials matching the given `ids` and `rp_id`.
     ///
-    /// If multiple are found, it is recommended to sort the credentials using their creation date
-    /// before returning as the algorithm will take the first credential from the list for assertions.
+    /// The `ids` are the whole allow list of the request, which may hold hundreds of descriptors,
+    /// so they should be looked up together, in a single query to the storage, rather than one at
+    /// a time. The credentials found are returned once each, in the order of the allow list, as
+    /// the authenticator asserts the first one. IDs which are not found are skipped, and
+    /// `CTAP2_ERR_NO_CREDENTIALS` is returned when none is. A [`DescriptorIndex`] matches the allow
+    /// list against the credentials of the store with these semantics.
+    ///
+    /// When no `ids` are given, the authenticator asserts the most recently created credential
+    /// according to their [`Passkey::created_at`], or the first one listed among those of unknown
+    /// creation time.
     async fn find_credentials(
         &self,
         ids: Option<&[PublicKeyCredentialDescriptor]>,
@@ -33,12 +97,264 @@ pub trait CredentialStore {
         user: PublicKeyCredentialUserEntity,
         rp: PublicKeyCredentialRpEntity,
     ) -> Result<(), StatusCode>;
+
+    /// List every discoverable credential in the store for credential management.
+    ///
+    /// Credential management is unsupported by default, returning `CTAP1_ERR_INVALID_COMMAND`.
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// List every credential in the store, discoverable or not, only those of the RP with the
+    /// given `rp_id` if there is one, for example for the credential manager of a host.
+    ///
+    /// Unsupported by default, returning `CTAP1_ERR_INVALID_COMMAND`.
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        let _ = rp_id;
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Find the credentials of the RP with the given `rp_id` saved for the user with the given
+    /// `user_handle`, which a new discoverable credential for the same user overwrites, see
+    /// [`Authenticator::with_credential_overwrite`](crate::Authenticator::with_credential_overwrite).
+    ///
+    /// Defaults to filtering the [`CredentialStore::enumerate`]d credentials of the RP. Stores
+    /// which do not enumerate their credentials find none, so nothing is overwritten, which is
+    /// logged as a warning the first time it happens.
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        match self.enumerate(Some(rp_id)).await {
+            Ok(credentials) => Ok(credentials
+                .into_iter()
+                .filter(|passkey| {
+                    passkey.user_handle.as_deref().map(Vec::as_slice) == Some(user_handle)
+                })
+                .collect()),
+            Err(StatusCode::Ctap1(U2FError::InvalidCommand)) => {
+                static WARN_UNSUPPORTED: Once = Once::new();
+                WARN_UNSUPPORTED.call_once(|| {
+                    log::warn!(
+                        "Credential store does not enumerate its credentials, credentials of the \
+                         same user will not be overwritten"
+                    );
+                });
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// How many more credentials can be saved, or `None` if the store has no such limit.
+    ///
+    /// When no more can be saved, creating a credential which must be stored fails with
+    /// `CTAP2_ERR_KEY_STORE_FULL`. It is also reported by `authenticatorGetInfo`.
+    async fn remaining_capacity(&self) -> Option<u32> {
+        None
+    }
+
+    /// An estimate of how many more discoverable credentials can be saved.
+    ///
+    /// Defaults to the [`CredentialStore::remaining_capacity`], or `u32::MAX` without limit.
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        Ok(self.remaining_capacity().await.unwrap_or(u32::MAX))
+    }
+
+    /// How the store supports discoverable credentials, [`DiscoverabilitySupport::Full`] by
+    /// default.
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        DiscoverabilitySupport::Full
+    }
+
+    /// Whether the store is read-only, for example because it mirrors a synced vault which is only
+    /// written to elsewhere. `false` by default.
+    ///
+    /// The authenticator then refuses to create credentials which must be stored, and to delete or
+    /// update credentials through `authenticatorCredentialManagement`, with
+    /// `CTAP2_ERR_OPERATION_DENIED`. Assertions are still made, without recording when the
+    /// credentials are used.
+    async fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// The user the credential with the given ID was saved with, whose names are returned by the
+    /// assertions made with it once the user is verified.
+    ///
+    /// Defaults to `None`, in which case assertions only return the user handle of the credential.
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        let _ = credential_id;
+        None
+    }
+
+    /// Delete the credential with the given ID, returning `CTAP2_ERR_NO_CREDENTIALS` if there is
+    /// none.
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        let _ = credential_id;
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Replace the user information saved with the credential with the given ID. The user ID is
+    /// checked to be unchanged beforehand.
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        let _ = (credential_id, user);
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Update the [`Passkey::backup_state`] of the credential with the given ID, returning
+    /// `CTAP2_ERR_NO_CREDENTIALS` if there is none, and `CTAP2_ERR_NOT_ALLOWED` when backing up a
+    /// credential which is not [`Passkey::backup_eligible`].
+    ///
+    /// Unsupported by default, returning `CTAP1_ERR_INVALID_COMMAND`.
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        let _ = (credential_id, backup_state);
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Record that the credential with the given ID was used for an assertion at `used_at`, in its
+    /// [`Passkey::last_used_at`].
+    ///
+    /// Ignored by default, as a store need not keep when its credentials were last used.
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        let _ = (credential_id, used_at);
+        Ok(())
+    }
+
+    /// Start a transaction, whose changes are only kept once it is committed with
+    /// [`CredentialStore::commit_transaction`], and are undone if it is rolled back with
+    /// [`CredentialStore::rollback_transaction`].
+    ///
+    /// The authenticator saves a new credential along with its keys, and deletes a credential
+    /// along with its keys, in a transaction, so the store is left unchanged when any of them
+    /// fails. Stores without transactions apply their changes immediately, which is the default.
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        Ok(())
+    }
+
+    /// Keep the changes made since [`CredentialStore::begin_transaction`].
+    ///
+    /// Does nothing by default.
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        Ok(())
+    }
+
+    /// Undo the changes made since [`CredentialStore::begin_transaction`].
+    ///
+    /// Does nothing by default.
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        Ok(())
+    }
+
+    /// Delete every credential in the store, along with their signature counters, when the
+    /// authenticator is reset.
+    ///
+    /// Unsupported by default, returning `CTAP1_ERR_INVALID_COMMAND`.
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        Err(U2FError::InvalidCommand.into())
+    }
 }
 
-/// In-memory store for Passkeys
+/// In-memory store for Passkeys, which dereferences to its map of credentials by credential ID.
+/// The user and RP of every credential are kept along with it.
 ///
-/// Useful for tests.
-pub type MemoryStore = std::collections::HashMap<Vec<u8>, Passkey>;
+/// Useful for tests, and for embedders with little room through
+/// [`MemoryStore::with_max_credentials`].
+#[derive(Default, Clone)]
+pub struct MemoryStore {
+    credentials: HashMap<Vec<u8>, StoredCredential>,
+    max_credentials: Option<usize>,
+    on_evict: Option<Arc<dyn Fn(Passkey) + Send + Sync>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store without a limit on its number of credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method for limiting the number of credentials of the store to `max_credentials`.
+    ///
+    /// Once it is reached, saving another credential fails with `CTAP2_ERR_KEY_STORE_FULL`, which
+    /// the authenticator also reports through [`CredentialStore::remaining_capacity`], unless
+    /// credentials are evicted, see [`MemoryStore::with_lru_eviction`].
+    pub fn with_max_credentials(self, max_credentials: usize) -> Self {
+        Self {
+            max_credentials: Some(max_credentials),
+            ..self
+        }
+    }
+
+    /// Builder method for evicting the least recently used credential when saving a credential
+    /// to a store which has reached its [`MemoryStore::with_max_credentials`], instead of failing.
+    ///
+    /// Credentials are ordered by their [`Passkey::last_used_at`], or their
+    /// [`Passkey::created_at`] if they were never used, and credentials for which neither is known
+    /// are evicted first. Every evicted credential is given to `on_evict`.
+    pub fn with_lru_eviction(self, on_evict: impl Fn(Passkey) + Send + Sync + 'static) -> Self {
+        Self {
+            on_evict: Some(Arc::new(on_evict)),
+            ..self
+        }
+    }
+
+    /// The credential with the given ID, or `CTAP2_ERR_NO_CREDENTIALS` if there is none.
+    fn credential_mut(
+        &mut self,
+        credential_id: &[u8],
+    ) -> Result<&mut StoredCredential, StatusCode> {
+        self.get_mut(credential_id)
+            .ok_or(Ctap2Error::NoCredentials.into())
+    }
+
+    /// Make room for a new credential if the store is full, evicting the least recently used
+    /// credential when eviction is enabled and failing with `CTAP2_ERR_KEY_STORE_FULL` otherwise.
+    fn make_room(&mut self) -> Result<(), StatusCode> {
+        if self
+            .max_credentials
+            .is_none_or(|max| self.credentials.len() < max)
+        {
+            return Ok(());
+        }
+        let on_evict = self.on_evict.clone().ok_or(Ctap2Error::KeyStoreFull)?;
+        let least_recently_used = self
+            .credentials
+            .iter()
+            .min_by_key(|(_, cred)| cred.passkey.last_used_at.or(cred.passkey.created_at))
+            .map(|(credential_id, _)| credential_id.clone())
+            .ok_or(Ctap2Error::KeyStoreFull)?;
+        if let Some(evicted) = self.credentials.remove(&least_recently_used) {
+            on_evict(evicted.passkey);
+        }
+        Ok(())
+    }
+}
+
+impl Deref for MemoryStore {
+    type Target = HashMap<Vec<u8>, StoredCredential>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.credentials
+    }
+}
+
+impl DerefMut for MemoryStore {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.credentials
+    }
+}
 
 #[async_trait::async_trait]
 impl CredentialStore for MemoryStore {
@@ -47,12 +363,15 @@ impl CredentialStore for MemoryStore {
     async fn find_credentials(
         &self,
         allow_credentials: Option<&[PublicKeyCredentialDescriptor]>,
-        _rp_id: &str,
+        rp_id: &str,
     ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
         let creds: Vec<Passkey> = allow_credentials
-            .into_iter()
-            .flatten()
-            .filter_map(|id| self.get(&*id.id))
+            .map(DescriptorIndex::new)
+            .iter()
+            .flat_map(DescriptorIndex::ids)
+            .filter_map(|id| self.get(id))
+            .map(|cred| &cred.passkey)
+            .filter(|passkey| passkey.rp_id == rp_id)
             .cloned()
             .collect();
         if creds.is_empty() {
@@ -65,10 +384,91 @@ impl CredentialStore for MemoryStore {
     async fn save_credential(
         &mut self,
         cred: Passkey,
-        _user: PublicKeyCredentialUserEntity,
-        _rp: PublicKeyCredentialRpEntity,
+        user: PublicKeyCredentialUserEntity,
+        rp: PublicKeyCredentialRpEntity,
+    ) -> Result<(), StatusCode> {
+        // Replacing a credential takes no more room.
+        if !self.contains_key(&*cred.credential_id) {
+            self.make_room()?;
+        }
+        self.insert(
+            cred.credential_id.clone().into(),
+            StoredCredential {
+                passkey: cred,
+                user,
+                rp,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stores which evict credentials are never full, so they have no limit.
+    async fn remaining_capacity(&self) -> Option<u32> {
+        let max = self.max_credentials.filter(|_| self.on_evict.is_none())?;
+        let remaining = max.saturating_sub(self.len());
+        Some(u32::try_from(remaining).unwrap_or(u32::MAX))
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        Ok(self
+            .values()
+            .map(|cred| &cred.passkey)
+            .filter(|passkey| rp_id.is_none_or(|rp_id| passkey.rp_id == rp_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        Ok(self
+            .values()
+            .filter(|cred| cred.passkey.user_handle.is_some())
+            .cloned()
+            .collect())
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.get(credential_id).map(|cred| cred.user.clone())
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.remove(credential_id)
+            .map(|_| ())
+            .ok_or(Ctap2Error::NoCredentials.into())
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.credential_mut(credential_id)?.user = user;
+        Ok(())
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        let passkey = &mut self.credential_mut(credential_id)?.passkey;
+        if backup_state && !passkey.backup_eligible {
+            return Err(Ctap2Error::NotAllowed.into());
+        }
+        passkey.backup_state = backup_state;
+        Ok(())
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
     ) -> Result<(), StatusCode> {
-        self.insert(cred.credential_id.clone().into(), cred);
+        self.credential_mut(credential_id)?.passkey.last_used_at = Some(used_at);
+        Ok(())
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.clear();
         Ok(())
     }
 }
@@ -80,16 +480,15 @@ impl CredentialStore for Option<Passkey> {
     async fn find_credentials(
         &self,
         id: Option<&[PublicKeyCredentialDescriptor]>,
-        _rp_id: &str,
+        rp_id: &str,
     ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
         if let Some(id) = id {
-            id.iter().find_map(|id| {
-                // TODO: && pk.rp_id == rp_id) need rp_id on typeshared passkey
-                self.clone().filter(|pk| pk.credential_id == id.id)
-            })
+            id.iter()
+                .find_map(|id| self.clone().filter(|pk| pk.credential_id == id.id))
         } else {
-            self.clone() // TODO: .filter(|pk| pk.rp_id == rp_id) need rp_id on typeshared passkey
+            self.clone()
         }
+        .filter(|pk| pk.rp_id == rp_id)
         .map(|pk| vec![pk])
         .ok_or(Ctap2Error::NoCredentials.into())
     }
@@ -103,6 +502,78 @@ impl CredentialStore for Option<Passkey> {
         self.replace(cred);
         Ok(())
     }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        Ok(self
+            .iter()
+            .filter(|passkey| rp_id.is_none_or(|rp_id| passkey.rp_id == rp_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        Ok(self
+            .iter()
+            .filter(|passkey| passkey.user_handle.is_some())
+            .cloned()
+            .map(DiscoverableCredential::from)
+            .collect())
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        Ok(self.is_none().into())
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.take_if(|pk| *pk.credential_id == credential_id)
+            .map(|_| ())
+            .ok_or(Ctap2Error::NoCredentials.into())
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        _user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.as_ref()
+            .filter(|pk| *pk.credential_id == credential_id)
+            .map(|_| ())
+            .ok_or(Ctap2Error::NoCredentials.into())
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        let passkey = self
+            .as_mut()
+            .filter(|pk| *pk.credential_id == credential_id)
+            .ok_or(Ctap2Error::NoCredentials)?;
+        if backup_state && !passkey.backup_eligible {
+            return Err(Ctap2Error::NotAllowed.into());
+        }
+        passkey.backup_state = backup_state;
+        Ok(())
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        let passkey = self
+            .as_mut()
+            .filter(|pk| *pk.credential_id == credential_id)
+            .ok_or(Ctap2Error::NoCredentials)?;
+        passkey.last_used_at = Some(used_at);
+        Ok(())
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        *self = None;
+        Ok(())
+    }
 }
 
 #[cfg(any(feature = "tokio", test))]
@@ -128,6 +599,95 @@ impl<S: CredentialStore<PasskeyItem = Passkey> + Send + Sync> CredentialStore
     ) -> Result<(), StatusCode> {
         self.lock().await.save_credential(cred, user, rp).await
     }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.lock().await.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.lock().await.enumerate(rp_id).await
+    }
+
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.lock()
+            .await
+            .find_by_rp_and_user_handle(rp_id, user_handle)
+            .await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.lock().await.saved_user(credential_id).await
+    }
+
+    async fn remaining_capacity(&self) -> Option<u32> {
+        self.lock().await.remaining_capacity().await
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        self.lock().await.remaining_discoverable_credentials().await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        self.lock().await.discoverability().await
+    }
+
+    async fn is_read_only(&self) -> bool {
+        self.lock().await.is_read_only().await
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.lock().await.delete_credential(credential_id).await
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.lock().await.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.lock()
+            .await
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.lock()
+            .await
+            .update_last_used(credential_id, used_at)
+            .await
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.begin_transaction().await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.commit_transaction().await
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.rollback_transaction().await
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.clear_all().await
+    }
 }
 
 #[cfg(any(feature = "tokio", test))]
@@ -153,6 +713,95 @@ impl<S: CredentialStore<PasskeyItem = Passkey> + Send + Sync> CredentialStore
     ) -> Result<(), StatusCode> {
         self.write().await.save_credential(cred, user, rp).await
     }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.read().await.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.read().await.enumerate(rp_id).await
+    }
+
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.read()
+            .await
+            .find_by_rp_and_user_handle(rp_id, user_handle)
+            .await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.read().await.saved_user(credential_id).await
+    }
+
+    async fn remaining_capacity(&self) -> Option<u32> {
+        self.read().await.remaining_capacity().await
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        self.read().await.remaining_discoverable_credentials().await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        self.read().await.discoverability().await
+    }
+
+    async fn is_read_only(&self) -> bool {
+        self.read().await.is_read_only().await
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.write().await.delete_credential(credential_id).await
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.write().await.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.write()
+            .await
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.write()
+            .await
+            .update_last_used(credential_id, used_at)
+            .await
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.begin_transaction().await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.commit_transaction().await
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.rollback_transaction().await
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.write().await.clear_all().await
+    }
 }
 
 #[cfg(any(feature = "tokio", test))]
@@ -178,6 +827,95 @@ impl<S: CredentialStore<PasskeyItem = Passkey> + Send + Sync> CredentialStore
     ) -> Result<(), StatusCode> {
         self.lock().await.save_credential(cred, user, rp).await
     }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.lock().await.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.lock().await.enumerate(rp_id).await
+    }
+
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.lock()
+            .await
+            .find_by_rp_and_user_handle(rp_id, user_handle)
+            .await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.lock().await.saved_user(credential_id).await
+    }
+
+    async fn remaining_capacity(&self) -> Option<u32> {
+        self.lock().await.remaining_capacity().await
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        self.lock().await.remaining_discoverable_credentials().await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        self.lock().await.discoverability().await
+    }
+
+    async fn is_read_only(&self) -> bool {
+        self.lock().await.is_read_only().await
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.lock().await.delete_credential(credential_id).await
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.lock().await.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.lock()
+            .await
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.lock()
+            .await
+            .update_last_used(credential_id, used_at)
+            .await
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.begin_transaction().await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.commit_transaction().await
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.rollback_transaction().await
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.lock().await.clear_all().await
+    }
 }
 
 #[cfg(any(feature = "tokio", test))]
@@ -203,4 +941,350 @@ impl<S: CredentialStore<PasskeyItem = Passkey> + Send + Sync> CredentialStore
     ) -> Result<(), StatusCode> {
         self.write().await.save_credential(cred, user, rp).await
     }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.read().await.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.read().await.enumerate(rp_id).await
+    }
+
+    async fn find_by_rp_and_user_handle(
+        &self,
+        rp_id: &str,
+        user_handle: &[u8],
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.read()
+            .await
+            .find_by_rp_and_user_handle(rp_id, user_handle)
+            .await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.read().await.saved_user(credential_id).await
+    }
+
+    async fn remaining_capacity(&self) -> Option<u32> {
+        self.read().await.remaining_capacity().await
+    }
+
+    async fn remaining_discoverable_credentials(&self) -> Result<u32, StatusCode> {
+        self.read().await.remaining_discoverable_credentials().await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        self.read().await.discoverability().await
+    }
+
+    async fn is_read_only(&self) -> bool {
+        self.read().await.is_read_only().await
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.write().await.delete_credential(credential_id).await
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.write().await.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.write()
+            .await
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.write()
+            .await
+            .update_last_used(credential_id, used_at)
+            .await
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.begin_transaction().await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.commit_transaction().await
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        self.write().await.rollback_transaction().await
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.write().await.clear_all().await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A backed up credential of "example.com" with the given ID, which is also its user handle,
+    /// for the tests to adjust as they need.
+    pub(crate) fn passkey(credential_id: &[u8]) -> Passkey {
+        Passkey {
+            key: Default::default(),
+            rp_id: "example.com".into(),
+            credential_id: credential_id.to_vec().into(),
+            user_handle: Some(credential_id.to_vec().into()),
+            counter: None,
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
+        }
+    }
+
+    /// A [`MemoryStore`] with transactions, keeping the credentials as they were when the
+    /// transaction began to restore them if it is rolled back.
+    #[derive(Default)]
+    pub(crate) struct TransactionalStore {
+        pub(crate) credentials: MemoryStore,
+        pub(crate) snapshot: Option<MemoryStore>,
+        pub(crate) rollbacks: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl CredentialStore for TransactionalStore {
+        type PasskeyItem = Passkey;
+
+        async fn find_credentials(
+            &self,
+            ids: Option<&[PublicKeyCredentialDescriptor]>,
+            rp_id: &str,
+        ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
+            self.credentials.find_credentials(ids, rp_id).await
+        }
+
+        async fn save_credential(
+            &mut self,
+            cred: Passkey,
+            user: PublicKeyCredentialUserEntity,
+            rp: PublicKeyCredentialRpEntity,
+        ) -> Result<(), StatusCode> {
+            self.credentials.save_credential(cred, user, rp).await
+        }
+
+        async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+            self.credentials.delete_credential(credential_id).await
+        }
+
+        async fn discoverable_credentials(
+            &self,
+        ) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+            self.credentials.discoverable_credentials().await
+        }
+
+        async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+            self.snapshot = Some(self.credentials.clone());
+            Ok(())
+        }
+
+        async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+            self.snapshot
+                .take()
+                .map(|_| ())
+                .ok_or(U2FError::Other.into())
+        }
+
+        async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+            self.credentials = self.snapshot.take().ok_or(U2FError::Other)?;
+            self.rollbacks += 1;
+            Ok(())
+        }
+    }
+
+    /// A store of discoverable credentials which lists them in the order they were saved, oldest
+    /// first.
+    #[derive(Default)]
+    pub(crate) struct ListStore(pub(crate) Vec<Passkey>);
+
+    #[async_trait::async_trait]
+    impl CredentialStore for ListStore {
+        type PasskeyItem = Passkey;
+
+        async fn find_credentials(
+            &self,
+            ids: Option<&[PublicKeyCredentialDescriptor]>,
+            rp_id: &str,
+        ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
+            let creds: Vec<Passkey> = match ids {
+                Some(ids) => DescriptorIndex::new(ids)
+                    .select(rp_id, &self.0)
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+                None => self
+                    .0
+                    .iter()
+                    .filter(|cred| cred.rp_id == rp_id)
+                    .cloned()
+                    .collect(),
+            };
+            if creds.is_empty() {
+                Err(Ctap2Error::NoCredentials.into())
+            } else {
+                Ok(creds)
+            }
+        }
+
+        async fn save_credential(
+            &mut self,
+            cred: Passkey,
+            _user: PublicKeyCredentialUserEntity,
+            _rp: PublicKeyCredentialRpEntity,
+        ) -> Result<(), StatusCode> {
+            self.0.push(cred);
+            Ok(())
+        }
+
+        async fn update_last_used(
+            &mut self,
+            credential_id: &[u8],
+            used_at: SystemTime,
+        ) -> Result<(), StatusCode> {
+            let passkey = self
+                .0
+                .iter_mut()
+                .find(|pk| *pk.credential_id == credential_id)
+                .ok_or(Ctap2Error::NoCredentials)?;
+            passkey.last_used_at = Some(used_at);
+            Ok(())
+        }
+    }
+
+    fn user(passkey: &Passkey) -> PublicKeyCredentialUserEntity {
+        PublicKeyCredentialUserEntity {
+            id: passkey.user_handle.clone().unwrap(),
+            name: None,
+            display_name: None,
+            icon_url: None,
+        }
+    }
+
+    fn rp() -> PublicKeyCredentialRpEntity {
+        PublicKeyCredentialRpEntity {
+            id: "example.com".into(),
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_are_enumerated_by_rp() {
+        let mut store = MemoryStore::new();
+        let mut other_rp = passkey(&[2]);
+        other_rp.rp_id = "other.example.com".into();
+        other_rp.user_handle = None;
+        let user = user(&passkey(&[1]));
+        for credential in [passkey(&[1]), other_rp] {
+            store
+                .save_credential(credential, user.clone(), rp())
+                .await
+                .unwrap();
+        }
+
+        let ids = |passkeys: Vec<Passkey>| {
+            let mut ids: Vec<_> = passkeys
+                .iter()
+                .map(|passkey| passkey.credential_id.to_vec())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(store.enumerate(None).await.unwrap()), [[1], [2]]);
+        assert_eq!(
+            ids(store.enumerate(Some("other.example.com")).await.unwrap()),
+            [[2]]
+        );
+        assert!(store
+            .enumerate(Some("unknown.example.com"))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Unlike the discoverable credentials, non-discoverable ones are enumerated too.
+        assert_eq!(store.discoverable_credentials().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn full_memory_store_refuses_new_credentials() {
+        let mut store = MemoryStore::new().with_max_credentials(2);
+        for id in [1, 2] {
+            let passkey = passkey(&[id]);
+            store
+                .save_credential(passkey.clone(), user(&passkey), rp())
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.remaining_capacity().await, Some(0));
+
+        let new = passkey(&[3]);
+        assert_eq!(
+            store.save_credential(new.clone(), user(&new), rp()).await,
+            Err(Ctap2Error::KeyStoreFull.into())
+        );
+        // Credentials may still be replaced.
+        let replaced = passkey(&[2]);
+        store
+            .save_credential(replaced.clone(), user(&replaced), rp())
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_credentials_are_evicted() {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_evict = {
+            let evicted = evicted.clone();
+            move |passkey: Passkey| evicted.lock().unwrap().push(passkey.credential_id.clone())
+        };
+        let mut store = MemoryStore::new()
+            .with_max_credentials(2)
+            .with_lru_eviction(on_evict);
+        assert_eq!(store.remaining_capacity().await, None);
+
+        let start = SystemTime::now();
+        let minutes = |minutes: u64| start + std::time::Duration::from_secs(60 * minutes);
+        for id in [1, 2] {
+            let mut passkey = passkey(&[id]);
+            passkey.created_at = Some(minutes(id.into()));
+            store
+                .save_credential(passkey.clone(), user(&passkey), rp())
+                .await
+                .unwrap();
+        }
+        // The oldest credential was used since, so the other one is least recently used.
+        store.update_last_used(&[1], minutes(3)).await.unwrap();
+        let mut new = passkey(&[3]);
+        new.created_at = Some(minutes(4));
+        store
+            .save_credential(new.clone(), user(&new), rp())
+            .await
+            .unwrap();
+
+        assert_eq!(*evicted.lock().unwrap(), [vec![2].into()]);
+        let mut remaining: Vec<_> = store.keys().cloned().collect();
+        remaining.sort();
+        assert_eq!(remaining, [vec![1], vec![3]]);
+    }
 }
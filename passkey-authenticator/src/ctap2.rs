@@ -5,10 +5,18 @@
 //!
 //! <https://fidoalliance.org/specs/fido-v2.0-ps-20190130/fido-client-to-authenticator-protocol-v2.0-ps-20190130.html#authenticator-api>
 
-use passkey_types::ctap2::{get_assertion, get_info, make_credential, StatusCode};
+use passkey_types::ctap2::{
+    authenticator_config, bio_enrollment, client_pin, credential_management, get_assertion,
+    get_info, large_blobs, make_credential, StatusCode,
+};
+use passkey_types::Passkey;
 
 use crate::{Authenticator, CredentialStore, UserValidationMethod};
 
+mod server;
+
+pub use self::server::Ctap2Server;
+
 mod sealed {
     use crate::{Authenticator, CredentialStore, UserValidationMethod};
 
@@ -40,9 +48,45 @@ pub trait Ctap2Api: sealed::Sealed {
 
     /// Request to assert a user's existing credential that might exist in the authenticator.
     async fn get_assertion(
-        &self,
+        &mut self,
         request: get_assertion::Request,
     ) -> Result<get_assertion::Response, StatusCode>;
+
+    /// Request to set or change the authenticator's PIN, or to get a PIN token from it.
+    async fn client_pin(
+        &mut self,
+        request: client_pin::Request,
+    ) -> Result<client_pin::Response, StatusCode>;
+
+    /// Request to change the configuration of the authenticator, such as its minimum PIN length.
+    async fn authenticator_config(
+        &mut self,
+        request: authenticator_config::Request,
+    ) -> Result<(), StatusCode>;
+
+    /// Request to list, delete or update the discoverable credentials of the authenticator.
+    async fn credential_management(
+        &mut self,
+        request: credential_management::Request,
+    ) -> Result<credential_management::Response, StatusCode>;
+
+    /// Request to enroll the user's fingerprints or to list, name or remove the enrollments.
+    async fn bio_enrollment(
+        &mut self,
+        request: bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, StatusCode>;
+
+    /// Request to delete every credential and the PIN, resetting the authenticator.
+    async fn reset(&mut self) -> Result<(), StatusCode>;
+
+    /// Request the user to pick this authenticator by confirming their presence on it.
+    async fn selection(&self) -> Result<(), StatusCode>;
+
+    /// Request to read or write a fragment of the authenticator's large-blob array.
+    async fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, StatusCode>;
 }
 
 #[async_trait::async_trait]
@@ -50,9 +94,13 @@ impl<S, U> Ctap2Api for Authenticator<S, U>
 where
     S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync + Send,
+    // Required by `Authenticator::get_assertion`, without which the call below would resolve to
+    // this trait method itself.
+    <S as CredentialStore>::PasskeyItem: Send,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
 {
     async fn get_info(&self) -> get_info::Response {
-        self.get_info()
+        self.get_info().await
     }
 
     async fn make_credential(
@@ -63,9 +111,52 @@ where
     }
 
     async fn get_assertion(
-        &self,
+        &mut self,
         request: get_assertion::Request,
     ) -> Result<get_assertion::Response, StatusCode> {
         self.get_assertion(request).await
     }
+
+    async fn client_pin(
+        &mut self,
+        request: client_pin::Request,
+    ) -> Result<client_pin::Response, StatusCode> {
+        self.client_pin(request).await
+    }
+
+    async fn authenticator_config(
+        &mut self,
+        request: authenticator_config::Request,
+    ) -> Result<(), StatusCode> {
+        self.authenticator_config(request)
+    }
+
+    async fn credential_management(
+        &mut self,
+        request: credential_management::Request,
+    ) -> Result<credential_management::Response, StatusCode> {
+        self.credential_management(request).await
+    }
+
+    async fn bio_enrollment(
+        &mut self,
+        request: bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, StatusCode> {
+        self.bio_enrollment(request).await
+    }
+
+    async fn reset(&mut self) -> Result<(), StatusCode> {
+        self.reset().await
+    }
+
+    async fn selection(&self) -> Result<(), StatusCode> {
+        self.selection().await
+    }
+
+    async fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, StatusCode> {
+        self.large_blobs(request)
+    }
 }
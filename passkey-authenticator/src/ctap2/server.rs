@@ -0,0 +1,300 @@
+use passkey_types::ctap2::{Command, Ctap2Error, StatusCode, U2FError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Ctap2Api;
+
+/// The status byte of a successful response.
+const CTAP2_OK: u8 = 0x00;
+
+/// The CBOR encoding of an empty map, which is sent as no response data at all.
+const EMPTY_MAP: [u8; 1] = [0xa0];
+
+/// Handles raw CTAP2 messages on behalf of a [`Ctap2Api`], for transports which receive the
+/// messages as bytes.
+///
+/// A message is decoded into the request of its command, the matching [`Ctap2Api`] method is
+/// called, and its response or error is encoded back into a CTAP2 response.
+///
+/// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#message-encoding>
+pub struct Ctap2Server<A> {
+    authenticator: A,
+}
+
+impl<A: Ctap2Api + Send> Ctap2Server<A> {
+    /// Serve the given authenticator.
+    pub fn new(authenticator: A) -> Self {
+        Self { authenticator }
+    }
+
+    /// Access the served authenticator.
+    pub fn authenticator(&self) -> &A {
+        &self.authenticator
+    }
+
+    /// Mutably access the served authenticator.
+    pub fn authenticator_mut(&mut self) -> &mut A {
+        &mut self.authenticator
+    }
+
+    /// Stop serving the authenticator and give it back.
+    pub fn into_inner(self) -> A {
+        self.authenticator
+    }
+
+    /// Handle a CTAP2 message: a command byte followed by its CBOR encoded parameters. The
+    /// response is a status byte, followed by the CBOR encoded response of a successful command
+    /// which has one.
+    pub async fn handle(&mut self, message: &[u8]) -> Vec<u8> {
+        let Some((&command, parameters)) = message.split_first() else {
+            return vec![U2FError::InvalidLength.into()];
+        };
+        match self.dispatch(command, parameters).await {
+            Ok(response) => std::iter::once(CTAP2_OK).chain(response).collect(),
+            Err(status) => vec![status.into()],
+        }
+    }
+
+    /// Decode the CBOR encoded `parameters` of `command`, perform the command and encode its
+    /// response. Commands without a response, or with an empty one, return no bytes.
+    pub async fn dispatch(
+        &mut self,
+        command: u8,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let command = Command::try_from(command).map_err(|_| U2FError::InvalidCommand)?;
+        let authenticator = &mut self.authenticator;
+        match command {
+            Command::MakeCredential => {
+                encode(&authenticator.make_credential(decode(parameters)?).await?)
+            }
+            Command::GetAssertion => {
+                encode(&authenticator.get_assertion(decode(parameters)?).await?)
+            }
+            Command::GetInfo => encode(&authenticator.get_info().await),
+            Command::ClientPin => encode(&authenticator.client_pin(decode(parameters)?).await?),
+            Command::Reset => authenticator.reset().await.map(|()| Vec::new()),
+            // Only one credential is ever asserted at a time, so there is never a next one.
+            Command::GetNextAssertion => Err(Ctap2Error::NotAllowed.into()),
+            Command::BioEnrollment => {
+                encode(&authenticator.bio_enrollment(decode(parameters)?).await?)
+            }
+            Command::CredentialManagement => encode(
+                &authenticator
+                    .credential_management(decode(parameters)?)
+                    .await?,
+            ),
+            Command::Selection => authenticator.selection().await.map(|()| Vec::new()),
+            Command::LargeBlobs => encode(&authenticator.large_blobs(decode(parameters)?).await?),
+            Command::Config => authenticator
+                .authenticator_config(decode(parameters)?)
+                .await
+                .map(|()| Vec::new()),
+            _ => Err(U2FError::InvalidCommand.into()),
+        }
+    }
+}
+
+/// Decode the parameters of a command, mapping CBOR errors to their status code.
+fn decode<T: DeserializeOwned>(parameters: &[u8]) -> Result<T, StatusCode> {
+    ciborium::de::from_reader(parameters).map_err(|error| {
+        match error {
+            ciborium::de::Error::Semantic(_, message) if message.starts_with("missing field") => {
+                Ctap2Error::MissingParameter
+            }
+            ciborium::de::Error::Semantic(..) => Ctap2Error::CborUnexpectedType,
+            _ => Ctap2Error::InvalidCbor,
+        }
+        .into()
+    })
+}
+
+/// Encode the response of a command, leaving out empty responses.
+fn encode<T: Serialize>(response: &T) -> Result<Vec<u8>, StatusCode> {
+    let mut encoded = Vec::new();
+    ciborium::ser::into_writer(response, &mut encoded).map_err(|_| U2FError::Other)?;
+    if encoded == EMPTY_MAP {
+        encoded.clear();
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::{cbor, value::Value};
+    use coset::iana;
+    use passkey_types::{
+        ctap2::{
+            get_assertion, get_info,
+            make_credential::{self, Options, PublicKeyCredentialRpEntity},
+            Aaguid,
+        },
+        rand::random_vec,
+        webauthn,
+    };
+
+    use super::*;
+    use crate::{user_validation::MockUserValidationMethod, Authenticator, MemoryStore};
+
+    fn server() -> Ctap2Server<Authenticator<MemoryStore, MockUserValidationMethod>> {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| Some(true));
+        user_mock
+            .expect_check_user_verification()
+            .returning(|| Box::pin(async { true }));
+        user_mock
+            .expect_check_user_presence()
+            .returning(|| Box::pin(async { true }));
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        Ctap2Server::new(Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            user_mock,
+        ))
+    }
+
+    fn message<T: Serialize>(command: Command, parameters: &T) -> Vec<u8> {
+        let mut message = vec![command.into()];
+        ciborium::ser::into_writer(parameters, &mut message).unwrap();
+        message
+    }
+
+    #[tokio::test]
+    async fn get_info() {
+        let mut server = server();
+
+        let response = server.handle(&[Command::GetInfo.into()]).await;
+
+        assert_eq!(response[0], CTAP2_OK);
+        let info: get_info::Response = ciborium::de::from_reader(&response[1..]).unwrap();
+        assert_eq!(info, server.authenticator().get_info().await);
+    }
+
+    #[tokio::test]
+    async fn make_credential() {
+        let mut server = server();
+        let request = make_credential::Request {
+            client_data_hash: random_vec(32).into(),
+            rp: PublicKeyCredentialRpEntity {
+                id: "future.1password.com".into(),
+                name: Some("1password".into()),
+            },
+            user: webauthn::PublicKeyCredentialUserEntity {
+                id: random_vec(16).into(),
+                display_name: "wendy".into(),
+                name: "Appleseed".into(),
+            },
+            pub_key_cred_params: vec![webauthn::PublicKeyCredentialParameters {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                alg: iana::Algorithm::ES256,
+            }],
+            exclude_list: None,
+            extensions: None,
+            options: Options {
+                rk: true,
+                up: true,
+                uv: true,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+            enterprise_attestation: None,
+        };
+
+        let response = server
+            .handle(&message(Command::MakeCredential, &request))
+            .await;
+
+        assert_eq!(response[0], CTAP2_OK);
+        let response: make_credential::Response =
+            ciborium::de::from_reader(&response[1..]).unwrap();
+        assert_eq!(response.fmt, "none");
+        assert_eq!(server.authenticator().store().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn large_blob_key_round_trip() {
+        let mut server = server();
+        let user_id = Value::Bytes(random_vec(16));
+        let make_credential = cbor!({
+            0x01 => Value::Bytes(random_vec(32)),
+            0x02 => { "id" => "future.1password.com" },
+            0x03 => { "id" => user_id, "name" => "Appleseed", "displayName" => "wendy" },
+            0x04 => [{ "type" => "public-key", "alg" => -7 }],
+            0x06 => { "largeBlobKey" => true },
+            0x07 => { "rk" => true },
+        })
+        .unwrap();
+
+        let response = server
+            .handle(&message(Command::MakeCredential, &make_credential))
+            .await;
+
+        assert_eq!(response[0], CTAP2_OK);
+        let response: make_credential::Response =
+            ciborium::de::from_reader(&response[1..]).unwrap();
+        let large_blob_key = response.large_blob_key.expect("missing large blob key");
+        assert_eq!(large_blob_key.len(), 32);
+        let credential_id = response
+            .auth_data
+            .attested_credential_data
+            .expect("missing attested credential data")
+            .credential_id()
+            .to_vec();
+
+        let get_assertion = cbor!({
+            0x01 => "future.1password.com",
+            0x02 => Value::Bytes(random_vec(32)),
+            0x03 => [{ "type" => "public-key", "id" => Value::Bytes(credential_id) }],
+            0x04 => { "largeBlobKey" => true },
+        })
+        .unwrap();
+
+        let response = server
+            .handle(&message(Command::GetAssertion, &get_assertion))
+            .await;
+
+        assert_eq!(response[0], CTAP2_OK);
+        let response: get_assertion::Response = ciborium::de::from_reader(&response[1..]).unwrap();
+        assert_eq!(response.large_blob_key, Some(large_blob_key));
+    }
+
+    #[tokio::test]
+    async fn commands_without_response_data() {
+        let mut server = server();
+
+        let response = server.handle(&[Command::Selection.into()]).await;
+
+        assert_eq!(response, [CTAP2_OK]);
+    }
+
+    #[tokio::test]
+    async fn errors_are_returned_as_status() {
+        let mut server = server();
+
+        assert_eq!(
+            server.handle(&[]).await,
+            [u8::from(U2FError::InvalidLength)]
+        );
+        assert_eq!(
+            server.handle(&[0x40]).await,
+            [u8::from(U2FError::InvalidCommand)]
+        );
+        assert_eq!(
+            server.handle(&[Command::ClientPin.into(), 0xa1]).await,
+            [u8::from(Ctap2Error::InvalidCbor)]
+        );
+        // authenticatorClientPIN requires its subcommand
+        assert_eq!(
+            server
+                .handle(&message(Command::ClientPin, &cbor!({ 0x01 => 2 }).unwrap()))
+                .await,
+            [u8::from(Ctap2Error::MissingParameter)]
+        );
+        assert_eq!(
+            server.handle(&[Command::GetNextAssertion.into()]).await,
+            [u8::from(Ctap2Error::NotAllowed)]
+        );
+    }
+}
@@ -0,0 +1,607 @@
+//! The [Credential Exchange Format] of the FIDO Alliance, with which credential managers export
+//! their passkeys and import those of others, so that users migrate between credential managers
+//! built on a [`CredentialStore`].
+//!
+//! The models use the JSON encoding of the format, binary values being base64url encoded. Only the
+//! parts describing passkeys are modelled: other credentials, such as passwords, are ignored when
+//! importing, and so are the fields of the format which do not apply to passkeys.
+//!
+//! [Credential Exchange Format]: https://fidoalliance.org/specs/cx/cxf-v1.0-ps-20250814.html
+
+use std::time::{Duration, SystemTime};
+
+use passkey_types::{
+    ctap2::{
+        make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+        StatusCode,
+    },
+    encoding, CredRandoms, Passkey,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    attestation::chain::{cose_key_from_pkcs8, pkcs8_from_cose_key},
+    hmac_secret::cred_random,
+    CredentialStore, HmacSecretUvPolicy,
+};
+
+/// The version of the format written by [`Header::new`].
+pub const CXF_VERSION: Version = Version { major: 1, minor: 0 };
+
+/// The algorithm of the [`HmacCredentials`], the only one the `hmac-secret` extension uses.
+const HMAC_SHA256: &str = "hmac-sha256";
+
+/// The reasons an exchange of credentials fails.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExchangeError {
+    /// A passkey is not valid: one of its binary values is not valid base64url, its key is not a
+    /// PKCS#8 document of a supported algorithm, or its CredRandoms are not 32 bytes long.
+    InvalidPasskey,
+    /// The credential store failed with the given status.
+    Store(StatusCode),
+}
+
+impl From<StatusCode> for ExchangeError {
+    fn from(status: StatusCode) -> Self {
+        ExchangeError::Store(status)
+    }
+}
+
+/// The version of the format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// Incremented by changes which are not compatible with previous versions.
+    pub major: u8,
+    /// Incremented by changes which are compatible with previous versions.
+    pub minor: u8,
+}
+
+/// The top level of an export, with the accounts of the exporting credential manager.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header {
+    /// The version of the format of the export.
+    pub version: Version,
+    /// The RP ID of the exporting credential manager.
+    pub exporter_rp_id: String,
+    /// The name of the exporting credential manager, displayed to the user.
+    pub exporter_display_name: String,
+    /// When the export was made, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The exported accounts.
+    pub accounts: Vec<Account>,
+}
+
+impl Header {
+    /// Start an export of the credential manager with the given RP ID and display name, made at
+    /// the given time, to which [`Account`]s are added.
+    pub fn new(
+        exporter_rp_id: impl Into<String>,
+        exporter_display_name: impl Into<String>,
+        timestamp: SystemTime,
+    ) -> Self {
+        Self {
+            version: CXF_VERSION,
+            exporter_rp_id: exporter_rp_id.into(),
+            exporter_display_name: exporter_display_name.into(),
+            timestamp: to_seconds(timestamp).unwrap_or_default(),
+            accounts: Vec::new(),
+        }
+    }
+}
+
+/// An account of the user in a credential manager, with the items it holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    /// The base64url ID of the account in the exporting credential manager.
+    pub id: String,
+    /// The name of the user of the account.
+    pub username: String,
+    /// The email address of the user of the account.
+    pub email: String,
+    /// The full name of the user of the account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    /// The collections grouping the items of the account.
+    #[serde(default)]
+    pub collections: Vec<Collection>,
+    /// The items of the account.
+    pub items: Vec<Item>,
+}
+
+/// A collection of items, like a folder or a vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    /// The base64url ID of the collection.
+    pub id: String,
+    /// The title of the collection, displayed to the user.
+    pub title: String,
+    /// The items in the collection.
+    #[serde(default)]
+    pub items: Vec<LinkedItem>,
+}
+
+/// A reference to an item, in the same account unless another is given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedItem {
+    /// The ID of the item.
+    pub item: String,
+    /// The ID of the account of the item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// An item of an account, holding the credentials of the user for a service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    /// The base64url ID of the item.
+    pub id: String,
+    /// When the item was created, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_at: Option<u64>,
+    /// When the item was last modified, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<u64>,
+    /// The title of the item, displayed to the user.
+    pub title: String,
+    /// The credentials of the item.
+    pub credentials: Vec<Credential>,
+}
+
+/// A credential of an item, by type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Credential {
+    /// A passkey.
+    Passkey(Box<PasskeyCredential>),
+    /// A credential of another type, which is not imported. It cannot be exported either.
+    #[serde(other, skip_serializing)]
+    Unsupported,
+}
+
+/// An exported passkey.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCredential {
+    /// The base64url ID of the credential.
+    pub credential_id: String,
+    /// The RP ID of the credential.
+    pub rp_id: String,
+    /// The name of the user of the credential.
+    pub username: String,
+    /// The display name of the user of the credential.
+    pub user_display_name: String,
+    /// The base64url user handle of the credential.
+    pub user_handle: String,
+    /// The base64url private key of the credential, as a PKCS#8 document.
+    ///
+    /// # PII considerations
+    /// This value is secret, it is zeroized when dropped.
+    pub key: String,
+    /// The state of the extensions of the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fido2_extensions: Option<Fido2Extensions>,
+}
+
+impl Drop for PasskeyCredential {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl std::fmt::Debug for PasskeyCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasskeyCredential")
+            .field("credential_id", &self.credential_id)
+            .field("rp_id", &self.rp_id)
+            .field("username", &self.username)
+            .field("user_display_name", &self.user_display_name)
+            .field("user_handle", &self.user_handle)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The state of the extensions of an exported passkey.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fido2Extensions {
+    /// The CredRandoms of the `hmac-secret` extension.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac_credentials: Option<HmacCredentials>,
+    /// Whether the credential may be used for payments by third parties.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payments: Option<bool>,
+}
+
+/// The CredRandoms of the `hmac-secret` extension of an exported passkey, see [`CredRandoms`].
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HmacCredentials {
+    /// The HMAC algorithm, `hmac-sha256`.
+    pub algorithm: String,
+    /// The base64url CredRandom used when the user is verified.
+    #[serde(rename = "credWithUV")]
+    pub cred_with_uv: String,
+    /// The base64url CredRandom used when the user is not verified.
+    #[serde(rename = "credWithoutUV")]
+    pub cred_without_uv: String,
+}
+
+impl Drop for HmacCredentials {
+    fn drop(&mut self) {
+        self.cred_with_uv.zeroize();
+        self.cred_without_uv.zeroize();
+    }
+}
+
+impl std::fmt::Debug for HmacCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HmacCredentials")
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PasskeyCredential {
+    /// Export the discoverable `passkey` saved with the given `user`.
+    ///
+    /// Returns `None` for credentials which are not discoverable, and those whose private key
+    /// cannot be encoded as a PKCS#8 document, such as credentials derived from their ID.
+    pub fn export(passkey: &Passkey, user: &PublicKeyCredentialUserEntity) -> Option<Self> {
+        let user_handle = passkey.user_handle.as_ref()?;
+        let key = Zeroizing::new(pkcs8_from_cose_key(&passkey.key)?);
+        // The CredRandoms in use are exported, including those derived from the private key, so
+        // that the outputs of the credential stay the same once it is imported.
+        let hmac_credentials = cred_random(passkey, true, HmacSecretUvPolicy::Separate)
+            .zip(cred_random(passkey, false, HmacSecretUvPolicy::Separate))
+            .map(|(with_uv, without_uv)| HmacCredentials {
+                algorithm: HMAC_SHA256.into(),
+                cred_with_uv: encoding::base64url(with_uv.as_ref()),
+                cred_without_uv: encoding::base64url(without_uv.as_ref()),
+            });
+        Some(Self {
+            credential_id: encoding::base64url(&passkey.credential_id),
+            rp_id: passkey.rp_id.clone(),
+            username: user.name.clone().unwrap_or_default(),
+            user_display_name: user.display_name.clone().unwrap_or_default(),
+            user_handle: encoding::base64url(user_handle),
+            key: encoding::base64url(&key),
+            fido2_extensions: Some(Fido2Extensions {
+                hmac_credentials,
+                payments: passkey.third_party_payment.then_some(true),
+            }),
+        })
+    }
+
+    /// Import the passkey, created at the given time if known, along with its user.
+    ///
+    /// Imported passkeys are backup eligible and backed up, as they are synced between credential
+    /// managers.
+    pub fn import(
+        &self,
+        created_at: Option<SystemTime>,
+    ) -> Result<(Passkey, PublicKeyCredentialUserEntity), ExchangeError> {
+        let decode =
+            |value: &str| encoding::try_from_base64url(value).ok_or(ExchangeError::InvalidPasskey);
+        let key = Zeroizing::new(decode(&self.key)?);
+        let key = cose_key_from_pkcs8(&key).map_err(|_| ExchangeError::InvalidPasskey)?;
+        let user_handle = decode(&self.user_handle)?;
+        let extensions = self.fido2_extensions.as_ref();
+        let cred_randoms = extensions
+            .and_then(|extensions| extensions.hmac_credentials.as_ref())
+            .filter(|hmac_credentials| hmac_credentials.algorithm == HMAC_SHA256)
+            .map(|hmac_credentials| {
+                let decode_cred_random = |value: &str| {
+                    let cred_random = Zeroizing::new(decode(value)?);
+                    <[u8; 32]>::try_from(cred_random.as_slice())
+                        .map_err(|_| ExchangeError::InvalidPasskey)
+                };
+                Ok::<_, ExchangeError>(CredRandoms {
+                    with_uv: decode_cred_random(&hmac_credentials.cred_with_uv)?,
+                    without_uv: Some(decode_cred_random(&hmac_credentials.cred_without_uv)?),
+                })
+            })
+            .transpose()?;
+
+        let passkey = Passkey {
+            key,
+            credential_id: decode(&self.credential_id)?.into(),
+            rp_id: self.rp_id.clone(),
+            user_handle: Some(user_handle.clone().into()),
+            counter: None,
+            third_party_payment: extensions
+                .and_then(|extensions| extensions.payments)
+                .unwrap_or_default(),
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms,
+            created_at,
+            last_used_at: None,
+        };
+        let user = PublicKeyCredentialUserEntity {
+            id: user_handle.into(),
+            name: Some(self.username.clone()).filter(|name| !name.is_empty()),
+            display_name: Some(self.user_display_name.clone()).filter(|name| !name.is_empty()),
+            icon_url: None,
+        };
+        Ok((passkey, user))
+    }
+}
+
+impl Account {
+    /// Create an account with the given ID, to which the passkeys of a store are exported.
+    pub fn new(id: &[u8], username: impl Into<String>, email: impl Into<String>) -> Self {
+        Self {
+            id: encoding::base64url(id),
+            username: username.into(),
+            email: email.into(),
+            full_name: None,
+            collections: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Export the discoverable credentials of the `store` into the account, one item each, see
+    /// [`PasskeyCredential::export`]. The store must [enumerate](CredentialStore::enumerate) its
+    /// credentials.
+    ///
+    /// Returns the number of exported credentials.
+    pub async fn export_passkeys<S>(&mut self, store: &S) -> Result<usize, ExchangeError>
+    where
+        S: CredentialStore + Sync,
+    {
+        let mut exported = 0;
+        for passkey in store.enumerate(None).await? {
+            let user = store
+                .saved_user(&passkey.credential_id)
+                .await
+                .unwrap_or_else(|| PublicKeyCredentialUserEntity {
+                    id: passkey.user_handle.clone().unwrap_or_default(),
+                    name: None,
+                    display_name: None,
+                    icon_url: None,
+                });
+            let Some(credential) = PasskeyCredential::export(&passkey, &user) else {
+                continue;
+            };
+            self.items.push(Item {
+                // Credential IDs may be longer than the IDs of items, so their digest is used.
+                id: encoding::base64url(&Sha256::digest(passkey.credential_id.as_slice())),
+                creation_at: passkey.created_at.and_then(to_seconds),
+                modified_at: None,
+                title: passkey.rp_id.clone(),
+                credentials: vec![Credential::Passkey(Box::new(credential))],
+            });
+            exported += 1;
+        }
+        Ok(exported)
+    }
+
+    /// Import the passkeys of the account into the `store`, replacing those with the same
+    /// credential ID, see [`PasskeyCredential::import`]. They are saved in a transaction of the
+    /// store, so that none is kept when one of them is not valid or fails to be saved.
+    ///
+    /// Returns the number of imported passkeys.
+    pub async fn import_passkeys<S>(&self, store: &mut S) -> Result<usize, ExchangeError>
+    where
+        S: CredentialStore + Send,
+    {
+        store.begin_transaction().await?;
+        let imported = async {
+            let mut imported = 0;
+            for item in &self.items {
+                let created_at = item
+                    .creation_at
+                    .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+                for credential in &item.credentials {
+                    let Credential::Passkey(credential) = credential else {
+                        continue;
+                    };
+                    let (passkey, user) = credential.import(created_at)?;
+                    let rp = PublicKeyCredentialRpEntity {
+                        id: passkey.rp_id.clone(),
+                        name: None,
+                    };
+                    store.save_credential(passkey, user, rp).await?;
+                    imported += 1;
+                }
+            }
+            Ok(imported)
+        }
+        .await;
+        match imported {
+            Ok(imported) => {
+                store.commit_transaction().await?;
+                Ok(imported)
+            }
+            Err(e) => {
+                store.rollback_transaction().await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// The seconds since the Unix epoch of the given `time`, `None` before it.
+fn to_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use passkey_types::rand::random_vec;
+
+    use super::*;
+    use crate::{
+        credential_store::{self, tests::TransactionalStore},
+        KeyProvider, MemoryStore, SoftwareKeyProvider,
+    };
+
+    /// A credential of "example.com" with a new key of the given `algorithm`.
+    fn passkey(algorithm: iana::Algorithm) -> Passkey {
+        let key = SoftwareKeyProvider
+            .generate_key(algorithm, &mut rand::thread_rng())
+            .unwrap()
+            .into_parts()
+            .1;
+        let mut passkey = credential_store::tests::passkey(&random_vec(16));
+        passkey.key = key;
+        passkey.counter = Some(7);
+        passkey.third_party_payment = algorithm == iana::Algorithm::EdDSA;
+        passkey.created_at = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        passkey
+    }
+
+    #[tokio::test]
+    async fn passkeys_are_exported_and_imported() {
+        let mut store = MemoryStore::new();
+        let passkeys = [
+            passkey(iana::Algorithm::ES256),
+            passkey(iana::Algorithm::EdDSA),
+        ];
+        for passkey in passkeys.iter().cloned() {
+            let user = PublicKeyCredentialUserEntity {
+                id: passkey.user_handle.clone().unwrap(),
+                name: Some("appleseed".into()),
+                display_name: Some("Wendy Appleseed".into()),
+                icon_url: None,
+            };
+            let rp = PublicKeyCredentialRpEntity {
+                id: passkey.rp_id.clone(),
+                name: None,
+            };
+            store.save_credential(passkey, user, rp).await.unwrap();
+        }
+        // Non-discoverable credentials are not exported.
+        let mut non_discoverable = passkey(iana::Algorithm::ES256);
+        non_discoverable.user_handle = None;
+        store.insert(
+            non_discoverable.credential_id.to_vec(),
+            non_discoverable.into(),
+        );
+
+        let mut header = Header::new("exporter.example.com", "Exporter", SystemTime::now());
+        let mut account = Account::new(b"account", "appleseed", "wendy@example.com");
+        assert_eq!(account.export_passkeys(&store).await, Ok(2));
+        header.accounts.push(account);
+
+        let json = serde_json::to_string(&header).unwrap();
+        let header: Header = serde_json::from_str(&json).unwrap();
+        assert_eq!(header.version, CXF_VERSION);
+
+        let mut imported = MemoryStore::new();
+        assert_eq!(
+            header.accounts[0].import_passkeys(&mut imported).await,
+            Ok(2)
+        );
+        for passkey in &passkeys {
+            let credential = &imported[passkey.credential_id.as_slice()];
+            assert_eq!(credential.passkey.rp_id, passkey.rp_id);
+            assert_eq!(credential.passkey.user_handle, passkey.user_handle);
+            assert_eq!(credential.passkey.created_at, passkey.created_at);
+            assert_eq!(
+                credential.passkey.third_party_payment,
+                passkey.third_party_payment
+            );
+            assert_eq!(credential.user.name.as_deref(), Some("appleseed"));
+            assert_eq!(
+                credential.user.display_name.as_deref(),
+                Some("Wendy Appleseed")
+            );
+            assert_eq!(
+                pkcs8_from_cose_key(&credential.passkey.key),
+                pkcs8_from_cose_key(&passkey.key)
+            );
+            // The imported CredRandoms are those the original credential derives.
+            for uv in [true, false] {
+                assert_eq!(
+                    cred_random(&credential.passkey, uv, HmacSecretUvPolicy::Separate),
+                    cred_random(passkey, uv, HmacSecretUvPolicy::Separate)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn other_credentials_are_ignored() {
+        let passkey = passkey(iana::Algorithm::ES256);
+        let user = PublicKeyCredentialUserEntity {
+            id: passkey.user_handle.clone().unwrap(),
+            name: None,
+            display_name: None,
+            icon_url: None,
+        };
+        let credential = serde_json::to_value(Credential::Passkey(Box::new(
+            PasskeyCredential::export(&passkey, &user).unwrap(),
+        )))
+        .unwrap();
+        let account: Account = serde_json::from_value(serde_json::json!({
+            "id": "YWNjb3VudA",
+            "username": "appleseed",
+            "email": "wendy@example.com",
+            "items": [{
+                "id": "aXRlbQ",
+                "title": "example.com",
+                "favorite": true,
+                "credentials": [
+                    { "type": "basic-auth", "username": { "fieldType": "string", "value": "wendy" } },
+                    credential,
+                ],
+            }],
+        }))
+        .unwrap();
+        assert_eq!(account.items[0].credentials[0], Credential::Unsupported);
+
+        let mut store = MemoryStore::new();
+        assert_eq!(account.import_passkeys(&mut store).await, Ok(1));
+        let imported = &store[passkey.credential_id.as_slice()];
+        assert_eq!(imported.passkey.created_at, None);
+        assert_eq!(imported.user.name, None);
+    }
+
+    #[tokio::test]
+    async fn invalid_passkeys_are_not_imported() {
+        let mut store = MemoryStore::new();
+        store
+            .save_credential(
+                passkey(iana::Algorithm::ES256),
+                PublicKeyCredentialUserEntity {
+                    id: random_vec(16).into(),
+                    name: None,
+                    display_name: None,
+                    icon_url: None,
+                },
+                PublicKeyCredentialRpEntity {
+                    id: "example.com".into(),
+                    name: None,
+                },
+            )
+            .await
+            .unwrap();
+        let mut account = Account::new(b"account", "appleseed", "wendy@example.com");
+        account.export_passkeys(&store).await.unwrap();
+        let mut invalid = account.items[0].clone();
+        let Credential::Passkey(credential) = &mut invalid.credentials[0] else {
+            unreachable!("exported a passkey");
+        };
+        credential.key = encoding::base64url(b"not a PKCS#8 document");
+        account.items.push(invalid);
+
+        let mut store = TransactionalStore::default();
+        assert_eq!(
+            account.import_passkeys(&mut store).await,
+            Err(ExchangeError::InvalidPasskey)
+        );
+        assert!(store.credentials.is_empty());
+        assert_eq!(store.rollbacks, 1);
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use passkey_types::{webauthn::PublicKeyCredentialDescriptor, Passkey};
+
+#[cfg(doc)]
+use crate::CredentialStore;
+
+/// An index of the credential IDs of an allow list, with which a [`CredentialStore`] matches an
+/// allow list of any length against its credentials without comparing every credential to every
+/// descriptor, following the semantics of [`CredentialStore::find_credentials`].
+///
+/// Descriptors listed more than once are indexed at their first position.
+#[derive(Debug, Clone)]
+pub struct DescriptorIndex<'a> {
+    positions: HashMap<&'a [u8], usize>,
+    ids: Vec<&'a [u8]>,
+}
+
+impl<'a> DescriptorIndex<'a> {
+    /// Index the credential IDs of the `descriptors` of an allow list.
+    pub fn new(descriptors: &'a [PublicKeyCredentialDescriptor]) -> Self {
+        let mut positions = HashMap::with_capacity(descriptors.len());
+        let mut ids = Vec::with_capacity(descriptors.len());
+        for descriptor in descriptors {
+            let id = descriptor.id.as_slice();
+            positions.entry(id).or_insert_with(|| {
+                ids.push(id);
+                ids.len() - 1
+            });
+        }
+        Self { positions, ids }
+    }
+
+    /// The number of distinct credential IDs in the allow list.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the allow list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The distinct credential IDs of the allow list, in its order, for example to look them all
+    /// up in a single query to the storage.
+    pub fn ids(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        self.ids.iter().copied()
+    }
+
+    /// Whether the credential with the given ID is allowed.
+    pub fn contains(&self, credential_id: &[u8]) -> bool {
+        self.positions.contains_key(credential_id)
+    }
+
+    /// The position in the allow list of the credential with the given ID, among distinct IDs.
+    pub fn position(&self, credential_id: &[u8]) -> Option<usize> {
+        self.positions.get(credential_id).copied()
+    }
+
+    /// The `credentials` of the RP with the given `rp_id` which are allowed, once each, in the order
+    /// of the allow list.
+    pub fn select<'p>(
+        &self,
+        rp_id: &str,
+        credentials: impl IntoIterator<Item = &'p Passkey>,
+    ) -> Vec<&'p Passkey> {
+        let mut selected: Vec<Option<&Passkey>> = vec![None; self.ids.len()];
+        for passkey in credentials {
+            if passkey.rp_id != rp_id {
+                continue;
+            }
+            if let Some(position) = self.position(&passkey.credential_id) {
+                selected[position].get_or_insert(passkey);
+            }
+        }
+        selected.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use passkey_types::webauthn::PublicKeyCredentialType;
+
+    use super::*;
+    use crate::credential_store::tests::passkey;
+
+    fn descriptor(id: u16) -> PublicKeyCredentialDescriptor {
+        PublicKeyCredentialDescriptor {
+            ty: PublicKeyCredentialType::PublicKey,
+            id: id.to_be_bytes().to_vec().into(),
+            transports: None,
+        }
+    }
+
+    #[test]
+    fn allowed_credentials_are_selected_in_order() {
+        // Every third credential is allowed, in reverse order and listed twice.
+        let allow_list: Vec<_> = (0..1000)
+            .rev()
+            .filter(|id| id % 3 == 0)
+            .chain([999, 0])
+            .map(descriptor)
+            .collect();
+        let index = DescriptorIndex::new(&allow_list);
+        assert_eq!(index.len(), 334);
+        assert_eq!(index.ids().next(), Some([0x03, 0xe7].as_slice()));
+        assert!(index.contains(&3u16.to_be_bytes()));
+        assert!(!index.contains(&4u16.to_be_bytes()));
+        assert_eq!(index.position(&0u16.to_be_bytes()), Some(333));
+
+        let mut other_rp = passkey(&3u16.to_be_bytes());
+        other_rp.rp_id = "example.org".into();
+        let credentials: Vec<_> = (0..1000u16)
+            .map(|id| passkey(&id.to_be_bytes()))
+            .chain([other_rp, passkey(&6u16.to_be_bytes())])
+            .collect();
+        let selected: Vec<_> = index
+            .select("example.com", &credentials)
+            .into_iter()
+            .map(|passkey| u16::from_be_bytes(passkey.credential_id[..].try_into().unwrap()))
+            .collect();
+        let expected: Vec<_> = (0..1000).rev().filter(|id| id % 3 == 0).collect();
+        assert_eq!(selected, expected);
+        // The first credential with a given ID is selected.
+        assert!(std::ptr::eq(
+            index.select("example.com", &credentials)[expected.len() - 3],
+            &credentials[6]
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use coset::CoseKey;
+use passkey_types::{crypto::zeroize_cose_key, ctap2::StatusCode};
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// The device keys of the `devicePubKey` extension, which must survive restarts of an
+/// [`Authenticator`] but never leave the device, even when the credentials themselves are synced.
+#[derive(Default, Clone, PartialEq)]
+pub struct StoredDeviceKeys {
+    /// The private device key of every credential which was used with the extension, by
+    /// credential ID.
+    pub keys: HashMap<Vec<u8>, CoseKey>,
+}
+
+impl Debug for StoredDeviceKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StoredDeviceKeys")
+            .field("credentials", &self.keys.len())
+            .finish()
+    }
+}
+
+impl Drop for StoredDeviceKeys {
+    fn drop(&mut self) {
+        self.keys.values_mut().for_each(zeroize_cose_key);
+    }
+}
+
+/// Use this on a type that persists the [`StoredDeviceKeys`] of an [`Authenticator`].
+///
+/// The keys are loaded once when the store is given to the authenticator and saved every time they
+/// change: when a device key is generated for a credential, and when a credential is deleted along
+/// with its device key.
+pub trait DeviceKeyStore {
+    /// Load the persisted device keys, `None` if they were never saved.
+    fn load(&self) -> Option<StoredDeviceKeys>;
+
+    /// Persist the new device keys. An error aborts the operation which caused the change.
+    fn save(&mut self, device_keys: &StoredDeviceKeys) -> Result<(), StatusCode>;
+}
+
+/// In-memory device key store, the default of an [`Authenticator`]. The device keys are forgotten
+/// along with it.
+impl DeviceKeyStore for Option<StoredDeviceKeys> {
+    fn load(&self) -> Option<StoredDeviceKeys> {
+        self.clone()
+    }
+
+    fn save(&mut self, device_keys: &StoredDeviceKeys) -> Result<(), StatusCode> {
+        self.replace(device_keys.clone());
+        Ok(())
+    }
+}
+
+impl<S: DeviceKeyStore> DeviceKeyStore for Arc<Mutex<S>> {
+    fn load(&self) -> Option<StoredDeviceKeys> {
+        self.lock().unwrap_or_else(PoisonError::into_inner).load()
+    }
+
+    fn save(&mut self, device_keys: &StoredDeviceKeys) -> Result<(), StatusCode> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .save(device_keys)
+    }
+}
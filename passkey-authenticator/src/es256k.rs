@@ -0,0 +1,100 @@
+//! Support for ECDSA over secp256k1 (ES256K), enabled with the `es256k` feature.
+
+use coset::{
+    iana::{self, EnumI64},
+    CoseKey, CoseKeyBuilder,
+};
+use k256::{
+    ecdsa::{
+        signature::{RandomizedSigner, Signer},
+        Signature, SigningKey,
+    },
+    SecretKey,
+};
+use passkey_types::ctap2::Ctap2Error;
+use rand_core::CryptoRngCore;
+use zeroize::Zeroizing;
+
+use crate::CoseKeyPair;
+
+/// Extract a secp256k1 secret key from an EC2 [`CoseKey`].
+fn secret_key_from_cose_key(key: &CoseKey) -> Result<SecretKey, Ctap2Error> {
+    key.params
+        .iter()
+        .find(|(k, _)| k == &coset::Label::Int(iana::Ec2KeyParameter::D.to_i64()))
+        .and_then(|(_, v)| v.as_bytes())
+        .and_then(|d| SecretKey::from_slice(d).ok())
+        .ok_or(Ctap2Error::InvalidCredential)
+}
+
+/// Sign `data` with a secp256k1 [`CoseKey`], returning a DER encoded signature.
+pub(crate) fn sign(
+    key: &CoseKey,
+    data: &[u8],
+    rng: Option<&mut dyn CryptoRngCore>,
+) -> Result<Vec<u8>, Ctap2Error> {
+    let signing_key = SigningKey::from(secret_key_from_cose_key(key)?);
+    let signature: Signature = match rng {
+        Some(mut rng) => signing_key.sign_with_rng(&mut rng, data),
+        None => signing_key.sign(data),
+    };
+    Ok(signature.to_der().to_bytes().to_vec())
+}
+
+impl CoseKeyPair {
+    /// Encode a secp256k1 `private_key` as an ES256K key pair.
+    pub fn from_k256_secret_key(private_key: &SecretKey) -> Self {
+        let public_key = SigningKey::from(private_key)
+            .verifying_key()
+            .to_encoded_point(false);
+        // SAFETY: These unwraps are safe because the public_key above is not compressed (false
+        // parameter) therefore x and y are guarateed to contain values.
+        let x = public_key.x().unwrap().to_vec();
+        let y = public_key.y().unwrap().to_vec();
+        let d: Zeroizing<[u8; 32]> = Zeroizing::new(private_key.to_bytes().into());
+        let private = CoseKeyBuilder::new_ec2_priv_key(
+            iana::EllipticCurve::Secp256k1,
+            x.clone(),
+            y.clone(),
+            d.to_vec(),
+        )
+        .algorithm(iana::Algorithm::ES256K)
+        .build();
+        let public = CoseKeyBuilder::new_ec2_pub_key(iana::EllipticCurve::Secp256k1, x, y)
+            .algorithm(iana::Algorithm::ES256K)
+            .build();
+
+        Self { public, private }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use k256::ecdsa::{signature::Verifier, Signature, SigningKey};
+    use passkey_types::rand::random_vec;
+
+    use crate::{public_key_der_from_cose_key, KeyProvider, SoftwareKeyProvider};
+
+    #[test]
+    fn es256k_sign_and_verify() {
+        let key_pair = SoftwareKeyProvider
+            .generate_key(iana::Algorithm::ES256K, &mut rand::rngs::OsRng)
+            .expect("could not generate ES256K key");
+
+        let data = random_vec(64);
+        let signature = SoftwareKeyProvider
+            .sign(&key_pair.private, &data, None)
+            .expect("failed to sign");
+        let signature = Signature::from_der(&signature).expect("not a DER signature");
+
+        let signing_key =
+            SigningKey::from(super::secret_key_from_cose_key(&key_pair.private).unwrap());
+        signing_key
+            .verifying_key()
+            .verify(&data, &signature)
+            .expect("failed to verify signature");
+
+        public_key_der_from_cose_key(&key_pair.public).expect("could not encode public key");
+    }
+}
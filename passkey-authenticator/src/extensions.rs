@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+
+use ciborium::value::Value;
+use passkey_types::{
+    ctap2::{DevicePublicKeyOutput, Flags, StatusCode, UnsignedExtensionOutputs},
+    webauthn::AuthenticationExtensionsClientInputs,
+};
+
+use crate::{Authenticator, CredentialStore, UserValidationMethod};
+
+/// Use this on a type implementing a custom extension, such as a vendor extension, which the
+/// [`Authenticator`] does not support itself.
+///
+/// The handler receives the raw CBOR input of its extension, as found in the
+/// [`AuthenticationExtensionsClientInputs::unknown_keys`] of a request, and is only called when the
+/// request includes the extension. Extensions which the authenticator recognizes, such as
+/// `hmac-secret`, are never passed to handlers.
+#[async_trait::async_trait]
+pub trait ExtensionHandler {
+    /// Process the extension during `authenticatorMakeCredential`, once the new credential is
+    /// generated and before it is attested.
+    async fn make_credential(
+        &self,
+        input: &Value,
+        context: &ExtensionContext<'_>,
+    ) -> Result<ExtensionOutput, StatusCode> {
+        let _ = (input, context);
+        Ok(ExtensionOutput::default())
+    }
+
+    /// Process the extension during `authenticatorGetAssertion`, once the credential is selected
+    /// and the user checked, before the assertion is signed.
+    async fn get_assertion(
+        &self,
+        input: &Value,
+        context: &ExtensionContext<'_>,
+    ) -> Result<ExtensionOutput, StatusCode> {
+        let _ = (input, context);
+        Ok(ExtensionOutput::default())
+    }
+}
+
+/// The operation an [`ExtensionHandler`] is called for.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtensionContext<'a> {
+    /// The RP ID of the operation.
+    pub rp_id: &'a str,
+    /// The ID of the created or asserted credential.
+    pub credential_id: &'a [u8],
+    /// The flags of the authenticator data, telling whether the user was present and verified.
+    pub flags: Flags,
+}
+
+/// The outputs of an extension, either of which may be left out.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExtensionOutput {
+    /// The output included in the extensions of the authenticator data, which are signed.
+    pub signed: Option<Value>,
+    /// The output returned along with the authenticator data in the unsigned extension outputs.
+    pub unsigned: Option<Value>,
+}
+
+/// An extension which the [`Authenticator`] supports itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    /// `hmac-secret`, from which WebAuthn's `prf` extension is built.
+    HmacSecret,
+    /// `hmac-secret-mc`, which computes the `hmac-secret` outputs of a new credential during its
+    /// creation. It requires [`Extension::HmacSecret`].
+    HmacSecretMc,
+    /// `largeBlobKey`, from which WebAuthn's `largeBlob` extension is built along with the
+    /// `authenticatorLargeBlobs` command.
+    LargeBlobKey,
+    /// `devicePubKey`, a key pair which stays on the device even when the credential is synced.
+    DevicePubKey,
+    /// `thirdPartyPayment`, which enables credentials for Secure Payment Confirmation.
+    ThirdPartyPayment,
+}
+
+impl Extension {
+    /// Every extension supported by the [`Authenticator`].
+    pub const ALL: [Self; 5] = [
+        Self::HmacSecret,
+        Self::HmacSecretMc,
+        Self::LargeBlobKey,
+        Self::DevicePubKey,
+        Self::ThirdPartyPayment,
+    ];
+
+    /// The identifier of the extension, as reported in `authenticatorGetInfo`.
+    pub fn identifier(self) -> &'static str {
+        match self {
+            Self::HmacSecret => "hmac-secret",
+            Self::HmacSecretMc => "hmac-secret-mc",
+            Self::LargeBlobKey => "largeBlobKey",
+            Self::DevicePubKey => "devicePubKey",
+            Self::ThirdPartyPayment => "thirdPartyPayment",
+        }
+    }
+}
+
+/// The outputs of every extension of an operation, by extension identifier.
+#[derive(Debug, Default)]
+pub(crate) struct ExtensionOutputs {
+    pub signed: Vec<(Value, Value)>,
+    pub unsigned: Vec<(String, Value)>,
+}
+
+impl ExtensionOutputs {
+    /// The unsigned extension outputs of an operation, given the `devicePubKey` output if there
+    /// is one, or `None` if there are no unsigned outputs at all.
+    pub(crate) fn unsigned_extension_outputs(
+        self,
+        device_pub_key: Option<DevicePublicKeyOutput>,
+    ) -> Option<UnsignedExtensionOutputs> {
+        (device_pub_key.is_some() || !self.unsigned.is_empty()).then(|| UnsignedExtensionOutputs {
+            device_pub_key,
+            unknown_keys: self.unsigned.into_iter().collect(),
+        })
+    }
+}
+
+/// The extensions of an [`Authenticator`]: the enabled built-in ones, and the custom ones by
+/// identifier, in the order they were registered.
+pub(crate) struct ExtensionRegistry {
+    builtins: Vec<Extension>,
+    handlers: Vec<(String, Box<dyn ExtensionHandler + Send + Sync>)>,
+}
+
+impl Default for ExtensionRegistry {
+    fn default() -> Self {
+        Self {
+            builtins: Extension::ALL.to_vec(),
+            handlers: Vec::new(),
+        }
+    }
+}
+
+impl ExtensionRegistry {
+    /// Enable only the given built-in extensions.
+    pub(crate) fn set_builtins(&mut self, extensions: impl IntoIterator<Item = Extension>) {
+        self.builtins = Extension::ALL.to_vec();
+        let enabled: Vec<_> = extensions.into_iter().collect();
+        self.builtins
+            .retain(|extension| enabled.contains(extension));
+    }
+
+    /// Whether the built-in `extension` is enabled.
+    pub(crate) fn is_enabled(&self, extension: Extension) -> bool {
+        match extension {
+            Extension::HmacSecretMc => {
+                self.builtins.contains(&extension) && self.builtins.contains(&Extension::HmacSecret)
+            }
+            _ => self.builtins.contains(&extension),
+        }
+    }
+
+    /// The identifiers of every extension, built-in ones first, as reported in
+    /// `authenticatorGetInfo`.
+    pub(crate) fn identifiers(&self) -> Vec<Cow<'static, str>> {
+        self.builtins
+            .iter()
+            .filter(|extension| self.is_enabled(**extension))
+            .map(|extension| extension.identifier().into())
+            .chain(self.handlers.iter().map(|(id, _)| id.clone().into()))
+            .collect()
+    }
+
+    /// Drop the inputs of the built-in extensions which are disabled, so they are processed like
+    /// extensions which the authenticator does not support.
+    pub(crate) fn remove_disabled(&self, inputs: &mut AuthenticationExtensionsClientInputs) {
+        if !self.is_enabled(Extension::HmacSecret) {
+            inputs.hmac_secret = None;
+        }
+        if !self.is_enabled(Extension::HmacSecretMc) {
+            inputs.hmac_secret_mc = None;
+        }
+        if !self.is_enabled(Extension::LargeBlobKey) {
+            inputs.large_blob_key = None;
+        }
+        if !self.is_enabled(Extension::DevicePubKey) {
+            inputs.device_pub_key = None;
+        }
+        if !self.is_enabled(Extension::ThirdPartyPayment) {
+            inputs.third_party_payment = None;
+            inputs.payment = None;
+        }
+    }
+
+    /// Register the `handler` of the extension with the given `identifier`, replacing the handler
+    /// previously registered for it.
+    pub(crate) fn register(
+        &mut self,
+        identifier: String,
+        handler: Box<dyn ExtensionHandler + Send + Sync>,
+    ) {
+        match self.handlers.iter_mut().find(|(id, _)| *id == identifier) {
+            Some((_, registered)) => *registered = handler,
+            None => self.handlers.push((identifier, handler)),
+        }
+    }
+
+    /// Process the `inputs` of the registered extensions during `authenticatorMakeCredential`, or
+    /// `authenticatorGetAssertion` when `assertion` is set. Inputs of extensions which are not
+    /// registered are ignored.
+    pub(crate) async fn process<'a>(
+        &self,
+        inputs: impl IntoIterator<Item = (&'a String, &'a Value)>,
+        context: &ExtensionContext<'_>,
+        assertion: bool,
+    ) -> Result<ExtensionOutputs, StatusCode> {
+        let mut outputs = ExtensionOutputs::default();
+        for (identifier, input) in inputs {
+            let Some((_, handler)) = self.handlers.iter().find(|(id, _)| id == identifier) else {
+                continue;
+            };
+            let output = if assertion {
+                handler.get_assertion(input, context).await?
+            } else {
+                handler.make_credential(input, context).await?
+            };
+            if let Some(signed) = output.signed {
+                outputs
+                    .signed
+                    .push((Value::Text(identifier.clone()), signed));
+            }
+            if let Some(unsigned) = output.unsigned {
+                outputs.unsigned.push((identifier.clone(), unsigned));
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+impl<S: CredentialStore, U: UserValidationMethod> Authenticator<S, U> {
+    /// Process the inputs of the custom extensions registered with
+    /// [`Authenticator::with_extension`] which are included in `extensions`.
+    pub(crate) async fn custom_extension_outputs(
+        &self,
+        extensions: Option<&AuthenticationExtensionsClientInputs>,
+        context: &ExtensionContext<'_>,
+        assertion: bool,
+    ) -> Result<ExtensionOutputs, StatusCode> {
+        match extensions {
+            Some(extensions) => {
+                self.extensions()
+                    .process(&extensions.unknown_keys, context, assertion)
+                    .await
+            }
+            None => Ok(ExtensionOutputs::default()),
+        }
+    }
+}
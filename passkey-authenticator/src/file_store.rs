@@ -0,0 +1,838 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use coset::{CborSerializable, CoseKey};
+use passkey_types::{
+    ctap2::{
+        make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+        Ctap2Error, StatusCode, U2FError,
+    },
+    webauthn::PublicKeyCredentialDescriptor,
+    Bytes, CredRandoms, Passkey,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{CredentialStore, DescriptorIndex, DiscoverableCredential, StoredCredential};
+
+/// Length of the random nonce prepended to every encrypted record.
+const NONCE_LEN: usize = 12;
+
+/// Length of the big-endian length prepended to every record.
+const LENGTH_LEN: usize = 4;
+
+/// Length of the authentication tag appended to every encrypted record.
+const TAG_LEN: usize = 16;
+
+/// Authenticated along with every record, so that records encrypted for another purpose with the
+/// same key are refused.
+const RECORD_AAD: &[u8] = b"passkey-authenticator encrypted file store v1";
+
+/// A credential store persisted to a single file, encrypted with AES-256-GCM under a key supplied
+/// by the caller, for CLI tools and soft tokens.
+///
+/// Every credential is saved as its own encrypted record, along with the user and RP it was saved
+/// with. New credentials are appended to the file, and a credential saved again replaces the one
+/// read before it, so the credentials whose last use is updated are appended again as well.
+/// Otherwise updating or deleting credentials rewrites the file atomically, compacting it: the
+/// records are written to a temporary file next to it, which then replaces it. The file is also
+/// compacted once the records replaced by appended ones outnumber the credentials. Transactions
+/// are supported, in which case the file is only rewritten when the transaction is committed.
+///
+/// The whole store is kept in memory and the file is only read by [`EncryptedFileStore::open`],
+/// so the file must not be shared by several stores at the same time.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    credentials: HashMap<Vec<u8>, StoredCredential>,
+    /// The credentials as they were when the current transaction began.
+    transaction: Option<HashMap<Vec<u8>, StoredCredential>>,
+    /// The number of records in the file which were replaced by records appended after them.
+    superseded: usize,
+}
+
+impl EncryptedFileStore {
+    /// Open the store in the file at `path`, decrypting its credentials with `key`. The file is
+    /// created when the first credential is saved if it does not exist yet.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if a record of the file cannot be decrypted with
+    /// `key`. A truncated last record, left by an append which was interrupted, is ignored and
+    /// removed from the file, so that the records appended after it can be read. This is only
+    /// done when the rest of the file is too short to hold a whole record, otherwise the length
+    /// of a record was corrupted and opening fails without touching the file.
+    pub fn open(path: impl Into<PathBuf>, key: [u8; 32]) -> io::Result<Self> {
+        let path = path.into();
+        let cipher = Aes256Gcm::new(&key.into());
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut credentials = HashMap::new();
+        let mut superseded = 0;
+        let mut rest = contents.as_slice();
+        while let Some((record, next)) = split_record(rest) {
+            let credential = decrypt_record(&cipher, record)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid record"))?;
+            if credentials
+                .insert(credential.passkey.credential_id.to_vec(), credential)
+                .is_some()
+            {
+                superseded += 1;
+            }
+            rest = next;
+        }
+        if rest.len() >= LENGTH_LEN + min_record_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid record length",
+            ));
+        }
+        if !rest.is_empty() {
+            log::warn!(
+                "Removing a truncated record at the end of {}",
+                path.display()
+            );
+            let length = u64::try_from(contents.len() - rest.len())
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+            let file = OpenOptions::new().write(true).open(&path)?;
+            file.set_len(length)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            path,
+            cipher,
+            credentials,
+            transaction: None,
+            superseded,
+        })
+    }
+
+    /// The path of the file of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of credentials in the store.
+    pub fn len(&self) -> usize {
+        self.credentials.len()
+    }
+
+    /// Whether the store has no credentials.
+    pub fn is_empty(&self) -> bool {
+        self.credentials.is_empty()
+    }
+
+    /// Encrypt `credential` into a record, prepended with its length.
+    fn encrypt_record(&self, credential: &StoredCredential) -> Result<Vec<u8>, StatusCode> {
+        let mut plaintext = Zeroizing::new(Vec::new());
+        ciborium::ser::into_writer(&Record::from(credential), &mut *plaintext)
+            .map_err(|_| U2FError::Other)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: RECORD_AAD,
+                },
+            )
+            .map_err(|_| U2FError::Other)?;
+
+        let length = u32::try_from(NONCE_LEN + ciphertext.len()).map_err(|_| U2FError::Other)?;
+        let mut record = length.to_be_bytes().to_vec();
+        record.extend(nonce);
+        record.extend(ciphertext);
+        Ok(record)
+    }
+
+    /// Save `credential`, appending it to the file unless a transaction is in progress. The file
+    /// is compacted instead once it holds more replaced records than credentials.
+    fn append(&mut self, credential: StoredCredential) -> Result<(), StatusCode> {
+        let credential_id = credential.passkey.credential_id.to_vec();
+        let replaces = self.credentials.contains_key(&credential_id);
+        if self.transaction.is_some() {
+            self.credentials.insert(credential_id, credential);
+            return Ok(());
+        }
+        if replaces && self.superseded >= self.credentials.len() {
+            return self.update(|credentials| {
+                credentials.insert(credential_id, credential);
+                Ok(())
+            });
+        }
+
+        let record = self.encrypt_record(&credential)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(&record)?;
+                file.sync_data()
+            })
+            .map_err(|_| U2FError::Other)?;
+        self.credentials.insert(credential_id, credential);
+        if replaces {
+            self.superseded += 1;
+        }
+        Ok(())
+    }
+
+    /// Change the credentials with `change`, rewriting the file with the result unless a
+    /// transaction is in progress. The credentials are left unchanged if either fails.
+    fn update(
+        &mut self,
+        change: impl FnOnce(&mut HashMap<Vec<u8>, StoredCredential>) -> Result<(), StatusCode>,
+    ) -> Result<(), StatusCode> {
+        let mut credentials = self.credentials.clone();
+        change(&mut credentials)?;
+        if self.transaction.is_none() {
+            self.rewrite(&credentials)?;
+            self.superseded = 0;
+        }
+        self.credentials = credentials;
+        Ok(())
+    }
+
+    /// Replace the file with one holding only `credentials`, written to a temporary file first so
+    /// that the file is never left partially written.
+    fn rewrite(&self, credentials: &HashMap<Vec<u8>, StoredCredential>) -> Result<(), StatusCode> {
+        let mut contents = Vec::new();
+        for credential in credentials.values() {
+            contents.extend(self.encrypt_record(credential)?);
+        }
+
+        let mut temporary_name = self
+            .path
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+        temporary_name.push(".tmp");
+        let temporary_path = self.path.with_file_name(temporary_name);
+        File::create(&temporary_path)
+            .and_then(|mut file| {
+                file.write_all(&contents)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary_path, &self.path))
+            .map_err(|_| {
+                let _ = fs::remove_file(&temporary_path);
+                U2FError::Other.into()
+            })
+    }
+
+    /// The credential with the given ID, or `CTAP2_ERR_NO_CREDENTIALS` if there is none.
+    fn credential_mut<'a>(
+        credentials: &'a mut HashMap<Vec<u8>, StoredCredential>,
+        credential_id: &[u8],
+    ) -> Result<&'a mut StoredCredential, StatusCode> {
+        credentials
+            .get_mut(credential_id)
+            .ok_or(Ctap2Error::NoCredentials.into())
+    }
+}
+
+/// Split the first record off `contents`, returning it without its length along with the records
+/// which follow it, or `None` if there is no complete record left.
+fn split_record(contents: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (length, rest) = contents.split_first_chunk::<LENGTH_LEN>()?;
+    let length = usize::try_from(u32::from_be_bytes(*length)).ok()?;
+    (rest.len() >= length).then(|| rest.split_at(length))
+}
+
+/// The length of the shortest record, without its length, that [`EncryptedFileStore`] writes.
+fn min_record_len() -> usize {
+    let record = Record {
+        key: Bytes::default(),
+        credential_id: Bytes::default(),
+        rp_id: String::new(),
+        user_handle: None,
+        counter: None,
+        third_party_payment: false,
+        backup_eligible: false,
+        backup_state: false,
+        cred_random_with_uv: None,
+        cred_random_without_uv: None,
+        created_at: None,
+        last_used_at: None,
+        user: PublicKeyCredentialUserEntity {
+            id: Bytes::default(),
+            name: None,
+            display_name: None,
+            icon_url: None,
+        },
+        rp: PublicKeyCredentialRpEntity {
+            id: String::new(),
+            name: None,
+        },
+    };
+    let mut plaintext = Vec::new();
+    // SAFETY: serializing into a vector only fails if allocating it does.
+    ciborium::ser::into_writer(&record, &mut plaintext).unwrap();
+    NONCE_LEN + plaintext.len() + TAG_LEN
+}
+
+/// Decrypt a `record` encrypted by [`EncryptedFileStore::encrypt_record`].
+fn decrypt_record(cipher: &Aes256Gcm, record: &[u8]) -> Option<StoredCredential> {
+    let (nonce, ciphertext) = record.split_first_chunk::<NONCE_LEN>()?;
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(
+                &Nonce::from(*nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: RECORD_AAD,
+                },
+            )
+            .ok()?,
+    );
+    let record: Record = ciborium::de::from_reader(plaintext.as_slice()).ok()?;
+    record.to_credential()
+}
+
+/// The milliseconds elapsed between the Unix epoch and `time`, if it is after it.
+fn to_millis(time: SystemTime) -> Option<u64> {
+    let elapsed = time.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(elapsed.as_millis()).ok()
+}
+
+/// A credential as it is encoded in CBOR in a record of an [`EncryptedFileStore`].
+///
+/// # PII considerations
+/// The private key and CredRandoms are zeroized when dropped.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    key: Bytes,
+    credential_id: Bytes,
+    rp_id: String,
+    user_handle: Option<Bytes>,
+    counter: Option<u32>,
+    third_party_payment: bool,
+    backup_eligible: bool,
+    backup_state: bool,
+    cred_random_with_uv: Option<Bytes>,
+    cred_random_without_uv: Option<Bytes>,
+    /// Milliseconds since the Unix epoch.
+    created_at: Option<u64>,
+    /// Milliseconds since the Unix epoch.
+    last_used_at: Option<u64>,
+    user: PublicKeyCredentialUserEntity,
+    rp: PublicKeyCredentialRpEntity,
+}
+
+impl From<&StoredCredential> for Record {
+    fn from(credential: &StoredCredential) -> Self {
+        let passkey = &credential.passkey;
+        let cred_randoms = passkey.cred_randoms.as_ref();
+        Self {
+            key: passkey.key.clone().to_vec().unwrap_or_default().into(),
+            credential_id: passkey.credential_id.clone(),
+            rp_id: passkey.rp_id.clone(),
+            user_handle: passkey.user_handle.clone(),
+            counter: passkey.counter,
+            third_party_payment: passkey.third_party_payment,
+            backup_eligible: passkey.backup_eligible,
+            backup_state: passkey.backup_state,
+            cred_random_with_uv: cred_randoms.map(|randoms| randoms.with_uv.to_vec().into()),
+            cred_random_without_uv: cred_randoms
+                .and_then(|randoms| randoms.without_uv)
+                .map(|without_uv| without_uv.to_vec().into()),
+            created_at: passkey.created_at.and_then(to_millis),
+            last_used_at: passkey.last_used_at.and_then(to_millis),
+            user: credential.user.clone(),
+            rp: credential.rp.clone(),
+        }
+    }
+}
+
+impl Record {
+    /// Rebuild the credential of this record, `None` if it is malformed.
+    fn to_credential(&self) -> Option<StoredCredential> {
+        let cred_randoms = match &self.cred_random_with_uv {
+            Some(with_uv) => Some(CredRandoms {
+                with_uv: with_uv.as_slice().try_into().ok()?,
+                without_uv: self
+                    .cred_random_without_uv
+                    .as_ref()
+                    .map(|without_uv| without_uv.as_slice().try_into())
+                    .transpose()
+                    .ok()?,
+            }),
+            None => None,
+        };
+        let from_millis = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        Some(StoredCredential {
+            passkey: Passkey {
+                key: CoseKey::from_slice(&self.key).ok()?,
+                credential_id: self.credential_id.clone(),
+                rp_id: self.rp_id.clone(),
+                user_handle: self.user_handle.clone(),
+                counter: self.counter,
+                third_party_payment: self.third_party_payment,
+                backup_eligible: self.backup_eligible,
+                backup_state: self.backup_state,
+                cred_randoms,
+                created_at: self.created_at.map(from_millis),
+                last_used_at: self.last_used_at.map(from_millis),
+            },
+            user: self.user.clone(),
+            rp: self.rp.clone(),
+        })
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.cred_random_with_uv
+            .iter_mut()
+            .chain(&mut self.cred_random_without_uv)
+            .for_each(|cred_random| cred_random.zeroize());
+    }
+}
+
+#[async_trait::async_trait]
+impl CredentialStore for EncryptedFileStore {
+    type PasskeyItem = Passkey;
+
+    /// Without `ids`, only the discoverable credentials of the RP are found.
+    async fn find_credentials(
+        &self,
+        ids: Option<&[PublicKeyCredentialDescriptor]>,
+        rp_id: &str,
+    ) -> Result<Vec<Self::PasskeyItem>, StatusCode> {
+        let found: Vec<&Passkey> = match ids {
+            Some(ids) => DescriptorIndex::new(ids)
+                .ids()
+                .filter_map(|id| self.credentials.get(id))
+                .map(|credential| &credential.passkey)
+                .collect(),
+            None => self
+                .credentials
+                .values()
+                .map(|credential| &credential.passkey)
+                .filter(|passkey| passkey.user_handle.is_some())
+                .collect(),
+        };
+        let creds: Vec<Passkey> = found
+            .into_iter()
+            .filter(|passkey| passkey.rp_id == rp_id)
+            .cloned()
+            .collect();
+        if creds.is_empty() {
+            Err(Ctap2Error::NoCredentials.into())
+        } else {
+            Ok(creds)
+        }
+    }
+
+    async fn save_credential(
+        &mut self,
+        cred: Passkey,
+        user: PublicKeyCredentialUserEntity,
+        rp: PublicKeyCredentialRpEntity,
+    ) -> Result<(), StatusCode> {
+        self.append(StoredCredential {
+            passkey: cred,
+            user,
+            rp,
+        })
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        Ok(self
+            .credentials
+            .values()
+            .map(|credential| &credential.passkey)
+            .filter(|passkey| rp_id.is_none_or(|rp_id| passkey.rp_id == rp_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        Ok(self
+            .credentials
+            .values()
+            .filter(|credential| credential.passkey.user_handle.is_some())
+            .cloned()
+            .collect())
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.credentials
+            .get(credential_id)
+            .map(|credential| credential.user.clone())
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.update(|credentials| {
+            credentials
+                .remove(credential_id)
+                .map(|_| ())
+                .ok_or(Ctap2Error::NoCredentials.into())
+        })
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.update(|credentials| {
+            Self::credential_mut(credentials, credential_id)?.user = user;
+            Ok(())
+        })
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.update(|credentials| {
+            let passkey = &mut Self::credential_mut(credentials, credential_id)?.passkey;
+            if backup_state && !passkey.backup_eligible {
+                return Err(Ctap2Error::NotAllowed.into());
+            }
+            passkey.backup_state = backup_state;
+            Ok(())
+        })
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        // Appending the updated credential is enough as it replaces the earlier one when the file
+        // is read, which saves rewriting the file on every assertion.
+        let mut credential = self
+            .credentials
+            .get(credential_id)
+            .cloned()
+            .ok_or(Ctap2Error::NoCredentials)?;
+        credential.passkey.last_used_at = Some(used_at);
+        self.append(credential)
+    }
+
+    async fn begin_transaction(&mut self) -> Result<(), StatusCode> {
+        if self.transaction.is_some() {
+            return Err(U2FError::InvalidSequence.into());
+        }
+        self.transaction = Some(self.credentials.clone());
+        Ok(())
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StatusCode> {
+        let snapshot = self.transaction.take().ok_or(U2FError::InvalidSequence)?;
+        if let Err(err) = self.rewrite(&self.credentials) {
+            // The changes of the transaction are lost along with the file write.
+            self.credentials = snapshot;
+            return Err(err);
+        }
+        self.superseded = 0;
+        Ok(())
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StatusCode> {
+        self.credentials = self.transaction.take().ok_or(U2FError::InvalidSequence)?;
+        Ok(())
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.update(|credentials| {
+            credentials.clear();
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use passkey_types::{
+        ctap2::{make_credential, Aaguid},
+        encoding,
+        rand::random_vec,
+        webauthn,
+    };
+
+    use super::*;
+    use crate::{user_validation::MockUserValidationMethod, Authenticator, MemoryStore};
+
+    /// A path in the temporary directory whose file is removed when dropped.
+    struct TemporaryPath(PathBuf);
+
+    impl TemporaryPath {
+        fn new() -> Self {
+            let name = format!("passkey-store-{}", encoding::base64url(&random_vec(8)));
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TemporaryPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn make_credential_request(rk: bool) -> make_credential::Request {
+        make_credential::Request {
+            client_data_hash: random_vec(32).into(),
+            rp: PublicKeyCredentialRpEntity {
+                id: "future.1password.com".into(),
+                name: Some("1password".into()),
+            },
+            user: webauthn::PublicKeyCredentialUserEntity {
+                id: random_vec(16).into(),
+                display_name: "wendy".into(),
+                name: "Appleseed".into(),
+            },
+            pub_key_cred_params: vec![webauthn::PublicKeyCredentialParameters {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                alg: iana::Algorithm::ES256,
+            }],
+            exclude_list: None,
+            extensions: None,
+            options: make_credential::Options {
+                rk,
+                up: true,
+                uv: true,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+            enterprise_attestation: None,
+        }
+    }
+
+    /// Create `count` discoverable credentials to save in a store.
+    async fn new_credentials(count: usize) -> Vec<StoredCredential> {
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            MockUserValidationMethod::verified_user(count),
+        );
+        for _ in 0..count {
+            authenticator
+                .make_credential(make_credential_request(true))
+                .await
+                .expect("failed to create credential");
+        }
+        authenticator.store().values().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn credentials_survive_reopening() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            store,
+            MockUserValidationMethod::verified_user(2),
+        );
+        for rk in [true, false] {
+            authenticator
+                .make_credential(make_credential_request(rk))
+                .await
+                .expect("failed to create credential");
+        }
+        let discoverable = authenticator
+            .store()
+            .discoverable_credentials()
+            .await
+            .unwrap();
+        assert_eq!(discoverable.len(), 1);
+        let credential_id = discoverable[0].passkey.credential_id.clone();
+
+        let mut reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 2);
+        let found = reopened
+            .find_credentials(None, "future.1password.com")
+            .await
+            .expect("missing discoverable credential");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].credential_id, credential_id);
+        // Times are saved to the millisecond.
+        let created_at = discoverable[0].passkey.created_at.and_then(to_millis);
+        assert_eq!(found[0].created_at.and_then(to_millis), created_at);
+        let listed = reopened.discoverable_credentials().await.unwrap();
+        assert_eq!(listed[0].user.name.as_deref(), Some("Appleseed"));
+
+        // Deleting rewrites the file without the credential.
+        reopened.delete_credential(&credential_id).await.unwrap();
+        let reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 1);
+        assert!(reopened
+            .find_credentials(None, "future.1password.com")
+            .await
+            .is_err());
+
+        // The file cannot be read without the key.
+        let err = EncryptedFileStore::open(&path.0, [8; 32])
+            .err()
+            .expect("opened with the wrong key");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn transactions_are_only_written_once_committed() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let mut store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let credentials = new_credentials(2).await;
+
+        store.begin_transaction().await.unwrap();
+        let first = credentials[0].clone();
+        store
+            .save_credential(first.passkey, first.user, first.rp)
+            .await
+            .unwrap();
+        assert!(!path.0.exists());
+        store.rollback_transaction().await.unwrap();
+        assert!(store.is_empty());
+
+        store.begin_transaction().await.unwrap();
+        for credential in credentials {
+            store
+                .save_credential(credential.passkey, credential.user, credential.rp)
+                .await
+                .unwrap();
+        }
+        store.commit_transaction().await.unwrap();
+        let reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn truncated_records_are_ignored() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let credentials = new_credentials(2).await;
+        let mut contents = store.encrypt_record(&credentials[0]).unwrap();
+        let second = store.encrypt_record(&credentials[1]).unwrap();
+        contents.extend_from_slice(&second[..min_record_len()]);
+        fs::write(&path.0, contents).unwrap();
+
+        let mut reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 1);
+
+        // The truncated record was removed, so the records appended after it can be read.
+        let second = credentials[1].clone();
+        reopened
+            .save_credential(second.passkey, second.user, second.rp)
+            .await
+            .unwrap();
+        let reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn corrupted_record_lengths_are_refused() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let credentials = new_credentials(2).await;
+        let mut contents = store.encrypt_record(&credentials[0]).unwrap();
+        contents.extend(store.encrypt_record(&credentials[1]).unwrap());
+        // The length of the first record now overruns the file.
+        contents[..LENGTH_LEN].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(&path.0, &contents).unwrap();
+
+        let err = EncryptedFileStore::open(&path.0, key)
+            .err()
+            .expect("opened a corrupted store");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path.0).unwrap(), contents);
+    }
+
+    #[tokio::test]
+    async fn last_use_is_appended() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let mut store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let credential = new_credentials(1).await.remove(0);
+        let credential_id = credential.passkey.credential_id.clone();
+        store
+            .save_credential(credential.passkey, credential.user, credential.rp)
+            .await
+            .unwrap();
+        let length = fs::metadata(&path.0).unwrap().len();
+
+        let used_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        store
+            .update_last_used(&credential_id, used_at)
+            .await
+            .unwrap();
+        let appended_length = fs::metadata(&path.0).unwrap().len();
+        assert!(appended_length > 2 * length);
+        let mut reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        assert_eq!(reopened.len(), 1);
+        let found = reopened.enumerate(None).await.unwrap();
+        assert_eq!(found[0].last_used_at, Some(used_at));
+
+        // The file is compacted instead once it holds as many replaced records as credentials.
+        reopened
+            .update_last_used(&credential_id, used_at + Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::metadata(&path.0).unwrap().len(),
+            appended_length - length
+        );
+        let reopened = EncryptedFileStore::open(&path.0, key).expect("failed to reopen store");
+        let found = reopened.enumerate(None).await.unwrap();
+        assert_eq!(
+            found[0].last_used_at,
+            Some(used_at + Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_compact_the_file() {
+        let path = TemporaryPath::new();
+        let key = [7; 32];
+        let mut store = EncryptedFileStore::open(&path.0, key).expect("failed to open store");
+        let credentials = new_credentials(2).await;
+        let credential_id = credentials[0].passkey.credential_id.clone();
+        for credential in credentials {
+            store
+                .save_credential(credential.passkey, credential.user, credential.rp)
+                .await
+                .unwrap();
+        }
+        store
+            .update_last_used(&credential_id, SystemTime::now())
+            .await
+            .unwrap();
+        let appended_length = fs::metadata(&path.0).unwrap().len();
+
+        // Other updates compact the file, after which the last use is appended again.
+        store.set_backup_state(&credential_id, false).await.unwrap();
+        let compacted_length = fs::metadata(&path.0).unwrap().len();
+        assert!(compacted_length < appended_length);
+        store
+            .update_last_used(&credential_id, SystemTime::now())
+            .await
+            .unwrap();
+        assert!(fs::metadata(&path.0).unwrap().len() > compacted_length);
+    }
+}
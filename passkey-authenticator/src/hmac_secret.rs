@@ -0,0 +1,213 @@
+use coset::{iana, iana::EnumI64, CoseKey, Label};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use passkey_types::{
+    ctap2::{StatusCode, U2FError},
+    Passkey,
+};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Length of a salt of the `hmac-secret` extension, and of each output secret.
+const SALT_LEN: usize = 32;
+
+const CRED_RANDOM_WITH_UV_INFO: &[u8] = b"passkey-rs hmac-secret credRandomWithUV";
+const CRED_RANDOM_WITHOUT_UV_INFO: &[u8] = b"passkey-rs hmac-secret credRandomWithoutUV";
+
+/// How the [`Authenticator`] uses the two CredRandoms of the `hmac-secret` extension, the one used
+/// when the user is verified and the one used when they are not.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HmacSecretConfig {
+    /// Which CredRandom is used depending on whether the user is verified.
+    pub uv_policy: HmacSecretUvPolicy,
+}
+
+/// Which CredRandom of the `hmac-secret` extension is used depending on whether the user is
+/// verified.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HmacSecretUvPolicy {
+    /// Use a different CredRandom depending on whether the user is verified, as specified by CTAP.
+    ///
+    /// Credentials imported with only the CredRandom used with user verification return no output
+    /// when the user is not verified, rather than one which differs from the authenticator they
+    /// were exported from.
+    #[default]
+    Separate,
+    /// Always use the CredRandom of user verification, so the outputs do not depend on whether the
+    /// user is verified. This matches authenticators which always verify the user.
+    Shared,
+}
+
+/// The CredRandom of the `hmac-secret` extension for `credential`, the one used when the user is
+/// verified if `uv` is set, following the `policy`.
+///
+/// The CredRandoms imported along with the credential are used when it has them. Otherwise,
+/// rather than being generated and stored along with the credential, the CredRandoms are derived
+/// with HKDF-SHA256 from the private key. This way they are available for credentials which the
+/// [`Authenticator`] does not store, and follow credentials which are synced. Returns `None` for
+/// keys without private key material, such as those held by an external
+/// [`KeyProvider`](crate::KeyProvider).
+pub(crate) fn cred_random(
+    credential: &Passkey,
+    uv: bool,
+    policy: HmacSecretUvPolicy,
+) -> Option<Zeroizing<[u8; 32]>> {
+    let uv = uv || policy == HmacSecretUvPolicy::Shared;
+    match &credential.cred_randoms {
+        Some(imported) if uv => Some(Zeroizing::new(imported.with_uv)),
+        Some(imported) => imported.without_uv.map(Zeroizing::new),
+        None => derive_cred_random(&credential.key, uv),
+    }
+}
+
+/// Derive the CredRandom of the credential with the private `key`, see [`cred_random`].
+fn derive_cred_random(key: &CoseKey, uv: bool) -> Option<Zeroizing<[u8; 32]>> {
+    // NB: EC2 and OKP keys share the label of their private key parameter.
+    let private_key = key
+        .params
+        .iter()
+        .find(|(label, _)| label == &Label::Int(iana::Ec2KeyParameter::D.to_i64()))
+        .and_then(|(_, value)| value.as_bytes())?;
+    let info = if uv {
+        CRED_RANDOM_WITH_UV_INFO
+    } else {
+        CRED_RANDOM_WITHOUT_UV_INFO
+    };
+    let mut cred_random = Zeroizing::new([0; 32]);
+    // SAFETY: HKDF-SHA-256 can output up to 255 * 32 bytes.
+    Hkdf::<Sha256>::new(None, private_key)
+        .expand(info, cred_random.as_mut())
+        .unwrap();
+    Some(cred_random)
+}
+
+/// The output secrets for one or two concatenated `salts`: the HMAC-SHA-256 of each salt with
+/// `cred_random`.
+pub(crate) fn output_secrets(
+    cred_random: &[u8],
+    salts: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+    if salts.len() != SALT_LEN && salts.len() != 2 * SALT_LEN {
+        return Err(U2FError::InvalidLength.into());
+    }
+    let outputs = salts
+        .chunks(SALT_LEN)
+        .flat_map(|salt| {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(cred_random)
+                .expect("HMAC can take a key of any size");
+            mac.update(salt);
+            mac.finalize().into_bytes()
+        })
+        .collect();
+    Ok(Zeroizing::new(outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana::Algorithm;
+
+    use passkey_types::CredRandoms;
+
+    use super::*;
+    use crate::{credential_store, KeyProvider, SoftwareKeyProvider};
+
+    fn passkey(key: CoseKey, cred_randoms: Option<CredRandoms>) -> Passkey {
+        let mut passkey = credential_store::tests::passkey(&[1; 16]);
+        passkey.key = key;
+        passkey.cred_randoms = cred_randoms;
+        passkey
+    }
+
+    #[test]
+    fn cred_randoms_are_derived_from_the_private_key() {
+        let mut rng = rand::thread_rng();
+        let (public, private) = SoftwareKeyProvider
+            .generate_key(Algorithm::ES256, &mut rng)
+            .unwrap()
+            .into_parts();
+        let private = passkey(private, None);
+        let public = passkey(public, None);
+
+        let with_uv =
+            cred_random(&private, true, HmacSecretUvPolicy::Separate).expect("missing CredRandom");
+        let without_uv =
+            cred_random(&private, false, HmacSecretUvPolicy::Separate).expect("missing CredRandom");
+        assert_ne!(with_uv, without_uv);
+        assert_eq!(
+            cred_random(&private, true, HmacSecretUvPolicy::Separate),
+            Some(with_uv.clone())
+        );
+        assert_eq!(
+            cred_random(&public, true, HmacSecretUvPolicy::Separate),
+            None
+        );
+
+        // The shared policy does not depend on user verification.
+        assert_eq!(
+            cred_random(&private, false, HmacSecretUvPolicy::Shared),
+            Some(with_uv)
+        );
+    }
+
+    #[test]
+    fn imported_cred_randoms_are_used() {
+        let key = SoftwareKeyProvider
+            .generate_key(Algorithm::ES256, &mut rand::thread_rng())
+            .unwrap()
+            .into_parts()
+            .1;
+        let both = passkey(
+            key.clone(),
+            Some(CredRandoms {
+                with_uv: [1; 32],
+                without_uv: Some([2; 32]),
+            }),
+        );
+        for (uv, expected) in [(true, [1; 32]), (false, [2; 32])] {
+            assert_eq!(
+                cred_random(&both, uv, HmacSecretUvPolicy::Separate).as_deref(),
+                Some(&expected)
+            );
+            assert_eq!(
+                cred_random(&both, uv, HmacSecretUvPolicy::Shared).as_deref(),
+                Some(&[1; 32])
+            );
+        }
+
+        // Without the CredRandom of unverified users, none is derived in its place.
+        let with_uv_only = passkey(
+            key,
+            Some(CredRandoms {
+                with_uv: [1; 32],
+                without_uv: None,
+            }),
+        );
+        assert_eq!(
+            cred_random(&with_uv_only, false, HmacSecretUvPolicy::Separate),
+            None
+        );
+        assert_eq!(
+            cred_random(&with_uv_only, false, HmacSecretUvPolicy::Shared).as_deref(),
+            Some(&[1; 32])
+        );
+    }
+
+    #[test]
+    fn one_or_two_salts_are_accepted() {
+        let cred_random = [1; 32];
+        let one = output_secrets(&cred_random, &[2; 32]).unwrap();
+        let two = output_secrets(&cred_random, &[[2; 32], [3; 32]].concat()).unwrap();
+        assert_eq!(one.len(), 32);
+        assert_eq!(two.len(), 64);
+        assert_eq!(one[..], two[..32]);
+        assert_ne!(two[..32], two[32..]);
+
+        assert_eq!(
+            output_secrets(&cred_random, &[2; 48]).unwrap_err(),
+            U2FError::InvalidLength.into()
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Conversions between [`StoredCredential`]s and the JSON objects in which existing credential
+//! managers export passkeys, so that the synced passkeys of users are imported from them.
+//!
+//! There is no standard for these objects, unlike the Credential Exchange Format of
+//! [`Header`](crate::Header), but they share their fields: the private key as a PKCS#8 document,
+//! the RP ID, the user handle and the signature counter. [`ExportedPasskey`] accepts the names and encodings in use for each of them.
+
+use std::str::FromStr;
+
+use passkey_types::{
+    ctap2::make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+    encoding, Passkey,
+};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    attestation::chain::{cose_key_from_pkcs8, pkcs8_from_cose_key},
+    AttestationChainError, StoredCredential,
+};
+
+/// The longest credential ID, in bytes.
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+
+/// The longest user handle, in bytes.
+const MAX_USER_HANDLE_LENGTH: usize = 64;
+
+/// The reasons an [`ExportedPasskey`] is not a valid passkey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterchangeError {
+    /// The credential ID is not a base64 or UUID encoding of 1 to 1023 bytes.
+    InvalidCredentialId,
+    /// The RP ID is empty.
+    InvalidRpId,
+    /// The user handle is not a base64 encoding of 1 to 64 bytes.
+    InvalidUserHandle,
+    /// The passkey is discoverable but has no user handle.
+    MissingUserHandle,
+    /// The private key is not a base64 encoded PKCS#8 document.
+    InvalidPrivateKey,
+    /// The algorithm of the private key is not supported.
+    UnsupportedAlgorithm,
+}
+
+/// A passkey in the JSON encoding of the exports of existing credential managers.
+///
+/// Binary values are read as base64url or base64, with or without padding, and credential IDs as
+/// UUIDs as well. Numbers and booleans are read from strings as well. They are written as
+/// base64url, numbers and booleans.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedPasskey {
+    /// The ID of the credential.
+    #[serde(alias = "id")]
+    pub credential_id: String,
+    /// The RP ID of the credential.
+    pub rp_id: String,
+    /// The name of the RP of the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rp_name: Option<String>,
+    /// The user handle of the credential, which discoverable credentials must have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_handle: Option<String>,
+    /// The name of the user of the credential.
+    #[serde(default, alias = "username", skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    /// The display name of the user of the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_display_name: Option<String>,
+    /// The private key of the credential, as a PKCS#8 document.
+    ///
+    /// # PII considerations
+    /// This value is secret, it is zeroized when dropped.
+    #[serde(alias = "keyValue", alias = "key")]
+    pub private_key: String,
+    /// The signature counter of the credential, `None` or zero when it has none.
+    #[serde(
+        default,
+        alias = "signCount",
+        deserialize_with = "stringified",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub counter: Option<u32>,
+    /// Whether the credential is discoverable, which passkeys are when it is not given.
+    #[serde(
+        default,
+        deserialize_with = "stringified",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub discoverable: Option<bool>,
+}
+
+impl Drop for ExportedPasskey {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+impl std::fmt::Debug for ExportedPasskey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExportedPasskey")
+            .field("credential_id", &self.credential_id)
+            .field("rp_id", &self.rp_id)
+            .field("user_handle", &self.user_handle)
+            .field("counter", &self.counter)
+            .field("discoverable", &self.discoverable)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read a value which is either itself or its string representation.
+fn stringified<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stringified<T> {
+        Value(T),
+        String(String),
+    }
+
+    match Option::<Stringified<T>>::deserialize(de)? {
+        Some(Stringified::Value(value)) => Ok(Some(value)),
+        Some(Stringified::String(value)) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("{value} is not a valid value"))),
+        None => Ok(None),
+    }
+}
+
+/// Decode base64url or base64, with or without padding.
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    encoding::try_from_base64url(&value.replace('+', "-").replace('/', "_"))
+}
+
+/// Decode the 16 bytes of a hyphenated UUID.
+fn decode_uuid(value: &str) -> Option<Vec<u8>> {
+    let hyphens = [8, 13, 18, 23];
+    let is_uuid = value.len() == 36
+        && value.char_indices().all(|(i, c)| {
+            if hyphens.contains(&i) {
+                c == '-'
+            } else {
+                c.is_ascii_hexdigit()
+            }
+        });
+    if !is_uuid {
+        return None;
+    }
+    let hex = value.replace('-', "");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl TryFrom<&ExportedPasskey> for StoredCredential {
+    type Error = InterchangeError;
+
+    /// Validate and import the `exported` passkey along with its user and RP.
+    ///
+    /// Imported passkeys are backup eligible and backed up, as they are synced between credential
+    /// managers.
+    fn try_from(exported: &ExportedPasskey) -> Result<Self, Self::Error> {
+        let credential_id = decode_uuid(&exported.credential_id)
+            .or_else(|| decode_base64(&exported.credential_id))
+            .filter(|id| (1..=MAX_CREDENTIAL_ID_LENGTH).contains(&id.len()))
+            .ok_or(InterchangeError::InvalidCredentialId)?;
+        if exported.rp_id.is_empty() {
+            return Err(InterchangeError::InvalidRpId);
+        }
+        let user_handle = exported
+            .user_handle
+            .as_deref()
+            .map(|user_handle| {
+                decode_base64(user_handle)
+                    .filter(|handle| (1..=MAX_USER_HANDLE_LENGTH).contains(&handle.len()))
+                    .ok_or(InterchangeError::InvalidUserHandle)
+            })
+            .transpose()?;
+        let discoverable = exported.discoverable.unwrap_or(true);
+        if discoverable && user_handle.is_none() {
+            return Err(InterchangeError::MissingUserHandle);
+        }
+        let private_key = Zeroizing::new(
+            decode_base64(&exported.private_key).ok_or(InterchangeError::InvalidPrivateKey)?,
+        );
+        let key = cose_key_from_pkcs8(&private_key).map_err(|e| match e {
+            AttestationChainError::UnsupportedAlgorithm => InterchangeError::UnsupportedAlgorithm,
+            _ => InterchangeError::InvalidPrivateKey,
+        })?;
+
+        Ok(StoredCredential {
+            user: PublicKeyCredentialUserEntity {
+                id: user_handle.clone().unwrap_or_default().into(),
+                name: exported.user_name.clone(),
+                display_name: exported.user_display_name.clone(),
+                icon_url: None,
+            },
+            rp: PublicKeyCredentialRpEntity {
+                id: exported.rp_id.clone(),
+                name: exported.rp_name.clone(),
+            },
+            passkey: Passkey {
+                key,
+                credential_id: credential_id.into(),
+                rp_id: exported.rp_id.clone(),
+                user_handle: user_handle.filter(|_| discoverable).map(Into::into),
+                counter: exported.counter.filter(|counter| *counter != 0),
+                third_party_payment: false,
+                backup_eligible: true,
+                backup_state: true,
+                cred_randoms: None,
+                created_at: None,
+                last_used_at: None,
+            },
+        })
+    }
+}
+
+impl TryFrom<&StoredCredential> for ExportedPasskey {
+    type Error = InterchangeError;
+
+    /// Export the `credential`, failing with [`InterchangeError::UnsupportedAlgorithm`] when its
+    /// private key cannot be encoded as a PKCS#8 document, such as for credentials derived from
+    /// their ID.
+    fn try_from(credential: &StoredCredential) -> Result<Self, Self::Error> {
+        let StoredCredential { passkey, user, rp } = credential;
+        let private_key = Zeroizing::new(
+            pkcs8_from_cose_key(&passkey.key).ok_or(InterchangeError::UnsupportedAlgorithm)?,
+        );
+        Ok(ExportedPasskey {
+            credential_id: encoding::base64url(&passkey.credential_id),
+            rp_id: passkey.rp_id.clone(),
+            rp_name: rp.name.clone(),
+            user_handle: passkey
+                .user_handle
+                .as_ref()
+                .map(|user_handle| encoding::base64url(user_handle)),
+            user_name: user.name.clone(),
+            user_display_name: user.display_name.clone(),
+            private_key: encoding::base64url(&private_key),
+            counter: passkey.counter,
+            discoverable: Some(passkey.user_handle.is_some()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::{pkcs8::EncodePrivateKey, SecretKey};
+    use passkey_types::rand::random_vec;
+
+    use super::*;
+
+    fn private_key() -> Vec<u8> {
+        SecretKey::random(&mut rand::thread_rng())
+            .to_pkcs8_der()
+            .unwrap()
+            .as_bytes()
+            .to_vec()
+    }
+
+    #[test]
+    fn passkeys_are_imported_from_their_exports() {
+        let private_key = private_key();
+        let exported: ExportedPasskey = serde_json::from_value(serde_json::json!({
+            "credentialId": "2b7e1516-28ae-d2a6-abf7-158809cf4f3c",
+            "keyType": "public-key",
+            "keyAlgorithm": "ECDSA",
+            "keyCurve": "P-256",
+            "keyValue": encoding::base64url(&private_key),
+            "rpId": "example.com",
+            "rpName": "Example",
+            "userHandle": encoding::base64url(b"wendy"),
+            "userName": "appleseed",
+            "counter": "7",
+            "discoverable": "true",
+        }))
+        .unwrap();
+        let credential = StoredCredential::try_from(&exported).unwrap();
+        assert_eq!(
+            credential.passkey.credential_id.as_slice(),
+            [
+                0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+                0x4f, 0x3c
+            ]
+        );
+        assert_eq!(
+            credential.passkey.user_handle.as_deref().map(Vec::as_slice),
+            Some(b"wendy".as_slice())
+        );
+        assert_eq!(credential.passkey.counter, Some(7));
+        assert_eq!(credential.user.name.as_deref(), Some("appleseed"));
+        assert_eq!(credential.rp.name.as_deref(), Some("Example"));
+        assert_eq!(
+            pkcs8_from_cose_key(&credential.passkey.key),
+            Some(private_key.clone())
+        );
+
+        // Padded base64 with numbers, and without a discoverability.
+        let credential_id = random_vec(32);
+        let exported: ExportedPasskey = serde_json::from_value(serde_json::json!({
+            "id": encoding::base64(&credential_id),
+            "rpId": "example.com",
+            "userHandle": encoding::base64(b"wendy"),
+            "privateKey": encoding::base64(&private_key),
+            "signCount": 0,
+        }))
+        .unwrap();
+        let credential = StoredCredential::try_from(&exported).unwrap();
+        assert_eq!(credential.passkey.credential_id.as_slice(), credential_id);
+        assert_eq!(credential.passkey.counter, None);
+        assert!(credential.passkey.user_handle.is_some());
+
+        let reexported = ExportedPasskey::try_from(&credential).unwrap();
+        let reimported = StoredCredential::try_from(&reexported).unwrap();
+        assert_eq!(
+            reimported.passkey.credential_id,
+            credential.passkey.credential_id
+        );
+        assert_eq!(
+            reimported.passkey.user_handle,
+            credential.passkey.user_handle
+        );
+        assert_eq!(
+            pkcs8_from_cose_key(&reimported.passkey.key),
+            Some(private_key)
+        );
+    }
+
+    #[test]
+    fn invalid_passkeys_are_refused() {
+        let valid = ExportedPasskey {
+            credential_id: encoding::base64url(&random_vec(16)),
+            rp_id: "example.com".into(),
+            rp_name: None,
+            user_handle: Some(encoding::base64url(b"wendy")),
+            user_name: None,
+            user_display_name: None,
+            private_key: encoding::base64url(&private_key()),
+            counter: None,
+            discoverable: None,
+        };
+        StoredCredential::try_from(&valid).expect("failed to import valid passkey");
+
+        let modified = |modify: fn(&mut ExportedPasskey)| {
+            let mut exported = valid.clone();
+            modify(&mut exported);
+            exported
+        };
+        let cases = [
+            (
+                modified(|exported| exported.credential_id = "not base64!".into()),
+                InterchangeError::InvalidCredentialId,
+            ),
+            (
+                modified(|exported| exported.credential_id = encoding::base64url(&[0; 1024])),
+                InterchangeError::InvalidCredentialId,
+            ),
+            (
+                modified(|exported| exported.rp_id = String::new()),
+                InterchangeError::InvalidRpId,
+            ),
+            (
+                modified(|exported| exported.user_handle = Some(encoding::base64url(&[0; 65]))),
+                InterchangeError::InvalidUserHandle,
+            ),
+            (
+                modified(|exported| exported.user_handle = None),
+                InterchangeError::MissingUserHandle,
+            ),
+            (
+                modified(|exported| {
+                    exported.private_key = encoding::base64url(b"not a PKCS#8 document")
+                }),
+                InterchangeError::InvalidPrivateKey,
+            ),
+        ];
+        for (exported, error) in cases {
+            assert_eq!(StoredCredential::try_from(&exported).unwrap_err(), error);
+        }
+
+        // Non-discoverable passkeys need no user handle.
+        let credential = StoredCredential::try_from(&modified(|exported| {
+            exported.user_handle = None;
+            exported.discoverable = Some(false);
+        }))
+        .unwrap();
+        assert_eq!(credential.passkey.user_handle, None);
+    }
+}
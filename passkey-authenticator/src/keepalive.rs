@@ -0,0 +1,24 @@
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// The status of an operation in progress on an [`Authenticator`], which a transport reports to
+/// the host with keepalive messages such as `CTAPHID_KEEPALIVE` on USB.
+///
+/// The status is given to the callback of [`Authenticator::with_keepalive`] whenever it changes.
+/// Transports which must send keepalive messages periodically repeat the latest status until the
+/// operation completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeepaliveStatus {
+    /// The authenticator is still processing the current request.
+    Processing = 0x01,
+    /// The authenticator is waiting for the user's presence or verification.
+    UpNeeded = 0x02,
+}
+
+impl From<KeepaliveStatus> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: KeepaliveStatus) -> Self {
+        src as u8
+    }
+}
@@ -0,0 +1,213 @@
+use coset::iana;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use passkey_types::{ctap2::Ctap2Error, webauthn::PublicKeyCredentialDescriptor, Passkey};
+use rand_core::CryptoRngCore;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::CoseKeyPair;
+
+#[cfg(doc)]
+use crate::{Authenticator, CredentialStore};
+
+/// Length of the random nonce used as HKDF salt for each credential.
+const NONCE_LEN: usize = 16;
+/// Length of the truncated HMAC authenticating a derived credential ID.
+const TAG_LEN: usize = 16;
+/// Length of a derived credential ID: algorithm marker, nonce and tag.
+const CREDENTIAL_ID_LEN: usize = 1 + NONCE_LEN + TAG_LEN;
+
+const KEY_INFO: &[u8] = b"passkey-rs derived credential key";
+const MAC_INFO: &[u8] = b"passkey-rs derived credential id";
+
+/// Markers of the credential's algorithm, stored as the first byte of the credential ID.
+const ES256_MARKER: u8 = 1;
+const EDDSA_MARKER: u8 = 2;
+#[cfg(feature = "es256k")]
+const ES256K_MARKER: u8 = 3;
+
+/// Device master seed from which per-RP credential keys are derived.
+///
+/// When set on the [`Authenticator`], a new credential gets a random nonce and its private key is
+/// derived with HKDF-SHA256 from the seed, the nonce, the algorithm and the Relying Party ID. The
+/// credential ID is made of the algorithm, the nonce and a MAC, which lets the authenticator derive
+/// the same key again when the credential is used.
+///
+/// This means no private key ever needs to be stored:
+/// * Non-discoverable credentials are not saved in the [`CredentialStore`] at all.
+/// * Discoverable credentials are saved with only their public key, so the user handle can be
+///   found during discovery.
+///
+/// Keys are always derived in software, bypassing the configured [`KeyProvider`](crate::KeyProvider)
+/// for key generation. Losing the seed means losing every credential derived from it.
+pub struct MasterSeed(Zeroizing<[u8; 32]>);
+
+impl MasterSeed {
+    /// Create a master seed from 32 bytes of secret entropy.
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self(Zeroizing::new(seed))
+    }
+
+    /// Create a new credential for `rp_id`, returning its credential ID and key pair.
+    pub(crate) fn new_credential(
+        &self,
+        rp_id: &str,
+        algorithm: iana::Algorithm,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<(Vec<u8>, CoseKeyPair), Ctap2Error> {
+        let marker = algorithm_marker(algorithm).ok_or(Ctap2Error::UnsupportedAlgorithm)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let key_pair = self.derive_key(rp_id, marker, &nonce)?;
+
+        let mut credential_id = Vec::with_capacity(CREDENTIAL_ID_LEN);
+        credential_id.push(marker);
+        credential_id.extend(nonce);
+        credential_id.extend(&self.tag(rp_id, &credential_id)[..TAG_LEN]);
+        Ok((credential_id, key_pair))
+    }
+
+    /// Derive the key pair of a credential ID created by [`MasterSeed::new_credential`] for the
+    /// same `rp_id`.
+    ///
+    /// Returns `None` if the credential ID was not derived from this seed or for this Relying
+    /// Party.
+    pub(crate) fn derive(&self, rp_id: &str, credential_id: &[u8]) -> Option<CoseKeyPair> {
+        if credential_id.len() != CREDENTIAL_ID_LEN {
+            return None;
+        }
+        let (data, tag) = credential_id.split_at(1 + NONCE_LEN);
+        self.mac(rp_id, data).verify_truncated_left(tag).ok()?;
+        self.derive_key(rp_id, data[0], &data[1..]).ok()
+    }
+
+    /// Find the first credential in `list` that was derived for `rp_id` and rebuild its
+    /// [`Passkey`].
+    pub(crate) fn find_credential(
+        &self,
+        list: &[PublicKeyCredentialDescriptor],
+        rp_id: &str,
+    ) -> Option<Passkey> {
+        list.iter().find_map(|descriptor| {
+            self.derive(rp_id, &descriptor.id).map(|key_pair| Passkey {
+                key: key_pair.into_parts().1,
+                credential_id: descriptor.id.clone(),
+                rp_id: rp_id.into(),
+                user_handle: None,
+                counter: None,
+                third_party_payment: false,
+                backup_eligible: false,
+                backup_state: false,
+                cred_randoms: None,
+                created_at: None,
+                last_used_at: None,
+            })
+        })
+    }
+
+    fn derive_key(&self, rp_id: &str, marker: u8, nonce: &[u8]) -> Result<CoseKeyPair, Ctap2Error> {
+        let hkdf = Hkdf::<Sha256>::new(Some(nonce), self.0.as_slice());
+        // A derived scalar can be out of range for the curve with negligible probability, in
+        // which case the next counter value is tried.
+        for counter in 0..=u8::MAX {
+            let mut okm = Zeroizing::new([0u8; 32]);
+            // SAFETY: 32 bytes is well within the maximum output length of HKDF-SHA256.
+            hkdf.expand_multi_info(
+                &[KEY_INFO, &[marker, counter], rp_id.as_bytes()],
+                okm.as_mut_slice(),
+            )
+            .unwrap();
+
+            let key_pair = match marker {
+                ES256_MARKER => p256::SecretKey::from_bytes(&(*okm).into())
+                    .ok()
+                    .map(|key| CoseKeyPair::from_secret_key(&key, iana::Algorithm::ES256)),
+                EDDSA_MARKER => Some(CoseKeyPair::from_ed25519(
+                    &ed25519_dalek::SigningKey::from_bytes(&okm),
+                )),
+                #[cfg(feature = "es256k")]
+                ES256K_MARKER => k256::SecretKey::from_bytes(&(*okm).into())
+                    .ok()
+                    .map(|key| CoseKeyPair::from_k256_secret_key(&key)),
+                _ => return Err(Ctap2Error::UnsupportedAlgorithm),
+            };
+            if let Some(key_pair) = key_pair {
+                return Ok(key_pair);
+            }
+        }
+        Err(Ctap2Error::InvalidCredential)
+    }
+
+    fn mac(&self, rp_id: &str, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac_key = Zeroizing::new([0u8; 32]);
+        // SAFETY: 32 bytes is well within the maximum output length of HKDF-SHA256.
+        Hkdf::<Sha256>::new(None, self.0.as_slice())
+            .expand(MAC_INFO, mac_key.as_mut_slice())
+            .unwrap();
+        // SAFETY: HMAC accepts keys of any length.
+        let mut mac = Hmac::<Sha256>::new_from_slice(mac_key.as_slice()).unwrap();
+        mac.update(data);
+        mac.update(rp_id.as_bytes());
+        mac
+    }
+
+    fn tag(&self, rp_id: &str, data: &[u8]) -> Vec<u8> {
+        self.mac(rp_id, data).finalize().into_bytes().to_vec()
+    }
+}
+
+impl From<[u8; 32]> for MasterSeed {
+    fn from(seed: [u8; 32]) -> Self {
+        Self::new(seed)
+    }
+}
+
+impl std::fmt::Debug for MasterSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MasterSeed").finish_non_exhaustive()
+    }
+}
+
+fn algorithm_marker(algorithm: iana::Algorithm) -> Option<u8> {
+    match algorithm {
+        iana::Algorithm::ES256 => Some(ES256_MARKER),
+        iana::Algorithm::EdDSA => Some(EDDSA_MARKER),
+        #[cfg(feature = "es256k")]
+        iana::Algorithm::ES256K => Some(ES256K_MARKER),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+
+    use super::*;
+
+    #[test]
+    fn derived_keys_are_bound_to_seed_and_rp_id() {
+        let seed = MasterSeed::new([3; 32]);
+        for algorithm in [iana::Algorithm::ES256, iana::Algorithm::EdDSA] {
+            let (credential_id, key_pair) = seed
+                .new_credential("future.1password.com", algorithm, &mut rand::rngs::OsRng)
+                .expect("could not create credential");
+
+            let derived = seed
+                .derive("future.1password.com", &credential_id)
+                .expect("could not derive key");
+            assert_eq!(derived.private, key_pair.private);
+            assert_eq!(derived.public, key_pair.public);
+
+            assert!(seed.derive("evil.example.com", &credential_id).is_none());
+            assert!(MasterSeed::new([4; 32])
+                .derive("future.1password.com", &credential_id)
+                .is_none());
+
+            let mut tampered = credential_id.clone();
+            tampered[1] ^= 1;
+            assert!(seed.derive("future.1password.com", &tampered).is_none());
+        }
+    }
+}
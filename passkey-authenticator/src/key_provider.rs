@@ -0,0 +1,180 @@
+use coset::{iana, CoseKey};
+use p256::SecretKey;
+use passkey_types::ctap2::Ctap2Error;
+use rand_core::CryptoRngCore;
+
+use crate::{sign_with_cose_key, CoseKeyPair};
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Pluggable backend for the [`Authenticator`] to generate credential key pairs and sign with them.
+///
+/// This allows delegating key material to platform keystores such as the Secure Enclave, Android
+/// Keystore, a TPM or an HSM instead of the software keys generated by [`SoftwareKeyProvider`].
+///
+/// The private [`CoseKey`] returned from [`KeyProvider::generate_key`] is what gets saved in the
+/// [`Passkey`](passkey_types::Passkey), and is handed back to [`KeyProvider::sign`] when the
+/// credential is used. Hardware backed providers may therefore store a reference to the key in
+/// it rather than the key material itself.
+pub trait KeyProvider {
+    /// The algorithms this provider can generate keys for, in order of preference.
+    fn supported_algorithms(&self) -> Vec<iana::Algorithm>;
+
+    /// Generate a new key pair for the given `algorithm`.
+    ///
+    /// The `rng` is the one configured on the [`Authenticator`], software providers should use it
+    /// so key generation can be reproduced with a seeded RNG. Hardware backed providers are free
+    /// to ignore it.
+    ///
+    /// Returns [`Ctap2Error::UnsupportedAlgorithm`] if the algorithm is not one of
+    /// [`KeyProvider::supported_algorithms`].
+    fn generate_key(
+        &self,
+        algorithm: iana::Algorithm,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<CoseKeyPair, Ctap2Error>;
+
+    /// Sign `data` with the private `key` previously returned by [`KeyProvider::generate_key`].
+    ///
+    /// ES256 signatures are expected to be DER encoded while EdDSA signatures are the raw
+    /// 64 bytes, as expected by WebAuthn.
+    ///
+    /// When `rng` is `Some`, the [`Authenticator`] was configured with [`EcdsaNonce::Hedged`] and
+    /// ECDSA nonces should mix in its output. Otherwise they should be derived deterministically
+    /// following [RFC 6979].
+    ///
+    /// [RFC 6979]: https://www.rfc-editor.org/rfc/rfc6979
+    fn sign(
+        &self,
+        key: &CoseKey,
+        data: &[u8],
+        rng: Option<&mut dyn CryptoRngCore>,
+    ) -> Result<Vec<u8>, Ctap2Error>;
+}
+
+/// The default [`KeyProvider`] which generates software keys using the [RustCrypto] libraries.
+///
+/// Supports ES256 and EdDSA, as well as ES256K when the `es256k` feature is enabled.
+///
+/// [RustCrypto]: https://github.com/RustCrypto
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SoftwareKeyProvider;
+
+impl KeyProvider for SoftwareKeyProvider {
+    fn supported_algorithms(&self) -> Vec<iana::Algorithm> {
+        vec![
+            iana::Algorithm::ES256,
+            iana::Algorithm::EdDSA,
+            #[cfg(feature = "es256k")]
+            iana::Algorithm::ES256K,
+        ]
+    }
+
+    fn generate_key(
+        &self,
+        algorithm: iana::Algorithm,
+        mut rng: &mut dyn CryptoRngCore,
+    ) -> Result<CoseKeyPair, Ctap2Error> {
+        match algorithm {
+            iana::Algorithm::ES256 => Ok(CoseKeyPair::from_secret_key(
+                &SecretKey::random(&mut rng),
+                algorithm,
+            )),
+            iana::Algorithm::EdDSA => Ok(CoseKeyPair::from_ed25519(
+                &ed25519_dalek::SigningKey::generate(&mut rng),
+            )),
+            #[cfg(feature = "es256k")]
+            iana::Algorithm::ES256K => Ok(CoseKeyPair::from_k256_secret_key(
+                &k256::SecretKey::random(&mut rng),
+            )),
+            _ => Err(Ctap2Error::UnsupportedAlgorithm),
+        }
+    }
+
+    fn sign(
+        &self,
+        key: &CoseKey,
+        data: &[u8],
+        rng: Option<&mut dyn CryptoRngCore>,
+    ) -> Result<Vec<u8>, Ctap2Error> {
+        sign_with_cose_key(key, data, rng)
+    }
+}
+
+/// How the nonce of ECDSA signatures is generated.
+///
+/// EdDSA signatures are always deterministic and are not affected by this setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EcdsaNonce {
+    /// Derive the nonce from the private key and message as described in [RFC 6979]. Signing the
+    /// same data twice gives the same signature and no randomness is consumed, so a weak RNG
+    /// cannot leak the private key.
+    ///
+    /// [RFC 6979]: https://www.rfc-editor.org/rfc/rfc6979
+    #[default]
+    Deterministic,
+    /// Mix fresh output of the [`Authenticator`]'s RNG into the RFC 6979 nonce derivation. This
+    /// hardens signatures against fault attacks while still never relying solely on the RNG.
+    Hedged,
+}
+
+/// How ECDSA signatures are encoded in assertions.
+///
+/// Ed25519 signatures have no DER encoding and are always the raw 64 bytes, regardless of this
+/// setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+    /// ASN.1 DER encoded `Ecdsa-Sig-Value`, as required by WebAuthn.
+    #[default]
+    Der,
+    /// The fixed size concatenation `r || s` of the signature's scalars, as used by some CTAP1/U2F
+    /// compatibility layers and test harnesses.
+    Raw,
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use passkey_types::ctap2::Ctap2Error;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{KeyProvider, SoftwareKeyProvider};
+
+    #[test]
+    fn software_provider_generates_supported_algorithms() {
+        let provider = SoftwareKeyProvider;
+        let mut rng = rand::rngs::OsRng;
+        for alg in provider.supported_algorithms() {
+            let pair = provider
+                .generate_key(alg, &mut rng)
+                .expect("could not generate a supported key");
+            assert_eq!(
+                pair.public.alg,
+                Some(coset::RegisteredLabelWithPrivate::Assigned(alg))
+            );
+            provider
+                .sign(&pair.private, b"signature target", None)
+                .expect("could not sign with generated key");
+        }
+
+        assert!(matches!(
+            provider.generate_key(iana::Algorithm::RS256, &mut rng),
+            Err(Ctap2Error::UnsupportedAlgorithm)
+        ));
+    }
+
+    #[test]
+    fn software_provider_is_deterministic_with_seeded_rng() {
+        let provider = SoftwareKeyProvider;
+        for alg in provider.supported_algorithms() {
+            let first = provider
+                .generate_key(alg, &mut StdRng::seed_from_u64(1))
+                .expect("could not generate key");
+            let second = provider
+                .generate_key(alg, &mut StdRng::seed_from_u64(1))
+                .expect("could not generate key");
+            assert_eq!(first.private, second.private);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use coset::{CborSerializable, CoseKey};
+use passkey_types::{ctap2::Ctap2Error, webauthn::PublicKeyCredentialDescriptor, Passkey};
+use rand_core::CryptoRngCore;
+use zeroize::Zeroizing;
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// Length of the random nonce prepended to every wrapped credential ID.
+const NONCE_LEN: usize = 12;
+
+/// Authenticator master secret used to wrap the private keys of non-discoverable credentials into
+/// their credential IDs.
+///
+/// When set on the [`Authenticator`], credentials created with `rk=false` are not persisted in the
+/// [`CredentialStore`](crate::CredentialStore). Instead the private key is encrypted with
+/// AES-256-GCM, bound to the Relying Party ID, and returned as the credential ID. The Relying
+/// Party then hands it back in the allowList during assertions where it is unwrapped again.
+///
+/// Losing this secret means losing every credential wrapped with it.
+#[derive(Clone)]
+pub struct WrappingKey(Aes256Gcm);
+
+impl WrappingKey {
+    /// Create a wrapping key from a 32 byte master secret.
+    pub fn new(master_secret: [u8; 32]) -> Self {
+        Self(Aes256Gcm::new(&master_secret.into()))
+    }
+
+    /// Encrypt the private `key` for the given `rp_id` into a credential ID, using `rng` for the
+    /// nonce.
+    pub(crate) fn wrap(
+        &self,
+        rp_id: &str,
+        key: &CoseKey,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<Vec<u8>, Ctap2Error> {
+        let plaintext = Zeroizing::new(
+            key.clone()
+                .to_vec()
+                .map_err(|_| Ctap2Error::InvalidCredential)?,
+        );
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .0
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: rp_id.as_bytes(),
+                },
+            )
+            .map_err(|_| Ctap2Error::InvalidCredential)?;
+
+        let mut credential_id = nonce.to_vec();
+        credential_id.extend(ciphertext);
+        Ok(credential_id)
+    }
+
+    /// Decrypt a credential ID previously created by [`WrappingKey::wrap`] for the same `rp_id`.
+    ///
+    /// Returns `None` if the credential ID was not wrapped by this key or for this Relying Party.
+    pub(crate) fn unwrap(&self, rp_id: &str, credential_id: &[u8]) -> Option<CoseKey> {
+        if credential_id.len() <= NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = credential_id.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().ok()?;
+        let plaintext = Zeroizing::new(
+            self.0
+                .decrypt(
+                    &Nonce::from(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: rp_id.as_bytes(),
+                    },
+                )
+                .ok()?,
+        );
+        CoseKey::from_slice(&plaintext).ok()
+    }
+
+    /// Find the first credential in `list` that can be unwrapped for `rp_id` and rebuild its
+    /// [`Passkey`].
+    pub(crate) fn find_credential(
+        &self,
+        list: &[PublicKeyCredentialDescriptor],
+        rp_id: &str,
+    ) -> Option<Passkey> {
+        list.iter().find_map(|descriptor| {
+            self.unwrap(rp_id, &descriptor.id).map(|key| Passkey {
+                key,
+                credential_id: descriptor.id.clone(),
+                rp_id: rp_id.into(),
+                user_handle: None,
+                counter: None,
+                third_party_payment: false,
+                backup_eligible: false,
+                backup_state: false,
+                cred_randoms: None,
+                created_at: None,
+                last_used_at: None,
+            })
+        })
+    }
+}
+
+impl From<[u8; 32]> for WrappingKey {
+    fn from(master_secret: [u8; 32]) -> Self {
+        Self::new(master_secret)
+    }
+}
+
+impl std::fmt::Debug for WrappingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WrappingKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::iana;
+    use passkey_types::Bytes;
+
+    use super::*;
+    use crate::{KeyProvider, SoftwareKeyProvider};
+
+    fn descriptor(id: Bytes) -> PublicKeyCredentialDescriptor {
+        PublicKeyCredentialDescriptor {
+            ty: passkey_types::webauthn::PublicKeyCredentialType::PublicKey,
+            id,
+            transports: None,
+        }
+    }
+
+    #[test]
+    fn wrap_round_trip_is_bound_to_rp_id() {
+        let wrapping_key = WrappingKey::new([7; 32]);
+        let mut rng = rand::rngs::OsRng;
+        let pair = SoftwareKeyProvider
+            .generate_key(iana::Algorithm::ES256, &mut rng)
+            .expect("could not generate key");
+
+        let credential_id = wrapping_key
+            .wrap("future.1password.com", &pair.private, &mut rng)
+            .expect("could not wrap key");
+
+        let unwrapped = wrapping_key
+            .unwrap("future.1password.com", &credential_id)
+            .expect("could not unwrap key");
+        assert_eq!(unwrapped, pair.private);
+
+        assert!(wrapping_key
+            .unwrap("evil.example.com", &credential_id)
+            .is_none());
+        assert!(WrappingKey::new([8; 32])
+            .unwrap("future.1password.com", &credential_id)
+            .is_none());
+
+        let passkey = wrapping_key
+            .find_credential(
+                &[descriptor(credential_id.clone().into())],
+                "future.1password.com",
+            )
+            .expect("did not find wrapped credential");
+        assert_eq!(passkey.credential_id, Bytes::from(credential_id));
+    }
+}
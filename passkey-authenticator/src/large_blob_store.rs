@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use passkey_types::ctap2::{large_blobs::EMPTY_LARGE_BLOB_ARRAY, StatusCode};
+use zeroize::Zeroize;
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// The large-blob state of an authenticator which must survive restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredLargeBlobs {
+    /// The serialized large-blob array written by the platform, which the authenticator only
+    /// checks the trailing hash of.
+    pub array: Vec<u8>,
+    /// The `largeBlobKey` of every credential created with one, by credential ID.
+    pub keys: HashMap<Vec<u8>, [u8; 32]>,
+}
+
+impl Default for StoredLargeBlobs {
+    fn default() -> Self {
+        Self {
+            array: EMPTY_LARGE_BLOB_ARRAY.to_vec(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl Drop for StoredLargeBlobs {
+    fn drop(&mut self) {
+        self.keys.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+/// Use this on a type that persists the [`StoredLargeBlobs`] of an [`Authenticator`].
+///
+/// The state is loaded once when the store is given to the authenticator and saved every time it
+/// changes: when a large-blob array write completes, and when a credential is created with or
+/// deleted along with a `largeBlobKey`.
+pub trait LargeBlobStore {
+    /// Load the persisted large-blob state, `None` if it was never saved.
+    fn load(&self) -> Option<StoredLargeBlobs>;
+
+    /// Persist the new large-blob state. An error aborts the operation which caused the change.
+    fn save(&mut self, large_blobs: &StoredLargeBlobs) -> Result<(), StatusCode>;
+}
+
+/// In-memory large-blob store, the default of an [`Authenticator`]. The large blobs are forgotten
+/// along with it.
+impl LargeBlobStore for Option<StoredLargeBlobs> {
+    fn load(&self) -> Option<StoredLargeBlobs> {
+        self.clone()
+    }
+
+    fn save(&mut self, large_blobs: &StoredLargeBlobs) -> Result<(), StatusCode> {
+        self.replace(large_blobs.clone());
+        Ok(())
+    }
+}
+
+impl<S: LargeBlobStore> LargeBlobStore for Arc<Mutex<S>> {
+    fn load(&self) -> Option<StoredLargeBlobs> {
+        self.lock().unwrap_or_else(PoisonError::into_inner).load()
+    }
+
+    fn save(&mut self, large_blobs: &StoredLargeBlobs) -> Result<(), StatusCode> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .save(large_blobs)
+    }
+}
@@ -14,8 +14,9 @@
 //!
 //! For targeting WASM, yes there are other cryptographic libraries out there that allow targeting
 //! WASM, but none of them are as easy to compile to wasm than the pure rust implementations of the
-//! [RustCrypto] libraries. Now this does come with limitations, so there are plans to provide a
-//! similar backing trait to "plug-in" the desired cryptography from a vendor. Work is ongoing for this.
+//! [RustCrypto] libraries. Now this does come with limitations, so the [`KeyProvider`] trait allows
+//! "plugging-in" the desired cryptography from a vendor, such as a platform keystore. The default
+//! [`SoftwareKeyProvider`] uses the [RustCrypto] libraries.
 //!
 //! [github]: https://img.shields.io/badge/GitHub-1Password%2Fpasskey--rs%2Fpasskey--authenticator-informational?logo=github&style=flat
 //! [version]: https://img.shields.io/crates/v/passkey-authenticator?logo=rust&style=flat
@@ -23,30 +24,83 @@
 //! [CTAP 2.0]: https://fidoalliance.org/specs/fido-v2.0-ps-20190130/fido-client-to-authenticator-protocol-v2.0-ps-20190130.html
 //! [RustCrypto]: https://github.com/RustCrypto
 
+mod attestation;
 mod authenticator;
+mod cached_store;
+mod cancellation;
+mod clock;
+mod config_store;
 mod credential_store;
 mod ctap2;
+mod cxf;
+mod descriptor_index;
+mod device_key_store;
+#[cfg(feature = "es256k")]
+mod es256k;
+mod extensions;
+mod file_store;
+mod hmac_secret;
+mod interchange;
+mod keepalive;
+mod key_derivation;
+mod key_provider;
+mod key_wrapping;
+mod large_blob_store;
+mod pin_protocol;
+mod pin_store;
 mod u2f;
 mod user_validation;
+mod webdriver;
 
 use coset::{
+    cbor::value::Value,
     iana::{self, Algorithm, EnumI64},
     CoseKey, CoseKeyBuilder,
 };
-use p256::{
-    ecdsa::SigningKey,
-    elliptic_curve::{generic_array::GenericArray, sec1::FromEncodedPoint},
-    pkcs8::EncodePublicKey,
-    EncodedPoint, PublicKey, SecretKey,
-};
-use passkey_types::{ctap2::Ctap2Error, Bytes};
+use p256::{ecdsa::SigningKey, SecretKey};
+use passkey_types::{cose, crypto::zeroize_cose_key, ctap2::Ctap2Error, Bytes};
+use rand_core::CryptoRngCore;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 
 pub use self::{
+    attestation::{
+        Attestation, AttestationChain, AttestationChainError, AttestationInput,
+        AttestationProvider, AttestationStatement, BatchAttestation, FidoU2fAttestation,
+        PackedAttestation,
+    },
     authenticator::Authenticator,
-    credential_store::{CredentialStore, MemoryStore},
-    ctap2::Ctap2Api,
-    u2f::U2fApi,
-    user_validation::UserValidationMethod,
+    cached_store::CachedCredentialStore,
+    cancellation::CancellationToken,
+    clock::{Clock, SystemClock},
+    config_store::{AuthenticatorConfigStore, StoredConfig},
+    credential_store::{
+        CredentialStore, DiscoverabilitySupport, DiscoverableCredential, MemoryStore,
+        StoredCredential,
+    },
+    ctap2::{Ctap2Api, Ctap2Server},
+    cxf::{
+        Account, Collection, Credential, ExchangeError, Fido2Extensions, Header, HmacCredentials,
+        Item, LinkedItem, PasskeyCredential, Version, CXF_VERSION,
+    },
+    descriptor_index::DescriptorIndex,
+    device_key_store::{DeviceKeyStore, StoredDeviceKeys},
+    extensions::{Extension, ExtensionContext, ExtensionHandler, ExtensionOutput},
+    file_store::EncryptedFileStore,
+    hmac_secret::{HmacSecretConfig, HmacSecretUvPolicy},
+    interchange::{ExportedPasskey, InterchangeError},
+    keepalive::KeepaliveStatus,
+    key_derivation::MasterSeed,
+    key_provider::{EcdsaNonce, KeyProvider, SignatureFormat, SoftwareKeyProvider},
+    key_wrapping::WrappingKey,
+    large_blob_store::{LargeBlobStore, StoredLargeBlobs},
+    pin_protocol::PinProtocol,
+    pin_store::{PinState, PinStore, StoredPin},
+    u2f::{U2fApi, U2fServer},
+    user_validation::{EnrollmentSample, FingerprintSensor, UserValidationMethod, UvRateLimit},
+    webdriver::{
+        VirtualAuthenticatorOptions, VirtualAuthenticators, VirtualCredential, VirtualProtocol,
+        VirtualStore, VirtualUser, WebDriverError,
+    },
 };
 
 #[cfg(feature = "testable")]
@@ -85,83 +139,144 @@ fn private_key_from_cose_key(key: &CoseKey) -> Result<SecretKey, Ctap2Error> {
         .ok_or(Ctap2Error::InvalidCredential)
 }
 
-/// Convert a Cose Key to a X.509 SubjectPublicKeyInfo formatted byte array.
-///
-/// This should be used by the client when creating the [Easy Credential Data Accessors][ez]
-///
-/// [ez]: https://w3c.github.io/webauthn/#sctn-public-key-easy
-pub fn public_key_der_from_cose_key(key: &CoseKey) -> Result<Bytes, Ctap2Error> {
+/// Extract an Ed25519 signing key from an OKP [`CoseKey`].
+fn ed25519_key_from_cose_key(key: &CoseKey) -> Result<ed25519_dalek::SigningKey, Ctap2Error> {
+    let secret = okp_ed25519_param(key, iana::OkpKeyParameter::D)?;
+    let secret: &ed25519_dalek::SecretKey = secret
+        .as_slice()
+        .try_into()
+        .map_err(|_| Ctap2Error::InvalidCredential)?;
+    Ok(ed25519_dalek::SigningKey::from_bytes(secret))
+}
+
+/// Get the value of the given `param` from an Ed25519 OKP [`CoseKey`], validating the key's
+/// algorithm, type and curve along the way.
+fn okp_ed25519_param(key: &CoseKey, param: iana::OkpKeyParameter) -> Result<&Vec<u8>, Ctap2Error> {
     if !matches!(
         key.alg,
         Some(coset::RegisteredLabelWithPrivate::Assigned(
-            iana::Algorithm::ES256
+            iana::Algorithm::EdDSA
         ))
     ) {
         return Err(Ctap2Error::UnsupportedAlgorithm);
     }
     if !matches!(
         key.kty,
-        coset::RegisteredLabel::Assigned(iana::KeyType::EC2)
+        coset::RegisteredLabel::Assigned(iana::KeyType::OKP)
     ) {
         return Err(Ctap2Error::InvalidCredential);
     }
 
-    let (mut x, mut y) = (None, None);
-    for (key, value) in &key.params {
-        if let coset::Label::Int(i) = key {
-            let key = iana::Ec2KeyParameter::from_i64(*i).ok_or(Ctap2Error::InvalidCbor)?;
-            match key {
-                iana::Ec2KeyParameter::X => {
-                    if value.as_bytes().and_then(|v| x.replace(v)).is_some() {
-                        log::warn!("Cose key has multiple entries for X coordinate");
-                    }
-                }
-                iana::Ec2KeyParameter::Y => {
-                    if value.as_bytes().and_then(|v| y.replace(v)).is_some() {
-                        log::warn!("Cose key has multiple entries for Y coordinate");
-                    }
-                }
-                _ => (),
-            }
+    let label_of = |param: iana::OkpKeyParameter| coset::Label::Int(param.to_i64());
+    let curve = key
+        .params
+        .iter()
+        .find(|(k, _)| k == &label_of(iana::OkpKeyParameter::Crv))
+        .and_then(|(_, v)| v.as_integer());
+    if curve != Some(iana::EllipticCurve::Ed25519.to_i64().into()) {
+        return Err(Ctap2Error::InvalidCredential);
+    }
+
+    key.params
+        .iter()
+        .find(|(k, _)| k == &label_of(param))
+        .and_then(|(_, v)| v.as_bytes())
+        .ok_or(Ctap2Error::InvalidCredential)
+}
+
+/// Sign `data` using the private key contained in the given [`CoseKey`].
+///
+/// ES256 and ES256K signatures are DER encoded while EdDSA signatures are the raw 64 bytes as
+/// expected by WebAuthn. ECDSA nonces are hedged with `rng` when given, and purely deterministic
+/// otherwise.
+fn sign_with_cose_key(
+    key: &CoseKey,
+    data: &[u8],
+    rng: Option<&mut dyn CryptoRngCore>,
+) -> Result<Vec<u8>, Ctap2Error> {
+    use p256::ecdsa::signature::{RandomizedSigner, Signer};
+
+    // Catch malformed stored keys before they get anywhere near the signing code.
+    cose::validate_private_key(key)?;
+
+    match key.alg {
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::EdDSA)) => {
+            let signing_key = ed25519_key_from_cose_key(key)?;
+            let signature: ed25519_dalek::Signature = signing_key.sign(data);
+            Ok(signature.to_bytes().to_vec())
+        }
+        #[cfg(feature = "es256k")]
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256K)) => {
+            es256k::sign(key, data, rng)
+        }
+        _ => {
+            let signing_key = SigningKey::from(private_key_from_cose_key(key)?);
+            let signature: p256::ecdsa::Signature = match rng {
+                Some(mut rng) => signing_key.sign_with_rng(&mut rng, data),
+                None => signing_key.sign(data),
+            };
+            Ok(signature.to_der().to_bytes().to_vec())
         }
     }
-    let (Some(x), Some(y)) = (x, y) else {
-        return Err(Ctap2Error::CborUnexpectedType);
-    };
+}
 
-    let point = EncodedPoint::from_affine_coordinates(
-        GenericArray::from_slice(x.as_slice()),
-        GenericArray::from_slice(y.as_slice()),
-        false,
-    );
-    let Some(pub_key): Option<PublicKey> = PublicKey::from_encoded_point(&point).into() else {
-        return Err(Ctap2Error::InvalidCredential);
-    };
-    pub_key
-        .to_public_key_der()
-        .map_err(|_| Ctap2Error::InvalidCredential)
-        .map(|pk| pk.as_ref().to_vec().into())
+/// Convert a DER encoded ECDSA `signature` made with `key` to its raw `r || s` form.
+///
+/// Signatures of other algorithms are returned untouched.
+fn ecdsa_der_to_raw(key: &CoseKey, signature: Vec<u8>) -> Result<Vec<u8>, Ctap2Error> {
+    match key.alg {
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256)) => {
+            p256::ecdsa::Signature::from_der(&signature)
+                .map(|sig| sig.to_bytes().to_vec())
+                .map_err(|_| Ctap2Error::InvalidCredential)
+        }
+        #[cfg(feature = "es256k")]
+        Some(coset::RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256K)) => {
+            k256::ecdsa::Signature::from_der(&signature)
+                .map(|sig| sig.to_bytes().to_vec())
+                .map_err(|_| Ctap2Error::InvalidCredential)
+        }
+        _ => Ok(signature),
+    }
 }
 
-pub(crate) struct CoseKeyPair {
-    public: CoseKey,
-    private: CoseKey,
+/// Convert a Cose Key to a X.509 SubjectPublicKeyInfo formatted byte array, see
+/// [`cose::public_key_der_from_cose_key`].
+///
+/// This should be used by the client when creating the [Easy Credential Data Accessors][ez]
+///
+/// [ez]: https://w3c.github.io/webauthn/#sctn-public-key-easy
+pub fn public_key_der_from_cose_key(key: &CoseKey) -> Result<Bytes, Ctap2Error> {
+    cose::public_key_der_from_cose_key(key).map_err(Into::into)
+}
+
+/// A newly generated credential key pair in its [`CoseKey`] representation.
+///
+/// The private key material is zeroized when the pair is dropped.
+pub struct CoseKeyPair {
+    /// The public key which is returned to the Relying Party in the attested credential data.
+    pub public: CoseKey,
+    /// The private key which is saved in the [`Passkey`](passkey_types::Passkey) and used for
+    /// signing.
+    pub private: CoseKey,
 }
 
 impl CoseKeyPair {
-    fn from_secret_key(private_key: &SecretKey, algorithm: Algorithm) -> Self {
+    /// Encode a P-256 `private_key` for the given `algorithm`.
+    pub fn from_secret_key(private_key: &SecretKey, algorithm: Algorithm) -> Self {
         let public_key = SigningKey::from(private_key)
             .verifying_key()
             .to_encoded_point(false);
         // SAFETY: These unwraps are safe because the public_key above is not compressed (false
         // parameter) therefore x and y are guarateed to contain values.
-        let x = public_key.x().unwrap().as_slice().to_vec();
-        let y = public_key.y().unwrap().as_slice().to_vec();
+        let x = public_key.x().unwrap().to_vec();
+        let y = public_key.y().unwrap().to_vec();
+        let d: Zeroizing<[u8; 32]> = Zeroizing::new(private_key.to_bytes().into());
         let private = CoseKeyBuilder::new_ec2_priv_key(
             iana::EllipticCurve::P_256,
             x.clone(),
             y.clone(),
-            private_key.to_bytes().to_vec(),
+            d.to_vec(),
         )
         .algorithm(algorithm)
         .build();
@@ -171,8 +286,48 @@ impl CoseKeyPair {
 
         Self { public, private }
     }
+
+    /// Encode an Ed25519 `signing_key` as an OKP key pair.
+    pub fn from_ed25519(signing_key: &ed25519_dalek::SigningKey) -> Self {
+        let x = signing_key.verifying_key().to_bytes().to_vec();
+        let okp_key = |x: Vec<u8>| {
+            CoseKeyBuilder::new_okp_key()
+                .algorithm(Algorithm::EdDSA)
+                .param(
+                    iana::OkpKeyParameter::Crv.to_i64(),
+                    Value::from(iana::EllipticCurve::Ed25519.to_i64()),
+                )
+                .param(iana::OkpKeyParameter::X.to_i64(), Value::Bytes(x))
+        };
+        let d = Zeroizing::new(signing_key.to_bytes());
+        let private = okp_key(x.clone())
+            .param(iana::OkpKeyParameter::D.to_i64(), Value::Bytes(d.to_vec()))
+            .build();
+        let public = okp_key(x).build();
+
+        Self { public, private }
+    }
+
+    /// Split the pair into its public and private keys, in that order.
+    ///
+    /// The caller becomes responsible for zeroizing the private key, for example by moving it into
+    /// a [`Passkey`](passkey_types::Passkey) which does so when dropped.
+    pub fn into_parts(mut self) -> (CoseKey, CoseKey) {
+        (
+            std::mem::take(&mut self.public),
+            std::mem::take(&mut self.private),
+        )
+    }
+}
+
+impl Drop for CoseKeyPair {
+    fn drop(&mut self) {
+        zeroize_cose_key(&mut self.private);
+    }
 }
 
+impl ZeroizeOnDrop for CoseKeyPair {}
+
 #[cfg(test)]
 mod tests {
     use coset::iana;
@@ -185,7 +340,10 @@ mod tests {
     };
     use passkey_types::{ctap2::AuthenticatorData, rand::random_vec};
 
-    use super::{private_key_from_cose_key, CoseKeyPair};
+    use super::{
+        ecdsa_der_to_raw, ed25519_key_from_cose_key, private_key_from_cose_key,
+        public_key_der_from_cose_key, sign_with_cose_key, CoseKeyPair,
+    };
 
     #[test]
     fn private_key_cose_round_trip_sanity_check() {
@@ -193,10 +351,8 @@ mod tests {
             let mut rng = rand::thread_rng();
             SecretKey::random(&mut rng)
         };
-        let CoseKeyPair {
-            private: private_cose,
-            ..
-        } = CoseKeyPair::from_secret_key(&private_key, iana::Algorithm::ES256);
+        let key_pair = CoseKeyPair::from_secret_key(&private_key, iana::Algorithm::ES256);
+        let private_cose = &key_pair.private;
         let public_signing_key = SigningKey::from(&private_key);
         let public_key = public_signing_key.verifying_key();
 
@@ -204,7 +360,7 @@ mod tests {
         let mut signature_target = auth_data.to_vec();
         signature_target.extend(random_vec(32));
 
-        let secret_key = private_key_from_cose_key(&private_cose).expect("to get a private key");
+        let secret_key = private_key_from_cose_key(private_cose).expect("to get a private key");
 
         let private_key = SigningKey::from(secret_key);
         let signature: p256::ecdsa::Signature = private_key.sign(&signature_target);
@@ -213,4 +369,76 @@ mod tests {
             .verify(&signature_target, &signature)
             .expect("failed to verify signature")
     }
+
+    #[test]
+    fn ed25519_cose_round_trip_sanity_check() {
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+        let (public, private) = CoseKeyPair::from_ed25519(&signing_key).into_parts();
+
+        let recovered = ed25519_key_from_cose_key(&private).expect("to get a private key");
+        assert_eq!(recovered.to_bytes(), signing_key.to_bytes());
+        ed25519_key_from_cose_key(&public).expect_err("public key has no private component");
+
+        let signature_target = random_vec(64);
+        let signature =
+            sign_with_cose_key(&private, &signature_target, None).expect("failed to sign");
+        let signature =
+            ed25519_dalek::Signature::from_slice(&signature).expect("not an ed25519 signature");
+        signing_key
+            .verifying_key()
+            .verify(&signature_target, &signature)
+            .expect("failed to verify signature");
+
+        public_key_der_from_cose_key(&public).expect("could not encode public key as DER");
+    }
+
+    #[test]
+    fn ecdsa_nonce_modes() {
+        let key_pair = CoseKeyPair::from_secret_key(
+            &SecretKey::random(&mut rand::thread_rng()),
+            iana::Algorithm::ES256,
+        );
+        let data = random_vec(64);
+
+        let deterministic = sign_with_cose_key(&key_pair.private, &data, None).unwrap();
+        assert_eq!(
+            deterministic,
+            sign_with_cose_key(&key_pair.private, &data, None).unwrap()
+        );
+
+        let mut rng = rand::rngs::OsRng;
+        let hedged = sign_with_cose_key(&key_pair.private, &data, Some(&mut rng)).unwrap();
+        assert_ne!(deterministic, hedged);
+
+        let public_key = SigningKey::from(private_key_from_cose_key(&key_pair.private).unwrap());
+        let signature = p256::ecdsa::Signature::from_der(&hedged).expect("not a DER signature");
+        public_key
+            .verifying_key()
+            .verify(&data, &signature)
+            .expect("failed to verify hedged signature");
+    }
+
+    #[test]
+    fn ecdsa_der_to_raw_conversion() {
+        let key_pair = CoseKeyPair::from_secret_key(
+            &SecretKey::random(&mut rand::thread_rng()),
+            iana::Algorithm::ES256,
+        );
+        let data = random_vec(64);
+        let der = sign_with_cose_key(&key_pair.private, &data, None).unwrap();
+        let raw = ecdsa_der_to_raw(&key_pair.private, der.clone()).unwrap();
+        assert_eq!(raw.len(), 64);
+        assert_eq!(
+            p256::ecdsa::Signature::from_slice(&raw).unwrap(),
+            p256::ecdsa::Signature::from_der(&der).unwrap()
+        );
+
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+        let private = CoseKeyPair::from_ed25519(&signing_key).into_parts().1;
+        let signature = sign_with_cose_key(&private, &data, None).unwrap();
+        assert_eq!(
+            ecdsa_der_to_raw(&private, signature.clone()).unwrap(),
+            signature
+        );
+    }
 }
@@ -0,0 +1,348 @@
+//! The PIN/UV auth protocols used by `authenticatorClientPIN` to establish a shared secret with
+//! the platform, transport the PIN and PIN token encrypted, and authenticate requests. The
+//! `hmac-secret` extension reuses the same shared secret to encrypt its salts and outputs.
+//!
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-pin-uv-auth-protocols>
+
+use aes::{
+    cipher::{BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes256, Block,
+};
+use coset::{iana, CoseKey, CoseKeyBuilder};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use p256::{
+    elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint},
+    EncodedPoint, PublicKey, SecretKey,
+};
+use passkey_types::{
+    cose::ec2_coordinates,
+    crypto::sha256,
+    ctap2::{StatusCode, U2FError},
+};
+use rand_core::CryptoRngCore;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// The AES block size, PIN protocol ciphertexts are always a multiple of it.
+const BLOCK_SIZE: usize = 16;
+
+/// The size of the HMAC and AES keys of PIN protocol 2.
+const KEY_SIZE: usize = 32;
+
+/// A PIN/UV auth protocol supported by the authenticator.
+///
+/// Besides its use within the [`Authenticator`](crate::Authenticator), this provides the platform
+/// side of the protocol: [`PinProtocol::encapsulate`] establishes a shared secret with the
+/// public key returned by the `getKeyAgreement` subcommand of `authenticatorClientPIN`, with
+/// which the platform then encrypts and authenticates the parameters it sends, such as the
+/// salts of the `hmac-secret` extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinProtocol {
+    /// PIN protocol 1, using AES-256-CBC with a zero IV and HMAC-SHA-256 truncated to 16 bytes.
+    One,
+    /// PIN/UV auth protocol 2, using separate HKDF derived keys for AES-256-CBC with a random IV
+    /// and for HMAC-SHA-256.
+    Two,
+}
+
+impl PinProtocol {
+    /// All supported protocols, in order of preference, as listed in `getInfo`.
+    pub const SUPPORTED: [PinProtocol; 2] = [PinProtocol::Two, PinProtocol::One];
+
+    /// The protocol with the given version number, if supported.
+    pub fn from_version(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(PinProtocol::One),
+            2 => Some(PinProtocol::Two),
+            _ => None,
+        }
+    }
+
+    /// The version number of the protocol.
+    pub fn version(self) -> u8 {
+        match self {
+            PinProtocol::One => 1,
+            PinProtocol::Two => 2,
+        }
+    }
+
+    /// The platform side of the key agreement: generate an ephemeral key pair and derive the
+    /// secret it shares with the authenticator's `getKeyAgreement` public key.
+    ///
+    /// Returns the public key to send to the authenticator as the `keyAgreement` parameter along
+    /// with the shared secret.
+    pub fn encapsulate(
+        self,
+        authenticator_key: &CoseKey,
+        mut rng: &mut dyn CryptoRngCore,
+    ) -> Result<(CoseKey, Zeroizing<Vec<u8>>), StatusCode> {
+        let platform_key = SecretKey::random(&mut rng);
+        let shared_secret = self.decapsulate(&platform_key, authenticator_key)?;
+        Ok((key_agreement_public_key(&platform_key), shared_secret))
+    }
+
+    /// Derive the secret shared between the private `key_agreement` key and the peer's public
+    /// key.
+    ///
+    /// For protocol 2 this is the HMAC key followed by the AES key.
+    pub(crate) fn decapsulate(
+        self,
+        key_agreement: &SecretKey,
+        peer_key: &CoseKey,
+    ) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+        let peer_key = peer_public_key(peer_key)?;
+        let shared =
+            p256::ecdh::diffie_hellman(key_agreement.to_nonzero_scalar(), peer_key.as_affine());
+        match self {
+            PinProtocol::One => Ok(Zeroizing::new(sha256(shared.raw_secret_bytes()).to_vec())),
+            PinProtocol::Two => {
+                let hkdf = Hkdf::<Sha256>::new(Some(&[0; 32]), shared.raw_secret_bytes());
+                let mut secret = Zeroizing::new(vec![0; 2 * KEY_SIZE]);
+                let (hmac_key, aes_key) = secret.split_at_mut(KEY_SIZE);
+                // SAFETY: HKDF-SHA-256 can output up to 255 * 32 bytes.
+                hkdf.expand(b"CTAP2 HMAC key", hmac_key).unwrap();
+                hkdf.expand(b"CTAP2 AES key", aes_key).unwrap();
+                Ok(secret)
+            }
+        }
+    }
+
+    /// Encrypt `plaintext`, whose length must be a multiple of the AES block size, with the
+    /// shared secret.
+    pub fn encrypt(
+        self,
+        shared_secret: &[u8],
+        plaintext: &[u8],
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<Vec<u8>, StatusCode> {
+        match self {
+            PinProtocol::One => cbc_encrypt(shared_secret, &[0; BLOCK_SIZE], plaintext),
+            PinProtocol::Two => {
+                let mut iv = [0; BLOCK_SIZE];
+                rng.fill_bytes(&mut iv);
+                let ciphertext = cbc_encrypt(aes_key(shared_secret)?, &iv, plaintext)?;
+                Ok([iv.as_slice(), &ciphertext].concat())
+            }
+        }
+    }
+
+    /// Decrypt a `ciphertext` produced by the other party with the shared secret.
+    pub fn decrypt(
+        self,
+        shared_secret: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+        match self {
+            PinProtocol::One => cbc_decrypt(shared_secret, &[0; BLOCK_SIZE], ciphertext),
+            PinProtocol::Two => {
+                if ciphertext.len() < BLOCK_SIZE {
+                    return Err(U2FError::InvalidLength.into());
+                }
+                let (iv, ciphertext) = ciphertext.split_at(BLOCK_SIZE);
+                // SAFETY: split at the block size just above
+                cbc_decrypt(aes_key(shared_secret)?, iv.try_into().unwrap(), ciphertext)
+            }
+        }
+    }
+
+    /// Authenticate `message` with `key`, which is either the shared secret or a PIN/UV auth
+    /// token.
+    pub fn authenticate(self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        match self {
+            PinProtocol::One => hmac(key, message).finalize().into_bytes()[..16].to_vec(),
+            PinProtocol::Two => hmac(hmac_key(key), message)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+
+    /// Check in constant time that `signature` authenticates `message` with `key`, which is either
+    /// the shared secret or a PIN/UV auth token.
+    pub fn verify(self, key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            PinProtocol::One => {
+                signature.len() == 16 && hmac(key, message).verify_truncated_left(signature).is_ok()
+            }
+            PinProtocol::Two => hmac(hmac_key(key), message).verify_slice(signature).is_ok(),
+        }
+    }
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(message);
+    mac
+}
+
+/// The HMAC key of protocol 2, the first half of a shared secret or the whole PIN token.
+fn hmac_key(key: &[u8]) -> &[u8] {
+    &key[..key.len().min(KEY_SIZE)]
+}
+
+/// The AES key of protocol 2, the second half of the shared secret.
+fn aes_key(shared_secret: &[u8]) -> Result<&[u8], StatusCode> {
+    shared_secret
+        .get(KEY_SIZE..)
+        .ok_or_else(|| U2FError::InvalidParameter.into())
+}
+
+/// The public key of the authenticator's key agreement key, as returned by `getKeyAgreement`.
+pub(crate) fn key_agreement_public_key(key_agreement: &SecretKey) -> CoseKey {
+    let point = key_agreement.public_key().to_encoded_point(false);
+    // SAFETY: an uncompressed point always has both coordinates.
+    CoseKeyBuilder::new_ec2_pub_key(
+        iana::EllipticCurve::P_256,
+        point.x().unwrap().to_vec(),
+        point.y().unwrap().to_vec(),
+    )
+    .algorithm(iana::Algorithm::ECDH_ES_HKDF_256)
+    .build()
+}
+
+fn peer_public_key(key: &CoseKey) -> Result<PublicKey, StatusCode> {
+    let (x, y) = ec2_coordinates(key).map_err(|_| U2FError::InvalidParameter)?;
+    if x.len() != 32 || y.len() != 32 {
+        return Err(U2FError::InvalidParameter.into());
+    }
+    let point = EncodedPoint::from_affine_coordinates(x.into(), y.into(), false);
+    Option::from(PublicKey::from_encoded_point(&point))
+        .ok_or_else(|| U2FError::InvalidParameter.into())
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256, StatusCode> {
+    Aes256::new_from_slice(key).map_err(|_| U2FError::InvalidParameter.into())
+}
+
+fn cbc_encrypt(key: &[u8], iv: &[u8; BLOCK_SIZE], plaintext: &[u8]) -> Result<Vec<u8>, StatusCode> {
+    if !plaintext.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(U2FError::InvalidLength.into());
+    }
+    let cipher = cipher(key)?;
+    let mut previous = *iv;
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+    for chunk in plaintext.chunks_exact(BLOCK_SIZE) {
+        let mut block = Block::default();
+        block.copy_from_slice(chunk);
+        block
+            .iter_mut()
+            .zip(previous)
+            .for_each(|(byte, chained)| *byte ^= chained);
+        cipher.encrypt_block(&mut block);
+        previous = block.into();
+        ciphertext.extend_from_slice(&block);
+    }
+    Ok(ciphertext)
+}
+
+fn cbc_decrypt(
+    key: &[u8],
+    iv: &[u8; BLOCK_SIZE],
+    ciphertext: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, StatusCode> {
+    if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(U2FError::InvalidLength.into());
+    }
+    let cipher = cipher(key)?;
+    let mut previous = *iv;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(ciphertext.len()));
+    for chunk in ciphertext.chunks_exact(BLOCK_SIZE) {
+        let mut block = Block::default();
+        block.copy_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+        block
+            .iter_mut()
+            .zip(previous)
+            .for_each(|(byte, chained)| *byte ^= chained);
+        previous.copy_from_slice(chunk);
+        plaintext.extend_from_slice(&block);
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_agreement_is_symmetric() {
+        for (protocol, secret_len) in [(PinProtocol::One, 32), (PinProtocol::Two, 64)] {
+            let authenticator = SecretKey::random(&mut rand::thread_rng());
+            let (platform_key, platform_secret) = protocol
+                .encapsulate(
+                    &key_agreement_public_key(&authenticator),
+                    &mut rand::thread_rng(),
+                )
+                .unwrap();
+            let authenticator_secret = protocol.decapsulate(&authenticator, &platform_key).unwrap();
+            assert_eq!(platform_secret, authenticator_secret);
+            assert_eq!(platform_secret.len(), secret_len);
+        }
+    }
+
+    #[test]
+    fn cbc_round_trip() {
+        let mut rng = rand::thread_rng();
+        let key = [7; 32];
+        let plaintext = (0..64).collect::<Vec<u8>>();
+        let ciphertext = PinProtocol::One
+            .encrypt(&key, &plaintext, &mut rng)
+            .unwrap();
+        assert_eq!(ciphertext.len(), plaintext.len());
+        assert_ne!(ciphertext, plaintext);
+        // Identical plaintext blocks must not produce identical ciphertext blocks.
+        let repeated = PinProtocol::One.encrypt(&key, &[1; 32], &mut rng).unwrap();
+        assert_ne!(repeated[..16], repeated[16..]);
+
+        let decrypted = PinProtocol::One.decrypt(&key, &ciphertext).unwrap();
+        assert_eq!(*decrypted, plaintext);
+
+        assert!(PinProtocol::One.encrypt(&key, &[0; 15], &mut rng).is_err());
+        assert!(PinProtocol::One.decrypt(&key, &[]).is_err());
+    }
+
+    #[test]
+    fn cbc_round_trip_with_random_iv() {
+        let mut rng = rand::thread_rng();
+        let shared_secret = [[1; 32], [2; 32]].concat();
+        let plaintext = (0..64).collect::<Vec<u8>>();
+        let ciphertext = PinProtocol::Two
+            .encrypt(&shared_secret, &plaintext, &mut rng)
+            .unwrap();
+        assert_eq!(ciphertext.len(), BLOCK_SIZE + plaintext.len());
+        let other = PinProtocol::Two
+            .encrypt(&shared_secret, &plaintext, &mut rng)
+            .unwrap();
+        assert_ne!(ciphertext, other);
+
+        for ciphertext in [ciphertext, other] {
+            let decrypted = PinProtocol::Two
+                .decrypt(&shared_secret, &ciphertext)
+                .unwrap();
+            assert_eq!(*decrypted, plaintext);
+        }
+        // Protocol 2 only encrypts with the AES half of the secret.
+        assert!(PinProtocol::Two
+            .encrypt(&shared_secret[..32], &plaintext, &mut rng)
+            .is_err());
+        assert!(PinProtocol::Two.decrypt(&shared_secret, &[0; 15]).is_err());
+    }
+
+    #[test]
+    fn authenticate_and_verify() {
+        for (protocol, signature_len) in [(PinProtocol::One, 16), (PinProtocol::Two, 32)] {
+            let key = [3; 32];
+            let signature = protocol.authenticate(&key, b"message");
+            assert_eq!(signature.len(), signature_len);
+            assert!(protocol.verify(&key, b"message", &signature));
+            assert!(!protocol.verify(&key, b"other message", &signature));
+            assert!(!protocol.verify(&key, b"message", &signature[..15]));
+        }
+        // Protocol 2 authenticates with the HMAC half of the shared secret.
+        let shared_secret = [[1; 32], [2; 32]].concat();
+        let signature = PinProtocol::Two.authenticate(&shared_secret, b"message");
+        assert!(PinProtocol::Two.verify(&shared_secret[..32], b"message", &signature));
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use passkey_types::ctap2::StatusCode;
+use zeroize::Zeroize;
+
+#[cfg(doc)]
+use crate::Authenticator;
+
+/// The number of PIN attempts allowed before the authenticator is blocked.
+pub(crate) const MAX_PIN_RETRIES: u8 = 8;
+
+/// The minimum PIN length in Unicode code points until it is raised through
+/// `authenticatorConfig`.
+pub(crate) const DEFAULT_MIN_PIN_LENGTH: u8 = 4;
+
+/// The number of failed built-in user verifications allowed before it is blocked.
+pub(crate) const MAX_UV_RETRIES: u8 = 8;
+
+/// The PIN and user verification state of an authenticator which must survive restarts.
+///
+/// State which is lost on power cycle per the specification, such as the number of consecutive
+/// wrong PINs or the PIN/UV auth token, is kept in memory only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredPin {
+    /// The first 16 bytes of the SHA-256 hash of the current PIN, if one is set.
+    pub pin_hash: Option<[u8; 16]>,
+    /// The length of the current PIN in Unicode code points, zero if none is set.
+    pub pin_length: u8,
+    /// The minimum length of new PINs in Unicode code points. It may only ever increase.
+    pub min_pin_length: u8,
+    /// The number of PIN attempts remaining. The PIN is blocked when this reaches zero.
+    pub retries: u8,
+    /// Whether the PIN must be changed before a PIN/UV auth token can be obtained with it.
+    pub force_change: bool,
+    /// The number of built-in user verification attempts remaining. Built-in user verification
+    /// can no longer be used, to get a PIN/UV auth token or for the "uv" option of a request, when
+    /// this reaches zero.
+    pub uv_retries: u8,
+}
+
+impl Default for StoredPin {
+    fn default() -> Self {
+        Self {
+            pin_hash: None,
+            pin_length: 0,
+            min_pin_length: DEFAULT_MIN_PIN_LENGTH,
+            retries: MAX_PIN_RETRIES,
+            force_change: false,
+            uv_retries: MAX_UV_RETRIES,
+        }
+    }
+}
+
+impl Drop for StoredPin {
+    fn drop(&mut self) {
+        self.pin_hash.zeroize();
+    }
+}
+
+/// A snapshot of an authenticator's PIN and user verification status, see
+/// [`Authenticator::pin_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinState {
+    /// Whether a client PIN has been set.
+    pub is_set: bool,
+    /// The number of PIN attempts remaining. The PIN is blocked when this reaches zero.
+    pub retries: u8,
+    /// Whether built-in user verification can no longer be used, either because its retries are
+    /// exhausted or because it is locked out by a [`UvRateLimit`](crate::UvRateLimit).
+    pub uv_blocked: bool,
+}
+
+/// Use this on a type that persists the [`StoredPin`] of an [`Authenticator`].
+///
+/// The state is loaded once when the store is given to the authenticator and saved every time it
+/// changes, including before every PIN comparison so that a retry cannot be recovered by
+/// interrupting the authenticator.
+pub trait PinStore {
+    /// Load the persisted PIN state, `None` if it was never saved.
+    fn load(&self) -> Option<StoredPin>;
+
+    /// Persist the new PIN state. An error aborts the PIN operation which caused the change.
+    fn save(&mut self, pin: &StoredPin) -> Result<(), StatusCode>;
+}
+
+/// In-memory PIN store, the default of an [`Authenticator`]. The PIN is forgotten along with it.
+impl PinStore for Option<StoredPin> {
+    fn load(&self) -> Option<StoredPin> {
+        self.clone()
+    }
+
+    fn save(&mut self, pin: &StoredPin) -> Result<(), StatusCode> {
+        self.replace(pin.clone());
+        Ok(())
+    }
+}
+
+impl<S: PinStore> PinStore for Arc<Mutex<S>> {
+    fn load(&self) -> Option<StoredPin> {
+        self.lock().unwrap_or_else(PoisonError::into_inner).load()
+    }
+
+    fn save(&mut self, pin: &StoredPin) -> Result<(), StatusCode> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .save(pin)
+    }
+}
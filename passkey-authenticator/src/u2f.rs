@@ -1,18 +1,20 @@
 //! Follows U2F 1.2 <https://fidoalliance.org/specs/fido-u2f-v1.2-ps-20170411/fido-u2f-raw-message-formats-v1.2-ps-20170411.html>
 
-use crate::{Authenticator, CoseKeyPair, CredentialStore, UserValidationMethod};
+use crate::{Authenticator, CredentialStore, UserValidationMethod};
 use coset::iana;
-use p256::{
-    ecdsa::{signature::Signer, SigningKey},
-    SecretKey,
-};
 use passkey_types::{
+    cose::ec2_coordinates,
     ctap2::{Flags, U2FError},
     u2f::{
         AuthenticationRequest, AuthenticationResponse, PublicKey, RegisterRequest, RegisterResponse,
     },
     Bytes, Passkey,
 };
+
+mod server;
+
+pub use self::server::U2fServer;
+
 mod sealed {
     use crate::{Authenticator, CredentialStore, UserValidationMethod};
 
@@ -49,39 +51,53 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
         request: RegisterRequest,
         handle: &[u8],
     ) -> Result<RegisterResponse, U2FError> {
-        // Create Keypair on P256 curve
-        let private_key = {
-            let mut rng = rand::thread_rng();
-            SecretKey::random(&mut rng)
-        };
+        // U2F cannot verify the user, so it is disabled while alwaysUv is enabled.
+        if self.always_uv() {
+            return Err(U2FError::InvalidCommand);
+        }
 
-        // SAFETY: Can only fail if key is malformed
-        let CoseKeyPair { public: _, private } =
-            CoseKeyPair::from_secret_key(&private_key, iana::Algorithm::ES256);
-        let signing_key = SigningKey::from(private_key);
-        let public_key = signing_key.verifying_key();
-        let pub_key_encoded = public_key.to_encoded_point(false);
+        // Create Keypair on P256 curve
+        let key_pair = self
+            .key_provider()
+            .generate_key(iana::Algorithm::ES256, self.rng().as_mut())
+            .map_err(|_| U2FError::Other)?;
 
-        // SAFETY: These unwraps are safe due to the encoding not having any compression (false above)
-        // this makes sure that both x and y points are present in the encoded and are of 32 bytes
-        // in size.
+        // U2F public keys are always the uncompressed x and y coordinates of 32 bytes each.
+        let (x, y) = ec2_coordinates(&key_pair.public).map_err(|_| U2FError::Other)?;
         let public_key = PublicKey {
-            x: pub_key_encoded.x().unwrap().as_slice().try_into().unwrap(),
-            y: pub_key_encoded.y().unwrap().as_slice().try_into().unwrap(),
+            x: x.try_into().map_err(|_| U2FError::Other)?,
+            y: y.try_into().map_err(|_| U2FError::Other)?,
         };
 
-        // create signature, see [`RegisterResponse::signature`]'s documentation for more information
-        let signature_target = [0x00] // 1. reserved byte
-            .into_iter()
-            .chain(request.application) // 2. application parameter
-            .chain(request.challenge) // 3. challenge parameter
-            .chain(handle.iter().copied()) // 4. Key handle
-            .chain(public_key.encode()) // 5. public key
-            .collect::<Vec<u8>>();
-        let signature_singleton: p256::ecdsa::Signature = signing_key.sign(&signature_target);
-        let signature = signature_singleton.to_vec();
-
-        let attestation_certificate = Vec::new();
+        // create signature, see [`RegisterResponse::signature`]'s documentation for more information.
+        // In U2F compatibility mode it is made by the attestation key, otherwise the credential
+        // attests itself.
+        let (attestation_certificate, signature) = match self.u2f_attestation() {
+            Some(attestation) => {
+                let signature = attestation
+                    .sign_registration(
+                        &request.application,
+                        &request.challenge,
+                        handle,
+                        &public_key.encode().collect::<Vec<u8>>(),
+                    )
+                    .map_err(|_| U2FError::Other)?;
+                (attestation.certificate().to_vec(), signature)
+            }
+            None => {
+                let signature_target = [0x00] // 1. reserved byte
+                    .into_iter()
+                    .chain(request.application) // 2. application parameter
+                    .chain(request.challenge) // 3. challenge parameter
+                    .chain(handle.iter().copied()) // 4. Key handle
+                    .chain(public_key.encode()) // 5. public key
+                    .collect::<Vec<u8>>();
+                let signature = self
+                    .sign(&key_pair.private, &signature_target)
+                    .map_err(|_| U2FError::Other)?;
+                (Vec::new(), signature)
+            }
+        };
 
         let response = RegisterResponse {
             public_key,
@@ -90,9 +106,13 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
             signature,
         };
 
-        let (passkey, user, rp) = passkey_types::Passkey::wrap_u2f_registration_request(
-            &request, &response, handle, &private,
+        let (mut passkey, user, rp) = passkey_types::Passkey::wrap_u2f_registration_request(
+            &request,
+            &response,
+            handle,
+            &key_pair.private,
         );
+        (passkey.backup_eligible, passkey.backup_state) = self.backup_flags();
 
         let result = self.store_mut().save_credential(passkey, user, rp).await;
 
@@ -109,6 +129,10 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
         counter: u32,
         user_presence: Flags,
     ) -> Result<AuthenticationResponse, U2FError> {
+        if self.always_uv() {
+            return Err(U2FError::InvalidCommand);
+        }
+
         // Turn the Authentication Request into a PublicKeyCredentialDescriptor and
         // an rp_id in order to find the secret key in our store
 
@@ -118,9 +142,10 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
             transports: None,
         };
         let id_bytes: Bytes = request.application.to_vec().into();
+        let app_id = String::from(id_bytes);
         let maybe_credential = self
             .store()
-            .find_credentials(Some(&[pk_descriptor]), String::from(id_bytes).as_str())
+            .find_credentials(Some(&[pk_descriptor]), &app_id)
             .await
             .map_err(|_| U2FError::Other);
 
@@ -131,9 +156,11 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
             .try_into()
             .map_err(|_| U2FError::Other)?;
 
-        let secret_key =
-            super::private_key_from_cose_key(&credential.key).map_err(|_| U2FError::Other)?;
-        let signing_key = SigningKey::from(secret_key);
+        // Stores may only look up the key handle, but it must also have been registered for this
+        // application.
+        if credential.rp_id != app_id {
+            return Err(U2FError::Other);
+        }
 
         // The following signature_target is specified in the U2F Raw Message Formats spec:
         // https://fidoalliance.org/specs/fido-u2f-v1.2-ps-20170411/fido-u2f-raw-message-formats-v1.2-ps-20170411.html#authentication-response-message-success
@@ -146,8 +173,9 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
             .chain(request.challenge) // 4. The challenge parameter [32 bytes] from the authentication request message.
             .collect::<Vec<u8>>();
 
-        let signature: p256::ecdsa::Signature = signing_key.sign(&signature_target);
-        let signature_bytes = signature.to_der().as_bytes().to_vec();
+        let signature_bytes = self
+            .sign(&credential.key, &signature_target)
+            .map_err(|_| U2FError::Other)?;
 
         Ok(AuthenticationResponse {
             user_presence,
@@ -159,9 +187,11 @@ impl<S: CredentialStore + Sync + Send, U: UserValidationMethod + Sync + Send> U2
 
 #[cfg(test)]
 mod tests {
-    use super::{AuthenticationRequest, Authenticator, RegisterRequest};
-    use crate::{u2f::U2fApi, user_validation::MockUserValidationMethod};
-    use generic_array::GenericArray;
+    use super::{iana, AuthenticationRequest, Authenticator, RegisterRequest};
+    use crate::{
+        u2f::U2fApi, user_validation::MockUserValidationMethod, AttestationChain, CoseKeyPair,
+        FidoU2fAttestation,
+    };
     use p256::{
         ecdsa::{signature::Verifier, Signature, VerifyingKey},
         EncodedPoint,
@@ -194,6 +224,30 @@ mod tests {
         let response = store_result.unwrap();
         let public_key = response.public_key;
 
+        // Recover the VerifyingKey from the uncompressed X, Y points for the public key
+        let ep = EncodedPoint::from_affine_coordinates(
+            &public_key.x.into(),
+            &public_key.y.into(),
+            false,
+        );
+        let verifying_key = VerifyingKey::from_encoded_point(&ep).unwrap();
+
+        // Without an attestation, the registration is signed by the new key itself, in the DER
+        // encoding U2F requires.
+        let signature_target = [0x00]
+            .into_iter()
+            .chain(application)
+            .chain(challenge)
+            .chain(handle)
+            .chain(response.public_key.encode())
+            .collect::<Vec<u8>>();
+        verifying_key
+            .verify(
+                &signature_target,
+                &Signature::from_der(&response.signature).unwrap(),
+            )
+            .expect("registration is not signed by the new key");
+
         // Now generate an authentication challenge using the original application
         let challenge: [u8; 32] = ::rand::random();
         let auth_req = AuthenticationRequest {
@@ -215,14 +269,6 @@ mod tests {
 
         // Now can we verify the signature from the Authenticator using the
         // public key we received above?
-
-        // Recover the VerifyingKey from the uncompressed X, Y points for the public key
-        let ep = EncodedPoint::from_affine_coordinates(
-            &GenericArray::clone_from_slice(&public_key.x),
-            &GenericArray::clone_from_slice(&public_key.y),
-            false,
-        );
-        let verifying_key = VerifyingKey::from_encoded_point(&ep).unwrap();
         let sig = Signature::from_der(&auth_result.signature).unwrap();
 
         // Generate the expected challenge message that
@@ -238,4 +284,60 @@ mod tests {
         // Verify that the given signature is correct for the given message.
         assert!(verifying_key.verify(&signature_target, &sig).is_ok());
     }
+
+    #[tokio::test]
+    async fn register_with_u2f_attestation() {
+        let attestation_key = p256::SecretKey::random(&mut ::rand::thread_rng());
+        let certificate = crate::attestation::chain::tests::certificate(
+            &crate::attestation::chain::tests::spki(&attestation_key),
+            None,
+        );
+        let attestation = FidoU2fAttestation::new(
+            AttestationChain::new(
+                CoseKeyPair::from_secret_key(&attestation_key, iana::Algorithm::ES256)
+                    .into_parts()
+                    .1,
+                vec![certificate.clone()],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut authenticator = Authenticator::new(
+            Aaguid::new_empty(),
+            None::<Passkey>,
+            MockUserValidationMethod::verified_user(0),
+        )
+        .with_u2f_attestation(attestation);
+
+        let request = RegisterRequest {
+            challenge: ::rand::random(),
+            application: ::rand::random(),
+        };
+        let handle: [u8; 16] = ::rand::random();
+        let response = authenticator
+            .register(
+                RegisterRequest {
+                    challenge: request.challenge,
+                    application: request.application,
+                },
+                &handle,
+            )
+            .await
+            .expect("failed to register");
+        assert_eq!(response.attestation_certificate, certificate);
+
+        let signature_target = [0x00]
+            .into_iter()
+            .chain(request.application)
+            .chain(request.challenge)
+            .chain(handle)
+            .chain(response.public_key.encode())
+            .collect::<Vec<u8>>();
+        VerifyingKey::from(attestation_key.public_key())
+            .verify(
+                &signature_target,
+                &Signature::from_der(&response.signature).unwrap(),
+            )
+            .expect("registration is not signed by the attestation key");
+    }
 }
@@ -0,0 +1,334 @@
+use passkey_types::{
+    ctap2::{Flags, U2FError},
+    u2f::{
+        AuthenticationParameter, AuthenticationRequest, RegisterRequest, Request, RequestPayload,
+        ResponseStatusWords, Version,
+    },
+};
+use rand::RngCore;
+
+use super::U2fApi;
+use crate::Ctap2Api;
+
+/// The length of the random key handles given to U2F registrations.
+const KEY_HANDLE_LEN: usize = 32;
+
+/// Handles raw U2F request messages on behalf of an authenticator, so that it can serve clients
+/// which only speak CTAP1/U2F.
+///
+/// User presence is tested with [`Ctap2Api::selection`] and U2F is only offered while the
+/// authenticator lists `U2F_V2` in [`Ctap2Api::get_info`], like when alwaysUv is disabled. The
+/// signatures of U2F cover its own message formats rather than authenticator data, so they are
+/// made through [`U2fApi`].
+///
+/// U2F has a single signature counter for every credential, which starts from
+/// [`U2fServer::with_counter`].
+///
+/// <https://fidoalliance.org/specs/fido-u2f-v1.2-ps-20170411/fido-u2f-raw-message-formats-v1.2-ps-20170411.html>
+pub struct U2fServer<A> {
+    authenticator: A,
+    counter: u32,
+}
+
+impl<A: Ctap2Api + U2fApi + Send + Sync> U2fServer<A> {
+    /// Serve the given authenticator with a signature counter starting from 0.
+    pub fn new(authenticator: A) -> Self {
+        Self {
+            authenticator,
+            counter: 0,
+        }
+    }
+
+    /// Builder method for resuming the signature counter from a previous server, see
+    /// [`U2fServer::counter`].
+    pub fn with_counter(self, counter: u32) -> Self {
+        Self { counter, ..self }
+    }
+
+    /// The current signature counter, which should be persisted to never go back on restarts.
+    pub fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    /// Access the served authenticator.
+    pub fn authenticator(&self) -> &A {
+        &self.authenticator
+    }
+
+    /// Mutably access the served authenticator.
+    pub fn authenticator_mut(&mut self) -> &mut A {
+        &mut self.authenticator
+    }
+
+    /// Stop serving the authenticator and give it back.
+    pub fn into_inner(self) -> A {
+        self.authenticator
+    }
+
+    /// Handle a U2F request message. The response is the response message of the command followed
+    /// by its status word, or only the status word on errors.
+    pub async fn handle(&mut self, message: &[u8]) -> Vec<u8> {
+        match self.dispatch(message).await {
+            Ok(response) => response,
+            Err(status) => status.as_primitive().to_be_bytes().to_vec(),
+        }
+    }
+
+    async fn dispatch(&mut self, message: &[u8]) -> Result<Vec<u8>, ResponseStatusWords> {
+        let request = Request::try_from(message)?;
+        let supported = self
+            .authenticator
+            .get_info()
+            .await
+            .versions
+            .iter()
+            .any(|version| version == "U2F_V2");
+        if !supported {
+            return Err(ResponseStatusWords::InsNotSupported);
+        }
+
+        match request.data {
+            RequestPayload::Register(request) => self.register(request).await,
+            RequestPayload::Authenticate(request) => self.authenticate(request).await,
+            RequestPayload::Version => Ok(Version.encode()),
+        }
+    }
+
+    async fn register(&mut self, request: RegisterRequest) -> Result<Vec<u8>, ResponseStatusWords> {
+        if self.authenticator.selection().await.is_err() {
+            return Err(ResponseStatusWords::ConditionsNotSatisfied);
+        }
+
+        let mut handle = [0; KEY_HANDLE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut handle);
+        self.authenticator
+            .register(request, &handle)
+            .await
+            .map(|response| response.encode())
+            .map_err(status)
+    }
+
+    async fn authenticate(
+        &mut self,
+        request: AuthenticationRequest,
+    ) -> Result<Vec<u8>, ResponseStatusWords> {
+        let user_presence = match request.parameter {
+            AuthenticationParameter::CheckOnly => {
+                // Known key handles are reported with the test-of-user-presence-required error,
+                // without signing anything.
+                return match self
+                    .authenticator
+                    .authenticate(request, self.counter, Flags::empty())
+                    .await
+                {
+                    Ok(_) => Err(ResponseStatusWords::ConditionsNotSatisfied),
+                    Err(error) => Err(status(error)),
+                };
+            }
+            AuthenticationParameter::EnforceUserPresence => {
+                if self.authenticator.selection().await.is_err() {
+                    return Err(ResponseStatusWords::ConditionsNotSatisfied);
+                }
+                Flags::UP
+            }
+            AuthenticationParameter::DontEnforceUserPresence => Flags::empty(),
+        };
+
+        let counter = self.counter.wrapping_add(1);
+        let response = self
+            .authenticator
+            .authenticate(request, counter, user_presence)
+            .await
+            .map_err(status)?;
+        self.counter = counter;
+        Ok(response.encode())
+    }
+}
+
+/// The status word of a failed U2F operation. Apart from disabled commands, failures are reported
+/// as the key handle being invalid, since U2F has no status word for internal errors.
+fn status(error: U2FError) -> ResponseStatusWords {
+    match error {
+        U2FError::InvalidCommand => ResponseStatusWords::InsNotSupported,
+        _ => ResponseStatusWords::WrongData,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use passkey_types::ctap2::Aaguid;
+
+    use super::*;
+    use crate::{user_validation::MockUserValidationMethod, Authenticator, MemoryStore};
+
+    const NO_ERROR: [u8; 2] = [0x90, 0x00];
+
+    fn server(present: bool) -> U2fServer<Authenticator<MemoryStore, MockUserValidationMethod>> {
+        let mut user_mock = MockUserValidationMethod::new();
+        user_mock
+            .expect_check_user_presence()
+            .returning(move || Box::pin(async move { present }));
+        user_mock
+            .expect_is_verification_enabled()
+            .returning(|| None);
+        user_mock.expect_is_presence_enabled().returning(|| true);
+        user_mock.expect_fingerprint_sensor().returning(|| None);
+        U2fServer::new(Authenticator::new(
+            Aaguid::new_empty(),
+            MemoryStore::new(),
+            user_mock,
+        ))
+    }
+
+    /// An extended length request message.
+    fn message(ins: u8, p1: u8, data: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(data.len()).unwrap();
+        [0x00, ins, p1, 0x00, 0x00]
+            .into_iter()
+            .chain(len.to_be_bytes())
+            .chain(data.iter().copied())
+            .collect()
+    }
+
+    fn status(response: &[u8]) -> u16 {
+        u16::from_be_bytes(response[response.len() - 2..].try_into().unwrap())
+    }
+
+    /// Register a credential for `application`, returning its key handle.
+    async fn register(
+        server: &mut U2fServer<Authenticator<MemoryStore, MockUserValidationMethod>>,
+        application: [u8; 32],
+    ) -> Vec<u8> {
+        let challenge: [u8; 32] = ::rand::random();
+        let data = [challenge, application].concat();
+        let response = server.handle(&message(0x01, 0x00, &data)).await;
+        assert!(response.ends_with(&NO_ERROR));
+        assert_eq!(response[0], 0x05);
+        // reserved byte and public key, followed by the key handle's length and the key handle
+        let handle_len = usize::from(response[66]);
+        response[67..67 + handle_len].to_vec()
+    }
+
+    fn authentication(
+        p1: AuthenticationParameter,
+        application: [u8; 32],
+        handle: &[u8],
+    ) -> Vec<u8> {
+        let challenge: [u8; 32] = ::rand::random();
+        let data = challenge
+            .into_iter()
+            .chain(application)
+            .chain([u8::try_from(handle.len()).unwrap()])
+            .chain(handle.iter().copied())
+            .collect::<Vec<u8>>();
+        message(0x02, p1.into(), &data)
+    }
+
+    #[tokio::test]
+    async fn register_and_authenticate() {
+        let mut server = server(true);
+        let application: [u8; 32] = ::rand::random();
+        let handle = register(&mut server, application).await;
+
+        let request = authentication(
+            AuthenticationParameter::EnforceUserPresence,
+            application,
+            &handle,
+        );
+        let response = server.handle(&request).await;
+        assert!(response.ends_with(&NO_ERROR));
+        assert_eq!(response[0], u8::from(Flags::UP));
+        assert_eq!(response[1..5], 1u32.to_be_bytes());
+
+        let response = server.handle(&request).await;
+        assert_eq!(response[1..5], 2u32.to_be_bytes());
+        assert_eq!(server.counter(), 2);
+    }
+
+    #[tokio::test]
+    async fn check_only() {
+        let mut server = server(true);
+        let application: [u8; 32] = ::rand::random();
+        let handle = register(&mut server, application).await;
+
+        let response = server
+            .handle(&authentication(
+                AuthenticationParameter::CheckOnly,
+                application,
+                &handle,
+            ))
+            .await;
+        assert_eq!(
+            status(&response),
+            ResponseStatusWords::ConditionsNotSatisfied.as_primitive()
+        );
+
+        let response = server
+            .handle(&authentication(
+                AuthenticationParameter::CheckOnly,
+                ::rand::random(),
+                &handle,
+            ))
+            .await;
+        assert_eq!(
+            status(&response),
+            ResponseStatusWords::WrongData.as_primitive()
+        );
+        assert_eq!(server.counter(), 0);
+    }
+
+    #[tokio::test]
+    async fn user_presence_is_required() {
+        let mut server = server(false);
+
+        let data = [[0; 32], ::rand::random()].concat();
+        let response = server.handle(&message(0x01, 0x00, &data)).await;
+
+        assert_eq!(
+            response,
+            ResponseStatusWords::ConditionsNotSatisfied
+                .as_primitive()
+                .to_be_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_requests() {
+        let mut server = server(true);
+
+        for (message, expected) in [
+            (vec![0x00, 0x03], ResponseStatusWords::WrongLength),
+            (
+                message(0x01, 0x00, &[0; 12]),
+                ResponseStatusWords::WrongLength,
+            ),
+            (
+                message(0x02, 0x01, &[0; 66]),
+                ResponseStatusWords::WrongData,
+            ),
+            (
+                message(0x02, 0x03, &[0; 64]),
+                ResponseStatusWords::WrongLength,
+            ),
+            (
+                message(0x40, 0x00, &[]),
+                ResponseStatusWords::InsNotSupported,
+            ),
+        ] {
+            assert_eq!(
+                server.handle(&message).await,
+                expected.as_primitive().to_be_bytes()
+            );
+        }
+
+        let mut truncated = message(0x01, 0x00, &[0; 64]);
+        truncated.truncate(40);
+        assert_eq!(
+            status(&server.handle(&truncated).await),
+            ResponseStatusWords::WrongLength.as_primitive()
+        );
+
+        let response = server.handle(&message(0x03, 0x00, &[])).await;
+        assert_eq!(response, [b"U2F_V2".as_slice(), &NO_ERROR].concat());
+    }
+}
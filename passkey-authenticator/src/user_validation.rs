@@ -1,3 +1,10 @@
+use std::time::{Duration, SystemTime};
+
+use passkey_types::ctap2::{
+    bio_enrollment::{FingerprintKind, LastEnrollSampleStatus, TemplateInfo},
+    StatusCode, U2FError,
+};
+
 #[cfg(doc)]
 use crate::Authenticator;
 
@@ -34,6 +41,136 @@ pub trait UserValidationMethod {
     /// If a device is capable of verifying the user within itself as well as able to do Client PIN,
     ///  it will return both `Some` and the Client PIN option.
     fn is_verification_enabled(&self) -> Option<bool>;
+
+    /// The fingerprint sensor used to verify the user, if its enrollments can be managed through
+    /// `authenticatorBioEnrollment`. The other bio enrollment methods are only called when this
+    /// is `Some`.
+    ///
+    /// Defaults to `None`, in which case bio enrollment is not supported.
+    fn fingerprint_sensor(&self) -> Option<FingerprintSensor> {
+        None
+    }
+
+    /// Start a new enrollment and capture its first sample, waiting at most `timeout` for the
+    /// user if given. Returns the ID of the new enrollment along with the outcome of the capture.
+    async fn enroll_begin(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<u8>, EnrollmentSample), StatusCode> {
+        let _ = timeout;
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Capture the next sample of the enrollment started with the given ID, waiting at most
+    /// `timeout` for the user if given.
+    async fn enroll_capture_next_sample(
+        &self,
+        template_id: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<EnrollmentSample, StatusCode> {
+        let _ = (template_id, timeout);
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Abandon the enrollment in progress, stopping any capture.
+    async fn cancel_enrollment(&self) {}
+
+    /// List every enrollment of the sensor.
+    async fn enumerate_enrollments(&self) -> Result<Vec<TemplateInfo>, StatusCode> {
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Name the enrollment with the given ID.
+    async fn set_friendly_name(&self, template_id: &[u8], name: &str) -> Result<(), StatusCode> {
+        let _ = (template_id, name);
+        Err(U2FError::InvalidCommand.into())
+    }
+
+    /// Delete the enrollment with the given ID.
+    async fn remove_enrollment(&self, template_id: &[u8]) -> Result<(), StatusCode> {
+        let _ = template_id;
+        Err(U2FError::InvalidCommand.into())
+    }
+}
+
+/// A fingerprint sensor whose enrollments are managed through `authenticatorBioEnrollment`, see
+/// [`UserValidationMethod::fingerprint_sensor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerprintSensor {
+    /// Whether the finger is placed on or swiped across the sensor.
+    pub kind: FingerprintKind,
+    /// The number of good samples needed to complete an enrollment.
+    pub max_capture_samples_required_for_enroll: u32,
+    /// The maximum length in bytes of the name of an enrollment, if it is limited.
+    pub max_template_friendly_name: Option<u32>,
+}
+
+/// The outcome of capturing a sample of an enrollment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnrollmentSample {
+    /// Whether the sample was good, or what was wrong with it.
+    pub status: LastEnrollSampleStatus,
+    /// The number of good samples still needed, the enrollment is complete at zero.
+    pub remaining_samples: u32,
+}
+
+/// Throttling of the user verification done for the "uv" option of `make_credential` and
+/// `get_assertion`, see [`Authenticator::with_uv_rate_limit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UvRateLimit {
+    /// The number of consecutive failed user verifications after which user verification is
+    /// locked out.
+    pub max_consecutive_failures: u8,
+    /// How long user verification stays locked out. Requests for it fail with
+    /// `CTAP2_ERR_UV_BLOCKED` in the meantime.
+    pub lockout: Duration,
+}
+
+impl Default for UvRateLimit {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: 5,
+            lockout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The consecutive failed user verifications counted against a [`UvRateLimit`].
+#[derive(Debug, Default)]
+pub(crate) struct UvLockout {
+    failures: u8,
+    locked_until: Option<SystemTime>,
+}
+
+impl UvLockout {
+    /// Whether user verification is locked out at `now`. An expired lockout is lifted along with
+    /// the failures which caused it.
+    pub(crate) fn is_locked_out(&mut self, now: SystemTime) -> bool {
+        match self.locked_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                *self = Self::default();
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Count a failed user verification at `now`, returning whether it locked user verification
+    /// out.
+    pub(crate) fn fail(&mut self, limit: &UvRateLimit, now: SystemTime) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < limit.max_consecutive_failures {
+            return false;
+        }
+        self.locked_until = Some(now + limit.lockout);
+        true
+    }
+
+    /// Forget the failures after a successful user verification.
+    pub(crate) fn succeed(&mut self) {
+        self.failures = 0;
+    }
 }
 
 #[cfg(any(test, feature = "testable"))]
@@ -0,0 +1,732 @@
+//! The [Virtual Authenticators] of WebDriver, with which browser automation adds authenticators
+//! and credentials for tests and inspects them afterwards, on top of an [`Authenticator`] storing
+//! its credentials in memory.
+//!
+//! The options and credentials use the JSON encoding of the WebDriver commands, so that their
+//! parameters can be deserialized as received and their results serialized as returned.
+//!
+//! [Virtual Authenticators]: https://w3c.github.io/webauthn/#sctn-automation-virtual-authenticators
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use passkey_types::{
+    ctap2::{
+        make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+        Aaguid, Ctap2Error, StatusCode,
+    },
+    encoding,
+    webauthn::{self, PublicKeyCredentialDescriptor},
+    Passkey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attestation::chain::{cose_key_from_pkcs8, pkcs8_from_cose_key},
+    Authenticator, CredentialStore, DiscoverabilitySupport, DiscoverableCredential, Extension,
+    MemoryStore, StoredCredential, UserValidationMethod,
+};
+
+/// The errors of the commands of [`VirtualAuthenticators`], by WebDriver error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebDriverError {
+    /// No authenticator or credential has the given ID, or a parameter is not valid.
+    InvalidArgument,
+    /// The options of a new authenticator ask for an extension which is not supported.
+    UnsupportedOperation,
+}
+
+impl WebDriverError {
+    /// The WebDriver error code of the error, as returned to the remote end.
+    pub fn error_code(self) -> &'static str {
+        match self {
+            WebDriverError::InvalidArgument => "invalid argument",
+            WebDriverError::UnsupportedOperation => "unsupported operation",
+        }
+    }
+}
+
+/// The protocol a virtual authenticator speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VirtualProtocol {
+    /// CTAP1/U2F, which neither stores discoverable credentials nor verifies the user.
+    #[serde(rename = "ctap1/u2f")]
+    Ctap1U2f,
+    /// CTAP 2.0.
+    #[serde(rename = "ctap2")]
+    Ctap2,
+    /// CTAP 2.1.
+    #[serde(rename = "ctap2_1")]
+    Ctap2_1,
+}
+
+/// The parameters of the Add Virtual Authenticator command.
+///
+/// <https://w3c.github.io/webauthn/#sctn-automation-add-virtual-authenticator>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualAuthenticatorOptions {
+    /// The protocol the authenticator speaks.
+    pub protocol: VirtualProtocol,
+    /// The transport through which the authenticator is reached, which makes it a platform
+    /// authenticator when it is `internal`.
+    pub transport: webauthn::AuthenticatorTransport,
+    /// Whether the authenticator stores discoverable credentials.
+    #[serde(default)]
+    pub has_resident_key: bool,
+    /// Whether the authenticator can verify the user.
+    #[serde(default)]
+    pub has_user_verification: bool,
+    /// Whether the user consents to the operations of the authenticator.
+    #[serde(default = "consenting")]
+    pub is_user_consenting: bool,
+    /// Whether user verification succeeds, when the authenticator can verify the user.
+    #[serde(default)]
+    pub is_user_verified: bool,
+    /// The identifiers of the extensions the authenticator supports, of which `prf` and
+    /// `hmac-secret` are.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Whether new credentials are backup eligible.
+    #[serde(default)]
+    pub default_backup_eligibility: bool,
+    /// Whether new credentials are backed up.
+    #[serde(default)]
+    pub default_backup_state: bool,
+}
+
+/// Users consent to the operations of virtual authenticators by default.
+fn consenting() -> bool {
+    true
+}
+
+/// A credential of a virtual authenticator, as given to the Add Credential command and returned by
+/// the Get Credentials command. Binary values are base64url encoded.
+///
+/// <https://w3c.github.io/webauthn/#credential-parameters>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualCredential {
+    /// The ID of the credential.
+    pub credential_id: String,
+    /// Whether the credential is discoverable.
+    pub is_resident_credential: bool,
+    /// The RP ID of the credential.
+    pub rp_id: String,
+    /// The private key of the credential, as a PKCS#8 document.
+    pub private_key: String,
+    /// The user handle of the credential, which discoverable credentials must have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_handle: Option<String>,
+    /// The signature counter of the credential.
+    #[serde(default)]
+    pub sign_count: u32,
+    /// The large blob of the credential, which virtual authenticators do not support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large_blob: Option<String>,
+    /// Whether the credential is backup eligible, the default of the authenticator when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_eligibility: Option<bool>,
+    /// Whether the credential is backed up, the default of the authenticator when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_state: Option<bool>,
+    /// The name of the user of the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    /// The display name of the user of the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_display_name: Option<String>,
+}
+
+/// The user of a virtual authenticator, who consents and is verified as its options tell.
+pub struct VirtualUser {
+    consenting: bool,
+    verification: bool,
+    verified: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl UserValidationMethod for VirtualUser {
+    async fn check_user_verification(&self) -> bool {
+        self.consenting && self.verified.load(Ordering::Relaxed)
+    }
+
+    async fn check_user_presence(&self) -> bool {
+        self.consenting
+    }
+
+    fn is_presence_enabled(&self) -> bool {
+        true
+    }
+
+    fn is_verification_enabled(&self) -> Option<bool> {
+        self.verification.then_some(true)
+    }
+}
+
+/// The credentials of a virtual authenticator, along with their users.
+#[derive(Default)]
+pub struct VirtualStore {
+    credentials: MemoryStore,
+    resident_keys: bool,
+}
+
+#[async_trait::async_trait]
+impl CredentialStore for VirtualStore {
+    type PasskeyItem = Passkey;
+
+    async fn find_credentials(
+        &self,
+        ids: Option<&[PublicKeyCredentialDescriptor]>,
+        rp_id: &str,
+    ) -> Result<Vec<Passkey>, StatusCode> {
+        self.credentials.find_credentials(ids, rp_id).await
+    }
+
+    async fn save_credential(
+        &mut self,
+        cred: Passkey,
+        user: PublicKeyCredentialUserEntity,
+        rp: PublicKeyCredentialRpEntity,
+    ) -> Result<(), StatusCode> {
+        if cred.user_handle.is_some() && !self.resident_keys {
+            return Err(Ctap2Error::UnsupportedOption.into());
+        }
+        self.credentials.save_credential(cred, user, rp).await
+    }
+
+    async fn discoverable_credentials(&self) -> Result<Vec<DiscoverableCredential>, StatusCode> {
+        self.credentials.discoverable_credentials().await
+    }
+
+    async fn enumerate(&self, rp_id: Option<&str>) -> Result<Vec<Passkey>, StatusCode> {
+        self.credentials.enumerate(rp_id).await
+    }
+
+    async fn saved_user(&self, credential_id: &[u8]) -> Option<PublicKeyCredentialUserEntity> {
+        self.credentials.saved_user(credential_id).await
+    }
+
+    async fn discoverability(&self) -> DiscoverabilitySupport {
+        if self.resident_keys {
+            DiscoverabilitySupport::Full
+        } else {
+            DiscoverabilitySupport::OnlyNonDiscoverable
+        }
+    }
+
+    async fn delete_credential(&mut self, credential_id: &[u8]) -> Result<(), StatusCode> {
+        self.credentials.delete_credential(credential_id).await
+    }
+
+    async fn update_user(
+        &mut self,
+        credential_id: &[u8],
+        user: PublicKeyCredentialUserEntity,
+    ) -> Result<(), StatusCode> {
+        self.credentials.update_user(credential_id, user).await
+    }
+
+    async fn set_backup_state(
+        &mut self,
+        credential_id: &[u8],
+        backup_state: bool,
+    ) -> Result<(), StatusCode> {
+        self.credentials
+            .set_backup_state(credential_id, backup_state)
+            .await
+    }
+
+    async fn update_last_used(
+        &mut self,
+        credential_id: &[u8],
+        used_at: SystemTime,
+    ) -> Result<(), StatusCode> {
+        self.credentials
+            .update_last_used(credential_id, used_at)
+            .await
+    }
+
+    async fn clear_all(&mut self) -> Result<(), StatusCode> {
+        self.credentials.clear_all().await
+    }
+}
+
+/// A virtual authenticator, with the switch of the verification of its user.
+struct VirtualAuthenticator {
+    authenticator: Authenticator<VirtualStore, VirtualUser>,
+    user_verified: Arc<AtomicBool>,
+}
+
+/// The virtual authenticators of a WebDriver session, by authenticator ID.
+///
+/// Each command of the WebDriver extension maps to a method, failing with the
+/// [`WebDriverError`] the command returns.
+#[derive(Default)]
+pub struct VirtualAuthenticators {
+    authenticators: HashMap<String, VirtualAuthenticator>,
+    next_id: u64,
+}
+
+impl VirtualAuthenticators {
+    /// Create a session without authenticators.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add Virtual Authenticator: add an authenticator with the given `options`, returning its
+    /// ID.
+    ///
+    /// Fails with [`WebDriverError::UnsupportedOperation`] when one of the extensions is not
+    /// supported, and with [`WebDriverError::InvalidArgument`] when a CTAP1/U2F authenticator is
+    /// to store discoverable credentials or verify the user.
+    pub fn add_authenticator(
+        &mut self,
+        options: VirtualAuthenticatorOptions,
+    ) -> Result<String, WebDriverError> {
+        let extensions = options
+            .extensions
+            .iter()
+            .map(|extension| match extension.as_str() {
+                "prf" | "hmac-secret" => Ok(Extension::HmacSecret),
+                _ => Err(WebDriverError::UnsupportedOperation),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if options.protocol == VirtualProtocol::Ctap1U2f
+            && (options.has_resident_key || options.has_user_verification)
+        {
+            return Err(WebDriverError::InvalidArgument);
+        }
+
+        let user_verified = Arc::new(AtomicBool::new(options.is_user_verified));
+        let user = VirtualUser {
+            consenting: options.is_user_consenting,
+            verification: options.has_user_verification,
+            verified: user_verified.clone(),
+        };
+        let store = VirtualStore {
+            resident_keys: options.has_resident_key,
+            ..Default::default()
+        };
+        let authenticator = Authenticator::new(Aaguid::new_empty(), store, user)
+            .transports(vec![options.transport])
+            .with_builtin_extensions(extensions)
+            .with_backup_flags(
+                options.default_backup_eligibility,
+                options.default_backup_state,
+            );
+
+        self.next_id += 1;
+        let id = format!("virtual-authenticator-{}", self.next_id);
+        self.authenticators.insert(
+            id.clone(),
+            VirtualAuthenticator {
+                authenticator,
+                user_verified,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Remove Virtual Authenticator: remove the authenticator with the given ID along with its
+    /// credentials.
+    pub fn remove_authenticator(&mut self, authenticator_id: &str) -> Result<(), WebDriverError> {
+        self.authenticators
+            .remove(authenticator_id)
+            .map(|_| ())
+            .ok_or(WebDriverError::InvalidArgument)
+    }
+
+    /// The authenticator with the given ID, through which requests are made to it.
+    pub fn authenticator(
+        &self,
+        authenticator_id: &str,
+    ) -> Option<&Authenticator<VirtualStore, VirtualUser>> {
+        self.authenticators
+            .get(authenticator_id)
+            .map(|virtual_authenticator| &virtual_authenticator.authenticator)
+    }
+
+    /// Write access to the authenticator with the given ID, through which requests are made to
+    /// it.
+    pub fn authenticator_mut(
+        &mut self,
+        authenticator_id: &str,
+    ) -> Option<&mut Authenticator<VirtualStore, VirtualUser>> {
+        self.authenticators
+            .get_mut(authenticator_id)
+            .map(|virtual_authenticator| &mut virtual_authenticator.authenticator)
+    }
+
+    fn get_mut(
+        &mut self,
+        authenticator_id: &str,
+    ) -> Result<&mut VirtualAuthenticator, WebDriverError> {
+        self.authenticators
+            .get_mut(authenticator_id)
+            .ok_or(WebDriverError::InvalidArgument)
+    }
+
+    /// Add Credential: import a `credential` into the authenticator with the given ID, replacing
+    /// any credential with the same ID.
+    ///
+    /// Fails with [`WebDriverError::InvalidArgument`] when a parameter is not valid base64url,
+    /// the private key is not a PKCS#8 document of a supported algorithm, a discoverable
+    /// credential has no user handle or the authenticator does not store discoverable
+    /// credentials, or a large blob is given.
+    pub fn add_credential(
+        &mut self,
+        authenticator_id: &str,
+        credential: VirtualCredential,
+    ) -> Result<(), WebDriverError> {
+        let virtual_authenticator = self.get_mut(authenticator_id)?;
+        let store = virtual_authenticator.authenticator.store_mut();
+        if credential.large_blob.is_some()
+            || (credential.is_resident_credential && !store.resident_keys)
+        {
+            return Err(WebDriverError::InvalidArgument);
+        }
+        let decode = |value: &str| {
+            encoding::try_from_base64url(value).ok_or(WebDriverError::InvalidArgument)
+        };
+        let credential_id = decode(&credential.credential_id)?;
+        let private_key = decode(&credential.private_key)?;
+        let key = cose_key_from_pkcs8(&private_key).map_err(|_| WebDriverError::InvalidArgument)?;
+        let user_handle = if credential.is_resident_credential {
+            let user_handle = credential
+                .user_handle
+                .as_deref()
+                .ok_or(WebDriverError::InvalidArgument)?;
+            Some(decode(user_handle)?.into())
+        } else {
+            None
+        };
+        let (default_eligibility, default_state) =
+            virtual_authenticator.authenticator.backup_flags();
+        let backup_eligible = credential.backup_eligibility.unwrap_or(default_eligibility);
+
+        let store = virtual_authenticator.authenticator.store_mut();
+        store.credentials.insert(
+            credential_id.clone(),
+            StoredCredential {
+                user: PublicKeyCredentialUserEntity {
+                    id: user_handle.clone().unwrap_or_default(),
+                    name: credential.user_name,
+                    display_name: credential.user_display_name,
+                    icon_url: None,
+                },
+                rp: PublicKeyCredentialRpEntity {
+                    id: credential.rp_id.clone(),
+                    name: None,
+                },
+                passkey: Passkey {
+                    key,
+                    credential_id: credential_id.into(),
+                    rp_id: credential.rp_id,
+                    user_handle,
+                    counter: Some(credential.sign_count),
+                    third_party_payment: false,
+                    backup_eligible,
+                    backup_state: credential.backup_state.unwrap_or(default_state),
+                    cred_randoms: None,
+                    created_at: None,
+                    last_used_at: None,
+                },
+            },
+        );
+        virtual_authenticator.authenticator.invalidate_info();
+        Ok(())
+    }
+
+    /// Get Credentials: every credential of the authenticator with the given ID.
+    ///
+    /// Credentials whose private key cannot be encoded as a PKCS#8 document are left out.
+    pub fn credentials(
+        &self,
+        authenticator_id: &str,
+    ) -> Result<Vec<VirtualCredential>, WebDriverError> {
+        let store = self
+            .authenticator(authenticator_id)
+            .ok_or(WebDriverError::InvalidArgument)?
+            .store();
+        Ok(store
+            .credentials
+            .values()
+            .filter_map(|StoredCredential { passkey, user, .. }| {
+                Some(VirtualCredential {
+                    credential_id: encoding::base64url(&passkey.credential_id),
+                    is_resident_credential: passkey.user_handle.is_some(),
+                    rp_id: passkey.rp_id.clone(),
+                    private_key: encoding::base64url(&pkcs8_from_cose_key(&passkey.key)?),
+                    user_handle: passkey
+                        .user_handle
+                        .as_ref()
+                        .map(|user_handle| encoding::base64url(user_handle)),
+                    sign_count: passkey.counter.unwrap_or(0),
+                    large_blob: None,
+                    backup_eligibility: Some(passkey.backup_eligible),
+                    backup_state: Some(passkey.backup_state),
+                    user_name: user.name.clone(),
+                    user_display_name: user.display_name.clone(),
+                })
+            })
+            .collect())
+    }
+
+    /// Remove Credential: delete the credential with the given base64url ID from the
+    /// authenticator with the given ID.
+    pub fn remove_credential(
+        &mut self,
+        authenticator_id: &str,
+        credential_id: &str,
+    ) -> Result<(), WebDriverError> {
+        let credential_id =
+            encoding::try_from_base64url(credential_id).ok_or(WebDriverError::InvalidArgument)?;
+        let virtual_authenticator = self.get_mut(authenticator_id)?;
+        let store = virtual_authenticator.authenticator.store_mut();
+        store
+            .credentials
+            .remove(&credential_id)
+            .ok_or(WebDriverError::InvalidArgument)?;
+        virtual_authenticator.authenticator.invalidate_info();
+        Ok(())
+    }
+
+    /// Remove All Credentials: delete every credential of the authenticator with the given ID.
+    pub fn remove_all_credentials(&mut self, authenticator_id: &str) -> Result<(), WebDriverError> {
+        let virtual_authenticator = self.get_mut(authenticator_id)?;
+        let store = virtual_authenticator.authenticator.store_mut();
+        store.credentials.clear();
+        virtual_authenticator.authenticator.invalidate_info();
+        Ok(())
+    }
+
+    /// Set User Verified: whether user verification succeeds on the authenticator with the given
+    /// ID.
+    pub fn set_user_verified(
+        &mut self,
+        authenticator_id: &str,
+        is_user_verified: bool,
+    ) -> Result<(), WebDriverError> {
+        self.get_mut(authenticator_id)?
+            .user_verified
+            .store(is_user_verified, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Set Credential Properties: update the backup flags of the credential with the given
+    /// base64url ID on the authenticator with the given ID, leaving those which are `None`.
+    pub fn set_credential_properties(
+        &mut self,
+        authenticator_id: &str,
+        credential_id: &str,
+        backup_eligibility: Option<bool>,
+        backup_state: Option<bool>,
+    ) -> Result<(), WebDriverError> {
+        let credential_id =
+            encoding::try_from_base64url(credential_id).ok_or(WebDriverError::InvalidArgument)?;
+        let passkey = self
+            .get_mut(authenticator_id)?
+            .authenticator
+            .store_mut()
+            .credentials
+            .get_mut(&credential_id)
+            .map(|credential| &mut credential.passkey)
+            .ok_or(WebDriverError::InvalidArgument)?;
+        if let Some(backup_eligibility) = backup_eligibility {
+            passkey.backup_eligible = backup_eligibility;
+        }
+        if let Some(backup_state) = backup_state {
+            passkey.backup_state = backup_state;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::{pkcs8::EncodePrivateKey, SecretKey};
+    use passkey_types::{
+        ctap2::{get_assertion, Flags},
+        rand::random_vec,
+    };
+
+    use super::*;
+
+    fn options(json: serde_json::Value) -> VirtualAuthenticatorOptions {
+        serde_json::from_value(json).expect("could not deserialize the options")
+    }
+
+    fn assertion_request(credential_id: &[u8]) -> get_assertion::Request {
+        get_assertion::Request {
+            rp_id: "future.1password.com".into(),
+            client_data_hash: random_vec(32).into(),
+            allow_list: Some(vec![PublicKeyCredentialDescriptor {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                id: credential_id.to_vec().into(),
+                transports: None,
+            }]),
+            extensions: None,
+            options: get_assertion::Options {
+                rk: false,
+                up: true,
+                uv: true,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_are_added_used_and_removed() {
+        let mut session = VirtualAuthenticators::new();
+        let id = session
+            .add_authenticator(options(serde_json::json!({
+                "protocol": "ctap2",
+                "transport": "usb",
+                "hasResidentKey": true,
+                "hasUserVerification": true,
+                "isUserVerified": true,
+            })))
+            .expect("failed to add an authenticator");
+
+        let private_key = SecretKey::random(&mut rand::thread_rng())
+            .to_pkcs8_der()
+            .unwrap();
+        let credential_id = random_vec(16);
+        let credential = VirtualCredential {
+            credential_id: encoding::base64url(&credential_id),
+            is_resident_credential: true,
+            rp_id: "future.1password.com".into(),
+            private_key: encoding::base64url(private_key.as_bytes()),
+            user_handle: Some(encoding::base64url(b"wendy")),
+            sign_count: 0,
+            large_blob: None,
+            backup_eligibility: None,
+            backup_state: None,
+            user_name: Some("wendy".into()),
+            user_display_name: None,
+        };
+        session
+            .add_credential(&id, credential.clone())
+            .expect("failed to add a credential");
+
+        let response = session
+            .authenticator_mut(&id)
+            .unwrap()
+            .get_assertion(assertion_request(&credential_id))
+            .await
+            .expect("failed to assert the added credential");
+        assert!(response.auth_data.flags.contains(Flags::UV));
+        assert_eq!(
+            session.credentials(&id),
+            Ok(vec![VirtualCredential {
+                backup_eligibility: Some(false),
+                backup_state: Some(false),
+                ..credential
+            }])
+        );
+
+        session.set_user_verified(&id, false).unwrap();
+        session
+            .authenticator_mut(&id)
+            .unwrap()
+            .get_assertion(assertion_request(&credential_id))
+            .await
+            .expect_err("the user should not be verified");
+
+        let encoded_id = encoding::base64url(&credential_id);
+        session
+            .set_credential_properties(&id, &encoded_id, Some(true), Some(true))
+            .unwrap();
+        let credentials = session.credentials(&id).unwrap();
+        assert_eq!(credentials[0].backup_state, Some(true));
+        session.remove_credential(&id, &encoded_id).unwrap();
+        assert_eq!(session.credentials(&id), Ok(Vec::new()));
+        assert_eq!(
+            session.remove_credential(&id, &encoded_id),
+            Err(WebDriverError::InvalidArgument)
+        );
+
+        session.remove_authenticator(&id).unwrap();
+        assert_eq!(
+            session.credentials(&id),
+            Err(WebDriverError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn invalid_commands_are_refused() {
+        let mut session = VirtualAuthenticators::new();
+        assert_eq!(
+            session.add_authenticator(options(serde_json::json!({
+                "protocol": "ctap2_1",
+                "transport": "internal",
+                "extensions": ["credBlob"],
+            }))),
+            Err(WebDriverError::UnsupportedOperation)
+        );
+        assert_eq!(
+            session.add_authenticator(options(serde_json::json!({
+                "protocol": "ctap1/u2f",
+                "transport": "usb",
+                "hasResidentKey": true,
+            }))),
+            Err(WebDriverError::InvalidArgument)
+        );
+
+        let id = session
+            .add_authenticator(options(serde_json::json!({
+                "protocol": "ctap2",
+                "transport": "nfc",
+                "extensions": ["prf"],
+            })))
+            .expect("failed to add an authenticator");
+        let private_key = SecretKey::random(&mut rand::thread_rng())
+            .to_pkcs8_der()
+            .unwrap();
+        let credential = VirtualCredential {
+            credential_id: encoding::base64url(&random_vec(16)),
+            is_resident_credential: true,
+            rp_id: "future.1password.com".into(),
+            private_key: encoding::base64url(private_key.as_bytes()),
+            user_handle: Some(encoding::base64url(b"wendy")),
+            sign_count: 0,
+            large_blob: None,
+            backup_eligibility: None,
+            backup_state: None,
+            user_name: None,
+            user_display_name: None,
+        };
+        // The authenticator does not store discoverable credentials.
+        assert_eq!(
+            session.add_credential(&id, credential.clone()),
+            Err(WebDriverError::InvalidArgument)
+        );
+        assert_eq!(
+            session.add_credential(
+                &id,
+                VirtualCredential {
+                    is_resident_credential: false,
+                    private_key: encoding::base64url(b"not a key"),
+                    ..credential.clone()
+                }
+            ),
+            Err(WebDriverError::InvalidArgument)
+        );
+        assert_eq!(
+            session.add_credential("unknown", credential),
+            Err(WebDriverError::InvalidArgument)
+        );
+        assert_eq!(
+            WebDriverError::InvalidArgument.error_code(),
+            "invalid argument"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Cancellation of the request of a client in progress, such as when the user dismisses the UI of
+//! the request or closes the page which made it, in the manner of an `AbortSignal`.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-abort-operations>
+
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Poll, Waker},
+};
+
+use crate::WebauthnError;
+
+/// A handle with which the request of a [`Client`](crate::Client) in progress is aborted, see
+/// [`Client::abort_handle`](crate::Client::abort_handle).
+///
+/// Handles can be cloned and sent to the UI of the host, since the client is borrowed for as long
+/// as its request is in progress.
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    state: Arc<Mutex<AbortState>>,
+}
+
+#[derive(Debug, Default)]
+struct AbortState {
+    in_progress: bool,
+    aborted: bool,
+    waker: Option<Waker>,
+}
+
+impl AbortHandle {
+    /// Abort the request in progress, which then fails with [`WebauthnError::Aborted`] and
+    /// stops waiting for the user. Requests made afterwards are not affected.
+    pub fn abort(&self) {
+        let mut state = self.state();
+        if state.in_progress {
+            state.aborted = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, AbortState> {
+        // The state is always left consistent, so it is still usable after a panic.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Run the `operation` of a request until it completes or the request is aborted, dropping it
+    /// in the latter case so that its pending check of the user is abandoned.
+    pub(crate) async fn run<T>(
+        &self,
+        operation: impl Future<Output = Result<T, WebauthnError>>,
+    ) -> Result<T, WebauthnError> {
+        *self.state() = AbortState {
+            in_progress: true,
+            ..Default::default()
+        };
+        let mut operation = pin!(operation);
+        let result = poll_fn(|cx| {
+            {
+                let mut state = self.state();
+                if state.aborted {
+                    return Poll::Ready(Err(WebauthnError::Aborted));
+                }
+                state.waker = Some(cx.waker().clone());
+            }
+            operation.as_mut().poll(cx)
+        })
+        .await;
+        *self.state() = AbortState::default();
+        result
+    }
+}
@@ -0,0 +1,59 @@
+//! Selection of the account to sign in with when a request allows any credential and several
+//! discoverable credentials of its Relying Party are found, in the manner of the account chooser
+//! browsers show.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-discoverable-credential-details>
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn, Bytes, Passkey};
+
+use crate::{Client, CredentialChoice, WebauthnError};
+
+/// Use this on the UI of the host in which the user chooses the account to sign in with, see
+/// [`Client::with_account_selector`].
+#[async_trait::async_trait]
+pub trait AccountSelector {
+    /// Ask the user which of the `accounts` of the Relying Party with the given ID to sign in
+    /// with, telling them apart by their [`CredentialChoice::user`], and return the
+    /// [`CredentialChoice::id`] of the chosen one. Return `None` when the user cancels.
+    async fn select_account(&self, rp_id: &str, accounts: &[CredentialChoice]) -> Option<Bytes>;
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Let the user choose through the [`AccountSelector`] which of the discoverable credentials
+    /// of `rp_id` to assert, returning `None` when there is no selector, or when at most one
+    /// credential is found and the authenticator can pick it alone.
+    ///
+    /// Fails with [`WebauthnError::CredentialNotFound`] when the user cancels.
+    pub(crate) async fn select_account(
+        &self,
+        rp_id: &str,
+    ) -> Result<Option<webauthn::PublicKeyCredentialDescriptor>, WebauthnError> {
+        let Some(selector) = self.account_selector.as_deref() else {
+            return Ok(None);
+        };
+        // Stores which cannot enumerate their credentials leave the choice to the authenticator.
+        let Ok(accounts) = self.silently_discover_credentials(rp_id).await else {
+            return Ok(None);
+        };
+        if accounts.len() < 2 {
+            return Ok(None);
+        }
+        let id = selector
+            .select_account(rp_id, &accounts)
+            .await
+            .filter(|id| accounts.iter().any(|account| account.id == *id))
+            .ok_or(WebauthnError::CredentialNotFound)?;
+        Ok(Some(webauthn::PublicKeyCredentialDescriptor {
+            ty: webauthn::PublicKeyCredentialType::PublicKey,
+            id,
+            transports: None,
+        }))
+    }
+}
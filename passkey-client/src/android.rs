@@ -0,0 +1,121 @@
+//! Requests from Android apps, whose origin is the hash of the certificate the app is signed
+//! with, `android:apk-key-hash:{base64url}`, rather than a web origin. A Relying Party lets apps
+//! use its credentials by listing them in the Digital Asset Links it serves at
+//! `https://{RP ID}/.well-known/assetlinks.json`.
+//!
+//! <https://developer.android.com/identity/sign-in/credential-manager#add-support-dal>
+
+use std::collections::HashMap;
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{encoding, Passkey};
+use url::Url;
+
+use crate::{Client, WebauthnError};
+
+/// The scheme of the origin of requests from Android apps.
+const ANDROID_SCHEME: &str = "android";
+
+/// The prefix of the path of the origin of requests from Android apps, followed by the base64url
+/// SHA-256 hash of the certificate the app is signed with.
+const APK_KEY_HASH: &str = "apk-key-hash:";
+
+/// An Android app which may use the credentials of a Relying Party, as the target of a
+/// `delegate_permission/common.get_login_creds` statement of its Digital Asset Links.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AndroidApp {
+    /// The package name of the app, such as `com.example.app`.
+    pub package_name: String,
+    /// The SHA-256 fingerprints of the certificates the app may be signed with, as colon
+    /// separated hexadecimal bytes such as `14:6D:E9:...`.
+    pub sha256_cert_fingerprints: Vec<String>,
+}
+
+impl AndroidApp {
+    /// Whether the app may be signed with the certificate whose SHA-256 hash is `key_hash`.
+    fn is_signed_with(&self, key_hash: &[u8]) -> bool {
+        self.sha256_cert_fingerprints
+            .iter()
+            .any(|fingerprint| parse_fingerprint(fingerprint).as_deref() == Some(key_hash))
+    }
+}
+
+/// Use this on a type that provides the Android apps a Relying Party lets use its credentials, for
+/// example from its Digital Asset Links, see [`Client::with_android_apps`].
+#[async_trait::async_trait]
+pub trait AndroidAppsProvider {
+    /// The apps of the Relying Party with the given ID, or `None` if it has none or its asset
+    /// links could not be fetched or parsed.
+    async fn android_apps(&self, rp_id: &str) -> Option<Vec<AndroidApp>>;
+}
+
+/// Apps supplied ahead of time, by RP ID.
+#[async_trait::async_trait]
+impl AndroidAppsProvider for HashMap<String, Vec<AndroidApp>> {
+    async fn android_apps(&self, rp_id: &str) -> Option<Vec<AndroidApp>> {
+        self.get(rp_id).cloned()
+    }
+}
+
+/// Whether `origin` is the origin of an Android app.
+pub(crate) fn is_app_origin(origin: &Url) -> bool {
+    origin.scheme() == ANDROID_SCHEME
+}
+
+/// The SHA-256 hash of the signing certificate of the app making a request from `origin`, or
+/// `None` if the origin is malformed.
+fn apk_key_hash(origin: &Url) -> Option<Vec<u8>> {
+    origin
+        .path()
+        .strip_prefix(APK_KEY_HASH)
+        .and_then(encoding::try_from_base64url)
+}
+
+/// The bytes of a fingerprint such as `14:6D:E9:...`.
+fn parse_fingerprint(fingerprint: &str) -> Option<Vec<u8>> {
+    fingerprint
+        .split(':')
+        .map(|byte| {
+            (byte.len() == 2)
+                .then(|| u8::from_str_radix(byte, 16).ok())
+                .flatten()
+        })
+        .collect()
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Verify that the Android app making a request from `origin` may use `rp_id`, which it must
+    /// give since its origin has no domain.
+    ///
+    /// Fails with [`WebauthnError::UnprotectedOrigin`] without an [`AndroidAppsProvider`] or when
+    /// the origin is malformed, like other origins not using HTTPS, and with
+    /// [`WebauthnError::OriginRpMissmatch`] when the Relying Party does not list an app signed
+    /// with the certificate of the origin.
+    pub(crate) async fn assert_android_app<'a>(
+        &self,
+        origin: &Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError> {
+        let provider = self
+            .android_apps
+            .as_deref()
+            .ok_or(WebauthnError::UnprotectedOrigin)?;
+        let key_hash = apk_key_hash(origin).ok_or(WebauthnError::UnprotectedOrigin)?;
+        let rp_id = rp_id.ok_or(WebauthnError::OriginMissingDomain)?;
+        let apps = provider
+            .android_apps(rp_id)
+            .await
+            .ok_or(WebauthnError::OriginRpMissmatch)?;
+        if apps.iter().any(|app| app.is_signed_with(&key_hash)) {
+            Ok(rp_id)
+        } else {
+            Err(WebauthnError::OriginRpMissmatch)
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Requests from native apps on Apple platforms, which may use the RP IDs listed as
+//! `webcredentials` associated domains in their entitlements, once the Relying Party lists the app
+//! in the `apple-app-site-association` file of the domain.
+//!
+//! <https://developer.apple.com/documentation/xcode/supporting-associated-domains>
+
+use url::Url;
+
+use crate::{RpIdValidator, WebauthnError};
+
+/// The service of the associated domains whose credentials an app may use.
+const WEB_CREDENTIALS: &str = "webcredentials:";
+
+/// An [`RpIdValidator`] accepting the RP IDs of an app's `webcredentials` associated domains, see
+/// [`Client::with_rp_id_validator`](crate::Client::with_rp_id_validator).
+///
+/// Native apps have no web origin, so only the RP ID is verified, against the domain of the origin
+/// when the request gives none. Apple platforms report `https://{RP ID}` as the origin of the
+/// requests of apps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssociatedDomains {
+    /// The domains of the `webcredentials` entitlements, and whether they match subdomains only.
+    domains: Vec<(String, bool)>,
+}
+
+impl AssociatedDomains {
+    /// The associated domains of an app from its `com.apple.developer.associated-domains`
+    /// entitlements, such as `webcredentials:example.com` or
+    /// `webcredentials:*.example.com?mode=developer`. Entitlements of other services are ignored.
+    pub fn new<I>(entitlements: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let domains = entitlements
+            .into_iter()
+            .filter_map(|entitlement| {
+                let domain = entitlement.as_ref().strip_prefix(WEB_CREDENTIALS)?;
+                // The `mode` of an entitlement only tells where its association is fetched from.
+                let domain = domain.split_once('?').map_or(domain, |(domain, _)| domain);
+                let (domain, subdomains_only) = match domain.strip_prefix("*.") {
+                    Some(domain) => (domain, true),
+                    None => (domain, false),
+                };
+                (!domain.is_empty()).then(|| (domain.to_ascii_lowercase(), subdomains_only))
+            })
+            .collect();
+        Self { domains }
+    }
+
+    /// Whether the app may use the RP ID `rp_id`.
+    pub fn contains(&self, rp_id: &str) -> bool {
+        let rp_id = rp_id.to_ascii_lowercase();
+        self.domains.iter().any(|(domain, subdomains_only)| {
+            match rp_id.strip_suffix(domain.as_str()) {
+                Some("") => !subdomains_only,
+                Some(subdomain) => *subdomains_only && subdomain.ends_with('.'),
+                None => false,
+            }
+        })
+    }
+}
+
+impl RpIdValidator for AssociatedDomains {
+    fn assert_domain<'a>(
+        &self,
+        origin: &'a Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError> {
+        let rp_id = rp_id
+            .or_else(|| origin.domain())
+            .ok_or(WebauthnError::OriginMissingDomain)?;
+        if self.contains(rp_id) {
+            Ok(rp_id)
+        } else {
+            Err(WebauthnError::OriginRpMissmatch)
+        }
+    }
+}
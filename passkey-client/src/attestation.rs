@@ -0,0 +1,474 @@
+//! Verification of attestation objects, for use by Relying Parties.
+//!
+//! [`verify_attestation`] parses an attestation object returned from a registration, verifies its
+//! attestation statement according to its format and checks the result against an
+//! [`AttestationPolicy`]. The formats `none`, `packed`, `fido-u2f`, `apple` and `tpm` are
+//! supported.
+//!
+//! Verifying the returned [`VerifiedAttestation::trust_path`] up to a trusted root, for example one
+//! of the roots published in the FIDO Metadata Service, is left to the caller.
+
+use ciborium::value::Value;
+use coset::{
+    iana::{self, EnumI64},
+    CoseKey, RegisteredLabelWithPrivate,
+};
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::{spki::SubjectPublicKeyInfoRef, DecodePublicKey},
+};
+use passkey_types::{
+    cose::{ec2_coordinates, public_key_der_from_cose_key},
+    crypto::sha256,
+    ctap2::{
+        attestation_statement::{
+            AppleStatement, AttestationStatement, TpmPublicKey, TpmStatement, TpmsAttest,
+        },
+        Aaguid, AuthenticatorData,
+    },
+    x509::{self, Certificate},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Reasons an attestation object can fail verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    /// The attestation object or its authenticator data could not be decoded.
+    InvalidAttestationObject,
+    /// The authenticator data does not contain the attested credential data.
+    MissingCredentialData,
+    /// The attestation statement format is not supported.
+    UnsupportedFormat(String),
+    /// The attestation statement does not have the structure required by its format.
+    InvalidStatement,
+    /// The algorithm of the statement or of a key is not supported.
+    UnsupportedAlgorithm,
+    /// The statement's algorithm does not match the key it is verified with.
+    AlgorithmMismatch,
+    /// The signature of the attestation statement is invalid.
+    InvalidSignature,
+    /// The attestation certificate is malformed or does not meet the format's requirements.
+    InvalidCertificate,
+    /// The attestation certificate was issued for a different AAGUID than the authenticator data.
+    AaguidMismatch,
+    /// The authenticator's AAGUID is not allowed by the [`AttestationPolicy`].
+    AaguidNotAllowed,
+    /// The attestation type is not allowed by the [`AttestationPolicy`].
+    AttestationTypeNotAllowed(AttestationType),
+}
+
+/// The [attestation type] established by a verified attestation statement.
+///
+/// [attestation type]: https://w3c.github.io/webauthn/#sctn-attestation-types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationType {
+    /// No attestation information is available.
+    None,
+    /// The statement is signed by the credential's own private key.
+    SelfAttestation,
+    /// The statement is signed by an attestation key certified by the [`VerifiedAttestation::trust_path`].
+    Basic,
+    /// The credential key is certified by an anonymization CA, such as Apple's, which issues a
+    /// certificate per credential.
+    AnonymizationCa,
+    /// The statement is signed by an attestation key certified by an Attestation CA, such as a
+    /// TPM's attestation identity key.
+    AttestationCa,
+}
+
+/// Which attestations a Relying Party accepts.
+///
+/// The default accepts every supported attestation from any authenticator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationPolicy {
+    /// Accept attestation objects in the `none` format.
+    pub allow_none: bool,
+    /// Accept self attestation.
+    pub allow_self_attestation: bool,
+    /// Only accept authenticators with one of these AAGUIDs. `None` accepts any AAGUID.
+    pub allowed_aaguids: Option<Vec<Aaguid>>,
+}
+
+impl Default for AttestationPolicy {
+    fn default() -> Self {
+        Self {
+            allow_none: true,
+            allow_self_attestation: true,
+            allowed_aaguids: None,
+        }
+    }
+}
+
+/// The result of a successful attestation verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedAttestation {
+    /// The attestation statement format.
+    pub fmt: String,
+    /// The attestation type established by the statement.
+    pub attestation_type: AttestationType,
+    /// The AAGUID of the authenticator.
+    pub aaguid: Aaguid,
+    /// The ID of the new credential.
+    pub credential_id: Vec<u8>,
+    /// The public key of the new credential.
+    pub public_key: CoseKey,
+    /// The DER encoded attestation certificate chain, leaf first, for all attestation types
+    /// backed by certificates.
+    pub trust_path: Vec<Vec<u8>>,
+}
+
+/// Verify a CBOR encoded `attestation_object` for the given `client_data_hash`, the SHA-256 of
+/// the registration's `clientDataJSON`.
+///
+/// This follows the [verification procedure] of the statement's format and then applies the
+/// `policy`. Checking the RP ID hash and flags of the authenticator data, as well as the trust
+/// path, remains the responsibility of the caller.
+///
+/// [verification procedure]: https://w3c.github.io/webauthn/#sctn-defined-attestation-formats
+pub fn verify_attestation(
+    attestation_object: &[u8],
+    client_data_hash: &[u8],
+    policy: &AttestationPolicy,
+) -> Result<VerifiedAttestation, AttestationError> {
+    let object: Value = ciborium::de::from_reader(attestation_object)
+        .map_err(|_| AttestationError::InvalidAttestationObject)?;
+    let fmt = map_entry(&object, "fmt")
+        .and_then(Value::as_text)
+        .ok_or(AttestationError::InvalidAttestationObject)?;
+    let att_stmt = map_entry(&object, "attStmt")
+        .filter(|stmt| stmt.is_map())
+        .ok_or(AttestationError::InvalidAttestationObject)?;
+    let auth_data_bytes = map_entry(&object, "authData")
+        .and_then(Value::as_bytes)
+        .ok_or(AttestationError::InvalidAttestationObject)?;
+    let auth_data = AuthenticatorData::from_slice(auth_data_bytes)
+        .map_err(|_| AttestationError::InvalidAttestationObject)?;
+    let credential = auth_data
+        .attested_credential_data
+        .as_ref()
+        .ok_or(AttestationError::MissingCredentialData)?;
+
+    let input = StatementInput {
+        att_stmt,
+        auth_data_bytes,
+        auth_data: &auth_data,
+        client_data_hash,
+        public_key: &credential.key,
+        credential_id: credential.credential_id(),
+        aaguid: credential.aaguid,
+    };
+    let (attestation_type, trust_path) = match fmt {
+        "none" => verify_none(&input)?,
+        "packed" => verify_packed(&input)?,
+        "fido-u2f" => verify_fido_u2f(&input)?,
+        AppleStatement::FORMAT => verify_apple(&input)?,
+        TpmStatement::FORMAT => verify_tpm(&input)?,
+        other => return Err(AttestationError::UnsupportedFormat(other.to_owned())),
+    };
+
+    let type_allowed = match attestation_type {
+        AttestationType::None => policy.allow_none,
+        AttestationType::SelfAttestation => policy.allow_self_attestation,
+        AttestationType::Basic
+        | AttestationType::AnonymizationCa
+        | AttestationType::AttestationCa => true,
+    };
+    if !type_allowed {
+        return Err(AttestationError::AttestationTypeNotAllowed(
+            attestation_type,
+        ));
+    }
+    if let Some(allowed) = &policy.allowed_aaguids {
+        if !allowed.contains(&credential.aaguid) {
+            return Err(AttestationError::AaguidNotAllowed);
+        }
+    }
+
+    Ok(VerifiedAttestation {
+        fmt: fmt.to_owned(),
+        attestation_type,
+        aaguid: credential.aaguid,
+        credential_id: credential.credential_id().to_vec(),
+        public_key: credential.key.clone(),
+        trust_path,
+    })
+}
+
+/// Everything a statement format needs for verification.
+struct StatementInput<'a> {
+    att_stmt: &'a Value,
+    auth_data_bytes: &'a [u8],
+    auth_data: &'a AuthenticatorData,
+    client_data_hash: &'a [u8],
+    public_key: &'a CoseKey,
+    credential_id: &'a [u8],
+    aaguid: Aaguid,
+}
+
+impl StatementInput<'_> {
+    fn signature_target(&self) -> Vec<u8> {
+        [self.auth_data_bytes, self.client_data_hash].concat()
+    }
+
+    /// The `x5c` certificates of the statement, `None` if absent.
+    fn x5c(&self) -> Result<Option<Vec<Vec<u8>>>, AttestationError> {
+        map_entry(self.att_stmt, "x5c")
+            .map(|x5c| {
+                x5c.as_array()
+                    .filter(|certs| !certs.is_empty())
+                    .ok_or(AttestationError::InvalidStatement)?
+                    .iter()
+                    .map(|cert| {
+                        cert.as_bytes()
+                            .cloned()
+                            .ok_or(AttestationError::InvalidStatement)
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+
+    fn sig(&self) -> Result<&[u8], AttestationError> {
+        map_entry(self.att_stmt, "sig")
+            .and_then(Value::as_bytes)
+            .map(Vec::as_slice)
+            .ok_or(AttestationError::InvalidStatement)
+    }
+}
+
+type Verified = (AttestationType, Vec<Vec<u8>>);
+
+/// <https://w3c.github.io/webauthn/#sctn-none-attestation>
+fn verify_none(input: &StatementInput<'_>) -> Result<Verified, AttestationError> {
+    match input.att_stmt.as_map() {
+        Some(stmt) if stmt.is_empty() => Ok((AttestationType::None, Vec::new())),
+        _ => Err(AttestationError::InvalidStatement),
+    }
+}
+
+/// <https://w3c.github.io/webauthn/#sctn-packed-attestation>
+fn verify_packed(input: &StatementInput<'_>) -> Result<Verified, AttestationError> {
+    let alg = map_entry(input.att_stmt, "alg")
+        .and_then(Value::as_integer)
+        .and_then(|alg| i64::try_from(alg).ok())
+        .and_then(iana::Algorithm::from_i64)
+        .ok_or(AttestationError::InvalidStatement)?;
+    let sig = input.sig()?;
+    let signature_target = input.signature_target();
+
+    let Some(x5c) = input.x5c()? else {
+        // Self attestation
+        if input.public_key.alg != Some(RegisteredLabelWithPrivate::Assigned(alg)) {
+            return Err(AttestationError::AlgorithmMismatch);
+        }
+        let public_key = public_key_der_from_cose_key(input.public_key)
+            .map_err(|_| AttestationError::UnsupportedAlgorithm)?;
+        verify_signature(&public_key, alg, &signature_target, sig)?;
+        return Ok((AttestationType::SelfAttestation, Vec::new()));
+    };
+
+    let leaf = Certificate::from_der(&x5c[0]).map_err(|_| AttestationError::InvalidCertificate)?;
+    let meets_requirements = leaf.version == 3
+        && leaf.subject_attribute(x509::ORGANIZATIONAL_UNIT_NAME)
+            == Some("Authenticator Attestation")
+        && [
+            x509::COUNTRY_NAME,
+            x509::ORGANIZATION_NAME,
+            x509::COMMON_NAME,
+        ]
+        .iter()
+        .all(|oid| leaf.subject_attribute(oid).is_some())
+        && !leaf.is_ca;
+    if !meets_requirements {
+        return Err(AttestationError::InvalidCertificate);
+    }
+    if leaf.aaguid.is_some_and(|aaguid| aaguid != input.aaguid) {
+        return Err(AttestationError::AaguidMismatch);
+    }
+    verify_signature(leaf.subject_public_key_info, alg, &signature_target, sig)?;
+    Ok((AttestationType::Basic, x5c))
+}
+
+/// <https://w3c.github.io/webauthn/#sctn-fido-u2f-attestation>
+fn verify_fido_u2f(input: &StatementInput<'_>) -> Result<Verified, AttestationError> {
+    let x5c = match input.x5c()? {
+        Some(x5c) if x5c.len() == 1 => x5c,
+        _ => return Err(AttestationError::InvalidStatement),
+    };
+    let sig = input.sig()?;
+    let leaf = Certificate::from_der(&x5c[0]).map_err(|_| AttestationError::InvalidCertificate)?;
+    if VerifyingKey::from_public_key_der(leaf.subject_public_key_info).is_err() {
+        return Err(AttestationError::InvalidCertificate);
+    }
+
+    if input.public_key.alg != Some(RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256)) {
+        return Err(AttestationError::UnsupportedAlgorithm);
+    }
+    let (x, y) =
+        ec2_coordinates(input.public_key).map_err(|_| AttestationError::UnsupportedAlgorithm)?;
+    let verification_data = [
+        &[0x00],
+        input.auth_data.rp_id_hash(),
+        input.client_data_hash,
+        input.credential_id,
+        &[0x04],
+        x,
+        y,
+    ]
+    .concat();
+    verify_signature(
+        leaf.subject_public_key_info,
+        iana::Algorithm::ES256,
+        &verification_data,
+        sig,
+    )?;
+    Ok((AttestationType::Basic, x5c))
+}
+
+/// <https://w3c.github.io/webauthn/#sctn-apple-anonymous-attestation>
+fn verify_apple(input: &StatementInput<'_>) -> Result<Verified, AttestationError> {
+    let statement =
+        AppleStatement::from_value(input.att_stmt).ok_or(AttestationError::InvalidStatement)?;
+    let credential_certificate = statement
+        .x5c
+        .first()
+        .ok_or(AttestationError::InvalidStatement)?;
+    let credential_certificate = Certificate::from_der(credential_certificate)
+        .map_err(|_| AttestationError::InvalidCertificate)?;
+
+    let nonce = sha256(&input.signature_target());
+    if AppleStatement::nonce(&credential_certificate) != Some(nonce.as_slice()) {
+        return Err(AttestationError::InvalidSignature);
+    }
+
+    let public_key = public_key_der_from_cose_key(input.public_key)
+        .map_err(|_| AttestationError::UnsupportedAlgorithm)?;
+    let public_key = SubjectPublicKeyInfoRef::try_from(public_key.as_slice())
+        .map_err(|_| AttestationError::UnsupportedAlgorithm)?;
+    let certified_key =
+        SubjectPublicKeyInfoRef::try_from(credential_certificate.subject_public_key_info)
+            .map_err(|_| AttestationError::InvalidCertificate)?;
+    if public_key.algorithm.oid != certified_key.algorithm.oid
+        || public_key.subject_public_key != certified_key.subject_public_key
+    {
+        return Err(AttestationError::InvalidCertificate);
+    }
+
+    Ok((
+        AttestationType::AnonymizationCa,
+        statement.x5c.into_iter().map(Vec::from).collect(),
+    ))
+}
+
+/// <https://w3c.github.io/webauthn/#sctn-tpm-attestation>
+fn verify_tpm(input: &StatementInput<'_>) -> Result<Verified, AttestationError> {
+    let statement =
+        TpmStatement::from_value(input.att_stmt).ok_or(AttestationError::InvalidStatement)?;
+    if statement.ver != TpmStatement::VERSION {
+        return Err(AttestationError::InvalidStatement);
+    }
+
+    let pub_area = statement
+        .parse_pub_area()
+        .ok_or(AttestationError::InvalidStatement)?;
+    match &pub_area.public_key {
+        TpmPublicKey::Ecc { curve, x, y, .. } if *curve == TpmPublicKey::ECC_NIST_P256 => {
+            if ec2_coordinates(input.public_key).ok() != Some((x, y)) {
+                return Err(AttestationError::InvalidStatement);
+            }
+        }
+        _ => return Err(AttestationError::UnsupportedAlgorithm),
+    }
+
+    let cert_info = statement
+        .parse_cert_info()
+        .ok_or(AttestationError::InvalidStatement)?;
+    let extra_data = match statement.alg {
+        iana::Algorithm::ES256 => sha256(&input.signature_target()),
+        _ => return Err(AttestationError::UnsupportedAlgorithm),
+    };
+    let name = statement
+        .pub_area_name()
+        .ok_or(AttestationError::UnsupportedAlgorithm)?;
+    if cert_info.magic != TpmsAttest::TPM_GENERATED_VALUE
+        || cert_info.extra_data != extra_data
+        || cert_info.certified_name != name
+    {
+        return Err(AttestationError::InvalidStatement);
+    }
+
+    let aik_certificate = statement
+        .x5c
+        .first()
+        .ok_or(AttestationError::InvalidStatement)?;
+    let aik_certificate =
+        Certificate::from_der(aik_certificate).map_err(|_| AttestationError::InvalidCertificate)?;
+    let meets_requirements = aik_certificate.version == 3
+        && aik_certificate.subject.is_empty()
+        && aik_certificate
+            .extension(x509::SUBJECT_ALT_NAME_EXTENSION)
+            .is_some()
+        && aik_certificate
+            .extended_key_usage()
+            .iter()
+            .any(|usage| usage == TpmStatement::AIK_CERTIFICATE_KEY_USAGE)
+        && !aik_certificate.is_ca;
+    if !meets_requirements {
+        return Err(AttestationError::InvalidCertificate);
+    }
+    if aik_certificate
+        .aaguid
+        .is_some_and(|aaguid| aaguid != input.aaguid)
+    {
+        return Err(AttestationError::AaguidMismatch);
+    }
+    verify_signature(
+        aik_certificate.subject_public_key_info,
+        statement.alg,
+        &statement.cert_info,
+        &statement.sig,
+    )?;
+
+    Ok((
+        AttestationType::AttestationCa,
+        statement.x5c.into_iter().map(Vec::from).collect(),
+    ))
+}
+
+/// Verify `signature` over `data` with a DER encoded SubjectPublicKeyInfo for the given `alg`.
+pub(crate) fn verify_signature(
+    public_key: &[u8],
+    alg: iana::Algorithm,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), AttestationError> {
+    let verified = match alg {
+        iana::Algorithm::ES256 => {
+            let key = VerifyingKey::from_public_key_der(public_key)
+                .map_err(|_| AttestationError::AlgorithmMismatch)?;
+            let signature =
+                Signature::from_der(signature).map_err(|_| AttestationError::InvalidSignature)?;
+            key.verify(data, &signature).is_ok()
+        }
+        iana::Algorithm::EdDSA => {
+            let key = ed25519_dalek::VerifyingKey::from_public_key_der(public_key)
+                .map_err(|_| AttestationError::AlgorithmMismatch)?;
+            let signature = ed25519_dalek::Signature::from_slice(signature)
+                .map_err(|_| AttestationError::InvalidSignature)?;
+            key.verify(data, &signature).is_ok()
+        }
+        _ => return Err(AttestationError::UnsupportedAlgorithm),
+    };
+    verified
+        .then_some(())
+        .ok_or(AttestationError::InvalidSignature)
+}
+
+fn map_entry<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
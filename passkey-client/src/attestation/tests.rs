@@ -0,0 +1,458 @@
+use ciborium::cbor;
+use coset::iana;
+use p256::{ecdsa::SigningKey, pkcs8::EncodePublicKey};
+use passkey_authenticator::{
+    Attestation, AttestationChain, Authenticator, CoseKeyPair, FidoU2fAttestation, MemoryStore,
+    MockUserValidationMethod, PackedAttestation,
+};
+use passkey_types::{
+    ctap2::{make_credential, AttestationObject},
+    rand::random_vec,
+    webauthn,
+};
+
+use super::*;
+
+const AAGUID: Aaguid = Aaguid([0x42; 16]);
+
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match contents.len() {
+        len @ 0..=0x7f => out.push(len as u8),
+        len @ 0x80..=0xff => out.extend([0x81, len as u8]),
+        len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+/// Build an unsigned attestation certificate for `key` as required by the packed format.
+fn attestation_certificate(key: &p256::SecretKey, aaguid: Option<Aaguid>) -> Vec<u8> {
+    let extensions = aaguid
+        .map(|aaguid| {
+            [
+                tlv(0x06, x509::AAGUID_EXTENSION.as_bytes()),
+                tlv(0x04, &tlv(0x04, &aaguid.0)),
+            ]
+            .concat()
+        })
+        .into_iter()
+        .collect::<Vec<_>>();
+    certificate(
+        SUBJECT,
+        &key.public_key().to_public_key_der().unwrap().into_vec(),
+        &extensions,
+    )
+}
+
+/// The subject required of packed attestation certificates, as `(attribute type, value)` pairs.
+const SUBJECT: &[(u8, &str)] = &[
+    (0x06, "US"),
+    (0x0a, "1Password"),
+    (0x0b, "Authenticator Attestation"),
+    (0x03, "passkey-rs"),
+];
+
+/// Build an unsigned certificate for the DER encoded `spki` with the given encoded extensions.
+fn certificate(subject: &[(u8, &str)], spki: &[u8], extensions: &[Vec<u8>]) -> Vec<u8> {
+    let subject = subject
+        .iter()
+        .map(|(oid, value)| {
+            tlv(
+                0x31,
+                &tlv(
+                    0x30,
+                    &[tlv(0x06, &[0x55, 0x04, *oid]), tlv(0x0c, value.as_bytes())].concat(),
+                ),
+            )
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    let algorithm = tlv(
+        0x30,
+        &tlv(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02]),
+    );
+    let mut tbs = [
+        tlv(0xa0, &tlv(0x02, &[2])),
+        tlv(0x02, &[1]),
+        algorithm.clone(),
+        tlv(0x30, &[]),
+        tlv(0x30, &[]),
+        tlv(0x30, &subject),
+        spki.to_vec(),
+    ]
+    .concat();
+    if !extensions.is_empty() {
+        let extensions = extensions
+            .iter()
+            .map(|extension| tlv(0x30, extension))
+            .collect::<Vec<_>>()
+            .concat();
+        tbs.extend(tlv(0xa3, &tlv(0x30, &extensions)));
+    }
+    tlv(
+        0x30,
+        &[tlv(0x30, &tbs), algorithm, tlv(0x03, &[0, 0x30, 0x00])].concat(),
+    )
+}
+
+fn request(client_data_hash: &[u8]) -> make_credential::Request {
+    make_credential::Request {
+        client_data_hash: client_data_hash.to_vec().into(),
+        rp: make_credential::PublicKeyCredentialRpEntity {
+            id: "future.1password.com".into(),
+            name: None,
+        },
+        user: webauthn::PublicKeyCredentialUserEntity {
+            id: random_vec(16).into(),
+            display_name: "wendy".into(),
+            name: "wendy".into(),
+        },
+        pub_key_cred_params: vec![webauthn::PublicKeyCredentialParameters {
+            ty: webauthn::PublicKeyCredentialType::PublicKey,
+            alg: iana::Algorithm::ES256,
+        }],
+        exclude_list: None,
+        extensions: None,
+        options: make_credential::Options {
+            rk: true,
+            up: true,
+            uv: true,
+        },
+        pin_auth: None,
+        pin_protocol: None,
+        enterprise_attestation: None,
+    }
+}
+
+fn encode(fmt: &str, att_stmt: Value, auth_data: Vec<u8>) -> Vec<u8> {
+    AttestationObject {
+        fmt: fmt.into(),
+        auth_data: AuthenticatorData::from_slice(&auth_data).unwrap(),
+        att_stmt,
+    }
+    .to_cbor()
+}
+
+/// Create a credential and return its attestation object along with the client data hash.
+async fn attestation_object(
+    authenticator: Authenticator<MemoryStore, MockUserValidationMethod>,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut authenticator = authenticator;
+    let client_data_hash = random_vec(32);
+    let response = authenticator
+        .make_credential(request(&client_data_hash))
+        .await
+        .expect("failed to create credential");
+    (
+        AttestationObject::from(response).to_cbor(),
+        client_data_hash,
+    )
+}
+
+fn authenticator() -> Authenticator<MemoryStore, MockUserValidationMethod> {
+    Authenticator::new(
+        AAGUID,
+        MemoryStore::new(),
+        MockUserValidationMethod::verified_user(1),
+    )
+}
+
+#[tokio::test]
+async fn verify_none_attestation() {
+    let (object, client_data_hash) = attestation_object(authenticator()).await;
+
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify none attestation");
+    assert_eq!(verified.fmt, "none");
+    assert_eq!(verified.attestation_type, AttestationType::None);
+    assert_eq!(verified.aaguid, AAGUID);
+    assert!(verified.trust_path.is_empty());
+
+    let policy = AttestationPolicy {
+        allow_none: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        verify_attestation(&object, &client_data_hash, &policy),
+        Err(AttestationError::AttestationTypeNotAllowed(
+            AttestationType::None
+        ))
+    );
+}
+
+#[tokio::test]
+async fn verify_packed_self_attestation() {
+    let (object, client_data_hash) =
+        attestation_object(authenticator().with_attestation(Attestation::SelfAttestation)).await;
+
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify self attestation");
+    assert_eq!(verified.fmt, "packed");
+    assert_eq!(verified.attestation_type, AttestationType::SelfAttestation);
+
+    assert_eq!(
+        verify_attestation(&object, &random_vec(32), &AttestationPolicy::default()),
+        Err(AttestationError::InvalidSignature)
+    );
+}
+
+#[tokio::test]
+async fn verify_packed_basic_attestation() {
+    let attestation_key = p256::SecretKey::random(&mut rand::thread_rng());
+    let certificate = attestation_certificate(&attestation_key, Some(AAGUID));
+    let chain = AttestationChain::new(
+        CoseKeyPair::from_secret_key(&attestation_key, iana::Algorithm::ES256)
+            .into_parts()
+            .1,
+        vec![certificate.clone()],
+    )
+    .expect("failed to load attestation chain");
+    let (object, client_data_hash) = attestation_object(
+        authenticator().with_attestation_provider(PackedAttestation::Basic(chain)),
+    )
+    .await;
+
+    let verified = verify_attestation(
+        &object,
+        &client_data_hash,
+        &AttestationPolicy {
+            allowed_aaguids: Some(vec![AAGUID]),
+            ..Default::default()
+        },
+    )
+    .expect("failed to verify basic attestation");
+    assert_eq!(verified.attestation_type, AttestationType::Basic);
+    assert_eq!(verified.trust_path, vec![certificate]);
+
+    assert_eq!(
+        verify_attestation(
+            &object,
+            &client_data_hash,
+            &AttestationPolicy {
+                allowed_aaguids: Some(vec![Aaguid::new_empty()]),
+                ..Default::default()
+            },
+        ),
+        Err(AttestationError::AaguidNotAllowed)
+    );
+}
+
+#[tokio::test]
+async fn verify_fido_u2f_attestation() {
+    let client_data_hash = random_vec(32);
+    let response = authenticator()
+        .make_credential(request(&client_data_hash))
+        .await
+        .expect("failed to create credential");
+    let credential = response.auth_data.attested_credential_data.clone().unwrap();
+    let (x, y) = ec2_coordinates(&credential.key).unwrap();
+
+    let attestation_key = p256::SecretKey::random(&mut rand::thread_rng());
+    let verification_data = [
+        &[0x00],
+        response.auth_data.rp_id_hash(),
+        &client_data_hash,
+        credential.credential_id(),
+        &[0x04],
+        x,
+        y,
+    ]
+    .concat();
+    let signature: Signature = p256::ecdsa::signature::Signer::sign(
+        &SigningKey::from(&attestation_key),
+        &verification_data,
+    );
+    let att_stmt = cbor!({
+        "sig" => Value::Bytes(signature.to_der().as_bytes().to_vec()),
+        "x5c" => [Value::Bytes(attestation_certificate(&attestation_key, None))],
+    })
+    .unwrap();
+    let object = encode("fido-u2f", att_stmt, response.auth_data.to_vec());
+
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify fido-u2f attestation");
+    assert_eq!(verified.attestation_type, AttestationType::Basic);
+    assert_eq!(verified.credential_id, credential.credential_id());
+
+    assert_eq!(
+        verify_attestation(
+            &encode(
+                "android-key",
+                cbor!({}).unwrap(),
+                response.auth_data.to_vec()
+            ),
+            &client_data_hash,
+            &AttestationPolicy::default()
+        ),
+        Err(AttestationError::UnsupportedFormat("android-key".into()))
+    );
+}
+
+#[tokio::test]
+async fn verify_emitted_fido_u2f_attestation() {
+    let attestation_key = p256::SecretKey::random(&mut rand::thread_rng());
+    let certificate = attestation_certificate(&attestation_key, None);
+    let attestation = FidoU2fAttestation::new(
+        AttestationChain::new(
+            CoseKeyPair::from_secret_key(&attestation_key, iana::Algorithm::ES256)
+                .into_parts()
+                .1,
+            vec![certificate.clone()],
+        )
+        .expect("failed to load attestation chain"),
+    )
+    .expect("failed to create fido-u2f attestation");
+    let (object, client_data_hash) =
+        attestation_object(authenticator().with_u2f_attestation(attestation)).await;
+
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify fido-u2f attestation");
+    assert_eq!(verified.fmt, "fido-u2f");
+    assert_eq!(verified.attestation_type, AttestationType::Basic);
+    assert_eq!(verified.trust_path, vec![certificate]);
+}
+
+#[tokio::test]
+async fn verify_apple_attestation() {
+    let client_data_hash = random_vec(32);
+    let response = authenticator()
+        .make_credential(request(&client_data_hash))
+        .await
+        .expect("failed to create credential");
+    let auth_data = response.auth_data.to_vec();
+    let credential = response.auth_data.attested_credential_data.unwrap();
+    let spki = public_key_der_from_cose_key(&credential.key).unwrap();
+
+    let apple_object = |spki: &[u8], client_data_hash: &[u8]| {
+        let nonce = sha256(&[auth_data.as_slice(), client_data_hash].concat());
+        let extension = [
+            tlv(
+                0x06,
+                &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x63, 0x64, 0x08, 0x02],
+            ),
+            tlv(0x04, &tlv(0x30, &tlv(0xa1, &tlv(0x04, &nonce)))),
+        ]
+        .concat();
+        let statement = AppleStatement {
+            x5c: vec![certificate(SUBJECT, spki, &[extension]).into()],
+        };
+        encode("apple", statement.to_value(), auth_data.clone())
+    };
+
+    let object = apple_object(&spki, &client_data_hash);
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify apple attestation");
+    assert_eq!(verified.fmt, "apple");
+    assert_eq!(verified.attestation_type, AttestationType::AnonymizationCa);
+    assert_eq!(verified.trust_path.len(), 1);
+
+    assert_eq!(
+        verify_attestation(&object, &random_vec(32), &AttestationPolicy::default()),
+        Err(AttestationError::InvalidSignature)
+    );
+
+    let other_key = p256::SecretKey::random(&mut rand::thread_rng());
+    let object = apple_object(
+        other_key
+            .public_key()
+            .to_public_key_der()
+            .unwrap()
+            .as_bytes(),
+        &client_data_hash,
+    );
+    assert_eq!(
+        verify_attestation(&object, &client_data_hash, &AttestationPolicy::default()),
+        Err(AttestationError::InvalidCertificate)
+    );
+}
+
+#[tokio::test]
+async fn verify_tpm_attestation() {
+    fn tpm2b(buffer: &[u8]) -> Vec<u8> {
+        [&(buffer.len() as u16).to_be_bytes(), buffer].concat()
+    }
+
+    let client_data_hash = random_vec(32);
+    let response = authenticator()
+        .make_credential(request(&client_data_hash))
+        .await
+        .expect("failed to create credential");
+    let auth_data = response.auth_data.to_vec();
+    let credential = response.auth_data.attested_credential_data.unwrap();
+    let (x, y) = ec2_coordinates(&credential.key).unwrap();
+
+    let pub_area = [
+        &[0x00, 0x23, 0x00, 0x0b][..],
+        &0x0006_0472u32.to_be_bytes(),
+        &tpm2b(&[]),
+        &[0x00, 0x10, 0x00, 0x10, 0x00, 0x03, 0x00, 0x10],
+        &tpm2b(x),
+        &tpm2b(y),
+    ]
+    .concat();
+    let name = [&[0x00, 0x0b][..], &sha256(&pub_area)].concat();
+    let cert_info = |extra_data: &[u8]| {
+        [
+            &TpmsAttest::TPM_GENERATED_VALUE.to_be_bytes()[..],
+            &TpmsAttest::TPM_ST_ATTEST_CERTIFY.to_be_bytes(),
+            &tpm2b(&[]),
+            &tpm2b(extra_data),
+            &[0; 17],
+            &[0; 8],
+            &tpm2b(&name),
+            &tpm2b(&[]),
+        ]
+        .concat()
+    };
+
+    let aik = p256::SecretKey::random(&mut rand::thread_rng());
+    let extensions = [
+        [tlv(0x06, &[0x55, 0x1d, 0x11]), tlv(0x04, &tlv(0x30, &[]))].concat(),
+        [
+            tlv(0x06, &[0x55, 0x1d, 0x25]),
+            tlv(
+                0x04,
+                &tlv(0x30, &tlv(0x06, &[0x67, 0x81, 0x05, 0x08, 0x03])),
+            ),
+        ]
+        .concat(),
+    ];
+    let aik_certificate = certificate(
+        &[],
+        aik.public_key().to_public_key_der().unwrap().as_bytes(),
+        &extensions,
+    );
+    let tpm_object = |cert_info: Vec<u8>, aik_certificate: Vec<u8>| {
+        let signature: Signature =
+            p256::ecdsa::signature::Signer::sign(&SigningKey::from(&aik), &cert_info);
+        let statement = TpmStatement {
+            ver: TpmStatement::VERSION.into(),
+            alg: iana::Algorithm::ES256,
+            x5c: vec![aik_certificate.into()],
+            sig: signature.to_der().as_bytes().to_vec().into(),
+            cert_info: cert_info.into(),
+            pub_area: pub_area.clone().into(),
+        };
+        encode("tpm", statement.to_value(), auth_data.clone())
+    };
+
+    let extra_data = sha256(&[auth_data.as_slice(), &client_data_hash].concat());
+    let object = tpm_object(cert_info(&extra_data), aik_certificate.clone());
+    let verified = verify_attestation(&object, &client_data_hash, &AttestationPolicy::default())
+        .expect("failed to verify tpm attestation");
+    assert_eq!(verified.fmt, "tpm");
+    assert_eq!(verified.attestation_type, AttestationType::AttestationCa);
+    assert_eq!(verified.trust_path, vec![aik_certificate.clone()]);
+
+    assert_eq!(
+        verify_attestation(&object, &random_vec(32), &AttestationPolicy::default()),
+        Err(AttestationError::InvalidStatement)
+    );
+
+    let object = tpm_object(cert_info(&extra_data), attestation_certificate(&aik, None));
+    assert_eq!(
+        verify_attestation(&object, &client_data_hash, &AttestationPolicy::default()),
+        Err(AttestationError::InvalidCertificate)
+    );
+}
@@ -0,0 +1,151 @@
+//! Events of the ceremonies of a client, with which hosts audit and debug the requests of Relying
+//! Parties without parsing logs.
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{ctap2, webauthn, Bytes, Passkey};
+
+use crate::{Client, WebauthnError};
+
+/// An event of a ceremony, in the order they occur. Every ceremony starts with
+/// [`CeremonyEvent::Started`] and ends with either [`CeremonyEvent::Completed`] or
+/// [`CeremonyEvent::Failed`], the events in between depending on how far it got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CeremonyEvent {
+    /// The ceremony started for a request from the given origin.
+    Started {
+        /// The origin of the request.
+        origin: String,
+    },
+    /// The origin of the request may use the RP ID of the ceremony.
+    OriginValidated {
+        /// The RP ID of the ceremony.
+        rp_id: String,
+    },
+    /// The request was routed to one of the authenticators of the client.
+    AuthenticatorSelected {
+        /// The position of the authenticator in [`Client::authenticators`].
+        index: usize,
+        /// The AAGUID of the authenticator.
+        aaguid: ctap2::Aaguid,
+    },
+    /// The authenticator checked the user, as reported by the flags of its authenticator data.
+    UserChecked {
+        /// Whether the user was present.
+        present: bool,
+        /// Whether the user was verified.
+        verified: bool,
+    },
+    /// The client processed the extensions of the request, reporting outputs for those listed.
+    ExtensionsProcessed {
+        /// The identifiers of the client extension outputs of the ceremony.
+        extensions: Vec<String>,
+    },
+    /// The ceremony completed with the given credential.
+    Completed {
+        /// The ID of the created or asserted credential.
+        credential_id: Bytes,
+    },
+    /// The ceremony failed with the given error.
+    Failed {
+        /// The error returned to the caller.
+        error: WebauthnError,
+    },
+}
+
+/// Use this on a type which records the ceremonies of a client, see
+/// [`Client::with_ceremony_observer`].
+///
+/// It is implemented for closures taking the type of a ceremony and one of its events.
+pub trait CeremonyObserver {
+    /// An event occurred during a ceremony of the given type, which is
+    /// [`webauthn::ClientDataType::Create`] for registrations and
+    /// [`webauthn::ClientDataType::Get`] for authentications.
+    fn ceremony_event(&self, ty: webauthn::ClientDataType, event: CeremonyEvent);
+}
+
+impl<F> CeremonyObserver for F
+where
+    F: Fn(webauthn::ClientDataType, CeremonyEvent),
+{
+    fn ceremony_event(&self, ty: webauthn::ClientDataType, event: CeremonyEvent) {
+        self(ty, event)
+    }
+}
+
+/// The identifiers of the client extension `outputs`, in their JSON encoding.
+fn extension_identifiers(outputs: &webauthn::AuthenticatorExtensionsClientOutputs) -> Vec<String> {
+    match serde_json::to_value(outputs) {
+        Ok(serde_json::Value::Object(outputs)) => outputs.into_iter().map(|(id, _)| id).collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Tell the [`CeremonyObserver`] the `event` of a ceremony of the given type, if there is one.
+    /// The event is only built when it is observed.
+    pub(crate) fn audit(
+        &self,
+        ty: webauthn::ClientDataType,
+        event: impl FnOnce() -> CeremonyEvent,
+    ) {
+        if let Some(observer) = self.ceremony_observer.as_deref() {
+            observer.ceremony_event(ty, event());
+        }
+    }
+
+    /// Tell the [`CeremonyObserver`] that the request was routed to the selected authenticator.
+    pub(crate) fn audit_selected(&self, ty: webauthn::ClientDataType) {
+        self.audit(ty, || CeremonyEvent::AuthenticatorSelected {
+            index: self.selected,
+            aaguid: *self.selected().aaguid(),
+        });
+    }
+
+    /// Tell the [`CeremonyObserver`] how the authenticator checked the user, given the `flags` of
+    /// its authenticator data.
+    pub(crate) fn audit_user_checked(&self, ty: webauthn::ClientDataType, flags: ctap2::Flags) {
+        self.audit(ty, || CeremonyEvent::UserChecked {
+            present: flags.contains(ctap2::Flags::UP),
+            verified: flags.contains(ctap2::Flags::UV),
+        });
+    }
+
+    /// Tell the [`CeremonyObserver`] which client extension `outputs` the ceremony reports, if it
+    /// reports any.
+    pub(crate) fn audit_extensions(
+        &self,
+        ty: webauthn::ClientDataType,
+        outputs: &webauthn::AuthenticatorExtensionsClientOutputs,
+    ) {
+        if self.ceremony_observer.is_none() {
+            return;
+        }
+        let extensions = extension_identifiers(outputs);
+        if !extensions.is_empty() {
+            self.audit(ty, || CeremonyEvent::ExtensionsProcessed { extensions });
+        }
+    }
+
+    /// Tell the [`CeremonyObserver`] the outcome of a ceremony of the given type, given the ID of
+    /// its credential when it completed.
+    pub(crate) fn audit_outcome(
+        &self,
+        ty: webauthn::ClientDataType,
+        result: Result<&[u8], &WebauthnError>,
+    ) {
+        self.audit(ty, || match result {
+            Ok(credential_id) => CeremonyEvent::Completed {
+                credential_id: credential_id.to_vec().into(),
+            },
+            Err(error) => CeremonyEvent::Failed {
+                error: error.clone(),
+            },
+        });
+    }
+}
@@ -0,0 +1,120 @@
+//! The capabilities of a client, which Relying Parties query to decide which ceremonies to offer,
+//! such as whether to show a passkey sign-in button or to rely on autofill.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-getClientCapabilities>
+
+use passkey_authenticator::{CredentialStore, DiscoverabilitySupport, UserValidationMethod};
+use passkey_types::{webauthn, Passkey};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::Client;
+
+/// The WebAuthn extensions which the client processes whichever its authenticators are.
+const CLIENT_EXTENSIONS: [&str; 3] = ["appid", "appidExclude", "credProps"];
+
+/// The WebAuthn extensions which the client supports when one of its authenticators supports the
+/// CTAP extension they are built on.
+const AUTHENTICATOR_EXTENSIONS: [(&str, &str); 3] = [
+    ("largeBlob", "largeBlobKey"),
+    ("devicePubKey", "devicePubKey"),
+    ("payment", "thirdPartyPayment"),
+];
+
+/// The capabilities of a [`Client`], see [`Client::capabilities`].
+///
+/// It serializes to the record returned by `PublicKeyCredential.getClientCapabilities()`, in which
+/// each supported extension is reported under the `extension:` prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientCapabilities {
+    /// Whether credentials can be created without interrupting the user, as with conditional
+    /// mediation.
+    pub conditional_create: bool,
+    /// Whether credentials can be offered in the existing UI of the host with conditional
+    /// mediation, which requires a [`CredentialPicker`](crate::CredentialPicker).
+    pub conditional_get: bool,
+    /// Whether one of the authenticators can be reached through the hybrid transport, such as a
+    /// phone used to sign in on a computer.
+    pub hybrid_transport: bool,
+    /// Whether one of the authenticators is a platform authenticator which verifies the user and
+    /// creates discoverable credentials.
+    pub passkey_platform_authenticator: bool,
+    /// Whether one of the authenticators is a platform authenticator which verifies the user.
+    pub user_verifying_platform_authenticator: bool,
+    /// Whether requests from the related origins of a Relying Party are accepted, which requires
+    /// a [`RelatedOriginsProvider`](crate::RelatedOriginsProvider).
+    pub related_origins: bool,
+    /// The identifiers of the supported WebAuthn extensions, such as `credProps`.
+    pub extensions: Vec<String>,
+}
+
+impl Serialize for ClientCapabilities {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(6 + self.extensions.len()))?;
+        map.serialize_entry("conditionalCreate", &self.conditional_create)?;
+        map.serialize_entry("conditionalGet", &self.conditional_get)?;
+        map.serialize_entry("hybridTransport", &self.hybrid_transport)?;
+        map.serialize_entry(
+            "passkeyPlatformAuthenticator",
+            &self.passkey_platform_authenticator,
+        )?;
+        map.serialize_entry(
+            "userVerifyingPlatformAuthenticator",
+            &self.user_verifying_platform_authenticator,
+        )?;
+        map.serialize_entry("relatedOrigins", &self.related_origins)?;
+        for extension in &self.extensions {
+            map.serialize_entry(&format!("extension:{extension}"), &true)?;
+        }
+        map.end()
+    }
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// The capabilities of the client, computed from its authenticators and how it is configured,
+    /// in the manner of `PublicKeyCredential.getClientCapabilities()`.
+    pub async fn capabilities(&self) -> ClientCapabilities {
+        let mut capabilities = ClientCapabilities {
+            conditional_get: self.credential_picker.is_some(),
+            related_origins: self.related_origins.is_some(),
+            extensions: CLIENT_EXTENSIONS.map(str::to_owned).to_vec(),
+            ..Default::default()
+        };
+        let mut authenticator_extensions = Vec::new();
+        for authenticator in &self.authenticators {
+            let info = authenticator.get_info().await;
+            capabilities.hybrid_transport |= info.transports.as_ref().is_some_and(|transports| {
+                transports.contains(&webauthn::AuthenticatorTransport::Hybrid)
+            });
+            let verifying_platform = authenticator.attachment_type()
+                == webauthn::AuthenticatorAttachment::Platform
+                && info.options.as_ref().and_then(|options| options.uv) == Some(true);
+            capabilities.user_verifying_platform_authenticator |= verifying_platform;
+            capabilities.passkey_platform_authenticator |= verifying_platform
+                && authenticator.store().discoverability().await
+                    != DiscoverabilitySupport::OnlyNonDiscoverable;
+            authenticator_extensions.extend(info.extensions.unwrap_or_default());
+        }
+        capabilities.extensions.extend(
+            AUTHENTICATOR_EXTENSIONS
+                .iter()
+                .filter(|(_, ctap)| authenticator_extensions.iter().any(|ext| ext == ctap))
+                .map(|(extension, _)| (*extension).to_owned()),
+        );
+        capabilities
+    }
+
+    /// Whether one of the authenticators of the client is a platform authenticator which verifies
+    /// the user, in the manner of
+    /// `PublicKeyCredential.isUserVerifyingPlatformAuthenticatorAvailable()`.
+    pub async fn is_user_verifying_platform_authenticator_available(&self) -> bool {
+        self.capabilities()
+            .await
+            .user_verifying_platform_authenticator
+    }
+}
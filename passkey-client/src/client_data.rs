@@ -0,0 +1,132 @@
+//! Customization of the client data of requests, with which embedders add the members the
+//! Relying Parties they serve expect, while the client still serializes and hashes it.
+//!
+//! <https://w3c.github.io/webauthn/#dictionary-client-data>
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn, Passkey};
+use serde_json::Value;
+
+use crate::Client;
+
+/// The members of the client data which the client sets itself, and which may therefore not be
+/// inserted through [`ClientDataFields::insert`].
+const RESERVED_KEYS: [&str; 7] = [
+    "type",
+    "challenge",
+    "origin",
+    "crossOrigin",
+    "topOrigin",
+    "tokenBinding",
+    "payment",
+];
+
+/// Use this on a type which adds members to the client data of requests, see
+/// [`Client::with_client_data_customizer`].
+///
+/// It is implemented for closures taking the [`ClientDataFields`] of a request.
+pub trait ClientDataCustomizer {
+    /// Customize the client data of a request before it is serialized and hashed.
+    fn customize_client_data(&self, fields: &mut ClientDataFields<'_>);
+}
+
+impl<F> ClientDataCustomizer for F
+where
+    F: Fn(&mut ClientDataFields<'_>),
+{
+    fn customize_client_data(&self, fields: &mut ClientDataFields<'_>) {
+        self(fields)
+    }
+}
+
+/// The client data of a request, given to a [`ClientDataCustomizer`].
+///
+/// The type, challenge, origin and `crossOrigin` members of the client data can only be read,
+/// since the Relying Party verifies them against the request.
+#[derive(Debug)]
+pub struct ClientDataFields<'a> {
+    client_data: &'a mut webauthn::CollectedClientData,
+}
+
+impl ClientDataFields<'_> {
+    /// The type of the request.
+    pub fn ty(&self) -> webauthn::ClientDataType {
+        self.client_data.ty
+    }
+
+    /// The origin of the request.
+    pub fn origin(&self) -> &str {
+        &self.client_data.origin
+    }
+
+    /// Whether the request was made from a frame which is cross-origin with its ancestors.
+    pub fn cross_origin(&self) -> bool {
+        self.client_data.cross_origin == Some(true)
+    }
+
+    /// The origin of the top-level page of the request, if it is set.
+    pub fn top_origin(&self) -> Option<&str> {
+        self.client_data
+            .unknown_keys
+            .get("topOrigin")
+            .and_then(Value::as_str)
+    }
+
+    /// Set or remove the origin of the top-level page of the request.
+    pub fn set_top_origin(&mut self, top_origin: Option<String>) {
+        self.set("topOrigin", top_origin.map(Value::String));
+    }
+
+    /// Set or remove the state of the Token Binding protocol used with the Relying Party.
+    pub fn set_token_binding(&mut self, token_binding: Option<webauthn::TokenBinding>) {
+        // SAFETY: it is a developer error if serializing this struct fails.
+        let token_binding = token_binding.map(|binding| serde_json::to_value(binding).unwrap());
+        self.set("tokenBinding", token_binding);
+    }
+
+    /// Insert an additional member, replacing the previous value of `key`.
+    ///
+    /// Returns `false`, leaving the client data unchanged, when `key` is one of the members set
+    /// by the client, such as `challenge` or `topOrigin`.
+    pub fn insert(&mut self, key: impl Into<String>, value: Value) -> bool {
+        let key = key.into();
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return false;
+        }
+        self.client_data.unknown_keys.insert(key, value);
+        true
+    }
+
+    fn set(&mut self, key: &str, value: Option<Value>) {
+        match value {
+            Some(value) => {
+                self.client_data.unknown_keys.insert(key.to_owned(), value);
+            }
+            None => {
+                self.client_data.unknown_keys.shift_remove(key);
+            }
+        }
+    }
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Let the [`ClientDataCustomizer`] of the client, if any, customize the `client_data` of a
+    /// request.
+    pub(crate) fn customize_client_data(
+        &self,
+        mut client_data: webauthn::CollectedClientData,
+    ) -> webauthn::CollectedClientData {
+        if let Some(customizer) = self.client_data_customizer.as_deref() {
+            customizer.customize_client_data(&mut ClientDataFields {
+                client_data: &mut client_data,
+            });
+        }
+        client_data
+    }
+}
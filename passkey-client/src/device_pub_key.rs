@@ -0,0 +1,48 @@
+//! Verification of the outputs of the `devicePubKey` extension, for use by Relying Parties.
+//!
+//! [`verify_device_pub_key`] checks the attestation and signature of a device public key returned
+//! from a registration or an authentication. A Relying Party records the device keys of each
+//! credential and recognizes them on later authentications with
+//! [`DevicePublicKeyAttestation::is_same_device`]. An unknown device key means the credential,
+//! which may be synced, is used from a new device.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-device-publickey-extension>
+
+use coset::RegisteredLabelWithPrivate;
+use passkey_types::{
+    cose::public_key_der_from_cose_key, ctap2::DevicePublicKeyAttestation,
+    webauthn::AuthenticationExtensionsDevicePublicKeyOutputs,
+};
+
+use crate::attestation::{verify_signature, AttestationError};
+
+/// Verify the `devicePubKey` `outputs` of a ceremony given its `authenticator_data` and
+/// `client_data_hash`, the SHA-256 of its `clientDataJSON`, returning the attested device key.
+///
+/// Only the `none` attestation format is supported for device keys.
+pub fn verify_device_pub_key(
+    outputs: &AuthenticationExtensionsDevicePublicKeyOutputs,
+    authenticator_data: &[u8],
+    client_data_hash: &[u8],
+) -> Result<DevicePublicKeyAttestation, AttestationError> {
+    let attestation = DevicePublicKeyAttestation::from_slice(&outputs.dpk_att_obj)
+        .ok_or(AttestationError::InvalidAttestationObject)?;
+    match attestation.fmt.as_str() {
+        "none" if attestation.att_stmt.as_map().is_some_and(Vec::is_empty) => {}
+        "none" => return Err(AttestationError::InvalidStatement),
+        other => return Err(AttestationError::UnsupportedFormat(other.to_owned())),
+    }
+
+    let Some(RegisteredLabelWithPrivate::Assigned(alg)) = attestation.dpk.alg else {
+        return Err(AttestationError::UnsupportedAlgorithm);
+    };
+    let public_key = public_key_der_from_cose_key(&attestation.dpk)
+        .map_err(|_| AttestationError::UnsupportedAlgorithm)?;
+    verify_signature(
+        &public_key,
+        alg,
+        &[authenticator_data, client_data_hash].concat(),
+        &outputs.signature,
+    )?;
+    Ok(attestation)
+}
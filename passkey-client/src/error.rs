@@ -0,0 +1,105 @@
+//! The mapping of [`WebauthnError`] to the `DOMException` with which browsers reject WebAuthn
+//! requests, so that hosts can report errors to Relying Parties, and show them to users, as a
+//! browser would.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-createCredential>
+//! <https://w3c.github.io/webauthn/#sctn-getAssertion>
+
+use passkey_types::ctap2::{Ctap2Code, Ctap2Error, StatusCode};
+use typeshare::typeshare;
+
+use crate::WebauthnError;
+
+/// The name of the `DOMException` with which browsers reject a WebAuthn request.
+#[typeshare]
+#[derive(Debug, Clone, Copy, serde::Serialize, PartialEq, Eq)]
+pub enum DomExceptionName {
+    /// The request was denied by the user, timed out, or no credential could be used, which
+    /// browsers do not tell apart so as not to reveal which credentials exist.
+    NotAllowedError,
+    /// The authenticator holds one of the credentials excluded by the Relying Party.
+    InvalidStateError,
+    /// The origin of the request may not use its RP ID or FIDO AppID.
+    SecurityError,
+    /// No authenticator satisfies the criteria required by the Relying Party.
+    ConstraintError,
+    /// The request asks for something the client or the authenticator does not support, such as
+    /// an algorithm or an extension input.
+    NotSupportedError,
+    /// The request was aborted by the host.
+    AbortError,
+    /// Any other error.
+    UnknownError,
+}
+
+/// The step of a WebAuthn ceremony at which a request failed.
+#[typeshare]
+#[derive(Debug, Clone, Copy, serde::Serialize, PartialEq, Eq)]
+pub enum CeremonyStep {
+    /// Verifying the origin of the request and the frame it was made from, against its RP ID and
+    /// FIDO AppIDs.
+    OriginVerification,
+    /// Validating the options and extension inputs of the request.
+    OptionsValidation,
+    /// Selecting an authenticator which satisfies the criteria of the request.
+    AuthenticatorSelection,
+    /// Waiting for the user to complete the request.
+    UserInteraction,
+    /// Creating a credential or an assertion with the authenticator.
+    AuthenticatorOperation,
+}
+
+impl WebauthnError {
+    /// The name of the `DOMException` with which a browser rejects a request failing with this
+    /// error.
+    pub fn dom_exception(&self) -> DomExceptionName {
+        match self {
+            Self::OriginMissingDomain
+            | Self::OriginRpMissmatch
+            | Self::UnprotectedOrigin
+            | Self::InsecureLocalhostNotAllowed
+            | Self::InvalidRpId
+            | Self::InvalidAppId => DomExceptionName::SecurityError,
+            Self::CredentialNotFound | Self::NotAllowed | Self::Timeout => {
+                DomExceptionName::NotAllowedError
+            }
+            Self::UnsatisfiedCriteria => DomExceptionName::ConstraintError,
+            Self::NotSupported => DomExceptionName::NotSupportedError,
+            Self::Aborted => DomExceptionName::AbortError,
+            Self::CredentialExcluded => DomExceptionName::InvalidStateError,
+            Self::CredentialIdTooLong => DomExceptionName::UnknownError,
+            Self::AuthenticatorError(code) => match StatusCode::from(*code) {
+                StatusCode::Ctap2(Ctap2Code::Known(Ctap2Error::CredentialExcluded)) => {
+                    DomExceptionName::InvalidStateError
+                }
+                StatusCode::Ctap2(Ctap2Code::Known(Ctap2Error::UnsupportedAlgorithm)) => {
+                    DomExceptionName::NotSupportedError
+                }
+                StatusCode::Ctap2(Ctap2Code::Known(
+                    Ctap2Error::UnsupportedOption | Ctap2Error::InvalidOption,
+                )) => DomExceptionName::ConstraintError,
+                _ => DomExceptionName::NotAllowedError,
+            },
+        }
+    }
+
+    /// The step of the ceremony at which a request failing with this error failed.
+    pub fn step(&self) -> CeremonyStep {
+        match self {
+            Self::OriginMissingDomain
+            | Self::OriginRpMissmatch
+            | Self::UnprotectedOrigin
+            | Self::InsecureLocalhostNotAllowed
+            | Self::InvalidRpId
+            | Self::InvalidAppId
+            | Self::NotAllowed => CeremonyStep::OriginVerification,
+            Self::NotSupported => CeremonyStep::OptionsValidation,
+            Self::UnsatisfiedCriteria => CeremonyStep::AuthenticatorSelection,
+            Self::Timeout | Self::Aborted => CeremonyStep::UserInteraction,
+            Self::CredentialIdTooLong
+            | Self::CredentialNotFound
+            | Self::CredentialExcluded
+            | Self::AuthenticatorError(_) => CeremonyStep::AuthenticatorOperation,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! The credentials a Relying Party excludes from a registration, usually those the user already
+//! registered, so that it does not create a second credential on the same authenticator.
+//!
+//! Relying Parties only learn that a credential is excluded once the user confirmed the request
+//! with a gesture, so that they cannot probe authenticators for credentials without the user.
+//!
+//! <https://w3c.github.io/webauthn/#dom-publickeycredentialcreationoptions-excludecredentials>
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn, Bytes, Passkey};
+
+use crate::{Client, WebauthnError};
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// The first of the `exclude_credentials` of `rp_id` stored by one of the authenticators of
+    /// the client, found without involving the user.
+    ///
+    /// This lets hosts tell users that they already have a credential for the Relying Party
+    /// before starting a registration, which would fail with
+    /// [`WebauthnError::CredentialExcluded`] once the user confirms it.
+    pub async fn find_excluded_credential(
+        &self,
+        rp_id: &str,
+        exclude_credentials: &[webauthn::PublicKeyCredentialDescriptor],
+    ) -> Option<Bytes> {
+        if exclude_credentials.is_empty() {
+            return None;
+        }
+        for authenticator in &self.authenticators {
+            let Ok(credentials) = authenticator
+                .store()
+                .find_credentials(Some(exclude_credentials), rp_id)
+                .await
+            else {
+                continue;
+            };
+            if let Some(passkey) = credentials
+                .into_iter()
+                .find_map(|credential| Passkey::try_from(credential).ok())
+            {
+                return Some(passkey.credential_id.clone());
+            }
+        }
+        None
+    }
+
+    /// The error with which a registration excluded by the Relying Party fails, once the user
+    /// confirms it with a gesture on the selected authenticator.
+    pub(crate) async fn credential_excluded(&self) -> WebauthnError {
+        match self.selected().selection().await {
+            Ok(()) => WebauthnError::CredentialExcluded,
+            Err(err) => err.into(),
+        }
+    }
+}
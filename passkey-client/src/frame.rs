@@ -0,0 +1,58 @@
+//! Requests made from embedded frames, such as the iframe of a sign-in widget, which are only
+//! allowed when the permissions policy of the page delegates the `publickey-credentials-create`
+//! or `publickey-credentials-get` feature to the frame.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-iframe-guidance>
+
+use passkey_types::webauthn::ClientDataType;
+use url::Url;
+
+use crate::WebauthnError;
+
+/// The browsing context of a request made from an embedded frame, as known to the host of the
+/// [`Client`](crate::Client), see [`Client::register_in_frame`](crate::Client::register_in_frame)
+/// and [`Client::authenticate_in_frame`](crate::Client::authenticate_in_frame).
+#[derive(Debug, Clone, Default)]
+pub struct FrameContext {
+    /// The origins of the ancestors of the frame, from its parent up to the top-level document.
+    pub ancestor_origins: Vec<Url>,
+    /// Whether the permissions policy allows the frame to use the `publickey-credentials-create`
+    /// feature, with which credentials are created.
+    pub allows_create: bool,
+    /// Whether the permissions policy allows the frame to use the `publickey-credentials-get`
+    /// feature, with which assertions are made.
+    pub allows_get: bool,
+}
+
+impl FrameContext {
+    /// Check that a frame at `origin` may make a request of the given type, and return the
+    /// `crossOrigin` and `topOrigin` members of its client data.
+    ///
+    /// Fails with [`WebauthnError::NotAllowed`] when the permissions policy does not allow the
+    /// feature of the request.
+    pub(crate) fn assert_allowed(
+        &self,
+        origin: &Url,
+        ty: ClientDataType,
+    ) -> Result<(bool, Option<String>), WebauthnError> {
+        let allowed = match ty {
+            ClientDataType::Create => self.allows_create,
+            ClientDataType::Get | ClientDataType::PaymentGet => self.allows_get,
+        };
+        if !allowed {
+            return Err(WebauthnError::NotAllowed);
+        }
+
+        let origin = origin.origin();
+        let cross_origin = self
+            .ancestor_origins
+            .iter()
+            .any(|ancestor| ancestor.origin() != origin);
+        let top_origin = self
+            .ancestor_origins
+            .last()
+            .filter(|_| cross_origin)
+            .map(|top| top.origin().ascii_serialization());
+        Ok((cross_origin, top_origin))
+    }
+}
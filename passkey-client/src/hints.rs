@@ -0,0 +1,113 @@
+//! The hints of a request, with which a Relying Party tells which kind of authenticator it expects
+//! users to satisfy the request with, so that the client offers it first.
+//!
+//! <https://w3c.github.io/webauthn/#enum-hints>
+
+use passkey_authenticator::{Authenticator, CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn, Passkey};
+
+use crate::Client;
+
+/// Use this on the UI of the host, to be told the hints of each request before the client asks
+/// for the user, for example to show a QR code right away for the `hybrid` hint. See
+/// [`Client::with_hints_listener`].
+///
+/// It is implemented for closures taking the type and the hints of a request.
+pub trait HintsListener {
+    /// A request of the given type starts with the `hints` of the Relying Party, in decreasing
+    /// order of preference.
+    fn request_hints(
+        &self,
+        ty: webauthn::ClientDataType,
+        hints: &[webauthn::PublicKeyCredentialHints],
+    );
+}
+
+impl<F> HintsListener for F
+where
+    F: Fn(webauthn::ClientDataType, &[webauthn::PublicKeyCredentialHints]),
+{
+    fn request_hints(
+        &self,
+        ty: webauthn::ClientDataType,
+        hints: &[webauthn::PublicKeyCredentialHints],
+    ) {
+        self(ty, hints)
+    }
+}
+
+/// The position of the first of the `hints` which the `authenticator` matches, or the number of
+/// hints when it matches none of them.
+async fn hint_rank<S, U>(
+    authenticator: &Authenticator<S, U>,
+    hints: &[webauthn::PublicKeyCredentialHints],
+) -> usize
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+{
+    use webauthn::AuthenticatorTransport::*;
+
+    let attachment = authenticator.attachment_type();
+    let transports = authenticator
+        .get_info()
+        .await
+        .transports
+        .unwrap_or_default();
+    hints
+        .iter()
+        .position(|hint| match hint {
+            webauthn::PublicKeyCredentialHints::SecurityKey => {
+                attachment == webauthn::AuthenticatorAttachment::CrossPlatform
+                    && transports
+                        .iter()
+                        .any(|transport| matches!(transport, Usb | Nfc | Ble))
+            }
+            webauthn::PublicKeyCredentialHints::ClientDevice => {
+                attachment == webauthn::AuthenticatorAttachment::Platform
+            }
+            webauthn::PublicKeyCredentialHints::Hybrid => transports.contains(&Hybrid),
+            _ => false,
+        })
+        .unwrap_or(hints.len())
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Tell the [`HintsListener`] the `hints` of a request of the given type, if it has any.
+    pub(crate) fn notify_hints(
+        &self,
+        ty: webauthn::ClientDataType,
+        hints: Option<&[webauthn::PublicKeyCredentialHints]>,
+    ) {
+        if let (Some(listener), Some(hints)) = (
+            self.hints_listener.as_deref(),
+            hints.filter(|hints| !hints.is_empty()),
+        ) {
+            listener.request_hints(ty, hints);
+        }
+    }
+
+    /// The indices of the authenticators of the client, those matching the earliest `hints` first
+    /// and otherwise in the order they were added.
+    pub(crate) async fn hinted_order(
+        &self,
+        hints: Option<&[webauthn::PublicKeyCredentialHints]>,
+    ) -> Vec<usize> {
+        let hints = hints.unwrap_or_default();
+        if hints.is_empty() || self.authenticators.len() < 2 {
+            return (0..self.authenticators.len()).collect();
+        }
+        let mut ranks = Vec::with_capacity(self.authenticators.len());
+        for (index, authenticator) in self.authenticators.iter().enumerate() {
+            ranks.push((hint_rank(authenticator, hints).await, index));
+        }
+        ranks.sort();
+        ranks.into_iter().map(|(_, index)| index).collect()
+    }
+}
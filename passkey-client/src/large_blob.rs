@@ -0,0 +1,285 @@
+//! The `largeBlob` extension, which stores opaque data along with a discoverable credential in the
+//! authenticator's large-blob array.
+//!
+//! Each large blob is compressed with DEFLATE and encrypted with AES-256-GCM under the
+//! `largeBlobKey` of its credential, so the array can be shared by every RP while each blob is
+//! only readable by the RP of its credential.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-large-blob-extension>
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#large-blob>
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use ciborium::value::{Integer, Value};
+use passkey_authenticator::{CredentialStore, PinProtocol, UserValidationMethod};
+use passkey_types::{
+    crypto::sha256,
+    ctap2::{
+        client_pin,
+        large_blobs::{self, is_valid_large_blob_array, LARGE_BLOB_ARRAY_HASH_LENGTH},
+        Permissions, StatusCode, U2FError,
+    },
+    webauthn::{AuthenticationExtensionsLargeBlobInputs, AuthenticationExtensionsLargeBlobOutputs},
+    Passkey,
+};
+
+use crate::Client;
+
+/// The maximum message size of the authenticator's transport when it does not report one.
+const DEFAULT_MAX_MSG_SIZE: u32 = 1024;
+
+/// The PIN/UV auth protocol used to authenticate large blob writes.
+pub(crate) const PIN_PROTOCOL: PinProtocol = PinProtocol::Two;
+
+/// The length of the nonce of an encrypted large blob.
+const NONCE_LENGTH: usize = 12;
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Get a PIN/UV auth token with which to assert a credential of `rp_id` and then write its
+    /// large blob, when the authenticator requires writes to be authenticated.
+    ///
+    /// The token is obtained through the authenticator's built-in user verification. Without it,
+    /// `None` is returned and writes only succeed on authenticators which are not protected by a
+    /// PIN.
+    pub(crate) async fn large_blob_write_token(
+        &mut self,
+        rp_id: &str,
+    ) -> Result<Option<Vec<u8>>, StatusCode> {
+        let info = self.selected().get_info().await;
+        if info.options.and_then(|options| options.uv) != Some(true) {
+            return Ok(None);
+        }
+
+        let authenticator_key = self
+            .selected_mut()
+            .client_pin(pin_request(client_pin::Subcommand::GetKeyAgreement))
+            .await?
+            .key_agreement
+            .ok_or(U2FError::Other)?;
+        let (platform_key, shared_secret) =
+            PIN_PROTOCOL.encapsulate(&authenticator_key, &mut OsRng)?;
+        let pin_token = self
+            .selected_mut()
+            .client_pin(client_pin::Request {
+                key_agreement: Some(platform_key),
+                permissions: Some((Permissions::GA | Permissions::LBW).bits()),
+                rp_id: Some(rp_id.to_owned()),
+                ..pin_request(client_pin::Subcommand::GetPinUvAuthTokenUsingUvWithPermissions)
+            })
+            .await?
+            .pin_token
+            .ok_or(U2FError::Other)?;
+        let pin_token = PIN_PROTOCOL.decrypt(&shared_secret, &pin_token)?;
+        Ok(Some(pin_token.to_vec()))
+    }
+
+    /// Read or write the large blob of an asserted credential as asked by `inputs`, given the
+    /// credential's `large_blob_key` if the authenticator returned one.
+    ///
+    /// Failures are reported through the outputs rather than failing the assertion: the blob is
+    /// left out when it cannot be read and `written` is false when it cannot be written.
+    pub(crate) async fn process_large_blob(
+        &mut self,
+        inputs: AuthenticationExtensionsLargeBlobInputs,
+        large_blob_key: Option<&[u8]>,
+        token: Option<&[u8]>,
+    ) -> AuthenticationExtensionsLargeBlobOutputs {
+        let mut outputs = AuthenticationExtensionsLargeBlobOutputs::default();
+        if let Some(blob) = inputs.write {
+            let written = match large_blob_key {
+                Some(key) => self.write_large_blob(key, &blob, token).await.is_ok(),
+                None => false,
+            };
+            outputs.written = Some(written);
+        } else if inputs.read == Some(true) {
+            if let Some(key) = large_blob_key {
+                let array = self.read_large_blob_array().await.unwrap_or_default();
+                outputs.blob = parse_array(&array)
+                    .iter()
+                    .find_map(|entry| decrypt(entry, key))
+                    .map(Into::into);
+            }
+        }
+        outputs
+    }
+
+    /// Replace the large blob encrypted with `key` by `blob`.
+    async fn write_large_blob(
+        &mut self,
+        key: &[u8],
+        blob: &[u8],
+        token: Option<&[u8]>,
+    ) -> Result<(), StatusCode> {
+        let array = self.read_large_blob_array().await?;
+        let mut entries = parse_array(&array);
+        entries.retain(|entry| decrypt(entry, key).is_none());
+        entries.push(encrypt(key, blob).ok_or(U2FError::Other)?);
+        let array = serialize_array(entries);
+
+        let max_fragment_length = self.max_fragment_length().await;
+        let length = u32::try_from(array.len()).map_err(|_| U2FError::InvalidLength)?;
+        let mut offset = 0;
+        for fragment in array.chunks(max_fragment_length) {
+            let mut request = large_blobs::Request {
+                get: None,
+                set: Some(fragment.to_vec().into()),
+                offset,
+                length: (offset == 0).then_some(length),
+                pin_uv_auth_param: None,
+                pin_uv_auth_protocol: None,
+            };
+            if let Some(token) = token {
+                let pin_uv_auth_param =
+                    PIN_PROTOCOL.authenticate(token, &request.pin_uv_auth_message());
+                request.pin_uv_auth_param = Some(pin_uv_auth_param.into());
+                request.pin_uv_auth_protocol = Some(PIN_PROTOCOL.version());
+            }
+            self.selected_mut().large_blobs(request)?;
+            // SAFETY: the fragments add up to the length of the array, which fits in a u32
+            offset += u32::try_from(fragment.len()).unwrap();
+        }
+        Ok(())
+    }
+
+    /// Read the whole serialized large-blob array in fragments.
+    async fn read_large_blob_array(&mut self) -> Result<Vec<u8>, StatusCode> {
+        let max_fragment_length = self.max_fragment_length().await;
+        // SAFETY: the fragment length is derived from a u32 message size
+        let get = u32::try_from(max_fragment_length).unwrap();
+        let mut array = Vec::new();
+        loop {
+            let fragment = self
+                .selected_mut()
+                .large_blobs(large_blobs::Request {
+                    get: Some(get),
+                    set: None,
+                    offset: u32::try_from(array.len()).map_err(|_| U2FError::InvalidLength)?,
+                    length: None,
+                    pin_uv_auth_param: None,
+                    pin_uv_auth_protocol: None,
+                })?
+                .config
+                .unwrap_or_default();
+            array.extend_from_slice(&fragment);
+            // A fragment shorter than requested is the last one.
+            if fragment.len() < max_fragment_length {
+                return Ok(array);
+            }
+        }
+    }
+
+    /// The largest fragment of the large-blob array which fits in a message to the authenticator,
+    /// leaving 64 bytes for the rest of the message.
+    async fn max_fragment_length(&self) -> usize {
+        let max_msg_size = self
+            .selected()
+            .get_info()
+            .await
+            .max_msg_size
+            .and_then(|max_msg_size| u32::try_from(max_msg_size.get()).ok())
+            .unwrap_or(DEFAULT_MAX_MSG_SIZE);
+        // SAFETY: a u32 always fits in a usize on the supported platforms
+        usize::try_from(max_msg_size.saturating_sub(64)).unwrap()
+    }
+}
+
+/// A `authenticatorClientPIN` request for `sub_command` with no parameters.
+fn pin_request(sub_command: client_pin::Subcommand) -> client_pin::Request {
+    client_pin::Request {
+        pin_protocol: PIN_PROTOCOL.version(),
+        sub_command,
+        key_agreement: None,
+        pin_auth: None,
+        new_pin_enc: None,
+        pin_hash_enc: None,
+        permissions: None,
+        rp_id: None,
+    }
+}
+
+/// The entries of a serialized large-blob array. An array whose hash does not match is treated as
+/// empty, like an array to which nothing was written.
+fn parse_array(serialized: &[u8]) -> Vec<Value> {
+    if !is_valid_large_blob_array(serialized) {
+        return Vec::new();
+    }
+    let array = &serialized[..serialized.len() - LARGE_BLOB_ARRAY_HASH_LENGTH];
+    match ciborium::de::from_reader(array) {
+        Ok(Value::Array(entries)) => entries,
+        _ => Vec::new(),
+    }
+}
+
+/// Serialize the large-blob array of `entries`, followed by the truncated hash which ends it.
+fn serialize_array(entries: Vec<Value>) -> Vec<u8> {
+    let mut serialized = Vec::new();
+    // SAFETY: writing to a Vec does not fail and CBOR values are always representable
+    ciborium::ser::into_writer(&Value::Array(entries), &mut serialized).unwrap();
+    let hash = sha256(&serialized);
+    serialized.extend_from_slice(&hash[..LARGE_BLOB_ARRAY_HASH_LENGTH]);
+    serialized
+}
+
+/// Compress and encrypt `blob` with `key` into an entry of the large-blob array.
+fn encrypt(key: &[u8], blob: &[u8]) -> Option<Value> {
+    let orig_size = u64::try_from(blob.len()).ok()?;
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let compressed = miniz_oxide::deflate::compress_to_vec(blob, 6);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &compressed,
+                aad: &associated_data(orig_size),
+            },
+        )
+        .ok()?;
+    Some(Value::Map(vec![
+        (Value::Integer(1.into()), Value::Bytes(ciphertext)),
+        (Value::Integer(2.into()), Value::Bytes(nonce.to_vec())),
+        (Value::Integer(3.into()), Value::Integer(orig_size.into())),
+    ]))
+}
+
+/// Decrypt and decompress the large blob of `entry`, if it was encrypted with `key`.
+fn decrypt(entry: &Value, key: &[u8]) -> Option<Vec<u8>> {
+    let fields = entry.as_map()?;
+    let field = |index: u8| {
+        fields
+            .iter()
+            .find(|(key, _)| key.as_integer() == Some(Integer::from(index)))
+            .map(|(_, value)| value)
+    };
+    let ciphertext = field(1)?.as_bytes()?;
+    let nonce: [u8; NONCE_LENGTH] = field(2)?.as_bytes()?.as_slice().try_into().ok()?;
+    let orig_size = u64::try_from(field(3)?.as_integer()?).ok()?;
+
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let compressed = cipher
+        .decrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &associated_data(orig_size),
+            },
+        )
+        .ok()?;
+    let limit = usize::try_from(orig_size).ok()?;
+    miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, limit)
+        .ok()
+        .filter(|blob| blob.len() == limit)
+}
+
+/// The associated data of an encrypted large blob: "blob" followed by its uncompressed size.
+fn associated_data(orig_size: u64) -> Vec<u8> {
+    [b"blob".as_slice(), &orig_size.to_le_bytes()].concat()
+}
@@ -14,27 +14,77 @@
 //! [version]: https://img.shields.io/crates/v/passkey-client?logo=rust&style=flat
 //! [documentation]: https://img.shields.io/docsrs/passkey-client/latest?logo=docs.rs&style=flat
 //! [Webauthn]: https://w3c.github.io/webauthn/
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::Arc};
 
-use ciborium::{cbor, value::Value};
-use coset::{iana::EnumI64, Algorithm};
-use passkey_authenticator::{Authenticator, CredentialStore, UserValidationMethod};
+use ciborium::value::Value;
+use coset::{
+    iana::{self, EnumI64},
+    Algorithm,
+};
+use passkey_authenticator::{
+    Authenticator, CredentialStore, DiscoverabilitySupport, UserValidationMethod,
+};
 use passkey_types::{
     crypto::sha256,
     ctap2, encoding, webauthn,
-    webauthn::{AuthenticatorExtensionsClientOutputs, CredentialPropertiesOutput},
+    webauthn::{
+        AuthenticationExtensionsLargeBlobOutputs, AuthenticatorExtensionsClientOutputs,
+        CredentialPropertiesOutput, LargeBlobSupport,
+    },
     Passkey,
 };
 use typeshare::typeshare;
 use url::Url;
 
+pub use self::{
+    abort::AbortHandle,
+    account_selection::AccountSelector,
+    android::{AndroidApp, AndroidAppsProvider},
+    associated_domains::AssociatedDomains,
+    audit::{CeremonyEvent, CeremonyObserver},
+    capabilities::ClientCapabilities,
+    client_data::{ClientDataCustomizer, ClientDataFields},
+    error::{CeremonyStep, DomExceptionName},
+    frame::FrameContext,
+    hints::HintsListener,
+    mediation::{CredentialChoice, CredentialPicker},
+    registration::Registration,
+    related_origins::RelatedOriginsProvider,
+    timeout::Timer,
+    verification_policy::UserVerificationPolicy,
+};
+
+mod abort;
+mod account_selection;
+mod android;
+mod associated_domains;
+pub mod attestation;
+mod audit;
+mod capabilities;
+mod client_data;
+pub mod device_pub_key;
+mod error;
+mod exclude;
+mod frame;
+mod hints;
+mod large_blob;
+mod mediation;
+mod registration;
+mod related_origins;
+mod routing;
+mod timeout;
+mod verification_policy;
+
 #[cfg(test)]
 mod tests;
 
 #[typeshare]
-#[derive(Debug, serde::Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Serialize, PartialEq, Eq)]
 #[serde(tag = "type", content = "content")]
 /// Errors produced by Webauthn Operations.
+///
+/// Use [`WebauthnError::dom_exception`] for the error a browser would report instead, and
+/// [`WebauthnError::step`] for the step of the ceremony which failed.
 pub enum WebauthnError {
     /// A credential ID can be a maximum of 1023 bytes.
     CredentialIdTooLong,
@@ -52,6 +102,24 @@ pub enum WebauthnError {
     InvalidRpId,
     /// Internal authenticator error whose value represents a `ctap2::StatusCode`
     AuthenticatorError(u8),
+    /// An extension input is not valid in this ceremony, or requires a capability which the
+    /// authenticator does not support.
+    NotSupported,
+    /// The FIDO AppID given in an extension may not be used by the request origin.
+    InvalidAppId,
+    /// The request was made from a frame which the permissions policy does not allow to use the
+    /// feature of the request.
+    NotAllowed,
+    /// The timeout of the request elapsed before the user completed it.
+    Timeout,
+    /// The request was aborted through an [`AbortHandle`].
+    Aborted,
+    /// No authenticator is of the attachment, or can create the discoverable credential or
+    /// verify the user, which the Relying Party requires.
+    UnsatisfiedCriteria,
+    /// One of the credentials excluded by the Relying Party is on an authenticator, which the
+    /// user confirmed with a gesture.
+    CredentialExcluded,
 }
 
 impl From<ctap2::StatusCode> for WebauthnError {
@@ -68,9 +136,184 @@ impl From<ctap2::StatusCode> for WebauthnError {
     }
 }
 
+/// Replace the potentially identifying attestation statement of a new credential with a `none`
+/// attestation for Relying Parties not interested in attestation, zeroing the AAGUID as well when
+/// `zero_aaguid` is set.
+///
+/// <https://w3c.github.io/webauthn/#dom-attestationconveyancepreference-none>
+fn anonymize_attestation(response: &mut ctap2::make_credential::Response, zero_aaguid: bool) {
+    response.fmt = "none".into();
+    response.att_stmt = Value::Map(Vec::new());
+    response.ep_att = None;
+    if !zero_aaguid {
+        return;
+    }
+    if let Some(credential) = response.auth_data.attested_credential_data.as_mut() {
+        credential.aaguid = ctap2::Aaguid::new_empty();
+    }
+}
+
+/// Whether to create a discoverable credential given the Relying Party's `criteria` and how the
+/// store supports them, failing when a required discoverable credential cannot be created.
+///
+/// <https://w3c.github.io/webauthn/#dom-authenticatorselectioncriteria-residentkey>
+fn resident_key(
+    criteria: Option<&webauthn::AuthenticatorSelectionCriteria>,
+    discoverability: DiscoverabilitySupport,
+) -> Result<bool, WebauthnError> {
+    let requirement = match criteria {
+        Some(webauthn::AuthenticatorSelectionCriteria {
+            resident_key: Some(requirement),
+            ..
+        }) => *requirement,
+        Some(criteria) if criteria.require_resident_key => {
+            webauthn::ResidentKeyRequirement::Required
+        }
+        _ => webauthn::ResidentKeyRequirement::Discouraged,
+    };
+    match (discoverability, requirement) {
+        (
+            DiscoverabilitySupport::OnlyNonDiscoverable,
+            webauthn::ResidentKeyRequirement::Required,
+        ) => Err(WebauthnError::UnsatisfiedCriteria),
+        (DiscoverabilitySupport::OnlyNonDiscoverable, _) => Ok(false),
+        (DiscoverabilitySupport::ForcedDiscoverable, _) => Ok(true),
+        (DiscoverabilitySupport::Full, requirement) => {
+            Ok(requirement != webauthn::ResidentKeyRequirement::Discouraged)
+        }
+    }
+}
+
+/// Whether to verify the user given the Relying Party's `requirement` and the `options` of the
+/// authenticator, failing when required user verification is not available.
+///
+/// Preferred user verification is only done when the authenticator is capable of it and
+/// configured, the user's presence is checked otherwise.
+///
+/// <https://w3c.github.io/webauthn/#enumdef-userverificationrequirement>
+fn user_verification(
+    requirement: webauthn::UserVerificationRequirement,
+    options: Option<&ctap2::get_info::Options>,
+) -> Result<bool, WebauthnError> {
+    let available = options.and_then(|options| options.uv) == Some(true);
+    match requirement {
+        webauthn::UserVerificationRequirement::Required if !available => {
+            Err(WebauthnError::UnsatisfiedCriteria)
+        }
+        webauthn::UserVerificationRequirement::Discouraged => Ok(false),
+        _ => Ok(available),
+    }
+}
+
+/// The credential parameters to forward to the authenticator, in order of preference: those of the
+/// Relying Party whose type is known and whose algorithm is not reserved, without duplicates.
+///
+/// An empty list asks for ES256 or RS256 credentials, while a list of which no parameters remain
+/// is not supported.
+///
+/// <https://w3c.github.io/webauthn/#sctn-createCredential>
+fn pub_key_cred_params(
+    params: Vec<webauthn::PublicKeyCredentialParameters>,
+) -> Result<Vec<webauthn::PublicKeyCredentialParameters>, WebauthnError> {
+    if params.is_empty() {
+        return Ok([iana::Algorithm::ES256, iana::Algorithm::RS256]
+            .into_iter()
+            .map(|alg| webauthn::PublicKeyCredentialParameters {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                alg,
+            })
+            .collect());
+    }
+    let mut normalized: Vec<webauthn::PublicKeyCredentialParameters> = Vec::new();
+    for param in params {
+        if param.ty == webauthn::PublicKeyCredentialType::PublicKey
+            && param.alg != iana::Algorithm::Reserved
+            && !normalized.contains(&param)
+        {
+            normalized.push(param);
+        }
+    }
+    if normalized.is_empty() {
+        return Err(WebauthnError::NotSupported);
+    }
+    Ok(normalized)
+}
+
+/// The transports of a new credential, as reported to the Relying Party: the transports of its
+/// authenticator without duplicates, in lexicographical order, or `None` when they are unknown.
+///
+/// <https://w3c.github.io/webauthn/#dom-authenticatorattestationresponse-gettransports>
+fn registration_transports(
+    transports: Option<Vec<webauthn::AuthenticatorTransport>>,
+) -> Option<Vec<webauthn::AuthenticatorTransport>> {
+    use webauthn::AuthenticatorTransport::*;
+    let mut transports = transports.filter(|transports| !transports.is_empty())?;
+    transports.sort_by_key(|transport| match transport {
+        Ble => "ble",
+        Hybrid => "hybrid",
+        Internal => "internal",
+        Nfc => "nfc",
+        Usb => "usb",
+    });
+    transports.dedup();
+    Some(transports)
+}
+
+/// The client extension outputs taken from the unsigned extension outputs of the authenticator:
+/// the `devicePubKey` output, and the outputs of extensions the client does not recognize, which
+/// are passed through untouched so that Relying Parties can use extensions implemented by custom
+/// authenticator handlers.
+fn unsigned_client_outputs(
+    outputs: Option<ctap2::UnsignedExtensionOutputs>,
+) -> AuthenticatorExtensionsClientOutputs {
+    let Some(outputs) = outputs else {
+        return AuthenticatorExtensionsClientOutputs::default();
+    };
+    AuthenticatorExtensionsClientOutputs {
+        device_pub_key: outputs.device_pub_key.map(Into::into),
+        unknown_keys: outputs.unknown_keys,
+        ..Default::default()
+    }
+}
+
+/// The payment details to report in the client data of a Secure Payment Confirmation assertion, or
+/// `None` when `inputs` do not ask for a payment to be confirmed.
+///
+/// The payment must name the RP ID of its credentials, its amount, its instrument and at least one
+/// of its payee's name or origin. The top origin is always the `top_origin` of the request, the
+/// one given in the `inputs` is ignored.
+///
+/// <https://w3c.github.io/secure-payment-confirmation/#sctn-collectedclientpaymentdata-dictionary>
+fn payment_data(
+    inputs: Option<&webauthn::AuthenticationExtensionsPaymentInputs>,
+    top_origin: &str,
+) -> Result<Option<webauthn::CollectedClientPaymentData>, WebauthnError> {
+    let Some(inputs) = inputs.filter(|inputs| inputs.is_payment == Some(true)) else {
+        return Ok(None);
+    };
+    let (Some(rp_id), Some(total), Some(instrument)) = (
+        inputs.rp_id.clone(),
+        inputs.total.clone(),
+        inputs.instrument.clone(),
+    ) else {
+        return Err(WebauthnError::NotSupported);
+    };
+    if inputs.payee_name.is_none() && inputs.payee_origin.is_none() {
+        return Err(WebauthnError::NotSupported);
+    }
+    Ok(Some(webauthn::CollectedClientPaymentData {
+        rp_id,
+        top_origin: top_origin.to_owned(),
+        payee_name: inputs.payee_name.clone(),
+        payee_origin: inputs.payee_origin.clone(),
+        total,
+        instrument,
+    }))
+}
+
 /// Returns a decoded [String] if the domain name is punycode otherwise
 /// the original string reference [str] is returned.
-fn decode_host(host: &str) -> Option<Cow<str>> {
+fn decode_host(host: &str) -> Option<Cow<'_, str>> {
     if host.split('.').any(|s| s.starts_with("xn--")) {
         let (decoded, result) = idna::domain_to_unicode(host);
         result.ok().map(|_| Cow::from(decoded))
@@ -90,18 +333,32 @@ fn decode_host(host: &str) -> Option<Cow<str>> {
 /// Public Suffix List.
 pub struct Client<S, U, P>
 where
-    S: CredentialStore + Sync,
+    S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync,
     P: public_suffix::EffectiveTLDProvider + Sync + 'static,
     Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
 {
-    authenticator: Authenticator<S, U>,
+    authenticators: Vec<Authenticator<S, U>>,
+    selected: usize,
     rp_id_verifier: RpIdVerifier<P>,
+    zero_aaguid: bool,
+    omit_user_handle: bool,
+    credential_picker: Option<Box<dyn CredentialPicker + Send + Sync>>,
+    account_selector: Option<Box<dyn AccountSelector + Send + Sync>>,
+    related_origins: Option<Box<dyn RelatedOriginsProvider + Send + Sync>>,
+    android_apps: Option<Box<dyn AndroidAppsProvider + Send + Sync>>,
+    rp_id_validator: Option<Box<dyn RpIdValidator + Send + Sync>>,
+    client_data_customizer: Option<Box<dyn ClientDataCustomizer + Send + Sync>>,
+    hints_listener: Option<Box<dyn HintsListener + Send + Sync>>,
+    user_verification_policy: Option<Box<dyn UserVerificationPolicy + Send + Sync>>,
+    ceremony_observer: Option<Box<dyn CeremonyObserver + Send + Sync>>,
+    timer: Option<Arc<dyn Timer + Send + Sync>>,
+    abort: AbortHandle,
 }
 
 impl<S, U> Client<S, U, public_suffix::PublicSuffixList>
 where
-    S: CredentialStore + Sync,
+    S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync,
     Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
 {
@@ -109,15 +366,29 @@ where
     /// TLD verifier provided by `[public_suffix]`.
     pub fn new(authenticator: Authenticator<S, U>) -> Self {
         Self {
-            authenticator,
+            authenticators: vec![authenticator],
+            selected: 0,
             rp_id_verifier: RpIdVerifier::new(public_suffix::DEFAULT_PROVIDER),
+            zero_aaguid: true,
+            omit_user_handle: false,
+            credential_picker: None,
+            account_selector: None,
+            related_origins: None,
+            android_apps: None,
+            rp_id_validator: None,
+            client_data_customizer: None,
+            hints_listener: None,
+            user_verification_policy: None,
+            ceremony_observer: None,
+            timer: None,
+            abort: AbortHandle::default(),
         }
     }
 }
 
 impl<S, U, P> Client<S, U, P>
 where
-    S: CredentialStore + Sync,
+    S: CredentialStore + Sync + Send,
     U: UserValidationMethod + Sync,
     P: public_suffix::EffectiveTLDProvider + Sync + 'static,
     Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
@@ -129,8 +400,22 @@ where
         custom_provider: P,
     ) -> Self {
         Self {
-            authenticator,
+            authenticators: vec![authenticator],
+            selected: 0,
             rp_id_verifier: RpIdVerifier::new(custom_provider),
+            zero_aaguid: true,
+            omit_user_handle: false,
+            credential_picker: None,
+            account_selector: None,
+            related_origins: None,
+            android_apps: None,
+            rp_id_validator: None,
+            client_data_customizer: None,
+            hints_listener: None,
+            user_verification_policy: None,
+            ceremony_observer: None,
+            timer: None,
+            abort: AbortHandle::default(),
         }
     }
 
@@ -140,65 +425,430 @@ where
         self
     }
 
-    /// Read access to the Client's `Authenticator`.
+    /// Sets whether the AAGUID of new credentials is zeroed when the Relying Party requests no
+    /// attestation, enabled by default. The real AAGUID is always kept when `indirect`, `direct`
+    /// or `enterprise` attestation is requested.
+    ///
+    /// Disabling this lets Relying Parties identify the authenticator model, for example to
+    /// display the name of the passkey provider, without receiving an attestation statement.
+    pub fn zero_aaguid_without_attestation(mut self, zero_aaguid: bool) -> Self {
+        self.zero_aaguid = zero_aaguid;
+        self
+    }
+
+    /// Sets whether the user handle is left out of assertions for which the Relying Party allowed
+    /// specific credentials, disabled by default.
+    ///
+    /// Relying Parties which allow specific credentials already know the account being
+    /// authenticated, so browsers may leave the user handle out of such assertions, as U2F
+    /// authenticators always did. Enabling this checks that Relying Parties do not rely on it.
+    pub fn omit_user_handle_with_allow_list(mut self, omit: bool) -> Self {
+        self.omit_user_handle = omit;
+        self
+    }
+
+    /// Sets the [`CredentialPicker`] through which the credentials of requests with
+    /// [conditional mediation](webauthn::CredentialMediationRequirement::Conditional) are offered
+    /// to the user. Without one, such requests fail with [`WebauthnError::NotSupported`].
+    pub fn with_credential_picker(
+        mut self,
+        picker: impl CredentialPicker + Send + Sync + 'static,
+    ) -> Self {
+        self.credential_picker = Some(Box::new(picker));
+        self
+    }
+
+    /// Sets the [`AccountSelector`] through which the user chooses the account to sign in with
+    /// when a request allows any credential and several discoverable credentials of its Relying
+    /// Party are found. Without one, the authenticator asserts the first credential it finds.
+    pub fn with_account_selector(
+        mut self,
+        selector: impl AccountSelector + Send + Sync + 'static,
+    ) -> Self {
+        self.account_selector = Some(Box::new(selector));
+        self
+    }
+
+    /// Sets the [`RpIdValidator`] deciding which RP IDs requests may use given their origin, in
+    /// place of the built-in [`RpIdVerifier`]. Related origins are still accepted when it fails
+    /// with [`WebauthnError::OriginRpMissmatch`], see [`Client::with_related_origins`].
+    ///
+    /// The FIDO AppIDs of the `appid` extensions are always verified by the [`RpIdVerifier`].
+    pub fn with_rp_id_validator(
+        mut self,
+        validator: impl RpIdValidator + Send + Sync + 'static,
+    ) -> Self {
+        self.rp_id_validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Sets the [`RelatedOriginsProvider`] from which the related origins of a Relying Party are
+    /// obtained when a request comes from an origin outside of its RP ID. Without one, such
+    /// requests fail with [`WebauthnError::OriginRpMissmatch`].
+    pub fn with_related_origins(
+        mut self,
+        provider: impl RelatedOriginsProvider + Send + Sync + 'static,
+    ) -> Self {
+        self.related_origins = Some(Box::new(provider));
+        self
+    }
+
+    /// Sets the [`AndroidAppsProvider`] from which the Android apps of a Relying Party are
+    /// obtained, accepting the requests of those apps from their `android:apk-key-hash:` origin.
+    /// Without one, such requests fail with [`WebauthnError::UnprotectedOrigin`].
+    pub fn with_android_apps(
+        mut self,
+        provider: impl AndroidAppsProvider + Send + Sync + 'static,
+    ) -> Self {
+        self.android_apps = Some(Box::new(provider));
+        self
+    }
+
+    /// Sets the [`ClientDataCustomizer`] which adds members to the client data of every request,
+    /// before the client serializes and hashes it.
+    pub fn with_client_data_customizer(
+        mut self,
+        customizer: impl ClientDataCustomizer + Send + Sync + 'static,
+    ) -> Self {
+        self.client_data_customizer = Some(Box::new(customizer));
+        self
+    }
+
+    /// Sets the [`HintsListener`] told the hints of each request which has some. Whether or not
+    /// one is set, the authenticators matching the hints of a request are tried first.
+    pub fn with_hints_listener(
+        mut self,
+        listener: impl HintsListener + Send + Sync + 'static,
+    ) -> Self {
+        self.hints_listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Sets the [`UserVerificationPolicy`] deciding the user verification requirement of each
+    /// request in place of the Relying Party, for registrations and authentications alike.
+    /// Without one, the requirement of the Relying Party applies.
+    pub fn with_user_verification_policy(
+        mut self,
+        policy: impl UserVerificationPolicy + Send + Sync + 'static,
+    ) -> Self {
+        self.user_verification_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Sets the [`CeremonyObserver`] told the [`CeremonyEvent`]s of every registration and
+    /// authentication, from their start to their completion or failure.
+    pub fn with_ceremony_observer(
+        mut self,
+        observer: impl CeremonyObserver + Send + Sync + 'static,
+    ) -> Self {
+        self.ceremony_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Sets the [`Timer`] with which the timeout of requests is enforced. Without one, requests
+    /// wait for the user indefinitely.
+    ///
+    /// The timeout requested by the Relying Party is clamped to the range recommended for the
+    /// user verification it requires, and a default applies when it requests none.
+    pub fn with_timer(mut self, timer: impl Timer + Send + Sync + 'static) -> Self {
+        self.timer = Some(Arc::new(timer));
+        self
+    }
+
+    /// A handle with which the request of the client in progress is aborted, for example when the
+    /// user dismisses its UI.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// Adds an `Authenticator` to the client, such as one whose credentials are synced next to one
+    /// whose credentials are bound to the device.
+    ///
+    /// Registrations are routed to the first authenticator, in the order they were added, which
+    /// satisfies the authenticator selection criteria of the request. Assertions are routed to the
+    /// first one storing an allowed credential, or a discoverable credential of the RP when any
+    /// credential is allowed.
+    pub fn with_authenticator(mut self, authenticator: Authenticator<S, U>) -> Self {
+        self.authenticators.push(authenticator);
+        self
+    }
+
+    /// Read access to the Client's first `Authenticator`, which it was created with.
     pub fn authenticator(&self) -> &Authenticator<S, U> {
-        &self.authenticator
+        &self.authenticators[0]
     }
 
-    /// Write access to the Client's `Authenticator`.
+    /// Write access to the Client's first `Authenticator`, which it was created with.
     pub fn authenticator_mut(&mut self) -> &mut Authenticator<S, U> {
-        &mut self.authenticator
+        &mut self.authenticators[0]
+    }
+
+    /// Read access to every `Authenticator` of the Client, in the order they were added.
+    pub fn authenticators(&self) -> &[Authenticator<S, U>] {
+        &self.authenticators
+    }
+
+    /// Write access to every `Authenticator` of the Client, in the order they were added.
+    pub fn authenticators_mut(&mut self) -> &mut [Authenticator<S, U>] {
+        &mut self.authenticators
     }
 
     /// Register a webauthn `request` from the given `origin`.
     ///
-    /// Returns either a [`webauthn::CreatedPublicKeyCredential`] on success or some [`WebauthnError`]
+    /// Returns either a [`Registration`] on success or some [`WebauthnError`]
     pub async fn register(
         &mut self,
         origin: &Url,
         request: webauthn::CredentialCreationOptions,
         client_data_hash: Option<Vec<u8>>,
-    ) -> Result<webauthn::CreatedPublicKeyCredential, WebauthnError> {
-        // extract inner value of request as there is nothing else of value directly in CredentialCreationOptions
-        let request = request.public_key;
-        let auth_info = self.authenticator.get_info();
+    ) -> Result<Registration, WebauthnError> {
+        self.register_from(origin, None, request, client_data_hash)
+            .await
+    }
 
-        // TODO: Handle given timeout here, If the value is not within what we consider a reasonable range
-        // override to our default
-        // let timeout = request
-        //     .timeout
-        //     .map(|t| t.clamp(MIN_TIMEOUT, MAX_TIMEOUT))
-        //     .unwrap_or(MAX_TIMEOUT);
+    /// Register a webauthn `request` from a frame at the given `origin`, embedded in the pages
+    /// of its [`FrameContext`].
+    ///
+    /// Fails with [`WebauthnError::NotAllowed`] when the frame may not create credentials.
+    /// Otherwise the client data tells the Relying Party whether the frame is cross-origin with
+    /// its ancestors, and the origin of the top-level page if so.
+    pub async fn register_in_frame(
+        &mut self,
+        origin: &Url,
+        frame: &FrameContext,
+        request: webauthn::CredentialCreationOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<Registration, WebauthnError> {
+        self.register_from(origin, Some(frame), request, client_data_hash)
+            .await
+    }
+
+    async fn register_from(
+        &mut self,
+        origin: &Url,
+        frame: Option<&FrameContext>,
+        request: webauthn::CredentialCreationOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<Registration, WebauthnError> {
+        let timeout = timeout::ceremony_timeout(
+            request.public_key.timeout,
+            request
+                .public_key
+                .authenticator_selection
+                .as_ref()
+                .map(|selection| selection.user_verification)
+                .unwrap_or_default(),
+        );
+        self.audit(webauthn::ClientDataType::Create, || {
+            CeremonyEvent::Started {
+                origin: origin.as_str().trim_end_matches('/').to_owned(),
+            }
+        });
+        let timer = self.timer.clone();
+        let abort = self.abort.clone();
+        let result = timeout::race(
+            timer.as_deref(),
+            timeout,
+            abort.run(self.create(origin, frame, request, client_data_hash)),
+        )
+        .await;
+        self.audit_outcome(
+            webauthn::ClientDataType::Create,
+            result.as_ref().map(Registration::credential_id),
+        );
+        result
+    }
 
-        let rp_id = self
-            .rp_id_verifier
-            .assert_domain(origin, request.rp.id.as_deref())?;
+    async fn create(
+        &mut self,
+        origin: &Url,
+        frame: Option<&FrameContext>,
+        request: webauthn::CredentialCreationOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<Registration, WebauthnError> {
+        // extract inner value of request as there is nothing else of value directly in CredentialCreationOptions
+        let mut request = request.public_key;
+
+        let (cross_origin, top_origin) = frame
+            .map(|frame| frame.assert_allowed(origin, webauthn::ClientDataType::Create))
+            .transpose()?
+            .unwrap_or_default();
+        let rp_id = self.assert_rp_id(origin, request.rp.id.as_deref()).await?;
+        self.audit(webauthn::ClientDataType::Create, || {
+            CeremonyEvent::OriginValidated {
+                rp_id: rp_id.to_owned(),
+            }
+        });
+        let app_id_exclude = request
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.appid_exclude.as_deref())
+            .map(|app_id| self.rp_id_verifier.assert_app_id(origin, app_id))
+            .transpose()?
+            .map(str::to_owned);
+        let pub_key_cred_params =
+            pub_key_cred_params(std::mem::take(&mut request.pub_key_cred_params))?;
+        self.notify_hints(webauthn::ClientDataType::Create, request.hints.as_deref());
+        let requested_user_verification = request
+            .authenticator_selection
+            .as_ref()
+            .map(|selection| selection.user_verification)
+            .unwrap_or_default();
+        let user_verification = self.user_verification_requirement(
+            webauthn::ClientDataType::Create,
+            rp_id,
+            requested_user_verification,
+        );
+        if user_verification != requested_user_verification {
+            request
+                .authenticator_selection
+                .get_or_insert(webauthn::AuthenticatorSelectionCriteria {
+                    authenticator_attachment: None,
+                    resident_key: None,
+                    require_resident_key: false,
+                    user_verification,
+                })
+                .user_verification = user_verification;
+        }
 
         let collected_client_data = webauthn::CollectedClientData {
             ty: webauthn::ClientDataType::Create,
             challenge: encoding::base64url(&request.challenge),
             origin: origin.as_str().trim_end_matches('/').to_owned(),
-            cross_origin: None,
-            unknown_keys: Default::default(),
+            cross_origin: Some(cross_origin),
+            unknown_keys: top_origin
+                .map(|top_origin| ("topOrigin".to_owned(), top_origin.into()))
+                .into_iter()
+                .collect(),
         };
 
+        let collected_client_data = self.customize_client_data(collected_client_data);
         // SAFETY: it is a developer error if serializing this struct fails.
         let client_data_json = serde_json::to_string(&collected_client_data).unwrap();
         let client_data_json_hash =
             client_data_hash.unwrap_or_else(|| sha256(client_data_json.as_bytes()).to_vec());
 
+        // The authenticator must be of the requested attachment, and satisfy the discoverability
+        // and user verification the Relying Party requires. Credentials excluded by the Relying
+        // Party may be on any of the authenticators.
+        let routing::RegistrationOptions {
+            rk,
+            uv,
+            info: auth_info,
+        } = self
+            .select_for_registration(
+                request.authenticator_selection.as_ref(),
+                request.hints.as_deref(),
+            )
+            .await?;
+        self.audit_selected(webauthn::ClientDataType::Create);
+        if let Some(exclude_list) = request.exclude_credentials.as_deref() {
+            if self
+                .find_excluded_credential(rp_id, exclude_list)
+                .await
+                .is_some()
+            {
+                return Err(self.credential_excluded().await);
+            }
+        }
         let cred_props =
             if let Some(true) = request.extensions.as_ref().and_then(|ext| ext.cred_props) {
                 Some(CredentialPropertiesOutput {
-                    discoverable: Some(true), // Set to true because it is set in the Options of make_credential.
-                    authenticator_display_name: self.authenticator.display_name().cloned(),
+                    discoverable: Some(rk),
+                    authenticator_display_name: self.selected().display_name().cloned(),
                 })
             } else {
                 None
             };
 
+        // Large blobs are only read or written during authentication. A credential which requires
+        // large blob storage cannot be created on an authenticator which does not support it, nor
+        // as a non-discoverable credential. Otherwise the authenticator is asked for the key of
+        // the large blob through the CTAP largeBlobKey extension.
+        let large_blob_support = match request
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.large_blob.as_ref())
+        {
+            Some(large_blob) if large_blob.read.is_some() || large_blob.write.is_some() => {
+                return Err(WebauthnError::NotSupported);
+            }
+            Some(large_blob) => large_blob.support,
+            None => None,
+        };
+        let supports_large_blobs = rk
+            && auth_info
+                .extensions
+                .as_ref()
+                .is_some_and(|extensions| extensions.iter().any(|ext| ext == "largeBlobKey"));
+        if !supports_large_blobs && large_blob_support == Some(LargeBlobSupport::Required) {
+            return Err(WebauthnError::NotSupported);
+        }
+        if let Some(extensions) = request.extensions.as_mut() {
+            extensions.large_blob = None;
+            extensions.large_blob_key =
+                (supports_large_blobs && large_blob_support.is_some()).then_some(true);
+        }
+
+        // A credential registered for payments is enabled for third-party payments through the
+        // CTAP thirdPartyPayment extension, when the authenticator supports it.
+        let supports_third_party_payment = auth_info
+            .extensions
+            .as_ref()
+            .is_some_and(|extensions| extensions.iter().any(|ext| ext == "thirdPartyPayment"));
+        if let Some(extensions) = request.extensions.as_mut().filter(|ext| {
+            ext.payment
+                .as_ref()
+                .is_some_and(|payment| payment.is_payment == Some(true))
+        }) {
+            if supports_third_party_payment {
+                extensions.third_party_payment = Some(true);
+            }
+        }
+
+        // Credentials registered with the FIDO U2F API are bound to the AppID rather than the RP ID.
+        // Look for the excluded ones under it as well, asking for the user's presence only once
+        // one is found.
+        if let (Some(app_id), Some(exclude_list)) = (
+            app_id_exclude.as_ref(),
+            request
+                .exclude_credentials
+                .as_ref()
+                .filter(|list| !list.is_empty()),
+        ) {
+            let excluded = self
+                .selected_mut()
+                .get_assertion(ctap2::get_assertion::Request {
+                    rp_id: app_id.clone(),
+                    client_data_hash: client_data_json_hash.clone().into(),
+                    allow_list: Some(exclude_list.clone()),
+                    extensions: None,
+                    options: ctap2::get_assertion::Options {
+                        rk: false,
+                        up: false,
+                        uv: false,
+                    },
+                    pin_auth: None,
+                    pin_protocol: None,
+                })
+                .await
+                .is_ok();
+            if excluded {
+                return Err(self.credential_excluded().await);
+            }
+        }
+
+        // Only forward enterprise attestation requests to authenticators which support it. The
+        // request is vendor facilitated, leaving the authenticator to decide whether the RP ID may
+        // receive an enterprise attestation.
+        let attestation = request.attestation;
+        let enterprise_attestation = (attestation
+            == webauthn::AttestationConveyancePreference::Enterprise
+            && auth_info.options.as_ref().and_then(|options| options.ep) == Some(true))
+        .then_some(1);
+
         let ctap2_response = self
-            .authenticator
+            .selected_mut()
             .make_credential(ctap2::make_credential::Request {
                 client_data_hash: client_data_json_hash.into(),
                 rp: ctap2::make_credential::PublicKeyCredentialRpEntity {
@@ -206,40 +856,48 @@ where
                     name: Some(request.rp.name),
                 },
                 user: request.user,
-                pub_key_cred_params: request.pub_key_cred_params,
+                pub_key_cred_params,
                 exclude_list: request.exclude_credentials,
                 extensions: request.extensions,
-                options: ctap2::make_credential::Options {
-                    rk: true,
-                    up: true,
-                    uv: true,
-                },
+                options: ctap2::make_credential::Options { rk, up: true, uv },
                 pin_auth: None,
                 pin_protocol: None,
+                enterprise_attestation,
             })
-            .await
-            .map_err(|sc| WebauthnError::AuthenticatorError(sc.into()))?;
-
-        let mut attestation_object = Vec::with_capacity(128);
-        // SAFETY: The Results here are from serializing all the internals of `cbor!` into `ciborium::Value`
-        // then serializing said value to bytes. The unwraps here are safe because it would otherwise be
-        // programmer error.
-        // TODO: Create strong attestation type definitions, part of CTAP2
-        let attestation_object_value = cbor!({
-               // TODO: Follow preference and/or implement AnonCA https://w3c.github.io/webauthn/#anonymization-ca
-               "fmt" => "none",
-                "attStmt" => {},
-                // Explicitly define these fields as bytes since specialization is still fairly far
-               "authData" => Value::Bytes(ctap2_response.auth_data.to_vec()),
-        })
-        .unwrap();
-        ciborium::ser::into_writer(&attestation_object_value, &mut attestation_object).unwrap();
+            .await;
+        let mut ctap2_response = match ctap2_response {
+            Ok(response) => response,
+            // The authenticator collected the user's gesture before checking the exclude list.
+            Err(ctap2::StatusCode::Ctap2(ctap2::Ctap2Code::Known(
+                ctap2::Ctap2Error::CredentialExcluded,
+            ))) => return Err(WebauthnError::CredentialExcluded),
+            Err(sc) => return Err(WebauthnError::AuthenticatorError(sc.into())),
+        };
+        self.audit_user_checked(
+            webauthn::ClientDataType::Create,
+            ctap2_response.auth_data.flags,
+        );
+
+        if attestation == webauthn::AttestationConveyancePreference::None {
+            anonymize_attestation(&mut ctap2_response, self.zero_aaguid);
+        }
+
+        let unsigned_outputs =
+            unsigned_client_outputs(ctap2_response.unsigned_extension_outputs.take());
+
+        let large_blob = large_blob_support.map(|_| AuthenticationExtensionsLargeBlobOutputs {
+            supported: Some(ctap2_response.large_blob_key.is_some()),
+            ..Default::default()
+        });
 
-        // SAFETY: this unwrap is safe because the ctap2_response was just created in make_credential()
+        // TODO: implement AnonCA for indirect attestation https://w3c.github.io/webauthn/#anonymization-ca
+        let attestation_object = ctap2::AttestationObject::from(ctap2_response);
+
+        // SAFETY: this unwrap is safe because the attestation_object was just created in make_credential()
         // above, which currently sets auth_data.attested_credential_data unconditionally.
         // If this fails, it's a programmer error in that the postconditions of make_credential will
         // have changed.
-        let credential_id = ctap2_response
+        let credential_id = attestation_object
             .auth_data
             .attested_credential_data
             .as_ref()
@@ -252,89 +910,316 @@ where
             }
         };
         let public_key = Some(
-            passkey_authenticator::public_key_der_from_cose_key(&credential_id.key)
-                .map_err(|e| WebauthnError::AuthenticatorError(e.into()))?,
+            passkey_types::cose::public_key_der_from_cose_key(&credential_id.key).map_err(|e| {
+                WebauthnError::AuthenticatorError(ctap2::Ctap2Error::from(e).into())
+            })?,
         );
 
-        let response = webauthn::CreatedPublicKeyCredential {
+        let credential = webauthn::CreatedPublicKeyCredential {
             id: encoding::base64url(credential_id.credential_id()),
             raw_id: credential_id.credential_id().to_vec().into(),
             ty: webauthn::PublicKeyCredentialType::PublicKey,
             response: webauthn::AuthenticatorAttestationResponse {
                 client_data_json: Vec::from(client_data_json).into(),
-                authenticator_data: ctap2_response.auth_data.to_vec().into(),
+                authenticator_data: attestation_object.auth_data.to_vec().into(),
                 public_key,
                 public_key_algorithm: alg,
-                attestation_object: attestation_object.into(),
-                transports: auth_info.transports,
+                attestation_object: attestation_object.to_cbor().into(),
+                transports: registration_transports(auth_info.transports),
+            },
+            authenticator_attachment: Some(self.selected().attachment_type()),
+            client_extension_results: AuthenticatorExtensionsClientOutputs {
+                cred_props,
+                large_blob,
+                appid_exclude: app_id_exclude.map(|_| true),
+                ..unsigned_outputs
             },
-            authenticator_attachment: Some(self.authenticator().attachment_type()),
-            client_extension_results: AuthenticatorExtensionsClientOutputs { cred_props },
         };
 
-        Ok(response)
+        self.audit_extensions(
+            webauthn::ClientDataType::Create,
+            &credential.client_extension_results,
+        );
+        Ok(Registration {
+            credential,
+            attestation_object,
+        })
     }
 
     /// Authenticate a Webauthn request.
     ///
     /// Returns either an [`webauthn::AuthenticatedPublicKeyCredential`] on success or some [`WebauthnError`].
     pub async fn authenticate(
-        &self,
+        &mut self,
         origin: &Url,
         request: webauthn::CredentialRequestOptions,
         client_data_hash: Option<Vec<u8>>,
     ) -> Result<webauthn::AuthenticatedPublicKeyCredential, WebauthnError> {
-        // extract inner value of request as there is nothing else of value directly in CredentialRequestOptions
-        let request = request.public_key;
+        self.authenticate_from(origin, None, request, client_data_hash)
+            .await
+    }
 
-        // TODO: Handle given timeout here, If the value is not within what we consider a reasonable range
-        // override to our default
-        // let timeout = request
-        //     .timeout
-        //     .map(|t| t.clamp(MIN_TIMEOUT, MAX_TIMEOUT))
-        //     .unwrap_or(MAX_TIMEOUT);
+    /// Authenticate a Webauthn request from a frame at the given `origin`, embedded in the pages
+    /// of its [`FrameContext`].
+    ///
+    /// Fails with [`WebauthnError::NotAllowed`] when the frame may not make assertions.
+    /// Otherwise the client data tells the Relying Party whether the frame is cross-origin with
+    /// its ancestors, and the origin of the top-level page if so.
+    pub async fn authenticate_in_frame(
+        &mut self,
+        origin: &Url,
+        frame: &FrameContext,
+        request: webauthn::CredentialRequestOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<webauthn::AuthenticatedPublicKeyCredential, WebauthnError> {
+        self.authenticate_from(origin, Some(frame), request, client_data_hash)
+            .await
+    }
 
-        let rp_id = self
-            .rp_id_verifier
-            .assert_domain(origin, request.rp_id.as_deref())?;
+    async fn authenticate_from(
+        &mut self,
+        origin: &Url,
+        frame: Option<&FrameContext>,
+        request: webauthn::CredentialRequestOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<webauthn::AuthenticatedPublicKeyCredential, WebauthnError> {
+        let timeout = timeout::ceremony_timeout(
+            request.public_key.timeout,
+            request.public_key.user_verification,
+        );
+        self.audit(webauthn::ClientDataType::Get, || CeremonyEvent::Started {
+            origin: origin.as_str().trim_end_matches('/').to_owned(),
+        });
+        let timer = self.timer.clone();
+        let abort = self.abort.clone();
+        let result = timeout::race(
+            timer.as_deref(),
+            timeout,
+            abort.run(self.get(origin, frame, request, client_data_hash)),
+        )
+        .await;
+        self.audit_outcome(
+            webauthn::ClientDataType::Get,
+            result
+                .as_ref()
+                .map(|credential| credential.raw_id.as_slice()),
+        );
+        result
+    }
+
+    async fn get(
+        &mut self,
+        origin: &Url,
+        frame: Option<&FrameContext>,
+        request: webauthn::CredentialRequestOptions,
+        client_data_hash: Option<Vec<u8>>,
+    ) -> Result<webauthn::AuthenticatedPublicKeyCredential, WebauthnError> {
+        let mediation = request.mediation.unwrap_or_default();
+        let mut request = request.public_key;
+
+        let origin_str = origin.as_str().trim_end_matches('/');
+        let (cross_origin, top_origin) = frame
+            .map(|frame| frame.assert_allowed(origin, webauthn::ClientDataType::Get))
+            .transpose()?
+            .unwrap_or_default();
+        // A payment may be confirmed from any secure origin, with credentials of the RP ID it
+        // names, and only with the credentials it allows.
+        let payment = payment_data(
+            request
+                .extensions
+                .as_ref()
+                .and_then(|ext| ext.payment.as_ref()),
+            top_origin.as_deref().unwrap_or(origin_str),
+        )?;
+        let rp_id = match &payment {
+            Some(_) if request.allow_credentials.as_ref().is_none_or(Vec::is_empty) => {
+                return Err(WebauthnError::NotSupported);
+            }
+            Some(payment) => {
+                self.assert_rp_id(origin, None).await?;
+                payment.rp_id.as_str()
+            }
+            None => self.assert_rp_id(origin, request.rp_id.as_deref()).await?,
+        };
+        self.audit(webauthn::ClientDataType::Get, || {
+            CeremonyEvent::OriginValidated {
+                rp_id: rp_id.to_owned(),
+            }
+        });
+        self.notify_hints(webauthn::ClientDataType::Get, request.hints.as_deref());
+        request.user_verification = self.user_verification_requirement(
+            webauthn::ClientDataType::Get,
+            rp_id,
+            request.user_verification,
+        );
+        let rp_allows_credentials = request
+            .allow_credentials
+            .as_ref()
+            .is_some_and(|list| !list.is_empty());
+        // With conditional mediation, the request stays pending until the user picks one of the
+        // discoverable credentials of the RP, which is then the only one allowed.
+        if mediation == webauthn::CredentialMediationRequirement::Conditional {
+            if payment.is_some() {
+                return Err(WebauthnError::NotSupported);
+            }
+            let credential = self
+                .pick_credential(rp_id, request.allow_credentials.as_deref())
+                .await?;
+            request.allow_credentials = Some(vec![credential]);
+        } else if !rp_allows_credentials {
+            // The user chooses among the accounts of the RP, the chosen credential is then the
+            // only one allowed.
+            if let Some(credential) = self.select_account(rp_id).await? {
+                request.allow_credentials = Some(vec![credential]);
+            }
+        }
+        // The authenticator reports whether the credential is enabled for payments through the
+        // CTAP thirdPartyPayment extension.
+        if let Some(extensions) = request.extensions.as_mut().filter(|_| payment.is_some()) {
+            extensions.third_party_payment = Some(true);
+        }
+        let app_id = request
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.appid.as_deref())
+            .map(|app_id| self.rp_id_verifier.assert_app_id(origin, app_id))
+            .transpose()?
+            .map(str::to_owned);
+        self.select_for_assertion(
+            rp_id,
+            app_id.as_deref(),
+            request.allow_credentials.as_deref(),
+            request.hints.as_deref(),
+        )
+        .await;
+        self.audit_selected(webauthn::ClientDataType::Get);
 
+        // SAFETY: it is a developer error if serializing this struct fails.
+        let payment_data = payment
+            .as_ref()
+            .map(|payment| ("payment".to_owned(), serde_json::to_value(payment).unwrap()));
         let collected_client_data = webauthn::CollectedClientData {
-            ty: webauthn::ClientDataType::Get,
+            ty: if payment.is_some() {
+                webauthn::ClientDataType::PaymentGet
+            } else {
+                webauthn::ClientDataType::Get
+            },
             challenge: encoding::base64url(&request.challenge),
-            origin: origin.as_str().trim_end_matches('/').to_owned(),
-            cross_origin: None, //Some(false),
-            unknown_keys: Default::default(),
+            origin: origin_str.to_owned(),
+            cross_origin: Some(cross_origin),
+            unknown_keys: top_origin
+                .map(|top_origin| ("topOrigin".to_owned(), top_origin.into()))
+                .into_iter()
+                .chain(payment_data)
+                .collect(),
         };
 
+        let collected_client_data = self.customize_client_data(collected_client_data);
         // SAFETY: it is a developer error if serializing this struct fails.
         let client_data_json = serde_json::to_string(&collected_client_data).unwrap();
         let client_data_json_hash =
             client_data_hash.unwrap_or_else(|| sha256(client_data_json.as_bytes()).to_vec());
 
-        let ctap2_response = self
-            .authenticator
-            .get_assertion(ctap2::get_assertion::Request {
-                rp_id: rp_id.to_owned(),
-                client_data_hash: client_data_json_hash.into(),
-                allow_list: request.allow_credentials,
-                extensions: request.extensions,
-                options: ctap2::get_assertion::Options {
-                    rk: true,
-                    up: true,
-                    uv: true,
-                },
-                pin_auth: None,
-                pin_protocol: None,
-            })
-            .await
-            .map_err(Into::<WebauthnError>::into)?;
+        // A large blob is either read or written, and writing one may require a PIN/UV auth token
+        // with the permission to do so, which then also verifies the user for the assertion. The
+        // authenticator is asked for the key of the large blob through the CTAP largeBlobKey
+        // extension.
+        let large_blob = request.extensions.as_mut().and_then(|ext| {
+            let large_blob = ext.large_blob.take();
+            ext.large_blob_key = large_blob.as_ref().map(|_| true);
+            large_blob
+        });
+        let token = match &large_blob {
+            Some(large_blob)
+                if large_blob.support.is_some()
+                    || (large_blob.read.is_some() && large_blob.write.is_some()) =>
+            {
+                return Err(WebauthnError::NotSupported);
+            }
+            Some(large_blob) if large_blob.write.is_some() => {
+                self.large_blob_write_token(rp_id).await?
+            }
+            _ => None,
+        };
+        let pin_auth = token
+            .as_deref()
+            .map(|token| large_blob::PIN_PROTOCOL.authenticate(token, &client_data_json_hash));
+        // A PIN/UV auth token already verifies the user.
+        let uv = pin_auth.is_none()
+            && user_verification(
+                request.user_verification,
+                self.selected().get_info().await.options.as_ref(),
+            )?;
+
+        let assertion_request = ctap2::get_assertion::Request {
+            rp_id: rp_id.to_owned(),
+            client_data_hash: client_data_json_hash.into(),
+            allow_list: request.allow_credentials,
+            extensions: request.extensions,
+            options: ctap2::get_assertion::Options {
+                rk: true,
+                up: true,
+                uv,
+            },
+            pin_protocol: pin_auth
+                .is_some()
+                .then(|| large_blob::PIN_PROTOCOL.version()),
+            pin_auth: pin_auth.map(Into::into),
+        };
+        // Credentials registered with the FIDO U2F API are bound to the AppID rather than the RP
+        // ID, look for them under it when none is found under the RP ID.
+        let result = self
+            .selected_mut()
+            .get_assertion(assertion_request.clone())
+            .await;
+        let (result, used_app_id) = match (result, app_id.as_ref()) {
+            (Err(err), Some(app_id)) if err == ctap2::Ctap2Error::NoCredentials.into() => {
+                let result = self
+                    .selected_mut()
+                    .get_assertion(ctap2::get_assertion::Request {
+                        rp_id: app_id.clone(),
+                        ..assertion_request
+                    })
+                    .await;
+                (result, true)
+            }
+            (result, _) => (result, false),
+        };
+        let mut ctap2_response = result.map_err(Into::<WebauthnError>::into)?;
+        self.audit_user_checked(
+            webauthn::ClientDataType::Get,
+            ctap2_response.auth_data.flags,
+        );
+        if payment.is_some()
+            && ctap2_response
+                .auth_data
+                .parse_extensions()
+                .ok()
+                .and_then(|outputs| outputs.third_party_payment)
+                != Some(true)
+        {
+            return Err(WebauthnError::CredentialNotFound);
+        }
+        let unsigned_outputs =
+            unsigned_client_outputs(ctap2_response.unsigned_extension_outputs.take());
 
         // SAFETY: This unwrap is safe because ctap2_response was created immedately
         // above and the postcondition of that function is that response.credential
         // will yield a credential. If none was found, we will have already returned
         // a WebauthnError::CredentialNotFound error from map_err in that line.
         let credential_id_bytes = ctap2_response.credential.unwrap().id;
-        Ok(webauthn::AuthenticatedPublicKeyCredential {
+        let large_blob = match large_blob {
+            Some(large_blob) => Some(
+                self.process_large_blob(
+                    large_blob,
+                    ctap2_response.large_blob_key.as_deref().map(Vec::as_slice),
+                    token.as_deref(),
+                )
+                .await,
+            ),
+            None => None,
+        };
+        let credential = webauthn::AuthenticatedPublicKeyCredential {
             id: encoding::base64url(&credential_id_bytes),
             raw_id: credential_id_bytes.to_vec().into(),
             ty: webauthn::PublicKeyCredentialType::PublicKey,
@@ -342,12 +1227,54 @@ where
                 client_data_json: Vec::from(client_data_json).into(),
                 authenticator_data: ctap2_response.auth_data.to_vec().into(),
                 signature: ctap2_response.signature,
-                user_handle: ctap2_response.user.map(|user| user.id),
+                user_handle: ctap2_response
+                    .user
+                    .map(|user| user.id)
+                    .filter(|_| !(self.omit_user_handle && rp_allows_credentials)),
                 attestation_object: None,
             },
-            authenticator_attachment: Some(self.authenticator().attachment_type()),
-            client_extension_results: AuthenticatorExtensionsClientOutputs::default(),
-        })
+            authenticator_attachment: Some(self.selected().attachment_type()),
+            client_extension_results: AuthenticatorExtensionsClientOutputs {
+                large_blob,
+                appid: app_id.map(|_| used_app_id),
+                ..unsigned_outputs
+            },
+        };
+        self.audit_extensions(
+            webauthn::ClientDataType::Get,
+            &credential.client_extension_results,
+        );
+        Ok(credential)
+    }
+}
+
+/// Use this on a type that verifies the RP ID of a request against its origin, for example to use
+/// internal domains of an enterprise, or `localhost` and custom schemes in development.
+///
+/// It is implemented by [`RpIdVerifier`] following the WebAuthn rules, which can be relied on for
+/// the origins that are not handled specially.
+pub trait RpIdValidator {
+    /// Verify the given Relying Party ID, if any, against the `origin` of the request.
+    ///
+    /// Returns the effective RP ID on success, the domain of the origin when no RP ID is given,
+    /// or some [`WebauthnError`]
+    fn assert_domain<'a>(
+        &self,
+        origin: &'a Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError>;
+}
+
+impl<P> RpIdValidator for RpIdVerifier<P>
+where
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+{
+    fn assert_domain<'a>(
+        &self,
+        origin: &'a Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError> {
+        RpIdVerifier::assert_domain(self, origin, rp_id)
     }
 }
 
@@ -425,4 +1352,98 @@ where
 
         Ok(effective_domain)
     }
+
+    /// Verify that `origin` is one of the [`RelatedOrigins`](webauthn::RelatedOrigins) of the
+    /// Relying Party with the given ID, for requests from an origin outside of the RP ID.
+    ///
+    /// Only the origins with the first five distinct labels of their registrable domain, such as
+    /// `example` in `https://example.co.uk`, are considered.
+    ///
+    /// This follows the related origins validation procedure: <https://w3c.github.io/webauthn/#sctn-validating-relation-origin>
+    ///
+    /// Returns the RP ID on success or some [`WebauthnError`]
+    pub fn assert_related_origin<'a>(
+        &self,
+        origin: &Url,
+        rp_id: &'a str,
+        related_origins: &webauthn::RelatedOrigins,
+    ) -> Result<&'a str, WebauthnError> {
+        if !(origin.scheme().eq_ignore_ascii_case("https")) {
+            return Err(WebauthnError::UnprotectedOrigin);
+        }
+        let registrable_label = |domain: &str| {
+            decode_host(domain).and_then(|domain| {
+                self.tld_provider
+                    .effective_tld_plus_one(&domain)
+                    .ok()
+                    .and_then(|domain| domain.split('.').next())
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_owned)
+            })
+        };
+        if registrable_label(rp_id).is_none() {
+            return Err(WebauthnError::InvalidRpId);
+        }
+
+        let mut labels_seen = Vec::new();
+        for related_origin in &related_origins.origins {
+            let Some(url) = Url::parse(related_origin).ok() else {
+                continue;
+            };
+            let Some(label) = url.domain().and_then(registrable_label) else {
+                continue;
+            };
+            if labels_seen.len() >= related_origins::MAX_RELATED_ORIGIN_LABELS
+                && !labels_seen.contains(&label)
+            {
+                continue;
+            }
+            if url.origin() == origin.origin() {
+                return Ok(rp_id);
+            }
+            if !labels_seen.contains(&label) {
+                labels_seen.push(label);
+            }
+        }
+        Err(WebauthnError::OriginRpMissmatch)
+    }
+
+    /// Verify that the FIDO AppID of a credential registered with the legacy FIDO U2F API may be
+    /// used by the origin of the request, which is the case when both use HTTPS and share the
+    /// same registrable domain.
+    ///
+    /// This is the check of the `appid` and `appidExclude` extensions: <https://w3c.github.io/webauthn/#sctn-appid-extension>
+    ///
+    /// Returns the AppID on success or some [`WebauthnError`]
+    pub fn assert_app_id<'a>(
+        &self,
+        origin: &Url,
+        app_id: &'a str,
+    ) -> Result<&'a str, WebauthnError> {
+        let origin_domain = origin.domain().ok_or(WebauthnError::OriginMissingDomain)?;
+        if !(origin.scheme().eq_ignore_ascii_case("https")) {
+            return Err(WebauthnError::UnprotectedOrigin);
+        }
+
+        let app_id_url = Url::parse(app_id).map_err(|_| WebauthnError::InvalidAppId)?;
+        if !(app_id_url.scheme().eq_ignore_ascii_case("https")) {
+            return Err(WebauthnError::InvalidAppId);
+        }
+        let app_id_domain = app_id_url.domain().ok_or(WebauthnError::InvalidAppId)?;
+
+        let registrable_domain = |domain: &str| {
+            decode_host(domain).and_then(|domain| {
+                self.tld_provider
+                    .effective_tld_plus_one(&domain)
+                    .ok()
+                    .map(str::to_owned)
+            })
+        };
+        match registrable_domain(origin_domain) {
+            Some(domain) if Some(&domain) == registrable_domain(app_id_domain).as_ref() => {
+                Ok(app_id)
+            }
+            _ => Err(WebauthnError::InvalidAppId),
+        }
+    }
 }
@@ -0,0 +1,136 @@
+//! Conditional mediation, in which the credentials of a request are offered in the existing UI of
+//! the host, such as the autofill suggestions of a username field, and the request stays pending
+//! until the user picks one of them.
+//!
+//! <https://w3c.github.io/webappsec-credential-management/#dom-credentialmediationrequirement-conditional>
+
+use passkey_authenticator::{CredentialStore, DiscoverableCredential, UserValidationMethod};
+use passkey_types::{ctap2, webauthn, Bytes, Passkey};
+
+use crate::{Client, WebauthnError};
+
+/// A discoverable credential offered to the user by a [`CredentialPicker`].
+#[derive(Debug, Clone)]
+pub struct CredentialChoice {
+    /// The ID of the credential.
+    pub id: Bytes,
+    /// The user the credential was created for, whose names are shown to tell credentials apart.
+    pub user: ctap2::make_credential::PublicKeyCredentialUserEntity,
+}
+
+/// Use this on the UI of the host in which a [`Client`] offers the credentials of a conditional
+/// request, see [`Client::with_credential_picker`].
+#[async_trait::async_trait]
+pub trait CredentialPicker {
+    /// Offer the `credentials` of the Relying Party with the given ID to the user and wait until
+    /// they pick one of them, returning its [`CredentialChoice::id`]. Return `None` when the
+    /// request is dismissed, for example because the user left the page.
+    async fn pick_credential(&self, rp_id: &str, credentials: &[CredentialChoice])
+        -> Option<Bytes>;
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// The discoverable credentials of every authenticator of the client, in the order the
+    /// authenticators were added, leaving out those of authenticators which cannot enumerate them.
+    ///
+    /// Fails with the error of the first authenticator when none of them can enumerate its
+    /// credentials.
+    pub async fn discoverable_credentials(
+        &self,
+    ) -> Result<Vec<DiscoverableCredential>, WebauthnError> {
+        let mut credentials = Vec::<DiscoverableCredential>::new();
+        let mut first_error = None;
+        let mut enumerated = false;
+        for authenticator in &self.authenticators {
+            match authenticator.store().discoverable_credentials().await {
+                Ok(found) => {
+                    enumerated = true;
+                    for credential in found {
+                        let known = credentials.iter().any(|known| {
+                            known.passkey.credential_id == credential.passkey.credential_id
+                        });
+                        if !known {
+                            credentials.push(credential);
+                        }
+                    }
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        match first_error {
+            Some(err) if !enumerated => Err(err.into()),
+            _ => Ok(credentials),
+        }
+    }
+
+    /// The discoverable credentials of `rp_id` on every authenticator of the client, along with
+    /// the handle and the names the stores know of the users they were created for, found without
+    /// involving the user and without signing anything.
+    ///
+    /// This lets hosts build an account picker, or the UI of a conditional request, ahead of the
+    /// ceremony in which the picked credential is asserted.
+    pub async fn silently_discover_credentials(
+        &self,
+        rp_id: &str,
+    ) -> Result<Vec<CredentialChoice>, WebauthnError> {
+        Ok(self
+            .discoverable_credentials()
+            .await?
+            .into_iter()
+            .filter(|credential| credential.rp.id == rp_id)
+            .map(|credential| CredentialChoice {
+                id: credential.passkey.credential_id.clone(),
+                user: credential.user,
+            })
+            .collect())
+    }
+
+    /// Offer the discoverable credentials of `rp_id` to the user through the [`CredentialPicker`],
+    /// only keeping those in `allow_credentials` when it is not empty, and return the credential
+    /// they pick.
+    ///
+    /// Fails with [`WebauthnError::NotSupported`] without a picker, and with
+    /// [`WebauthnError::CredentialNotFound`] when the request is dismissed.
+    pub(crate) async fn pick_credential(
+        &self,
+        rp_id: &str,
+        allow_credentials: Option<&[webauthn::PublicKeyCredentialDescriptor]>,
+    ) -> Result<webauthn::PublicKeyCredentialDescriptor, WebauthnError> {
+        let picker = self
+            .credential_picker
+            .as_deref()
+            .ok_or(WebauthnError::NotSupported)?;
+        let allow_credentials = allow_credentials.filter(|allowed| !allowed.is_empty());
+        let credentials: Vec<_> = self
+            .silently_discover_credentials(rp_id)
+            .await?
+            .into_iter()
+            .filter(|credential| {
+                allow_credentials.is_none_or(|allowed| {
+                    allowed
+                        .iter()
+                        .any(|descriptor| descriptor.id == credential.id)
+                })
+            })
+            .collect();
+
+        let id = picker
+            .pick_credential(rp_id, &credentials)
+            .await
+            .filter(|id| credentials.iter().any(|credential| credential.id == *id))
+            .ok_or(WebauthnError::CredentialNotFound)?;
+        Ok(webauthn::PublicKeyCredentialDescriptor {
+            ty: webauthn::PublicKeyCredentialType::PublicKey,
+            id,
+            transports: None,
+        })
+    }
+}
@@ -0,0 +1,82 @@
+//! The result of a registration ceremony, from which the credential is returned to the Relying
+//! Party in whichever rendering it expects.
+
+use coset::{iana, iana::EnumI64, CoseKey};
+use passkey_types::{ctap2, webauthn};
+
+/// A credential created by [`Client::register`](crate::Client::register), along with its parsed
+/// attestation object.
+///
+/// The credential is returned to Relying Parties in its JSON encoding with
+/// [`Registration::into_json`], while those verifying registrations themselves can use its raw
+/// attestation object, or the public key and algorithm already extracted from it.
+#[derive(Debug)]
+pub struct Registration {
+    /// The created credential, as returned by `navigator.credentials.create()`.
+    pub credential: webauthn::CreatedPublicKeyCredential,
+    /// The attestation object of the credential, whose CBOR encoding is
+    /// [`Registration::attestation_object_bytes`].
+    pub attestation_object: ctap2::AttestationObject,
+}
+
+impl Registration {
+    /// The ID of the created credential.
+    pub fn credential_id(&self) -> &[u8] {
+        &self.credential.raw_id
+    }
+
+    /// The CBOR encoding of the attestation object, as sent to the Relying Party.
+    pub fn attestation_object_bytes(&self) -> &[u8] {
+        &self.credential.response.attestation_object
+    }
+
+    /// The public key of the credential in its COSE encoding.
+    pub fn public_key(&self) -> Option<&CoseKey> {
+        self.attestation_object
+            .auth_data
+            .attested_credential_data
+            .as_ref()
+            .map(|data| &data.key)
+    }
+
+    /// The DER `SubjectPublicKeyInfo` of the public key of the credential.
+    pub fn public_key_der(&self) -> Option<&[u8]> {
+        self.credential
+            .response
+            .public_key
+            .as_deref()
+            .map(Vec::as_slice)
+    }
+
+    /// The COSE algorithm of the credential, or `None` if it is not a registered algorithm.
+    pub fn public_key_algorithm(&self) -> Option<iana::Algorithm> {
+        iana::Algorithm::from_i64(self.credential.response.public_key_algorithm)
+    }
+
+    /// The transports through which the authenticator of the credential can be reached, which
+    /// is empty when they are unknown.
+    pub fn transports(&self) -> &[webauthn::AuthenticatorTransport] {
+        self.credential
+            .response
+            .transports
+            .as_deref()
+            .unwrap_or_default()
+    }
+
+    /// The JSON encoding of the credential, as returned by `PublicKeyCredential.toJSON()`.
+    pub fn into_json(self) -> webauthn::RegistrationResponseJSON {
+        self.credential.into()
+    }
+}
+
+impl From<Registration> for webauthn::CreatedPublicKeyCredential {
+    fn from(value: Registration) -> Self {
+        value.credential
+    }
+}
+
+impl From<Registration> for webauthn::RegistrationResponseJSON {
+    fn from(value: Registration) -> Self {
+        value.into_json()
+    }
+}
@@ -0,0 +1,73 @@
+//! Related origin requests, in which a Relying Party lets origins outside of its RP ID, such as
+//! its country-specific domains, use its credentials by listing them in the document it serves at
+//! `https://{RP ID}/.well-known/webauthn`.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-related-origins>
+
+use std::collections::HashMap;
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn::RelatedOrigins, Passkey};
+use url::Url;
+
+use crate::{android, Client, WebauthnError};
+
+/// The maximum number of distinct registrable domain labels, such as `example` in
+/// `example.co.uk`, whose origins are accepted from a [`RelatedOrigins`] document.
+pub(crate) const MAX_RELATED_ORIGIN_LABELS: usize = 5;
+
+/// Use this on a type that provides the [`RelatedOrigins`] document of a Relying Party, for
+/// example by fetching it from `https://{RP ID}/.well-known/webauthn`, see
+/// [`Client::with_related_origins`].
+#[async_trait::async_trait]
+pub trait RelatedOriginsProvider {
+    /// The document of the Relying Party with the given ID, or `None` if it has none or it could
+    /// not be fetched or parsed.
+    async fn related_origins(&self, rp_id: &str) -> Option<RelatedOrigins>;
+}
+
+/// Documents supplied ahead of time, by RP ID.
+#[async_trait::async_trait]
+impl RelatedOriginsProvider for HashMap<String, RelatedOrigins> {
+    async fn related_origins(&self, rp_id: &str) -> Option<RelatedOrigins> {
+        self.get(rp_id).cloned()
+    }
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// Verify the RP ID of a request from `origin` with the [`RpIdValidator`], falling back to the
+    /// [`RelatedOrigins`] of the RP ID when the origin is not one of its own. Requests from Android
+    /// apps are verified against the apps of the RP ID instead.
+    ///
+    /// [`RpIdValidator`]: crate::RpIdValidator
+    pub(crate) async fn assert_rp_id<'a>(
+        &self,
+        origin: &'a Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError> {
+        if android::is_app_origin(origin) {
+            return self.assert_android_app(origin, rp_id).await;
+        }
+        let result = match self.rp_id_validator.as_deref() {
+            Some(validator) => validator.assert_domain(origin, rp_id),
+            None => self.rp_id_verifier.assert_domain(origin, rp_id),
+        };
+        match (result, rp_id, self.related_origins.as_deref()) {
+            (Err(WebauthnError::OriginRpMissmatch), Some(rp_id), Some(provider)) => {
+                let related_origins = provider
+                    .related_origins(rp_id)
+                    .await
+                    .ok_or(WebauthnError::OriginRpMissmatch)?;
+                self.rp_id_verifier
+                    .assert_related_origin(origin, rp_id, &related_origins)
+            }
+            (result, _, _) => result,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Routing of the requests of a client over several authenticators, such as one whose credentials
+//! are bound to the device and one whose credentials are synced, to the authenticator which
+//! satisfies the criteria of a registration or holds the credentials of an assertion.
+
+use passkey_authenticator::{Authenticator, CredentialStore, UserValidationMethod};
+use passkey_types::{ctap2, webauthn, Passkey};
+
+use crate::{resident_key, user_verification, Client, WebauthnError};
+
+/// The authenticator selected for a registration, see [`Client::select_for_registration`].
+pub(crate) struct RegistrationOptions {
+    /// Whether to create a discoverable credential.
+    pub rk: bool,
+    /// Whether to verify the user.
+    pub uv: bool,
+    /// The info of the selected authenticator.
+    pub info: ctap2::get_info::Response,
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// The authenticator selected for the request in progress.
+    pub(crate) fn selected(&self) -> &Authenticator<S, U> {
+        &self.authenticators[self.selected]
+    }
+
+    /// Write access to the authenticator selected for the request in progress.
+    pub(crate) fn selected_mut(&mut self) -> &mut Authenticator<S, U> {
+        &mut self.authenticators[self.selected]
+    }
+
+    /// Select the first authenticator of the requested attachment which satisfies the
+    /// discoverability and user verification required by the `criteria` of a registration,
+    /// trying those matching its `hints` first.
+    ///
+    /// Fails with the error of the first authenticator when none of them satisfies the criteria.
+    pub(crate) async fn select_for_registration(
+        &mut self,
+        criteria: Option<&webauthn::AuthenticatorSelectionCriteria>,
+        hints: Option<&[webauthn::PublicKeyCredentialHints]>,
+    ) -> Result<RegistrationOptions, WebauthnError> {
+        let mut first_error = None;
+        for index in self.hinted_order(hints).await {
+            match satisfies(&self.authenticators[index], criteria).await {
+                Ok(options) => {
+                    self.selected = index;
+                    return Ok(options);
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        Err(first_error.unwrap_or(WebauthnError::UnsatisfiedCriteria))
+    }
+
+    /// Select the first authenticator which stores one of the `allow_credentials` of an
+    /// assertion under its RP ID or FIDO AppID, or a discoverable credential of the RP ID when
+    /// any credential is allowed, trying those matching its `hints` first. The first
+    /// authenticator in that order is selected when none does.
+    pub(crate) async fn select_for_assertion(
+        &mut self,
+        rp_id: &str,
+        app_id: Option<&str>,
+        allow_credentials: Option<&[webauthn::PublicKeyCredentialDescriptor]>,
+        hints: Option<&[webauthn::PublicKeyCredentialHints]>,
+    ) {
+        let allow_credentials = allow_credentials.filter(|list| !list.is_empty());
+        let order = self.hinted_order(hints).await;
+        self.selected = order[0];
+        for index in order {
+            let store = self.authenticators[index].store();
+            let found = match allow_credentials {
+                Some(list) => {
+                    let mut found = false;
+                    for rp_id in std::iter::once(rp_id).chain(app_id) {
+                        found |= store
+                            .find_credentials(Some(list), rp_id)
+                            .await
+                            .is_ok_and(|credentials| !credentials.is_empty());
+                    }
+                    found
+                }
+                None => store
+                    .discoverable_credentials()
+                    .await
+                    .is_ok_and(|credentials| {
+                        credentials
+                            .iter()
+                            .any(|credential| credential.rp.id == rp_id)
+                    }),
+            };
+            if found {
+                self.selected = index;
+                return;
+            }
+        }
+    }
+}
+
+/// Whether the `authenticator` is of the attachment requested by the `criteria` of a registration
+/// and satisfies the discoverability and user verification they require.
+async fn satisfies<S, U>(
+    authenticator: &Authenticator<S, U>,
+    criteria: Option<&webauthn::AuthenticatorSelectionCriteria>,
+) -> Result<RegistrationOptions, WebauthnError>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+{
+    if let Some(attachment) = criteria.and_then(|criteria| criteria.authenticator_attachment) {
+        if attachment != authenticator.attachment_type() {
+            return Err(WebauthnError::UnsatisfiedCriteria);
+        }
+    }
+    let discoverability = authenticator.store().discoverability().await;
+    let rk = resident_key(criteria, discoverability)?;
+    let info = authenticator.get_info().await;
+    let uv = user_verification(
+        criteria
+            .map(|criteria| criteria.user_verification)
+            .unwrap_or_default(),
+        info.options.as_ref(),
+    )?;
+    Ok(RegistrationOptions { rk, uv, info })
+}
@@ -1,6 +1,6 @@
 use super::*;
 use coset::iana;
-use passkey_authenticator::{MemoryStore, MockUserValidationMethod};
+use passkey_authenticator::{Attestation, MemoryStore, MockUserValidationMethod, StoredConfig};
 use passkey_types::{ctap2, rand::random_vec, Bytes};
 use url::{ParseError, Url};
 
@@ -50,12 +50,14 @@ fn good_credential_request_options(
     }
 }
 
+/// A user validation method for `times` ceremonies, each of which reads the info of the
+/// authenticator and verifies the user once.
 fn uv_mock_with_creation(times: usize) -> MockUserValidationMethod {
     let mut user_mock = MockUserValidationMethod::new();
     user_mock
         .expect_is_verification_enabled()
         .returning(|| Some(true))
-        .times(times + 1);
+        .times(times * 2);
     user_mock
         .expect_check_user_verification()
         .returning(|| Box::pin(async { true }))
@@ -63,7 +65,11 @@ fn uv_mock_with_creation(times: usize) -> MockUserValidationMethod {
     user_mock
         .expect_is_presence_enabled()
         .returning(|| true)
-        .times(1);
+        .times(times);
+    user_mock
+        .expect_fingerprint_sensor()
+        .returning(|| None)
+        .times(times);
     user_mock
 }
 
@@ -83,12 +89,14 @@ async fn create_and_authenticate() {
     let cred = client
         .register(&origin, options, None)
         .await
-        .expect("failed to register with options");
+        .expect("failed to register with options")
+        .credential;
 
     let credential_id = cred.raw_id;
 
     let auth_options = webauthn::CredentialRequestOptions {
         public_key: good_credential_request_options(credential_id),
+        mediation: None,
     };
     client
         .authenticate(&origin, auth_options, None)
@@ -116,11 +124,11 @@ async fn create_and_authenticate_with_origin_subdomain() {
     let cred = client
         .register(&origin, options, None)
         .await
-        .expect("failed to register with options");
+        .expect("failed to register with options")
+        .credential;
 
-    let att_obj: ctap2::make_credential::Response =
-        ciborium::de::from_reader(cred.response.attestation_object.as_slice())
-            .expect("could not deserialize response");
+    let att_obj = ctap2::AttestationObject::from_cbor(&cred.response.attestation_object)
+        .expect("could not deserialize response");
     assert_eq!(
         att_obj.auth_data.rp_id_hash(),
         &sha256(b"future.1password.com")
@@ -128,6 +136,7 @@ async fn create_and_authenticate_with_origin_subdomain() {
 
     let auth_options = webauthn::CredentialRequestOptions {
         public_key: good_credential_request_options(cred.raw_id),
+        mediation: None,
     };
     let res = client
         .authenticate(&origin, auth_options, None)
@@ -160,11 +169,11 @@ async fn create_and_authenticate_without_rp_id() {
     let cred = client
         .register(&origin, options, None)
         .await
-        .expect("failed to register with options");
+        .expect("failed to register with options")
+        .credential;
 
-    let att_obj: ctap2::make_credential::Response =
-        ciborium::de::from_reader(cred.response.attestation_object.as_slice())
-            .expect("could not deserialize response");
+    let att_obj = ctap2::AttestationObject::from_cbor(&cred.response.attestation_object)
+        .expect("could not deserialize response");
     assert_eq!(
         att_obj.auth_data.rp_id_hash(),
         &sha256(b"www.future.1password.com")
@@ -175,6 +184,7 @@ async fn create_and_authenticate_without_rp_id() {
             rp_id: None,
             ..good_credential_request_options(cred.raw_id)
         },
+        mediation: None,
     };
     let res = client
         .authenticate(&origin, auth_options, None)
@@ -185,50 +195,2292 @@ async fn create_and_authenticate_without_rp_id() {
     assert_eq!(att_obj.rp_id_hash(), &sha256(b"www.future.1password.com"));
 }
 
-#[test]
-fn validate_rp_id() -> Result<(), ParseError> {
-    let client = RpIdVerifier::new(public_suffix::DEFAULT_PROVIDER);
+/// Register a credential with the given attestation conveyance preference and return its decoded
+/// attestation object.
+async fn register_with_attestation(
+    mut client: Client<MemoryStore, MockUserValidationMethod, public_suffix::PublicSuffixList>,
+    attestation: webauthn::AttestationConveyancePreference,
+) -> ctap2::AttestationObject {
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            attestation,
+            ..good_credential_creation_options()
+        },
+    };
+    client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options")
+        .attestation_object
+}
 
-    let example = "https://example.com".parse()?;
-    let com_tld = client.assert_domain(&example, Some("com"));
-    assert_eq!(com_tld, Err(WebauthnError::InvalidRpId));
+#[tokio::test]
+async fn registration_renders_the_credential() {
+    let auth = Authenticator::new(
+        ctap2::Aaguid::new_empty(),
+        MemoryStore::new(),
+        uv_mock_with_creation(1),
+    );
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+    let registration = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options");
 
-    let example_dots = "https://example...com".parse()?;
-    let bunch_of_dots = client.assert_domain(&example_dots, Some("...com"));
-    assert_eq!(bunch_of_dots, Err(WebauthnError::InvalidRpId));
+    assert_eq!(
+        registration.public_key_algorithm(),
+        Some(iana::Algorithm::ES256)
+    );
+    assert!(registration.public_key().is_some());
+    assert!(registration.public_key_der().is_some());
+    assert!(!registration.transports().is_empty());
+    assert_eq!(
+        registration.attestation_object.to_cbor(),
+        registration.attestation_object_bytes()
+    );
 
-    let future = "https://www.future.1password.com".parse()?;
-    let sub_domain_ignored = client.assert_domain(&future, Some("future.1password.com"));
-    assert_eq!(sub_domain_ignored, Ok("future.1password.com"));
+    let credential_id = encoding::base64url(registration.credential_id());
+    let json = serde_json::to_value(registration.into_json()).unwrap();
+    assert_eq!(json["id"], credential_id);
+    assert_eq!(json["rawId"], credential_id);
+    assert_eq!(json["type"], "public-key");
+    assert_eq!(json["response"]["publicKeyAlgorithm"], -7);
+    assert!(json["response"]["attestationObject"].is_string());
+}
 
-    let use_effective_domain = client.assert_domain(&future, None);
-    assert_eq!(use_effective_domain, Ok("www.future.1password.com"));
+#[tokio::test]
+async fn transports_are_reported_in_lexicographical_order() {
+    use webauthn::AuthenticatorTransport::*;
 
-    let not_protected = "http://example.com".parse()?;
-    let not_https = client.assert_domain(&not_protected, Some("example.com"));
-    assert_eq!(not_https, Err(WebauthnError::UnprotectedOrigin));
+    let auth = Authenticator::new(
+        ctap2::Aaguid::new_empty(),
+        MemoryStore::new(),
+        uv_mock_with_creation(1),
+    )
+    .transports(vec![Usb, Nfc, Usb]);
+    assert_eq!(
+        auth.attachment_type(),
+        webauthn::AuthenticatorAttachment::CrossPlatform
+    );
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+    let registration = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options");
+    assert_eq!(registration.transports(), [Nfc, Usb]);
+    assert_eq!(
+        registration.credential.authenticator_attachment,
+        Some(webauthn::AuthenticatorAttachment::CrossPlatform)
+    );
 
-    let localhost = "http://localhost:8080".parse()?;
-    let should_still_match = client.assert_domain(&localhost, Some("example.com"));
-    assert_eq!(should_still_match, Err(WebauthnError::OriginRpMissmatch));
+    // The attachment can be set regardless of the transports.
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock())
+        .transports(vec![Hybrid])
+        .with_attachment(webauthn::AuthenticatorAttachment::Platform);
+    assert_eq!(
+        auth.attachment_type(),
+        webauthn::AuthenticatorAttachment::Platform
+    );
+}
 
-    let localhost_not_allowed = client.assert_domain(&localhost, Some("localhost"));
+#[tokio::test]
+async fn attestation_conveyance_preference() {
+    let aaguid = ctap2::Aaguid::from([7; 16]);
+    let authenticator = || {
+        Authenticator::new(aaguid, MemoryStore::new(), uv_mock_with_creation(1))
+            .with_attestation(Attestation::SelfAttestation)
+    };
+
+    let att_obj = register_with_attestation(
+        Client::new(authenticator()),
+        webauthn::AttestationConveyancePreference::None,
+    )
+    .await;
+    assert_eq!(att_obj.fmt, "none");
+    assert_eq!(att_obj.att_stmt, Value::Map(Vec::new()));
     assert_eq!(
-        localhost_not_allowed,
-        Err(WebauthnError::InsecureLocalhostNotAllowed)
+        att_obj.auth_data.attested_credential_data.unwrap().aaguid,
+        ctap2::Aaguid::new_empty()
     );
-    let localhost_not_allowed = client.assert_domain(&localhost, None);
+
+    for preference in [
+        webauthn::AttestationConveyancePreference::Indirect,
+        webauthn::AttestationConveyancePreference::Direct,
+        webauthn::AttestationConveyancePreference::Enterprise,
+    ] {
+        let att_obj = register_with_attestation(Client::new(authenticator()), preference).await;
+        assert_eq!(att_obj.fmt, "packed");
+        assert_eq!(
+            att_obj.auth_data.attested_credential_data.unwrap().aaguid,
+            aaguid
+        );
+    }
+}
+
+#[tokio::test]
+async fn keep_aaguid_without_attestation() {
+    let aaguid = ctap2::Aaguid::from([7; 16]);
+    let authenticator = Authenticator::new(aaguid, MemoryStore::new(), uv_mock_with_creation(1))
+        .with_attestation(Attestation::SelfAttestation);
+    let client = Client::new(authenticator).zero_aaguid_without_attestation(false);
+
+    let att_obj =
+        register_with_attestation(client, webauthn::AttestationConveyancePreference::None).await;
+    assert_eq!(att_obj.fmt, "none");
+    assert_eq!(att_obj.att_stmt, Value::Map(Vec::new()));
     assert_eq!(
-        localhost_not_allowed,
-        Err(WebauthnError::InsecureLocalhostNotAllowed)
+        att_obj.auth_data.attested_credential_data.unwrap().aaguid,
+        aaguid
     );
+}
 
-    let client = client.allows_insecure_localhost(true);
-    let skips_http_and_tld_check = client.assert_domain(&localhost, Some("localhost"));
-    assert_eq!(skips_http_and_tld_check, Ok("localhost"));
-    let skips_http_and_tld_check = client.assert_domain(&localhost, None);
-    assert_eq!(skips_http_and_tld_check, Ok("localhost"));
+#[tokio::test]
+async fn enterprise_attestation_is_forwarded() {
+    let authenticator = || {
+        Authenticator::new(
+            ctap2::Aaguid::new_empty(),
+            MemoryStore::new(),
+            uv_mock_with_creation(1),
+        )
+        .with_enterprise_attestation(
+            Attestation::SelfAttestation,
+            vec!["future.1password.com".into()],
+        )
+        .with_config_store(Some(StoredConfig {
+            enterprise_attestation: true,
+            ..Default::default()
+        }))
+    };
+
+    let att_obj = register_with_attestation(
+        Client::new(authenticator()),
+        webauthn::AttestationConveyancePreference::Enterprise,
+    )
+    .await;
+    assert_eq!(att_obj.fmt, "packed");
+
+    let att_obj = register_with_attestation(
+        Client::new(authenticator()),
+        webauthn::AttestationConveyancePreference::Direct,
+    )
+    .await;
+    assert_eq!(att_obj.fmt, "none");
+}
+
+/// A user validation mock verifying the user as often as needed, for flows which fetch the
+/// authenticator info or a PIN/UV auth token along the way.
+fn uv_mock() -> MockUserValidationMethod {
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| Some(true));
+    user_mock
+        .expect_check_user_verification()
+        .returning(|| Box::pin(async { true }));
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { true }));
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    user_mock
+}
+
+/// Authenticator selection criteria with the given `resident_key` requirement.
+fn resident_key_selection(
+    resident_key: webauthn::ResidentKeyRequirement,
+) -> Option<webauthn::AuthenticatorSelectionCriteria> {
+    Some(webauthn::AuthenticatorSelectionCriteria {
+        authenticator_attachment: None,
+        resident_key: Some(resident_key),
+        require_resident_key: resident_key == webauthn::ResidentKeyRequirement::Required,
+        user_verification: Default::default(),
+    })
+}
+
+#[tokio::test]
+async fn cred_props_reports_discoverability() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+
+    for (requirement, discoverable) in [
+        (webauthn::ResidentKeyRequirement::Discouraged, false),
+        (webauthn::ResidentKeyRequirement::Preferred, true),
+        (webauthn::ResidentKeyRequirement::Required, true),
+    ] {
+        let options = webauthn::CredentialCreationOptions {
+            public_key: webauthn::PublicKeyCredentialCreationOptions {
+                authenticator_selection: resident_key_selection(requirement),
+                extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                    cred_props: Some(true),
+                    ..Default::default()
+                }),
+                ..good_credential_creation_options()
+            },
+        };
+        let cred = client
+            .register(&origin, options, None)
+            .await
+            .expect("failed to register with options")
+            .credential;
+        let cred_props = cred
+            .client_extension_results
+            .cred_props
+            .expect("missing credProps");
+        assert_eq!(cred_props.discoverable, Some(discoverable));
+        let stored = client
+            .authenticator()
+            .store()
+            .get(&*cred.raw_id)
+            .expect("credential not saved");
+        assert_eq!(stored.passkey.user_handle.is_some(), discoverable);
+    }
+}
+
+#[tokio::test]
+async fn authenticator_selection_is_enforced() {
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options =
+        |authenticator_attachment, user_verification| webauthn::CredentialCreationOptions {
+            public_key: webauthn::PublicKeyCredentialCreationOptions {
+                authenticator_selection: Some(webauthn::AuthenticatorSelectionCriteria {
+                    authenticator_attachment,
+                    resident_key: None,
+                    require_resident_key: false,
+                    user_verification,
+                }),
+                ..good_credential_creation_options()
+            },
+        };
 
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let res = client
+        .register(
+            &origin,
+            options(
+                Some(webauthn::AuthenticatorAttachment::CrossPlatform),
+                webauthn::UserVerificationRequirement::Preferred,
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnsatisfiedCriteria);
+
+    // Without built-in user verification, only the user's presence can be checked.
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| None);
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { true }));
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock);
+    let mut client = Client::new(auth);
+    let res = client
+        .register(
+            &origin,
+            options(None, webauthn::UserVerificationRequirement::Required),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnsatisfiedCriteria);
+
+    for user_verification in [
+        webauthn::UserVerificationRequirement::Preferred,
+        webauthn::UserVerificationRequirement::Discouraged,
+    ] {
+        let cred = client
+            .register(
+                &origin,
+                options(
+                    Some(webauthn::AuthenticatorAttachment::Platform),
+                    user_verification,
+                ),
+                None,
+            )
+            .await
+            .expect("failed to register without user verification")
+            .credential;
+        let att_obj = ctap2::AttestationObject::from_cbor(&cred.response.attestation_object)
+            .expect("could not deserialize response");
+        assert!(att_obj.auth_data.flags.contains(ctap2::Flags::UP));
+        assert!(!att_obj.auth_data.flags.contains(ctap2::Flags::UV));
+    }
+}
+
+#[tokio::test]
+async fn user_verification_policy_overrides_the_relying_party() {
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let policy = |requirement| {
+        move |_, rp_id: &str, requested| {
+            if rp_id == "future.1password.com" {
+                requirement
+            } else {
+                requested
+            }
+        }
+    };
+
+    // Policies may relax the requirement of the Relying Party...
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth)
+        .with_user_verification_policy(policy(webauthn::UserVerificationRequirement::Discouraged));
+    let cred = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await
+        .expect("failed to register")
+        .credential;
+    let auth_data = ctap2::AuthenticatorData::from_slice(&cred.response.authenticator_data)
+        .expect("could not parse the authenticator data");
+    assert!(!auth_data.flags.contains(ctap2::Flags::UV));
+    let response = client
+        .authenticate(
+            &origin,
+            webauthn::CredentialRequestOptions {
+                public_key: webauthn::PublicKeyCredentialRequestOptions {
+                    user_verification: webauthn::UserVerificationRequirement::Required,
+                    ..good_credential_request_options(cred.raw_id)
+                },
+                mediation: None,
+            },
+            None,
+        )
+        .await
+        .expect("failed to authenticate");
+    let auth_data = ctap2::AuthenticatorData::from_slice(&response.response.authenticator_data)
+        .expect("could not parse the authenticator data");
+    assert!(!auth_data.flags.contains(ctap2::Flags::UV));
+
+    // ... or require user verification which the authenticator cannot do.
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| None);
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { true }));
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock);
+    let mut client = Client::new(auth);
+    let cred = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await
+        .expect("failed to register without user verification")
+        .credential;
+    let mut client = client
+        .with_user_verification_policy(policy(webauthn::UserVerificationRequirement::Required));
+    let res = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnsatisfiedCriteria);
+    let res = client
+        .authenticate(
+            &origin,
+            webauthn::CredentialRequestOptions {
+                public_key: good_credential_request_options(cred.raw_id),
+                mediation: None,
+            },
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnsatisfiedCriteria);
+}
+
+#[tokio::test]
+async fn ceremony_events_are_observed() {
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let observer = {
+        let events = events.clone();
+        move |ty, event| events.lock().unwrap().push((ty, event))
+    };
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth).with_ceremony_observer(observer);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                cred_props: Some(true),
+                ..Default::default()
+            }),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register")
+        .credential;
+    let create = webauthn::ClientDataType::Create;
+    assert_eq!(
+        std::mem::take(&mut *events.lock().unwrap()),
+        vec![
+            (
+                create,
+                CeremonyEvent::Started {
+                    origin: "https://future.1password.com".into()
+                }
+            ),
+            (
+                create,
+                CeremonyEvent::OriginValidated {
+                    rp_id: "future.1password.com".into()
+                }
+            ),
+            (
+                create,
+                CeremonyEvent::AuthenticatorSelected {
+                    index: 0,
+                    aaguid: ctap2::Aaguid::new_empty()
+                }
+            ),
+            (
+                create,
+                CeremonyEvent::UserChecked {
+                    present: true,
+                    verified: true
+                }
+            ),
+            (
+                create,
+                CeremonyEvent::ExtensionsProcessed {
+                    extensions: vec!["credProps".into()]
+                }
+            ),
+            (
+                create,
+                CeremonyEvent::Completed {
+                    credential_id: cred.raw_id.clone()
+                }
+            ),
+        ]
+    );
+
+    // Failed ceremonies report their error, after the events of the steps they completed.
+    let other_origin = Url::parse("https://example.com").unwrap();
+    let res = client
+        .authenticate(
+            &other_origin,
+            webauthn::CredentialRequestOptions {
+                public_key: good_credential_request_options(cred.raw_id),
+                mediation: None,
+            },
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::OriginRpMissmatch);
+    let get = webauthn::ClientDataType::Get;
+    assert_eq!(
+        std::mem::take(&mut *events.lock().unwrap()),
+        vec![
+            (
+                get,
+                CeremonyEvent::Started {
+                    origin: "https://example.com".into()
+                }
+            ),
+            (
+                get,
+                CeremonyEvent::Failed {
+                    error: WebauthnError::OriginRpMissmatch
+                }
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn requests_are_routed_between_authenticators() {
+    // A security key without built-in user verification, next to a synced passkey provider.
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| None);
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { true }));
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    let security_key =
+        Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock)
+            .with_backup_flags(false, false);
+    let provider = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(security_key).with_authenticator(provider);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = |user_verification, exclude_credentials| webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            authenticator_selection: Some(webauthn::AuthenticatorSelectionCriteria {
+                user_verification,
+                ..resident_key_selection(webauthn::ResidentKeyRequirement::Required).unwrap()
+            }),
+            exclude_credentials,
+            ..good_credential_creation_options()
+        },
+    };
+
+    // Only the provider can verify the user.
+    let synced = client
+        .register(
+            &origin,
+            options(webauthn::UserVerificationRequirement::Required, None),
+            None,
+        )
+        .await
+        .expect("failed to register with user verification")
+        .credential;
+    assert!(client.authenticators()[1]
+        .store()
+        .get(&*synced.raw_id)
+        .is_some());
+    let device_bound = client
+        .register(
+            &origin,
+            options(webauthn::UserVerificationRequirement::Discouraged, None),
+            None,
+        )
+        .await
+        .expect("failed to register without user verification")
+        .credential;
+    assert!(client.authenticators()[0]
+        .store()
+        .get(&*device_bound.raw_id)
+        .is_some());
+    assert_eq!(client.discoverable_credentials().await.unwrap().len(), 2);
+
+    // Credentials are excluded whichever authenticator holds them.
+    let excluded = webauthn::PublicKeyCredentialDescriptor {
+        ty: webauthn::PublicKeyCredentialType::PublicKey,
+        id: synced.raw_id.clone(),
+        transports: None,
+    };
+    let res = client
+        .register(
+            &origin,
+            options(
+                webauthn::UserVerificationRequirement::Discouraged,
+                Some(vec![excluded]),
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialExcluded);
+
+    // Assertions are made by the authenticator holding the allowed credential.
+    let auth_options = webauthn::CredentialRequestOptions {
+        public_key: good_credential_request_options(synced.raw_id.clone()),
+        mediation: None,
+    };
+    let assertion = client
+        .authenticate(&origin, auth_options, None)
+        .await
+        .expect("failed to authenticate with the synced credential");
+    let auth_data =
+        ctap2::AuthenticatorData::from_slice(&assertion.response.authenticator_data).unwrap();
+    assert!(auth_data
+        .flags
+        .contains(ctap2::Flags::UV | ctap2::Flags::BS));
+}
+
+#[tokio::test]
+async fn hints_prioritize_authenticators() {
+    let platform = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let security_key =
+        Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock())
+            .transports(vec![webauthn::AuthenticatorTransport::Usb]);
+    let notified = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let listener = {
+        let notified = notified.clone();
+        move |ty, hints: &[webauthn::PublicKeyCredentialHints]| {
+            notified.lock().unwrap().push((ty, hints.to_vec()));
+        }
+    };
+    let mut client = Client::new(platform)
+        .with_authenticator(security_key)
+        .with_hints_listener(listener);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = |hints| webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            hints,
+            ..good_credential_creation_options()
+        },
+    };
+
+    let cred = client
+        .register(
+            &origin,
+            options(Some(vec![
+                webauthn::PublicKeyCredentialHints::SecurityKey,
+                webauthn::PublicKeyCredentialHints::ClientDevice,
+            ])),
+            None,
+        )
+        .await
+        .expect("failed to register with a security key")
+        .credential;
+    assert_eq!(
+        cred.authenticator_attachment,
+        Some(webauthn::AuthenticatorAttachment::CrossPlatform)
+    );
+    let cred = client
+        .register(&origin, options(None), None)
+        .await
+        .expect("failed to register without hints")
+        .credential;
+    assert_eq!(
+        cred.authenticator_attachment,
+        Some(webauthn::AuthenticatorAttachment::Platform)
+    );
+
+    // Only requests with hints are reported.
+    assert_eq!(
+        *notified.lock().unwrap(),
+        [(
+            webauthn::ClientDataType::Create,
+            vec![
+                webauthn::PublicKeyCredentialHints::SecurityKey,
+                webauthn::PublicKeyCredentialHints::ClientDevice,
+            ]
+        )]
+    );
+}
+
+#[test]
+fn resident_key_follows_discoverability_support() {
+    use webauthn::ResidentKeyRequirement::*;
+
+    assert!(!resident_key(None, DiscoverabilitySupport::Full).unwrap());
+    assert!(resident_key(
+        Some(&webauthn::AuthenticatorSelectionCriteria {
+            authenticator_attachment: None,
+            resident_key: None,
+            require_resident_key: true,
+            user_verification: Default::default(),
+        }),
+        DiscoverabilitySupport::Full
+    )
+    .unwrap());
+
+    for (requirement, full, only_non_discoverable) in [
+        (Discouraged, Ok(false), Ok(false)),
+        (Preferred, Ok(true), Ok(false)),
+        (Required, Ok(true), Err(WebauthnError::UnsatisfiedCriteria)),
+    ] {
+        let criteria = resident_key_selection(requirement);
+        assert_eq!(
+            resident_key(criteria.as_ref(), DiscoverabilitySupport::Full),
+            full
+        );
+        assert_eq!(
+            resident_key(
+                criteria.as_ref(),
+                DiscoverabilitySupport::OnlyNonDiscoverable
+            ),
+            only_non_discoverable
+        );
+        assert_eq!(
+            resident_key(
+                criteria.as_ref(),
+                DiscoverabilitySupport::ForcedDiscoverable
+            ),
+            Ok(true)
+        );
+    }
+}
+
+#[test]
+fn pub_key_cred_params_are_normalized() {
+    let param = |ty, alg| webauthn::PublicKeyCredentialParameters { ty, alg };
+    let public_key = webauthn::PublicKeyCredentialType::PublicKey;
+    let algs = |params: Vec<webauthn::PublicKeyCredentialParameters>| {
+        params
+            .into_iter()
+            .map(|param| param.alg)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        algs(pub_key_cred_params(Vec::new()).unwrap()),
+        [iana::Algorithm::ES256, iana::Algorithm::RS256]
+    );
+    assert_eq!(
+        algs(
+            pub_key_cred_params(vec![
+                param(public_key, iana::Algorithm::EdDSA),
+                param(
+                    webauthn::PublicKeyCredentialType::Unknown,
+                    iana::Algorithm::ES256
+                ),
+                param(public_key, iana::Algorithm::Reserved),
+                param(public_key, iana::Algorithm::ES256),
+                param(public_key, iana::Algorithm::EdDSA),
+            ])
+            .unwrap()
+        ),
+        [iana::Algorithm::EdDSA, iana::Algorithm::ES256]
+    );
+    assert_eq!(
+        pub_key_cred_params(vec![param(
+            webauthn::PublicKeyCredentialType::Unknown,
+            iana::Algorithm::ES256
+        )]),
+        Err(WebauthnError::NotSupported)
+    );
+}
+
+#[tokio::test]
+async fn register_with_the_default_algorithms() {
+    let auth = Authenticator::new(
+        ctap2::Aaguid::new_empty(),
+        MemoryStore::new(),
+        uv_mock_with_creation(1),
+    );
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            pub_key_cred_params: Vec::new(),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with the default algorithms")
+        .credential;
+    assert_eq!(
+        cred.response.public_key_algorithm,
+        iana::Algorithm::ES256.to_i64()
+    );
+}
+
+fn large_blob_extension(
+    large_blob: webauthn::AuthenticationExtensionsLargeBlobInputs,
+) -> Option<webauthn::AuthenticationExtensionsClientInputs> {
+    Some(webauthn::AuthenticationExtensionsClientInputs {
+        large_blob: Some(large_blob),
+        ..Default::default()
+    })
+}
+
+/// Register a credential which supports large blobs, returning its ID.
+async fn register_with_large_blob(
+    client: &mut Client<MemoryStore, MockUserValidationMethod, public_suffix::PublicSuffixList>,
+) -> Bytes {
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            extensions: large_blob_extension(webauthn::AuthenticationExtensionsLargeBlobInputs {
+                support: Some(LargeBlobSupport::Preferred),
+                ..Default::default()
+            }),
+            authenticator_selection: resident_key_selection(
+                webauthn::ResidentKeyRequirement::Required,
+            ),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options")
+        .credential;
+    assert_eq!(
+        cred.client_extension_results.large_blob,
+        Some(AuthenticationExtensionsLargeBlobOutputs {
+            supported: Some(true),
+            ..Default::default()
+        })
+    );
+    cred.raw_id
+}
+
+async fn authenticate_with_large_blob(
+    client: &mut Client<MemoryStore, MockUserValidationMethod, public_suffix::PublicSuffixList>,
+    credential_id: Bytes,
+    large_blob: webauthn::AuthenticationExtensionsLargeBlobInputs,
+) -> Result<Option<AuthenticationExtensionsLargeBlobOutputs>, WebauthnError> {
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            extensions: large_blob_extension(large_blob),
+            ..good_credential_request_options(credential_id)
+        },
+        mediation: None,
+    };
+    client
+        .authenticate(&origin, options, None)
+        .await
+        .map(|cred| cred.client_extension_results.large_blob)
+}
+
+#[tokio::test]
+async fn large_blob_is_written_and_read() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let credential_id = register_with_large_blob(&mut client).await;
+    let other_credential_id = register_with_large_blob(&mut client).await;
+
+    let read = webauthn::AuthenticationExtensionsLargeBlobInputs {
+        read: Some(true),
+        ..Default::default()
+    };
+    let outputs = authenticate_with_large_blob(&mut client, credential_id.clone(), read.clone())
+        .await
+        .expect("failed to read an empty large blob array");
+    assert_eq!(outputs, Some(Default::default()));
+
+    for (id, blob) in [
+        (&credential_id, vec![1; 2000]),
+        (&other_credential_id, vec![2; 10]),
+        (&credential_id, vec![3; 5]),
+    ] {
+        let write = webauthn::AuthenticationExtensionsLargeBlobInputs {
+            write: Some(blob.into()),
+            ..Default::default()
+        };
+        let outputs = authenticate_with_large_blob(&mut client, id.clone(), write)
+            .await
+            .expect("failed to write a large blob");
+        assert_eq!(
+            outputs,
+            Some(AuthenticationExtensionsLargeBlobOutputs {
+                written: Some(true),
+                ..Default::default()
+            })
+        );
+    }
+
+    for (id, blob) in [
+        (credential_id, vec![3; 5]),
+        (other_credential_id, vec![2; 10]),
+    ] {
+        let outputs = authenticate_with_large_blob(&mut client, id, read.clone())
+            .await
+            .expect("failed to read a large blob");
+        assert_eq!(
+            outputs,
+            Some(AuthenticationExtensionsLargeBlobOutputs {
+                blob: Some(blob.into()),
+                ..Default::default()
+            })
+        );
+    }
+}
+
+#[tokio::test]
+async fn large_blob_inputs_are_validated() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            extensions: large_blob_extension(webauthn::AuthenticationExtensionsLargeBlobInputs {
+                support: Some(LargeBlobSupport::Required),
+                read: Some(true),
+                ..Default::default()
+            }),
+            ..good_credential_creation_options()
+        },
+    };
+    let res = client.register(&origin, options, None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::NotSupported);
+
+    let credential_id = register_with_large_blob(&mut client).await;
+    let res = authenticate_with_large_blob(
+        &mut client,
+        credential_id.clone(),
+        webauthn::AuthenticationExtensionsLargeBlobInputs {
+            read: Some(true),
+            write: Some(vec![1].into()),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(res, Err(WebauthnError::NotSupported));
+
+    let res = authenticate_with_large_blob(
+        &mut client,
+        credential_id,
+        webauthn::AuthenticationExtensionsLargeBlobInputs {
+            support: Some(LargeBlobSupport::Preferred),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(res, Err(WebauthnError::NotSupported));
+}
+
+#[tokio::test]
+async fn device_pub_key_is_verified() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let extensions = Some(webauthn::AuthenticationExtensionsClientInputs {
+        device_pub_key: Some(Default::default()),
+        ..Default::default()
+    });
+
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            extensions: extensions.clone(),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options")
+        .credential;
+    let outputs = cred
+        .client_extension_results
+        .device_pub_key
+        .expect("missing devicePubKey outputs");
+    let client_data_hash = sha256(&cred.response.client_data_json);
+    let registered = device_pub_key::verify_device_pub_key(
+        &outputs,
+        &cred.response.authenticator_data,
+        &client_data_hash,
+    )
+    .expect("invalid devicePubKey outputs on registration");
+
+    let options = webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            extensions,
+            ..good_credential_request_options(cred.raw_id)
+        },
+        mediation: None,
+    };
+    let cred = client
+        .authenticate(&origin, options, None)
+        .await
+        .expect("failed to authenticate");
+    let mut outputs = cred
+        .client_extension_results
+        .device_pub_key
+        .expect("missing devicePubKey outputs");
+    let client_data_hash = sha256(&cred.response.client_data_json);
+    let asserted = device_pub_key::verify_device_pub_key(
+        &outputs,
+        &cred.response.authenticator_data,
+        &client_data_hash,
+    )
+    .expect("invalid devicePubKey outputs on authentication");
+    assert!(asserted.is_same_device(&registered));
+
+    outputs.signature = cred.response.signature;
+    assert_eq!(
+        device_pub_key::verify_device_pub_key(
+            &outputs,
+            &cred.response.authenticator_data,
+            &client_data_hash,
+        ),
+        Err(attestation::AttestationError::InvalidSignature)
+    );
+}
+
+/// A vendor extension echoing its input in the unsigned extension outputs.
+struct EchoExtension;
+
+#[async_trait::async_trait]
+impl passkey_authenticator::ExtensionHandler for EchoExtension {
+    async fn make_credential(
+        &self,
+        input: &ciborium::value::Value,
+        _: &passkey_authenticator::ExtensionContext<'_>,
+    ) -> Result<passkey_authenticator::ExtensionOutput, ctap2::StatusCode> {
+        Ok(passkey_authenticator::ExtensionOutput {
+            signed: None,
+            unsigned: Some(input.clone()),
+        })
+    }
+
+    async fn get_assertion(
+        &self,
+        input: &ciborium::value::Value,
+        context: &passkey_authenticator::ExtensionContext<'_>,
+    ) -> Result<passkey_authenticator::ExtensionOutput, ctap2::StatusCode> {
+        self.make_credential(input, context).await
+    }
+}
+
+#[tokio::test]
+async fn unknown_extensions_are_passed_through() {
+    use ciborium::value::Value;
+
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock())
+        .with_extension("example.echo", EchoExtension);
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let input = Value::Map(vec![(
+        Value::Text("raw".into()),
+        Value::Bytes(vec![1, 2, 3]),
+    )]);
+    let extensions = || {
+        Some(webauthn::AuthenticationExtensionsClientInputs {
+            unknown_keys: [
+                ("example.echo".to_owned(), input.clone()),
+                ("example.unhandled".to_owned(), Value::Bool(true)),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        })
+    };
+
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            extensions: extensions(),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with an unknown extension")
+        .credential;
+    let outputs = &cred.client_extension_results;
+    assert_eq!(outputs.unknown_keys.get("example.echo"), Some(&input));
+    // Extensions which no authenticator handles have no outputs.
+    assert_eq!(outputs.unknown_keys.len(), 1);
+
+    let options = webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            extensions: extensions(),
+            ..good_credential_request_options(cred.raw_id)
+        },
+        mediation: None,
+    };
+    let response = client
+        .authenticate(&origin, options, None)
+        .await
+        .expect("failed to authenticate with an unknown extension");
+    let outputs = response.client_extension_results;
+    assert_eq!(outputs.unknown_keys.get("example.echo"), Some(&input));
+
+    let json = serde_json::to_value(&outputs).unwrap();
+    assert!(json.get("example.echo").is_some());
+}
+
+/// Payment extension inputs confirming a payment with credentials of "future.1password.com".
+fn payment_extension() -> Option<webauthn::AuthenticationExtensionsClientInputs> {
+    Some(webauthn::AuthenticationExtensionsClientInputs {
+        payment: Some(webauthn::AuthenticationExtensionsPaymentInputs {
+            is_payment: Some(true),
+            rp_id: Some("future.1password.com".into()),
+            top_origin: None,
+            payee_name: Some("Merchant".into()),
+            payee_origin: None,
+            total: Some(webauthn::PaymentCurrencyAmount {
+                currency: "USD".into(),
+                value: "12.34".into(),
+            }),
+            instrument: Some(webauthn::PaymentCredentialInstrument {
+                display_name: "Card".into(),
+                icon: "https://future.1password.com/card.png".into(),
+                icon_must_be_shown: false,
+            }),
+        }),
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn payment_is_confirmed_with_payment_credentials() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let payment_credential = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: webauthn::PublicKeyCredentialCreationOptions {
+                    extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                        payment: Some(webauthn::AuthenticationExtensionsPaymentInputs {
+                            is_payment: Some(true),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..good_credential_creation_options()
+                },
+            },
+            None,
+        )
+        .await
+        .expect("failed to register a payment credential")
+        .credential
+        .raw_id;
+    let credential = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await
+        .expect("failed to register with options")
+        .credential
+        .raw_id;
+
+    // The payment is confirmed from the merchant's origin.
+    let merchant = Url::parse("https://merchant.example").unwrap();
+    let payment_options = |credential_id: Bytes| webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            rp_id: None,
+            extensions: payment_extension(),
+            ..good_credential_request_options(credential_id)
+        },
+        mediation: None,
+    };
+    let cred = client
+        .authenticate(&merchant, payment_options(payment_credential.clone()), None)
+        .await
+        .expect("failed to confirm the payment");
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&cred.response.client_data_json).unwrap();
+    assert_eq!(client_data.ty, webauthn::ClientDataType::PaymentGet);
+    assert_eq!(client_data.origin, "https://merchant.example");
+    let payment: webauthn::CollectedClientPaymentData =
+        serde_json::from_value(client_data.unknown_keys["payment"].clone()).unwrap();
+    assert_eq!(payment.rp_id, "future.1password.com");
+    assert_eq!(payment.top_origin, "https://merchant.example");
+    assert_eq!(payment.payee_name.as_deref(), Some("Merchant"));
+    assert_eq!(payment.total.value, "12.34");
+    let auth_data =
+        ctap2::AuthenticatorData::from_slice(&cred.response.authenticator_data).unwrap();
+    assert_eq!(
+        auth_data.parse_extensions().unwrap().third_party_payment,
+        Some(true)
+    );
+
+    let res = client
+        .authenticate(&merchant, payment_options(credential), None)
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialNotFound);
+
+    let mut options = payment_options(payment_credential.clone());
+    if let Some(payment) = options
+        .public_key
+        .extensions
+        .as_mut()
+        .and_then(|ext| ext.payment.as_mut())
+    {
+        payment.total = None;
+    }
+    let res = client.authenticate(&merchant, options, None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::NotSupported);
+
+    // The top origin given by the page is not trusted.
+    let mut options = payment_options(payment_credential.clone());
+    if let Some(payment) = options
+        .public_key
+        .extensions
+        .as_mut()
+        .and_then(|ext| ext.payment.as_mut())
+    {
+        payment.top_origin = Some("https://bank.example".into());
+    }
+    let cred = client
+        .authenticate(&merchant, options, None)
+        .await
+        .expect("failed to confirm the payment");
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&cred.response.client_data_json).unwrap();
+    let payment: webauthn::CollectedClientPaymentData =
+        serde_json::from_value(client_data.unknown_keys["payment"].clone()).unwrap();
+    assert_eq!(payment.top_origin, "https://merchant.example");
+
+    // The merchant's origin must still be secure.
+    let insecure_merchant = Url::parse("http://merchant.example").unwrap();
+    let res = client
+        .authenticate(
+            &insecure_merchant,
+            payment_options(payment_credential),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnprotectedOrigin);
+}
+
+/// A credential picker choosing the credential of the given user, dismissing the request when it
+/// is not offered.
+struct UserPicker(Bytes);
+
+#[async_trait::async_trait]
+impl CredentialPicker for UserPicker {
+    async fn pick_credential(
+        &self,
+        rp_id: &str,
+        credentials: &[CredentialChoice],
+    ) -> Option<Bytes> {
+        assert_eq!(rp_id, "future.1password.com");
+        credentials
+            .iter()
+            .find(|credential| credential.user.id == self.0)
+            .map(|credential| credential.id.clone())
+    }
+}
+
+#[tokio::test]
+async fn user_handle_is_omitted_with_an_allow_list() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            authenticator_selection: resident_key_selection(
+                webauthn::ResidentKeyRequirement::Required,
+            ),
+            ..good_credential_creation_options()
+        },
+    };
+    let user = options.public_key.user.id.clone();
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with options")
+        .credential;
+    let request = || webauthn::CredentialRequestOptions {
+        public_key: good_credential_request_options(cred.raw_id.clone()),
+        mediation: None,
+    };
+
+    let response = client
+        .authenticate(&origin, request(), None)
+        .await
+        .expect("failed to authenticate with the allowed credential");
+    assert_eq!(response.response.user_handle, Some(user));
+
+    // Relying Parties which allowed the credential already know the account.
+    let mut client = client.omit_user_handle_with_allow_list(true);
+    let response = client
+        .authenticate(&origin, request(), None)
+        .await
+        .expect("failed to authenticate with the allowed credential");
+    assert_eq!(response.response.user_handle, None);
+}
+
+#[tokio::test]
+async fn conditional_mediation_asserts_the_picked_credential() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+
+    let mut credentials = Vec::new();
+    for _ in 0..2 {
+        let options = webauthn::CredentialCreationOptions {
+            public_key: webauthn::PublicKeyCredentialCreationOptions {
+                authenticator_selection: resident_key_selection(
+                    webauthn::ResidentKeyRequirement::Required,
+                ),
+                ..good_credential_creation_options()
+            },
+        };
+        let user = options.public_key.user.id.clone();
+        let cred = client
+            .register(&origin, options, None)
+            .await
+            .expect("failed to register with options")
+            .credential;
+        credentials.push((cred.raw_id, user));
+    }
+    let conditional = |allow_credentials| webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            allow_credentials,
+            ..good_credential_request_options(Bytes::from(Vec::new()))
+        },
+        mediation: Some(webauthn::CredentialMediationRequirement::Conditional),
+    };
+
+    // Conditional requests require a UI in which to offer the credentials.
+    let res = client.authenticate(&origin, conditional(None), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::NotSupported);
+
+    let (credential_id, user) = credentials[1].clone();
+    let mut client = client.with_credential_picker(UserPicker(user.clone()));
+    let response = client
+        .authenticate(&origin, conditional(None), None)
+        .await
+        .expect("failed to authenticate with the picked credential");
+    assert_eq!(response.raw_id, credential_id);
+    assert_eq!(response.response.user_handle, Some(user));
+
+    // Only the allowed credentials are offered, the request is dismissed without the user's.
+    let allowed = webauthn::PublicKeyCredentialDescriptor {
+        ty: webauthn::PublicKeyCredentialType::PublicKey,
+        id: credentials[0].0.clone(),
+        transports: None,
+    };
+    let res = client
+        .authenticate(&origin, conditional(Some(vec![allowed])), None)
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialNotFound);
+}
+
+#[async_trait::async_trait]
+impl AccountSelector for UserPicker {
+    async fn select_account(&self, rp_id: &str, accounts: &[CredentialChoice]) -> Option<Bytes> {
+        self.pick_credential(rp_id, accounts).await
+    }
+}
+
+#[tokio::test]
+async fn account_selector_chooses_among_discoverable_credentials() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+
+    let mut credentials = Vec::new();
+    for _ in 0..2 {
+        let options = webauthn::CredentialCreationOptions {
+            public_key: webauthn::PublicKeyCredentialCreationOptions {
+                authenticator_selection: resident_key_selection(
+                    webauthn::ResidentKeyRequirement::Required,
+                ),
+                ..good_credential_creation_options()
+            },
+        };
+        let user = options.public_key.user.id.clone();
+        let cred = client
+            .register(&origin, options, None)
+            .await
+            .expect("failed to register with options")
+            .credential;
+        credentials.push((cred.raw_id, user));
+    }
+    let any_credential = || webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            allow_credentials: None,
+            ..good_credential_request_options(Bytes::from(Vec::new()))
+        },
+        mediation: None,
+    };
+
+    let (credential_id, user) = credentials[1].clone();
+    let mut client = client.with_account_selector(UserPicker(user.clone()));
+    let response = client
+        .authenticate(&origin, any_credential(), None)
+        .await
+        .expect("failed to authenticate with the selected account");
+    assert_eq!(response.raw_id, credential_id);
+    assert_eq!(response.response.user_handle, Some(user));
+
+    // The request fails when the user cancels the selection.
+    let mut client = client.with_account_selector(UserPicker(random_vec(16).into()));
+    let res = client.authenticate(&origin, any_credential(), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialNotFound);
+}
+
+const APP_ID: &str = "https://future.1password.com/appid.json";
+
+#[tokio::test]
+async fn capabilities_follow_the_authenticators() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let client = Client::new(auth);
+    let capabilities = client.capabilities().await;
+    assert!(!capabilities.conditional_get);
+    assert!(capabilities.passkey_platform_authenticator);
+    assert!(
+        client
+            .is_user_verifying_platform_authenticator_available()
+            .await
+    );
+    assert!(capabilities.extensions.iter().any(|ext| ext == "largeBlob"));
+
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock())
+        .with_builtin_extensions([]);
+    let client = Client::new(auth).with_credential_picker(UserPicker(Bytes::from(vec![0])));
+    let capabilities = client.capabilities().await;
+    assert_eq!(
+        serde_json::to_value(&capabilities).unwrap(),
+        serde_json::json!({
+            "conditionalCreate": false,
+            "conditionalGet": true,
+            "hybridTransport": true,
+            "passkeyPlatformAuthenticator": true,
+            "userVerifyingPlatformAuthenticator": true,
+            "relatedOrigins": false,
+            "extension:appid": true,
+            "extension:appidExclude": true,
+            "extension:credProps": true,
+        })
+    );
+}
+
+#[tokio::test]
+async fn silent_discovery_lists_credentials_without_the_user() {
+    // The user is only involved in the two registrations.
+    let auth = Authenticator::new(
+        ctap2::Aaguid::new_empty(),
+        MemoryStore::new(),
+        uv_mock_with_creation(2),
+    );
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+
+    let mut users = Vec::new();
+    for requirement in [
+        webauthn::ResidentKeyRequirement::Required,
+        webauthn::ResidentKeyRequirement::Discouraged,
+    ] {
+        let options = webauthn::CredentialCreationOptions {
+            public_key: webauthn::PublicKeyCredentialCreationOptions {
+                authenticator_selection: resident_key_selection(requirement),
+                ..good_credential_creation_options()
+            },
+        };
+        users.push(options.public_key.user.id.clone());
+        client
+            .register(&origin, options, None)
+            .await
+            .expect("failed to register with options");
+    }
+
+    // Only the discoverable credential is listed.
+    let credentials = client
+        .silently_discover_credentials("future.1password.com")
+        .await
+        .unwrap();
+    assert_eq!(credentials.len(), 1);
+    assert_eq!(credentials[0].user.id, users[0]);
+    assert!(client
+        .silently_discover_credentials("1password.com")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// Create a credential bound to [`APP_ID`], as if it was registered with the FIDO U2F API, and
+/// return its ID.
+async fn register_with_app_id(
+    client: &mut Client<MemoryStore, MockUserValidationMethod, public_suffix::PublicSuffixList>,
+) -> Bytes {
+    let options = good_credential_creation_options();
+    let response = client
+        .authenticator_mut()
+        .make_credential(ctap2::make_credential::Request {
+            client_data_hash: random_vec(32).into(),
+            rp: ctap2::make_credential::PublicKeyCredentialRpEntity {
+                id: APP_ID.into(),
+                name: None,
+            },
+            user: options.user,
+            pub_key_cred_params: options.pub_key_cred_params,
+            exclude_list: None,
+            extensions: None,
+            options: ctap2::make_credential::Options {
+                rk: false,
+                up: true,
+                uv: true,
+            },
+            pin_auth: None,
+            pin_protocol: None,
+            enterprise_attestation: None,
+        })
+        .await
+        .expect("failed to create a credential for the AppID");
+    response
+        .auth_data
+        .attested_credential_data
+        .expect("missing attested credential data")
+        .credential_id()
+        .to_vec()
+        .into()
+}
+
+#[tokio::test]
+async fn authenticate_with_app_id() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let credential_id = register_with_app_id(&mut client).await;
+
+    let options = |appid: Option<&str>| webauthn::CredentialRequestOptions {
+        public_key: webauthn::PublicKeyCredentialRequestOptions {
+            extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                appid: appid.map(Into::into),
+                ..Default::default()
+            }),
+            ..good_credential_request_options(credential_id.clone())
+        },
+        mediation: None,
+    };
+
+    let res = client.authenticate(&origin, options(None), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialNotFound);
+
+    let res = client
+        .authenticate(&origin, options(Some(APP_ID)), None)
+        .await
+        .expect("failed to authenticate with the AppID");
+    assert_eq!(res.client_extension_results.appid, Some(true));
+    let auth_data = ctap2::AuthenticatorData::from_slice(&res.response.authenticator_data)
+        .expect("could not deserialize response");
+    assert_eq!(auth_data.rp_id_hash(), &sha256(APP_ID.as_bytes()));
+
+    let res = client
+        .authenticate(
+            &origin,
+            options(Some("https://example.com/appid.json")),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::InvalidAppId);
+
+    // Credentials found under the RP ID do not use the AppID.
+    let cred = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await
+        .expect("failed to register with options")
+        .credential;
+    let res = client
+        .authenticate(
+            &origin,
+            webauthn::CredentialRequestOptions {
+                public_key: webauthn::PublicKeyCredentialRequestOptions {
+                    extensions: options(Some(APP_ID)).public_key.extensions,
+                    ..good_credential_request_options(cred.raw_id)
+                },
+                mediation: None,
+            },
+            None,
+        )
+        .await
+        .expect("failed to authenticate with freshly created credential");
+    assert_eq!(res.client_extension_results.appid, Some(false));
+}
+
+#[tokio::test]
+async fn register_with_app_id_exclude() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let credential_id = register_with_app_id(&mut client).await;
+
+    let options = |exclude_credentials| webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            exclude_credentials,
+            extensions: Some(webauthn::AuthenticationExtensionsClientInputs {
+                appid_exclude: Some(APP_ID.into()),
+                ..Default::default()
+            }),
+            ..good_credential_creation_options()
+        },
+    };
+
+    let res = client
+        .register(
+            &origin,
+            options(Some(vec![webauthn::PublicKeyCredentialDescriptor {
+                ty: webauthn::PublicKeyCredentialType::PublicKey,
+                id: credential_id,
+                transports: None,
+            }])),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialExcluded);
+
+    let cred = client
+        .register(&origin, options(None), None)
+        .await
+        .expect("failed to register with options")
+        .credential;
+    assert_eq!(cred.client_extension_results.appid_exclude, Some(true));
+}
+
+#[tokio::test]
+async fn credentials_excluded_by_the_authenticator_need_no_second_gesture() {
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| Some(true));
+    user_mock
+        .expect_check_user_verification()
+        .returning(|| Box::pin(async { true }))
+        .times(2);
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    user_mock.expect_check_user_presence().never();
+    // Non-discoverable credentials are wrapped into their IDs, so only the authenticator knows
+    // them.
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock)
+        .with_wrapping_key([42; 32]);
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = |exclude_credentials| webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            exclude_credentials,
+            authenticator_selection: resident_key_selection(
+                webauthn::ResidentKeyRequirement::Discouraged,
+            ),
+            ..good_credential_creation_options()
+        },
+    };
+    let cred = client
+        .register(&origin, options(None), None)
+        .await
+        .expect("failed to register with options")
+        .credential;
+
+    let exclude_credentials = vec![webauthn::PublicKeyCredentialDescriptor {
+        ty: webauthn::PublicKeyCredentialType::PublicKey,
+        id: cred.raw_id,
+        transports: None,
+    }];
+    let res = client
+        .register(&origin, options(Some(exclude_credentials)), None)
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialExcluded);
+}
+
+#[tokio::test]
+async fn excluded_credentials_are_reported_after_a_user_gesture() {
+    let mut user_mock = uv_mock_with_creation(1);
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| Some(true));
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { true }))
+        .times(1);
+    user_mock
+        .expect_check_user_presence()
+        .returning(|| Box::pin(async { false }))
+        .times(1);
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock);
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let cred = client
+        .register(
+            &origin,
+            webauthn::CredentialCreationOptions {
+                public_key: good_credential_creation_options(),
+            },
+            None,
+        )
+        .await
+        .expect("failed to register with options")
+        .credential;
+
+    // The exclude list is checked without the user.
+    let exclude_credentials = vec![webauthn::PublicKeyCredentialDescriptor {
+        ty: webauthn::PublicKeyCredentialType::PublicKey,
+        id: cred.raw_id.clone(),
+        transports: None,
+    }];
+    assert_eq!(
+        client
+            .find_excluded_credential("future.1password.com", &exclude_credentials)
+            .await,
+        Some(cred.raw_id.clone())
+    );
+    assert_eq!(
+        client
+            .find_excluded_credential("1password.com", &exclude_credentials)
+            .await,
+        None
+    );
+
+    // Registrations only fail with the exclusion once the user confirmed them.
+    let options = || webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            exclude_credentials: Some(exclude_credentials.clone()),
+            ..good_credential_creation_options()
+        },
+    };
+    let res = client.register(&origin, options(), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::CredentialExcluded);
+    let res = client.register(&origin, options(), None).await;
+    assert_eq!(
+        res.unwrap_err(),
+        ctap2::StatusCode::from(ctap2::Ctap2Error::OperationDenied).into()
+    );
+}
+
+#[test]
+fn validate_rp_id() -> Result<(), ParseError> {
+    let client = RpIdVerifier::new(public_suffix::DEFAULT_PROVIDER);
+
+    let example = "https://example.com".parse()?;
+    let com_tld = client.assert_domain(&example, Some("com"));
+    assert_eq!(com_tld, Err(WebauthnError::InvalidRpId));
+
+    let example_dots = "https://example...com".parse()?;
+    let bunch_of_dots = client.assert_domain(&example_dots, Some("...com"));
+    assert_eq!(bunch_of_dots, Err(WebauthnError::InvalidRpId));
+
+    let future = "https://www.future.1password.com".parse()?;
+    let sub_domain_ignored = client.assert_domain(&future, Some("future.1password.com"));
+    assert_eq!(sub_domain_ignored, Ok("future.1password.com"));
+
+    let use_effective_domain = client.assert_domain(&future, None);
+    assert_eq!(use_effective_domain, Ok("www.future.1password.com"));
+
+    let not_protected = "http://example.com".parse()?;
+    let not_https = client.assert_domain(&not_protected, Some("example.com"));
+    assert_eq!(not_https, Err(WebauthnError::UnprotectedOrigin));
+
+    let localhost = "http://localhost:8080".parse()?;
+    let should_still_match = client.assert_domain(&localhost, Some("example.com"));
+    assert_eq!(should_still_match, Err(WebauthnError::OriginRpMissmatch));
+
+    let localhost_not_allowed = client.assert_domain(&localhost, Some("localhost"));
+    assert_eq!(
+        localhost_not_allowed,
+        Err(WebauthnError::InsecureLocalhostNotAllowed)
+    );
+    let localhost_not_allowed = client.assert_domain(&localhost, None);
+    assert_eq!(
+        localhost_not_allowed,
+        Err(WebauthnError::InsecureLocalhostNotAllowed)
+    );
+
+    let client = client.allows_insecure_localhost(true);
+    let skips_http_and_tld_check = client.assert_domain(&localhost, Some("localhost"));
+    assert_eq!(skips_http_and_tld_check, Ok("localhost"));
+    let skips_http_and_tld_check = client.assert_domain(&localhost, None);
+    assert_eq!(skips_http_and_tld_check, Ok("localhost"));
+
+    Ok(())
+}
+
+#[test]
+fn validate_app_id() -> Result<(), ParseError> {
+    let client = RpIdVerifier::new(public_suffix::DEFAULT_PROVIDER);
+    let origin = "https://www.future.1password.com".parse()?;
+
+    let app_id = "https://future.1password.com/appid.json";
+    assert_eq!(client.assert_app_id(&origin, app_id), Ok(app_id));
+    // Only the registrable domain has to match.
+    let app_id = "https://login.1password.com/appid.json";
+    assert_eq!(client.assert_app_id(&origin, app_id), Ok(app_id));
+
+    for app_id in [
+        "https://example.com/appid.json",
+        "http://future.1password.com/appid.json",
+        "future.1password.com",
+    ] {
+        assert_eq!(
+            client.assert_app_id(&origin, app_id),
+            Err(WebauthnError::InvalidAppId)
+        );
+    }
+
+    let not_protected = "http://www.future.1password.com".parse()?;
+    assert_eq!(
+        client.assert_app_id(&not_protected, "https://future.1password.com/appid.json"),
+        Err(WebauthnError::UnprotectedOrigin)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn validate_related_origins() -> Result<(), ParseError> {
+    let verifier = RpIdVerifier::new(public_suffix::DEFAULT_PROVIDER);
+    let rp_id = "future.1password.com";
+    let related_origins = webauthn::RelatedOrigins {
+        origins: vec![
+            "not an origin".into(),
+            "https://1password.ca".into(),
+            "https://login.1password.ca".into(),
+            "https://one.example".into(),
+            "https://two.example".into(),
+            "https://three.example".into(),
+            "https://four.example".into(),
+            // Beyond the first five labels, only origins of labels already seen are accepted.
+            "https://five.example".into(),
+            "https://www.four.example".into(),
+        ],
+    };
+
+    for origin in [
+        "https://1password.ca",
+        "https://login.1password.ca",
+        "https://www.four.example",
+    ] {
+        assert_eq!(
+            verifier.assert_related_origin(&origin.parse()?, rp_id, &related_origins),
+            Ok(rp_id)
+        );
+    }
+    for origin in ["https://five.example", "https://www.1password.ca"] {
+        assert_eq!(
+            verifier.assert_related_origin(&origin.parse()?, rp_id, &related_origins),
+            Err(WebauthnError::OriginRpMissmatch)
+        );
+    }
+    assert_eq!(
+        verifier.assert_related_origin(&"http://1password.ca".parse()?, rp_id, &related_origins),
+        Err(WebauthnError::UnprotectedOrigin)
+    );
+    assert_eq!(
+        verifier.assert_related_origin(&"https://1password.ca".parse()?, "ca", &related_origins),
+        Err(WebauthnError::InvalidRpId)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn register_from_related_origin() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://1password.ca").unwrap();
+    let options = || webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+
+    // Requests from another origin are refused unless the RP lists it as related.
+    let res = client.register(&origin, options(), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::OriginRpMissmatch);
+
+    let mut client = client.with_related_origins(std::collections::HashMap::from([(
+        "future.1password.com".to_owned(),
+        webauthn::RelatedOrigins {
+            origins: vec!["https://1password.ca".into()],
+        },
+    )]));
+    let cred = client
+        .register(&origin, options(), None)
+        .await
+        .expect("failed to register from a related origin")
+        .credential;
+    let att_obj = ctap2::AttestationObject::from_cbor(&cred.response.attestation_object)
+        .expect("could not deserialize response");
+    assert_eq!(
+        att_obj.auth_data.rp_id_hash(),
+        &sha256(b"future.1password.com")
+    );
+
+    let res = client
+        .register(
+            &Url::parse("https://1password.eu").unwrap(),
+            options(),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::OriginRpMissmatch);
+}
+
+#[tokio::test]
+async fn register_from_android_app() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let key_hash = random_vec(32);
+    let origin = Url::parse(&format!(
+        "android:apk-key-hash:{}",
+        encoding::base64url(&key_hash)
+    ))
+    .unwrap();
+    let options = || webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+
+    // Apps are refused unless the RP lists them.
+    let res = client.register(&origin, options(), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::UnprotectedOrigin);
+
+    let fingerprint = key_hash
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    let mut client = client.with_android_apps(std::collections::HashMap::from([(
+        "future.1password.com".to_owned(),
+        vec![AndroidApp {
+            package_name: "com.onepassword.android".into(),
+            sha256_cert_fingerprints: vec![fingerprint],
+        }],
+    )]));
+    let cred = client
+        .register(&origin, options(), None)
+        .await
+        .expect("failed to register from an Android app")
+        .credential;
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&cred.response.client_data_json).unwrap();
+    assert_eq!(client_data.origin, origin.as_str());
+
+    let other_app = Url::parse(&format!(
+        "android:apk-key-hash:{}",
+        encoding::base64url(&random_vec(32))
+    ))
+    .unwrap();
+    let res = client.register(&other_app, options(), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::OriginRpMissmatch);
+}
+
+#[tokio::test]
+async fn requests_from_frames_report_their_top_origin() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = || webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+    let mut frame = FrameContext {
+        ancestor_origins: vec![Url::parse("https://shop.example/checkout").unwrap()],
+        allows_create: false,
+        allows_get: true,
+    };
+
+    // The page must delegate the feature of the request to the frame.
+    let res = client
+        .register_in_frame(&origin, &frame, options(), None)
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::NotAllowed);
+
+    frame.allows_create = true;
+    let cred = client
+        .register_in_frame(&origin, &frame, options(), None)
+        .await
+        .expect("failed to register from a cross-origin frame")
+        .credential;
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&cred.response.client_data_json).unwrap();
+    assert_eq!(client_data.cross_origin, Some(true));
+    assert_eq!(
+        client_data.unknown_keys.get("topOrigin"),
+        Some(&"https://shop.example".into())
+    );
+
+    // A frame embedded in pages of its own origin is not cross-origin.
+    let frame = FrameContext {
+        ancestor_origins: vec![Url::parse("https://future.1password.com/account").unwrap()],
+        allows_create: false,
+        allows_get: true,
+    };
+    let auth_options = webauthn::CredentialRequestOptions {
+        public_key: good_credential_request_options(cred.raw_id),
+        mediation: None,
+    };
+    let res = client
+        .authenticate_in_frame(&origin, &frame, auth_options, None)
+        .await
+        .expect("failed to authenticate from a same-origin frame");
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&res.response.client_data_json).unwrap();
+    assert_eq!(client_data.cross_origin, Some(false));
+    assert!(client_data.unknown_keys.is_empty());
+}
+
+#[tokio::test]
+async fn client_data_customizer_adds_members() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client =
+        Client::new(auth).with_client_data_customizer(|fields: &mut ClientDataFields<'_>| {
+            assert_eq!(fields.origin(), "https://future.1password.com");
+            // The members verified by the Relying Party cannot be replaced.
+            assert!(!fields.insert("challenge", "forged".into()));
+            assert!(fields.insert("androidPackageName", "com.onepassword.android".into()));
+            fields.set_token_binding(Some(webauthn::TokenBinding {
+                status: webauthn::TokenBindingStatus::Supported,
+                id: None,
+            }));
+        });
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+    let challenge = encoding::base64url(&options.public_key.challenge);
+
+    let cred = client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register with a customized client data")
+        .credential;
+    let client_data: webauthn::CollectedClientData =
+        serde_json::from_slice(&cred.response.client_data_json).unwrap();
+    assert_eq!(client_data.challenge, challenge);
+    assert_eq!(
+        client_data.unknown_keys.get("androidPackageName"),
+        Some(&"com.onepassword.android".into())
+    );
+    assert_eq!(
+        client_data.unknown_keys.get("tokenBinding"),
+        Some(&serde_json::json!({ "status": "supported" }))
+    );
+}
+
+#[test]
+fn timeouts_are_clamped_to_the_recommended_range() {
+    use std::time::Duration;
+    use webauthn::UserVerificationRequirement as Uv;
+
+    let timeout = timeout::ceremony_timeout;
+    assert_eq!(timeout(None, Uv::Discouraged), Duration::from_secs(120));
+    assert_eq!(
+        timeout(Some(1_000), Uv::Discouraged),
+        Duration::from_secs(30)
+    );
+    assert_eq!(
+        timeout(Some(60_000), Uv::Discouraged),
+        Duration::from_secs(60)
+    );
+    assert_eq!(timeout(None, Uv::Preferred), Duration::from_secs(300));
+    assert_eq!(
+        timeout(Some(60_000), Uv::Required),
+        Duration::from_secs(300)
+    );
+    assert_eq!(
+        timeout(Some(u32::MAX), Uv::Required),
+        Duration::from_secs(600)
+    );
+}
+
+/// A timer whose every timeout has already elapsed.
+struct ElapsedTimer;
+
+#[async_trait::async_trait]
+impl Timer for ElapsedTimer {
+    async fn sleep(&self, _duration: std::time::Duration) {}
+}
+
+#[tokio::test]
+async fn requests_time_out_while_waiting_for_the_user() {
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| Some(true));
+    user_mock
+        .expect_check_user_verification()
+        .returning(|| Box::pin(std::future::pending()));
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock);
+    let mut client = Client::new(auth).with_timer(ElapsedTimer);
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+
+    let res = client.register(&origin, options, None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::Timeout);
+    assert!(client
+        .authenticator()
+        .store()
+        .discoverable_credentials()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn requests_are_aborted_while_waiting_for_the_user() {
+    let (tx, rx) = std::sync::mpsc::channel::<AbortHandle>();
+    let mut user_mock = MockUserValidationMethod::new();
+    user_mock
+        .expect_is_verification_enabled()
+        .returning(|| Some(true));
+    // The user dismisses the UI of the request instead of verifying themselves.
+    user_mock
+        .expect_check_user_verification()
+        .returning(move || {
+            rx.recv().unwrap().abort();
+            Box::pin(std::future::pending())
+        });
+    user_mock.expect_is_presence_enabled().returning(|| true);
+    user_mock.expect_fingerprint_sensor().returning(|| None);
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), user_mock);
+    let mut client = Client::new(auth);
+    tx.send(client.abort_handle()).unwrap();
+    let origin = Url::parse("https://future.1password.com").unwrap();
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+
+    let res = client.register(&origin, options, None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::Aborted);
+
+    // Aborting while no request is in progress does not affect the next one.
+    client.abort_handle().abort();
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    *client.authenticator_mut() = auth;
+    let options = webauthn::CredentialCreationOptions {
+        public_key: good_credential_creation_options(),
+    };
+    client
+        .register(&origin, options, None)
+        .await
+        .expect("failed to register after an aborted request");
+}
+
+#[test]
+fn errors_map_to_dom_exceptions() {
+    for (error, name, step) in [
+        (
+            WebauthnError::OriginRpMissmatch,
+            DomExceptionName::SecurityError,
+            CeremonyStep::OriginVerification,
+        ),
+        (
+            WebauthnError::UnsatisfiedCriteria,
+            DomExceptionName::ConstraintError,
+            CeremonyStep::AuthenticatorSelection,
+        ),
+        (
+            WebauthnError::Aborted,
+            DomExceptionName::AbortError,
+            CeremonyStep::UserInteraction,
+        ),
+        (
+            WebauthnError::CredentialExcluded,
+            DomExceptionName::InvalidStateError,
+            CeremonyStep::AuthenticatorOperation,
+        ),
+        (
+            ctap2::StatusCode::from(ctap2::Ctap2Error::UnsupportedAlgorithm).into(),
+            DomExceptionName::NotSupportedError,
+            CeremonyStep::AuthenticatorOperation,
+        ),
+        (
+            ctap2::StatusCode::from(ctap2::Ctap2Error::OperationDenied).into(),
+            DomExceptionName::NotAllowedError,
+            CeremonyStep::AuthenticatorOperation,
+        ),
+        (
+            ctap2::StatusCode::from(ctap2::Ctap2Error::NoCredentials).into(),
+            DomExceptionName::NotAllowedError,
+            CeremonyStep::AuthenticatorOperation,
+        ),
+    ] {
+        assert_eq!(error.dom_exception(), name, "{error:?}");
+        assert_eq!(error.step(), step, "{error:?}");
+    }
+}
+
+/// An RP ID validator accepting the single-label `intranet` domain of an enterprise, following the
+/// WebAuthn rules otherwise.
+struct InternalDomainValidator(RpIdVerifier<public_suffix::PublicSuffixList>);
+
+impl RpIdValidator for InternalDomainValidator {
+    fn assert_domain<'a>(
+        &self,
+        origin: &'a Url,
+        rp_id: Option<&'a str>,
+    ) -> Result<&'a str, WebauthnError> {
+        match origin.domain() {
+            Some("intranet") if rp_id.is_none_or(|rp_id| rp_id == "intranet") => Ok("intranet"),
+            _ => self.0.assert_domain(origin, rp_id),
+        }
+    }
+}
+
+#[tokio::test]
+async fn rp_id_validator_replaces_the_builtin_rules() {
+    let auth = Authenticator::new(ctap2::Aaguid::new_empty(), MemoryStore::new(), uv_mock());
+    let mut client = Client::new(auth);
+    let origin = Url::parse("https://intranet").unwrap();
+    let options = |rp_id: &str| webauthn::CredentialCreationOptions {
+        public_key: webauthn::PublicKeyCredentialCreationOptions {
+            rp: webauthn::PublicKeyCredentialRpEntity {
+                id: Some(rp_id.into()),
+                name: rp_id.into(),
+            },
+            ..good_credential_creation_options()
+        },
+    };
+
+    let res = client.register(&origin, options("intranet"), None).await;
+    assert_eq!(res.unwrap_err(), WebauthnError::InvalidRpId);
+
+    let mut client = client.with_rp_id_validator(InternalDomainValidator(RpIdVerifier::new(
+        public_suffix::DEFAULT_PROVIDER,
+    )));
+    let cred = client
+        .register(&origin, options("intranet"), None)
+        .await
+        .expect("failed to register with an internal domain")
+        .credential;
+    let att_obj = ctap2::AttestationObject::from_cbor(&cred.response.attestation_object)
+        .expect("could not deserialize response");
+    assert_eq!(att_obj.auth_data.rp_id_hash(), &sha256(b"intranet"));
+
+    // Other origins still follow the WebAuthn rules.
+    let res = client
+        .register(
+            &Url::parse("https://future.1password.com").unwrap(),
+            options("1password.ca"),
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), WebauthnError::OriginRpMissmatch);
+}
+
+#[test]
+fn validate_associated_domains() -> Result<(), ParseError> {
+    let domains = AssociatedDomains::new([
+        "applinks:1password.com",
+        "webcredentials:future.1password.com",
+        "webcredentials:*.1password.ca?mode=developer",
+    ]);
+    let origin = Url::parse("https://future.1password.com")?;
+
+    assert_eq!(
+        domains.assert_domain(&origin, None),
+        Ok("future.1password.com")
+    );
+    assert_eq!(
+        domains.assert_domain(&origin, Some("Future.1Password.com")),
+        Ok("Future.1Password.com")
+    );
+    assert_eq!(
+        domains.assert_domain(&origin, Some("my.1password.ca")),
+        Ok("my.1password.ca")
+    );
+    for rp_id in ["1password.com", "1password.ca", "not1password.ca"] {
+        assert_eq!(
+            domains.assert_domain(&origin, Some(rp_id)),
+            Err(WebauthnError::OriginRpMissmatch),
+            "{rp_id}"
+        );
+    }
+    assert_eq!(
+        domains.assert_domain(&Url::parse("app:")?, None),
+        Err(WebauthnError::OriginMissingDomain)
+    );
     Ok(())
 }
 
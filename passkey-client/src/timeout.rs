@@ -0,0 +1,69 @@
+//! Timeouts of WebAuthn ceremonies, within the ranges recommended for the user verification they
+//! require, after which the request fails and the pending check of the user is abandoned.
+//!
+//! <https://w3c.github.io/webauthn/#sctn-timeout-recommended-range>
+
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+use passkey_types::webauthn::UserVerificationRequirement;
+
+use crate::WebauthnError;
+
+/// Use this on the timer of the async runtime of the host, with which a [`Client`](crate::Client)
+/// enforces the timeout of its requests, see
+/// [`Client::with_timer`](crate::Client::with_timer).
+#[async_trait::async_trait]
+pub trait Timer {
+    /// Complete after the given `duration`.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// The timeout of a ceremony, given the `timeout` requested in milliseconds and the user
+/// verification it requires.
+///
+/// Requested timeouts are clamped to the recommended range, of 30 seconds to 3 minutes when user
+/// verification is discouraged, and of 5 to 10 minutes otherwise since verifying the user takes
+/// longer.
+pub(crate) fn ceremony_timeout(
+    timeout: Option<u32>,
+    user_verification: UserVerificationRequirement,
+) -> Duration {
+    let (min, max, default) = match user_verification {
+        UserVerificationRequirement::Discouraged => (30_000, 180_000, 120_000),
+        _ => (300_000, 600_000, 300_000),
+    };
+    let millis = timeout.map_or(default, |timeout| timeout.clamp(min, max));
+    Duration::from_millis(millis.into())
+}
+
+/// Run the `operation` of a ceremony until it completes or `timeout` elapses on the `timer`,
+/// dropping it in the latter case so that its pending check of the user is abandoned.
+///
+/// Fails with [`WebauthnError::Timeout`] when the timeout elapses first. Without a timer, the
+/// operation is never interrupted.
+pub(crate) async fn race<T>(
+    timer: Option<&(dyn Timer + Send + Sync)>,
+    timeout: Duration,
+    operation: impl Future<Output = Result<T, WebauthnError>>,
+) -> Result<T, WebauthnError> {
+    let Some(timer) = timer else {
+        return operation.await;
+    };
+    let mut operation = pin!(operation);
+    let mut deadline = timer.sleep(timeout);
+    poll_fn(|cx| {
+        if let Poll::Ready(result) = operation.as_mut().poll(cx) {
+            return Poll::Ready(result);
+        }
+        deadline
+            .as_mut()
+            .poll(cx)
+            .map(|()| Err(WebauthnError::Timeout))
+    })
+    .await
+}
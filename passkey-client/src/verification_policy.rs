@@ -0,0 +1,70 @@
+//! Policies of the host on user verification, which override the requirement of the Relying Party,
+//! for example when an enterprise requires users to be verified to use the credentials of some of
+//! its RP IDs.
+//!
+//! <https://w3c.github.io/webauthn/#enumdef-userverificationrequirement>
+
+use passkey_authenticator::{CredentialStore, UserValidationMethod};
+use passkey_types::{webauthn, Passkey};
+
+use crate::Client;
+
+/// Use this on a type deciding the user verification requirement of each request in place of the
+/// Relying Party, see [`Client::with_user_verification_policy`].
+///
+/// It is implemented for closures taking the type, the RP ID and the requested requirement of a
+/// request.
+pub trait UserVerificationPolicy {
+    /// The user verification requirement of a request of the given type for `rp_id`, given the
+    /// `requested` requirement of the Relying Party, or its default when it requested none.
+    ///
+    /// Returning `requested` keeps the requirement of the Relying Party. A `required` requirement
+    /// fails requests on authenticators which cannot verify the user.
+    fn user_verification(
+        &self,
+        ty: webauthn::ClientDataType,
+        rp_id: &str,
+        requested: webauthn::UserVerificationRequirement,
+    ) -> webauthn::UserVerificationRequirement;
+}
+
+impl<F> UserVerificationPolicy for F
+where
+    F: Fn(
+        webauthn::ClientDataType,
+        &str,
+        webauthn::UserVerificationRequirement,
+    ) -> webauthn::UserVerificationRequirement,
+{
+    fn user_verification(
+        &self,
+        ty: webauthn::ClientDataType,
+        rp_id: &str,
+        requested: webauthn::UserVerificationRequirement,
+    ) -> webauthn::UserVerificationRequirement {
+        self(ty, rp_id, requested)
+    }
+}
+
+impl<S, U, P> Client<S, U, P>
+where
+    S: CredentialStore + Sync + Send,
+    U: UserValidationMethod + Sync,
+    P: public_suffix::EffectiveTLDProvider + Sync + 'static,
+    Passkey: TryFrom<<S as CredentialStore>::PasskeyItem>,
+{
+    /// The user verification requirement of a request of the given type for `rp_id`: the one of
+    /// the [`UserVerificationPolicy`] if there is one, or the `requested` one otherwise.
+    pub(crate) fn user_verification_requirement(
+        &self,
+        ty: webauthn::ClientDataType,
+        rp_id: &str,
+        requested: webauthn::UserVerificationRequirement,
+    ) -> webauthn::UserVerificationRequirement {
+        self.user_verification_policy
+            .as_deref()
+            .map_or(requested, |policy| {
+                policy.user_verification(ty, rp_id, requested)
+            })
+    }
+}
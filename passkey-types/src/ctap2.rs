@@ -6,11 +6,23 @@
 
 mod aaguid;
 mod attestation_fmt;
+mod command;
 mod error;
+mod extensions;
 mod flags;
+mod pin_uv_auth_token;
 
+pub mod attestation_statement;
+pub mod authenticator_config;
+pub mod bio_enrollment;
+pub mod client_pin;
+pub mod credential_management;
 pub mod get_assertion;
 pub mod get_info;
+pub mod large_blobs;
 pub mod make_credential;
 
-pub use self::{aaguid::*, attestation_fmt::*, error::*, flags::*};
+pub use self::{
+    aaguid::*, attestation_fmt::*, command::*, error::*, extensions::*, flags::*,
+    pin_uv_auth_token::*,
+};
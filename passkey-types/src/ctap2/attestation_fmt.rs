@@ -9,9 +9,84 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     crypto::sha256,
-    ctap2::{Aaguid, Flags},
+    ctap2::{make_credential, Aaguid, AuthenticatorExtensionOutputs, Flags},
 };
 
+/// The attestation object returned to the Relying Party when a new credential is created. It
+/// wraps the [`AuthenticatorData`] together with the attestation statement and its format.
+///
+/// <https://w3c.github.io/webauthn/#sctn-attestation>
+#[derive(Debug, PartialEq)]
+pub struct AttestationObject {
+    /// The attestation statement format identifier.
+    pub fmt: String,
+
+    /// The authenticator data, containing the [`AttestedCredentialData`] of the new credential.
+    pub auth_data: AuthenticatorData,
+
+    /// The attestation statement, whose format is identified by [`Self::fmt`].
+    pub att_stmt: Value,
+}
+
+impl AttestationObject {
+    /// Encode the attestation object to its CBOR representation, with the keys in CTAP2
+    /// canonical order.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let object = Value::Map(vec![
+            (Value::Text("fmt".into()), Value::Text(self.fmt.clone())),
+            (Value::Text("attStmt".into()), self.att_stmt.clone()),
+            (
+                Value::Text("authData".into()),
+                Value::Bytes(self.auth_data.to_vec()),
+            ),
+        ]);
+        let mut bytes = Vec::with_capacity(128);
+        // SAFETY: serializing a `Value` into a `Vec` cannot fail.
+        ciborium::ser::into_writer(&object, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Decode an attestation object from its CBOR representation.
+    pub fn from_cbor(bytes: &[u8]) -> coset::Result<Self> {
+        let object: Value = ciborium::de::from_reader(bytes).map_err(io_error)?;
+        let Value::Map(entries) = object else {
+            return Err(coset::CoseError::UnexpectedItem("value", "map"));
+        };
+        let entry = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v)
+        };
+
+        let fmt = entry("fmt")
+            .and_then(Value::as_text)
+            .ok_or(coset::CoseError::UnexpectedItem("value", "fmt text"))?;
+        let att_stmt = entry("attStmt")
+            .filter(|stmt| stmt.is_map())
+            .ok_or(coset::CoseError::UnexpectedItem("value", "attStmt map"))?;
+        let auth_data = entry("authData")
+            .and_then(Value::as_bytes)
+            .ok_or(coset::CoseError::UnexpectedItem("value", "authData bytes"))?;
+
+        Ok(Self {
+            fmt: fmt.to_owned(),
+            auth_data: AuthenticatorData::from_slice(auth_data)?,
+            att_stmt: att_stmt.clone(),
+        })
+    }
+}
+
+impl From<make_credential::Response> for AttestationObject {
+    fn from(response: make_credential::Response) -> Self {
+        Self {
+            fmt: response.fmt,
+            auth_data: response.auth_data,
+            att_stmt: response.att_stmt,
+        }
+    }
+}
+
 /// The authenticator data structure encodes contextual bindings made by the authenticator. These
 /// bindings are controlled by the authenticator itself, and derive their trust from the WebAuthn
 /// Relying Party's assessment of the security properties of the authenticator. In one extreme case,
@@ -77,6 +152,23 @@ impl AuthenticatorData {
         self.set_flags(Flags::AT)
     }
 
+    /// Add the authenticator extension outputs, which must be a CBOR map, to the authenticator
+    /// data.
+    ///
+    /// This sets the [`Flags::ED`] value as well.
+    pub fn set_extensions(mut self, extensions: Value) -> Self {
+        self.extensions = Some(extensions);
+        self.set_flags(Flags::ED)
+    }
+
+    /// Set the [`Flags::BE`] and [`Flags::BS`] values of the credential, which are both set by
+    /// default.
+    pub fn set_backup_flags(mut self, backup_eligible: bool, backup_state: bool) -> Self {
+        self.flags.set(Flags::BE, backup_eligible);
+        self.flags.set(Flags::BS, backup_state);
+        self
+    }
+
     /// Set additional [`Flags`] to the authenticator data.
     pub fn set_flags(mut self, flags: Flags) -> Self {
         self.flags |= flags;
@@ -87,6 +179,19 @@ impl AuthenticatorData {
     pub fn rp_id_hash(&self) -> &[u8] {
         &self.rp_id_hash
     }
+
+    /// Parse the signed [`Self::extensions`] into typed extension outputs.
+    ///
+    /// Outputs of extensions which are not recognized are returned in
+    /// [`AuthenticatorExtensionOutputs::unknown`], while malformed outputs of recognized extensions
+    /// result in an error.
+    pub fn parse_extensions(&self) -> coset::Result<AuthenticatorExtensionOutputs> {
+        self.extensions
+            .as_ref()
+            .map(AuthenticatorExtensionOutputs::from_value)
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
 }
 
 impl Serialize for AuthenticatorData {
@@ -298,7 +403,10 @@ mod test {
     use coset::CoseKeyBuilder;
 
     use super::*;
-    use crate::utils::rand::random_vec;
+    use crate::{
+        ctap2::{CredBlobOutput, CredentialProtectionPolicy, HmacSecretOutput},
+        utils::rand::random_vec,
+    };
 
     #[test]
     fn deserialize_authenticator_data_with_at_and_ed() {
@@ -480,4 +588,104 @@ mod test {
 
         assert_eq!(expected, auth_data);
     }
+
+    #[test]
+    fn attestation_object_round_trip() {
+        let expected = AttestationObject {
+            fmt: "none".into(),
+            auth_data: AuthenticatorData::new("future.1password.com", Some(0))
+                .set_attested_credential_data(AttestedCredentialData {
+                    aaguid: Aaguid::new_empty(),
+                    credential_id: random_vec(16),
+                    key: CoseKeyBuilder::new_ec2_pub_key(
+                        coset::iana::EllipticCurve::P_256,
+                        random_vec(32),
+                        random_vec(32),
+                    )
+                    .algorithm(coset::iana::Algorithm::ES256)
+                    .build(),
+                }),
+            att_stmt: Value::Map(Vec::new()),
+        };
+
+        let bytes = expected.to_cbor();
+        let object: Value = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        let keys: Vec<_> = object
+            .as_map()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, _)| key.as_text())
+            .collect();
+        assert_eq!(keys, ["fmt", "attStmt", "authData"]);
+
+        let decoded = AttestationObject::from_cbor(&bytes).expect("could not deserialize");
+        assert_eq!(expected, decoded);
+
+        let mut missing_fmt = Vec::new();
+        ciborium::ser::into_writer(
+            &cbor!({
+                "attStmt" => {},
+                "authData" => Value::Bytes(expected.auth_data.to_vec()),
+            })
+            .unwrap(),
+            &mut missing_fmt,
+        )
+        .unwrap();
+        assert!(AttestationObject::from_cbor(&missing_fmt).is_err());
+    }
+
+    #[test]
+    fn parse_extension_outputs() {
+        let mut auth_data = AuthenticatorData::new("future.1password.com", Some(0));
+        assert_eq!(
+            auth_data.parse_extensions().unwrap(),
+            AuthenticatorExtensionOutputs::default()
+        );
+
+        auth_data.extensions = Some(
+            cbor!({
+                "credProtect" => 3,
+                "hmac-secret" => true,
+                "minPinLength" => 6,
+                "example.extension" => "value",
+            })
+            .unwrap(),
+        );
+        let outputs = auth_data.parse_extensions().unwrap();
+        assert_eq!(
+            outputs.cred_protect,
+            Some(CredentialProtectionPolicy::UserVerificationRequired)
+        );
+        assert_eq!(outputs.hmac_secret, Some(HmacSecretOutput::Created(true)));
+        assert_eq!(outputs.cred_blob, None);
+        assert_eq!(outputs.min_pin_length, Some(6));
+        assert_eq!(
+            outputs.unknown,
+            vec![("example.extension".into(), Value::Text("value".into()))]
+        );
+
+        auth_data.extensions = Some(
+            cbor!({
+                "hmac-secret" => Value::Bytes(vec![1; 32]),
+                "hmac-secret-mc" => Value::Bytes(vec![3; 48]),
+                "credBlob" => Value::Bytes(vec![2; 8]),
+                "thirdPartyPayment" => false,
+            })
+            .unwrap(),
+        );
+        let outputs = auth_data.parse_extensions().unwrap();
+        assert_eq!(
+            outputs.hmac_secret,
+            Some(HmacSecretOutput::Secret(vec![1; 32].into()))
+        );
+        assert_eq!(
+            outputs.cred_blob,
+            Some(CredBlobOutput::Blob(vec![2; 8].into()))
+        );
+        assert_eq!(outputs.hmac_secret_mc, Some(vec![3; 48].into()));
+        assert_eq!(outputs.third_party_payment, Some(false));
+
+        auth_data.extensions = Some(cbor!({ "credProtect" => 4 }).unwrap());
+        assert!(auth_data.parse_extensions().is_err());
+    }
 }
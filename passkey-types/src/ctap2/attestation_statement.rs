@@ -0,0 +1,506 @@
+//! Typed representations of the [attestation statement formats] found in the `attStmt` of an
+//! attestation object.
+//!
+//! [`make_credential::Response::att_stmt`] is a generic CBOR value, these types can be converted
+//! to and from it with [`AttestationStatement::from_value`] and [`AttestationStatement::to_value`].
+//!
+//! [attestation statement formats]: https://w3c.github.io/webauthn/#sctn-defined-attestation-formats
+
+use ciborium::value::Value;
+use coset::iana;
+use der::{asn1::OctetStringRef, Reader, SliceReader, TagMode, TagNumber};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    encoding::{try_from_base64, try_from_base64url},
+    utils::serde::{base64_certificates, i64_to_iana},
+    x509::Certificate,
+    Bytes,
+};
+
+#[cfg(doc)]
+use crate::ctap2::make_credential;
+
+mod tpm;
+
+pub use self::tpm::{TpmAlgId, TpmPublicKey, TpmStatement, TpmsAttest, TpmtPublic};
+
+/// Conversion of an attestation statement to and from its generic CBOR [`Value`].
+pub trait AttestationStatement: Serialize + DeserializeOwned {
+    /// The attestation statement format identifier.
+    const FORMAT: &'static str;
+
+    /// Decode the statement from the `attStmt` of an attestation object.
+    fn from_value(value: &Value) -> Option<Self> {
+        value.deserialized().ok()
+    }
+
+    /// Encode the statement as the `attStmt` of an attestation object.
+    fn to_value(&self) -> Value {
+        // SAFETY: statements only contain types which can always be represented in CBOR.
+        Value::serialized(self).unwrap()
+    }
+}
+
+/// The [Apple Anonymous] attestation statement, produced by Apple platform authenticators.
+///
+/// [Apple Anonymous]: https://w3c.github.io/webauthn/#sctn-apple-anonymous-attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppleStatement {
+    /// The DER encoded certificate chain, starting with the credential certificate which
+    /// certifies the credential public key.
+    pub x5c: Vec<Bytes>,
+}
+
+impl AttestationStatement for AppleStatement {
+    const FORMAT: &'static str = "apple";
+}
+
+impl AppleStatement {
+    /// The OID of the credential certificate extension holding the nonce.
+    pub const NONCE_EXTENSION: &'static str = "1.2.840.113635.100.8.2";
+
+    /// Extract the nonce from a credential certificate's `1.2.840.113635.100.8.2` extension.
+    ///
+    /// It is the SHA-256 of `authData || clientDataHash`, encoded as
+    /// `SEQUENCE { [1] EXPLICIT OCTET STRING }`.
+    pub fn nonce<'a>(credential_certificate: &Certificate<'a>) -> Option<&'a [u8]> {
+        let extension = credential_certificate.extension(Self::NONCE_EXTENSION)?;
+        let mut reader = SliceReader::new(extension).ok()?;
+        let nonce = reader
+            .sequence(|sequence| {
+                sequence.context_specific::<OctetStringRef<'a>>(TagNumber::N1, TagMode::Explicit)
+            })
+            .ok()??;
+        reader.finish(nonce.as_bytes()).ok()
+    }
+}
+
+/// The [Android Key] attestation statement, produced by authenticators backed by the Android
+/// hardware keystore.
+///
+/// [Android Key]: https://w3c.github.io/webauthn/#sctn-android-key-attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AndroidKeyStatement {
+    /// The algorithm of the signature.
+    #[serde(with = "i64_to_iana")]
+    pub alg: iana::Algorithm,
+    /// The signature over `authData || clientDataHash`, made with the credential private key.
+    pub sig: Bytes,
+    /// The DER encoded certificate chain, starting with the certificate for the credential
+    /// public key.
+    pub x5c: Vec<Bytes>,
+}
+
+impl AttestationStatement for AndroidKeyStatement {
+    const FORMAT: &'static str = "android-key";
+}
+
+impl AndroidKeyStatement {
+    /// The OID of the certificate extension holding the [`KeyDescription`].
+    pub const KEY_DESCRIPTION_EXTENSION: &'static str = "1.3.6.1.4.1.11129.2.1.17";
+
+    /// Parse the [`KeyDescription`] from a credential certificate's
+    /// `1.3.6.1.4.1.11129.2.1.17` extension.
+    pub fn key_description(credential_certificate: &Certificate<'_>) -> Option<KeyDescription> {
+        let extension = credential_certificate.extension(Self::KEY_DESCRIPTION_EXTENSION)?;
+        KeyDescription::from_der(extension)
+    }
+}
+
+/// The [key attestation] description of an Android keystore key.
+///
+/// Only the fields needed to verify an `android-key` statement are parsed.
+///
+/// [key attestation]: https://source.android.com/docs/security/features/keystore/attestation#schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDescription {
+    /// The version of the attestation schema.
+    pub attestation_version: i64,
+    /// Where the attestation was produced.
+    pub attestation_security_level: SecurityLevel,
+    /// The challenge given when the key was created, the `clientDataHash` for WebAuthn.
+    pub attestation_challenge: Vec<u8>,
+    /// The authorizations enforced by the Android system.
+    pub software_enforced: AuthorizationList,
+    /// The authorizations enforced by the trusted execution environment.
+    pub tee_enforced: AuthorizationList,
+}
+
+/// The security level of an Android keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityLevel {
+    /// Implemented in the Android system.
+    Software,
+    /// Implemented in a trusted execution environment.
+    TrustedEnvironment,
+    /// Implemented in a dedicated secure element.
+    StrongBox,
+}
+
+/// The subset of a keystore key's authorizations relevant to WebAuthn.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuthorizationList {
+    /// The `purpose` tag, the operations the key may be used for.
+    pub purpose: Vec<i64>,
+    /// Whether the `allApplications` tag is present, allowing any application to use the key.
+    pub all_applications: bool,
+    /// The `origin` tag, where the key was created.
+    pub origin: Option<i64>,
+}
+
+impl AuthorizationList {
+    /// The `KM_PURPOSE_SIGN` value of [`AuthorizationList::purpose`].
+    pub const PURPOSE_SIGN: i64 = 2;
+    /// The `KM_ORIGIN_GENERATED` value of [`AuthorizationList::origin`].
+    pub const ORIGIN_GENERATED: i64 = 0;
+
+    const PURPOSE_TAG: u32 = 1;
+    const ALL_APPLICATIONS_TAG: u32 = 600;
+    const ORIGIN_TAG: u32 = 702;
+
+    fn from_der(mut contents: &[u8]) -> Option<Self> {
+        let mut list = Self::default();
+        while !contents.is_empty() {
+            let field = read_tlv(&mut contents)?;
+            // Every field is explicitly tagged, unwrap it to the inner value.
+            let mut value = field.value;
+            let inner = read_tlv(&mut value)?;
+            match field.number {
+                Self::PURPOSE_TAG => {
+                    let mut purposes = inner.value;
+                    while !purposes.is_empty() {
+                        list.purpose.push(read_integer(&mut purposes)?);
+                    }
+                }
+                Self::ALL_APPLICATIONS_TAG => list.all_applications = true,
+                Self::ORIGIN_TAG => list.origin = Some(integer(inner.value)?),
+                _ => {}
+            }
+        }
+        Some(list)
+    }
+}
+
+impl KeyDescription {
+    /// Parse a DER encoded `KeyDescription`.
+    pub fn from_der(mut der: &[u8]) -> Option<Self> {
+        let sequence = read_tlv(&mut der)?;
+        if !der.is_empty() {
+            return None;
+        }
+        let mut fields = sequence.value;
+        let attestation_version = read_integer(&mut fields)?;
+        let attestation_security_level = match read_integer(&mut fields)? {
+            0 => SecurityLevel::Software,
+            1 => SecurityLevel::TrustedEnvironment,
+            2 => SecurityLevel::StrongBox,
+            _ => return None,
+        };
+        // keymasterVersion and keymasterSecurityLevel
+        read_tlv(&mut fields)?;
+        read_tlv(&mut fields)?;
+        let attestation_challenge = read_tlv(&mut fields)?.value.to_vec();
+        // uniqueId
+        read_tlv(&mut fields)?;
+        let software_enforced = AuthorizationList::from_der(read_tlv(&mut fields)?.value)?;
+        let tee_enforced = AuthorizationList::from_der(read_tlv(&mut fields)?.value)?;
+        Some(Self {
+            attestation_version,
+            attestation_security_level,
+            attestation_challenge,
+            software_enforced,
+            tee_enforced,
+        })
+    }
+}
+
+/// A DER TLV. Keystore authorization lists use tag numbers above 30, which `der` can't decode.
+struct Tlv<'a> {
+    number: u32,
+    value: &'a [u8],
+}
+
+fn read_tlv<'a>(input: &mut &'a [u8]) -> Option<Tlv<'a>> {
+    let (&identifier, mut rest) = input.split_first()?;
+    let mut number = u32::from(identifier & 0x1f);
+    if number == 0x1f {
+        number = 0;
+        loop {
+            let (&byte, remaining) = rest.split_first()?;
+            rest = remaining;
+            number = number.checked_mul(128)? | u32::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    let (&first, mut rest) = rest.split_first()?;
+    let length = if first & 0x80 == 0 {
+        usize::from(first)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > std::mem::size_of::<usize>() || rest.len() < octets {
+            return None;
+        }
+        let (length, remaining) = rest.split_at(octets);
+        rest = remaining;
+        length
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | usize::from(*byte))
+    };
+    if rest.len() < length {
+        return None;
+    }
+    let (value, rest) = rest.split_at(length);
+    *input = rest;
+    Some(Tlv { number, value })
+}
+
+fn read_integer(input: &mut &[u8]) -> Option<i64> {
+    integer(read_tlv(input)?.value)
+}
+
+/// Decode the contents of a DER INTEGER or ENUMERATED.
+fn integer(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let sign = if value[0] & 0x80 == 0 { 0 } else { -1 };
+    Some(
+        value
+            .iter()
+            .fold(sign, |integer: i64, byte| (integer << 8) | i64::from(*byte)),
+    )
+}
+
+/// The [Android SafetyNet] attestation statement, produced by Android devices using the
+/// SafetyNet attestation API.
+///
+/// [Android SafetyNet]: https://w3c.github.io/webauthn/#sctn-android-safetynet-attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AndroidSafetyNetStatement {
+    /// The version of Google Play Services responsible for the attestation.
+    pub ver: String,
+    /// The UTF-8 encoded JWS returned by the SafetyNet API, see [`Self::parse_response`].
+    pub response: Bytes,
+}
+
+impl AttestationStatement for AndroidSafetyNetStatement {
+    const FORMAT: &'static str = "android-safetynet";
+}
+
+impl AndroidSafetyNetStatement {
+    /// Parse the compact JWS [`Self::response`].
+    ///
+    /// This does not verify the JWS signature, which must be checked against
+    /// [`SafetyNetHeader::x5c`] whose leaf certificate is issued to `attest.android.com`.
+    pub fn parse_response(&self) -> Option<SafetyNetResponse> {
+        let jws = std::str::from_utf8(&self.response).ok()?;
+        let (signed_data, signature) = jws.rsplit_once('.')?;
+        let (header, payload) = signed_data.split_once('.')?;
+        Some(SafetyNetResponse {
+            header: serde_json::from_slice(&try_from_base64url(header)?).ok()?,
+            payload: serde_json::from_slice(&try_from_base64url(payload)?).ok()?,
+            signed_data: signed_data.to_owned(),
+            signature: try_from_base64url(signature)?,
+        })
+    }
+}
+
+/// The parsed JWS of an [`AndroidSafetyNetStatement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyNetResponse {
+    /// The JOSE header.
+    pub header: SafetyNetHeader,
+    /// The attestation result.
+    pub payload: SafetyNetPayload,
+    /// The ASCII `header.payload` part of the JWS, over which the signature is made.
+    pub signed_data: String,
+    /// The JWS signature.
+    pub signature: Vec<u8>,
+}
+
+/// The JOSE header of a [`SafetyNetResponse`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetyNetHeader {
+    /// The JWS signing algorithm, usually `RS256`.
+    pub alg: String,
+    /// The DER encoded signing certificate chain, leaf first.
+    #[serde(default, with = "base64_certificates")]
+    pub x5c: Vec<Vec<u8>>,
+}
+
+/// The attestation result of a [`SafetyNetResponse`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyNetPayload {
+    /// The `base64` encoded SHA-256 of `authData || clientDataHash`, see [`Self::nonce_bytes`].
+    pub nonce: String,
+    /// The time the response was generated, in milliseconds since the UNIX epoch.
+    pub timestamp_ms: u64,
+    /// Whether the device passed the Android compatibility checks.
+    #[serde(default)]
+    pub cts_profile_match: bool,
+    /// Whether the device has not been tampered with, a weaker check than
+    /// [`Self::cts_profile_match`].
+    #[serde(default)]
+    pub basic_integrity: bool,
+    /// The package name of the calling app.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apk_package_name: Option<String>,
+}
+
+impl SafetyNetPayload {
+    /// Decode the [`Self::nonce`].
+    pub fn nonce_bytes(&self) -> Option<Vec<u8>> {
+        try_from_base64(&self.nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apple_statement_round_trip() {
+        let statement = AppleStatement {
+            x5c: vec![vec![1, 2, 3].into()],
+        };
+        let value = Value::Map(vec![(
+            Value::Text("x5c".into()),
+            Value::Array(vec![Value::Bytes(vec![1, 2, 3])]),
+        )]);
+        assert_eq!(AppleStatement::from_value(&value), Some(statement.clone()));
+        assert_eq!(
+            AppleStatement::from_value(&statement.to_value()),
+            Some(statement)
+        );
+        assert_eq!(AppleStatement::from_value(&Value::Map(Vec::new())), None);
+    }
+
+    #[test]
+    fn apple_nonce_extension() {
+        let nonce = [7; 32];
+        let extension = [&[0x30, 36, 0xa1, 34, 0x04, 32][..], &nonce].concat();
+        let certificate = Certificate {
+            version: 3,
+            subject: Vec::new(),
+            subject_public_key_info: &[],
+            is_ca: false,
+            aaguid: None,
+            extensions: vec![crate::x509::Extension {
+                oid: AppleStatement::NONCE_EXTENSION.into(),
+                critical: false,
+                value: &extension,
+            }],
+        };
+        assert_eq!(AppleStatement::nonce(&certificate), Some(nonce.as_slice()));
+    }
+
+    #[test]
+    fn android_key_statement_round_trip() {
+        let statement = AndroidKeyStatement {
+            alg: iana::Algorithm::ES256,
+            sig: vec![1, 2, 3].into(),
+            x5c: vec![vec![4, 5, 6].into()],
+        };
+        let value = Value::Map(vec![
+            (Value::Text("alg".into()), Value::Integer((-7).into())),
+            (Value::Text("sig".into()), Value::Bytes(vec![1, 2, 3])),
+            (
+                Value::Text("x5c".into()),
+                Value::Array(vec![Value::Bytes(vec![4, 5, 6])]),
+            ),
+        ]);
+        assert_eq!(
+            AndroidKeyStatement::from_value(&value),
+            Some(statement.clone())
+        );
+        assert_eq!(
+            AndroidKeyStatement::from_value(&statement.to_value()),
+            Some(statement)
+        );
+    }
+
+    #[test]
+    fn parse_key_description() {
+        fn tlv(identifier: &[u8], contents: &[u8]) -> Vec<u8> {
+            [identifier, &[contents.len() as u8], contents].concat()
+        }
+        let software_enforced = tlv(&[0xbf, 0x84, 0x58], &tlv(&[0x05], &[]));
+        let tee_enforced = [
+            tlv(
+                &[0xa1],
+                &tlv(&[0x31], &[tlv(&[0x02], &[2]), tlv(&[0x02], &[3])].concat()),
+            ),
+            tlv(&[0xbf, 0x85, 0x3e], &tlv(&[0x02], &[0])),
+            // An unrelated field, `[704] rootOfTrust`
+            tlv(&[0xbf, 0x85, 0x40], &tlv(&[0x30], &[])),
+        ]
+        .concat();
+        let der = tlv(
+            &[0x30],
+            &[
+                tlv(&[0x02], &[3]),
+                tlv(&[0x0a], &[1]),
+                tlv(&[0x02], &[4]),
+                tlv(&[0x0a], &[1]),
+                tlv(&[0x04], &[9; 32]),
+                tlv(&[0x04], &[]),
+                tlv(&[0x30], &software_enforced),
+                tlv(&[0x30], &tee_enforced),
+            ]
+            .concat(),
+        );
+
+        let description = KeyDescription::from_der(&der).expect("failed to parse description");
+        assert_eq!(
+            description,
+            KeyDescription {
+                attestation_version: 3,
+                attestation_security_level: SecurityLevel::TrustedEnvironment,
+                attestation_challenge: vec![9; 32],
+                software_enforced: AuthorizationList {
+                    all_applications: true,
+                    ..Default::default()
+                },
+                tee_enforced: AuthorizationList {
+                    purpose: vec![AuthorizationList::PURPOSE_SIGN, 3],
+                    all_applications: false,
+                    origin: Some(AuthorizationList::ORIGIN_GENERATED),
+                },
+            }
+        );
+        assert_eq!(KeyDescription::from_der(&der[..der.len() - 1]), None);
+    }
+
+    #[test]
+    fn parse_safetynet_response() {
+        use crate::encoding::base64url;
+
+        let header = base64url(br#"{"alg":"RS256","x5c":["AQID"]}"#);
+        let payload = base64url(
+            br#"{"nonce":"BwcH","timestampMs":1700000000000,"ctsProfileMatch":true,"basicIntegrity":true}"#,
+        );
+        let statement = AndroidSafetyNetStatement {
+            ver: "19.0.0".into(),
+            response: format!("{header}.{payload}.{}", base64url(&[4, 5]))
+                .into_bytes()
+                .into(),
+        };
+        assert_eq!(
+            AndroidSafetyNetStatement::from_value(&statement.to_value()),
+            Some(statement.clone())
+        );
+
+        let response = statement
+            .parse_response()
+            .expect("failed to parse response");
+        assert_eq!(response.header.x5c, vec![vec![1, 2, 3]]);
+        assert_eq!(response.payload.nonce_bytes(), Some(vec![7, 7, 7]));
+        assert!(response.payload.cts_profile_match);
+        assert_eq!(response.signed_data, format!("{header}.{payload}"));
+        assert_eq!(response.signature, vec![4, 5]);
+    }
+}
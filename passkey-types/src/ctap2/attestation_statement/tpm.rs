@@ -0,0 +1,403 @@
+use coset::iana;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::AttestationStatement;
+use crate::{utils::serde::i64_to_iana, Bytes};
+
+/// The [TPM] attestation statement, produced by authenticators backed by a TPM 2.0.
+///
+/// [TPM]: https://w3c.github.io/webauthn/#sctn-tpm-attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TpmStatement {
+    /// The version of the TPM specification, always [`TpmStatement::VERSION`].
+    pub ver: String,
+    /// The algorithm of the signature.
+    #[serde(with = "i64_to_iana")]
+    pub alg: iana::Algorithm,
+    /// The DER encoded certificate chain, starting with the AIK certificate.
+    pub x5c: Vec<Bytes>,
+    /// The signature over [`TpmStatement::cert_info`], made with the AIK.
+    pub sig: Bytes,
+    /// The `TPMS_ATTEST` structure signed by the AIK, see [`TpmsAttest`].
+    #[serde(rename = "certInfo")]
+    pub cert_info: Bytes,
+    /// The `TPMT_PUBLIC` structure of the credential public key, see [`TpmtPublic`].
+    #[serde(rename = "pubArea")]
+    pub pub_area: Bytes,
+}
+
+impl AttestationStatement for TpmStatement {
+    const FORMAT: &'static str = "tpm";
+}
+
+impl TpmStatement {
+    /// The only supported [`TpmStatement::ver`].
+    pub const VERSION: &'static str = "2.0";
+
+    /// The `tcg-kp-AIKCertificate` extended key usage required of the AIK certificate.
+    pub const AIK_CERTIFICATE_KEY_USAGE: &'static str = "2.23.133.8.3";
+
+    /// Parse the [`TpmStatement::pub_area`].
+    pub fn parse_pub_area(&self) -> Option<TpmtPublic> {
+        TpmtPublic::from_bytes(&self.pub_area)
+    }
+
+    /// Parse the [`TpmStatement::cert_info`].
+    pub fn parse_cert_info(&self) -> Option<TpmsAttest> {
+        TpmsAttest::from_bytes(&self.cert_info)
+    }
+
+    /// The TPM name of the [`TpmStatement::pub_area`], its name algorithm followed by its digest
+    /// with that algorithm.
+    ///
+    /// This is what [`TpmsAttest::certified_name`] must be equal to. Returns `None` if the
+    /// public area is invalid or its name algorithm is not supported.
+    pub fn pub_area_name(&self) -> Option<Vec<u8>> {
+        let name_alg = self.parse_pub_area()?.name_alg;
+        let digest = match name_alg {
+            TpmAlgId::SHA256 => Sha256::digest(self.pub_area.as_slice()).to_vec(),
+            TpmAlgId::SHA384 => Sha384::digest(self.pub_area.as_slice()).to_vec(),
+            TpmAlgId::SHA512 => Sha512::digest(self.pub_area.as_slice()).to_vec(),
+            _ => return None,
+        };
+        Some([name_alg.0.to_be_bytes().as_slice(), &digest].concat())
+    }
+}
+
+/// A `TPM_ALG_ID`, identifying an algorithm in TPM structures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TpmAlgId(pub u16);
+
+impl TpmAlgId {
+    /// `TPM_ALG_RSA`
+    pub const RSA: Self = Self(0x0001);
+    /// `TPM_ALG_SHA1`
+    pub const SHA1: Self = Self(0x0004);
+    /// `TPM_ALG_SHA256`
+    pub const SHA256: Self = Self(0x000b);
+    /// `TPM_ALG_SHA384`
+    pub const SHA384: Self = Self(0x000c);
+    /// `TPM_ALG_SHA512`
+    pub const SHA512: Self = Self(0x000d);
+    /// `TPM_ALG_NULL`
+    pub const NULL: Self = Self(0x0010);
+    /// `TPM_ALG_ECC`
+    pub const ECC: Self = Self(0x0023);
+}
+
+/// A `TPMT_PUBLIC` structure, describing the public part of a TPM object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpmtPublic {
+    /// The algorithm used to compute the object's name.
+    pub name_alg: TpmAlgId,
+    /// The `TPMA_OBJECT` attributes of the object.
+    pub object_attributes: u32,
+    /// The policy required to use the object, if any.
+    pub auth_policy: Vec<u8>,
+    /// The public key of the object.
+    pub public_key: TpmPublicKey,
+}
+
+/// The algorithm specific parameters and unique identifier of a [`TpmtPublic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TpmPublicKey {
+    /// A `TPM_ALG_RSA` key.
+    Rsa {
+        /// The signing scheme of the key, [`TpmAlgId::NULL`] if unrestricted.
+        scheme: TpmAlgId,
+        /// The size of the modulus in bits.
+        key_bits: u16,
+        /// The public exponent, `0` for the default of 65537.
+        exponent: u32,
+        /// The big endian modulus.
+        modulus: Vec<u8>,
+    },
+    /// A `TPM_ALG_ECC` key.
+    Ecc {
+        /// The signing scheme of the key, [`TpmAlgId::NULL`] if unrestricted.
+        scheme: TpmAlgId,
+        /// The `TPM_ECC_CURVE` of the key, see [`TpmPublicKey::ECC_NIST_P256`].
+        curve: u16,
+        /// The key derivation function of the key, usually [`TpmAlgId::NULL`].
+        kdf: TpmAlgId,
+        /// The big endian x coordinate.
+        x: Vec<u8>,
+        /// The big endian y coordinate.
+        y: Vec<u8>,
+    },
+}
+
+impl TpmPublicKey {
+    /// `TPM_ECC_NIST_P256`
+    pub const ECC_NIST_P256: u16 = 0x0003;
+    /// `TPM_ECC_NIST_P384`
+    pub const ECC_NIST_P384: u16 = 0x0004;
+    /// `TPM_ECC_NIST_P521`
+    pub const ECC_NIST_P521: u16 = 0x0005;
+}
+
+impl TpmtPublic {
+    /// Parse a marshalled `TPMT_PUBLIC`. Only signing keys of type RSA or ECC are supported.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut cursor = Cursor(bytes);
+        let ty = TpmAlgId(cursor.u16()?);
+        let name_alg = TpmAlgId(cursor.u16()?);
+        let object_attributes = cursor.u32()?;
+        let auth_policy = cursor.tpm2b()?.to_vec();
+        // Signing keys have no symmetric algorithm, whose parameters would follow otherwise.
+        if TpmAlgId(cursor.u16()?) != TpmAlgId::NULL {
+            return None;
+        }
+        let public_key = match ty {
+            TpmAlgId::RSA => {
+                let scheme = cursor.scheme()?;
+                let key_bits = cursor.u16()?;
+                let exponent = cursor.u32()?;
+                let modulus = cursor.tpm2b()?.to_vec();
+                TpmPublicKey::Rsa {
+                    scheme,
+                    key_bits,
+                    exponent,
+                    modulus,
+                }
+            }
+            TpmAlgId::ECC => {
+                let scheme = cursor.scheme()?;
+                let curve = cursor.u16()?;
+                let kdf = cursor.scheme()?;
+                let x = cursor.tpm2b()?.to_vec();
+                let y = cursor.tpm2b()?.to_vec();
+                TpmPublicKey::Ecc {
+                    scheme,
+                    curve,
+                    kdf,
+                    x,
+                    y,
+                }
+            }
+            _ => return None,
+        };
+        cursor.finish(Self {
+            name_alg,
+            object_attributes,
+            auth_policy,
+            public_key,
+        })
+    }
+}
+
+/// A `TPMS_ATTEST` structure of type `TPM_ST_ATTEST_CERTIFY`, in which the TPM certifies that
+/// it holds an object with a given name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpmsAttest {
+    /// Always [`TpmsAttest::TPM_GENERATED_VALUE`] for structures created by a TPM.
+    pub magic: u32,
+    /// The name of the key which signed the structure.
+    pub qualified_signer: Vec<u8>,
+    /// Data supplied by the caller, the hash of `authData || clientDataHash` for WebAuthn.
+    pub extra_data: Vec<u8>,
+    /// The TPM clock, in milliseconds.
+    pub clock: u64,
+    /// The number of TPM resets.
+    pub reset_count: u32,
+    /// The number of TPM restarts since the last reset.
+    pub restart_count: u32,
+    /// Whether the clock has not been set back since it was last reported.
+    pub safe: bool,
+    /// The firmware version of the TPM.
+    pub firmware_version: u64,
+    /// The name of the certified object, see [`TpmStatement::pub_area_name`].
+    pub certified_name: Vec<u8>,
+    /// The qualified name of the certified object.
+    pub certified_qualified_name: Vec<u8>,
+}
+
+impl TpmsAttest {
+    /// `TPM_GENERATED_VALUE`, the magic number of TPM generated structures.
+    pub const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+    /// `TPM_ST_ATTEST_CERTIFY`, the only structure type that is parsed.
+    pub const TPM_ST_ATTEST_CERTIFY: u16 = 0x8017;
+
+    /// Parse a marshalled `TPMS_ATTEST`. Returns `None` if it does not certify an object.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut cursor = Cursor(bytes);
+        let magic = cursor.u32()?;
+        if cursor.u16()? != Self::TPM_ST_ATTEST_CERTIFY {
+            return None;
+        }
+        let attest = Self {
+            magic,
+            qualified_signer: cursor.tpm2b()?.to_vec(),
+            extra_data: cursor.tpm2b()?.to_vec(),
+            clock: cursor.u64()?,
+            reset_count: cursor.u32()?,
+            restart_count: cursor.u32()?,
+            safe: cursor.u8()? != 0,
+            firmware_version: cursor.u64()?,
+            certified_name: cursor.tpm2b()?.to_vec(),
+            certified_qualified_name: cursor.tpm2b()?.to_vec(),
+        };
+        cursor.finish(attest)
+    }
+}
+
+/// Reads the big endian fields of a marshalled TPM structure.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take().map(u8::from_be_bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_be_bytes)
+    }
+
+    /// A `TPM2B` sized buffer.
+    fn tpm2b(&mut self) -> Option<&'a [u8]> {
+        let size = usize::from(self.u16()?);
+        if self.0.len() < size {
+            return None;
+        }
+        let (buffer, rest) = self.0.split_at(size);
+        self.0 = rest;
+        Some(buffer)
+    }
+
+    /// A `TPMT_*_SCHEME`, whose details are only present if it is not [`TpmAlgId::NULL`].
+    fn scheme(&mut self) -> Option<TpmAlgId> {
+        let scheme = TpmAlgId(self.u16()?);
+        if scheme != TpmAlgId::NULL {
+            // The hash algorithm of the scheme
+            self.u16()?;
+        }
+        Some(scheme)
+    }
+
+    fn finish<T>(self, value: T) -> Option<T> {
+        self.0.is_empty().then_some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tpm2b(buffer: &[u8]) -> Vec<u8> {
+        [&(buffer.len() as u16).to_be_bytes(), buffer].concat()
+    }
+
+    #[test]
+    fn parse_ecc_pub_area() {
+        let pub_area = [
+            &[0x00, 0x23, 0x00, 0x0b][..],
+            &0x0006_0472u32.to_be_bytes(),
+            &tpm2b(&[]),
+            &[0x00, 0x10, 0x00, 0x18, 0x00, 0x0b, 0x00, 0x03, 0x00, 0x10],
+            &tpm2b(&[1; 32]),
+            &tpm2b(&[2; 32]),
+        ]
+        .concat();
+        let parsed = TpmtPublic::from_bytes(&pub_area).expect("failed to parse pubArea");
+        assert_eq!(parsed.name_alg, TpmAlgId::SHA256);
+        assert_eq!(
+            parsed.public_key,
+            TpmPublicKey::Ecc {
+                scheme: TpmAlgId(0x0018),
+                curve: TpmPublicKey::ECC_NIST_P256,
+                kdf: TpmAlgId::NULL,
+                x: vec![1; 32],
+                y: vec![2; 32],
+            }
+        );
+        assert_eq!(
+            TpmtPublic::from_bytes(&pub_area[..pub_area.len() - 1]),
+            None
+        );
+
+        let statement = TpmStatement {
+            ver: TpmStatement::VERSION.into(),
+            alg: iana::Algorithm::ES256,
+            x5c: Vec::new(),
+            sig: Vec::new().into(),
+            cert_info: Vec::new().into(),
+            pub_area: pub_area.clone().into(),
+        };
+        let name = statement.pub_area_name().unwrap();
+        assert_eq!(name[..2], [0x00, 0x0b]);
+        assert_eq!(name[2..], Sha256::digest(&pub_area)[..]);
+    }
+
+    #[test]
+    fn parse_rsa_pub_area() {
+        let pub_area = [
+            &[0x00, 0x01, 0x00, 0x0b][..],
+            &0x0006_0472u32.to_be_bytes(),
+            &tpm2b(&[]),
+            &[0x00, 0x10, 0x00, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &tpm2b(&[3; 256]),
+        ]
+        .concat();
+        assert_eq!(
+            TpmtPublic::from_bytes(&pub_area).unwrap().public_key,
+            TpmPublicKey::Rsa {
+                scheme: TpmAlgId::NULL,
+                key_bits: 2048,
+                exponent: 0,
+                modulus: vec![3; 256],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_cert_info() {
+        let cert_info = [
+            &TpmsAttest::TPM_GENERATED_VALUE.to_be_bytes()[..],
+            &TpmsAttest::TPM_ST_ATTEST_CERTIFY.to_be_bytes(),
+            &tpm2b(&[4; 34]),
+            &tpm2b(&[5; 32]),
+            &7u64.to_be_bytes(),
+            &1u32.to_be_bytes(),
+            &2u32.to_be_bytes(),
+            &[1],
+            &9u64.to_be_bytes(),
+            &tpm2b(&[6; 34]),
+            &tpm2b(&[8; 34]),
+        ]
+        .concat();
+        assert_eq!(
+            TpmsAttest::from_bytes(&cert_info),
+            Some(TpmsAttest {
+                magic: TpmsAttest::TPM_GENERATED_VALUE,
+                qualified_signer: vec![4; 34],
+                extra_data: vec![5; 32],
+                clock: 7,
+                reset_count: 1,
+                restart_count: 2,
+                safe: true,
+                firmware_version: 9,
+                certified_name: vec![6; 34],
+                certified_qualified_name: vec![8; 34],
+            })
+        );
+
+        let mut quote = cert_info;
+        quote[4..6].copy_from_slice(&0x8018u16.to_be_bytes());
+        assert_eq!(TpmsAttest::from_bytes(&quote), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorConfig>
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ctap2::Command, Bytes};
+
+serde_workaround! {
+    /// Request to the authenticator to change its configuration with one of the [`Subcommand`]s.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        /// The configuration operation to perform.
+        #[serde(rename = 0x01)]
+        pub sub_command: Subcommand,
+
+        /// The parameters of [`Subcommand::SetMinPinLength`].
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub sub_command_params: Option<SetMinPinLengthParams>,
+
+        /// The PIN/UV auth protocol version used to compute `pin_uv_auth_param`.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_protocol: Option<u8>,
+
+        /// The output of the PIN/UV auth protocol's `authenticate` function over
+        /// [`Request::pin_uv_auth_message`] with a PIN/UV auth token which has the `acfg`
+        /// permission.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_param: Option<Bytes>,
+    }
+}
+
+impl Request {
+    /// The message authenticated by `pin_uv_auth_param`: 32 bytes of `0xff`, the command byte,
+    /// the subcommand and the CBOR encoding of its parameters if there are any.
+    pub fn pin_uv_auth_message(&self) -> Vec<u8> {
+        let mut message = vec![0xff; 32];
+        message.push(Command::Config.into());
+        message.push(self.sub_command.into());
+        if let Some(params) = &self.sub_command_params {
+            // SAFETY: writing to a Vec does not fail and the parameters are always representable
+            ciborium::ser::into_writer(params, &mut message).unwrap();
+        }
+        message
+    }
+}
+
+serde_workaround! {
+    /// The parameters of [`Subcommand::SetMinPinLength`].
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct SetMinPinLengthParams {
+        /// The new minimum PIN length in Unicode code points. It may only ever increase. When
+        /// absent, the current minimum is kept.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub new_min_pin_length: Option<u8>,
+
+        /// The RP IDs allowed to read the minimum PIN length through the `minPinLength` extension.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub min_pin_length_rp_ids: Option<Vec<String>>,
+
+        /// Whether the PIN must be changed before it can be used to get a PIN/UV auth token.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub force_change_pin: Option<bool>,
+    }
+}
+
+/// The operations of the `authenticatorConfig` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Subcommand {
+    /// Enable enterprise attestation.
+    EnableEnterpriseAttestation = 0x01,
+    /// Toggle whether user verification is always required.
+    ToggleAlwaysUv = 0x02,
+    /// Raise the minimum PIN length and optionally force a PIN change.
+    SetMinPinLength = 0x03,
+    /// Vendor specific configuration, for prototyping only.
+    VendorPrototype = 0xFF,
+}
+
+impl From<Subcommand> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: Subcommand) -> Self {
+        src as u8
+    }
+}
+
+impl TryFrom<u8> for Subcommand {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Subcommand::EnableEnterpriseAttestation),
+            0x02 => Ok(Subcommand::ToggleAlwaysUv),
+            0x03 => Ok(Subcommand::SetMinPinLength),
+            0xFF => Ok(Subcommand::VendorPrototype),
+            other => Err(other),
+        }
+    }
+}
+
+impl Serialize for Subcommand {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Subcommand {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+        Subcommand::try_from(value).map_err(|value| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(value.into()),
+                &"an authenticatorConfig subcommand",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::{cbor, value::Value};
+
+    use super::*;
+
+    #[test]
+    fn deserialize_request() {
+        let value = cbor!({
+            0x01 => 3,
+            0x02 => { 0x01 => 6, 0x03 => true },
+            0x03 => 2,
+            0x04 => Value::Bytes(vec![1; 32]),
+        })
+        .unwrap();
+
+        let request: Request = value.deserialized().expect("failed to deserialize request");
+        assert_eq!(request.sub_command, Subcommand::SetMinPinLength);
+        assert_eq!(
+            request.sub_command_params,
+            Some(SetMinPinLengthParams {
+                new_min_pin_length: Some(6),
+                min_pin_length_rp_ids: None,
+                force_change_pin: Some(true),
+            })
+        );
+        assert_eq!(request.pin_uv_auth_protocol, Some(2));
+        assert_eq!(request.pin_uv_auth_param, Some(vec![1; 32].into()));
+    }
+
+    #[test]
+    fn pin_uv_auth_message() {
+        let request = Request {
+            sub_command: Subcommand::SetMinPinLength,
+            sub_command_params: Some(SetMinPinLengthParams {
+                new_min_pin_length: Some(6),
+                ..Default::default()
+            }),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        let mut expected = vec![0xff; 32];
+        // 0x0d, 0x03, then { 0x01: 6 } in CBOR
+        expected.extend([0x0d, 0x03, 0xa1, 0x01, 0x06]);
+        assert_eq!(request.pin_uv_auth_message(), expected);
+
+        let request = Request {
+            sub_command_params: None,
+            ..request
+        };
+        assert_eq!(request.pin_uv_auth_message()[32..], [0x0d, 0x03]);
+    }
+}
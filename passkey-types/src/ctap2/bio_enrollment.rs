@@ -0,0 +1,322 @@
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorBioEnrollment>
+
+use serde::{Deserialize, Serialize};
+
+use crate::Bytes;
+
+/// The only user verification modality defined for `authenticatorBioEnrollment`.
+pub const MODALITY_FINGERPRINT: u8 = 0x01;
+
+serde_workaround! {
+    /// Request to the authenticator to manage its biometric enrollments with one of the
+    /// [`Subcommand`]s, or to get the modality it supports.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        /// The user verification modality of the enrollments, [`MODALITY_FINGERPRINT`].
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub modality: Option<u8>,
+
+        /// The bio enrollment operation to perform.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub sub_command: Option<Subcommand>,
+
+        /// The parameters of the subcommands which act on an enrollment.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub sub_command_params: Option<SubcommandParams>,
+
+        /// The PIN/UV auth protocol version used to compute `pin_uv_auth_param`.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_protocol: Option<u8>,
+
+        /// The output of the PIN/UV auth protocol's `authenticate` function over
+        /// [`Request::pin_uv_auth_message`] with a PIN/UV auth token which has the `be`
+        /// permission.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_param: Option<Bytes>,
+
+        /// Get the supported modality instead of performing a subcommand.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub get_modality: Option<bool>,
+    }
+}
+
+impl Request {
+    /// The message authenticated by `pin_uv_auth_param`: the modality and the subcommand followed
+    /// by the CBOR encoding of its parameters if there are any.
+    pub fn pin_uv_auth_message(&self) -> Vec<u8> {
+        let mut message = vec![self.modality.unwrap_or_default()];
+        message.extend(self.sub_command.map(u8::from));
+        if let Some(params) = &self.sub_command_params {
+            // SAFETY: writing to a Vec does not fail and the parameters are always representable
+            ciborium::ser::into_writer(params, &mut message).unwrap();
+        }
+        message
+    }
+}
+
+serde_workaround! {
+    /// The parameters of the [`Subcommand`]s which act on an enrollment.
+    #[derive(Debug, Default, Clone)]
+    pub struct SubcommandParams {
+        /// The ID of the enrollment.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub template_id: Option<Bytes>,
+
+        /// The name of the enrollment to show to the user.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub template_friendly_name: Option<String>,
+
+        /// How long to wait for the user to present a sample.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub timeout_milliseconds: Option<u32>,
+    }
+}
+
+serde_workaround! {
+    /// Response of a bio enrollment [`Subcommand`]. Only the fields relevant to the subcommand
+    /// are set.
+    #[derive(Debug, Default)]
+    pub struct Response {
+        /// The user verification modality supported by the authenticator.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub modality: Option<u8>,
+
+        /// The [`FingerprintKind`] of the authenticator's sensor.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub fingerprint_kind: Option<u8>,
+
+        /// The number of good samples needed to complete an enrollment.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub max_capture_samples_required_for_enroll: Option<u32>,
+
+        /// The ID of the enrollment which was started.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub template_id: Option<Bytes>,
+
+        /// The [`LastEnrollSampleStatus`] of the sample which was just captured.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub last_enroll_sample_status: Option<u8>,
+
+        /// The number of good samples still needed to complete the enrollment.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub remaining_samples: Option<u32>,
+
+        /// Every enrollment of the authenticator.
+        #[serde(rename = 0x07, default, skip_serializing_if = Option::is_none)]
+        pub template_infos: Option<Vec<TemplateInfo>>,
+
+        /// The maximum length in bytes of the name of an enrollment.
+        #[serde(rename = 0x08, default, skip_serializing_if = Option::is_none)]
+        pub max_template_friendly_name: Option<u32>,
+    }
+}
+
+serde_workaround! {
+    /// An enrollment of the authenticator, as listed by [`Subcommand::EnumerateEnrollments`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TemplateInfo {
+        /// The ID of the enrollment.
+        #[serde(rename = 0x01)]
+        pub template_id: Bytes,
+
+        /// The name of the enrollment, if it was given one.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub template_friendly_name: Option<String>,
+    }
+}
+
+/// The kind of fingerprint sensor of an authenticator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FingerprintKind {
+    /// The finger is placed on the sensor.
+    Touch = 0x01,
+    /// The finger is swiped across the sensor.
+    Swipe = 0x02,
+}
+
+impl From<FingerprintKind> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: FingerprintKind) -> Self {
+        src as u8
+    }
+}
+
+/// The outcome of capturing a fingerprint sample during an enrollment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LastEnrollSampleStatus {
+    /// The sample was good.
+    Good = 0x00,
+    /// The fingerprint was too high.
+    TooHigh = 0x01,
+    /// The fingerprint was too low.
+    TooLow = 0x02,
+    /// The fingerprint was too far left.
+    TooLeft = 0x03,
+    /// The fingerprint was too far right.
+    TooRight = 0x04,
+    /// The finger was moved too fast.
+    TooFast = 0x05,
+    /// The finger was moved too slowly.
+    TooSlow = 0x06,
+    /// The sample was of poor quality.
+    PoorQuality = 0x07,
+    /// The finger was too skewed.
+    TooSkewed = 0x08,
+    /// The finger was too short.
+    TooShort = 0x09,
+    /// The sample could not be merged with the previous ones.
+    MergeFailure = 0x0A,
+    /// The fingerprint is already enrolled.
+    Exists = 0x0B,
+    /// No user activity was detected.
+    NoUserActivity = 0x0D,
+    /// The user did not present a finger in time.
+    NoUserPresenceTransition = 0x0E,
+}
+
+impl From<LastEnrollSampleStatus> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: LastEnrollSampleStatus) -> Self {
+        src as u8
+    }
+}
+
+/// The operations of the `authenticatorBioEnrollment` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Subcommand {
+    /// Start a new enrollment and capture its first sample.
+    EnrollBegin = 0x01,
+    /// Capture the next sample of the enrollment in progress.
+    EnrollCaptureNextSample = 0x02,
+    /// Cancel the enrollment in progress.
+    CancelCurrentEnrollment = 0x03,
+    /// List every enrollment.
+    EnumerateEnrollments = 0x04,
+    /// Name an enrollment.
+    SetFriendlyName = 0x05,
+    /// Delete an enrollment.
+    RemoveEnrollment = 0x06,
+    /// Get the kind of sensor and how many samples an enrollment needs.
+    GetFingerprintSensorInfo = 0x07,
+}
+
+impl Subcommand {
+    /// Whether this subcommand must be authenticated with a PIN/UV auth token.
+    pub fn requires_pin_uv_auth(&self) -> bool {
+        !matches!(
+            self,
+            Subcommand::CancelCurrentEnrollment | Subcommand::GetFingerprintSensorInfo
+        )
+    }
+}
+
+impl From<Subcommand> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: Subcommand) -> Self {
+        src as u8
+    }
+}
+
+impl TryFrom<u8> for Subcommand {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Subcommand::EnrollBegin),
+            0x02 => Ok(Subcommand::EnrollCaptureNextSample),
+            0x03 => Ok(Subcommand::CancelCurrentEnrollment),
+            0x04 => Ok(Subcommand::EnumerateEnrollments),
+            0x05 => Ok(Subcommand::SetFriendlyName),
+            0x06 => Ok(Subcommand::RemoveEnrollment),
+            0x07 => Ok(Subcommand::GetFingerprintSensorInfo),
+            other => Err(other),
+        }
+    }
+}
+
+impl Serialize for Subcommand {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Subcommand {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+        Subcommand::try_from(value).map_err(|value| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(value.into()),
+                &"an authenticatorBioEnrollment subcommand",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::{cbor, value::Value};
+
+    use super::*;
+
+    #[test]
+    fn deserialize_request() {
+        let value = cbor!({
+            0x01 => 1,
+            0x02 => 5,
+            0x03 => {
+                0x01 => Value::Bytes(vec![7; 4]),
+                0x02 => "Right thumb",
+            },
+            0x04 => 2,
+            0x05 => Value::Bytes(vec![1; 32]),
+        })
+        .unwrap();
+
+        let request: Request = value.deserialized().expect("failed to deserialize request");
+        assert_eq!(request.modality, Some(MODALITY_FINGERPRINT));
+        assert_eq!(request.sub_command, Some(Subcommand::SetFriendlyName));
+        let params = request.sub_command_params.expect("missing params");
+        assert_eq!(params.template_id, Some(vec![7; 4].into()));
+        assert_eq!(
+            params.template_friendly_name.as_deref(),
+            Some("Right thumb")
+        );
+        assert!(params.timeout_milliseconds.is_none());
+        assert_eq!(request.get_modality, None);
+    }
+
+    #[test]
+    fn pin_uv_auth_message() {
+        let request = Request {
+            modality: Some(MODALITY_FINGERPRINT),
+            sub_command: Some(Subcommand::RemoveEnrollment),
+            sub_command_params: Some(SubcommandParams {
+                template_id: Some(vec![0xaa; 2].into()),
+                ..Default::default()
+            }),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+            get_modality: None,
+        };
+        let mut expected = vec![0x01, 0x06];
+        ciborium::ser::into_writer(request.sub_command_params.as_ref().unwrap(), &mut expected)
+            .unwrap();
+        assert_eq!(request.pin_uv_auth_message(), expected);
+
+        let request = Request {
+            sub_command: Some(Subcommand::EnumerateEnrollments),
+            sub_command_params: None,
+            ..request
+        };
+        assert_eq!(request.pin_uv_auth_message(), [0x01, 0x04]);
+    }
+}
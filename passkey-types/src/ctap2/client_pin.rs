@@ -0,0 +1,204 @@
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorClientPIN>
+
+use coset::CoseKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{utils::serde::cose_key_opt, Bytes};
+
+serde_workaround! {
+    /// Request to the authenticator to perform one of the PIN [`Subcommand`]s.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        /// The PIN/UV auth protocol version chosen by the client.
+        #[serde(rename = 0x01)]
+        pub pin_protocol: u8,
+
+        /// The PIN operation to perform.
+        #[serde(rename = 0x02)]
+        pub sub_command: Subcommand,
+
+        /// The public key of the platform's key agreement key, used to derive the shared secret
+        /// with the authenticator.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none, serialize_with = cose_key_opt::serialize, deserialize_with = cose_key_opt::deserialize)]
+        pub key_agreement: Option<CoseKey>,
+
+        /// The output of the PIN protocol's `authenticate` function over the subcommand's
+        /// parameters, proving knowledge of the shared secret.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub pin_auth: Option<Bytes>,
+
+        /// The new PIN, padded with zeros to 64 bytes and encrypted with the shared secret.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub new_pin_enc: Option<Bytes>,
+
+        /// The first 16 bytes of the SHA-256 hash of the current PIN, encrypted with the shared
+        /// secret.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub pin_hash_enc: Option<Bytes>,
+
+        /// The permissions requested for the PIN/UV auth token, as the bits of
+        /// [`Permissions`](super::Permissions).
+        #[serde(rename = 0x09, default, skip_serializing_if = Option::is_none)]
+        pub permissions: Option<u8>,
+
+        /// The RP ID to which the permissions of the PIN/UV auth token are restricted.
+        #[serde(rename = 0x0A, default, skip_serializing_if = Option::is_none)]
+        pub rp_id: Option<String>,
+    }
+}
+
+serde_workaround! {
+    /// Response to a [`Request`], only the fields relevant to the subcommand are present.
+    #[derive(Debug, Default, Clone)]
+    pub struct Response {
+        /// The public key of the authenticator's key agreement key, returned by
+        /// [`Subcommand::GetKeyAgreement`].
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none, serialize_with = cose_key_opt::serialize, deserialize_with = cose_key_opt::deserialize)]
+        pub key_agreement: Option<CoseKey>,
+
+        /// The PIN token encrypted with the shared secret, returned by [`Subcommand::GetPinToken`],
+        /// [`Subcommand::GetPinUvAuthTokenUsingUvWithPermissions`] and
+        /// [`Subcommand::GetPinUvAuthTokenUsingPinWithPermissions`].
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub pin_token: Option<Bytes>,
+
+        /// The number of PIN attempts remaining before the authenticator is blocked, returned by
+        /// [`Subcommand::GetRetries`].
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub retries: Option<u8>,
+
+        /// Whether the authenticator must be power cycled before the PIN can be tried again,
+        /// returned by [`Subcommand::GetRetries`].
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub power_cycle_state: Option<bool>,
+
+        /// The number of built-in user verification attempts remaining before it is blocked,
+        /// returned by [`Subcommand::GetUvRetries`].
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub uv_retries: Option<u8>,
+    }
+}
+
+/// The operations of the `authenticatorClientPIN` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Subcommand {
+    /// Get the number of PIN attempts remaining.
+    GetRetries = 0x01,
+    /// Get the authenticator's key agreement public key.
+    GetKeyAgreement = 0x02,
+    /// Set the PIN when none has been set yet.
+    SetPin = 0x03,
+    /// Change the current PIN.
+    ChangePin = 0x04,
+    /// Get a PIN token by proving knowledge of the PIN.
+    GetPinToken = 0x05,
+    /// Get a PIN/UV auth token with specific permissions through the authenticator's built-in
+    /// user verification.
+    GetPinUvAuthTokenUsingUvWithPermissions = 0x06,
+    /// Get the number of built-in user verification attempts remaining.
+    GetUvRetries = 0x07,
+    /// Get a PIN/UV auth token with specific permissions by proving knowledge of the PIN.
+    GetPinUvAuthTokenUsingPinWithPermissions = 0x09,
+}
+
+impl From<Subcommand> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: Subcommand) -> Self {
+        src as u8
+    }
+}
+
+impl TryFrom<u8> for Subcommand {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Subcommand::GetRetries),
+            0x02 => Ok(Subcommand::GetKeyAgreement),
+            0x03 => Ok(Subcommand::SetPin),
+            0x04 => Ok(Subcommand::ChangePin),
+            0x05 => Ok(Subcommand::GetPinToken),
+            0x06 => Ok(Subcommand::GetPinUvAuthTokenUsingUvWithPermissions),
+            0x07 => Ok(Subcommand::GetUvRetries),
+            0x09 => Ok(Subcommand::GetPinUvAuthTokenUsingPinWithPermissions),
+            other => Err(other),
+        }
+    }
+}
+
+impl Serialize for Subcommand {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Subcommand {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+        Subcommand::try_from(value).map_err(|value| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(value.into()),
+                &"an authenticatorClientPIN subcommand",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::{cbor, value::Value};
+    use coset::{iana, CoseKeyBuilder};
+
+    use super::*;
+
+    #[test]
+    fn deserialize_request() {
+        let key =
+            CoseKeyBuilder::new_ec2_pub_key(iana::EllipticCurve::P_256, vec![1; 32], vec![2; 32])
+                .algorithm(iana::Algorithm::ECDH_ES_HKDF_256)
+                .build();
+        let value = cbor!({
+            0x01 => 1,
+            0x02 => 5,
+            0x03 => coset::AsCborValue::to_cbor_value(key.clone()).unwrap(),
+            0x06 => Value::Bytes(vec![3; 16]),
+            0x0A => "example.com",
+        })
+        .unwrap();
+
+        let request: Request = value.deserialized().expect("failed to deserialize request");
+        assert_eq!(request.pin_protocol, 1);
+        assert_eq!(request.sub_command, Subcommand::GetPinToken);
+        assert_eq!(request.key_agreement, Some(key));
+        assert_eq!(request.pin_hash_enc, Some(vec![3; 16].into()));
+        assert_eq!(request.new_pin_enc, None);
+        assert_eq!(request.permissions, None);
+        assert_eq!(request.rp_id.as_deref(), Some("example.com"));
+
+        let round_trip: Request = Value::serialized(&request)
+            .and_then(|value| value.deserialized())
+            .expect("failed to round trip request");
+        assert_eq!(round_trip.key_agreement, request.key_agreement);
+        assert_eq!(round_trip.pin_hash_enc, request.pin_hash_enc);
+
+        let unknown = cbor!({ 0x01 => 1, 0x02 => 0x20 }).unwrap();
+        assert!(unknown.deserialized::<Request>().is_err());
+    }
+
+    #[test]
+    fn serialize_response() {
+        let response = Response {
+            retries: Some(8),
+            ..Default::default()
+        };
+        let value = Value::serialized(&response).expect("failed to serialize response");
+        assert_eq!(value, cbor!({ 0x03 => 8 }).unwrap());
+    }
+}
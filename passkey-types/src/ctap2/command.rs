@@ -0,0 +1,30 @@
+repr_enum! {
+    /// The commands of the authenticator API. A CTAP2 message is the command byte followed by its
+    /// CBOR encoded parameters.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#commands>
+    Command: u8 {
+        /// authenticatorMakeCredential
+        MakeCredential: 0x01,
+        /// authenticatorGetAssertion
+        GetAssertion: 0x02,
+        /// authenticatorGetInfo
+        GetInfo: 0x04,
+        /// authenticatorClientPIN
+        ClientPin: 0x06,
+        /// authenticatorReset
+        Reset: 0x07,
+        /// authenticatorGetNextAssertion
+        GetNextAssertion: 0x08,
+        /// authenticatorBioEnrollment
+        BioEnrollment: 0x09,
+        /// authenticatorCredentialManagement
+        CredentialManagement: 0x0A,
+        /// authenticatorSelection
+        Selection: 0x0B,
+        /// authenticatorLargeBlobs
+        LargeBlobs: 0x0C,
+        /// authenticatorConfig
+        Config: 0x0D,
+    }
+}
@@ -0,0 +1,248 @@
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorCredentialManagement>
+
+use coset::CoseKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctap2::make_credential::{PublicKeyCredentialRpEntity, PublicKeyCredentialUserEntity},
+    utils::serde::cose_key_opt,
+    webauthn::PublicKeyCredentialDescriptor,
+    Bytes,
+};
+
+serde_workaround! {
+    /// Request to the authenticator to manage its discoverable credentials with one of the
+    /// [`Subcommand`]s.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        /// The credential management operation to perform.
+        #[serde(rename = 0x01)]
+        pub sub_command: Subcommand,
+
+        /// The parameters of the subcommands which act on an RP or a credential.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub sub_command_params: Option<SubcommandParams>,
+
+        /// The PIN/UV auth protocol version used to compute `pin_uv_auth_param`.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_protocol: Option<u8>,
+
+        /// The output of the PIN/UV auth protocol's `authenticate` function over
+        /// [`Request::pin_uv_auth_message`] with a PIN/UV auth token which has the `cm`
+        /// permission. Not needed to continue an enumeration.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_param: Option<Bytes>,
+    }
+}
+
+impl Request {
+    /// The message authenticated by `pin_uv_auth_param`: the subcommand followed by the CBOR
+    /// encoding of its parameters if there are any.
+    pub fn pin_uv_auth_message(&self) -> Vec<u8> {
+        let mut message = vec![self.sub_command.into()];
+        if let Some(params) = &self.sub_command_params {
+            // SAFETY: writing to a Vec does not fail and the parameters are always representable
+            ciborium::ser::into_writer(params, &mut message).unwrap();
+        }
+        message
+    }
+}
+
+serde_workaround! {
+    /// The parameters of [`Subcommand::EnumerateCredentialsBegin`],
+    /// [`Subcommand::DeleteCredential`] and [`Subcommand::UpdateUserInformation`].
+    #[derive(Debug, Default, Clone)]
+    pub struct SubcommandParams {
+        /// The SHA-256 hash of the RP ID whose credentials to enumerate.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub rp_id_hash: Option<Bytes>,
+
+        /// The credential to delete or update.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub credential_id: Option<PublicKeyCredentialDescriptor>,
+
+        /// The updated user information of the credential. Its ID must match the credential's.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub user: Option<PublicKeyCredentialUserEntity>,
+    }
+}
+
+serde_workaround! {
+    /// Response of a credential management [`Subcommand`]. Only the fields relevant to the
+    /// subcommand are set.
+    #[derive(Debug, Default)]
+    pub struct Response {
+        /// The number of discoverable credentials on the authenticator.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub existing_resident_credentials_count: Option<u32>,
+
+        /// An estimate of how many more discoverable credentials can be stored.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub max_possible_remaining_resident_credentials_count: Option<u32>,
+
+        /// An RP with discoverable credentials on the authenticator.
+        #[serde(rename = 0x03, default, skip_serializing_if = Option::is_none)]
+        pub rp: Option<PublicKeyCredentialRpEntity>,
+
+        /// The SHA-256 hash of `rp`'s ID.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub rp_id_hash: Option<Bytes>,
+
+        /// The number of RPs with discoverable credentials, returned with the first one.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub total_rps: Option<u32>,
+
+        /// The user of a discoverable credential.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub user: Option<PublicKeyCredentialUserEntity>,
+
+        /// The ID of a discoverable credential.
+        #[serde(rename = 0x07, default, skip_serializing_if = Option::is_none)]
+        pub credential_id: Option<PublicKeyCredentialDescriptor>,
+
+        /// The public key of a discoverable credential.
+        #[serde(rename = 0x08, default, skip_serializing_if = Option::is_none, serialize_with = cose_key_opt::serialize, deserialize_with = cose_key_opt::deserialize)]
+        pub public_key: Option<CoseKey>,
+
+        /// The number of discoverable credentials of the RP, returned with the first one.
+        #[serde(rename = 0x09, default, skip_serializing_if = Option::is_none)]
+        pub total_credentials: Option<u32>,
+
+        /// The credential protection policy of a discoverable credential.
+        #[serde(rename = 0x0A, default, skip_serializing_if = Option::is_none)]
+        pub cred_protect: Option<u8>,
+
+        /// The `largeBlobKey` of a discoverable credential, if it was created with one.
+        #[serde(rename = 0x0B, default, skip_serializing_if = Option::is_none)]
+        pub large_blob_key: Option<Bytes>,
+    }
+}
+
+/// The operations of the `authenticatorCredentialManagement` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Subcommand {
+    /// Get the number of discoverable credentials and how many more can be stored.
+    GetCredsMetadata = 0x01,
+    /// Get the first RP with discoverable credentials.
+    EnumerateRpsBegin = 0x02,
+    /// Get the next RP of the enumeration.
+    EnumerateRpsGetNextRp = 0x03,
+    /// Get the first discoverable credential of an RP.
+    EnumerateCredentialsBegin = 0x04,
+    /// Get the next credential of the enumeration.
+    EnumerateCredentialsGetNextCredential = 0x05,
+    /// Delete a discoverable credential.
+    DeleteCredential = 0x06,
+    /// Update the user name and display name of a discoverable credential.
+    UpdateUserInformation = 0x07,
+}
+
+impl Subcommand {
+    /// Whether this subcommand continues an enumeration, in which case it is not authenticated.
+    pub fn is_enumeration_step(&self) -> bool {
+        matches!(
+            self,
+            Subcommand::EnumerateRpsGetNextRp | Subcommand::EnumerateCredentialsGetNextCredential
+        )
+    }
+}
+
+impl From<Subcommand> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: Subcommand) -> Self {
+        src as u8
+    }
+}
+
+impl TryFrom<u8> for Subcommand {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Subcommand::GetCredsMetadata),
+            0x02 => Ok(Subcommand::EnumerateRpsBegin),
+            0x03 => Ok(Subcommand::EnumerateRpsGetNextRp),
+            0x04 => Ok(Subcommand::EnumerateCredentialsBegin),
+            0x05 => Ok(Subcommand::EnumerateCredentialsGetNextCredential),
+            0x06 => Ok(Subcommand::DeleteCredential),
+            0x07 => Ok(Subcommand::UpdateUserInformation),
+            other => Err(other),
+        }
+    }
+}
+
+impl Serialize for Subcommand {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Subcommand {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+        Subcommand::try_from(value).map_err(|value| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(value.into()),
+                &"an authenticatorCredentialManagement subcommand",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::{cbor, value::Value};
+
+    use super::*;
+
+    #[test]
+    fn deserialize_request() {
+        let value = cbor!({
+            0x01 => 6,
+            0x02 => {
+                0x02 => { "type" => "public-key", "id" => Value::Bytes(vec![7; 16]) },
+            },
+            0x03 => 2,
+            0x04 => Value::Bytes(vec![1; 32]),
+        })
+        .unwrap();
+
+        let request: Request = value.deserialized().expect("failed to deserialize request");
+        assert_eq!(request.sub_command, Subcommand::DeleteCredential);
+        let params = request.sub_command_params.expect("missing params");
+        assert_eq!(params.credential_id.unwrap().id, vec![7; 16].into());
+        assert!(params.rp_id_hash.is_none());
+        assert_eq!(request.pin_uv_auth_protocol, Some(2));
+    }
+
+    #[test]
+    fn pin_uv_auth_message() {
+        let request = Request {
+            sub_command: Subcommand::EnumerateCredentialsBegin,
+            sub_command_params: Some(SubcommandParams {
+                rp_id_hash: Some(vec![0xaa; 2].into()),
+                ..Default::default()
+            }),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        let mut expected = vec![0x04];
+        ciborium::ser::into_writer(request.sub_command_params.as_ref().unwrap(), &mut expected)
+            .unwrap();
+        assert_eq!(request.pin_uv_auth_message(), expected);
+
+        let request = Request {
+            sub_command: Subcommand::GetCredsMetadata,
+            sub_command_params: None,
+            ..request
+        };
+        assert_eq!(request.pin_uv_auth_message(), [0x01]);
+    }
+}
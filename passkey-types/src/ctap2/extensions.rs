@@ -0,0 +1,502 @@
+use ciborium::value::Value;
+use coset::{CborSerializable, CoseKey};
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    ctap2::Aaguid, utils::serde::cose_key_opt,
+    webauthn::AuthenticationExtensionsDevicePublicKeyOutputs, Bytes,
+};
+
+#[cfg(doc)]
+use crate::ctap2::AuthenticatorData;
+
+/// The signed authenticator extension outputs found in an [`AuthenticatorData`], see
+/// [`AuthenticatorData::parse_extensions`].
+///
+/// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#sctn-defined-extensions>
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AuthenticatorExtensionOutputs {
+    /// The `credProtect` output, the protection policy of a newly created credential.
+    pub cred_protect: Option<CredentialProtectionPolicy>,
+
+    /// The `hmac-secret` output.
+    pub hmac_secret: Option<HmacSecretOutput>,
+
+    /// The `hmac-secret-mc` output, the output secrets of a new credential computed during its
+    /// creation, encrypted with the shared secret of the PIN protocol. It is absent when the
+    /// outputs are left to be computed on assertion.
+    pub hmac_secret_mc: Option<Bytes>,
+
+    /// The `credBlob` output.
+    pub cred_blob: Option<CredBlobOutput>,
+
+    /// The `minPinLength` output, the current minimum PIN length of the authenticator.
+    pub min_pin_length: Option<u64>,
+
+    /// The `thirdPartyPayment` output, whether the credential is enabled for third-party
+    /// payments.
+    pub third_party_payment: Option<bool>,
+
+    /// Outputs of extensions which are not recognized, in the order they were encoded.
+    pub unknown: Vec<(String, Value)>,
+}
+
+/// The credential protection policy applied to a credential by the `credProtect` extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CredentialProtectionPolicy {
+    /// The credential can be used with or without user verification.
+    UserVerificationOptional = 0x01,
+    /// The credential can be used without user verification only when its ID is provided in the
+    /// allow list.
+    UserVerificationOptionalWithCredentialIdList = 0x02,
+    /// The credential can only be used with user verification.
+    UserVerificationRequired = 0x03,
+}
+
+impl TryFrom<u8> for CredentialProtectionPolicy {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::UserVerificationOptional),
+            0x02 => Ok(Self::UserVerificationOptionalWithCredentialIdList),
+            0x03 => Ok(Self::UserVerificationRequired),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The input of the `hmac-secret` extension as sent by a CTAP platform, which depends on the
+/// operation.
+///
+/// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-hmac-secret-extension>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HmacSecretInput {
+    /// On credential creation, whether the credential should be created with a CredRandom.
+    Create(bool),
+    /// On assertion, the salts from which to compute the output secrets.
+    Get(HmacSecretSalts),
+}
+
+serde_workaround! {
+    /// The `hmac-secret` input on assertion: one or two salts encrypted with the secret shared
+    /// through the PIN/UV auth protocol's key agreement.
+    #[derive(Debug, Clone)]
+    pub struct HmacSecretSalts {
+        /// The public key of the platform's key agreement key.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none, serialize_with = cose_key_opt::serialize, deserialize_with = cose_key_opt::deserialize)]
+        pub key_agreement: Option<CoseKey>,
+
+        /// One or two 32 byte salts, encrypted with the shared secret.
+        #[serde(rename = 0x02)]
+        pub salt_enc: Bytes,
+
+        /// The output of the PIN/UV auth protocol's `authenticate` function over `salt_enc`.
+        #[serde(rename = 0x03)]
+        pub salt_auth: Bytes,
+
+        /// The PIN/UV auth protocol version of the key agreement, 1 when absent.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_protocol: Option<u8>,
+    }
+}
+
+/// The output of the `hmac-secret` extension, which depends on the operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HmacSecretOutput {
+    /// On credential creation, whether the credential was created with a CredRandom.
+    Created(bool),
+    /// On assertion, the output secrets encrypted with the shared secret of the PIN protocol.
+    Secret(Bytes),
+}
+
+/// The output of the `credBlob` extension, which depends on the operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredBlobOutput {
+    /// On credential creation, whether the blob was stored.
+    Stored(bool),
+    /// On assertion, the blob stored with the credential.
+    Blob(Bytes),
+}
+
+/// The unsigned extension outputs of `authenticatorMakeCredential` and `authenticatorGetAssertion`,
+/// returned next to the authenticator data rather than in it.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsignedExtensionOutputs {
+    /// The `devicePubKey` output.
+    #[serde(
+        rename = "devicePubKey",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub device_pub_key: Option<DevicePublicKeyOutput>,
+
+    /// Outputs of extensions which are not recognized, such as vendor extensions, in the order
+    /// they were encoded.
+    #[serde(flatten)]
+    pub unknown_keys: IndexMap<String, Value>,
+}
+
+/// The output of the `devicePubKey` extension: the attested device public key of the credential
+/// on this authenticator, along with a signature by the device private key.
+///
+/// <https://w3c.github.io/webauthn/#sctn-device-publickey-extension>
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevicePublicKeyOutput {
+    /// The signature over `authData || clientDataHash` of the operation, made with the device
+    /// private key.
+    pub sig: Bytes,
+    /// The device public key and its attestation.
+    pub attestation: DevicePublicKeyAttestation,
+}
+
+/// The attestation object of a device public key, which the client returns to the Relying Party
+/// CBOR encoded as the `dpkAttObj`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevicePublicKeyAttestation {
+    /// The AAGUID of the authenticator holding the device key.
+    pub aaguid: Aaguid,
+    /// The device public key.
+    pub dpk: CoseKey,
+    /// Whether the device key is shared by the whole device or specific to the application.
+    pub scope: DevicePublicKeyScope,
+    /// A nonce of at most 32 bytes included in the attestation statement, empty when there is
+    /// none.
+    pub nonce: Bytes,
+    /// The attestation statement format identifier.
+    pub fmt: String,
+    /// The attestation statement over the device public key, whose format is identified by
+    /// [`Self::fmt`].
+    pub att_stmt: Value,
+}
+
+/// The scope of a device public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DevicePublicKeyScope {
+    /// The device key is used by every application on the device.
+    Device = 0x00,
+    /// The device key is specific to the application which created the credential.
+    App = 0x01,
+}
+
+impl TryFrom<u8> for DevicePublicKeyScope {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::Device),
+            0x01 => Ok(Self::App),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<DevicePublicKeyScope> for u8 {
+    #[allow(clippy::as_conversions)]
+    fn from(src: DevicePublicKeyScope) -> Self {
+        src as u8
+    }
+}
+
+impl DevicePublicKeyAttestation {
+    /// Decode the CBOR encoded attestation, such as the `dpkAttObj` of a client's extension
+    /// outputs.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        Self::from_value(&ciborium::de::from_reader(bytes).ok()?)
+    }
+
+    /// Encode the attestation in CBOR, as the `dpkAttObj` of a client's extension outputs.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        // SAFETY: writing to a Vec does not fail and CBOR values are always representable
+        ciborium::ser::into_writer(&Value::Map(self.entries()), &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Whether `other` attests the same device key, in which case both come from the same device.
+    pub fn is_same_device(&self, other: &Self) -> bool {
+        self.aaguid == other.aaguid && self.dpk == other.dpk && self.scope == other.scope
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let aaguid: [u8; 16] = map_entry(value, "aaguid")?
+            .as_bytes()?
+            .as_slice()
+            .try_into()
+            .ok()?;
+        let dpk = CoseKey::from_slice(map_entry(value, "dpk")?.as_bytes()?).ok()?;
+        let scope = map_entry(value, "scope")?
+            .as_integer()
+            .and_then(|scope| u8::try_from(scope).ok())
+            .and_then(|scope| DevicePublicKeyScope::try_from(scope).ok())?;
+        let nonce = map_entry(value, "nonce")?.as_bytes()?.clone();
+        let fmt = map_entry(value, "fmt")?.as_text()?.to_owned();
+        let att_stmt = map_entry(value, "attStmt").filter(|stmt| stmt.is_map())?;
+        Some(Self {
+            aaguid: Aaguid(aaguid),
+            dpk,
+            scope,
+            nonce: nonce.into(),
+            fmt,
+            att_stmt: att_stmt.clone(),
+        })
+    }
+
+    fn entries(&self) -> Vec<(Value, Value)> {
+        vec![
+            (
+                Value::Text("aaguid".into()),
+                Value::Bytes(self.aaguid.0.to_vec()),
+            ),
+            (
+                Value::Text("dpk".into()),
+                // SAFETY: a COSE key can always be encoded in CBOR
+                Value::Bytes(self.dpk.clone().to_vec().unwrap()),
+            ),
+            (
+                Value::Text("scope".into()),
+                Value::Integer(u8::from(self.scope).into()),
+            ),
+            (
+                Value::Text("nonce".into()),
+                Value::Bytes(self.nonce.to_vec()),
+            ),
+            (Value::Text("fmt".into()), Value::Text(self.fmt.clone())),
+            (Value::Text("attStmt".into()), self.att_stmt.clone()),
+        ]
+    }
+}
+
+impl Serialize for DevicePublicKeyOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries = vec![(Value::Text("sig".into()), Value::Bytes(self.sig.to_vec()))];
+        entries.extend(self.attestation.entries());
+        Value::Map(entries).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DevicePublicKeyOutput {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let sig = map_entry(&value, "sig")
+            .and_then(Value::as_bytes)
+            .ok_or_else(|| serde::de::Error::missing_field("sig"))?;
+        let attestation = DevicePublicKeyAttestation::from_value(&value)
+            .ok_or_else(|| serde::de::Error::custom("invalid device public key attestation"))?;
+        Ok(Self {
+            sig: sig.clone().into(),
+            attestation,
+        })
+    }
+}
+
+impl From<DevicePublicKeyOutput> for AuthenticationExtensionsDevicePublicKeyOutputs {
+    fn from(src: DevicePublicKeyOutput) -> Self {
+        Self {
+            dpk_att_obj: src.attestation.to_vec().into(),
+            signature: src.sig,
+        }
+    }
+}
+
+fn map_entry<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+impl AuthenticatorExtensionOutputs {
+    pub(super) fn from_value(extensions: &Value) -> coset::Result<Self> {
+        let entries = extensions
+            .as_map()
+            .ok_or(coset::CoseError::UnexpectedItem("value", "map"))?;
+
+        let mut outputs = Self::default();
+        for (key, value) in entries {
+            let key = key.as_text().ok_or(coset::CoseError::UnexpectedItem(
+                "value",
+                "text extension identifier",
+            ))?;
+            match key {
+                "credProtect" => {
+                    let policy = value
+                        .as_integer()
+                        .and_then(|policy| u8::try_from(policy).ok())
+                        .and_then(|policy| CredentialProtectionPolicy::try_from(policy).ok())
+                        .ok_or(coset::CoseError::UnexpectedItem(
+                            "value",
+                            "credProtect policy",
+                        ))?;
+                    outputs.cred_protect = Some(policy);
+                }
+                "hmac-secret" => {
+                    let output = match value {
+                        Value::Bool(created) => HmacSecretOutput::Created(*created),
+                        Value::Bytes(secret) => HmacSecretOutput::Secret(secret.clone().into()),
+                        _ => {
+                            return Err(coset::CoseError::UnexpectedItem(
+                                "value",
+                                "hmac-secret bool or bytes",
+                            ))
+                        }
+                    };
+                    outputs.hmac_secret = Some(output);
+                }
+                "hmac-secret-mc" => {
+                    let secret = value.as_bytes().ok_or(coset::CoseError::UnexpectedItem(
+                        "value",
+                        "hmac-secret-mc bytes",
+                    ))?;
+                    outputs.hmac_secret_mc = Some(secret.clone().into());
+                }
+                "credBlob" => {
+                    let output = match value {
+                        Value::Bool(stored) => CredBlobOutput::Stored(*stored),
+                        Value::Bytes(blob) => CredBlobOutput::Blob(blob.clone().into()),
+                        _ => {
+                            return Err(coset::CoseError::UnexpectedItem(
+                                "value",
+                                "credBlob bool or bytes",
+                            ))
+                        }
+                    };
+                    outputs.cred_blob = Some(output);
+                }
+                "minPinLength" => {
+                    let length = value
+                        .as_integer()
+                        .and_then(|length| u64::try_from(length).ok())
+                        .ok_or(coset::CoseError::UnexpectedItem(
+                            "value",
+                            "minPinLength uint",
+                        ))?;
+                    outputs.min_pin_length = Some(length);
+                }
+                "thirdPartyPayment" => {
+                    let enabled = value.as_bool().ok_or(coset::CoseError::UnexpectedItem(
+                        "value",
+                        "thirdPartyPayment bool",
+                    ))?;
+                    outputs.third_party_payment = Some(enabled);
+                }
+                _ => outputs.unknown.push((key.to_owned(), value.clone())),
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::cbor;
+    use coset::{iana, AsCborValue, CoseKeyBuilder};
+
+    use super::*;
+    use crate::webauthn::AuthenticationExtensionsClientInputs;
+
+    fn from_cbor(value: Value) -> AuthenticationExtensionsClientInputs {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).unwrap();
+        ciborium::de::from_reader(bytes.as_slice()).expect("could not deserialize extensions")
+    }
+
+    #[test]
+    fn device_pub_key_output_round_trip() {
+        let dpk =
+            CoseKeyBuilder::new_ec2_pub_key(iana::EllipticCurve::P_256, vec![1; 32], vec![2; 32])
+                .algorithm(iana::Algorithm::ES256)
+                .build();
+        let output = DevicePublicKeyOutput {
+            sig: vec![3; 64].into(),
+            attestation: DevicePublicKeyAttestation {
+                aaguid: Aaguid([4; 16]),
+                dpk,
+                scope: DevicePublicKeyScope::Device,
+                nonce: Vec::new().into(),
+                fmt: "none".into(),
+                att_stmt: Value::Map(Vec::new()),
+            },
+        };
+        let outputs = UnsignedExtensionOutputs {
+            device_pub_key: Some(output.clone()),
+            unknown_keys: IndexMap::from([("example.extension".into(), Value::Bool(true))]),
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&outputs, &mut bytes).unwrap();
+        let value: Value = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        let output_value = map_entry(&value, "devicePubKey").expect("missing devicePubKey");
+        assert_eq!(
+            map_entry(output_value, "sig").and_then(Value::as_bytes),
+            Some(&vec![3; 64])
+        );
+        assert_eq!(
+            map_entry(output_value, "scope").and_then(Value::as_integer),
+            Some(0.into())
+        );
+
+        let parsed: UnsignedExtensionOutputs = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(parsed, outputs);
+        let attestation = DevicePublicKeyAttestation::from_slice(&output.attestation.to_vec())
+            .expect("could not decode the attestation");
+        assert_eq!(attestation, output.attestation);
+        assert!(attestation.is_same_device(&output.attestation));
+    }
+
+    #[test]
+    fn parse_unknown_inputs() {
+        let inputs = from_cbor(
+            cbor!({
+                "example.vendor" => { "flag" => true },
+                "hmac-secret" => true,
+                "example.other" => Value::Bytes(vec![1; 4]),
+            })
+            .unwrap(),
+        );
+        assert!(inputs.hmac_secret.is_some());
+        assert_eq!(
+            inputs.unknown_keys.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("example.vendor".into(), cbor!({ "flag" => true }).unwrap()),
+                ("example.other".into(), Value::Bytes(vec![1; 4])),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_hmac_secret_inputs() {
+        let inputs = from_cbor(cbor!({ "hmac-secret" => true }).unwrap());
+        assert!(matches!(
+            inputs.hmac_secret,
+            Some(HmacSecretInput::Create(true))
+        ));
+
+        let key =
+            CoseKeyBuilder::new_ec2_pub_key(iana::EllipticCurve::P_256, vec![1; 32], vec![2; 32])
+                .build();
+        let inputs = from_cbor(Value::Map(vec![(
+            Value::Text("hmac-secret".into()),
+            Value::Map(vec![
+                (
+                    Value::Integer(1.into()),
+                    key.clone().to_cbor_value().unwrap(),
+                ),
+                (Value::Integer(2.into()), Value::Bytes(vec![3; 32])),
+                (Value::Integer(3.into()), Value::Bytes(vec![4; 32])),
+                (Value::Integer(4.into()), Value::Integer(2.into())),
+            ]),
+        )]));
+        let Some(HmacSecretInput::Get(salts)) = inputs.hmac_secret else {
+            panic!("expected hmac-secret salts");
+        };
+        assert_eq!(salts.key_agreement, Some(key));
+        assert_eq!(salts.salt_enc, vec![3; 32].into());
+        assert_eq!(salts.salt_auth, vec![4; 32].into());
+        assert_eq!(salts.pin_uv_auth_protocol, Some(2));
+    }
+}
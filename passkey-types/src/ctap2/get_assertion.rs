@@ -1,6 +1,6 @@
 //! <https://fidoalliance.org/specs/fido-v2.0-ps-20190130/fido-client-to-authenticator-protocol-v2.0-ps-20190130.html#authenticatorGetAssertion>
 use crate::{
-    ctap2::AuthenticatorData,
+    ctap2::{AuthenticatorData, UnsignedExtensionOutputs},
     webauthn::{
         AuthenticationExtensionsClientInputs, PublicKeyCredentialDescriptor,
         PublicKeyCredentialUserEntity,
@@ -16,7 +16,7 @@ use crate::webauthn::{CollectedClientData, PublicKeyCredentialRequestOptions};
 serde_workaround! {
     /// While similar in structure to [`PublicKeyCredentialRequestOptions`],
     /// it is not completely identical, namely the presence of the `options` key.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct Request {
         /// Relying Party Identifier
         #[serde(rename = 0x01)]
@@ -43,7 +43,7 @@ serde_workaround! {
         pub options: Options,
 
         /// First 16 bytes of HMAC-SHA-256 of clientDataHash using pinToken which platform got from
-        /// the authenticator: HMAC-SHA-256(pinToken, clientDataHash). (NOT YET SUPPORTED)
+        /// the authenticator: HMAC-SHA-256(pinToken, clientDataHash).
         #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
         pub pin_auth: Option<Bytes>,
 
@@ -106,5 +106,15 @@ serde_workaround! {
         /// file an enhancement request if this limit impacts your application.
         #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
         pub number_of_credentials: Option<u8>,
+
+        /// The key with which the platform encrypts the credential's entry in the large-blob
+        /// array, only present if requested and the credential was created with one.
+        #[serde(rename = 0x07, default, skip_serializing_if = Option::is_none)]
+        pub large_blob_key: Option<Bytes>,
+
+        /// The outputs of extensions which are not signed over in the authenticator data, such
+        /// as `devicePubKey`.
+        #[serde(rename = 0x08, default, skip_serializing_if = Option::is_none)]
+        pub unsigned_extension_outputs: Option<UnsignedExtensionOutputs>,
     }
 }
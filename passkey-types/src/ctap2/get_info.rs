@@ -1,15 +1,18 @@
-//! <https://fidoalliance.org/specs/fido-v2.0-ps-20190130/fido-client-to-authenticator-protocol-v2.0-ps-20190130.html#authenticatorGetInfo>
-use std::{borrow::Cow, num::NonZeroU128};
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorGetInfo>
+use std::{borrow::Cow, collections::BTreeMap, num::NonZeroU128};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{utils::serde::ignore_unknown_opt_vec, webauthn::AuthenticatorTransport};
+use crate::{
+    utils::serde::ignore_unknown_opt_vec,
+    webauthn::{AuthenticatorTransport, PublicKeyCredentialParameters},
+};
 
 use super::Aaguid;
 
 serde_workaround! {
     /// An Authenticator's metadata and capabilities.
-    #[derive(Debug, PartialEq, Eq)]
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
     pub struct Response {
         /// List of supported versions.
         /// Supported versions are:
@@ -44,6 +47,15 @@ serde_workaround! {
         #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
         pub pin_protocols: Option<Vec<u8>>,
 
+        /// The maximum number of credentials in the allow or exclude list of a request.
+        #[serde(rename = 0x07, default, skip_serializing_if = Option::is_none)]
+        pub max_credential_count_in_list: Option<u32>,
+
+        /// The maximum length in bytes of the credential IDs in the allow or exclude list of a
+        /// request.
+        #[serde(rename = 0x08, default, skip_serializing_if = Option::is_none)]
+        pub max_credential_id_length: Option<u32>,
+
         /// List of supported transports. Values are taken from the [`AuthenticatorTransport`] enum.
         /// The list MUST NOT include duplicate values nor be empty if present.
         /// Platforms MUST tolerate unknown values by ignoring them.
@@ -54,12 +66,66 @@ serde_workaround! {
             deserialize_with = ignore_unknown_opt_vec
         )]
         pub transports: Option<Vec<AuthenticatorTransport>>,
+
+        /// The credential algorithms supported by the authenticator, in order of preference.
+        #[serde(rename = 0x0A, default, skip_serializing_if = Option::is_none)]
+        pub algorithms: Option<Vec<PublicKeyCredentialParameters>>,
+
+        /// The maximum size in bytes of the serialized large-blob array the authenticator can
+        /// store, only present if it supports `authenticatorLargeBlobs`.
+        #[serde(rename = 0x0B, default, skip_serializing_if = Option::is_none)]
+        pub max_serialized_large_blob_array: Option<u32>,
+
+        /// Whether the PIN must be changed before it can be used to get a PIN/UV auth token.
+        #[serde(rename = 0x0C, default, skip_serializing_if = Option::is_none)]
+        pub force_pin_change: Option<bool>,
+
+        /// The current minimum PIN length in Unicode code points. When absent, it is 4.
+        #[serde(rename = 0x0D, default, skip_serializing_if = Option::is_none)]
+        pub min_pin_length: Option<u8>,
+
+        /// The version of the authenticator's firmware, whose meaning is up to the vendor.
+        #[serde(rename = 0x0E, default, skip_serializing_if = Option::is_none)]
+        pub firmware_version: Option<u64>,
+
+        /// The maximum length in bytes of a `credBlob`, only present if the extension is
+        /// supported.
+        #[serde(rename = 0x0F, default, skip_serializing_if = Option::is_none)]
+        pub max_cred_blob_length: Option<u32>,
+
+        /// The maximum number of RP IDs which `setMinPINLength` can allow to get the minimum PIN
+        /// length through the `minPinLength` extension.
+        #[serde(rename = 0x10, default, skip_serializing_if = Option::is_none)]
+        pub max_rpids_for_set_min_pin_length: Option<u32>,
+
+        /// How many times the platform should try to verify the user with built-in user
+        /// verification before falling back to the PIN.
+        #[serde(rename = 0x11, default, skip_serializing_if = Option::is_none)]
+        pub preferred_platform_uv_attempts: Option<u32>,
+
+        /// The user verification modalities of the authenticator, as the `USER_VERIFY` flags of
+        /// the FIDO registry.
+        #[serde(rename = 0x12, default, skip_serializing_if = Option::is_none)]
+        pub uv_modality: Option<u32>,
+
+        /// The certifications of the authenticator, mapping the name of a certification such as
+        /// "FIDO" to the certified level.
+        #[serde(rename = 0x13, default, skip_serializing_if = Option::is_none)]
+        pub certifications: Option<BTreeMap<Cow<'static, str>, u64>>,
+
+        /// An estimate of how many more discoverable credentials can be stored.
+        #[serde(rename = 0x14, default, skip_serializing_if = Option::is_none)]
+        pub remaining_discoverable_credentials: Option<u32>,
+
+        /// The vendor command IDs of the `vendorPrototype` subcommand of `authenticatorConfig`.
+        #[serde(rename = 0x15, default, skip_serializing_if = Option::is_none)]
+        pub vendor_prototype_config_commands: Option<Vec<u64>>,
     }
 }
 
 /// All options are in the form of key-value pairs with string IDs and boolean values.
 /// When an option is not present, the default is applied.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Options {
     /// Platform Device: Indicates that the device is attached to the client and therefore can’t be
@@ -108,6 +174,66 @@ pub struct Options {
     ///  it will return both "uv" and the Client PIN option.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub uv: Option<bool>,
+
+    /// Enterprise Attestation:
+    ///
+    /// If `Some(true)`, the device supports enterprise attestation and it is enabled.
+    ///
+    /// If `Some(false)`, the device supports enterprise attestation but it is disabled.
+    ///
+    /// If `None`, the device does not support enterprise attestation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ep: Option<bool>,
+
+    /// PIN/UV Auth Token: Indicates that the device supports the subcommands of
+    /// `authenticatorClientPIN` which give out PIN/UV auth tokens with permissions, including
+    /// through built-in user verification when `uv` is also `Some(true)`.
+    ///
+    /// If `None` or `Some(false)`, only PIN tokens from the legacy `getPinToken` subcommand are
+    /// supported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin_uv_auth_token: Option<bool>,
+
+    /// Credential Management: Indicates that the device supports the
+    /// `authenticatorCredentialManagement` command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cred_mgmt: Option<bool>,
+
+    /// Biometric Enrollment: Indicates that the device supports the `authenticatorBioEnrollment`
+    /// command.
+    ///
+    /// If `Some(true)`, at least one biometric enrollment was made.
+    ///
+    /// If `Some(false)`, no biometric enrollment was made yet.
+    ///
+    /// If `None`, biometric enrollment is not supported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bio_enroll: Option<bool>,
+
+    /// Large Blobs: Indicates that the device supports the `authenticatorLargeBlobs` command and
+    /// the `largeBlobKey` extension.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large_blobs: Option<bool>,
+
+    /// Always UV: Indicates whether the device requires user verification for every credential
+    /// operation, in which case U2F is disabled.
+    ///
+    /// If `None`, the device does not support toggling it through `authenticatorConfig`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub always_uv: Option<bool>,
+
+    /// Authenticator Config: Indicates that the device supports the `authenticatorConfig` command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authnr_cfg: Option<bool>,
+
+    /// Set Min PIN Length: Indicates that the device supports the `setMinPINLength` subcommand of
+    /// `authenticatorConfig`.
+    #[serde(
+        rename = "setMinPINLength",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub set_min_pin_length: Option<bool>,
 }
 
 #[must_use]
@@ -124,6 +250,14 @@ impl Default for Options {
             client_pin: None,
             up: true,
             uv: None,
+            ep: None,
+            pin_uv_auth_token: None,
+            cred_mgmt: None,
+            bio_enroll: None,
+            large_blobs: None,
+            always_uv: None,
+            authnr_cfg: None,
+            set_min_pin_length: None,
         }
     }
 }
@@ -132,7 +266,11 @@ impl Default for Options {
 mod tests {
     use ciborium::cbor;
 
+    use coset::iana;
+
     use super::{Aaguid, AuthenticatorTransport, Options, Response};
+    use crate::webauthn::{PublicKeyCredentialParameters, PublicKeyCredentialType};
+
     #[test]
     fn serialization_round_trip() {
         let expected = Response {
@@ -150,6 +288,10 @@ mod tests {
                 AuthenticatorTransport::Internal,
                 AuthenticatorTransport::Hybrid,
             ]),
+            max_serialized_large_blob_array: None,
+            force_pin_change: None,
+            min_pin_length: None,
+            ..Default::default()
         };
         let mut serialized = Vec::new();
         ciborium::ser::into_writer(&expected, &mut serialized)
@@ -180,6 +322,10 @@ mod tests {
                 AuthenticatorTransport::Internal,
                 AuthenticatorTransport::Hybrid,
             ]),
+            max_serialized_large_blob_array: None,
+            force_pin_change: None,
+            min_pin_length: None,
+            ..Default::default()
         };
         let mut serialized = Vec::new();
         ciborium::ser::into_writer(&input, &mut serialized).expect("Could not serialize to cbor");
@@ -245,8 +391,52 @@ mod tests {
             max_msg_size: None,
             pin_protocols: Some(vec![1]),
             transports: Some(vec![AuthenticatorTransport::Hybrid]),
+            max_serialized_large_blob_array: None,
+            force_pin_change: None,
+            min_pin_length: None,
+            ..Default::default()
         };
 
         assert_eq!(expected, deserialized);
     }
+
+    #[test]
+    fn serialization_of_ctap_2_1_fields() {
+        let input = Response {
+            versions: vec!["FIDO_2_1".into()],
+            aaguid: Aaguid::new_empty(),
+            max_credential_count_in_list: Some(8),
+            max_credential_id_length: Some(128),
+            algorithms: Some(vec![PublicKeyCredentialParameters {
+                ty: PublicKeyCredentialType::PublicKey,
+                alg: iana::Algorithm::ES256,
+            }]),
+            firmware_version: Some(3),
+            certifications: Some([("FIDO".into(), 1)].into()),
+            remaining_discoverable_credentials: Some(25),
+            ..Default::default()
+        };
+        let mut serialized = Vec::new();
+        ciborium::ser::into_writer(&input, &mut serialized).expect("Could not serialize to cbor");
+
+        let deserialized: ciborium::value::Value =
+            ciborium::de::from_reader(serialized.as_slice()).expect("Could not deserialize");
+
+        let expected = cbor!({
+            0x01 => vec!["FIDO_2_1"],
+            0x03 => ciborium::value::Value::Bytes([0;16].into()),
+            0x07 => 8,
+            0x08 => 128,
+            0x0A => vec![cbor!({ "type" => "public-key", "alg" => -7 }).unwrap()],
+            0x0E => 3,
+            0x13 => { "FIDO" => 1 },
+            0x14 => 25,
+        })
+        .unwrap();
+        assert_eq!(deserialized, expected);
+
+        let deserialized: Response =
+            ciborium::de::from_reader(serialized.as_slice()).expect("Could not deserialize");
+        assert_eq!(deserialized, input);
+    }
 }
@@ -0,0 +1,122 @@
+//! <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#authenticatorLargeBlobs>
+
+use crate::{crypto::sha256, ctap2::Command, Bytes};
+
+/// The length of the truncated SHA-256 hash which ends a serialized large-blob array.
+pub const LARGE_BLOB_ARRAY_HASH_LENGTH: usize = 16;
+
+/// The serialized large-blob array of an authenticator on which none were written: an empty CBOR
+/// array followed by the first 16 bytes of its SHA-256 hash.
+pub const EMPTY_LARGE_BLOB_ARRAY: [u8; 17] = [
+    0x80, 0x76, 0xbe, 0x8b, 0x52, 0x8d, 0x00, 0x75, 0xf7, 0xaa, 0xe9, 0x8d, 0x6f, 0xa5, 0x7a, 0x6d,
+    0x3c,
+];
+
+/// Whether `serialized` ends with the first 16 bytes of the SHA-256 hash of the rest of it, as a
+/// serialized large-blob array must.
+pub fn is_valid_large_blob_array(serialized: &[u8]) -> bool {
+    let Some(split) = serialized.len().checked_sub(LARGE_BLOB_ARRAY_HASH_LENGTH) else {
+        return false;
+    };
+    let (array, hash) = serialized.split_at(split);
+    sha256(array)[..LARGE_BLOB_ARRAY_HASH_LENGTH] == *hash
+}
+
+serde_workaround! {
+    /// Request to the authenticator to read or write a fragment of its serialized large-blob
+    /// array. Exactly one of `get` and `set` must be present.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        /// The number of bytes to read from `offset`.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub get: Option<u32>,
+
+        /// The fragment to write at `offset`.
+        #[serde(rename = 0x02, default, skip_serializing_if = Option::is_none)]
+        pub set: Option<Bytes>,
+
+        /// The byte offset of the fragment in the serialized large-blob array.
+        #[serde(rename = 0x03)]
+        pub offset: u32,
+
+        /// The total length of the serialized large-blob array being written. Only present with
+        /// the first fragment of a write, when `offset` is zero.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub length: Option<u32>,
+
+        /// The output of the PIN/UV auth protocol's `authenticate` function over
+        /// [`Request::pin_uv_auth_message`] with a PIN/UV auth token which has the `lbw`
+        /// permission.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_param: Option<Bytes>,
+
+        /// The PIN/UV auth protocol version used to compute `pin_uv_auth_param`.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub pin_uv_auth_protocol: Option<u8>,
+    }
+}
+
+impl Request {
+    /// The message authenticated by `pin_uv_auth_param` for a write: 32 bytes of `0xff`, the
+    /// command byte followed by `0x00`, the little endian `offset` and the SHA-256 hash of the
+    /// fragment.
+    pub fn pin_uv_auth_message(&self) -> Vec<u8> {
+        let mut message = vec![0xff; 32];
+        message.extend([Command::LargeBlobs.into(), 0x00]);
+        message.extend(self.offset.to_le_bytes());
+        let fragment = self.set.as_deref().map(Vec::as_slice).unwrap_or_default();
+        message.extend(sha256(fragment));
+        message
+    }
+}
+
+serde_workaround! {
+    /// Response to a large-blob read, empty after a write.
+    #[derive(Debug, Default)]
+    pub struct Response {
+        /// The fragment of the serialized large-blob array which was read.
+        #[serde(rename = 0x01, default, skip_serializing_if = Option::is_none)]
+        pub config: Option<Bytes>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::cbor;
+
+    use super::*;
+
+    #[test]
+    fn empty_large_blob_array_is_valid() {
+        assert!(is_valid_large_blob_array(&EMPTY_LARGE_BLOB_ARRAY));
+        let mut tampered = EMPTY_LARGE_BLOB_ARRAY;
+        tampered[0] = 0x81;
+        assert!(!is_valid_large_blob_array(&tampered));
+        assert!(!is_valid_large_blob_array(&EMPTY_LARGE_BLOB_ARRAY[1..]));
+    }
+
+    #[test]
+    fn deserialize_request() {
+        let value = cbor!({ 0x01 => 64, 0x03 => 17 }).unwrap();
+        let request: Request = value.deserialized().expect("failed to deserialize request");
+        assert_eq!(request.get, Some(64));
+        assert_eq!(request.offset, 17);
+        assert!(request.set.is_none());
+    }
+
+    #[test]
+    fn pin_uv_auth_message() {
+        let request = Request {
+            get: None,
+            set: Some(vec![1, 2, 3].into()),
+            offset: 0x0102,
+            length: None,
+            pin_uv_auth_param: None,
+            pin_uv_auth_protocol: None,
+        };
+        let message = request.pin_uv_auth_message();
+        assert_eq!(message[..32], [0xff; 32]);
+        assert_eq!(message[32..38], [0x0c, 0x00, 0x02, 0x01, 0x00, 0x00]);
+        assert_eq!(message[38..], sha256(&[1, 2, 3]));
+    }
+}
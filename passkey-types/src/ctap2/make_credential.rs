@@ -2,8 +2,13 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::{ctap2::AuthenticatorData, webauthn, Bytes};
+use crate::{
+    ctap2::{AuthenticatorData, UnsignedExtensionOutputs},
+    webauthn, Bytes,
+};
 
+#[cfg(doc)]
+use crate::ctap2::get_info;
 #[cfg(doc)]
 use crate::webauthn::{
     CollectedClientData, PublicKeyCredentialCreationOptions, PublicKeyCredentialDescriptor,
@@ -72,7 +77,7 @@ serde_workaround! {
         pub options: Options,
 
         /// First 16 bytes of HMAC-SHA-256 of clientDataHash using pinToken which platform got from
-        /// the authenticator: HMAC-SHA-256(pinToken, clientDataHash). (NOT YET SUPPORTED)
+        /// the authenticator: HMAC-SHA-256(pinToken, clientDataHash).
         #[serde(rename = 0x08, default, skip_serializing_if = Option::is_none)]
         pub pin_auth: Option<Bytes>,
 
@@ -81,6 +86,13 @@ serde_workaround! {
         /// if ever we hit more than 256 protocol versions, an enhacement request should be filed.
         #[serde(rename = 0x09, default, skip_serializing_if = Option::is_none)]
         pub pin_protocol: Option<u8>,
+
+        /// Requests an enterprise attestation from an authenticator which supports it, see
+        /// [`get_info::Options::ep`]. `1` is vendor facilitated, where the authenticator decides
+        /// whether the RP ID may receive it, and `2` is platform managed, where the client has
+        /// already decided so.
+        #[serde(rename = 0x0A, default, skip_serializing_if = Option::is_none)]
+        pub enterprise_attestation: Option<u8>,
     }
 }
 
@@ -90,7 +102,7 @@ serde_workaround! {
 ///
 /// [WebAuthn]: https://w3c.github.io/webauthn/#dictdef-publickeycredentialrpentity
 /// [CTAP2]: https://fidoalliance.org/specs/fido-v2.0-ps-20190130/fido-client-to-authenticator-protocol-v2.0-ps-20190130.html#authenticatorMakeCredential
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublicKeyCredentialRpEntity {
     /// The domain of the relying party
     pub id: String,
@@ -100,7 +112,7 @@ pub struct PublicKeyCredentialRpEntity {
 }
 
 /// This is a copy of [`webauthn::PublicKeyCredentialUserEntity`] with differing optional fields.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublicKeyCredentialUserEntity {
     /// The ID of the user
     pub id: Bytes,
@@ -170,7 +182,7 @@ impl TryFrom<webauthn::PublicKeyCredentialRpEntity> for PublicKeyCredentialRpEnt
 }
 
 /// The options that control how an authenticator will behave.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Options {
     /// Specifies whether this credential is to be discoverable or not.
     #[serde(default)]
@@ -218,5 +230,19 @@ serde_workaround! {
         // the keys
         #[serde(rename = 0x03)]
         pub att_stmt: ciborium::value::Value,
+
+        /// Whether an enterprise attestation was returned, only present if it was requested.
+        #[serde(rename = 0x04, default, skip_serializing_if = Option::is_none)]
+        pub ep_att: Option<bool>,
+
+        /// The key with which the platform encrypts the credential's entry in the large-blob
+        /// array, only present if requested through the `largeBlobKey` extension.
+        #[serde(rename = 0x05, default, skip_serializing_if = Option::is_none)]
+        pub large_blob_key: Option<Bytes>,
+
+        /// The outputs of extensions which are not signed over in the authenticator data, such
+        /// as `devicePubKey`.
+        #[serde(rename = 0x06, default, skip_serializing_if = Option::is_none)]
+        pub unsigned_extension_outputs: Option<UnsignedExtensionOutputs>,
     }
 }
@@ -0,0 +1,260 @@
+use std::time::{Duration, SystemTime};
+
+use bitflags::bitflags;
+use zeroize::Zeroizing;
+
+use super::Ctap2Error;
+
+bitflags! {
+    /// The permissions of a PIN/UV auth token, restricting the commands it can authenticate.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#permissions>
+    #[repr(transparent)]
+    pub struct Permissions: u8 {
+        /// MakeCredential
+        const MC = 0x01;
+        /// GetAssertion
+        const GA = 0x02;
+        /// Credential Management
+        const CM = 0x04;
+        /// Bio Enrollment
+        const BE = 0x08;
+        /// Large Blob Write
+        const LBW = 0x10;
+        /// Authenticator Configuration
+        const ACFG = 0x20;
+    }
+}
+
+impl Permissions {
+    /// Whether these permissions restrict the token to a single RP. Credential management and
+    /// large blob writes may be restricted with an explicit RP ID but are never bound implicitly.
+    pub fn are_rp_bound(&self) -> bool {
+        self.intersects(Permissions::MC | Permissions::GA)
+    }
+}
+
+impl From<Permissions> for u8 {
+    fn from(src: Permissions) -> Self {
+        src.bits
+    }
+}
+
+/// A PIN/UV auth token given out by `authenticatorClientPIN`, along with the commands and RP it
+/// may be used for and how long it remains valid.
+///
+/// The token only tracks its own state. Verifying that a request was authenticated with the
+/// token is up to the PIN/UV auth protocol which was used to give it out.
+///
+/// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#pinuvauthprotocol-pinuvauthtoken-state>
+pub struct PinUvAuthToken {
+    token: Zeroizing<[u8; 32]>,
+    permissions: Permissions,
+    /// The RP ID the token is restricted to. When absent, it is bound to the RP of the first
+    /// request it authorizes which needs one.
+    rp_id: Option<String>,
+    issued_at: SystemTime,
+    first_used_at: Option<SystemTime>,
+    usage_count: u32,
+}
+
+impl PinUvAuthToken {
+    /// How long a token remains valid if it is never used.
+    pub const INITIAL_USAGE_TIME_LIMIT: Duration = Duration::from_secs(30);
+
+    /// How long a token remains valid after it was given out, regardless of its use.
+    pub const MAX_USAGE_TIME_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+    /// Create a token given out at `issued_at` with the requested `permissions`, optionally
+    /// restricted to `rp_id`.
+    pub fn new(
+        token: impl Into<Zeroizing<[u8; 32]>>,
+        permissions: Permissions,
+        rp_id: Option<String>,
+        issued_at: SystemTime,
+    ) -> Self {
+        Self {
+            token: token.into(),
+            permissions,
+            rp_id,
+            issued_at,
+            first_used_at: None,
+            usage_count: 0,
+        }
+    }
+
+    /// The secret token, used as the key of the PIN/UV auth protocol's `authenticate` function.
+    pub fn token(&self) -> &[u8; 32] {
+        &self.token
+    }
+
+    /// The commands this token may authenticate.
+    pub fn permissions(&self) -> Permissions {
+        self.permissions
+    }
+
+    /// The RP this token is restricted to, if any.
+    pub fn rp_id(&self) -> Option<&str> {
+        self.rp_id.as_deref()
+    }
+
+    /// When this token was given out.
+    pub fn issued_at(&self) -> SystemTime {
+        self.issued_at
+    }
+
+    /// When this token first authorized a request, `None` if it never did.
+    pub fn first_used_at(&self) -> Option<SystemTime> {
+        self.first_used_at
+    }
+
+    /// The number of requests this token has authorized.
+    pub fn usage_count(&self) -> u32 {
+        self.usage_count
+    }
+
+    /// Whether this token can no longer be used at `now`, either because it was not used within
+    /// [`Self::INITIAL_USAGE_TIME_LIMIT`] or because [`Self::MAX_USAGE_TIME_PERIOD`] has elapsed.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        // A clock which went backwards is treated as if no time has elapsed.
+        let elapsed = now.duration_since(self.issued_at).unwrap_or_default();
+        elapsed > Self::MAX_USAGE_TIME_PERIOD
+            || (self.first_used_at.is_none() && elapsed > Self::INITIAL_USAGE_TIME_LIMIT)
+    }
+
+    /// Check that this token grants `permission` for `rp_id` at `now` and record its use. A token
+    /// without an RP ID becomes bound to `rp_id` when `permission` is bound to an RP. Permissions
+    /// which are not associated with an RP, such as [`Permissions::ACFG`], are checked with no
+    /// `rp_id`.
+    ///
+    /// Returns [`Ctap2Error::PinAuthInvalid`] if the token may not be used for the request.
+    pub fn authorize(
+        &mut self,
+        permission: Permissions,
+        rp_id: Option<&str>,
+        now: SystemTime,
+    ) -> Result<(), Ctap2Error> {
+        if self.is_expired(now) || !self.permissions.contains(permission) {
+            return Err(Ctap2Error::PinAuthInvalid);
+        }
+        match (&self.rp_id, rp_id) {
+            (Some(token_rp_id), Some(rp_id)) if token_rp_id != rp_id => {
+                return Err(Ctap2Error::PinAuthInvalid);
+            }
+            (None, Some(rp_id)) if permission.are_rp_bound() => {
+                self.rp_id = Some(rp_id.to_owned());
+            }
+            _ => {}
+        }
+        self.first_used_at.get_or_insert(now);
+        self.usage_count = self.usage_count.saturating_add(1);
+        Ok(())
+    }
+
+    /// Remove every permission but [`Permissions::LBW`]. This is done once the token authorized a
+    /// request for which the user was present, so that it cannot be replayed to create or use
+    /// other credentials without the user.
+    pub fn clear_permissions_except_lbw(&mut self) {
+        self.permissions &= Permissions::LBW;
+    }
+}
+
+impl std::fmt::Debug for PinUvAuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PinUvAuthToken")
+            .field("permissions", &self.permissions)
+            .field("rp_id", &self.rp_id)
+            .field("issued_at", &self.issued_at)
+            .field("first_used_at", &self.first_used_at)
+            .field("usage_count", &self.usage_count)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(permissions: Permissions, rp_id: Option<&str>) -> (PinUvAuthToken, SystemTime) {
+        let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let token = PinUvAuthToken::new([7; 32], permissions, rp_id.map(Into::into), issued_at);
+        (token, issued_at)
+    }
+
+    #[test]
+    fn authorize_checks_permissions_and_rp_id() {
+        let (mut token, now) = token(Permissions::MC, Some("example.com"));
+        assert_eq!(
+            token.authorize(Permissions::MC, Some("example.com"), now),
+            Ok(())
+        );
+        assert_eq!(
+            token.authorize(Permissions::GA, Some("example.com"), now),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        assert_eq!(
+            token.authorize(Permissions::MC, Some("other.example.com"), now),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        assert_eq!(token.usage_count(), 1);
+    }
+
+    #[test]
+    fn token_is_bound_to_first_rp() {
+        let (mut token, now) = token(Permissions::GA | Permissions::ACFG, None);
+        // Configuring the authenticator does not bind the token to an RP.
+        assert_eq!(token.authorize(Permissions::ACFG, None, now), Ok(()));
+        assert_eq!(token.rp_id(), None);
+
+        assert_eq!(
+            token.authorize(Permissions::GA, Some("example.com"), now),
+            Ok(())
+        );
+        assert_eq!(token.rp_id(), Some("example.com"));
+        assert_eq!(
+            token.authorize(Permissions::GA, Some("other.example.com"), now),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        assert_eq!(token.usage_count(), 2);
+        assert_eq!(token.first_used_at(), Some(now));
+    }
+
+    #[test]
+    fn clearing_permissions_keeps_lbw() {
+        let (mut token, now) = token(Permissions::MC | Permissions::GA | Permissions::LBW, None);
+        assert_eq!(
+            token.authorize(Permissions::MC, Some("example.com"), now),
+            Ok(())
+        );
+
+        token.clear_permissions_except_lbw();
+
+        assert_eq!(token.permissions(), Permissions::LBW);
+        assert_eq!(
+            token.authorize(Permissions::GA, Some("example.com"), now),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        assert_eq!(token.authorize(Permissions::LBW, None, now), Ok(()));
+    }
+
+    #[test]
+    fn token_expires() {
+        let (mut token, issued_at) = token(Permissions::GA, None);
+        let unused_for = issued_at + PinUvAuthToken::INITIAL_USAGE_TIME_LIMIT;
+        assert!(!token.is_expired(unused_for));
+        assert!(token.is_expired(unused_for + Duration::from_secs(1)));
+
+        // Using the token keeps it valid past the initial usage time limit.
+        token.authorize(Permissions::GA, None, unused_for).unwrap();
+        assert!(!token.is_expired(unused_for + Duration::from_secs(1)));
+
+        let max = issued_at + PinUvAuthToken::MAX_USAGE_TIME_PERIOD;
+        assert!(!token.is_expired(max));
+        assert_eq!(
+            token.authorize(Permissions::GA, None, max + Duration::from_secs(1)),
+            Err(Ctap2Error::PinAuthInvalid)
+        );
+        // A clock which went backwards does not expire the token.
+        assert!(!token.is_expired(issued_at - Duration::from_secs(60)));
+    }
+}
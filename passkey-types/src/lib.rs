@@ -34,14 +34,16 @@ mod utils;
 mod passkey;
 
 pub mod ctap2;
+#[cfg(feature = "mds")]
+pub mod mds;
 pub mod u2f;
 pub mod webauthn;
 
 // Re-exports
 pub use self::{
-    passkey::Passkey,
+    passkey::{CredRandoms, Passkey},
     utils::{
         bytes::{Bytes, NotBase64Encoded},
-        crypto, encoding, rand,
+        cose, crypto, encoding, rand, x509,
     },
 };
@@ -0,0 +1,499 @@
+//! Types for consuming the [FIDO Alliance Metadata Service] (MDS), enabled with the `mds` feature.
+//!
+//! The Metadata Service publishes a signed [`MetadataBlob`] which lists every certified
+//! authenticator model by its AAGUID, along with its [`MetadataStatement`] and the history of its
+//! certification and security status as [`StatusReport`]s.
+//!
+//! The BLOB is a JWT whose signature must be verified against the MDS root certificate before its
+//! contents are trusted. This crate does not ship an X.509 implementation, so verification is done
+//! through a [`BlobSignatureVerifier`] supplied by the caller.
+//!
+//! [FIDO Alliance Metadata Service]: https://fidoalliance.org/specs/mds/fido-metadata-service-v3.0-ps-20210518.html
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    ctap2::Aaguid,
+    encoding::{try_from_base64, try_from_base64url},
+    utils::serde::{base64_certificates, ignore_unknown_vec},
+};
+
+/// Reasons a [`MetadataBlob`] can fail to be parsed or verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MdsError {
+    /// The BLOB is not a JWS in compact serialization.
+    InvalidJwt,
+    /// The JWT header or payload could not be deserialized.
+    InvalidJson(String),
+    /// The [`BlobSignatureVerifier`] rejected the BLOB's signature.
+    InvalidSignature,
+}
+
+/// Hook for verifying the signature of a [`MetadataBlob`].
+///
+/// Implementations are expected to build a path from the certificates in the JWT header to the
+/// FIDO Alliance root certificate, check revocation, and verify the signature with the leaf
+/// certificate's public key.
+pub trait BlobSignatureVerifier {
+    /// Verify `signature` over `signed_data`, the ASCII `header.payload` part of the JWT.
+    ///
+    /// `header` contains the signing algorithm and the DER encoded certificate chain.
+    fn verify(
+        &self,
+        header: &BlobHeader,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<(), MdsError>;
+}
+
+/// The JOSE header of the [`MetadataBlob`] JWT.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobHeader {
+    /// The JWS signing algorithm, for example `ES256` or `RS256`.
+    pub alg: String,
+    /// The JWT type, if given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    /// The DER encoded signing certificate chain, leaf first.
+    #[serde(default, with = "base64_certificates")]
+    pub x5c: Vec<Vec<u8>>,
+}
+
+/// A parsed and verified Metadata BLOB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataBlob {
+    /// The JOSE header the BLOB was signed with.
+    pub header: BlobHeader,
+    /// The contents of the BLOB.
+    pub payload: MetadataBlobPayload,
+}
+
+impl MetadataBlob {
+    /// Parse the compact JWS serialization of a Metadata BLOB, verifying its signature with
+    /// `verifier` before the payload is deserialized.
+    pub fn from_jwt(jwt: &str, verifier: &dyn BlobSignatureVerifier) -> Result<Self, MdsError> {
+        let jwt = jwt.trim();
+        let (signed_data, signature) = jwt.rsplit_once('.').ok_or(MdsError::InvalidJwt)?;
+        let (header, payload) = signed_data.split_once('.').ok_or(MdsError::InvalidJwt)?;
+
+        let header: BlobHeader = from_base64url_json(header)?;
+        let signature = try_from_base64url(signature).ok_or(MdsError::InvalidJwt)?;
+        verifier.verify(&header, signed_data.as_bytes(), &signature)?;
+
+        Ok(Self {
+            header,
+            payload: from_base64url_json(payload)?,
+        })
+    }
+
+    /// Find the entry for the authenticator model with the given `aaguid`.
+    pub fn find_by_aaguid(&self, aaguid: &Aaguid) -> Option<&MetadataBlobPayloadEntry> {
+        self.payload.find_by_aaguid(aaguid)
+    }
+}
+
+fn from_base64url_json<T: for<'de> Deserialize<'de>>(input: &str) -> Result<T, MdsError> {
+    let json = try_from_base64url(input).ok_or(MdsError::InvalidJwt)?;
+    serde_json::from_slice(&json).map_err(|e| MdsError::InvalidJson(e.to_string()))
+}
+
+/// The payload of a [`MetadataBlob`].
+///
+/// <https://fidoalliance.org/specs/mds/fido-metadata-service-v3.0-ps-20210518.html#metadata-blob-payload-dictionary>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataBlobPayload {
+    /// The legal header which relying parties must accept before using the metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legal_header: Option<String>,
+    /// The serial number of this BLOB, incremented with every update.
+    pub no: u64,
+    /// The date by which the next BLOB is published, formatted as `YYYY-MM-DD`.
+    pub next_update: String,
+    /// The authenticator models described by this BLOB.
+    pub entries: Vec<MetadataBlobPayloadEntry>,
+}
+
+impl MetadataBlobPayload {
+    /// Find the entry for the authenticator model with the given `aaguid`.
+    pub fn find_by_aaguid(&self, aaguid: &Aaguid) -> Option<&MetadataBlobPayloadEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.aaguid.as_ref() == Some(aaguid))
+    }
+}
+
+/// The metadata of a single authenticator model.
+///
+/// <https://fidoalliance.org/specs/mds/fido-metadata-service-v3.0-ps-20210518.html#metadata-blob-payload-entry-dictionary>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataBlobPayloadEntry {
+    /// The AAID of UAF authenticators.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aaid: Option<String>,
+    /// The AAGUID of FIDO2 authenticators.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "aaguid_string"
+    )]
+    pub aaguid: Option<Aaguid>,
+    /// The hex encoded SHA-1 hashes of the public keys of attestation certificates, used by U2F
+    /// authenticators which have neither an AAID nor an AAGUID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_certificate_key_identifiers: Option<Vec<String>>,
+    /// The metadata statement of the authenticator model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_statement: Option<MetadataStatement>,
+    /// The status of the authenticator model, most recent last. Reports with a status unknown to
+    /// this library are skipped.
+    #[serde(deserialize_with = "ignore_unknown_vec")]
+    pub status_reports: Vec<StatusReport>,
+    /// The date of the most recent status change, formatted as `YYYY-MM-DD`.
+    pub time_of_last_status_change: String,
+}
+
+impl MetadataBlobPayloadEntry {
+    /// The most recent status report, which describes the current status of the model.
+    pub fn current_status(&self) -> Option<&StatusReport> {
+        self.status_reports.last()
+    }
+}
+
+/// The certification or security status of an authenticator model.
+///
+/// <https://fidoalliance.org/specs/mds/fido-metadata-service-v3.0-ps-20210518.html#authenticatorstatus-enum>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuthenticatorStatus {
+    /// The model is not FIDO certified.
+    NotFidoCertified,
+    /// The model passed FIDO functional certification.
+    FidoCertified,
+    /// Malware is able to bypass user verification.
+    UserVerificationBypass,
+    /// An attestation key of the model has been compromised.
+    AttestationKeyCompromise,
+    /// Private keys of the model can be extracted remotely.
+    UserKeyRemoteCompromise,
+    /// Private keys of the model can be extracted with physical access.
+    UserKeyPhysicalCompromise,
+    /// A firmware update is available.
+    UpdateAvailable,
+    /// The FIDO Alliance revoked the model's certification.
+    Revoked,
+    /// The vendor completed a self assertion of conformance.
+    SelfAssertionSubmitted,
+    /// The model passed level 1 security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L1")]
+    FidoCertifiedL1,
+    /// The model passed level 1+ security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L1plus")]
+    FidoCertifiedL1Plus,
+    /// The model passed level 2 security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L2")]
+    FidoCertifiedL2,
+    /// The model passed level 2+ security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L2plus")]
+    FidoCertifiedL2Plus,
+    /// The model passed level 3 security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L3")]
+    FidoCertifiedL3,
+    /// The model passed level 3+ security certification.
+    #[serde(rename = "FIDO_CERTIFIED_L3plus")]
+    FidoCertifiedL3Plus,
+}
+
+impl AuthenticatorStatus {
+    /// Whether this status indicates the model should no longer be trusted.
+    pub fn is_compromised(&self) -> bool {
+        matches!(
+            self,
+            Self::UserVerificationBypass
+                | Self::AttestationKeyCompromise
+                | Self::UserKeyRemoteCompromise
+                | Self::UserKeyPhysicalCompromise
+                | Self::Revoked
+        )
+    }
+}
+
+/// A change in the status of an authenticator model.
+///
+/// <https://fidoalliance.org/specs/mds/fido-metadata-service-v3.0-ps-20210518.html#statusreport-dictionary>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    /// The new status.
+    pub status: AuthenticatorStatus,
+    /// The date the status became effective, formatted as `YYYY-MM-DD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_date: Option<String>,
+    /// The lowest authenticator version this status applies to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authenticator_version: Option<u32>,
+    /// The base64 encoded certificate of a compromised attestation key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+    /// A URL with additional information about the status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The description of the certified product.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certification_descriptor: Option<String>,
+    /// The unique identifier of the certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_number: Option<String>,
+    /// The version of the certification policy the model was certified against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certification_policy_version: Option<String>,
+    /// The version of the security requirements the model was certified against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certification_requirements_version: Option<String>,
+}
+
+/// A version of the authenticator protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// The major version.
+    pub major: u16,
+    /// The minor version.
+    pub minor: u16,
+}
+
+/// The properties of an authenticator model as described by its vendor.
+///
+/// Only the members relevant to FIDO2 relying parties are typed, the less common ones are kept as
+/// raw JSON.
+///
+/// <https://fidoalliance.org/specs/mds/fido-metadata-statement-v3.0-ps-20210518.html#metadata-keys>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataStatement {
+    /// The legal header of the statement.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legal_header: Option<String>,
+    /// The AAID of UAF authenticators.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aaid: Option<String>,
+    /// The AAGUID of FIDO2 authenticators.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "aaguid_string"
+    )]
+    pub aaguid: Option<Aaguid>,
+    /// The attestation certificate key identifiers of U2F authenticators.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_certificate_key_identifiers: Option<Vec<String>>,
+    /// A human readable description of the model in English.
+    pub description: String,
+    /// The description in other languages, keyed by IETF language code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alternative_descriptions: Option<serde_json::Map<String, serde_json::Value>>,
+    /// The earliest firmware version this statement applies to.
+    pub authenticator_version: u32,
+    /// The protocol family, one of `uaf`, `u2f` or `fido2`.
+    pub protocol_family: String,
+    /// The version of the metadata statement schema.
+    pub schema: u16,
+    /// The supported versions of the authenticator protocol.
+    pub upv: Vec<Version>,
+    /// The supported signature algorithms, for example `secp256r1_ecdsa_sha256_raw`.
+    pub authentication_algorithms: Vec<String>,
+    /// The supported public key encodings, for example `cose`.
+    pub public_key_alg_and_encodings: Vec<String>,
+    /// The supported attestation types, for example `basic_full` or `self`.
+    pub attestation_types: Vec<String>,
+    /// The supported user verification methods.
+    pub user_verification_details: serde_json::Value,
+    /// How private keys are protected, for example `hardware` or `secure_element`.
+    pub key_protection: Vec<String>,
+    /// How the user verification matcher is protected.
+    pub matcher_protection: Vec<String>,
+    /// How the authenticator is attached to the client, for example `internal` or `nfc`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment_hint: Option<Vec<String>>,
+    /// The supported transaction confirmation displays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tc_display: Option<Vec<String>>,
+    /// The base64 encoded DER trust anchors for the model's attestation certificates.
+    pub attestation_root_certificates: Vec<String>,
+    /// A data URL of the model's icon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// The model's `authenticatorGetInfo` response, encoded as JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authenticator_get_info: Option<serde_json::Value>,
+    /// All other members of the statement.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MetadataStatement {
+    /// The DER encoded attestation root certificates.
+    ///
+    /// Certificates that are not valid base64 are skipped.
+    pub fn attestation_root_certificates_der(&self) -> Vec<Vec<u8>> {
+        self.attestation_root_certificates
+            .iter()
+            .filter_map(|cert| try_from_base64(cert))
+            .collect()
+    }
+}
+
+/// (De)serialize an [`Aaguid`] in its hyphenated UUID string form.
+mod aaguid_string {
+    use data_encoding::HEXLOWER_PERMISSIVE;
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(aaguid: &Option<Aaguid>, ser: S) -> Result<S::Ok, S::Error> {
+        let Some(aaguid) = aaguid else {
+            return ser.serialize_none();
+        };
+        let hex = HEXLOWER_PERMISSIVE.encode(&aaguid.0);
+        ser.serialize_str(&format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Aaguid>, D::Error> {
+        let Some(uuid) = Option::<String>::deserialize(de)? else {
+            return Ok(None);
+        };
+        let hex = uuid.replace('-', "");
+        HEXLOWER_PERMISSIVE
+            .decode(hex.as_bytes())
+            .ok()
+            .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+            .map(|bytes| Some(Aaguid::from(bytes)))
+            .ok_or_else(|| serde::de::Error::custom(format!("{uuid} is not a valid AAGUID")))
+    }
+}
+
+/// (De)serialize the base64 encoded certificates of the `x5c` header parameter.
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::encoding::base64url;
+
+    struct ExpectSignature(Vec<u8>);
+
+    impl BlobSignatureVerifier for ExpectSignature {
+        fn verify(
+            &self,
+            header: &BlobHeader,
+            _signed_data: &[u8],
+            signature: &[u8],
+        ) -> Result<(), MdsError> {
+            assert_eq!(header.x5c, vec![vec![1, 2, 3]]);
+            if signature == self.0 {
+                Ok(())
+            } else {
+                Err(MdsError::InvalidSignature)
+            }
+        }
+    }
+
+    fn jwt() -> String {
+        let header = json!({ "alg": "ES256", "typ": "JWT", "x5c": ["AQID"] });
+        let payload = json!({
+            "legalHeader": "Retrieval and use of this BLOB indicates acceptance of the terms.",
+            "no": 42,
+            "nextUpdate": "2024-06-01",
+            "entries": [{
+                "aaguid": "08987058-cadc-4b81-b6e1-30de50dcbe96",
+                "metadataStatement": {
+                    "aaguid": "08987058-cadc-4b81-b6e1-30de50dcbe96",
+                    "description": "Windows Hello Hardware Authenticator",
+                    "authenticatorVersion": 19041,
+                    "protocolFamily": "fido2",
+                    "schema": 3,
+                    "upv": [{ "major": 1, "minor": 0 }],
+                    "authenticationAlgorithms": ["rsassa_pkcsv15_sha256_raw"],
+                    "publicKeyAlgAndEncodings": ["cose"],
+                    "attestationTypes": ["attca"],
+                    "userVerificationDetails": [[{ "userVerificationMethod": "eyeprint_internal" }]],
+                    "keyProtection": ["hardware"],
+                    "matcherProtection": ["software"],
+                    "attachmentHint": ["internal"],
+                    "tcDisplay": [],
+                    "attestationRootCertificates": ["AQID"],
+                    "isKeyRestricted": false
+                },
+                "statusReports": [
+                    { "status": "FIDO_CERTIFIED_L1", "effectiveDate": "2020-08-05" },
+                    { "status": "SOMETHING_NEW" },
+                    { "status": "ATTESTATION_KEY_COMPROMISE", "effectiveDate": "2023-01-01" }
+                ],
+                "timeOfLastStatusChange": "2023-01-01"
+            }, {
+                "attestationCertificateKeyIdentifiers": ["923881fe2f214ee465484371aeb72e97f5a58e0a"],
+                "statusReports": [{ "status": "NOT_FIDO_CERTIFIED" }],
+                "timeOfLastStatusChange": "2018-05-19"
+            }]
+        });
+        format!(
+            "{}.{}.{}",
+            base64url(header.to_string().as_bytes()),
+            base64url(payload.to_string().as_bytes()),
+            base64url(b"signature")
+        )
+    }
+
+    #[test]
+    fn parse_metadata_blob() {
+        let blob = MetadataBlob::from_jwt(&jwt(), &ExpectSignature(b"signature".to_vec()))
+            .expect("failed to parse blob");
+        assert_eq!(blob.header.alg, "ES256");
+        assert_eq!(blob.payload.no, 42);
+        assert_eq!(blob.payload.entries.len(), 2);
+
+        let aaguid = Aaguid::from([
+            0x08, 0x98, 0x70, 0x58, 0xca, 0xdc, 0x4b, 0x81, 0xb6, 0xe1, 0x30, 0xde, 0x50, 0xdc,
+            0xbe, 0x96,
+        ]);
+        let entry = blob.find_by_aaguid(&aaguid).expect("missing entry");
+        assert_eq!(entry.status_reports.len(), 2);
+        let status = entry.current_status().expect("missing status").status;
+        assert_eq!(status, AuthenticatorStatus::AttestationKeyCompromise);
+        assert!(status.is_compromised());
+
+        let statement = entry
+            .metadata_statement
+            .as_ref()
+            .expect("missing statement");
+        assert_eq!(statement.aaguid, Some(aaguid));
+        assert_eq!(
+            statement.attestation_root_certificates_der(),
+            vec![vec![1, 2, 3]]
+        );
+        assert_eq!(statement.other.get("isKeyRestricted"), Some(&json!(false)));
+
+        let serialized = serde_json::to_value(entry).expect("failed to serialize entry");
+        assert_eq!(serialized["aaguid"], "08987058-cadc-4b81-b6e1-30de50dcbe96");
+
+        assert!(blob.find_by_aaguid(&Aaguid::new_empty()).is_none());
+    }
+
+    #[test]
+    fn reject_invalid_signature() {
+        assert_eq!(
+            MetadataBlob::from_jwt(&jwt(), &ExpectSignature(b"other".to_vec())),
+            Err(MdsError::InvalidSignature)
+        );
+        assert_eq!(
+            MetadataBlob::from_jwt("not a jwt", &ExpectSignature(Vec::new())),
+            Err(MdsError::InvalidJwt)
+        );
+    }
+}
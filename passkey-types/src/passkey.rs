@@ -1,8 +1,9 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, time::SystemTime};
 
 use super::u2f::{AuthenticationRequest, RegisterRequest, RegisterResponse};
 use crate::{ctap2::make_credential as ctap2, webauthn, Bytes};
 use coset::CoseKey;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// The private WebAuthn credential containing all relevant required and optional information for an
 /// authentication ceremony.
@@ -17,8 +18,12 @@ use coset::CoseKey;
 /// The rest of this struct should be considered secret, either for cryptographic security, or because
 /// its value could be used as PII.
 ///
+/// The private key material in [`Self::key`] is zeroized when the [`Passkey`] is dropped. Since
+/// version 0.2 it implements [`Drop`] to do so, which means that its fields can no longer be
+/// moved out of it, nor used in struct update syntax: clone them, or [`std::mem::take`] them out
+/// of a mutable [`Passkey`].
+///
 /// [cred-src]: https://w3c.github.io/webauthn/#public-key-credential-source
-// TODO: Implement Zeroize on this if/when rolling our own CoseKey type
 // TODO: use `#[non_exhaustive]` here with a builder pattern for building new passkeys
 #[derive(Clone)]
 pub struct Passkey {
@@ -28,6 +33,10 @@ pub struct Passkey {
     /// This value should be considered secret and never printed out as it is a secret cryptographic
     /// key. The only thing that get printed in the `Debug` implementation is the key type,
     /// e.g: EC2, RSA, etc.
+    ///
+    /// Authenticators that derive private keys from a device secret on every use may only store
+    /// the public key here, in which case the private key is recovered from the
+    /// [`Self::credential_id`].
     pub key: CoseKey,
 
     /// A probabilistically-unique byte sequence identifying this [`Passkey`]. It must be at most 1023
@@ -36,8 +45,8 @@ pub struct Passkey {
     /// Credential IDs are generated by authenticators in two forms:
     /// 1. At least 16 bytes that include at least 100 bits of entropy, or
     /// 2. The [`Passkey`] item, without its `credential_id`, encrypted so only its managing
-    /// authenticator can decrypt it. This form allows the authenticator to be nearly stateless, by
-    /// having the Relying Party store any necessary state.
+    ///    authenticator can decrypt it. This form allows the authenticator to be nearly stateless, by
+    ///    having the Relying Party store any necessary state.
     ///
     /// Relying Parties do not need to distinguish these two `credential id` forms.
     ///
@@ -83,9 +92,83 @@ pub struct Passkey {
     ///
     /// [signCount]: https://w3c.github.io/webauthn/#signature-counter
     pub counter: Option<u32>,
+
+    /// Whether this [`Passkey`] was registered for [Secure Payment Confirmation][SPC], which lets
+    /// parties other than its Relying Party use it to confirm payments.
+    ///
+    /// [SPC]: https://w3c.github.io/secure-payment-confirmation/
+    pub third_party_payment: bool,
+
+    /// Whether this [`Passkey`] may be backed up, for example by syncing it to other devices,
+    /// which is reported to the Relying Party as the [Backup Eligibility][BE] flag. It must not
+    /// change once the credential is created.
+    ///
+    /// [BE]: https://w3c.github.io/webauthn/#backup-eligibility
+    pub backup_eligible: bool,
+
+    /// Whether this [`Passkey`] is currently backed up, which is reported to the Relying Party as
+    /// the [Backup State][BS] flag. Only a backup eligible credential may be backed up.
+    ///
+    /// [BS]: https://w3c.github.io/webauthn/#backup-state
+    pub backup_state: bool,
+
+    /// The CredRandoms of the `hmac-secret` extension, when this [`Passkey`] was imported from
+    /// another authenticator along with them. Authenticators otherwise derive them from the
+    /// private key.
+    ///
+    /// # PII considerations
+    /// These values should be considered secret, they are zeroized when dropped.
+    pub cred_randoms: Option<CredRandoms>,
+
+    /// When this [`Passkey`] was created. When several credentials of a Relying Party are found
+    /// for an assertion which allows any of them, the most recently created one is used.
+    ///
+    /// It is `None` when unknown, for example for credentials wrapped into or derived from their
+    /// credential ID, or created before it was recorded.
+    pub created_at: Option<SystemTime>,
+
+    /// When this [`Passkey`] was last used for an assertion, or `None` if it never was or it is not
+    /// known. Authenticators record it in the credential stores which support it.
+    pub last_used_at: Option<SystemTime>,
+}
+
+/// The secrets from which the `hmac-secret` extension computes its outputs, and so the outputs of
+/// WebAuthn's `prf` extension. Importing them along with a [`Passkey`] keeps its outputs identical
+/// to those of the authenticator it was exported from.
+///
+/// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-hmac-secret-extension>
+#[derive(Clone)]
+pub struct CredRandoms {
+    /// The CredRandom used when the user is verified.
+    pub with_uv: [u8; 32],
+    /// The CredRandom used when the user is not verified, absent when it was not exported.
+    pub without_uv: Option<[u8; 32]>,
+}
+
+impl Drop for CredRandoms {
+    fn drop(&mut self) {
+        self.with_uv.zeroize();
+        self.without_uv.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for CredRandoms {}
+
+impl Debug for CredRandoms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CredRandoms").finish_non_exhaustive()
+    }
 }
 
 impl Passkey {
+    /// Derive the public [`CoseKey`] of this credential from its private [`Self::key`].
+    ///
+    /// This is useful to re-register or sync a credential without access to the public key that
+    /// was returned when it was created.
+    pub fn public_key(&self) -> Result<CoseKey, crate::cose::CoseKeyError> {
+        crate::cose::public_key_from_private(&self.key)
+    }
+
     /// Standardised way to "upgrade" a U2F register request into a passkey
     pub fn from_u2f_register_response(
         request: &RegisterRequest,
@@ -99,6 +182,12 @@ impl Passkey {
             rp_id: app_id.into(),
             user_handle: None,
             counter: Some(0),
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
         }
     }
 
@@ -115,6 +204,12 @@ impl Passkey {
             rp_id: app_id.into(),
             user_handle: None,
             counter: Some(counter),
+            third_party_payment: false,
+            backup_eligible: true,
+            backup_state: true,
+            cred_randoms: None,
+            created_at: None,
+            last_used_at: None,
         }
     }
 
@@ -150,10 +245,10 @@ impl Passkey {
 }
 
 impl From<Passkey> for webauthn::PublicKeyCredentialDescriptor {
-    fn from(value: Passkey) -> Self {
+    fn from(mut value: Passkey) -> Self {
         Self {
             ty: webauthn::PublicKeyCredentialType::PublicKey,
-            id: value.credential_id,
+            id: std::mem::take(&mut value.credential_id),
             transports: None,
         }
     }
@@ -169,6 +264,14 @@ impl From<&Passkey> for webauthn::PublicKeyCredentialDescriptor {
     }
 }
 
+impl Drop for Passkey {
+    fn drop(&mut self) {
+        crate::crypto::zeroize_cose_key(&mut self.key);
+    }
+}
+
+impl ZeroizeOnDrop for Passkey {}
+
 impl Debug for Passkey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Passkey")
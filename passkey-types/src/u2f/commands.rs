@@ -66,7 +66,11 @@ pub struct Request {
     pub data: RequestPayload,
 }
 
-const REQUEST_HEADER_LEN: usize = 6;
+/// `CLA`, `INS`, `P1`, `P2` and the 3 byte extended length `Lc`.
+const REQUEST_HEADER_LEN: usize = 7;
+
+/// The challenge and application parameters of a request, 32 bytes each.
+const PARAMETERS_LEN: usize = 64;
 
 impl TryFrom<&[u8]> for Request {
     type Error = ResponseStatusWords;
@@ -83,21 +87,37 @@ impl TryFrom<&[u8]> for Request {
         }
         let ins = Command::from(value[1]);
         let p1 = value[2];
-        let data_start = REQUEST_HEADER_LEN + 1;
+        let data_start = REQUEST_HEADER_LEN;
         // SAFETY: This unwrap is safe since 3..7 gives 4 bytes which is a safe conversion to an
         // array of len 4. Technically the first of these bytes is `p2` the second parameter,
         // but in the base U2F spec this will always be 0. So this length is safe.
         let data_len = u32::from_be_bytes(value[3..data_start].try_into().unwrap()) as usize;
         let data_end = data_start + data_len;
-        let payload = &value[data_start..data_end];
+        let payload = value
+            .get(data_start..data_end)
+            .ok_or(ResponseStatusWords::WrongLength)?;
 
         let data = match ins {
+            Command::Register if payload.len() != PARAMETERS_LEN => {
+                return Err(ResponseStatusWords::WrongLength)
+            }
             Command::Register => RequestPayload::Register(
                 payload
                     .try_into()
                     // Wrong length because it must be two SHA256's which are 32 bytes each
                     .map_err(|_| ResponseStatusWords::WrongLength)?,
             ),
+            Command::Authenticate if !matches!(p1, 0x03 | 0x07 | 0x08) => {
+                return Err(ResponseStatusWords::WrongData)
+            }
+            // The parameters are followed by the key handle's length and the key handle.
+            Command::Authenticate
+                if payload.len() <= PARAMETERS_LEN
+                    || payload.len()
+                        < PARAMETERS_LEN + 1 + usize::from(payload[PARAMETERS_LEN]) =>
+            {
+                return Err(ResponseStatusWords::WrongLength)
+            }
             Command::Authenticate => RequestPayload::Authenticate(
                 AuthenticationRequest::try_from(payload, p1)
                     .map_err(|_| ResponseStatusWords::WrongLength)?,
@@ -6,6 +6,8 @@ pub(crate) mod serde;
 #[macro_use]
 pub(crate) mod serde_workaround;
 
+pub mod cose;
 pub mod crypto;
 pub mod encoding;
 pub mod rand;
+pub mod x509;
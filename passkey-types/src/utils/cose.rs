@@ -0,0 +1,490 @@
+//! Validation of [`CoseKey`]s before they are used in cryptographic operations, and their
+//! conversion to other encodings.
+//!
+//! Keys read back from a credential store may have been corrupted or crafted. Validating them up
+//! front gives a precise [`CoseKeyError`] rather than an opaque failure deep within an assertion.
+
+use coset::{
+    cbor::value::Value,
+    iana::{self, EnumI64},
+    CoseKey, CoseKeyBuilder, KeyType, Label, RegisteredLabelWithPrivate,
+};
+use p256::{
+    elliptic_curve::{
+        generic_array::typenum::Unsigned,
+        sec1::{EncodedPoint, FromEncodedPoint, ModulusSize, ToEncodedPoint},
+        AffinePoint, CurveArithmetic, FieldBytes, FieldBytesSize, PublicKey, SecretKey,
+    },
+    pkcs8::EncodePublicKey,
+};
+
+use crate::{ctap2::Ctap2Error, Bytes};
+
+/// Reasons a [`CoseKey`] can fail validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoseKeyError {
+    /// The key does not declare an algorithm.
+    MissingAlgorithm,
+    /// The key's algorithm is not supported.
+    UnsupportedAlgorithm,
+    /// The key type is not the one required by the key's algorithm.
+    KeyTypeMismatch,
+    /// The curve is missing or is not the one required by the key's algorithm.
+    CurveMismatch,
+    /// A parameter required by the key type is missing or is not a byte string.
+    MissingParameter,
+    /// A coordinate or private scalar has the wrong length for the key's curve.
+    InvalidLength,
+    /// The public point is not on the key's curve.
+    PointNotOnCurve,
+    /// The private scalar is not a valid scalar for the key's curve.
+    InvalidPrivateKey,
+    /// The private key does not correspond to the public key stored alongside it.
+    KeyMismatch,
+}
+
+impl From<CoseKeyError> for Ctap2Error {
+    fn from(src: CoseKeyError) -> Self {
+        match src {
+            CoseKeyError::MissingAlgorithm | CoseKeyError::UnsupportedAlgorithm => {
+                Ctap2Error::UnsupportedAlgorithm
+            }
+            _ => Ctap2Error::InvalidCredential,
+        }
+    }
+}
+
+/// Validate the public components of a [`CoseKey`].
+///
+/// This checks that the key type and curve match the algorithm, that every coordinate has the
+/// correct length and that the public point lies on the curve.
+pub fn validate_public_key(key: &CoseKey) -> Result<(), CoseKeyError> {
+    match algorithm(key)? {
+        iana::Algorithm::ES256 => {
+            ec2_public_key::<p256::NistP256>(key, iana::EllipticCurve::P_256).map(|_| ())
+        }
+        #[cfg(feature = "es256k")]
+        iana::Algorithm::ES256K => {
+            ec2_public_key::<k256::Secp256k1>(key, iana::EllipticCurve::Secp256k1).map(|_| ())
+        }
+        iana::Algorithm::EdDSA => ed25519_public_key(key).map(|_| ()),
+        _ => Err(CoseKeyError::UnsupportedAlgorithm),
+    }
+}
+
+/// Validate a private [`CoseKey`].
+///
+/// On top of the checks done by [`validate_public_key`], this checks that the private scalar has
+/// the correct length, is valid for the curve and corresponds to the public key.
+pub fn validate_private_key(key: &CoseKey) -> Result<(), CoseKeyError> {
+    match algorithm(key)? {
+        iana::Algorithm::ES256 => {
+            ec2_private_key::<p256::NistP256>(key, iana::EllipticCurve::P_256)
+        }
+        #[cfg(feature = "es256k")]
+        iana::Algorithm::ES256K => {
+            ec2_private_key::<k256::Secp256k1>(key, iana::EllipticCurve::Secp256k1)
+        }
+        iana::Algorithm::EdDSA => {
+            let public = ed25519_public_key(key)?;
+            let d = fixed_len::<{ ed25519_dalek::SECRET_KEY_LENGTH }>(bytes_param(
+                key,
+                iana::OkpKeyParameter::D.to_i64(),
+            )?)?;
+            if ed25519_dalek::SigningKey::from_bytes(&d).verifying_key() != public {
+                return Err(CoseKeyError::KeyMismatch);
+            }
+            Ok(())
+        }
+        _ => Err(CoseKeyError::UnsupportedAlgorithm),
+    }
+}
+
+/// Derive the public [`CoseKey`] of a private [`CoseKey`].
+///
+/// The public components are recomputed from the private scalar rather than copied, so the result
+/// is correct even if the stored coordinates are not. The algorithm and key ID are preserved.
+pub fn public_key_from_private(key: &CoseKey) -> Result<CoseKey, CoseKeyError> {
+    let alg = algorithm(key)?;
+    let builder = match alg {
+        iana::Algorithm::ES256 => {
+            ec2_derive_public::<p256::NistP256>(key, iana::EllipticCurve::P_256)?
+        }
+        #[cfg(feature = "es256k")]
+        iana::Algorithm::ES256K => {
+            ec2_derive_public::<k256::Secp256k1>(key, iana::EllipticCurve::Secp256k1)?
+        }
+        iana::Algorithm::EdDSA => {
+            check_shape(
+                key,
+                iana::KeyType::OKP,
+                iana::OkpKeyParameter::Crv.to_i64(),
+                iana::EllipticCurve::Ed25519,
+            )?;
+            let d = fixed_len::<{ ed25519_dalek::SECRET_KEY_LENGTH }>(bytes_param(
+                key,
+                iana::OkpKeyParameter::D.to_i64(),
+            )?)?;
+            let x = ed25519_dalek::SigningKey::from_bytes(&d)
+                .verifying_key()
+                .to_bytes()
+                .to_vec();
+            CoseKeyBuilder::new_okp_key()
+                .param(
+                    iana::OkpKeyParameter::Crv.to_i64(),
+                    Value::from(iana::EllipticCurve::Ed25519.to_i64()),
+                )
+                .param(iana::OkpKeyParameter::X.to_i64(), Value::Bytes(x))
+        }
+        _ => return Err(CoseKeyError::UnsupportedAlgorithm),
+    };
+    Ok(builder.algorithm(alg).key_id(key.key_id.clone()).build())
+}
+
+/// Encode the public key of a [`CoseKey`] as a X.509 SubjectPublicKeyInfo, once validated with
+/// [`validate_public_key`].
+///
+/// This should be used by the client when creating the [Easy Credential Data Accessors][ez], and
+/// to verify signatures made with the key.
+///
+/// [ez]: https://w3c.github.io/webauthn/#sctn-public-key-easy
+pub fn public_key_der_from_cose_key(key: &CoseKey) -> Result<Bytes, CoseKeyError> {
+    let der = match algorithm(key)? {
+        iana::Algorithm::ES256 => {
+            ec2_public_key::<p256::NistP256>(key, iana::EllipticCurve::P_256)?.to_public_key_der()
+        }
+        #[cfg(feature = "es256k")]
+        iana::Algorithm::ES256K => {
+            ec2_public_key::<k256::Secp256k1>(key, iana::EllipticCurve::Secp256k1)?
+                .to_public_key_der()
+        }
+        iana::Algorithm::EdDSA => ed25519_public_key(key)?.to_public_key_der(),
+        _ => return Err(CoseKeyError::UnsupportedAlgorithm),
+    };
+    // SAFETY: encoding a valid public key only fails if the allocation of its encoding does.
+    Ok(der.unwrap().as_ref().to_vec().into())
+}
+
+/// The X and Y coordinates of an EC2 [`CoseKey`], as they are stored in the key. They are not
+/// validated, see [`validate_public_key`].
+pub fn ec2_coordinates(key: &CoseKey) -> Result<(&[u8], &[u8]), CoseKeyError> {
+    if key.kty != KeyType::Assigned(iana::KeyType::EC2) {
+        return Err(CoseKeyError::KeyTypeMismatch);
+    }
+    Ok((
+        bytes_param(key, iana::Ec2KeyParameter::X.to_i64())?,
+        bytes_param(key, iana::Ec2KeyParameter::Y.to_i64())?,
+    ))
+}
+
+fn algorithm(key: &CoseKey) -> Result<iana::Algorithm, CoseKeyError> {
+    match key.alg {
+        Some(RegisteredLabelWithPrivate::Assigned(alg)) => Ok(alg),
+        Some(_) => Err(CoseKeyError::UnsupportedAlgorithm),
+        None => Err(CoseKeyError::MissingAlgorithm),
+    }
+}
+
+fn param(key: &CoseKey, label: i64) -> Option<&Value> {
+    key.params
+        .iter()
+        .find(|(k, _)| k == &Label::Int(label))
+        .map(|(_, v)| v)
+}
+
+fn bytes_param(key: &CoseKey, label: i64) -> Result<&[u8], CoseKeyError> {
+    param(key, label)
+        .and_then(Value::as_bytes)
+        .map(Vec::as_slice)
+        .ok_or(CoseKeyError::MissingParameter)
+}
+
+fn fixed_len<const N: usize>(bytes: &[u8]) -> Result<[u8; N], CoseKeyError> {
+    bytes.try_into().map_err(|_| CoseKeyError::InvalidLength)
+}
+
+fn check_shape(
+    key: &CoseKey,
+    kty: iana::KeyType,
+    crv_label: i64,
+    crv: iana::EllipticCurve,
+) -> Result<(), CoseKeyError> {
+    if key.kty != KeyType::Assigned(kty) {
+        return Err(CoseKeyError::KeyTypeMismatch);
+    }
+    if param(key, crv_label).and_then(Value::as_integer) != Some(crv.to_i64().into()) {
+        return Err(CoseKeyError::CurveMismatch);
+    }
+    Ok(())
+}
+
+fn ec2_public_key<C>(key: &CoseKey, crv: iana::EllipticCurve) -> Result<PublicKey<C>, CoseKeyError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    check_shape(
+        key,
+        iana::KeyType::EC2,
+        iana::Ec2KeyParameter::Crv.to_i64(),
+        crv,
+    )?;
+    let x = field_bytes::<C>(bytes_param(key, iana::Ec2KeyParameter::X.to_i64())?)?;
+    let y = field_bytes::<C>(bytes_param(key, iana::Ec2KeyParameter::Y.to_i64())?)?;
+    let point = EncodedPoint::<C>::from_affine_coordinates(x, y, false);
+    Option::from(PublicKey::<C>::from_encoded_point(&point)).ok_or(CoseKeyError::PointNotOnCurve)
+}
+
+fn ec2_private_key<C>(key: &CoseKey, crv: iana::EllipticCurve) -> Result<(), CoseKeyError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    let public = ec2_public_key::<C>(key, crv)?;
+    let d = field_bytes::<C>(bytes_param(key, iana::Ec2KeyParameter::D.to_i64())?)?;
+    let secret = SecretKey::<C>::from_bytes(d).map_err(|_| CoseKeyError::InvalidPrivateKey)?;
+    if secret.public_key() != public {
+        return Err(CoseKeyError::KeyMismatch);
+    }
+    Ok(())
+}
+
+fn ec2_derive_public<C>(
+    key: &CoseKey,
+    crv: iana::EllipticCurve,
+) -> Result<CoseKeyBuilder, CoseKeyError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    check_shape(
+        key,
+        iana::KeyType::EC2,
+        iana::Ec2KeyParameter::Crv.to_i64(),
+        crv,
+    )?;
+    let d = field_bytes::<C>(bytes_param(key, iana::Ec2KeyParameter::D.to_i64())?)?;
+    let secret = SecretKey::<C>::from_bytes(d).map_err(|_| CoseKeyError::InvalidPrivateKey)?;
+    let point = secret.public_key().to_encoded_point(false);
+    // SAFETY: the point is uncompressed and not the identity, so both coordinates are present.
+    let x = point.x().unwrap().to_vec();
+    let y = point.y().unwrap().to_vec();
+    Ok(CoseKeyBuilder::new_ec2_pub_key(crv, x, y))
+}
+
+fn field_bytes<C: CurveArithmetic>(bytes: &[u8]) -> Result<&FieldBytes<C>, CoseKeyError> {
+    if bytes.len() != C::FieldBytesSize::USIZE {
+        return Err(CoseKeyError::InvalidLength);
+    }
+    Ok(bytes.into())
+}
+
+fn ed25519_public_key(key: &CoseKey) -> Result<ed25519_dalek::VerifyingKey, CoseKeyError> {
+    check_shape(
+        key,
+        iana::KeyType::OKP,
+        iana::OkpKeyParameter::Crv.to_i64(),
+        iana::EllipticCurve::Ed25519,
+    )?;
+    let x = fixed_len::<{ ed25519_dalek::PUBLIC_KEY_LENGTH }>(bytes_param(
+        key,
+        iana::OkpKeyParameter::X.to_i64(),
+    )?)?;
+    ed25519_dalek::VerifyingKey::from_bytes(&x).map_err(|_| CoseKeyError::PointNotOnCurve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coset::{cbor::value::Value, iana, iana::EnumI64};
+
+    fn p256_key(secret: &p256::SecretKey) -> CoseKey {
+        let point = secret.public_key().to_encoded_point(false);
+        CoseKeyBuilder::new_ec2_priv_key(
+            iana::EllipticCurve::P_256,
+            point.x().unwrap().to_vec(),
+            point.y().unwrap().to_vec(),
+            secret.to_bytes().to_vec(),
+        )
+        .algorithm(iana::Algorithm::ES256)
+        .build()
+    }
+
+    fn ed25519_key(secret: [u8; 32]) -> CoseKey {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&secret);
+        CoseKeyBuilder::new_okp_key()
+            .algorithm(iana::Algorithm::EdDSA)
+            .param(
+                iana::OkpKeyParameter::Crv.to_i64(),
+                Value::from(iana::EllipticCurve::Ed25519.to_i64()),
+            )
+            .param(
+                iana::OkpKeyParameter::X.to_i64(),
+                Value::Bytes(signing_key.verifying_key().to_bytes().to_vec()),
+            )
+            .param(
+                iana::OkpKeyParameter::D.to_i64(),
+                Value::Bytes(secret.to_vec()),
+            )
+            .build()
+    }
+
+    fn set_param(key: &mut CoseKey, label: i64, value: Value) {
+        for (k, v) in key.params.iter_mut() {
+            if k == &Label::Int(label) {
+                *v = value;
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn valid_keys_pass() {
+        let key = p256_key(&p256::SecretKey::random(&mut rand::thread_rng()));
+        assert_eq!(validate_public_key(&key), Ok(()));
+        assert_eq!(validate_private_key(&key), Ok(()));
+
+        let key = ed25519_key(rand::random());
+        assert_eq!(validate_public_key(&key), Ok(()));
+        assert_eq!(validate_private_key(&key), Ok(()));
+    }
+
+    #[test]
+    fn public_keys_are_encoded_as_spki() {
+        let secret = p256::SecretKey::random(&mut rand::thread_rng());
+        let key = p256_key(&secret);
+        let point = secret.public_key().to_encoded_point(false);
+        assert_eq!(
+            ec2_coordinates(&key),
+            Ok((&point.x().unwrap()[..], &point.y().unwrap()[..]))
+        );
+        let der = public_key_der_from_cose_key(&key).unwrap();
+        assert_eq!(
+            der.as_slice(),
+            secret.public_key().to_public_key_der().unwrap().as_bytes()
+        );
+
+        let secret = rand::random();
+        let key = ed25519_key(secret);
+        assert_eq!(ec2_coordinates(&key), Err(CoseKeyError::KeyTypeMismatch));
+        let der = public_key_der_from_cose_key(&key).unwrap();
+        assert_eq!(
+            der.as_slice(),
+            ed25519_dalek::SigningKey::from_bytes(&secret)
+                .verifying_key()
+                .to_public_key_der()
+                .unwrap()
+                .as_bytes()
+        );
+    }
+
+    #[test]
+    fn algorithm_must_match_key() {
+        let mut key = p256_key(&p256::SecretKey::random(&mut rand::thread_rng()));
+        key.alg = None;
+        assert_eq!(
+            validate_public_key(&key),
+            Err(CoseKeyError::MissingAlgorithm)
+        );
+
+        key.alg = Some(RegisteredLabelWithPrivate::Assigned(iana::Algorithm::EdDSA));
+        assert_eq!(
+            validate_public_key(&key),
+            Err(CoseKeyError::KeyTypeMismatch)
+        );
+
+        key.alg = Some(RegisteredLabelWithPrivate::Assigned(iana::Algorithm::ES256));
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::Crv.to_i64(),
+            Value::from(iana::EllipticCurve::P_384.to_i64()),
+        );
+        assert_eq!(validate_public_key(&key), Err(CoseKeyError::CurveMismatch));
+    }
+
+    #[test]
+    fn malformed_points_are_rejected() {
+        let mut key = p256_key(&p256::SecretKey::random(&mut rand::thread_rng()));
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::Y.to_i64(),
+            Value::Bytes(vec![1; 16]),
+        );
+        assert_eq!(validate_public_key(&key), Err(CoseKeyError::InvalidLength));
+
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::Y.to_i64(),
+            Value::Bytes(vec![1; 32]),
+        );
+        assert_eq!(
+            validate_public_key(&key),
+            Err(CoseKeyError::PointNotOnCurve)
+        );
+    }
+
+    #[test]
+    fn private_key_must_match_public_key() {
+        let mut key = p256_key(&p256::SecretKey::random(&mut rand::thread_rng()));
+        let other = p256::SecretKey::random(&mut rand::thread_rng());
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::D.to_i64(),
+            Value::Bytes(other.to_bytes().to_vec()),
+        );
+        assert_eq!(validate_public_key(&key), Ok(()));
+        assert_eq!(validate_private_key(&key), Err(CoseKeyError::KeyMismatch));
+
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::D.to_i64(),
+            Value::Bytes(vec![0; 32]),
+        );
+        assert_eq!(
+            validate_private_key(&key),
+            Err(CoseKeyError::InvalidPrivateKey)
+        );
+
+        let mut key = ed25519_key(rand::random());
+        set_param(
+            &mut key,
+            iana::OkpKeyParameter::D.to_i64(),
+            Value::Bytes(rand::random::<[u8; 32]>().to_vec()),
+        );
+        assert_eq!(validate_private_key(&key), Err(CoseKeyError::KeyMismatch));
+    }
+
+    #[test]
+    fn public_key_is_derived_from_private_key() {
+        let secret = p256::SecretKey::random(&mut rand::thread_rng());
+        let point = secret.public_key().to_encoded_point(false);
+        let expected = CoseKeyBuilder::new_ec2_pub_key(
+            iana::EllipticCurve::P_256,
+            point.x().unwrap().to_vec(),
+            point.y().unwrap().to_vec(),
+        )
+        .algorithm(iana::Algorithm::ES256)
+        .build();
+        let mut key = p256_key(&secret);
+        // Corrupted coordinates should not matter, only the private scalar is used.
+        set_param(
+            &mut key,
+            iana::Ec2KeyParameter::X.to_i64(),
+            Value::Bytes(vec![0; 32]),
+        );
+        assert_eq!(public_key_from_private(&key), Ok(expected));
+
+        let secret: [u8; 32] = rand::random();
+        let key = ed25519_key(secret);
+        let public = public_key_from_private(&key).expect("could not derive public key");
+        assert_eq!(validate_public_key(&public), Ok(()));
+        assert!(bytes_param(&public, iana::OkpKeyParameter::D.to_i64()).is_err());
+        assert_eq!(
+            bytes_param(&public, iana::OkpKeyParameter::X.to_i64()),
+            bytes_param(&key, iana::OkpKeyParameter::X.to_i64())
+        );
+    }
+}
@@ -1,9 +1,49 @@
 //! Collection of common cryptography primitives used in serialization of types.
 
+use coset::{cbor::value::Value, CoseKey};
 use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
 
 /// Compute the SHA-256 of the given `data`.
 pub fn sha256(data: &[u8]) -> [u8; 32] {
     // SAFETY: sha256 always gives a 32 byte array
     Sha256::digest(data).into()
 }
+
+/// Overwrite all the byte string parameters of a [`CoseKey`] with zeroes.
+///
+/// This clears the private key material, such as the `d` parameter of EC2 and OKP keys, so it does
+/// not linger in memory once the key is no longer needed.
+pub fn zeroize_cose_key(key: &mut CoseKey) {
+    for (_, value) in key.params.iter_mut() {
+        if let Value::Bytes(bytes) = value {
+            bytes.zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coset::{cbor::value::Value, iana, CoseKeyBuilder};
+
+    use super::zeroize_cose_key;
+
+    #[test]
+    fn zeroize_cose_key_clears_byte_params() {
+        let mut key = CoseKeyBuilder::new_ec2_priv_key(
+            iana::EllipticCurve::P_256,
+            vec![1; 32],
+            vec![2; 32],
+            vec![3; 32],
+        )
+        .build();
+
+        zeroize_cose_key(&mut key);
+
+        for (_, value) in &key.params {
+            if let Value::Bytes(bytes) = value {
+                assert!(bytes.is_empty());
+            }
+        }
+    }
+}
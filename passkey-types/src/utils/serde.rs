@@ -15,10 +15,7 @@ where
     D: Deserializer<'de>,
     T: Deserialize<'de> + Default,
 {
-    Ok(match T::deserialize(de) {
-        Ok(val) => val,
-        Err(_) => T::default(),
-    })
+    Ok(T::deserialize(de).unwrap_or_default())
 }
 
 #[derive(Debug, Default)]
@@ -122,6 +119,51 @@ pub mod i64_to_iana {
     }
 }
 
+/// (De)serialize DER certificates as a list of `base64` strings, as in the `x5c` header of a JWS.
+pub(crate) mod base64_certificates {
+    use data_encoding::BASE64;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::encoding::try_from_base64;
+
+    pub fn serialize<S: Serializer>(certificates: &[Vec<u8>], ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(certificates.iter().map(|cert| BASE64.encode(cert)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(de)?
+            .iter()
+            .map(|cert| {
+                try_from_base64(cert)
+                    .ok_or_else(|| serde::de::Error::custom("certificate is not valid base64"))
+            })
+            .collect()
+    }
+}
+
+/// (De)serialize an optional [`coset::CoseKey`] through its CBOR representation, for the fields of
+/// CTAP2 messages holding keys such as the PIN protocol's `keyAgreement`.
+pub(crate) mod cose_key_opt {
+    use ciborium::value::Value;
+    use coset::{AsCborValue, CoseKey};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &Option<CoseKey>, ser: S) -> Result<S::Ok, S::Error> {
+        key.clone()
+            .map(CoseKey::to_cbor_value)
+            .transpose()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(ser)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<CoseKey>, D::Error> {
+        Option::<Value>::deserialize(de)?
+            .map(CoseKey::from_cbor_value)
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 struct StringOrNum<T>(pub std::marker::PhantomData<T>);
 
 impl<'de, T> Visitor<'de> for StringOrNum<T>
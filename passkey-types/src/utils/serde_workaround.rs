@@ -11,7 +11,7 @@ macro_rules! serde_workaround {
         $(#[$attr:meta])*
         pub struct $name:ident {$(
             $(#[doc=$doc:literal])*
-            #[serde(rename = $discriminant:literal$(,$default:ident)?$(,skip_serializing_if = $method:path)?$(,deserialize_with = $de:path)?)]
+            #[serde(rename = $discriminant:literal$(,$default:ident)?$(,skip_serializing_if = $method:path)?$(,serialize_with = $ser:path)?$(,deserialize_with = $de:path)?)]
             $vis:vis $field:ident: $ty:ty,
         )*}
     ) => {
@@ -59,7 +59,7 @@ macro_rules! serde_workaround {
                 {
                     let mut serde_state = serde::Serializer::serialize_map(serializer, Some(struct_len(&self)))?;
                     $(
-                        serde_serialize_entry!{serde_state; self.$field $(;$method)?}
+                        serde_serialize_entry!{serde_state; self.$field; $ty $(; skip $method)? $(; with $ser)?}
                     )*
                     serde_state.end()
                 }
@@ -184,12 +184,24 @@ macro_rules! serde_workaround_struct_len {
 }
 
 macro_rules! serde_serialize_entry {
-    ($state:ident; $self:ident.$field:ident; $skip_if:path) => {
+    ($state:ident; $self:ident.$field:ident; $ty:ty; skip $skip_if:path $(; with $ser:path)?) => {
         if !$skip_if(&$self.$field) {
-            serde_serialize_entry!($state; $self.$field)
+            serde_serialize_entry!($state; $self.$field; $ty $(; with $ser)?)
         }
     };
-    ($state:ident; $self:ident.$field:ident) => {
+    ($state:ident; $self:ident.$field:ident; $ty:ty; with $ser_with:path) => {{
+        struct __SerializeWith<'a>(&'a $ty);
+        impl Serialize for __SerializeWith<'_> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                $ser_with(self.0, serializer)
+            }
+        }
+        $state.serialize_entry(&Ident::$field, &__SerializeWith(&$self.$field))?
+    }};
+    ($state:ident; $self:ident.$field:ident; $ty:ty) => {
         $state.serialize_entry(&Ident::$field, &$self.$field)?
     };
 }
@@ -0,0 +1,323 @@
+//! Minimal parsing of the X.509 certificates found in attestation statements.
+//!
+//! This only extracts the fields needed to process attestation: the version, the subject, the
+//! public key and the extensions defined by WebAuthn. It does not verify signatures or build
+//! certificate paths.
+
+use der::{
+    asn1::{AnyRef, ObjectIdentifier, OctetStringRef},
+    Decode, Reader, SliceReader, Tag, TagNumber, Tagged,
+};
+
+use crate::ctap2::Aaguid;
+
+/// OID of the subject's country name attribute.
+pub const COUNTRY_NAME: &str = "2.5.4.6";
+/// OID of the subject's organization name attribute.
+pub const ORGANIZATION_NAME: &str = "2.5.4.10";
+/// OID of the subject's organizational unit name attribute.
+pub const ORGANIZATIONAL_UNIT_NAME: &str = "2.5.4.11";
+/// OID of the subject's common name attribute.
+pub const COMMON_NAME: &str = "2.5.4.3";
+
+/// OID of the subject alternative name extension.
+pub const SUBJECT_ALT_NAME_EXTENSION: &str = "2.5.29.17";
+/// OID of the extended key usage extension, see [`Certificate::extended_key_usage`].
+pub const EXTENDED_KEY_USAGE_EXTENSION: &str = "2.5.29.37";
+
+/// The `id-fido-gen-ce-aaguid` extension which holds the AAGUID of the authenticator model the
+/// attestation certificate was issued for.
+pub const AAGUID_EXTENSION: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.45724.1.1.4");
+const BASIC_CONSTRAINTS_EXTENSION: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.19");
+
+/// Error returned when a certificate is not a well formed DER encoded X.509 certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidCertificate;
+
+impl From<der::Error> for InvalidCertificate {
+    fn from(_: der::Error) -> Self {
+        InvalidCertificate
+    }
+}
+
+/// The fields of a X.509 certificate relevant to attestation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate<'a> {
+    /// The X.509 version, `3` for certificates with extensions.
+    pub version: u8,
+    /// The subject's string attributes as `(OID, value)` pairs.
+    pub subject: Vec<(String, String)>,
+    /// The DER encoded SubjectPublicKeyInfo.
+    pub subject_public_key_info: &'a [u8],
+    /// Whether the basic constraints extension marks this as a CA certificate.
+    pub is_ca: bool,
+    /// The AAGUID from the `id-fido-gen-ce-aaguid` extension, if present.
+    pub aaguid: Option<Aaguid>,
+    /// All extensions of the certificate, in order.
+    pub extensions: Vec<Extension<'a>>,
+}
+
+/// A X.509 certificate extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension<'a> {
+    /// The dotted OID identifying the extension.
+    pub oid: String,
+    /// Whether the extension is marked critical.
+    pub critical: bool,
+    /// The DER encoded value of the extension, the contents of its `extnValue` OCTET STRING.
+    pub value: &'a [u8],
+}
+
+impl<'a> Certificate<'a> {
+    /// Parse a DER encoded certificate.
+    pub fn from_der(certificate: &'a [u8]) -> Result<Self, InvalidCertificate> {
+        let mut reader = SliceReader::new(certificate)?;
+        let parsed = reader.sequence(|certificate| {
+            let parsed = certificate.sequence(parse_tbs_certificate)?;
+            // signatureAlgorithm and signatureValue
+            certificate.tlv_bytes()?;
+            certificate.tlv_bytes()?;
+            Ok(parsed)
+        })?;
+        Ok(reader.finish(parsed)?)
+    }
+
+    /// The value of the first subject attribute with the given dotted `oid`.
+    pub fn subject_attribute(&self, oid: &str) -> Option<&str> {
+        self.subject
+            .iter()
+            .find(|(attribute, _)| attribute == oid)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of the extension with the given dotted `oid`.
+    pub fn extension(&self, oid: &str) -> Option<&'a [u8]> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.oid == oid)
+            .map(|extension| extension.value)
+    }
+
+    /// The dotted OIDs of the extended key usage extension, empty if the extension is absent or
+    /// malformed.
+    pub fn extended_key_usage(&self) -> Vec<String> {
+        let Some(extension) = self.extension(EXTENDED_KEY_USAGE_EXTENSION) else {
+            return Vec::new();
+        };
+        let parse = || -> der::Result<Vec<String>> {
+            let mut reader = SliceReader::new(extension)?;
+            let usages = reader.sequence(|usages| {
+                let mut oids = Vec::new();
+                while !usages.is_finished() {
+                    oids.push(usages.decode::<ObjectIdentifier>()?.to_string());
+                }
+                Ok(oids)
+            })?;
+            reader.finish(usages)
+        };
+        parse().unwrap_or_default()
+    }
+}
+
+fn parse_tbs_certificate<'a, R: Reader<'a>>(tbs: &mut R) -> der::Result<Certificate<'a>> {
+    let version = if tbs.peek_tag()? == context_specific(TagNumber::N0) {
+        let version: AnyRef<'_> = tbs.decode()?;
+        u8::from_der(version.value())?
+    } else {
+        0
+    };
+    // serialNumber, signature, issuer and validity
+    for _ in 0..4 {
+        tbs.tlv_bytes()?;
+    }
+    let subject = tbs.sequence(|name| {
+        let mut subject = Vec::new();
+        while !name.is_finished() {
+            let rdn: AnyRef<'_> = name.decode()?;
+            let mut rdn = SliceReader::new(rdn.value())?;
+            while !rdn.is_finished() {
+                rdn.sequence(|attribute| {
+                    let oid: ObjectIdentifier = attribute.decode()?;
+                    let value: AnyRef<'_> = attribute.decode()?;
+                    if let Ok(value) = std::str::from_utf8(value.value()) {
+                        subject.push((oid.to_string(), value.to_owned()));
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(subject)
+    })?;
+    let subject_public_key_info = tbs.tlv_bytes()?;
+
+    let mut certificate = Certificate {
+        version: version + 1,
+        subject,
+        subject_public_key_info,
+        is_ca: false,
+        aaguid: None,
+        extensions: Vec::new(),
+    };
+    // issuerUniqueID, subjectUniqueID and extensions
+    while !tbs.is_finished() {
+        let field: AnyRef<'_> = tbs.decode()?;
+        if field.tag() == context_specific(TagNumber::N3) {
+            parse_extensions(field.value(), &mut certificate)?;
+        }
+    }
+    Ok(certificate)
+}
+
+fn parse_extensions<'a>(
+    extensions: &'a [u8],
+    certificate: &mut Certificate<'a>,
+) -> der::Result<()> {
+    let mut reader = SliceReader::new(extensions)?;
+    reader.sequence(|extensions| {
+        while !extensions.is_finished() {
+            extensions.sequence(|extension| {
+                let id: ObjectIdentifier = extension.decode()?;
+                let critical = extension.peek_tag()? == Tag::Boolean && extension.decode()?;
+                let value: OctetStringRef<'a> = extension.decode()?;
+                certificate.extensions.push(Extension {
+                    oid: id.to_string(),
+                    critical,
+                    value: value.as_bytes(),
+                });
+                if id == AAGUID_EXTENSION {
+                    let inner = OctetStringRef::from_der(value.as_bytes())?;
+                    let bytes: [u8; 16] = inner
+                        .as_bytes()
+                        .try_into()
+                        .map_err(|_| Tag::OctetString.length_error())?;
+                    certificate.aaguid = Some(Aaguid::from(bytes));
+                } else if id == BASIC_CONSTRAINTS_EXTENSION {
+                    let mut constraints = SliceReader::new(value.as_bytes())?;
+                    certificate.is_ca = constraints.sequence(|constraints| {
+                        let is_ca = !constraints.is_finished()
+                            && constraints.peek_tag()? == Tag::Boolean
+                            && constraints.decode::<bool>()?;
+                        // pathLenConstraint
+                        while !constraints.is_finished() {
+                            constraints.tlv_bytes()?;
+                        }
+                        Ok(is_ca)
+                    })?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?;
+    reader.finish(())
+}
+
+fn context_specific(number: TagNumber) -> Tag {
+    Tag::ContextSpecific {
+        constructed: true,
+        number,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len => out.extend([0x81, len as u8]),
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    fn attribute(oid: &[u8], value: &str) -> Vec<u8> {
+        tlv(
+            0x31,
+            &tlv(
+                0x30,
+                &[tlv(0x06, oid), tlv(0x0c, value.as_bytes())].concat(),
+            ),
+        )
+    }
+
+    #[test]
+    fn parse_attestation_certificate() {
+        let subject = [
+            attribute(&[0x55, 0x04, 0x06], "US"),
+            attribute(&[0x55, 0x04, 0x0b], "Authenticator Attestation"),
+        ]
+        .concat();
+        let spki = tlv(0x30, &[tlv(0x30, &[]), tlv(0x03, &[0, 4])].concat());
+        let aaguid = [
+            tlv(0x06, AAGUID_EXTENSION.as_bytes()),
+            tlv(0x04, &tlv(0x04, &[7; 16])),
+        ]
+        .concat();
+        let extended_key_usage = [
+            tlv(0x06, &[0x55, 0x1d, 0x25]),
+            tlv(
+                0x04,
+                &tlv(0x30, &tlv(0x06, &[0x67, 0x81, 0x05, 0x08, 0x03])),
+            ),
+        ]
+        .concat();
+        let basic_constraints = [
+            tlv(0x06, &[0x55, 0x1d, 0x13]),
+            tlv(0x01, &[0xff]),
+            tlv(0x04, &tlv(0x30, &[])),
+        ]
+        .concat();
+        let extensions = tlv(
+            0xa3,
+            &tlv(
+                0x30,
+                &[
+                    tlv(0x30, &aaguid),
+                    tlv(0x30, &extended_key_usage),
+                    tlv(0x30, &basic_constraints),
+                ]
+                .concat(),
+            ),
+        );
+        let tbs = [
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            tlv(0x30, &[]),
+            tlv(0x30, &[]),
+            tlv(0x30, &[]),
+            tlv(0x30, &subject),
+            spki.clone(),
+            extensions,
+        ]
+        .concat();
+        let der = tlv(
+            0x30,
+            &[tlv(0x30, &tbs), tlv(0x30, &[]), tlv(0x03, &[0])].concat(),
+        );
+
+        let certificate = Certificate::from_der(&der).expect("failed to parse certificate");
+        assert_eq!(certificate.version, 3);
+        assert_eq!(certificate.subject_attribute(COUNTRY_NAME), Some("US"));
+        assert_eq!(
+            certificate.subject_attribute(ORGANIZATIONAL_UNIT_NAME),
+            Some("Authenticator Attestation")
+        );
+        assert_eq!(certificate.subject_attribute(COMMON_NAME), None);
+        assert_eq!(certificate.subject_public_key_info, spki.as_slice());
+        assert_eq!(certificate.aaguid, Some(Aaguid::from([7; 16])));
+        assert!(!certificate.is_ca);
+        assert_eq!(certificate.extensions.len(), 3);
+        assert!(certificate.extensions[2].critical);
+        assert_eq!(certificate.extended_key_usage(), vec!["2.23.133.8.3"]);
+        assert_eq!(
+            certificate.extension("2.5.29.19"),
+            Some([0x30, 0].as_slice())
+        );
+
+        assert_eq!(Certificate::from_der(&der[1..]), Err(InvalidCertificate));
+    }
+}
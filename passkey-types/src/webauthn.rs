@@ -11,9 +11,10 @@ mod assertion;
 mod attestation;
 mod common;
 mod extensions;
+mod json;
 
 // re-export types
-pub use self::{assertion::*, attestation::*, common::*, extensions::*};
+pub use self::{assertion::*, attestation::*, common::*, extensions::*, json::*};
 
 mod sealed {
     pub trait Sealed {}
@@ -172,6 +172,40 @@ pub struct PublicKeyCredentialRequestOptions {
 pub struct CredentialRequestOptions {
     /// The key defining that this is a request for a webauthn credential.
     pub public_key: PublicKeyCredentialRequestOptions,
+
+    /// How the user is involved in choosing the credential, see [`CredentialMediationRequirement`].
+    /// Unknown values are ignored.
+    ///
+    /// <https://w3c.github.io/webappsec-credential-management/#dom-credentialrequestoptions-mediation>
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown"
+    )]
+    pub mediation: Option<CredentialMediationRequirement>,
+}
+
+/// How the user is involved in choosing the credential of a request, which defaults to
+/// [`CredentialMediationRequirement::Optional`].
+///
+/// <https://w3c.github.io/webappsec-credential-management/#enumdef-credentialmediationrequirement>
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare(serialized_as = "String")]
+pub enum CredentialMediationRequirement {
+    /// The user is not involved, the request fails if it would require user interaction.
+    Silent,
+
+    /// The user is involved if the client requires it, for example to choose between credentials.
+    #[default]
+    Optional,
+
+    /// The request stays pending while the credentials are offered in the client's existing UI,
+    /// such as autofill suggestions, and only completes once the user picks one of them.
+    Conditional,
+
+    /// The user is always involved, even when the request could complete without them.
+    Required,
 }
 
 /// This type represents an authenticator's response to a client’s request for generation of a new
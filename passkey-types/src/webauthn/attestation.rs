@@ -11,8 +11,9 @@ use crate::{
     },
     webauthn::{
         AuthenticationExtensionsClientInputs, AuthenticatorAttachment, AuthenticatorTransport,
-        PublicKeyCredential, PublicKeyCredentialDescriptor, PublicKeyCredentialHints,
-        PublicKeyCredentialType, UserVerificationRequirement,
+        PaymentCredentialInstrument, PaymentCurrencyAmount, PublicKeyCredential,
+        PublicKeyCredentialDescriptor, PublicKeyCredentialHints, PublicKeyCredentialType,
+        UserVerificationRequirement,
     },
     Bytes,
 };
@@ -273,7 +274,7 @@ pub struct PublicKeyCredentialUserEntity {
 /// This type is used to supply additional parameters when creating a new credential.
 ///
 /// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialparameters>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[typeshare]
 pub struct PublicKeyCredentialParameters {
     /// This member specifies the type of credential to be created. The value SHOULD be a member of
@@ -588,6 +589,70 @@ pub struct CollectedClientData {
     pub unknown_keys: IndexMap<String, serde_json::value::Value>,
 }
 
+/// The details of a payment confirmed with Secure Payment Confirmation, which the Relying Party
+/// checks against the transaction it expects.
+///
+/// They are found under the `"payment"` key of the [`CollectedClientData::unknown_keys`] of a
+/// [`ClientDataType::PaymentGet`] assertion, so that the client data of other clients keeps its
+/// byte serialization.
+///
+/// <https://w3c.github.io/secure-payment-confirmation/#sctn-collectedclientpaymentdata-dictionary>
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct CollectedClientPaymentData {
+    /// The Relying Party of the credential.
+    pub rp_id: String,
+
+    /// The origin of the top-level frame the payment was confirmed from.
+    pub top_origin: String,
+
+    /// The name of the payee, if it was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_name: Option<String>,
+
+    /// The origin of the payee, if it was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_origin: Option<String>,
+
+    /// The amount of the payment.
+    pub total: PaymentCurrencyAmount,
+
+    /// The payment instrument the payment was made with.
+    pub instrument: PaymentCredentialInstrument,
+}
+
+/// The state of the Token Binding protocol used to communicate with the Relying Party, which
+/// clients may still report although the protocol is no longer supported by browsers.
+///
+/// It is found under the `"tokenBinding"` key of the [`CollectedClientData::unknown_keys`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-tokenbinding>
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[typeshare]
+pub struct TokenBinding {
+    /// Whether Token Binding was used.
+    pub status: TokenBindingStatus,
+
+    /// The base64url encoding of the Token Binding ID used, present when the status is
+    /// [`TokenBindingStatus::Present`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// Whether Token Binding was used to communicate with the Relying Party.
+///
+/// <https://w3c.github.io/webauthn/#enumdef-tokenbindingstatus>
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare]
+pub enum TokenBindingStatus {
+    /// Token Binding was used, with the Token Binding ID found in [`TokenBinding::id`].
+    Present,
+    /// The client supports Token Binding, but it was not negotiated with the Relying Party.
+    Supported,
+}
+
 fn truthiness<S>(cross_origin: &Option<bool>, ser: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -874,20 +939,20 @@ mod tests {
         let client_data_json = serde_json::to_string(&ccd).unwrap();
         let pattern = r#""crossOrigin":s*true"#;
         let regex = Regex::new(pattern).expect("Invalid regex pattern");
-        assert_eq!(regex.is_match(client_data_json.as_str()), true);
+        assert!(regex.is_match(client_data_json.as_str()));
 
         // Check that serialization of cross_origin with value Some(false) resolves to false
         ccd.cross_origin = Some(false);
         let client_data_json = serde_json::to_string(&ccd).unwrap();
         let pattern = r#""crossOrigin":s*false"#;
         let regex = Regex::new(pattern).expect("Invalid regex pattern");
-        assert_eq!(regex.is_match(client_data_json.as_str()), true);
+        assert!(regex.is_match(client_data_json.as_str()));
 
         // Check that serialization of cross_origin with value None resolves to false
         ccd.cross_origin = None;
         let client_data_json = serde_json::to_string(&ccd).unwrap();
         let pattern = r#""crossOrigin":s*false"#;
         let regex = Regex::new(pattern).expect("Invalid regex pattern");
-        assert_eq!(regex.is_match(client_data_json.as_str()), true);
+        assert!(regex.is_match(client_data_json.as_str()));
     }
 }
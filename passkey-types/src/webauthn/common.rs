@@ -40,7 +40,7 @@ pub enum PublicKeyCredentialType {
 /// It is recommended to ignore any credential whose type is [`PublicKeyCredentialType::Unknown`]
 ///
 /// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialdescriptor>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[typeshare]
 pub struct PublicKeyCredentialDescriptor {
     /// This member contains the type of the public key credential the caller is referring to. The
@@ -210,3 +210,15 @@ pub enum PublicKeyCredentialHints {
     /// the authenticatorAttachment SHOULD be set to [`AuthenticatorAttachment::CrossPlatform`].
     Hybrid,
 }
+
+/// The document served by a Relying Party at `https://{RP ID}/.well-known/webauthn`, listing the
+/// origins other than those of its RP ID which may use its credentials.
+///
+/// <https://w3c.github.io/webauthn/#sctn-related-origins>
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[typeshare]
+pub struct RelatedOrigins {
+    /// The related origins, such as `https://example.co.uk`. Of these, only the origins with the
+    /// first five distinct labels of their registrable domain are accepted.
+    pub origins: Vec<String>,
+}
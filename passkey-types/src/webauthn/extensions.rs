@@ -1,6 +1,15 @@
+use ciborium::value::Value;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
+use crate::{
+    ctap2::{HmacSecretInput, HmacSecretSalts},
+    utils::serde::{ignore_unknown, ignore_unknown_opt_vec},
+    webauthn::{AttestationConveyancePreference, AttestationStatementFormatIdentifiers},
+    Bytes,
+};
+
 #[cfg(doc)]
 use crate::webauthn::PublicKeyCredential;
 
@@ -10,7 +19,7 @@ use crate::webauthn::PublicKeyCredential;
 /// <https://w3c.github.io/webauthn/#dictdef-authenticationextensionsclientinputs>
 ///
 /// [WebAuthn Extensions]: https://w3c.github.io/webauthn/#webauthn-extensions
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 #[typeshare]
 pub struct AuthenticationExtensionsClientInputs {
@@ -19,6 +28,233 @@ pub struct AuthenticationExtensionsClientInputs {
     /// See [`CredentialPropertiesOutput`] for more information.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cred_props: Option<bool>,
+
+    /// Requests support for storing a large blob with a discoverable credential on creation, or
+    /// reading or writing it on assertion.
+    ///
+    /// See [`AuthenticationExtensionsLargeBlobInputs`] for more information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large_blob: Option<AuthenticationExtensionsLargeBlobInputs>,
+
+    /// The FIDO AppID of a credential registered with the legacy FIDO U2F API, under which the
+    /// client also looks for credentials during authentication.
+    ///
+    /// <https://w3c.github.io/webauthn/#sctn-appid-extension>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid: Option<String>,
+
+    /// The FIDO AppID under which the credentials to exclude from registration may have been
+    /// registered with the legacy FIDO U2F API.
+    ///
+    /// <https://w3c.github.io/webauthn/#sctn-appid-exclude-extension>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid_exclude: Option<String>,
+
+    /// The CTAP `hmac-secret` extension, in the format a platform sends it to the authenticator.
+    ///
+    /// See [`HmacSecretInput`] for more information.
+    #[serde(
+        rename = "hmac-secret",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[typeshare(skip)]
+    pub hmac_secret: Option<HmacSecretInput>,
+
+    /// The CTAP `hmac-secret-mc` extension, the salts from which to compute the `hmac-secret`
+    /// outputs of a new credential during its creation rather than on its first assertion. It is
+    /// only processed along with an `hmac-secret` input of `true`.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.2-rd-20230321/fido-client-to-authenticator-protocol-v2.2-rd-20230321.html#sctn-hmac-secret-make-cred-extension>
+    #[serde(
+        rename = "hmac-secret-mc",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[typeshare(skip)]
+    pub hmac_secret_mc: Option<HmacSecretSalts>,
+
+    /// The CTAP `largeBlobKey` extension, in the format a platform sends it to the authenticator:
+    /// `true` asks for the key with which the large blob of a discoverable credential is
+    /// encrypted in the authenticator's large-blob array. Platforms request it to process the
+    /// [`AuthenticationExtensionsLargeBlobInputs`] of the client.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-errata-20220621.html#sctn-largeBlobKey-extension>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[typeshare(skip)]
+    pub large_blob_key: Option<bool>,
+
+    /// The CTAP `thirdPartyPayment` extension, in the format a platform sends it to the
+    /// authenticator. On creation it enables the credential for third-party payments, and on
+    /// assertion it asks whether the credential is enabled for them.
+    ///
+    /// <https://fidoalliance.org/specs/fido-v2.2-rd-20230321/fido-client-to-authenticator-protocol-v2.2-rd-20230321.html#sctn-thirdPartyPayment-extension>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[typeshare(skip)]
+    pub third_party_payment: Option<bool>,
+
+    /// Requests a device-bound key pair for the credential on the authenticator, whose attested
+    /// public key is returned along with a signature on every use.
+    ///
+    /// See [`AuthenticationExtensionsDevicePublicKeyInputs`] for more information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_pub_key: Option<AuthenticationExtensionsDevicePublicKeyInputs>,
+
+    /// Registers a credential for Secure Payment Confirmation, or asks for a payment to be
+    /// confirmed with one.
+    ///
+    /// See [`AuthenticationExtensionsPaymentInputs`] for more information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment: Option<AuthenticationExtensionsPaymentInputs>,
+
+    /// The inputs of extensions which are not recognized, such as vendor extensions, in the order
+    /// they were given. An authenticator may process them with custom handlers.
+    #[serde(flatten)]
+    #[typeshare(skip)]
+    pub unknown_keys: IndexMap<String, Value>,
+}
+
+/// The inputs of the Secure Payment Confirmation extension.
+///
+/// On creation, `is_payment` marks the new credential as usable to confirm payments, including by
+/// parties other than its Relying Party. On assertion, `is_payment` asks for a payment to be
+/// confirmed: the remaining members describe the transaction and are reported to the Relying
+/// Party in the `payment` member of the [`CollectedClientData`].
+///
+/// <https://w3c.github.io/secure-payment-confirmation/#sctn-payment-extension-registration>
+///
+/// [`CollectedClientData`]: crate::webauthn::CollectedClientData
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticationExtensionsPaymentInputs {
+    /// Whether the ceremony is a payment one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_payment: Option<bool>,
+
+    /// The Relying Party of the credential, which may differ from the origin confirming the
+    /// payment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rp_id: Option<String>,
+
+    /// The origin of the top-level frame the payment is confirmed from.
+    ///
+    /// Clients ignore this input and report the origin of the actual top-level frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_origin: Option<String>,
+
+    /// The name of the payee, shown to the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_name: Option<String>,
+
+    /// The origin of the payee, shown to the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_origin: Option<String>,
+
+    /// The amount of the payment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<PaymentCurrencyAmount>,
+
+    /// The payment instrument the payment is made with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument: Option<PaymentCredentialInstrument>,
+}
+
+/// An amount of money in a given currency.
+///
+/// <https://w3c.github.io/payment-request/#dom-paymentcurrencyamount>
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PaymentCurrencyAmount {
+    /// The ISO 4217 currency code of the amount, such as `"USD"`.
+    pub currency: String,
+
+    /// The decimal monetary value of the amount, such as `"12.34"`.
+    pub value: String,
+}
+
+/// The payment instrument a payment is confirmed for, as shown to the user.
+///
+/// <https://w3c.github.io/secure-payment-confirmation/#dictdef-paymentcredentialinstrument>
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PaymentCredentialInstrument {
+    /// The name of the instrument.
+    pub display_name: String,
+
+    /// The URL of the icon of the instrument.
+    pub icon: String,
+
+    /// Whether the payment must fail when the icon cannot be shown.
+    #[serde(default = "icon_must_be_shown")]
+    pub icon_must_be_shown: bool,
+}
+
+fn icon_must_be_shown() -> bool {
+    true
+}
+
+/// The inputs of the device public key extension, which lets a Relying Party recognize the device
+/// a synced credential is used from by a key pair which never leaves that device.
+///
+/// <https://w3c.github.io/webauthn/#sctn-device-publickey-extension>
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticationExtensionsDevicePublicKeyInputs {
+    /// The Relying Party's preference regarding the attestation of the device public key.
+    #[serde(default, deserialize_with = "ignore_unknown")]
+    pub attestation: AttestationConveyancePreference,
+
+    /// The attestation statement formats the Relying Party prefers for the device public key, most
+    /// preferred first.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub attestation_formats: Option<Vec<AttestationStatementFormatIdentifiers>>,
+}
+
+/// The inputs of the large blob storage extension, which allows storing opaque data associated
+/// with a credential in the authenticator's large-blob array.
+///
+/// A client processes it with the CTAP `largeBlobKey` extension, see
+/// [`AuthenticationExtensionsClientInputs::large_blob_key`]: on creation, requesting `support`
+/// asks the authenticator for a key to be generated for the credential, and on assertion, `read`
+/// or `write` asks for it to be returned.
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticationextensionslargeblobinputs>
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticationExtensionsLargeBlobInputs {
+    /// Whether large blob storage is required or preferred on creation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub support: Option<LargeBlobSupport>,
+
+    /// Whether to read the credential's large blob on assertion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<bool>,
+
+    /// The large blob to write for the credential on assertion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write: Option<Bytes>,
+}
+
+/// The Relying Party's requirement for large blob storage of a new credential.
+///
+/// <https://w3c.github.io/webauthn/#enumdef-largeblobsupport>
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare(serialized_as = "String")]
+pub enum LargeBlobSupport {
+    /// The credential must be created on an authenticator which supports large blobs.
+    Required,
+    /// Large blob storage is used if the authenticator supports it.
+    Preferred,
 }
 
 /// This is a dictionary containing the client extension output values for zero or more
@@ -36,6 +272,72 @@ pub struct AuthenticatorExtensionsClientOutputs {
     /// See [`CredentialPropertiesOutput`] for more information
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cred_props: Option<CredentialPropertiesOutput>,
+
+    /// The results of the large blob storage extension, when it was requested.
+    ///
+    /// See [`AuthenticationExtensionsLargeBlobOutputs`] for more information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large_blob: Option<AuthenticationExtensionsLargeBlobOutputs>,
+
+    /// Whether the credential was found under the AppID rather than the RP ID, when an AppID was
+    /// given for authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid: Option<bool>,
+
+    /// Whether the AppID was also checked for excluded credentials during registration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid_exclude: Option<bool>,
+
+    /// The results of the device public key extension, when it was requested and the
+    /// authenticator supports it.
+    ///
+    /// See [`AuthenticationExtensionsDevicePublicKeyOutputs`] for more information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_pub_key: Option<AuthenticationExtensionsDevicePublicKeyOutputs>,
+
+    /// The outputs of extensions which are not recognized, such as vendor extensions, passed
+    /// through untouched from the unsigned extension outputs of the authenticator, in the order
+    /// they were returned.
+    #[serde(flatten)]
+    #[typeshare(skip)]
+    pub unknown_keys: IndexMap<String, Value>,
+}
+
+/// The outputs of the device public key extension.
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticationextensionsdevicepublickeyoutputs>
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticationExtensionsDevicePublicKeyOutputs {
+    /// The CBOR encoded attestation object of the device public key, see
+    /// [`DevicePublicKeyAttestation`](crate::ctap2::DevicePublicKeyAttestation).
+    pub dpk_att_obj: Bytes,
+
+    /// The signature over `authenticatorData || hash(clientDataJSON)` made with the device private
+    /// key.
+    pub signature: Bytes,
+}
+
+/// The outputs of the large blob storage extension. Which of them is present depends on the
+/// inputs: `supported` on creation, `blob` when reading and `written` when writing on assertion.
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticationextensionslargebloboutputs>
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticationExtensionsLargeBlobOutputs {
+    /// Whether the new credential supports storing a large blob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supported: Option<bool>,
+
+    /// The large blob of the credential, absent if it has none or it could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<Bytes>,
+
+    /// Whether the large blob was written for the credential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written: Option<bool>,
 }
 
 /// This client registration extension facilitates reporting certain credential properties known by
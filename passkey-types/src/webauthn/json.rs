@@ -0,0 +1,629 @@
+//! Types of the JSON encoding of WebAuthn requests and responses, as produced by
+//! [`PublicKeyCredential.toJSON()`] and consumed by [`parseCreationOptionsFromJSON()`] and
+//! [`parseRequestOptionsFromJSON()`] in browsers.
+//!
+//! Unlike the types they mirror, whose [`Bytes`] are only serialized as strings with the
+//! `serialize_bytes_as_base64_string` feature, every buffer is a `base64url` encoded string
+//! regardless of features. Each type converts [`From`] its counterpart, and back with [`TryFrom`],
+//! which fails with [`NotBase64Encoded`] when a buffer is not encoded. Extension inputs and outputs
+//! are shared with the types they mirror.
+//!
+//! [`PublicKeyCredential.toJSON()`]: https://w3c.github.io/webauthn/#dom-publickeycredential-tojson
+//! [`parseCreationOptionsFromJSON()`]: https://w3c.github.io/webauthn/#sctn-parseCreationOptionsFromJSON
+//! [`parseRequestOptionsFromJSON()`]: https://w3c.github.io/webauthn/#sctn-parseRequestOptionsFromJSON
+
+use serde::{Deserialize, Serialize};
+use typeshare::typeshare;
+
+use crate::{
+    utils::serde::{ignore_unknown, ignore_unknown_opt_vec, ignore_unknown_vec},
+    webauthn::{
+        AttestationConveyancePreference, AttestationStatementFormatIdentifiers,
+        AuthenticationExtensionsClientInputs, AuthenticatorAssertionResponse,
+        AuthenticatorAttachment, AuthenticatorAttestationResponse,
+        AuthenticatorExtensionsClientOutputs, AuthenticatorResponse,
+        AuthenticatorSelectionCriteria, AuthenticatorTransport, PublicKeyCredential,
+        PublicKeyCredentialCreationOptions, PublicKeyCredentialDescriptor,
+        PublicKeyCredentialHints, PublicKeyCredentialParameters, PublicKeyCredentialRequestOptions,
+        PublicKeyCredentialRpEntity, PublicKeyCredentialType, PublicKeyCredentialUserEntity,
+        UserVerificationRequirement,
+    },
+    Bytes, NotBase64Encoded,
+};
+
+/// The JSON encoding of a [`PublicKeyCredentialCreationOptions`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialcreationoptionsjson>
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PublicKeyCredentialCreationOptionsJSON {
+    /// See [`PublicKeyCredentialCreationOptions::rp`].
+    pub rp: PublicKeyCredentialRpEntity,
+
+    /// See [`PublicKeyCredentialCreationOptions::user`].
+    pub user: PublicKeyCredentialUserEntityJSON,
+
+    /// See [`PublicKeyCredentialCreationOptions::challenge`].
+    pub challenge: String,
+
+    /// See [`PublicKeyCredentialCreationOptions::pub_key_cred_params`].
+    #[serde(deserialize_with = "ignore_unknown_vec")]
+    pub pub_key_cred_params: Vec<PublicKeyCredentialParameters>,
+
+    /// See [`PublicKeyCredentialCreationOptions::timeout`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+
+    /// See [`PublicKeyCredentialCreationOptions::exclude_credentials`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub exclude_credentials: Option<Vec<PublicKeyCredentialDescriptorJSON>>,
+
+    /// See [`PublicKeyCredentialCreationOptions::authenticator_selection`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authenticator_selection: Option<AuthenticatorSelectionCriteria>,
+
+    /// See [`PublicKeyCredentialCreationOptions::hints`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub hints: Option<Vec<PublicKeyCredentialHints>>,
+
+    /// See [`PublicKeyCredentialCreationOptions::attestation`].
+    #[serde(default, deserialize_with = "ignore_unknown")]
+    pub attestation: AttestationConveyancePreference,
+
+    /// See [`PublicKeyCredentialCreationOptions::attestation_formats`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub attestation_formats: Option<Vec<AttestationStatementFormatIdentifiers>>,
+
+    /// See [`PublicKeyCredentialCreationOptions::extensions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<AuthenticationExtensionsClientInputs>,
+}
+
+/// The JSON encoding of a [`PublicKeyCredentialUserEntity`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialuserentityjson>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PublicKeyCredentialUserEntityJSON {
+    /// The `base64url` encoded [`PublicKeyCredentialUserEntity::id`].
+    pub id: String,
+
+    /// See [`PublicKeyCredentialUserEntity::name`].
+    pub name: String,
+
+    /// See [`PublicKeyCredentialUserEntity::display_name`].
+    pub display_name: String,
+}
+
+/// The JSON encoding of a [`PublicKeyCredentialDescriptor`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialdescriptorjson>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[typeshare]
+pub struct PublicKeyCredentialDescriptorJSON {
+    /// The `base64url` encoded [`PublicKeyCredentialDescriptor::id`].
+    pub id: String,
+
+    /// See [`PublicKeyCredentialDescriptor::ty`].
+    #[serde(rename = "type", deserialize_with = "ignore_unknown")]
+    pub ty: PublicKeyCredentialType,
+
+    /// See [`PublicKeyCredentialDescriptor::transports`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub transports: Option<Vec<AuthenticatorTransport>>,
+}
+
+/// The JSON encoding of a [`PublicKeyCredentialRequestOptions`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-publickeycredentialrequestoptionsjson>
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PublicKeyCredentialRequestOptionsJSON {
+    /// See [`PublicKeyCredentialRequestOptions::challenge`].
+    pub challenge: String,
+
+    /// See [`PublicKeyCredentialRequestOptions::timeout`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+
+    /// See [`PublicKeyCredentialRequestOptions::rp_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rp_id: Option<String>,
+
+    /// See [`PublicKeyCredentialRequestOptions::allow_credentials`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub allow_credentials: Option<Vec<PublicKeyCredentialDescriptorJSON>>,
+
+    /// See [`PublicKeyCredentialRequestOptions::user_verification`].
+    #[serde(default, deserialize_with = "ignore_unknown")]
+    pub user_verification: UserVerificationRequirement,
+
+    /// See [`PublicKeyCredentialRequestOptions::hints`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub hints: Option<Vec<PublicKeyCredentialHints>>,
+
+    /// See [`PublicKeyCredentialRequestOptions::attestation`].
+    #[serde(default, deserialize_with = "ignore_unknown")]
+    pub attestation: AttestationConveyancePreference,
+
+    /// See [`PublicKeyCredentialRequestOptions::attestation_formats`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown_opt_vec"
+    )]
+    pub attestation_formats: Option<Vec<AttestationStatementFormatIdentifiers>>,
+
+    /// See [`PublicKeyCredentialRequestOptions::extensions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<AuthenticationExtensionsClientInputs>,
+}
+
+/// The JSON encoding of a [`PublicKeyCredential`], as returned by `toJSON()`.
+///
+/// It is recommended to use the type aliases depending on which response you are expecting:
+/// * Credential Creation: [`RegistrationResponseJSON`]
+/// * Credential assertion: [`AuthenticationResponseJSON`]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PublicKeyCredentialJSON<R> {
+    /// See [`PublicKeyCredential::id`].
+    pub id: String,
+
+    /// The `base64url` encoded [`PublicKeyCredential::raw_id`].
+    pub raw_id: String,
+
+    /// The JSON encoding of [`PublicKeyCredential::response`].
+    pub response: R,
+
+    /// See [`PublicKeyCredential::authenticator_attachment`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "ignore_unknown"
+    )]
+    pub authenticator_attachment: Option<AuthenticatorAttachment>,
+
+    /// See [`PublicKeyCredential::client_extension_results`].
+    #[serde(default)]
+    pub client_extension_results: AuthenticatorExtensionsClientOutputs,
+
+    /// Always [`PublicKeyCredentialType::PublicKey`].
+    #[serde(rename = "type")]
+    pub ty: PublicKeyCredentialType,
+}
+
+/// The JSON encoding of a [`CreatedPublicKeyCredential`](crate::webauthn::CreatedPublicKeyCredential).
+///
+/// <https://w3c.github.io/webauthn/#dictdef-registrationresponsejson>
+#[typeshare]
+pub type RegistrationResponseJSON = PublicKeyCredentialJSON<AuthenticatorAttestationResponseJSON>;
+
+/// The JSON encoding of an
+/// [`AuthenticatedPublicKeyCredential`](crate::webauthn::AuthenticatedPublicKeyCredential).
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticationresponsejson>
+#[typeshare]
+pub type AuthenticationResponseJSON = PublicKeyCredentialJSON<AuthenticatorAssertionResponseJSON>;
+
+/// The JSON encoding of an [`AuthenticatorAttestationResponse`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticatorattestationresponsejson>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticatorAttestationResponseJSON {
+    /// The `base64url` encoded [`AuthenticatorAttestationResponse::client_data_json`].
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+
+    /// The `base64url` encoded [`AuthenticatorAttestationResponse::authenticator_data`].
+    pub authenticator_data: String,
+
+    /// See [`AuthenticatorAttestationResponse::transports`], which is empty when unknown.
+    #[serde(default, deserialize_with = "ignore_unknown_vec")]
+    pub transports: Vec<AuthenticatorTransport>,
+
+    /// The `base64url` encoded [`AuthenticatorAttestationResponse::public_key`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+
+    /// See [`AuthenticatorAttestationResponse::public_key_algorithm`].
+    pub public_key_algorithm: i64,
+
+    /// The `base64url` encoded [`AuthenticatorAttestationResponse::attestation_object`].
+    pub attestation_object: String,
+}
+
+/// The JSON encoding of an [`AuthenticatorAssertionResponse`].
+///
+/// <https://w3c.github.io/webauthn/#dictdef-authenticatorassertionresponsejson>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct AuthenticatorAssertionResponseJSON {
+    /// The `base64url` encoded [`AuthenticatorAssertionResponse::client_data_json`].
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+
+    /// The `base64url` encoded [`AuthenticatorAssertionResponse::authenticator_data`].
+    pub authenticator_data: String,
+
+    /// The `base64url` encoded [`AuthenticatorAssertionResponse::signature`].
+    pub signature: String,
+
+    /// The `base64url` encoded [`AuthenticatorAssertionResponse::user_handle`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_handle: Option<String>,
+
+    /// The `base64url` encoded [`AuthenticatorAssertionResponse::attestation_object`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_object: Option<String>,
+}
+
+/// Decode an optional `base64url` encoded buffer.
+fn decode_opt(value: Option<String>) -> Result<Option<Bytes>, NotBase64Encoded> {
+    value.as_deref().map(Bytes::try_from).transpose()
+}
+
+impl From<PublicKeyCredentialCreationOptions> for PublicKeyCredentialCreationOptionsJSON {
+    fn from(value: PublicKeyCredentialCreationOptions) -> Self {
+        Self {
+            rp: value.rp,
+            user: value.user.into(),
+            challenge: value.challenge.into(),
+            pub_key_cred_params: value.pub_key_cred_params,
+            timeout: value.timeout,
+            exclude_credentials: value
+                .exclude_credentials
+                .map(|credentials| credentials.into_iter().map(Into::into).collect()),
+            authenticator_selection: value.authenticator_selection,
+            hints: value.hints,
+            attestation: value.attestation,
+            attestation_formats: value.attestation_formats,
+            extensions: value.extensions,
+        }
+    }
+}
+
+impl TryFrom<PublicKeyCredentialCreationOptionsJSON> for PublicKeyCredentialCreationOptions {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: PublicKeyCredentialCreationOptionsJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            rp: value.rp,
+            user: value.user.try_into()?,
+            challenge: value.challenge.as_str().try_into()?,
+            pub_key_cred_params: value.pub_key_cred_params,
+            timeout: value.timeout,
+            exclude_credentials: value
+                .exclude_credentials
+                .map(|credentials| credentials.into_iter().map(TryInto::try_into).collect())
+                .transpose()?,
+            authenticator_selection: value.authenticator_selection,
+            hints: value.hints,
+            attestation: value.attestation,
+            attestation_formats: value.attestation_formats,
+            extensions: value.extensions,
+        })
+    }
+}
+
+impl From<PublicKeyCredentialUserEntity> for PublicKeyCredentialUserEntityJSON {
+    fn from(value: PublicKeyCredentialUserEntity) -> Self {
+        Self {
+            id: value.id.into(),
+            name: value.name,
+            display_name: value.display_name,
+        }
+    }
+}
+
+impl TryFrom<PublicKeyCredentialUserEntityJSON> for PublicKeyCredentialUserEntity {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: PublicKeyCredentialUserEntityJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id.as_str().try_into()?,
+            name: value.name,
+            display_name: value.display_name,
+        })
+    }
+}
+
+impl From<PublicKeyCredentialDescriptor> for PublicKeyCredentialDescriptorJSON {
+    fn from(value: PublicKeyCredentialDescriptor) -> Self {
+        Self {
+            id: value.id.into(),
+            ty: value.ty,
+            transports: value.transports,
+        }
+    }
+}
+
+impl TryFrom<PublicKeyCredentialDescriptorJSON> for PublicKeyCredentialDescriptor {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: PublicKeyCredentialDescriptorJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id.as_str().try_into()?,
+            ty: value.ty,
+            transports: value.transports,
+        })
+    }
+}
+
+impl From<PublicKeyCredentialRequestOptions> for PublicKeyCredentialRequestOptionsJSON {
+    fn from(value: PublicKeyCredentialRequestOptions) -> Self {
+        Self {
+            challenge: value.challenge.into(),
+            timeout: value.timeout,
+            rp_id: value.rp_id,
+            allow_credentials: value
+                .allow_credentials
+                .map(|credentials| credentials.into_iter().map(Into::into).collect()),
+            user_verification: value.user_verification,
+            hints: value.hints,
+            attestation: value.attestation,
+            attestation_formats: value.attestation_formats,
+            extensions: value.extensions,
+        }
+    }
+}
+
+impl TryFrom<PublicKeyCredentialRequestOptionsJSON> for PublicKeyCredentialRequestOptions {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: PublicKeyCredentialRequestOptionsJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            challenge: value.challenge.as_str().try_into()?,
+            timeout: value.timeout,
+            rp_id: value.rp_id,
+            allow_credentials: value
+                .allow_credentials
+                .map(|credentials| credentials.into_iter().map(TryInto::try_into).collect())
+                .transpose()?,
+            user_verification: value.user_verification,
+            hints: value.hints,
+            attestation: value.attestation,
+            attestation_formats: value.attestation_formats,
+            extensions: value.extensions,
+        })
+    }
+}
+
+impl<R, J> From<PublicKeyCredential<R>> for PublicKeyCredentialJSON<J>
+where
+    R: AuthenticatorResponse,
+    J: From<R>,
+{
+    fn from(value: PublicKeyCredential<R>) -> Self {
+        Self {
+            id: value.id,
+            raw_id: value.raw_id.into(),
+            response: value.response.into(),
+            authenticator_attachment: value.authenticator_attachment,
+            client_extension_results: value.client_extension_results,
+            ty: value.ty,
+        }
+    }
+}
+
+impl<R, J> TryFrom<PublicKeyCredentialJSON<J>> for PublicKeyCredential<R>
+where
+    R: AuthenticatorResponse + TryFrom<J, Error = NotBase64Encoded>,
+{
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: PublicKeyCredentialJSON<J>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            raw_id: value.raw_id.as_str().try_into()?,
+            ty: value.ty,
+            response: value.response.try_into()?,
+            authenticator_attachment: value.authenticator_attachment,
+            client_extension_results: value.client_extension_results,
+        })
+    }
+}
+
+impl From<AuthenticatorAttestationResponse> for AuthenticatorAttestationResponseJSON {
+    fn from(value: AuthenticatorAttestationResponse) -> Self {
+        Self {
+            client_data_json: value.client_data_json.into(),
+            authenticator_data: value.authenticator_data.into(),
+            transports: value.transports.unwrap_or_default(),
+            public_key: value.public_key.map(Into::into),
+            public_key_algorithm: value.public_key_algorithm,
+            attestation_object: value.attestation_object.into(),
+        }
+    }
+}
+
+impl TryFrom<AuthenticatorAttestationResponseJSON> for AuthenticatorAttestationResponse {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: AuthenticatorAttestationResponseJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client_data_json: value.client_data_json.as_str().try_into()?,
+            authenticator_data: value.authenticator_data.as_str().try_into()?,
+            public_key: decode_opt(value.public_key)?,
+            public_key_algorithm: value.public_key_algorithm,
+            attestation_object: value.attestation_object.as_str().try_into()?,
+            transports: (!value.transports.is_empty()).then_some(value.transports),
+        })
+    }
+}
+
+impl From<AuthenticatorAssertionResponse> for AuthenticatorAssertionResponseJSON {
+    fn from(value: AuthenticatorAssertionResponse) -> Self {
+        Self {
+            client_data_json: value.client_data_json.into(),
+            authenticator_data: value.authenticator_data.into(),
+            signature: value.signature.into(),
+            user_handle: value.user_handle.map(Into::into),
+            attestation_object: value.attestation_object.map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<AuthenticatorAssertionResponseJSON> for AuthenticatorAssertionResponse {
+    type Error = NotBase64Encoded;
+
+    fn try_from(value: AuthenticatorAssertionResponseJSON) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client_data_json: value.client_data_json.as_str().try_into()?,
+            authenticator_data: value.authenticator_data.as_str().try_into()?,
+            signature: value.signature.as_str().try_into()?,
+            user_handle: decode_opt(value.user_handle)?,
+            attestation_object: decode_opt(value.attestation_object)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encoding,
+        webauthn::{
+            AuthenticatedPublicKeyCredential, AuthenticatorAssertionResponse,
+            CreatedPublicKeyCredential,
+        },
+    };
+
+    #[test]
+    fn parse_creation_options_from_json() {
+        let json = r#"{
+            "rp": { "id": "future.1password.com", "name": "1Password" },
+            "user": { "id": "AQID", "name": "wendy", "displayName": "Wendy" },
+            "challenge": "BAUGBw",
+            "pubKeyCredParams": [{ "type": "public-key", "alg": -7 }],
+            "excludeCredentials": [{ "type": "public-key", "id": "CAkK", "transports": ["internal"] }],
+            "attestation": "none"
+        }"#;
+        let options: PublicKeyCredentialCreationOptionsJSON = serde_json::from_str(json).unwrap();
+        let options = PublicKeyCredentialCreationOptions::try_from(options).unwrap();
+        assert_eq!(*options.user.id, [1, 2, 3]);
+        assert_eq!(*options.challenge, [4, 5, 6, 7]);
+        let exclude_credentials = options.exclude_credentials.as_deref().unwrap();
+        assert_eq!(*exclude_credentials[0].id, [8, 9, 10]);
+
+        // Buffers are encoded back to base64url strings.
+        let json =
+            serde_json::to_value(PublicKeyCredentialCreationOptionsJSON::from(options)).unwrap();
+        assert_eq!(json["user"]["id"], "AQID");
+        assert_eq!(json["challenge"], "BAUGBw");
+        assert_eq!(json["excludeCredentials"][0]["id"], "CAkK");
+
+        let invalid = PublicKeyCredentialCreationOptionsJSON {
+            challenge: "not base64!".into(),
+            ..serde_json::from_value(json).unwrap()
+        };
+        assert!(PublicKeyCredentialCreationOptions::try_from(invalid).is_err());
+    }
+
+    #[test]
+    fn parse_request_options_from_json() {
+        let json = r#"{
+            "challenge": "BAUGBw",
+            "rpId": "future.1password.com",
+            "allowCredentials": [{ "type": "public-key", "id": "CAkK" }],
+            "userVerification": "required"
+        }"#;
+        let options: PublicKeyCredentialRequestOptionsJSON = serde_json::from_str(json).unwrap();
+        let options = PublicKeyCredentialRequestOptions::try_from(options).unwrap();
+        assert_eq!(*options.challenge, [4, 5, 6, 7]);
+        assert_eq!(
+            *options.allow_credentials.as_deref().unwrap()[0].id,
+            [8, 9, 10]
+        );
+        assert_eq!(
+            options.user_verification,
+            UserVerificationRequirement::Required
+        );
+    }
+
+    #[test]
+    fn responses_to_json() {
+        let registration = CreatedPublicKeyCredential {
+            id: encoding::base64url(&[1, 2, 3]),
+            raw_id: vec![1, 2, 3].into(),
+            ty: PublicKeyCredentialType::PublicKey,
+            response: AuthenticatorAttestationResponse {
+                client_data_json: b"{}".to_vec().into(),
+                authenticator_data: vec![4; 37].into(),
+                public_key: Some(vec![5; 91].into()),
+                public_key_algorithm: -7,
+                attestation_object: vec![6; 8].into(),
+                transports: None,
+            },
+            authenticator_attachment: Some(AuthenticatorAttachment::Platform),
+            client_extension_results: Default::default(),
+        };
+        let json = serde_json::to_value(RegistrationResponseJSON::from(registration)).unwrap();
+        assert_eq!(json["rawId"], "AQID");
+        assert_eq!(json["type"], "public-key");
+        assert_eq!(json["response"]["clientDataJSON"], "e30");
+        assert_eq!(json["response"]["transports"], serde_json::json!([]));
+        assert_eq!(json["response"]["publicKeyAlgorithm"], -7);
+
+        let registration: RegistrationResponseJSON = serde_json::from_value(json).unwrap();
+        let registration = CreatedPublicKeyCredential::try_from(registration).unwrap();
+        assert_eq!(*registration.response.public_key.unwrap(), [5; 91]);
+        assert_eq!(registration.response.transports, None);
+
+        let authentication = AuthenticatedPublicKeyCredential {
+            id: encoding::base64url(&[1, 2, 3]),
+            raw_id: vec![1, 2, 3].into(),
+            ty: PublicKeyCredentialType::PublicKey,
+            response: AuthenticatorAssertionResponse {
+                client_data_json: b"{}".to_vec().into(),
+                authenticator_data: vec![4; 37].into(),
+                signature: vec![7; 64].into(),
+                user_handle: Some(vec![8; 16].into()),
+                attestation_object: None,
+            },
+            authenticator_attachment: None,
+            client_extension_results: Default::default(),
+        };
+        let json = serde_json::to_value(AuthenticationResponseJSON::from(authentication)).unwrap();
+        assert_eq!(
+            json["response"]["userHandle"],
+            encoding::base64url(&[8; 16])
+        );
+        assert!(json["response"].get("attestationObject").is_none());
+
+        let authentication: AuthenticationResponseJSON = serde_json::from_value(json).unwrap();
+        let authentication = AuthenticatedPublicKeyCredential::try_from(authentication).unwrap();
+        assert_eq!(*authentication.response.signature, [7; 64]);
+    }
+}
@@ -70,7 +70,7 @@ async fn client_setup(
     };
 
     // Now create the credential.
-    let my_webauthn_credential = my_client.register(origin, request, None).await?;
+    let my_webauthn_credential = my_client.register(origin, request, None).await?.credential;
 
     // Let's try and authenticate.
     // Create a challenge that would usually come from the RP.
@@ -88,6 +88,7 @@ async fn client_setup(
             attestation_formats: None,
             extensions: None,
         },
+        mediation: None,
     };
 
     let authenticated_cred = my_client
@@ -122,6 +123,7 @@ async fn authenticator_setup(
         options: make_credential::Options::default(),
         pin_auth: None,
         pin_protocol: None,
+        enterprise_attestation: None,
     };
 
     let credential: make_credential::Response =
@@ -158,6 +158,7 @@
 //!         attestation_formats: None,
 //!         extensions: None,
 //!     },
+//!     mediation: None,
 //! };
 //!
 //! let authenticated_cred = my_client
@@ -234,6 +235,7 @@
 //!     options: make_credential::Options::default(),
 //!     pin_auth: None,
 //!     pin_protocol: None,
+//!     enterprise_attestation: None,
 //! };
 //!
 //! let credential: make_credential::Response =